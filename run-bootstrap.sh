@@ -132,7 +132,7 @@ fi
 CMD="./target/release/chiral-network --headless --is-bootstrap"
 
 # Add DHT port
-CMD="$CMD --dht-port $DHT_PORT"
+CMD="$CMD --port $DHT_PORT"
 
 # Add log level
 CMD="$CMD --log-level $LOG_LEVEL"
@@ -159,7 +159,7 @@ if [ -n "$SECRET" ]; then
 fi
 
 if [ "$DISABLE_AUTONAT" = true ]; then
-    CMD="$CMD --disable-autonat"
+    CMD="$CMD --no-autonat"
 fi
 
 if [ -n "$AUTONAT_PROBE_INTERVAL" ]; then
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime};
 use tokio::sync::Mutex;
@@ -75,6 +76,18 @@ pub struct BandwidthUsageEvent {
     pub timestamp: u64,
 }
 
+/// Per-transfer bandwidth limits in KB/s (0 = unlimited)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferRateLimit {
+    pub upload_kbps: u64,
+    pub download_kbps: u64,
+}
+
+/// Longest a throttled transfer sleeps before re-checking its own limit, so
+/// lifting or raising the limit takes effect promptly
+const TRANSFER_LIMIT_POLL: Duration = Duration::from_millis(100);
+
 /// Get current Unix timestamp in milliseconds
 fn current_timestamp_ms() -> u64 {
     SystemTime::now()
@@ -100,6 +113,13 @@ struct Inner {
     upload_bytes_used: u64,
     download_bytes_used: u64,
     stats_last_reset: Instant,
+    // Limits applied to individual transfers on top of the global buckets
+    transfers: HashMap<String, TransferBuckets>,
+}
+
+struct TransferBuckets {
+    upload: TokenBucket,
+    download: TokenBucket,
 }
 
 impl BandwidthController {
@@ -112,6 +132,7 @@ impl BandwidthController {
                 upload_bytes_used: 0,
                 download_bytes_used: 0,
                 stats_last_reset: Instant::now(),
+                transfers: HashMap::new(),
             }),
             event_bus: None,
         }
@@ -126,6 +147,7 @@ impl BandwidthController {
                 upload_bytes_used: 0,
                 download_bytes_used: 0,
                 stats_last_reset: Instant::now(),
+                transfers: HashMap::new(),
             }),
             event_bus: Some(event_bus),
         }
@@ -170,6 +192,51 @@ impl BandwidthController {
         (inner.upload.limit_kbps(), inner.download.limit_kbps())
     }
 
+    /// Limit a single transfer in addition to the global limits; whichever is
+    /// more restrictive wins. Passing 0 for both directions removes the limit.
+    pub async fn set_transfer_limits(
+        &self,
+        transfer_id: &str,
+        upload_kbps: u64,
+        download_kbps: u64,
+    ) {
+        let mut inner = self.inner.lock().await;
+        if upload_kbps == 0 && download_kbps == 0 {
+            inner.transfers.remove(transfer_id);
+            debug!("Removed bandwidth limit for transfer {}", transfer_id);
+            return;
+        }
+
+        let buckets = inner
+            .transfers
+            .entry(transfer_id.to_string())
+            .or_insert_with(|| TransferBuckets {
+                upload: TokenBucket::unlimited(),
+                download: TokenBucket::unlimited(),
+            });
+        buckets.upload.set_limit(upload_kbps);
+        buckets.download.set_limit(download_kbps);
+
+        debug!(
+            "Bandwidth limits for transfer {}: upload={}KB/s, download={}KB/s",
+            transfer_id, upload_kbps, download_kbps
+        );
+    }
+
+    /// Limits set for a single transfer, if any
+    pub async fn get_transfer_limits(&self, transfer_id: &str) -> Option<TransferRateLimit> {
+        let inner = self.inner.lock().await;
+        inner.transfers.get(transfer_id).map(|b| TransferRateLimit {
+            upload_kbps: b.upload.limit_kbps(),
+            download_kbps: b.download.limit_kbps(),
+        })
+    }
+
+    /// Forget the limits of a finished transfer
+    pub async fn clear_transfer_limits(&self, transfer_id: &str) {
+        self.inner.lock().await.transfers.remove(transfer_id);
+    }
+
     pub async fn acquire_upload(&self, bytes: usize) {
         self.acquire(bytes, Direction::Upload, None).await;
     }
@@ -238,6 +305,31 @@ impl BandwidthController {
                 }
             }
         }
+
+        // Then the transfer's own limit. Its bucket is re-checked on every poll
+        // so changing or removing the limit applies to in-flight waits.
+        if let Some(id) = &transfer_id {
+            loop {
+                let wait = {
+                    let mut inner = self.inner.lock().await;
+                    match inner.transfers.get_mut(id) {
+                        Some(buckets) => match direction {
+                            Direction::Upload => buckets.upload.reserve(bytes),
+                            Direction::Download => buckets.download.reserve(bytes),
+                        },
+                        None => None,
+                    }
+                };
+
+                match wait {
+                    Some(delay) if !delay.is_zero() => {
+                        was_throttled = true;
+                        sleep(delay.min(TRANSFER_LIMIT_POLL)).await;
+                    }
+                    _ => break,
+                }
+            }
+        }
         
         // Track usage
         {
@@ -409,6 +501,27 @@ impl TokenBucket {
         }
     }
 
+    /// Take `bytes` once enough tokens have accumulated, without discarding
+    /// partial tokens while waiting. Requests larger than the bucket are
+    /// admitted once it is full and leave the bucket in debt.
+    fn reserve(&mut self, bytes: usize) -> Option<Duration> {
+        let limit = match self.limit_bytes_per_sec {
+            None => return None,
+            Some(limit) if limit <= f64::EPSILON => return None,
+            Some(limit) => limit,
+        };
+
+        self.refill(limit);
+
+        let required = (bytes as f64).min(self.capacity);
+        if self.tokens >= required {
+            self.tokens -= bytes as f64;
+            None
+        } else {
+            Some(Duration::from_secs_f64((required - self.tokens) / limit))
+        }
+    }
+
     fn refill(&mut self, limit: f64) {
         let now = Instant::now();
         let elapsed = now.duration_since(self.last_refill).as_secs_f64();
@@ -512,6 +625,56 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_token_bucket_reserve_keeps_partial_tokens() {
+        let mut bucket = TokenBucket::unlimited();
+        bucket.set_limit(1); // 1024 bytes/s, capacity 2048
+        bucket.tokens = 512.0;
+
+        // Not enough yet: wait for the remainder, tokens are kept
+        let wait = bucket.reserve(1024).unwrap();
+        assert!(wait.as_secs_f64() > 0.4 && wait.as_secs_f64() < 0.6);
+        assert!(bucket.tokens >= 512.0);
+
+        // Oversized requests are admitted once the bucket is full
+        bucket.tokens = bucket.capacity;
+        assert!(bucket.reserve(10_000).is_none());
+        assert!(bucket.tokens < 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_limits_set_and_remove() {
+        let controller = BandwidthController::new();
+        assert_eq!(controller.get_transfer_limits("t1").await, None);
+
+        controller.set_transfer_limits("t1", 50, 10).await;
+        assert_eq!(
+            controller.get_transfer_limits("t1").await,
+            Some(TransferRateLimit {
+                upload_kbps: 50,
+                download_kbps: 10,
+            })
+        );
+        // Other transfers and the global limits are unaffected
+        assert_eq!(controller.get_transfer_limits("t2").await, None);
+        assert_eq!(controller.get_limits().await, (0, 0));
+
+        controller.set_transfer_limits("t1", 0, 0).await;
+        assert_eq!(controller.get_transfer_limits("t1").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_transfer_is_not_throttled() {
+        let controller = BandwidthController::new();
+        controller.set_transfer_limits("slow", 0, 1).await;
+
+        // Upload direction of the limited transfer and other transfers pass immediately
+        let start = Instant::now();
+        controller.acquire_upload_for_transfer(1_000_000, "slow").await;
+        controller.acquire_download_for_transfer(1_000_000, "other").await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
     #[test]
     fn test_direction_as_str() {
         assert_eq!(Direction::Upload.as_str(), "upload");
@@ -0,0 +1,449 @@
+// Time-of-day bandwidth limits
+//
+// A schedule is a list of weekly windows, each with its own upload and
+// download caps, evaluated in local time. Outside every window the default
+// limits from settings apply. `BandwidthScheduler` re-evaluates the schedule
+// periodically and pushes the result into the global `BandwidthController`
+// only when it changes, so a boundary takes effect within one poll. A manual
+// override replaces the scheduled limits until the schedule is resumed.
+
+use crate::bandwidth::BandwidthController;
+use chrono::{Datelike, Local, Timelike};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tracing::info;
+
+/// How often the schedule is re-evaluated
+pub const SCHEDULE_POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+const MINUTES_PER_WEEK: u32 = 7 * MINUTES_PER_DAY;
+
+/// One weekly window. Matches `BandwidthScheduleEntry` in the frontend's
+/// settings store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BandwidthScheduleEntry {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    /// "HH:MM", 24-hour local time
+    pub start_time: String,
+    /// "HH:MM"; before `start_time` the window runs past midnight, equal to
+    /// it the window lasts the whole day
+    pub end_time: String,
+    /// Days the window starts on, 0 = Sunday
+    pub days_of_week: Vec<u8>,
+    /// KB/s, 0 = unlimited
+    pub upload_limit: u64,
+    /// KB/s, 0 = unlimited
+    pub download_limit: u64,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ScheduleError {
+    #[error("schedule entry '{entry}' has an invalid time '{value}' (expected HH:MM)")]
+    InvalidTime { entry: String, value: String },
+    #[error("schedule entry '{entry}' has an invalid day {day} (expected 0-6)")]
+    InvalidDay { entry: String, day: u8 },
+    #[error("schedule entry '{entry}' has no days")]
+    NoDays { entry: String },
+    #[error("schedule entries '{first}' and '{second}' overlap")]
+    Overlap { first: String, second: String },
+}
+
+/// Minutes since midnight for "HH:MM"
+fn parse_time(entry: &BandwidthScheduleEntry, value: &str) -> Result<u32, ScheduleError> {
+    let invalid = || ScheduleError::InvalidTime {
+        entry: entry.label(),
+        value: value.to_string(),
+    };
+    let (hours, minutes) = value.trim().split_once(':').ok_or_else(invalid)?;
+    let hours: u32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u32 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 23 || minutes > 59 {
+        return Err(invalid());
+    }
+    Ok(hours * 60 + minutes)
+}
+
+impl BandwidthScheduleEntry {
+    fn label(&self) -> String {
+        if self.name.is_empty() {
+            self.id.clone()
+        } else {
+            self.name.clone()
+        }
+    }
+
+    /// The minutes of the week this entry covers, as `[start, end)` ranges
+    /// measured from Sunday 00:00; `end` is past the week for a window
+    /// starting on Saturday and running past midnight
+    fn windows(&self) -> Result<Vec<(u32, u32)>, ScheduleError> {
+        let start = parse_time(self, &self.start_time)?;
+        let end = parse_time(self, &self.end_time)?;
+        if self.days_of_week.is_empty() {
+            return Err(ScheduleError::NoDays {
+                entry: self.label(),
+            });
+        }
+        let length = match end.cmp(&start) {
+            std::cmp::Ordering::Greater => end - start,
+            std::cmp::Ordering::Less => MINUTES_PER_DAY - start + end,
+            std::cmp::Ordering::Equal => MINUTES_PER_DAY,
+        };
+        let mut days = self.days_of_week.clone();
+        days.sort_unstable();
+        days.dedup();
+        let mut windows = Vec::new();
+        for day in days {
+            if day > 6 {
+                return Err(ScheduleError::InvalidDay {
+                    entry: self.label(),
+                    day,
+                });
+            }
+            let from = day as u32 * MINUTES_PER_DAY + start;
+            windows.push((from, from + length));
+        }
+        Ok(windows)
+    }
+}
+
+/// A validated schedule: enabled entries never overlap
+#[derive(Debug, Clone, Default)]
+pub struct BandwidthSchedule {
+    entries: Vec<BandwidthScheduleEntry>,
+    /// (start, end, index into `entries`), sorted by start; windows
+    /// wrapping past the end of the week are split in two
+    windows: Vec<(u32, u32, usize)>,
+    /// Minutes of the week at which some window starts or ends
+    boundaries: Vec<u32>,
+}
+
+impl BandwidthSchedule {
+    pub fn new(entries: Vec<BandwidthScheduleEntry>) -> Result<Self, ScheduleError> {
+        let mut windows = Vec::new();
+        let mut boundaries = Vec::new();
+        for (index, entry) in entries.iter().enumerate() {
+            if !entry.enabled {
+                continue;
+            }
+            for (start, end) in entry.windows()? {
+                boundaries.push(start);
+                boundaries.push(end % MINUTES_PER_WEEK);
+                if end > MINUTES_PER_WEEK {
+                    windows.push((start, MINUTES_PER_WEEK, index));
+                    windows.push((0, end - MINUTES_PER_WEEK, index));
+                } else {
+                    windows.push((start, end, index));
+                }
+            }
+        }
+        windows.sort_unstable();
+        for pair in windows.windows(2) {
+            let (_, first_end, first) = pair[0];
+            let (second_start, _, second) = pair[1];
+            if second_start < first_end {
+                return Err(ScheduleError::Overlap {
+                    first: entries[first].label(),
+                    second: entries[second].label(),
+                });
+            }
+        }
+        Ok(Self {
+            entries,
+            windows,
+            boundaries,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    /// Entry covering `minute_of_week` (0 = Sunday 00:00), if any
+    pub fn active_at(&self, minute_of_week: u32) -> Option<&BandwidthScheduleEntry> {
+        self.windows
+            .iter()
+            .find(|(start, end, _)| (*start..*end).contains(&minute_of_week))
+            .map(|(_, _, index)| &self.entries[*index])
+    }
+
+    /// Minutes from `minute_of_week` until the next window starts or ends
+    pub fn minutes_until_change(&self, minute_of_week: u32) -> Option<u32> {
+        self.boundaries
+            .iter()
+            .map(|boundary| (boundary + MINUTES_PER_WEEK - minute_of_week - 1) % MINUTES_PER_WEEK + 1)
+            .min()
+    }
+}
+
+/// Where the limits in force come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LimitSource {
+    Default,
+    Schedule,
+    Override,
+}
+
+/// What `get_bandwidth_stats` reports about the schedule
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BandwidthScheduleStatus {
+    pub enabled: bool,
+    /// Suspended by a manual override
+    pub suspended: bool,
+    pub source: LimitSource,
+    pub active_entry: Option<BandwidthScheduleEntry>,
+    pub upload_limit_kbps: u64,
+    pub download_limit_kbps: u64,
+    /// When the schedule next changes the limits (Unix ms)
+    pub next_change_at: Option<u64>,
+}
+
+struct SchedulerState {
+    schedule: BandwidthSchedule,
+    enabled: bool,
+    default_limits: (u64, u64),
+    override_limits: Option<(u64, u64)>,
+    applied: Option<(u64, u64)>,
+}
+
+impl SchedulerState {
+    fn evaluate(&self, minute_of_week: u32, now_ms: u64) -> BandwidthScheduleStatus {
+        let scheduling = self.enabled && !self.schedule.is_empty();
+        let active = if scheduling {
+            self.schedule.active_at(minute_of_week)
+        } else {
+            None
+        };
+        let (source, (upload, download)) = match (self.override_limits, active) {
+            (Some(limits), _) => (LimitSource::Override, limits),
+            (None, Some(entry)) => (
+                LimitSource::Schedule,
+                (entry.upload_limit, entry.download_limit),
+            ),
+            (None, None) => (LimitSource::Default, self.default_limits),
+        };
+        let next_change_at = if scheduling && self.override_limits.is_none() {
+            self.schedule
+                .minutes_until_change(minute_of_week)
+                .map(|minutes| now_ms - now_ms % 60_000 + minutes as u64 * 60_000)
+        } else {
+            None
+        };
+        BandwidthScheduleStatus {
+            enabled: self.enabled,
+            suspended: self.override_limits.is_some(),
+            source,
+            active_entry: active.cloned(),
+            upload_limit_kbps: upload,
+            download_limit_kbps: download,
+            next_change_at,
+        }
+    }
+}
+
+/// Local time as minutes since Sunday 00:00, and Unix ms
+fn local_now() -> (u32, u64) {
+    let now = Local::now();
+    let minute_of_week =
+        now.weekday().num_days_from_sunday() * MINUTES_PER_DAY + now.hour() * 60 + now.minute();
+    (minute_of_week, now.timestamp_millis().max(0) as u64)
+}
+
+/// Applies the schedule (or an override) to the global bandwidth limits
+pub struct BandwidthScheduler {
+    controller: Arc<BandwidthController>,
+    state: Mutex<SchedulerState>,
+}
+
+impl BandwidthScheduler {
+    pub fn new(controller: Arc<BandwidthController>) -> Self {
+        Self {
+            controller,
+            state: Mutex::new(SchedulerState {
+                schedule: BandwidthSchedule::default(),
+                enabled: false,
+                default_limits: (0, 0),
+                override_limits: None,
+                applied: None,
+            }),
+        }
+    }
+
+    /// Replace the schedule. Invalid or overlapping entries are rejected and
+    /// the previous schedule stays in force.
+    pub async fn set_schedule(
+        &self,
+        entries: Vec<BandwidthScheduleEntry>,
+        enabled: bool,
+    ) -> Result<BandwidthScheduleStatus, ScheduleError> {
+        let schedule = BandwidthSchedule::new(entries)?;
+        {
+            let mut state = self.state.lock().await;
+            state.schedule = schedule;
+            state.enabled = enabled;
+        }
+        Ok(self.tick().await)
+    }
+
+    /// Limits used outside every schedule window
+    pub async fn set_default_limits(&self, upload_kbps: u64, download_kbps: u64) -> BandwidthScheduleStatus {
+        self.state.lock().await.default_limits = (upload_kbps, download_kbps);
+        self.tick().await
+    }
+
+    /// Use these limits and ignore the schedule until `resume` is called
+    pub async fn override_limits(&self, upload_kbps: u64, download_kbps: u64) -> BandwidthScheduleStatus {
+        self.state.lock().await.override_limits = Some((upload_kbps, download_kbps));
+        self.tick().await
+    }
+
+    /// Drop a manual override and follow the schedule again
+    pub async fn resume(&self) -> BandwidthScheduleStatus {
+        self.state.lock().await.override_limits = None;
+        self.tick().await
+    }
+
+    pub async fn status(&self) -> BandwidthScheduleStatus {
+        let (minute_of_week, now_ms) = local_now();
+        self.state.lock().await.evaluate(minute_of_week, now_ms)
+    }
+
+    /// Re-evaluate the schedule and apply the limits if they changed
+    pub async fn tick(&self) -> BandwidthScheduleStatus {
+        let (minute_of_week, now_ms) = local_now();
+        let status = {
+            let mut state = self.state.lock().await;
+            let status = state.evaluate(minute_of_week, now_ms);
+            let limits = (status.upload_limit_kbps, status.download_limit_kbps);
+            if state.applied == Some(limits) {
+                return status;
+            }
+            state.applied = Some(limits);
+            status
+        };
+        match &status.active_entry {
+            Some(entry) if status.source == LimitSource::Schedule => {
+                info!("Bandwidth schedule '{}' is active", entry.label())
+            }
+            _ => info!("Bandwidth limits from {:?}", status.source),
+        }
+        self.controller
+            .set_limits(status.upload_limit_kbps, status.download_limit_kbps)
+            .await;
+        status
+    }
+
+    /// Keep the limits in step with the clock
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(SCHEDULE_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.tick().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, start: &str, end: &str, days: &[u8]) -> BandwidthScheduleEntry {
+        BandwidthScheduleEntry {
+            id: id.to_string(),
+            name: String::new(),
+            start_time: start.to_string(),
+            end_time: end.to_string(),
+            days_of_week: days.to_vec(),
+            upload_limit: 100,
+            download_limit: 200,
+            enabled: true,
+        }
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> u32 {
+        day * MINUTES_PER_DAY + hour * 60 + minute
+    }
+
+    #[test]
+    fn test_overnight_window_runs_into_the_next_day() {
+        // Saturday night into Sunday morning wraps around the week
+        let schedule = BandwidthSchedule::new(vec![
+            entry("night", "22:00", "06:00", &[6]),
+            entry("day", "09:00", "17:00", &[1, 2, 3, 4, 5]),
+        ])
+        .unwrap();
+        assert_eq!(schedule.active_at(at(6, 23, 0)).unwrap().id, "night");
+        assert_eq!(schedule.active_at(at(0, 5, 59)).unwrap().id, "night");
+        assert!(schedule.active_at(at(0, 6, 0)).is_none());
+        assert_eq!(schedule.active_at(at(3, 9, 0)).unwrap().id, "day");
+        assert!(schedule.active_at(at(3, 17, 0)).is_none());
+        assert_eq!(schedule.minutes_until_change(at(3, 16, 0)), Some(60));
+        assert_eq!(schedule.minutes_until_change(at(6, 21, 0)), Some(60));
+        // Saturday midnight is not a boundary of the night window
+        assert_eq!(schedule.minutes_until_change(at(6, 23, 0)), Some(7 * 60));
+    }
+
+    #[test]
+    fn test_invalid_and_overlapping_entries_are_rejected() {
+        assert!(matches!(
+            BandwidthSchedule::new(vec![
+                entry("night", "22:00", "06:00", &[1]),
+                entry("morning", "05:00", "09:00", &[2]),
+            ]),
+            Err(ScheduleError::Overlap { .. })
+        ));
+        assert!(matches!(
+            BandwidthSchedule::new(vec![entry("bad", "25:00", "06:00", &[1])]),
+            Err(ScheduleError::InvalidTime { .. })
+        ));
+        assert!(matches!(
+            BandwidthSchedule::new(vec![entry("bad", "01:00", "06:00", &[7])]),
+            Err(ScheduleError::InvalidDay { .. })
+        ));
+
+        // Disabled entries and back-to-back windows do not conflict
+        let mut disabled = entry("off", "00:00", "00:00", &[1]);
+        disabled.enabled = false;
+        assert!(BandwidthSchedule::new(vec![
+            disabled,
+            entry("a", "08:00", "12:00", &[1]),
+            entry("b", "12:00", "18:00", &[1]),
+        ])
+        .is_ok());
+    }
+
+    #[test]
+    fn test_override_suspends_the_schedule() {
+        let mut state = SchedulerState {
+            schedule: BandwidthSchedule::new(vec![entry("day", "09:00", "17:00", &[1])]).unwrap(),
+            enabled: true,
+            default_limits: (0, 0),
+            override_limits: None,
+            applied: None,
+        };
+        let status = state.evaluate(at(1, 10, 0), 0);
+        assert_eq!(status.source, LimitSource::Schedule);
+        assert_eq!((status.upload_limit_kbps, status.download_limit_kbps), (100, 200));
+        assert_eq!(state.evaluate(at(1, 18, 0), 0).source, LimitSource::Default);
+
+        state.override_limits = Some((5, 5));
+        let status = state.evaluate(at(1, 10, 0), 0);
+        assert_eq!(status.source, LimitSource::Override);
+        assert!(status.suspended && status.next_change_at.is_none());
+        assert_eq!(status.upload_limit_kbps, 5);
+    }
+}
@@ -0,0 +1,73 @@
+//! Call one method on the RPC socket of a headless node and print the
+//! result as JSON, for scripts.
+//!
+//! ```bash
+//! chiral_rpc_client get_dht_connected_peers
+//! chiral_rpc_client connect_to_peer '{"peerAddress": "/ip4/10.0.0.2/tcp/4001/p2p/12D3KooW..."}'
+//! ```
+
+use clap::Parser;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[derive(Parser, Debug)]
+#[command(name = "chiral_rpc_client", about = "Call a node over its RPC socket")]
+struct Args {
+    /// Socket of the node [default: rpc.sock in the data directory]
+    #[arg(long, value_name = "PATH")]
+    socket: Option<PathBuf>,
+
+    /// Data directory of the node
+    #[arg(long, value_name = "PATH")]
+    data_dir: Option<PathBuf>,
+
+    /// Method to call; `list_methods` lists them
+    method: String,
+
+    /// Parameters as a JSON object
+    #[arg(default_value = "{}")]
+    params: String,
+}
+
+#[cfg(unix)]
+#[tokio::main]
+async fn main() -> ExitCode {
+    use chiral_network::data_dirs::DataDirs;
+    use chiral_network::rpc;
+
+    let args = Args::parse();
+    let params: serde_json::Value = match serde_json::from_str(&args.params) {
+        Ok(params) => params,
+        Err(e) => {
+            eprintln!("Parameters are not JSON: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let socket = args.socket.unwrap_or_else(|| {
+        let dirs = match args.data_dir {
+            Some(dir) => DataDirs::new(dir),
+            None => DataDirs::current(),
+        };
+        dirs.root().join(rpc::SOCKET_NAME)
+    });
+    match rpc::call(&socket, &args.method, params).await {
+        Ok(result) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&result).expect("result serializes")
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn main() -> ExitCode {
+    let _ = Args::parse();
+    eprintln!("The RPC socket is a Unix domain socket and is not available here");
+    ExitCode::FAILURE
+}
@@ -0,0 +1,138 @@
+//! Run NAT test scenarios and show the history of runs recorded in the
+//! `NatTestResultStore`.
+
+use chiral_network::nat_test::lossy::LossyScenario;
+use chiral_network::nat_test::{format_history, NatTestResultStore};
+use clap::{Parser, ValueEnum};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Scenario {
+    /// Nodes in Docker containers with random packet loss
+    Lossy,
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "nat_test", about = "NAT test history")]
+struct Args {
+    /// Run a scenario and record the result before any other action
+    #[arg(long, value_enum)]
+    scenario: Option<Scenario>,
+
+    /// Image the scenario containers run
+    #[arg(long, value_name = "IMAGE", default_value = "chiral-network")]
+    image: String,
+
+    /// Nodes started besides the bootstrap node
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    nodes: usize,
+
+    /// Share of packets dropped in each container, in percent
+    #[arg(long, value_name = "PERCENT", default_value_t = 20)]
+    #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
+    loss: u8,
+
+    /// Seconds the messages have to reach every node
+    #[arg(long, value_name = "SECS", default_value_t = 120)]
+    timeout: u64,
+
+    /// Print the latest runs as a table
+    #[arg(long)]
+    show_history: bool,
+
+    /// Number of runs shown by --show-history
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    last: usize,
+
+    /// Compare the two latest runs; exits 1 if the success rate dropped
+    #[arg(long)]
+    compare_last_two: bool,
+
+    /// History database [default: ~/.chiral/nat_test_history.db]
+    #[arg(long, value_name = "PATH")]
+    db: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let path = args.db.unwrap_or_else(NatTestResultStore::default_path);
+    let store = match NatTestResultStore::open(&path) {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!("Failed to open {}: {}", path.display(), e);
+            return ExitCode::from(2);
+        }
+    };
+
+    if args.scenario.is_none() && !args.show_history && !args.compare_last_two {
+        eprintln!("Nothing to do; pass --scenario, --show-history or --compare-last-two");
+        return ExitCode::from(2);
+    }
+
+    let mut scenario_failed = false;
+    if args.scenario == Some(Scenario::Lossy) {
+        let scenario = LossyScenario {
+            image: args.image.clone(),
+            nodes: args.nodes,
+            loss_percent: args.loss,
+            timeout: Duration::from_secs(args.timeout),
+            ..Default::default()
+        };
+        let report = match scenario.run() {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("Lossy scenario did not run: {}", e);
+                return ExitCode::from(2);
+            }
+        };
+        print!("{}", report.summary());
+        let result = report.to_result();
+        scenario_failed = !result.succeeded();
+        if let Err(e) = store.append(result) {
+            eprintln!("Failed to record the run: {}", e);
+            return ExitCode::from(2);
+        }
+    }
+
+    if args.show_history {
+        match store.last(args.last) {
+            Ok(runs) if runs.is_empty() => println!("No NAT test runs recorded in {}", path.display()),
+            Ok(runs) => print!("{}", format_history(&runs)),
+            Err(e) => {
+                eprintln!("Failed to read history: {}", e);
+                return ExitCode::from(2);
+            }
+        }
+    }
+
+    if args.compare_last_two {
+        let comparison = match store.compare_last_two() {
+            Ok(Some(comparison)) => comparison,
+            Ok(None) => {
+                println!("Fewer than two runs recorded; nothing to compare");
+                return ExitCode::SUCCESS;
+            }
+            Err(e) => {
+                eprintln!("Failed to read history: {}", e);
+                return ExitCode::from(2);
+            }
+        };
+        print!("{}", format_history(&[comparison.latest.clone(), comparison.previous.clone()]));
+        println!("Success rate change: {:+.1} points", comparison.success_rate_delta() * 100.0);
+        if let Some(delta) = comparison.dcutr_rate_delta() {
+            println!("DCUtR rate change: {:+.1} points", delta * 100.0);
+        }
+        if comparison.regressed() {
+            eprintln!("Success rate dropped");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if scenario_failed {
+        eprintln!("Scenario failed");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
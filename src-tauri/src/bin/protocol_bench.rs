@@ -0,0 +1,54 @@
+//! Time noise, identify, GossipSub, request-response and Kademlia on an
+//! in-process network and print the results as JSON.
+
+use chiral_network::protocol_bench::ProtocolBenchmarkSuite;
+use clap::Parser;
+use std::process::ExitCode;
+
+#[derive(Parser, Debug)]
+#[command(name = "protocol_bench", about = "Local protocol benchmarks")]
+struct Args {
+    /// Handshakes, GossipSub messages and echo requests measured
+    #[arg(long, value_name = "N", default_value_t = 100)]
+    samples: usize,
+
+    /// Kademlia lookups measured
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    kad_samples: usize,
+
+    /// Hops of the Kademlia lookup
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    kad_hops: usize,
+
+    /// Size of GossipSub messages and echo requests
+    #[arg(long, value_name = "BYTES", default_value_t = 1024)]
+    payload_bytes: usize,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    if args.samples == 0 || args.kad_samples == 0 || args.kad_hops == 0 {
+        eprintln!("--samples, --kad-samples and --kad-hops must be at least 1");
+        return ExitCode::from(2);
+    }
+    let suite = ProtocolBenchmarkSuite {
+        samples: args.samples,
+        kad_samples: args.kad_samples,
+        kad_hops: args.kad_hops,
+        payload_bytes: args.payload_bytes,
+    };
+    match suite.run().await {
+        Ok(report) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).expect("report serializes")
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Benchmark failed: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -359,7 +359,9 @@ impl BitTorrentHandler {
                             progress_percentage: progress_pct,
                             download_speed_bps: download_speed,
                             upload_speed_bps: upload_speed,
+                            instantaneous_speed_bps: download_speed,
                             eta_seconds: eta,
+                            stalled: false,
                             active_sources: 1,
                             timestamp: current_timestamp_ms(),
                         });
@@ -0,0 +1,343 @@
+//! Signed bootstrap node manifests.
+//!
+//! A bootstrap manifest is a CBOR document listing bootstrap nodes together
+//! with its validity window. The Ed25519 signature covers the CBOR encoding of
+//! every field except `signature` itself, so the list cannot be altered or
+//! replayed after it expires without the issuer's key.
+//!
+//! Verification failures are hard errors: a node configured with a trusted key
+//! never falls back to an unverified list.
+
+use crate::config::ChiralConfig;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tracing::info;
+
+/// Manifest format version produced by this build
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Timeout for fetching a manifest over HTTP
+const MANIFEST_FETCH_TIMEOUT_SECS: u64 = 15;
+
+#[derive(Debug, Error)]
+pub enum ManifestError {
+    #[error("failed to encode manifest: {0}")]
+    Encode(String),
+
+    #[error("failed to decode manifest: {0}")]
+    Decode(String),
+
+    #[error("invalid trusted key: {0}")]
+    InvalidKey(String),
+
+    #[error("manifest signature is invalid")]
+    InvalidSignature,
+
+    #[error("manifest expired at {expires_at} (now {now})")]
+    Expired { expires_at: u64, now: u64 },
+
+    #[error("manifest is not valid yet (issued at {issued_at}, now {now})")]
+    NotYetValid { issued_at: u64, now: u64 },
+
+    #[error("unsupported manifest version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("manifest lists no bootstrap nodes")]
+    Empty,
+
+    #[error("bootstrap_manifest_url is set but manifest_trusted_key is missing")]
+    MissingTrustedKey,
+
+    #[error("failed to fetch manifest: {0}")]
+    Fetch(String),
+}
+
+/// A single bootstrap node entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BootstrapNode {
+    /// Full multiaddr including the `/p2p/<peer id>` suffix
+    pub multiaddr: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+}
+
+/// Signed list of bootstrap nodes as distributed by the network operators
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BootstrapManifest {
+    pub version: u32,
+    /// Unix seconds
+    pub issued_at: u64,
+    /// Unix seconds; the manifest is rejected after this point
+    pub expires_at: u64,
+    pub nodes: Vec<BootstrapNode>,
+    /// Ed25519 signature, encoded as a CBOR byte string
+    #[serde(with = "serde_bytes")]
+    pub signature: Vec<u8>,
+}
+
+/// The signed portion of a manifest (everything except the signature)
+#[derive(Serialize)]
+struct UnsignedManifest<'a> {
+    version: u32,
+    issued_at: u64,
+    expires_at: u64,
+    nodes: &'a [BootstrapNode],
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl BootstrapManifest {
+    /// Bytes covered by the signature: CBOR of all fields except `signature`
+    pub fn signing_bytes(&self) -> Result<Vec<u8>, ManifestError> {
+        let unsigned = UnsignedManifest {
+            version: self.version,
+            issued_at: self.issued_at,
+            expires_at: self.expires_at,
+            nodes: &self.nodes,
+        };
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&unsigned, &mut buf)
+            .map_err(|e| ManifestError::Encode(e.to_string()))?;
+        Ok(buf)
+    }
+
+    /// Create and sign a manifest
+    pub fn new_signed(
+        nodes: Vec<BootstrapNode>,
+        issued_at: u64,
+        expires_at: u64,
+        key: &SigningKey,
+    ) -> Result<Self, ManifestError> {
+        let mut manifest = Self {
+            version: MANIFEST_VERSION,
+            issued_at,
+            expires_at,
+            nodes,
+            signature: Vec::new(),
+        };
+        let signature = key.sign(&manifest.signing_bytes()?);
+        manifest.signature = signature.to_bytes().to_vec();
+        Ok(manifest)
+    }
+
+    pub fn to_cbor(&self) -> Result<Vec<u8>, ManifestError> {
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(self, &mut buf)
+            .map_err(|e| ManifestError::Encode(e.to_string()))?;
+        Ok(buf)
+    }
+
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, ManifestError> {
+        ciborium::de::from_reader(bytes).map_err(|e| ManifestError::Decode(e.to_string()))
+    }
+}
+
+/// A bootstrap manifest whose signature and validity window have been checked.
+///
+/// The only way to obtain one is through [`SignedBootstrapList::verify`].
+#[derive(Debug, Clone)]
+pub struct SignedBootstrapList {
+    manifest: BootstrapManifest,
+}
+
+impl SignedBootstrapList {
+    /// Verify `manifest` against `trusted_key` at time `now` (Unix seconds)
+    pub fn verify(
+        manifest: BootstrapManifest,
+        trusted_key: &VerifyingKey,
+        now: u64,
+    ) -> Result<Self, ManifestError> {
+        if manifest.version != MANIFEST_VERSION {
+            return Err(ManifestError::UnsupportedVersion(manifest.version));
+        }
+
+        let signature = Signature::from_slice(&manifest.signature)
+            .map_err(|_| ManifestError::InvalidSignature)?;
+        let verified = trusted_key.verify(&manifest.signing_bytes()?, &signature);
+        crate::crypto::audit::record(
+            crate::crypto::CryptoOperation::verification(verified.is_ok()),
+            None,
+            format!("bootstrap manifest issued at {}", manifest.issued_at),
+        );
+        verified.map_err(|_| ManifestError::InvalidSignature)?;
+
+        if manifest.expires_at <= now {
+            return Err(ManifestError::Expired {
+                expires_at: manifest.expires_at,
+                now,
+            });
+        }
+        if manifest.issued_at > now {
+            return Err(ManifestError::NotYetValid {
+                issued_at: manifest.issued_at,
+                now,
+            });
+        }
+        if manifest.nodes.is_empty() {
+            return Err(ManifestError::Empty);
+        }
+
+        Ok(Self { manifest })
+    }
+
+    /// Decode CBOR bytes and verify them in one step
+    pub fn from_cbor(bytes: &[u8], trusted_key: &VerifyingKey) -> Result<Self, ManifestError> {
+        Self::verify(BootstrapManifest::from_cbor(bytes)?, trusted_key, now_secs())
+    }
+
+    pub fn manifest(&self) -> &BootstrapManifest {
+        &self.manifest
+    }
+
+    pub fn nodes(&self) -> &[BootstrapNode] {
+        &self.manifest.nodes
+    }
+
+    /// Multiaddrs in the form expected by `DhtService::new`
+    pub fn multiaddrs(&self) -> Vec<String> {
+        self.manifest
+            .nodes
+            .iter()
+            .map(|node| node.multiaddr.clone())
+            .collect()
+    }
+}
+
+/// Parse a hex-encoded Ed25519 public key
+pub fn parse_trusted_key(hex_key: &str) -> Result<VerifyingKey, ManifestError> {
+    let bytes = hex::decode(hex_key.trim().trim_start_matches("0x"))
+        .map_err(|e| ManifestError::InvalidKey(e.to_string()))?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| ManifestError::InvalidKey("expected 32 bytes".to_string()))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| ManifestError::InvalidKey(e.to_string()))
+}
+
+/// Fetch and verify the bootstrap manifest configured in `config`.
+///
+/// Returns `Ok(None)` when no manifest URL is configured. Any failure after
+/// that point (missing key, network error, bad signature, expiry) is an error.
+pub async fn fetch_signed_bootstrap_list(
+    config: &ChiralConfig,
+) -> Result<Option<SignedBootstrapList>, ManifestError> {
+    let Some(url) = config.bootstrap_manifest_url.as_deref() else {
+        return Ok(None);
+    };
+    let trusted_key = config
+        .manifest_trusted_key
+        .as_deref()
+        .ok_or(ManifestError::MissingTrustedKey)
+        .and_then(parse_trusted_key)?;
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(MANIFEST_FETCH_TIMEOUT_SECS))
+        .build()
+        .map_err(|e| ManifestError::Fetch(e.to_string()))?;
+    let bytes = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(|e| ManifestError::Fetch(e.to_string()))?
+        .bytes()
+        .await
+        .map_err(|e| ManifestError::Fetch(e.to_string()))?;
+
+    let list = SignedBootstrapList::from_cbor(&bytes, &trusted_key)?;
+    info!(
+        "Loaded signed bootstrap manifest from {} ({} nodes, expires at {})",
+        url,
+        list.nodes().len(),
+        list.manifest().expires_at
+    );
+    Ok(Some(list))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> SigningKey {
+        SigningKey::from_bytes(&[7u8; 32])
+    }
+
+    fn nodes() -> Vec<BootstrapNode> {
+        vec![BootstrapNode {
+            multiaddr: "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWFYTuQ2FY8tXRtFKfpXkTSipTF55mZkLntwtN1nHu83qE"
+                .to_string(),
+            region: Some("us-east".to_string()),
+        }]
+    }
+
+    #[test]
+    fn test_signed_manifest_round_trip() {
+        let manifest = BootstrapManifest::new_signed(nodes(), 100, 1_000, &key()).unwrap();
+        let bytes = manifest.to_cbor().unwrap();
+        let decoded = BootstrapManifest::from_cbor(&bytes).unwrap();
+        assert_eq!(decoded, manifest);
+
+        let list = SignedBootstrapList::verify(decoded, &key().verifying_key(), 500).unwrap();
+        assert_eq!(list.multiaddrs(), vec![nodes()[0].multiaddr.clone()]);
+    }
+
+    #[test]
+    fn test_tampered_manifest_is_rejected() {
+        let mut manifest = BootstrapManifest::new_signed(nodes(), 100, 1_000, &key()).unwrap();
+        manifest.nodes[0].multiaddr = "/ip4/6.6.6.6/tcp/4001".to_string();
+        assert!(matches!(
+            SignedBootstrapList::verify(manifest, &key().verifying_key(), 500),
+            Err(ManifestError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_wrong_key_is_rejected() {
+        let manifest = BootstrapManifest::new_signed(nodes(), 100, 1_000, &key()).unwrap();
+        let other = SigningKey::from_bytes(&[9u8; 32]).verifying_key();
+        assert!(matches!(
+            SignedBootstrapList::verify(manifest, &other, 500),
+            Err(ManifestError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_expired_manifest_is_rejected() {
+        let manifest = BootstrapManifest::new_signed(nodes(), 100, 1_000, &key()).unwrap();
+        assert!(matches!(
+            SignedBootstrapList::verify(manifest, &key().verifying_key(), 1_000),
+            Err(ManifestError::Expired { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_trusted_key() {
+        let hex_key = hex::encode(key().verifying_key().to_bytes());
+        assert_eq!(parse_trusted_key(&hex_key).unwrap(), key().verifying_key());
+        assert!(parse_trusted_key("abcd").is_err());
+        assert!(parse_trusted_key("not hex").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_url_without_key_is_an_error() {
+        let config = ChiralConfig {
+            bootstrap_manifest_url: Some("https://example.invalid/bootstrap.cbor".to_string()),
+            manifest_trusted_key: None,
+            ..Default::default()
+        };
+        assert!(matches!(
+            fetch_signed_bootstrap_list(&config).await,
+            Err(ManifestError::MissingTrustedKey)
+        ));
+        assert!(fetch_signed_bootstrap_list(&ChiralConfig::default())
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
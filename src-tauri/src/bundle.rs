@@ -0,0 +1,391 @@
+//! Directory bundles: content-addressed manifests for multi-file shares.
+//!
+//! A bundle manifest lists the relative path, content hash and size of every
+//! file under a shared directory. The serialized manifest is published like
+//! any other file, so a single hash identifies the whole directory. Members
+//! are fetched individually, which makes partial downloads (a subset of
+//! paths) and resuming (skip members already present and verified) cheap.
+
+use crate::shared_files::hash_file;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Current manifest format version
+pub const BUNDLE_MANIFEST_VERSION: u32 = 1;
+
+/// MIME type the manifest is published with
+pub const BUNDLE_MIME_TYPE: &str = "application/vnd.chiral.bundle+json";
+
+/// Upper bound on members so a hostile manifest cannot exhaust memory
+pub const MAX_BUNDLE_FILES: usize = 100_000;
+
+/// One file inside a bundle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleEntry {
+    /// Path relative to the bundle root, `/`-separated
+    pub path: String,
+    /// SHA-256 of the file content, hex encoded
+    pub content_hash: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Manifest describing a shared directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleManifest {
+    pub version: u32,
+    /// Name of the shared directory
+    pub name: String,
+    /// Seconds since the Unix epoch
+    pub created_at: u64,
+    /// Members sorted by path
+    pub files: Vec<BundleEntry>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// A manifest built from a local directory, with the on-disk source of each member
+#[derive(Debug, Clone)]
+pub struct LocalBundle {
+    pub manifest: BundleManifest,
+    /// Absolute path of each entry in `manifest.files`, same order
+    pub sources: Vec<PathBuf>,
+}
+
+impl BundleManifest {
+    pub fn total_size(&self) -> u64 {
+        self.files.iter().map(|f| f.size).sum()
+    }
+
+    /// Serialize the manifest. The content hash of these bytes is the bundle hash.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        serde_json::to_vec_pretty(self).map_err(|e| format!("Failed to serialize manifest: {}", e))
+    }
+
+    /// Parse and validate a manifest fetched from the network
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let manifest: Self = serde_json::from_slice(bytes)
+            .map_err(|e| format!("Invalid bundle manifest: {}", e))?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Content hash of serialized manifest bytes
+    pub fn hash_bytes(bytes: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(bytes))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.version != BUNDLE_MANIFEST_VERSION {
+            return Err(format!("Unsupported bundle manifest version {}", self.version));
+        }
+        if self.files.len() > MAX_BUNDLE_FILES {
+            return Err(format!(
+                "Bundle lists {} files, more than the supported {}",
+                self.files.len(),
+                MAX_BUNDLE_FILES
+            ));
+        }
+        let mut seen = HashSet::new();
+        for entry in &self.files {
+            validate_relative_path(&entry.path)?;
+            if entry.content_hash.len() != 64
+                || !entry.content_hash.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                return Err(format!("Invalid content hash for {}", entry.path));
+            }
+            if !seen.insert(entry.path.as_str()) {
+                return Err(format!("Duplicate bundle path {}", entry.path));
+            }
+        }
+        Ok(())
+    }
+
+    /// Entries to download. `None` selects everything; otherwise each selection is
+    /// a file path or a directory prefix (`docs` selects `docs/a.txt`).
+    pub fn select(&self, selected: Option<&[String]>) -> Result<Vec<&BundleEntry>, String> {
+        let Some(selected) = selected else {
+            return Ok(self.files.iter().collect());
+        };
+        let mut entries = Vec::new();
+        for selection in selected {
+            let selection = selection.trim_matches('/');
+            let prefix = format!("{}/", selection);
+            let mut matched = false;
+            for entry in &self.files {
+                if entry.path == selection || entry.path.starts_with(&prefix) {
+                    matched = true;
+                    if !entries.contains(&entry) {
+                        entries.push(entry);
+                    }
+                }
+            }
+            if !matched {
+                return Err(format!("{} is not part of this bundle", selection));
+            }
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+}
+
+/// Reject absolute paths and anything that could escape the destination directory
+fn validate_relative_path(path: &str) -> Result<(), String> {
+    if path.is_empty() || path.starts_with('/') || path.contains('\\') || path.contains(':') {
+        return Err(format!("Unsafe bundle path {:?}", path));
+    }
+    for component in path.split('/') {
+        if component.is_empty() || component == "." || component == ".." {
+            return Err(format!("Unsafe bundle path {:?}", path));
+        }
+    }
+    Ok(())
+}
+
+/// Where a bundle member is written under `dest_dir`
+pub fn entry_destination(dest_dir: &Path, relative: &str) -> Result<PathBuf, String> {
+    validate_relative_path(relative)?;
+    Ok(relative
+        .split('/')
+        .fold(dest_dir.to_path_buf(), |path, component| path.join(component)))
+}
+
+/// Whether `path` already holds the member's content (used to resume bundles)
+pub async fn is_entry_complete(path: &Path, entry: &BundleEntry) -> bool {
+    match tokio::fs::metadata(path).await {
+        Ok(meta) if meta.is_file() && meta.len() == entry.size => {}
+        _ => return false,
+    }
+    matches!(hash_file(path).await, Ok(hash) if hash == entry.content_hash)
+}
+
+/// Recursively list regular files under `root` as (absolute, relative) paths.
+/// Symlinks are skipped so a bundle never reaches outside its directory.
+async fn collect_files(root: &Path) -> Result<Vec<(PathBuf, String)>, String> {
+    let mut files = Vec::new();
+    let mut pending = vec![(root.to_path_buf(), String::new())];
+    while let Some((dir, prefix)) = pending.pop() {
+        let mut reader = tokio::fs::read_dir(&dir)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        while let Some(item) = reader
+            .next_entry()
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+        {
+            let Some(name) = item.file_name().to_str().map(str::to_string) else {
+                return Err(format!("Non UTF-8 file name in {}", dir.display()));
+            };
+            let relative = if prefix.is_empty() {
+                name
+            } else {
+                format!("{}/{}", prefix, name)
+            };
+            let file_type = item
+                .file_type()
+                .await
+                .map_err(|e| format!("Failed to stat {}: {}", item.path().display(), e))?;
+            if file_type.is_dir() {
+                pending.push((item.path(), relative));
+            } else if file_type.is_file() {
+                files.push((item.path(), relative));
+            }
+        }
+    }
+    files.sort_by(|a, b| a.1.cmp(&b.1));
+    Ok(files)
+}
+
+/// Build the manifest for a local directory, hashing every member
+pub async fn build_manifest(root: &Path) -> Result<LocalBundle, String> {
+    let meta = tokio::fs::metadata(root)
+        .await
+        .map_err(|e| format!("Failed to open {}: {}", root.display(), e))?;
+    if !meta.is_dir() {
+        return Err(format!("{} is not a directory", root.display()));
+    }
+
+    let files = collect_files(root).await?;
+    if files.is_empty() {
+        return Err(format!("{} contains no files", root.display()));
+    }
+    if files.len() > MAX_BUNDLE_FILES {
+        return Err(format!(
+            "{} contains {} files, more than the supported {}",
+            root.display(),
+            files.len(),
+            MAX_BUNDLE_FILES
+        ));
+    }
+
+    let mut entries = Vec::with_capacity(files.len());
+    let mut sources = Vec::with_capacity(files.len());
+    for (source, relative) in files {
+        validate_relative_path(&relative)?;
+        let size = tokio::fs::metadata(&source)
+            .await
+            .map_err(|e| format!("Failed to stat {}: {}", source.display(), e))?
+            .len();
+        entries.push(BundleEntry {
+            path: relative,
+            content_hash: hash_file(&source).await?,
+            size,
+            mime_type: None,
+            metadata: BTreeMap::new(),
+        });
+        sources.push(source);
+    }
+
+    let name = root
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("bundle")
+        .to_string();
+    Ok(LocalBundle {
+        manifest: BundleManifest {
+            version: BUNDLE_MANIFEST_VERSION,
+            name,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            files: entries,
+            metadata: BTreeMap::new(),
+        },
+        sources,
+    })
+}
+
+/// A member that could not be downloaded
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleFileError {
+    pub path: String,
+    pub error: String,
+}
+
+/// Aggregate progress of a bundle download
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleProgress {
+    pub manifest_hash: String,
+    pub name: String,
+    pub total_files: usize,
+    pub completed_files: usize,
+    /// Members that were already present and verified (resumed)
+    pub skipped_files: usize,
+    pub failed_files: Vec<BundleFileError>,
+    pub total_bytes: u64,
+    pub downloaded_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_file: Option<String>,
+}
+
+impl BundleProgress {
+    pub fn new(manifest_hash: &str, manifest: &BundleManifest, entries: &[&BundleEntry]) -> Self {
+        Self {
+            manifest_hash: manifest_hash.to_string(),
+            name: manifest.name.clone(),
+            total_files: entries.len(),
+            completed_files: 0,
+            skipped_files: 0,
+            failed_files: Vec::new(),
+            total_bytes: entries.iter().map(|e| e.size).sum(),
+            downloaded_bytes: 0,
+            current_file: None,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.completed_files == self.total_files
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str) -> BundleEntry {
+        BundleEntry {
+            path: path.to_string(),
+            content_hash: "ab".repeat(32),
+            size: 1,
+            mime_type: None,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    fn manifest(paths: &[&str]) -> BundleManifest {
+        BundleManifest {
+            version: BUNDLE_MANIFEST_VERSION,
+            name: "photos".to_string(),
+            created_at: 0,
+            files: paths.iter().map(|p| entry(p)).collect(),
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_rejects_paths_escaping_destination() {
+        for bad in ["../etc/passwd", "/abs", "a//b", "a/./b", "C:x", "a\\b", ""] {
+            let parsed = BundleManifest::from_bytes(&manifest(&[bad]).to_bytes().unwrap());
+            assert!(parsed.is_err(), "{:?} should be rejected", bad);
+        }
+        assert!(BundleManifest::from_bytes(&manifest(&["a", "a"]).to_bytes().unwrap()).is_err());
+        assert_eq!(
+            entry_destination(Path::new("/dl"), "a/b.txt").unwrap(),
+            Path::new("/dl").join("a").join("b.txt")
+        );
+    }
+
+    #[test]
+    fn test_select_files_and_directories() {
+        let m = manifest(&["a.txt", "docs/x.md", "docs/y.md", "docsx/z.md"]);
+        assert_eq!(m.select(None).unwrap().len(), 4);
+
+        let selected = m.select(Some(&["docs".to_string()])).unwrap();
+        let paths: Vec<&str> = selected.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["docs/x.md", "docs/y.md"]);
+
+        let selected = m
+            .select(Some(&["docs/x.md".to_string(), "docs".to_string()]))
+            .unwrap();
+        assert_eq!(selected.len(), 2);
+        assert!(m.select(Some(&["missing".to_string()])).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_build_manifest_and_resume_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("share");
+        tokio::fs::create_dir_all(root.join("sub")).await.unwrap();
+        tokio::fs::write(root.join("b.txt"), b"bravo").await.unwrap();
+        tokio::fs::write(root.join("sub").join("a.txt"), b"alpha").await.unwrap();
+
+        let local = build_manifest(&root).await.unwrap();
+        let m = &local.manifest;
+        assert_eq!(m.name, "share");
+        let paths: Vec<&str> = m.files.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["b.txt", "sub/a.txt"]);
+        assert_eq!(m.total_size(), 10);
+        assert_eq!(local.sources[1], root.join("sub").join("a.txt"));
+
+        let bytes = m.to_bytes().unwrap();
+        assert_eq!(&BundleManifest::from_bytes(&bytes).unwrap(), m);
+        assert_eq!(BundleManifest::hash_bytes(&bytes).len(), 64);
+
+        let target = root.join("sub").join("a.txt");
+        assert!(is_entry_complete(&target, &m.files[1]).await);
+        tokio::fs::write(&target, b"alphx").await.unwrap();
+        assert!(!is_entry_complete(&target, &m.files[1]).await);
+        assert!(!is_entry_complete(&root.join("nope"), &m.files[1]).await);
+    }
+}
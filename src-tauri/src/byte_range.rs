@@ -0,0 +1,139 @@
+//! Byte-range reads over chunked content.
+//!
+//! A range request names an `(offset, length)` slice of a file. The provider
+//! validates it against the content length and serves only the chunks that
+//! cover the slice; the requester verifies each of those chunks against the
+//! file's chunk hash list before cutting the requested bytes out of them.
+
+use sha2::{Digest, Sha256};
+
+/// Largest slice a single range request may ask for
+pub const MAX_RANGE_LENGTH: u64 = 16 * 1024 * 1024;
+
+/// Range requests a provider serves concurrently for one peer
+pub const MAX_CONCURRENT_RANGE_REQUESTS_PER_PEER: u32 = 4;
+
+/// Check that `offset..offset + length` is a non-empty slice of the content
+pub fn validate_range(offset: u64, length: u64, content_length: u64) -> Result<(), String> {
+    if length == 0 {
+        return Err("Range length must be greater than zero".to_string());
+    }
+    if length > MAX_RANGE_LENGTH {
+        return Err(format!(
+            "Range length {} exceeds the maximum of {} bytes",
+            length, MAX_RANGE_LENGTH
+        ));
+    }
+    match offset.checked_add(length) {
+        Some(end) if end <= content_length => Ok(()),
+        _ => Err(format!(
+            "Range {}+{} is outside the content length {}",
+            offset, length, content_length
+        )),
+    }
+}
+
+/// First chunk index and number of chunks covering a validated range
+pub fn covering_chunks(offset: u64, length: u64, chunk_size: usize) -> (u32, u32) {
+    let chunk_size = chunk_size as u64;
+    let first = offset / chunk_size;
+    let last = (offset + length - 1) / chunk_size;
+    (first as u32, (last - first + 1) as u32)
+}
+
+/// Verify the covering chunks against the hash list and return the requested bytes.
+///
+/// `chunks` must be the contiguous chunks starting at `first_chunk`, as served
+/// by the provider.
+pub fn assemble_range(
+    chunks: &[Vec<u8>],
+    first_chunk: u32,
+    chunk_hashes: &[String],
+    chunk_size: usize,
+    offset: u64,
+    length: u64,
+) -> Result<Vec<u8>, String> {
+    let (expected_first, expected_count) = covering_chunks(offset, length, chunk_size);
+    if first_chunk != expected_first || chunks.len() != expected_count as usize {
+        return Err(format!(
+            "Provider served chunks {}+{}, expected {}+{}",
+            first_chunk,
+            chunks.len(),
+            expected_first,
+            expected_count
+        ));
+    }
+
+    let mut data = Vec::with_capacity(chunks.iter().map(Vec::len).sum());
+    for (i, chunk) in chunks.iter().enumerate() {
+        let index = first_chunk as usize + i;
+        let expected = chunk_hashes
+            .get(index)
+            .ok_or_else(|| format!("Chunk {} is not in the hash list", index))?;
+        if format!("{:x}", Sha256::digest(chunk)) != *expected {
+            return Err(format!("Chunk {} failed hash verification", index));
+        }
+        data.extend_from_slice(chunk);
+    }
+
+    let start = (offset - first_chunk as u64 * chunk_size as u64) as usize;
+    let end = start + length as usize;
+    if end > data.len() {
+        return Err(format!(
+            "Provider served {} bytes, range needs {}",
+            data.len(),
+            end
+        ));
+    }
+    data.truncate(end);
+    Ok(data.split_off(start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(data: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(data))
+    }
+
+    #[test]
+    fn test_validate_range() {
+        assert!(validate_range(0, 10, 10).is_ok());
+        assert!(validate_range(5, 6, 10).is_err());
+        assert!(validate_range(3, 0, 10).is_err());
+        assert!(validate_range(u64::MAX, 2, 10).is_err());
+        assert!(validate_range(0, MAX_RANGE_LENGTH + 1, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_covering_chunks() {
+        assert_eq!(covering_chunks(0, 4, 4), (0, 1));
+        assert_eq!(covering_chunks(3, 2, 4), (0, 2));
+        assert_eq!(covering_chunks(8, 1, 4), (2, 1));
+        assert_eq!(covering_chunks(5, 8, 4), (1, 3));
+    }
+
+    #[test]
+    fn test_assemble_range_verifies_chunks() {
+        let file: Vec<u8> = (0u8..10).collect();
+        let chunks: Vec<Vec<u8>> = file.chunks(4).map(<[u8]>::to_vec).collect();
+        let hashes: Vec<String> = chunks.iter().map(|c| hash(c)).collect();
+
+        let served = chunks[1..3].to_vec();
+        assert_eq!(
+            assemble_range(&served, 1, &hashes, 4, 5, 4).unwrap(),
+            vec![5, 6, 7, 8]
+        );
+        assert_eq!(
+            assemble_range(&chunks[2..3], 2, &hashes, 4, 9, 1).unwrap(),
+            vec![9]
+        );
+
+        let mut tampered = served.clone();
+        tampered[0][0] ^= 0xff;
+        assert!(assemble_range(&tampered, 1, &hashes, 4, 5, 4).is_err());
+        // Serving more chunks than the range needs is rejected too
+        assert!(assemble_range(&chunks, 0, &hashes, 4, 5, 4).is_err());
+    }
+}
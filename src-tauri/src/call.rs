@@ -0,0 +1,358 @@
+//! Voice call signaling between two peers.
+//!
+//! Calls are negotiated over a request-response protocol: the caller sends an
+//! `Invite` carrying its WebRTC SDP offer, the callee answers with `Accept`
+//! (and its SDP answer) or `Reject`, and either side ends the call with
+//! `Hangup`. The SDP is produced and consumed by the frontend's
+//! `RTCPeerConnection`, which owns the microphone and the Opus audio track;
+//! the backend only carries the signaling and tracks call state.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+/// How long an invite may ring before the caller gives up
+pub const RING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Largest signaling frame accepted from the wire; SDP is a few KiB at most
+const MAX_CALL_FRAME: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallSignalingProtocol;
+
+impl AsRef<str> for CallSignalingProtocol {
+    fn as_ref(&self) -> &str {
+        crate::protocol::CALL_SIGNALING_PROTOCOL
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CallRequest {
+    Invite {
+        caller_id: String,
+        session_id: String,
+        sdp_offer: String,
+    },
+    Hangup { session_id: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CallResponse {
+    Accept { sdp_answer: String },
+    Reject { reason: String },
+    /// Reply to `Hangup`
+    Ack,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallDirection {
+    Outgoing,
+    Incoming,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallState {
+    /// Invite sent or received, not yet answered
+    Ringing,
+    Active,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallInfo {
+    pub session_id: String,
+    pub peer_id: String,
+    pub direction: CallDirection,
+    pub state: CallState,
+    /// Unix seconds
+    pub started_at: u64,
+    pub answered_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CallError {
+    #[error("already in a call")]
+    Busy,
+    #[error("unknown call session {0}")]
+    UnknownSession(String),
+    #[error("call session {0} already exists")]
+    DuplicateSession(String),
+    #[error("call {0} is not ringing")]
+    NotRinging(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallEventKind {
+    Incoming,
+    Accepted,
+    Rejected,
+    Ended,
+}
+
+impl CallEventKind {
+    /// Name of the Tauri event this is emitted as
+    pub fn event_name(&self) -> &'static str {
+        match self {
+            CallEventKind::Incoming => "incoming-call",
+            CallEventKind::Accepted => "call-accepted",
+            CallEventKind::Rejected => "call-rejected",
+            CallEventKind::Ended => "call-ended",
+        }
+    }
+}
+
+/// Payload of the `incoming-call`, `call-accepted`, `call-rejected` and
+/// `call-ended` events
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallEvent {
+    #[serde(skip)]
+    pub kind: CallEventKind,
+    pub session_id: String,
+    pub peer_id: String,
+    /// The caller's offer on `incoming-call`, the callee's answer on `call-accepted`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sdp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl CallEvent {
+    pub fn new(kind: CallEventKind, session_id: &str, peer_id: &str) -> Self {
+        Self {
+            kind,
+            session_id: session_id.to_string(),
+            peer_id: peer_id.to_string(),
+            sdp: None,
+            reason: None,
+        }
+    }
+
+    pub fn with_sdp(mut self, sdp: String) -> Self {
+        self.sdp = Some(sdp);
+        self
+    }
+
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Calls this node is part of. Only one call may be ringing or active at a
+/// time; further invites are rejected as busy.
+#[derive(Debug, Default)]
+pub struct CallStateManager {
+    calls: HashMap<String, CallInfo>,
+}
+
+impl CallStateManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn insert(
+        &mut self,
+        session_id: &str,
+        peer_id: &str,
+        direction: CallDirection,
+    ) -> Result<(), CallError> {
+        if self.calls.contains_key(session_id) {
+            return Err(CallError::DuplicateSession(session_id.to_string()));
+        }
+        if !self.calls.is_empty() {
+            return Err(CallError::Busy);
+        }
+        self.calls.insert(
+            session_id.to_string(),
+            CallInfo {
+                session_id: session_id.to_string(),
+                peer_id: peer_id.to_string(),
+                direction,
+                state: CallState::Ringing,
+                started_at: now_secs(),
+                answered_at: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// We are inviting `peer_id`
+    pub fn start_outgoing(&mut self, session_id: &str, peer_id: &str) -> Result<(), CallError> {
+        self.insert(session_id, peer_id, CallDirection::Outgoing)
+    }
+
+    /// `peer_id` is inviting us
+    pub fn start_incoming(&mut self, session_id: &str, peer_id: &str) -> Result<(), CallError> {
+        self.insert(session_id, peer_id, CallDirection::Incoming)
+    }
+
+    /// A ringing call was answered, by us or by the remote side
+    pub fn mark_active(&mut self, session_id: &str) -> Result<CallInfo, CallError> {
+        let call = self
+            .calls
+            .get_mut(session_id)
+            .ok_or_else(|| CallError::UnknownSession(session_id.to_string()))?;
+        if call.state != CallState::Ringing {
+            return Err(CallError::NotRinging(session_id.to_string()));
+        }
+        call.state = CallState::Active;
+        call.answered_at = Some(now_secs());
+        Ok(call.clone())
+    }
+
+    /// Forget a call that was rejected or hung up
+    pub fn end(&mut self, session_id: &str) -> Option<CallInfo> {
+        self.calls.remove(session_id)
+    }
+
+    /// Forget every call with `peer_id`, e.g. after the connection dropped
+    pub fn end_with_peer(&mut self, peer_id: &str) -> Vec<CallInfo> {
+        let sessions: Vec<String> = self
+            .calls
+            .values()
+            .filter(|c| c.peer_id == peer_id)
+            .map(|c| c.session_id.clone())
+            .collect();
+        sessions
+            .iter()
+            .filter_map(|session_id| self.calls.remove(session_id))
+            .collect()
+    }
+
+    pub fn get(&self, session_id: &str) -> Option<&CallInfo> {
+        self.calls.get(session_id)
+    }
+
+    pub fn calls(&self) -> Vec<CallInfo> {
+        self.calls.values().cloned().collect()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CallSignalingCodec;
+
+async fn read_call_frame<T, M>(io: &mut T) -> std::io::Result<M>
+where
+    T: futures::AsyncRead + Unpin + Send,
+    M: serde::de::DeserializeOwned,
+{
+    use futures::AsyncReadExt;
+    let mut len_buf = [0u8; 4];
+    io.read_exact(&mut len_buf).await?;
+    let len = u32::from_le_bytes(len_buf) as usize;
+    if len > MAX_CALL_FRAME {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("call signaling frame of {} bytes exceeds the limit", len),
+        ));
+    }
+    let mut data = vec![0u8; len];
+    io.read_exact(&mut data).await?;
+    serde_json::from_slice(&data)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+}
+
+async fn write_call_frame<T, M>(io: &mut T, message: &M) -> std::io::Result<()>
+where
+    T: futures::AsyncWrite + Unpin + Send,
+    M: Serialize,
+{
+    use futures::AsyncWriteExt;
+    let data = serde_json::to_vec(message)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+    io.write_all(&(data.len() as u32).to_le_bytes()).await?;
+    io.write_all(&data).await?;
+    io.flush().await
+}
+
+#[async_trait::async_trait]
+impl libp2p::request_response::Codec for CallSignalingCodec {
+    type Protocol = CallSignalingProtocol;
+    type Request = CallRequest;
+    type Response = CallResponse;
+
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> std::io::Result<Self::Request>
+    where
+        T: futures::AsyncRead + Unpin + Send,
+    {
+        read_call_frame(io).await
+    }
+
+    async fn read_response<T>(&mut self, _: &Self::Protocol, io: &mut T) -> std::io::Result<Self::Response>
+    where
+        T: futures::AsyncRead + Unpin + Send,
+    {
+        read_call_frame(io).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        request: Self::Request,
+    ) -> std::io::Result<()>
+    where
+        T: futures::AsyncWrite + Unpin + Send,
+    {
+        write_call_frame(io, &request).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        response: Self::Response,
+    ) -> std::io::Result<()>
+    where
+        T: futures::AsyncWrite + Unpin + Send,
+    {
+        write_call_frame(io, &response).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_call_is_busy_until_first_ends() {
+        let mut calls = CallStateManager::new();
+        calls.start_outgoing("s1", "peer-a").unwrap();
+        assert_eq!(calls.start_incoming("s2", "peer-b"), Err(CallError::Busy));
+        assert_eq!(
+            calls.start_outgoing("s1", "peer-b"),
+            Err(CallError::DuplicateSession("s1".to_string()))
+        );
+
+        let active = calls.mark_active("s1").unwrap();
+        assert_eq!(active.state, CallState::Active);
+        assert!(active.answered_at.is_some());
+        assert_eq!(calls.mark_active("s1"), Err(CallError::NotRinging("s1".to_string())));
+
+        assert!(calls.end("s1").is_some());
+        calls.start_incoming("s2", "peer-b").unwrap();
+        assert_eq!(calls.get("s2").unwrap().direction, CallDirection::Incoming);
+    }
+
+    #[test]
+    fn test_end_with_peer_drops_only_that_peer() {
+        let mut calls = CallStateManager::new();
+        calls.start_incoming("s1", "peer-a").unwrap();
+        assert!(calls.end_with_peer("peer-b").is_empty());
+        let ended = calls.end_with_peer("peer-a");
+        assert_eq!(ended.len(), 1);
+        assert_eq!(ended[0].session_id, "s1");
+        assert!(calls.calls().is_empty());
+    }
+}
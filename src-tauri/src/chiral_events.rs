@@ -0,0 +1,677 @@
+//! One Tauri event, `chiral-event`, for what the node reports to the UI.
+//!
+//! Every event carries a `kind` and a `payload`:
+//!
+//! ```json
+//! {"kind": "peer_connected", "payload": {"peerId": "12D3KooW...", "address": "/ip4/..."}}
+//! ```
+//!
+//! Nothing is emitted until the frontend asks for it with
+//! `subscribe_events(kinds)`, and `unsubscribe_events(kinds)` stops a kind
+//! again. High-frequency kinds are throttled: `transfer_progress` and
+//! `direct_transfer_progress` to 4 events a second per transfer and
+//! `bandwidth` to 1 a second, or to the `maxRate` given when subscribing;
+//! other kinds refuse a `maxRate`. An event over the rate is held back and
+//! sent when the interval is up unless a newer one replaces it, so the last
+//! state always arrives. A transfer's final progress event is never held.
+//!
+//! The payload structs below are the schema; `src/lib/types/chiralEvent.ts`
+//! mirrors them for the frontend. The older per-topic events
+//! (`dht_peer_connected`, `transfer:progress`, ...) are still emitted.
+
+use crate::dht::models::{NatConfidence, NatReachabilityState};
+use crate::dht::{DhtEvent, DhtService};
+use crate::monitoring::DashboardStats;
+use crate::transfer_events::{TransferEvent, TransferProgressEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, Weak};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+use tracing::error;
+
+pub const EVENT_NAME: &str = "chiral-event";
+
+/// How often `bandwidth` and `bootstrap` are sampled
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Throttle entries kept before old ones are pruned
+const MAX_THROTTLE_ENTRIES: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    PeerDiscovered,
+    PeerConnected,
+    PeerDisconnected,
+    NatStatus,
+    Relay,
+    Bootstrap,
+    Transfer,
+    TransferProgress,
+    DirectTransferProgress,
+    Bandwidth,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 10] = [
+        EventKind::PeerDiscovered,
+        EventKind::PeerConnected,
+        EventKind::PeerDisconnected,
+        EventKind::NatStatus,
+        EventKind::Relay,
+        EventKind::Bootstrap,
+        EventKind::Transfer,
+        EventKind::TransferProgress,
+        EventKind::DirectTransferProgress,
+        EventKind::Bandwidth,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventKind::PeerDiscovered => "peer_discovered",
+            EventKind::PeerConnected => "peer_connected",
+            EventKind::PeerDisconnected => "peer_disconnected",
+            EventKind::NatStatus => "nat_status",
+            EventKind::Relay => "relay",
+            EventKind::Bootstrap => "bootstrap",
+            EventKind::Transfer => "transfer",
+            EventKind::TransferProgress => "transfer_progress",
+            EventKind::DirectTransferProgress => "direct_transfer_progress",
+            EventKind::Bandwidth => "bandwidth",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == name)
+            .ok_or_else(|| format!("unknown event kind: {}", name))
+    }
+
+    /// Events per second when the subscriber sets no rate; `None` is
+    /// unlimited and takes no rate
+    pub fn default_max_rate(self) -> Option<f64> {
+        match self {
+            EventKind::TransferProgress | EventKind::DirectTransferProgress => Some(4.0),
+            EventKind::Bandwidth => Some(1.0),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", content = "payload", rename_all = "snake_case")]
+pub enum ChiralEvent {
+    PeerDiscovered(PeerDiscoveredPayload),
+    PeerConnected(PeerConnectedPayload),
+    PeerDisconnected(PeerDisconnectedPayload),
+    NatStatus(NatStatusPayload),
+    Relay(RelayPayload),
+    Bootstrap(BootstrapPayload),
+    /// Every transfer lifecycle event except progress
+    Transfer(TransferEvent),
+    TransferProgress(TransferProgressEvent),
+    /// Progress of a file sent straight to this node by a peer
+    DirectTransferProgress(DirectTransferProgressPayload),
+    Bandwidth(BandwidthPayload),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerDiscoveredPayload {
+    pub peer_id: String,
+    pub addresses: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerConnectedPayload {
+    pub peer_id: String,
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerDisconnectedPayload {
+    pub peer_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NatStatusPayload {
+    pub state: NatReachabilityState,
+    pub confidence: NatConfidence,
+    pub last_error: Option<String>,
+    pub summary: Option<String>,
+}
+
+/// A relay reputation event, e.g. `RelayReservationAccepted`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelayPayload {
+    pub peer_id: String,
+    pub event_type: String,
+    pub impact: f64,
+    pub data: serde_json::Value,
+}
+
+/// Emitted when the number of connected bootstrap nodes changes
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapPayload {
+    pub connected: usize,
+    pub configured: usize,
+    pub dht_ready: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectTransferProgressPayload {
+    pub transfer_id: String,
+    pub peer_id: String,
+    pub filename: String,
+    pub bytes_received: u64,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BandwidthPayload {
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    /// Bytes per second
+    pub download_rate: f64,
+    pub upload_rate: f64,
+}
+
+impl ChiralEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            ChiralEvent::PeerDiscovered(_) => EventKind::PeerDiscovered,
+            ChiralEvent::PeerConnected(_) => EventKind::PeerConnected,
+            ChiralEvent::PeerDisconnected(_) => EventKind::PeerDisconnected,
+            ChiralEvent::NatStatus(_) => EventKind::NatStatus,
+            ChiralEvent::Relay(_) => EventKind::Relay,
+            ChiralEvent::Bootstrap(_) => EventKind::Bootstrap,
+            ChiralEvent::Transfer(_) => EventKind::Transfer,
+            ChiralEvent::TransferProgress(_) => EventKind::TransferProgress,
+            ChiralEvent::DirectTransferProgress(_) => EventKind::DirectTransferProgress,
+            ChiralEvent::Bandwidth(_) => EventKind::Bandwidth,
+        }
+    }
+
+    /// Events of one kind with the same key share a rate limit
+    fn throttle_key(&self) -> &str {
+        match self {
+            ChiralEvent::TransferProgress(progress) => &progress.transfer_id,
+            ChiralEvent::DirectTransferProgress(progress) => &progress.transfer_id,
+            _ => "",
+        }
+    }
+
+    /// The last progress event of a transfer, which is never held back
+    fn is_final(&self) -> bool {
+        match self {
+            ChiralEvent::TransferProgress(progress) => {
+                progress.total_bytes > 0 && progress.downloaded_bytes >= progress.total_bytes
+            }
+            ChiralEvent::DirectTransferProgress(progress) => {
+                progress.bytes_received >= progress.total_bytes
+            }
+            _ => false,
+        }
+    }
+
+    /// The unified event for a DHT event, if it has one
+    pub fn from_dht(event: &DhtEvent) -> Option<Self> {
+        Some(match event {
+            DhtEvent::PeerDiscovered { peer_id, addresses } => {
+                Self::PeerDiscovered(PeerDiscoveredPayload {
+                    peer_id: peer_id.clone(),
+                    addresses: addresses.clone(),
+                })
+            }
+            DhtEvent::PeerConnected { peer_id, address } => {
+                Self::PeerConnected(PeerConnectedPayload {
+                    peer_id: peer_id.clone(),
+                    address: address.clone(),
+                })
+            }
+            DhtEvent::PeerDisconnected { peer_id } => {
+                Self::PeerDisconnected(PeerDisconnectedPayload {
+                    peer_id: peer_id.clone(),
+                })
+            }
+            DhtEvent::NatStatus {
+                state,
+                confidence,
+                last_error,
+                summary,
+            } => Self::NatStatus(NatStatusPayload {
+                state: *state,
+                confidence: *confidence,
+                last_error: last_error.clone(),
+                summary: summary.clone(),
+            }),
+            DhtEvent::ReputationEvent {
+                peer_id,
+                event_type,
+                impact,
+                data,
+            } => Self::Relay(RelayPayload {
+                peer_id: peer_id.clone(),
+                event_type: event_type.clone(),
+                impact: *impact,
+                data: data.clone(),
+            }),
+            DhtEvent::FileTransferProgress(progress) => {
+                Self::DirectTransferProgress(DirectTransferProgressPayload {
+                    transfer_id: progress.transfer_id.clone(),
+                    peer_id: progress.peer_id.clone(),
+                    filename: progress.filename.clone(),
+                    bytes_received: progress.bytes_received,
+                    total_bytes: progress.total_bytes,
+                })
+            }
+            _ => return None,
+        })
+    }
+
+    pub fn from_transfer(event: &TransferEvent) -> Self {
+        match event {
+            TransferEvent::Progress(progress) => Self::TransferProgress(progress.clone()),
+            other => Self::Transfer(other.clone()),
+        }
+    }
+
+    pub fn bandwidth(stats: &DashboardStats) -> Self {
+        Self::Bandwidth(BandwidthPayload {
+            bytes_received: stats.bytes_received,
+            bytes_sent: stats.bytes_sent,
+            download_rate: stats.download_rate,
+            upload_rate: stats.upload_rate,
+        })
+    }
+}
+
+impl BootstrapPayload {
+    pub fn from_stats(stats: &DashboardStats) -> Self {
+        Self {
+            connected: stats.bootstrap_connected,
+            configured: stats.bootstrap_configured,
+            dht_ready: stats.dht_ready,
+        }
+    }
+}
+
+/// Events of one kind sharing a rate limit
+type ThrottleKey = (EventKind, String);
+
+/// What `EventSubscriptions::admit` decided for an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Admission {
+    Emit,
+    /// Not subscribed
+    Drop,
+    /// Over the rate and held back; `take_held(key)` returns it once
+    /// `after` has passed
+    Hold {
+        key: ThrottleKey,
+        after: Duration,
+    },
+    /// Over the rate and replaced an event that is already held
+    Replace,
+}
+
+/// The kinds the frontend subscribed to, managed as Tauri state
+#[derive(Default)]
+pub struct EventSubscriptions {
+    inner: Mutex<Subscriptions>,
+    /// The node `spawn_stats_events` samples
+    stats_node: Mutex<Weak<DhtService>>,
+}
+
+#[derive(Default)]
+struct Subscriptions {
+    /// Subscribed kinds with the least time between two events
+    intervals: HashMap<EventKind, Option<Duration>>,
+    last_emitted: HashMap<ThrottleKey, Instant>,
+    /// The newest event over the rate, per key
+    held: HashMap<ThrottleKey, ChiralEvent>,
+}
+
+impl EventSubscriptions {
+    /// Emit `kinds` from now on, at most `max_rate` events a second per
+    /// kind and key, or at the kind's default rate. Only throttled kinds
+    /// take a `max_rate`.
+    pub fn subscribe(&self, kinds: &[EventKind], max_rate: Option<f64>) -> Result<(), String> {
+        if max_rate.is_some_and(|rate| !rate.is_finite() || rate <= 0.0) {
+            return Err("maxRate must be a positive number".to_string());
+        }
+        if max_rate.is_some() {
+            if let Some(kind) = kinds.iter().find(|kind| kind.default_max_rate().is_none()) {
+                return Err(format!(
+                    "{} is not rate limited and takes no maxRate",
+                    kind.as_str()
+                ));
+            }
+        }
+        let mut inner = self.inner.lock().unwrap();
+        for &kind in kinds {
+            let rate = max_rate.or(kind.default_max_rate());
+            let interval = rate
+                .filter(|rate| *rate > 0.0)
+                .map(|rate| Duration::from_secs_f64(1.0 / rate));
+            inner.intervals.insert(kind, interval);
+        }
+        Ok(())
+    }
+
+    pub fn unsubscribe(&self, kinds: &[EventKind]) {
+        let mut inner = self.inner.lock().unwrap();
+        for kind in kinds {
+            inner.intervals.remove(kind);
+        }
+        inner
+            .last_emitted
+            .retain(|(kind, _), _| !kinds.contains(kind));
+        inner.held.retain(|(kind, _), _| !kinds.contains(kind));
+    }
+
+    /// Subscribed kinds, sorted
+    pub fn subscribed(&self) -> Vec<EventKind> {
+        let mut kinds: Vec<EventKind> = self
+            .inner
+            .lock()
+            .unwrap()
+            .intervals
+            .keys()
+            .copied()
+            .collect();
+        kinds.sort();
+        kinds
+    }
+
+    pub fn is_subscribed(&self, kind: EventKind) -> bool {
+        self.inner.lock().unwrap().intervals.contains_key(&kind)
+    }
+
+    /// Make `dht` the node stats are sampled from; false if it is already
+    fn sample(&self, dht: &Weak<DhtService>) -> bool {
+        let mut node = self.stats_node.lock().unwrap();
+        if node.ptr_eq(dht) {
+            return false;
+        }
+        *node = dht.clone();
+        true
+    }
+
+    fn is_sampling(&self, dht: &Weak<DhtService>) -> bool {
+        self.stats_node.lock().unwrap().ptr_eq(dht)
+    }
+
+    /// Whether `event` is subscribed and within its rate at `now`; an
+    /// emitted event counts against the rate
+    pub fn admit(&self, event: &ChiralEvent, now: Instant) -> Admission {
+        let kind = event.kind();
+        let mut inner = self.inner.lock().unwrap();
+        let Some(&interval) = inner.intervals.get(&kind) else {
+            return Admission::Drop;
+        };
+        let Some(interval) = interval else {
+            return Admission::Emit;
+        };
+        let key = (kind, event.throttle_key().to_string());
+        if !event.is_final() {
+            if let Some(last) = inner.last_emitted.get(&key) {
+                let since = now.saturating_duration_since(*last);
+                if since < interval {
+                    return match inner.held.insert(key.clone(), event.clone()) {
+                        Some(_) => Admission::Replace,
+                        None => Admission::Hold {
+                            key,
+                            after: interval - since,
+                        },
+                    };
+                }
+            }
+        }
+        if inner.last_emitted.len() >= MAX_THROTTLE_ENTRIES {
+            inner
+                .last_emitted
+                .retain(|_, last| now.saturating_duration_since(*last) < Duration::from_secs(60));
+        }
+        // A held event is older than this one
+        inner.held.remove(&key);
+        inner.last_emitted.insert(key, now);
+        Admission::Emit
+    }
+
+    /// The event held back under `key`, if it was not sent or replaced by a
+    /// newer one since; it counts against the rate at `now`
+    pub fn take_held(&self, key: &ThrottleKey, now: Instant) -> Option<ChiralEvent> {
+        let mut inner = self.inner.lock().unwrap();
+        let event = inner.held.remove(key)?;
+        inner.last_emitted.insert(key.clone(), now);
+        Some(event)
+    }
+}
+
+/// Whether the app should build events of `kind` at all
+pub fn wants(app: &AppHandle, kind: EventKind) -> bool {
+    app.try_state::<EventSubscriptions>()
+        .is_some_and(|subscriptions| subscriptions.is_subscribed(kind))
+}
+
+/// Emit `event` as `chiral-event` if the app manages `EventSubscriptions`
+/// and the event is admitted
+pub fn emit(app: &AppHandle, event: ChiralEvent) {
+    let Some(subscriptions) = app.try_state::<EventSubscriptions>() else {
+        return;
+    };
+    match subscriptions.admit(&event, Instant::now()) {
+        Admission::Emit => send(app, &event),
+        Admission::Hold { key, after } => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                tokio::time::sleep(after).await;
+                let held = app
+                    .try_state::<EventSubscriptions>()
+                    .and_then(|subscriptions| subscriptions.take_held(&key, Instant::now()));
+                if let Some(event) = held {
+                    send(&app, &event);
+                }
+            });
+        }
+        Admission::Drop | Admission::Replace => {}
+    }
+}
+
+fn send(app: &AppHandle, event: &ChiralEvent) {
+    if let Err(e) = app.emit(EVENT_NAME, event) {
+        error!("Failed to emit {}: {}", EVENT_NAME, e);
+    }
+}
+
+/// Sample `dht` for `bandwidth` and `bootstrap` events while either is
+/// subscribed, until the node is dropped or another node is sampled.
+/// Does nothing if `dht` is sampled already.
+pub fn spawn_stats_events(app: AppHandle, dht: Weak<DhtService>) {
+    let Some(subscriptions) = app.try_state::<EventSubscriptions>() else {
+        return;
+    };
+    if !subscriptions.sample(&dht) {
+        return;
+    }
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(STATS_INTERVAL);
+        let mut last_bootstrap = None;
+        loop {
+            interval.tick().await;
+            let sampled = app
+                .try_state::<EventSubscriptions>()
+                .is_some_and(|subscriptions| subscriptions.is_sampling(&dht));
+            if !sampled {
+                break;
+            }
+            let bandwidth = wants(&app, EventKind::Bandwidth);
+            let bootstrap = wants(&app, EventKind::Bootstrap);
+            if !bootstrap {
+                // A new subscriber gets the current state first
+                last_bootstrap = None;
+            }
+            if !bandwidth && !bootstrap {
+                if dht.strong_count() == 0 {
+                    break;
+                }
+                continue;
+            }
+            let Some(node) = dht.upgrade() else {
+                break;
+            };
+            let stats = node.dashboard_stats().await;
+            drop(node);
+
+            if bandwidth {
+                emit(&app, ChiralEvent::bandwidth(&stats));
+            }
+            let payload = BootstrapPayload::from_stats(&stats);
+            if bootstrap && last_bootstrap.as_ref() != Some(&payload) {
+                last_bootstrap = Some(payload.clone());
+                emit(&app, ChiralEvent::Bootstrap(payload));
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(transfer_id: &str, bytes_received: u64) -> ChiralEvent {
+        ChiralEvent::DirectTransferProgress(DirectTransferProgressPayload {
+            transfer_id: transfer_id.to_string(),
+            peer_id: "peer".to_string(),
+            filename: "same.bin".to_string(),
+            bytes_received,
+            total_bytes: 100,
+        })
+    }
+
+    #[test]
+    fn test_subscriptions_and_rates() {
+        let event = ChiralEvent::PeerDisconnected(PeerDisconnectedPayload {
+            peer_id: "12D3KooW".to_string(),
+        });
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"kind": "peer_disconnected", "payload": {"peerId": "12D3KooW"}})
+        );
+        for kind in EventKind::ALL {
+            assert_eq!(EventKind::parse(kind.as_str()), Ok(kind));
+            assert_eq!(serde_json::to_value(kind).unwrap(), kind.as_str());
+        }
+        assert!(EventKind::parse("everything").is_err());
+
+        let subscriptions = EventSubscriptions::default();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        assert_eq!(
+            subscriptions.admit(&event, start),
+            Admission::Drop,
+            "nothing by default"
+        );
+
+        assert!(subscriptions
+            .subscribe(&[EventKind::PeerDisconnected], Some(2.0))
+            .is_err());
+        assert!(subscriptions
+            .subscribe(&[EventKind::Bandwidth], Some(0.0))
+            .is_err());
+        assert!(subscriptions.subscribed().is_empty());
+        subscriptions
+            .subscribe(
+                &[
+                    EventKind::PeerDisconnected,
+                    EventKind::DirectTransferProgress,
+                ],
+                None,
+            )
+            .unwrap();
+        assert_eq!(subscriptions.admit(&event, start), Admission::Emit);
+        assert_eq!(
+            subscriptions.admit(&event, start),
+            Admission::Emit,
+            "unlimited kind"
+        );
+        assert_eq!(
+            subscriptions.admit(&progress("a", 10), start),
+            Admission::Emit
+        );
+        let key = (EventKind::DirectTransferProgress, "a".to_string());
+        assert_eq!(
+            subscriptions.admit(&progress("a", 20), at(100)),
+            Admission::Hold {
+                key: key.clone(),
+                after: Duration::from_millis(150)
+            }
+        );
+        assert_eq!(
+            subscriptions.admit(&progress("a", 30), at(200)),
+            Admission::Replace
+        );
+        // Same file name, another transfer
+        assert_eq!(
+            subscriptions.admit(&progress("b", 10), at(100)),
+            Admission::Emit
+        );
+        // The newest held event is sent when its interval is up
+        let Some(ChiralEvent::DirectTransferProgress(held)) =
+            subscriptions.take_held(&key, at(250))
+        else {
+            panic!("nothing held");
+        };
+        assert_eq!(held.bytes_received, 30);
+        assert_eq!(
+            subscriptions.take_held(&key, at(250)).map(|e| e.kind()),
+            None
+        );
+        // The final event is sent at once and drops what is held
+        assert!(matches!(
+            subscriptions.admit(&progress("a", 40), at(300)),
+            Admission::Hold { .. }
+        ));
+        assert_eq!(
+            subscriptions.admit(&progress("a", 100), at(310)),
+            Admission::Emit
+        );
+        assert!(subscriptions.take_held(&key, at(600)).is_none());
+
+        subscriptions
+            .subscribe(&[EventKind::DirectTransferProgress], Some(1.0))
+            .unwrap();
+        assert_eq!(
+            subscriptions.admit(&progress("c", 10), at(900)),
+            Admission::Emit
+        );
+        assert_eq!(
+            subscriptions.admit(&progress("c", 20), at(1250)),
+            Admission::Hold {
+                key: (EventKind::DirectTransferProgress, "c".to_string()),
+                after: Duration::from_millis(650)
+            }
+        );
+        assert_eq!(
+            subscriptions.admit(&progress("c", 30), at(1900)),
+            Admission::Emit
+        );
+
+        subscriptions.unsubscribe(&[EventKind::PeerDisconnected]);
+        assert_eq!(
+            subscriptions.subscribed(),
+            vec![EventKind::DirectTransferProgress]
+        );
+        assert_eq!(subscriptions.admit(&event, start), Admission::Drop);
+    }
+}
@@ -0,0 +1,314 @@
+//! Chunk request pipelining for download sources.
+//!
+//! Waiting for each chunk before requesting the next leaves a high-latency
+//! source idle for a full round trip per chunk. A `RequestWindow` keeps
+//! several requests outstanding per source instead. In adaptive mode the
+//! window starts small, grows by one request whenever a full window of
+//! completions was faster than the one before, and halves on a timeout.
+//! Requests are also bounded by the bytes in flight, so a slow source never
+//! holds more than `max_in_flight_bytes` of buffered responses.
+
+use crate::config::DownloadsConfig;
+use crate::memory_budget;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// Requests outstanding per source by default; the ceiling in adaptive mode
+pub const DEFAULT_PIPELINE_DEPTH: usize = 16;
+
+/// Bytes in flight per source by default
+pub const DEFAULT_MAX_IN_FLIGHT_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Window an adaptive source starts with
+const INITIAL_ADAPTIVE_WINDOW: usize = 2;
+
+/// Throughput gain over the previous window that earns one more request
+const GROWTH_THRESHOLD: f64 = 1.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineConfig {
+    /// Requests kept outstanding per source; the ceiling in adaptive mode
+    pub max_depth: usize,
+    /// Grow the window while throughput improves, shrink it on timeouts
+    pub adaptive: bool,
+    /// Cap on the bytes requested from one source and not yet received
+    pub max_in_flight_bytes: u64,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_PIPELINE_DEPTH,
+            adaptive: true,
+            max_in_flight_bytes: DEFAULT_MAX_IN_FLIGHT_BYTES,
+        }
+    }
+}
+
+impl From<&DownloadsConfig> for PipelineConfig {
+    fn from(config: &DownloadsConfig) -> Self {
+        Self {
+            max_depth: config.pipeline_depth.max(1),
+            adaptive: config.adaptive_pipeline,
+            max_in_flight_bytes: config.max_in_flight_mib.max(1) as u64 * 1024 * 1024,
+        }
+    }
+}
+
+/// Pipelining state of one source, as shown in the transfer details
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestWindowInfo {
+    pub window: usize,
+    pub max_depth: usize,
+    pub adaptive: bool,
+    pub in_flight: usize,
+    pub in_flight_bytes: u64,
+    pub timeouts: u64,
+}
+
+/// Outstanding-request window of one source
+#[derive(Debug, Clone)]
+pub struct RequestWindow {
+    config: PipelineConfig,
+    window: usize,
+    in_flight: usize,
+    in_flight_bytes: u64,
+    /// Completions measured since `round_start`
+    round_start: Option<Instant>,
+    round_bytes: u64,
+    round_completions: usize,
+    /// Throughput of the last full round, bytes per second
+    last_round_bps: Option<f64>,
+    timeouts: u64,
+}
+
+impl RequestWindow {
+    pub fn new(config: PipelineConfig) -> Self {
+        let max_depth = config.max_depth.max(1);
+        let window = if config.adaptive {
+            INITIAL_ADAPTIVE_WINDOW.min(max_depth)
+        } else {
+            max_depth
+        };
+        Self {
+            config: PipelineConfig { max_depth, ..config },
+            window,
+            in_flight: 0,
+            in_flight_bytes: 0,
+            round_start: None,
+            round_bytes: 0,
+            round_completions: 0,
+            last_round_bps: None,
+            timeouts: 0,
+        }
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Whether a request for `bytes` more may be sent now. A source with
+    /// nothing in flight may always send, even a chunk above the byte cap.
+    pub fn can_send(&self, bytes: u64) -> bool {
+        self.in_flight < self.window
+            && (self.in_flight == 0
+                || self.in_flight_bytes + bytes <= self.config.max_in_flight_bytes)
+    }
+
+    pub fn on_send(&mut self, bytes: u64, now: Instant) {
+        self.in_flight += 1;
+        self.in_flight_bytes += bytes;
+        memory_budget::add_in_flight(bytes);
+        self.round_start.get_or_insert(now);
+    }
+
+    /// A request for `bytes` was answered
+    pub fn on_complete(&mut self, bytes: u64, now: Instant) {
+        self.release(bytes);
+        self.round_bytes += bytes;
+        self.round_completions += 1;
+        if self.round_completions < self.window {
+            return;
+        }
+
+        let elapsed = self
+            .round_start
+            .map(|start| now.duration_since(start).as_secs_f64())
+            .unwrap_or_default();
+        if elapsed > 0.0 {
+            let bps = self.round_bytes as f64 / elapsed;
+            if self.config.adaptive
+                && self.last_round_bps.is_some_and(|last| bps > last * GROWTH_THRESHOLD)
+            {
+                self.window = (self.window + 1).min(self.config.max_depth);
+            }
+            self.last_round_bps = Some(bps);
+        }
+        self.round_bytes = 0;
+        self.round_completions = 0;
+        self.round_start = (self.in_flight > 0).then_some(now);
+    }
+
+    /// A request timed out; the window halves in adaptive mode
+    pub fn on_timeout(&mut self, bytes: u64) {
+        self.release(bytes);
+        self.timeouts += 1;
+        if self.config.adaptive {
+            self.window = (self.window / 2).max(1);
+        }
+        self.last_round_bps = None;
+        self.round_start = None;
+        self.round_bytes = 0;
+        self.round_completions = 0;
+    }
+
+    /// A request failed for a reason other than a timeout
+    pub fn on_failure(&mut self, bytes: u64) {
+        self.release(bytes);
+    }
+
+    fn release(&mut self, bytes: u64) {
+        let released = bytes.min(self.in_flight_bytes);
+        self.in_flight = self.in_flight.saturating_sub(1);
+        self.in_flight_bytes -= released;
+        memory_budget::release_in_flight(released);
+    }
+
+    pub fn info(&self) -> RequestWindowInfo {
+        RequestWindowInfo {
+            window: self.window,
+            max_depth: self.config.max_depth,
+            adaptive: self.config.adaptive,
+            in_flight: self.in_flight,
+            in_flight_bytes: self.in_flight_bytes,
+            timeouts: self.timeouts,
+        }
+    }
+}
+
+impl Drop for RequestWindow {
+    /// Requests still outstanding when a source is dropped are not waited for
+    fn drop(&mut self) {
+        memory_budget::release_in_flight(self.in_flight_bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+    use std::time::Duration;
+
+    const CHUNK: u64 = 256 * 1024;
+
+    struct Outcome {
+        elapsed: Duration,
+        max_in_flight: usize,
+    }
+
+    /// Fetch `chunks` through `window` from a source with round trip `rtt`
+    /// whose link carries `bytes_per_sec`, one response at a time
+    fn simulate(window: &mut RequestWindow, rtt: Duration, bytes_per_sec: f64, chunks: usize) -> Outcome {
+        let start = Instant::now();
+        let transmit = Duration::from_secs_f64(CHUNK as f64 / bytes_per_sec);
+        let (mut now, mut link_free) = (Duration::ZERO, Duration::ZERO);
+        let mut completions = BinaryHeap::new();
+        let (mut sent, mut done, mut max_in_flight) = (0, 0, 0);
+        while done < chunks {
+            while sent < chunks && window.can_send(CHUNK) {
+                window.on_send(CHUNK, start + now);
+                link_free = (now + rtt / 2).max(link_free) + transmit;
+                completions.push(Reverse(link_free + rtt / 2));
+                sent += 1;
+                max_in_flight = max_in_flight.max(window.in_flight());
+            }
+            let Reverse(at) = completions.pop().expect("a request is in flight");
+            now = at;
+            window.on_complete(CHUNK, start + now);
+            done += 1;
+        }
+        Outcome {
+            elapsed: now,
+            max_in_flight,
+        }
+    }
+
+    fn config(max_depth: usize, adaptive: bool) -> PipelineConfig {
+        PipelineConfig {
+            max_depth,
+            adaptive,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_adaptive_window_grows_on_high_latency() {
+        let rtt = Duration::from_millis(400);
+        let mut serial = RequestWindow::new(config(1, false));
+        let serial = simulate(&mut serial, rtt, 2_000_000.0, 64);
+
+        let mut adaptive = RequestWindow::new(config(16, true));
+        let pipelined = simulate(&mut adaptive, rtt, 2_000_000.0, 64);
+        assert!(adaptive.window() >= 4, "window {}", adaptive.window());
+        assert!(pipelined.elapsed * 3 < serial.elapsed);
+    }
+
+    #[test]
+    fn test_adaptive_window_stays_small_on_low_latency() {
+        let mut window = RequestWindow::new(config(16, true));
+        simulate(&mut window, Duration::from_millis(5), 2_000_000.0, 64);
+        assert!(window.window() <= 3, "window {}", window.window());
+    }
+
+    #[test]
+    fn test_timeouts_shrink_the_window() {
+        let mut window = RequestWindow::new(config(16, true));
+        simulate(&mut window, Duration::from_millis(1000), 2_000_000.0, 64);
+        let grown = window.window();
+        assert!(grown >= 4);
+
+        let now = Instant::now();
+        window.on_send(CHUNK, now);
+        window.on_timeout(CHUNK);
+        assert_eq!(window.window(), grown / 2);
+        for _ in 0..8 {
+            window.on_send(CHUNK, now);
+            window.on_timeout(CHUNK);
+        }
+        assert_eq!(window.window(), 1);
+        assert_eq!(window.info().in_flight, 0);
+
+        // A fixed window does not adapt
+        let mut fixed = RequestWindow::new(config(4, false));
+        fixed.on_send(CHUNK, now);
+        fixed.on_timeout(CHUNK);
+        assert_eq!(fixed.window(), 4);
+    }
+
+    #[test]
+    fn test_in_flight_bytes_are_capped() {
+        let mut window = RequestWindow::new(PipelineConfig {
+            max_depth: 8,
+            adaptive: false,
+            max_in_flight_bytes: 600 * 1024,
+        });
+        let outcome = simulate(&mut window, Duration::from_millis(400), 2_000_000.0, 16);
+        assert_eq!(outcome.max_in_flight, 2);
+
+        // A chunk above the cap still goes out on an idle source
+        let mut tiny = RequestWindow::new(PipelineConfig {
+            max_in_flight_bytes: 1024,
+            ..config(8, false)
+        });
+        assert!(tiny.can_send(CHUNK));
+        tiny.on_send(CHUNK, Instant::now());
+        assert!(!tiny.can_send(CHUNK));
+    }
+}
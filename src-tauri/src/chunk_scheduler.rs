@@ -0,0 +1,217 @@
+//! Rarest-first chunk scheduling for multi-source downloads.
+//!
+//! Fetching chunks in order leaves the chunks held by only one (possibly
+//! flaky) source until last, so a transfer can stall right before it
+//! finishes. The scheduler tracks which chunks each source holds and hands out
+//! the least available chunks first. Once only a few chunks remain it enters
+//! endgame mode and duplicates the outstanding requests on other sources, so a
+//! single slow source cannot hold up completion. Sequential (streaming)
+//! downloads override the rarity order and always fetch the lowest chunk first.
+
+use std::collections::HashMap;
+
+/// Outstanding chunks at or below which endgame mode starts
+pub const ENDGAME_CHUNKS: usize = 4;
+
+/// Chunk availability across the current source set
+#[derive(Debug, Clone, Default)]
+pub struct ChunkScheduler {
+    total_chunks: u32,
+    /// Per-source "have" bitfield; `None` means the source holds every chunk
+    sources: HashMap<String, Option<Vec<bool>>>,
+    sequential: bool,
+}
+
+impl ChunkScheduler {
+    pub fn new(total_chunks: u32, sequential: bool) -> Self {
+        Self {
+            total_chunks,
+            sources: HashMap::new(),
+            sequential,
+        }
+    }
+
+    pub fn set_sequential(&mut self, sequential: bool) {
+        self.sequential = sequential;
+    }
+
+    pub fn is_sequential(&self) -> bool {
+        self.sequential
+    }
+
+    /// Add or update a source; `have` is its bitfield, or `None` for a full copy
+    pub fn add_source(&mut self, source_id: impl Into<String>, have: Option<Vec<bool>>) {
+        self.sources.insert(source_id.into(), have);
+    }
+
+    pub fn remove_source(&mut self, source_id: &str) {
+        self.sources.remove(source_id);
+    }
+
+    /// Whether `source_id` holds `chunk`
+    pub fn has(&self, source_id: &str, chunk: u32) -> bool {
+        if chunk >= self.total_chunks {
+            return false;
+        }
+        match self.sources.get(source_id) {
+            Some(None) => true,
+            Some(Some(have)) => have.get(chunk as usize).copied().unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// Number of sources holding `chunk`
+    pub fn availability(&self, chunk: u32) -> usize {
+        self.sources.keys().filter(|id| self.has(id, chunk)).count()
+    }
+
+    /// Order `chunks` by fetch priority: rarest first (ties by index), or by
+    /// index alone for sequential downloads
+    pub fn prioritize(&self, chunks: &mut [u32]) {
+        if self.sequential {
+            chunks.sort_unstable();
+        } else {
+            chunks.sort_by_cached_key(|&chunk| (self.availability(chunk), chunk));
+        }
+    }
+
+    /// Distribute `pending` chunks over `sources`, at most `limit` per source.
+    ///
+    /// Chunks are handed out in priority order, each to the least loaded
+    /// source that holds it. Chunks no listed source holds are left out.
+    pub fn assign(&self, pending: &[u32], sources: &[String], limit: usize) -> Vec<(String, Vec<u32>)> {
+        let mut assignments: Vec<(String, Vec<u32>)> =
+            sources.iter().map(|id| (id.clone(), Vec::new())).collect();
+
+        let mut ordered = pending.to_vec();
+        self.prioritize(&mut ordered);
+        for chunk in ordered {
+            let target = assignments
+                .iter_mut()
+                .filter(|(id, assigned)| assigned.len() < limit && self.has(id, chunk))
+                .min_by_key(|(_, assigned)| assigned.len());
+            if let Some((_, assigned)) = target {
+                assigned.push(chunk);
+            }
+        }
+        assignments
+    }
+
+    pub fn is_endgame(&self, outstanding_chunks: usize) -> bool {
+        outstanding_chunks > 0 && outstanding_chunks <= ENDGAME_CHUNKS
+    }
+
+    /// Duplicate requests for the outstanding chunks during endgame.
+    ///
+    /// `in_flight` lists, per outstanding chunk, the sources already fetching
+    /// it. Each chunk is additionally requested from every other source in
+    /// `sources` that holds it.
+    pub fn endgame_requests(
+        &self,
+        in_flight: &HashMap<u32, Vec<String>>,
+        sources: &[String],
+    ) -> Vec<(String, u32)> {
+        if !self.is_endgame(in_flight.len()) {
+            return Vec::new();
+        }
+        let mut chunks: Vec<u32> = in_flight.keys().copied().collect();
+        self.prioritize(&mut chunks);
+
+        let mut requests = Vec::new();
+        for chunk in chunks {
+            for source in sources {
+                if self.has(source, chunk) && !in_flight[&chunk].contains(source) {
+                    requests.push((source.clone(), chunk));
+                }
+            }
+        }
+        requests
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(s: &str) -> Option<Vec<bool>> {
+        Some(s.chars().map(|c| c == '1').collect())
+    }
+
+    fn ids(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    /// a: full copy, b: chunks 0-3, c: chunks 2 and 5
+    fn scheduler() -> ChunkScheduler {
+        let mut s = ChunkScheduler::new(6, false);
+        s.add_source("a", None);
+        s.add_source("b", bits("111100"));
+        s.add_source("c", bits("001001"));
+        s
+    }
+
+    #[test]
+    fn test_rarest_chunks_come_first() {
+        let s = scheduler();
+        assert_eq!(s.availability(2), 3);
+        assert_eq!(s.availability(4), 1);
+        assert_eq!(s.availability(9), 0);
+
+        let mut chunks: Vec<u32> = (0..6).collect();
+        s.prioritize(&mut chunks);
+        assert_eq!(chunks, vec![4, 0, 1, 3, 5, 2]);
+
+        let mut s = s;
+        s.set_sequential(true);
+        s.prioritize(&mut chunks);
+        assert_eq!(chunks, vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_assign_only_gives_sources_chunks_they_have() {
+        let s = scheduler();
+        let pending: Vec<u32> = (0..6).collect();
+        let assigned = s.assign(&pending, &ids(&["a", "b", "c"]), 10);
+
+        for (source, chunks) in &assigned {
+            assert!(chunks.iter().all(|&c| s.has(source, c)), "{source}: {chunks:?}");
+        }
+        let mut all: Vec<u32> = assigned.iter().flat_map(|(_, c)| c.clone()).collect();
+        all.sort_unstable();
+        assert_eq!(all, pending);
+        // The only holder of chunk 4 gets it first
+        assert_eq!(assigned[0].1[0], 4);
+
+        // Without the full copy chunk 4 cannot be placed
+        let assigned = s.assign(&pending, &ids(&["b", "c"]), 10);
+        assert!(assigned.iter().all(|(_, c)| !c.contains(&4)));
+        // Per-source limit is respected
+        let assigned = s.assign(&pending, &ids(&["a"]), 2);
+        assert_eq!(assigned[0].1, vec![4, 0]);
+    }
+
+    #[test]
+    fn test_endgame_duplicates_outstanding_chunks() {
+        let mut s = scheduler();
+        let sources = ids(&["a", "b", "c"]);
+
+        let mut in_flight = HashMap::new();
+        in_flight.insert(5, ids(&["c"]));
+        in_flight.insert(1, ids(&["b"]));
+        let mut requests = s.endgame_requests(&in_flight, &sources);
+        requests.sort();
+        assert_eq!(
+            requests,
+            vec![("a".to_string(), 1), ("a".to_string(), 5)]
+        );
+
+        // Losing a source drops it from availability
+        s.remove_source("a");
+        assert!(s.endgame_requests(&in_flight, &sources).is_empty());
+
+        // Too many outstanding chunks is not endgame yet
+        let busy: HashMap<u32, Vec<String>> = (0..6).map(|c| (c, Vec::new())).collect();
+        assert!(!s.is_endgame(busy.len()));
+        assert!(s.endgame_requests(&busy, &sources).is_empty());
+    }
+}
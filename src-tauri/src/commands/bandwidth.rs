@@ -0,0 +1,41 @@
+// Tauri commands for the bandwidth schedule
+
+use crate::bandwidth_schedule::{BandwidthScheduleEntry, BandwidthScheduleStatus};
+use crate::AppState;
+use tauri::State;
+
+/// Replace the bandwidth schedule; overlapping or malformed entries are
+/// rejected and the previous schedule stays in force
+#[tauri::command]
+pub async fn set_bandwidth_schedule(
+    state: State<'_, AppState>,
+    entries: Vec<BandwidthScheduleEntry>,
+    enabled: bool,
+) -> Result<BandwidthScheduleStatus, String> {
+    state
+        .bandwidth_schedule
+        .set_schedule(entries, enabled)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Apply these limits now and suspend the schedule until it is resumed
+#[tauri::command]
+pub async fn override_bandwidth_limits(
+    state: State<'_, AppState>,
+    upload_kbps: u64,
+    download_kbps: u64,
+) -> Result<BandwidthScheduleStatus, String> {
+    Ok(state
+        .bandwidth_schedule
+        .override_limits(upload_kbps, download_kbps)
+        .await)
+}
+
+/// End a manual override and follow the schedule again
+#[tauri::command]
+pub async fn resume_bandwidth_schedule(
+    state: State<'_, AppState>,
+) -> Result<BandwidthScheduleStatus, String> {
+    Ok(state.bandwidth_schedule.resume().await)
+}
@@ -1,7 +1,20 @@
 // Shared bootstrap node configuration
 // This module provides bootstrap nodes for both Tauri commands and headless mode
 
-use tauri::command;
+use crate::discovery::{
+    probe_bootstrap_node, BootstrapContributionStats, BootstrapNodeMonitor, BootstrapNodePruner, BootstrapNodeReport,
+    BootstrapTransition, PruneOutcome, QuarantinedBootstrapNode, BOOTSTRAP_MONITOR_INTERVAL, BOOTSTRAP_PROBE_TIMEOUT,
+};
+use libp2p::Multiaddr;
+use crate::dht::DhtService;
+use crate::AppState;
+use futures::future::join_all;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tauri::{command, AppHandle, Emitter, Manager, State};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
 
 pub fn get_bootstrap_nodes() -> Vec<String> {
     vec![
@@ -20,3 +33,207 @@ pub fn get_bootstrap_nodes() -> Vec<String> {
 pub fn get_bootstrap_nodes_command() -> Vec<String> {
     get_bootstrap_nodes()
 }
+
+/// Payload of `bootstrap-node-down` and `bootstrap-node-recovered`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BootstrapNodeEvent {
+    addr: String,
+    failure_count: u32,
+}
+
+#[command]
+pub async fn get_bootstrap_node_status(
+    monitor: State<'_, Mutex<BootstrapNodeMonitor>>,
+) -> Result<Vec<BootstrapNodeReport>, String> {
+    Ok(monitor.lock().await.report(Instant::now()))
+}
+
+/// Bootstrap nodes taken out of the active list after failing too many
+/// health checks in a row
+#[command]
+pub async fn get_quarantined_bootstrap_nodes_command(
+    pruner: State<'_, Mutex<BootstrapNodePruner>>,
+) -> Result<Vec<QuarantinedBootstrapNode>, String> {
+    Ok(pruner.lock().await.quarantined())
+}
+
+/// Return a quarantined bootstrap node to the active list and dial it
+#[command]
+pub async fn restore_quarantined_bootstrap_command(
+    state: State<'_, AppState>,
+    pruner: State<'_, Mutex<BootstrapNodePruner>>,
+    monitor: State<'_, Mutex<BootstrapNodeMonitor>>,
+    addr: String,
+) -> Result<(), String> {
+    let parsed: Multiaddr = addr
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid bootstrap address '{}': {}", addr, e))?;
+    let (active, quarantined) = {
+        let mut pruner = pruner.lock().await;
+        pruner.restore(&parsed)?;
+        (pruner.active(), pruner.quarantined_addrs())
+    };
+    monitor.lock().await.set_nodes(active);
+    info!("Bootstrap node {} restored from quarantine", parsed);
+
+    let dht = state.dht.lock().await.as_ref().cloned();
+    if let Some(dht) = dht {
+        dht.set_quarantined_bootstrap_nodes(quarantined).await;
+        if let Err(e) = dht.connect_peer(parsed.to_string()).await {
+            warn!("Failed to dial restored bootstrap node {}: {}", parsed, e);
+        }
+    }
+    Ok(())
+}
+
+/// Peers found through each bootstrap node the DHT was started with, most
+/// helpful first; nodes that introduce nothing are candidates for removal
+#[command]
+pub async fn get_bootstrap_contribution_stats_command(
+    state: State<'_, AppState>,
+) -> Result<Vec<BootstrapContributionStats>, String> {
+    let dht = state
+        .dht
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "DHT not running".to_string())?;
+    Ok(dht.get_bootstrap_contribution_stats().await)
+}
+
+/// Probe the bootstrap nodes `pruner` has due and record the results
+async fn probe_and_prune(pruner: &Mutex<BootstrapNodePruner>) -> (Vec<(Multiaddr, bool)>, PruneOutcome) {
+    let addrs = pruner.lock().await.due_for_probe();
+    if addrs.is_empty() {
+        return (Vec::new(), PruneOutcome::default());
+    }
+    let reachable = join_all(
+        addrs
+            .iter()
+            .map(|addr| probe_bootstrap_node(addr, BOOTSTRAP_PROBE_TIMEOUT)),
+    )
+    .await;
+    let results: Vec<(Multiaddr, bool)> = addrs.into_iter().zip(reachable).collect();
+    let unix_now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let outcome = pruner.lock().await.record_round(&results, unix_now);
+    (results, outcome)
+}
+
+/// Log what a health check changed, tell the DHT, and dial restored nodes
+async fn apply_prune_outcome(dht: Option<&DhtService>, pruner: &Mutex<BootstrapNodePruner>, outcome: &PruneOutcome) {
+    for addr in &outcome.quarantined {
+        warn!("Bootstrap node {} stayed unreachable, quarantined", addr);
+    }
+    let Some(dht) = dht else {
+        return;
+    };
+    if !outcome.is_empty() {
+        let quarantined = pruner.lock().await.quarantined_addrs();
+        dht.set_quarantined_bootstrap_nodes(quarantined).await;
+    }
+    for addr in &outcome.restored {
+        info!("Quarantined bootstrap node {} answered again, dialing", addr);
+        if let Err(e) = dht.connect_peer(addr.to_string()).await {
+            warn!("Failed to dial restored bootstrap node {}: {}", addr, e);
+        }
+    }
+}
+
+/// Quarantine and restore the bootstrap nodes of a headless node; the
+/// desktop app does the same in `run_bootstrap_monitor`
+pub async fn run_bootstrap_pruner(dht: Arc<DhtService>, nodes: Vec<Multiaddr>, prune_after_failures: u32) {
+    let pruner = Mutex::new(BootstrapNodePruner::new(prune_after_failures));
+    pruner.lock().await.set_nodes(nodes);
+    let mut interval = tokio::time::interval(BOOTSTRAP_MONITOR_INTERVAL);
+    loop {
+        interval.tick().await;
+        let (_, outcome) = probe_and_prune(&pruner).await;
+        apply_prune_outcome(Some(&dht), &pruner, &outcome).await;
+    }
+}
+
+/// Probe every bootstrap node the DHT was started with, report reachability
+/// changes to the frontend, and redial nodes that come back. Quarantined
+/// nodes are probed less often and return when they answer.
+pub async fn run_bootstrap_monitor(app: AppHandle) {
+    let mut interval = tokio::time::interval(BOOTSTRAP_MONITOR_INTERVAL);
+    loop {
+        interval.tick().await;
+        let pruner = app.state::<Mutex<BootstrapNodePruner>>();
+        let (results, outcome) = probe_and_prune(&pruner).await;
+        if results.is_empty() {
+            continue;
+        }
+
+        let monitor = app.state::<Mutex<BootstrapNodeMonitor>>();
+        let mut transitions = Vec::new();
+        {
+            let mut monitor = monitor.lock().await;
+            if !outcome.is_empty() {
+                monitor.set_nodes(pruner.lock().await.active());
+            }
+            let now = Instant::now();
+            // Only nodes in the active list have a reachability status
+            for (addr, reachable) in &results {
+                if monitor.status(addr).is_none() || outcome.restored.contains(addr) {
+                    continue;
+                }
+                let transition = if *reachable {
+                    monitor.record_success(addr, now)
+                } else {
+                    monitor.record_failure(addr)
+                };
+                if let Some(transition) = transition {
+                    let failure_count = monitor.status(addr).map_or(0, |s| s.failure_count);
+                    transitions.push((transition, failure_count));
+                }
+            }
+        }
+
+        let dht = app.state::<AppState>().dht.lock().await.as_ref().cloned();
+        apply_prune_outcome(dht.as_deref(), &pruner, &outcome).await;
+        for addr in &outcome.quarantined {
+            let _ = app.emit("bootstrap-node-quarantined", addr.to_string());
+        }
+        for addr in &outcome.restored {
+            let _ = app.emit("bootstrap-node-restored", addr.to_string());
+        }
+
+        for (transition, failure_count) in transitions {
+            match transition {
+                BootstrapTransition::Down(addr) => {
+                    warn!("Bootstrap node {} is unreachable", addr);
+                    let _ = app.emit(
+                        "bootstrap-node-down",
+                        BootstrapNodeEvent {
+                            addr: addr.to_string(),
+                            failure_count,
+                        },
+                    );
+                }
+                BootstrapTransition::Recovered(addr) => {
+                    info!("Bootstrap node {} is reachable again, dialing", addr);
+                    let dht = app.state::<AppState>().dht.lock().await.as_ref().cloned();
+                    if let Some(dht) = dht {
+                        if let Err(e) = dht.connect_peer(addr.to_string()).await {
+                            warn!("Failed to redial bootstrap node {}: {}", addr, e);
+                        }
+                    }
+                    let _ = app.emit(
+                        "bootstrap-node-recovered",
+                        BootstrapNodeEvent {
+                            addr: addr.to_string(),
+                            failure_count,
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
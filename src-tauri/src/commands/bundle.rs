@@ -0,0 +1,333 @@
+// Tauri commands for publishing and downloading directory bundles
+
+use crate::bundle::{self, BundleFileError, BundleManifest, BundleProgress, BUNDLE_MIME_TYPE};
+use crate::dht::{models::FileMetadata, DhtService};
+use crate::http_server::HttpFileMetadata;
+use crate::AppState;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, State};
+use tracing::{info, warn};
+
+/// How often an in-flight member download is polled
+const MEMBER_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A member that never shows up as an active download within this window failed
+/// to start (metadata lookups time out after 35s)
+const MEMBER_START_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// A member download that makes no progress for this long is abandoned
+const MEMBER_STALL_TIMEOUT: Duration = Duration::from_secs(120);
+
+fn running_dht(dht: Option<Arc<DhtService>>) -> Result<Arc<DhtService>, String> {
+    dht.ok_or_else(|| "DHT node is not running".to_string())
+}
+
+/// Who announces the content of a bundle, and at what price
+struct Publisher<'a> {
+    dht: &'a DhtService,
+    price: f64,
+    account: String,
+    local_peer_id: String,
+}
+
+/// Keep `content_hash` in local storage, register it for serving and announce it
+async fn share_content(
+    state: &State<'_, AppState>,
+    publisher: &Publisher<'_>,
+    source: &Path,
+    file_name: String,
+    content_hash: &str,
+    size: u64,
+    mime_type: Option<String>,
+) -> Result<(), String> {
+    let stored_path = state.http_server_state.storage_dir.join(content_hash);
+    if !stored_path.exists() {
+        tokio::fs::copy(source, &stored_path).await.map_err(|e| {
+            format!("Failed to copy {} to storage: {}", source.display(), e)
+        })?;
+    }
+
+    state
+        .http_server_state
+        .register_file(HttpFileMetadata {
+            hash: content_hash.to_string(),
+            file_hash: content_hash.to_string(),
+            name: file_name.clone(),
+            size,
+            encrypted: false,
+        })
+        .await;
+
+    if let Err(e) = state
+        .shared_files
+        .register(
+            content_hash.to_string(),
+            stored_path,
+            source.to_path_buf(),
+            file_name.clone(),
+            None,
+        )
+        .await
+    {
+        warn!("Failed to record shared file {}: {}", content_hash, e);
+    }
+
+    let metadata = FileMetadata {
+        merkle_root: content_hash.to_string(),
+        is_root: true,
+        file_name,
+        file_size: size,
+        seeders: vec![publisher.local_peer_id.clone()],
+        created_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        mime_type,
+        price: publisher.price,
+        uploader_address: Some(publisher.account.clone()),
+        ..Default::default()
+    };
+    publisher.dht.publish_file(metadata, None).await?;
+    state.shared_files.set_announced(content_hash, true).await;
+    Ok(())
+}
+
+/// Share every file under `path` and publish a bundle manifest listing them.
+///
+/// Returns the manifest hash, which is all a recipient needs to fetch the
+/// whole directory.
+#[tauri::command]
+pub async fn publish_directory(
+    state: State<'_, AppState>,
+    path: String,
+    price: Option<f64>,
+) -> Result<String, String> {
+    let account = crate::get_active_account(&state).await?;
+    let dht = running_dht(state.dht.lock().await.as_ref().cloned())?;
+    let publisher = Publisher {
+        dht: &dht,
+        price: price.unwrap_or(0.0),
+        account,
+        local_peer_id: dht.get_peer_id().await,
+    };
+
+    let local = bundle::build_manifest(Path::new(&path)).await?;
+    let manifest = &local.manifest;
+    info!(
+        "Publishing bundle {} ({} files, {} bytes)",
+        manifest.name,
+        manifest.files.len(),
+        manifest.total_size()
+    );
+
+    for (entry, source) in manifest.files.iter().zip(&local.sources) {
+        let file_name = entry.path.rsplit('/').next().unwrap_or(&entry.path).to_string();
+        share_content(
+            &state,
+            &publisher,
+            source,
+            file_name,
+            &entry.content_hash,
+            entry.size,
+            entry.mime_type.clone(),
+        )
+        .await
+        .map_err(|e| format!("Failed to publish {}: {}", entry.path, e))?;
+    }
+
+    let bytes = manifest.to_bytes()?;
+    let manifest_hash = BundleManifest::hash_bytes(&bytes);
+    let manifest_path = state.http_server_state.storage_dir.join(&manifest_hash);
+    tokio::fs::write(&manifest_path, &bytes)
+        .await
+        .map_err(|e| format!("Failed to store bundle manifest: {}", e))?;
+    share_content(
+        &state,
+        &publisher,
+        &manifest_path,
+        format!("{}.bundle.json", manifest.name),
+        &manifest_hash,
+        bytes.len() as u64,
+        Some(BUNDLE_MIME_TYPE.to_string()),
+    )
+    .await?;
+
+    info!("Published bundle {} as {}", manifest.name, manifest_hash);
+    Ok(manifest_hash)
+}
+
+/// Fetch one piece of content to `output`, from local data when possible and
+/// otherwise through the multi-source downloader. `on_progress` receives the
+/// bytes downloaded so far.
+async fn fetch_content(
+    state: &State<'_, AppState>,
+    content_hash: &str,
+    output: &Path,
+    mut on_progress: impl FnMut(u64),
+) -> Result<(), String> {
+    if state
+        .shared_files
+        .copy_local(content_hash, output)
+        .await?
+        .is_some()
+    {
+        return Ok(());
+    }
+
+    let ms = state
+        .multi_source_download
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or("Multi-source download service not available")?;
+    ms.start_download(
+        content_hash.to_string(),
+        output.to_string_lossy().to_string(),
+        None,
+        None,
+        false,
+        None,
+        false,
+        None,
+    )
+    .await?;
+
+    let started = Instant::now();
+    let mut seen = false;
+    let mut last_bytes = 0;
+    let mut last_change = Instant::now();
+    loop {
+        tokio::time::sleep(MEMBER_POLL_INTERVAL).await;
+        match ms.get_download_progress(content_hash).await {
+            Some(progress) => {
+                seen = true;
+                if progress.downloaded_size != last_bytes {
+                    last_bytes = progress.downloaded_size;
+                    last_change = Instant::now();
+                    on_progress(last_bytes);
+                } else if last_change.elapsed() > MEMBER_STALL_TIMEOUT {
+                    let _ = ms.cancel_download(content_hash.to_string()).await;
+                    return Err("download stalled".to_string());
+                }
+            }
+            None => {
+                if output.exists() {
+                    let actual = crate::shared_files::hash_file(output).await?;
+                    if actual == content_hash {
+                        return Ok(());
+                    }
+                    let _ = tokio::fs::remove_file(output).await;
+                    return Err(format!("content hash mismatch (got {})", actual));
+                }
+                if seen || started.elapsed() > MEMBER_START_TIMEOUT {
+                    return Err("download did not complete".to_string());
+                }
+            }
+        }
+    }
+}
+
+/// Download a bundle into `dest_dir`, recreating its directory structure.
+///
+/// `selected` limits the download to the given file paths or directory
+/// prefixes. Members already present with the right content are skipped, so
+/// calling this again resumes a half-finished bundle. Progress is emitted as
+/// `bundle_download_progress` events and the final state is returned; members
+/// that failed are listed in `failedFiles`.
+#[tauri::command]
+pub async fn download_bundle(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    manifest_hash: String,
+    dest_dir: String,
+    selected: Option<Vec<String>>,
+) -> Result<BundleProgress, String> {
+    let dest_dir = PathBuf::from(dest_dir);
+    tokio::fs::create_dir_all(&dest_dir)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", dest_dir.display(), e))?;
+
+    // The manifest is kept next to the download so a resume does not refetch it
+    let manifest_path = dest_dir.join(format!(".{}.bundle.json", manifest_hash));
+    let have_manifest = match tokio::fs::read(&manifest_path).await {
+        Ok(bytes) => BundleManifest::hash_bytes(&bytes) == manifest_hash,
+        Err(_) => false,
+    };
+    if !have_manifest {
+        let _ = tokio::fs::remove_file(&manifest_path).await;
+        fetch_content(&state, &manifest_hash, &manifest_path, |_| {})
+            .await
+            .map_err(|e| format!("Failed to fetch bundle manifest: {}", e))?;
+    }
+    let bytes = tokio::fs::read(&manifest_path)
+        .await
+        .map_err(|e| format!("Failed to read bundle manifest: {}", e))?;
+    let manifest = BundleManifest::from_bytes(&bytes)?;
+
+    let entries = manifest.select(selected.as_deref())?;
+    let total_bytes: u64 = entries.iter().map(|e| e.size).sum();
+    state
+        .storage
+        .check_transfer_capacity(&dest_dir, total_bytes)
+        .map_err(|e| format!("Download failed: {}", e))?;
+
+    let mut progress = BundleProgress::new(&manifest_hash, &manifest, &entries);
+    for entry in entries {
+        let target = bundle::entry_destination(&dest_dir, &entry.path)?;
+        if bundle::is_entry_complete(&target, entry).await {
+            progress.completed_files += 1;
+            progress.skipped_files += 1;
+            progress.downloaded_bytes += entry.size;
+            continue;
+        }
+        // Drop leftovers of an interrupted attempt before fetching again
+        let _ = tokio::fs::remove_file(&target).await;
+
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+
+        progress.current_file = Some(entry.path.clone());
+        let _ = app.emit("bundle_download_progress", &progress);
+
+        let base = progress.downloaded_bytes;
+        let snapshot = progress.clone();
+        let result = fetch_content(&state, &entry.content_hash, &target, |bytes| {
+            let mut update = snapshot.clone();
+            update.downloaded_bytes = base + bytes.min(entry.size);
+            let _ = app.emit("bundle_download_progress", &update);
+        })
+        .await;
+
+        match result {
+            Ok(()) => {
+                progress.completed_files += 1;
+                progress.downloaded_bytes += entry.size;
+            }
+            Err(e) => {
+                warn!("Bundle {} member {} failed: {}", manifest_hash, entry.path, e);
+                progress.failed_files.push(BundleFileError {
+                    path: entry.path.clone(),
+                    error: e,
+                });
+            }
+        }
+    }
+
+    progress.current_file = None;
+    let _ = app.emit("bundle_download_progress", &progress);
+    info!(
+        "Bundle {}: {}/{} files ({} resumed, {} failed)",
+        manifest_hash,
+        progress.completed_files,
+        progress.total_files,
+        progress.skipped_files,
+        progress.failed_files.len()
+    );
+    Ok(progress)
+}
@@ -0,0 +1,62 @@
+// Tauri commands for voice call signaling
+
+use crate::call::CallInfo;
+use crate::dht::DhtService;
+use crate::AppState;
+use std::sync::Arc;
+use tauri::State;
+
+async fn running_dht(state: &State<'_, AppState>) -> Result<Arc<DhtService>, String> {
+    state
+        .dht
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "DHT not running".to_string())
+}
+
+/// Invite `peer_id` to a call with the SDP offer from the local
+/// `RTCPeerConnection`. Returns the session id; the outcome is reported
+/// through `call-accepted`, `call-rejected` or `call-ended`.
+#[tauri::command]
+pub async fn start_call(
+    state: State<'_, AppState>,
+    peer_id: String,
+    sdp_offer: String,
+) -> Result<String, String> {
+    running_dht(&state).await?.start_call(&peer_id, sdp_offer).await
+}
+
+/// Answer an `incoming-call` with the local SDP answer
+#[tauri::command]
+pub async fn accept_call(
+    state: State<'_, AppState>,
+    session_id: String,
+    sdp_answer: String,
+) -> Result<(), String> {
+    running_dht(&state).await?.accept_call(&session_id, sdp_answer).await
+}
+
+#[tauri::command]
+pub async fn reject_call(
+    state: State<'_, AppState>,
+    session_id: String,
+    reason: Option<String>,
+) -> Result<(), String> {
+    running_dht(&state)
+        .await?
+        .reject_call(&session_id, reason.unwrap_or_else(|| "declined".to_string()))
+        .await
+}
+
+/// Hang up, or decline a call that is still ringing
+#[tauri::command]
+pub async fn end_call(state: State<'_, AppState>, session_id: String) -> Result<(), String> {
+    running_dht(&state).await?.end_call(&session_id).await
+}
+
+#[tauri::command]
+pub async fn get_active_calls(state: State<'_, AppState>) -> Result<Vec<CallInfo>, String> {
+    Ok(running_dht(&state).await?.active_calls().await)
+}
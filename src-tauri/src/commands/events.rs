@@ -0,0 +1,38 @@
+// Tauri commands choosing which `chiral-event` kinds are emitted
+
+use crate::chiral_events::{EventKind, EventSubscriptions};
+use tauri::State;
+
+fn parse_kinds(kinds: &[String]) -> Result<Vec<EventKind>, String> {
+    kinds.iter().map(|kind| EventKind::parse(kind)).collect()
+}
+
+fn names(kinds: Vec<EventKind>) -> Vec<String> {
+    kinds
+        .into_iter()
+        .map(|kind| kind.as_str().to_string())
+        .collect()
+}
+
+/// Start emitting `kinds`, at most `max_rate` events a second per kind and
+/// transfer; returns every subscribed kind. `max_rate` is refused for kinds
+/// that are not rate limited.
+#[tauri::command]
+pub fn subscribe_events(
+    subscriptions: State<'_, EventSubscriptions>,
+    kinds: Vec<String>,
+    max_rate: Option<f64>,
+) -> Result<Vec<String>, String> {
+    subscriptions.subscribe(&parse_kinds(&kinds)?, max_rate)?;
+    Ok(names(subscriptions.subscribed()))
+}
+
+/// Stop emitting `kinds`; returns the kinds still subscribed
+#[tauri::command]
+pub fn unsubscribe_events(
+    subscriptions: State<'_, EventSubscriptions>,
+    kinds: Vec<String>,
+) -> Result<Vec<String>, String> {
+    subscriptions.unsubscribe(&parse_kinds(&kinds)?);
+    Ok(names(subscriptions.subscribed()))
+}
@@ -0,0 +1,89 @@
+// Tauri commands for sending files directly to a peer
+
+use crate::compression::TransferCompressionStats;
+use crate::AppState;
+use std::path::PathBuf;
+use tauri::State;
+
+/// Offer the file at `file_path` to `peer_id` and stream it once accepted.
+///
+/// The receiving side asks its user with a `file-transfer-offer` event and
+/// reports progress through `file-transfer-progress` events. Returns the
+/// number of bytes the peer confirmed.
+#[tauri::command]
+pub async fn send_file_to_peer(
+    state: State<'_, AppState>,
+    peer_id: String,
+    file_path: String,
+) -> Result<u64, String> {
+    let dht = state
+        .dht
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "DHT not running".to_string())?;
+
+    dht.send_file_to_peer(&peer_id, &PathBuf::from(file_path)).await
+}
+
+/// Accept a `file-transfer-offer`. An offer larger than the free disk space
+/// or the storage quota left is rejected instead.
+#[tauri::command]
+pub async fn accept_file_transfer(
+    state: State<'_, AppState>,
+    transfer_id: String,
+) -> Result<(), String> {
+    let dht = state
+        .dht
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "DHT not running".to_string())?;
+
+    let offer = dht
+        .file_offer(&transfer_id)
+        .await
+        .ok_or_else(|| format!("no pending file offer {}", transfer_id))?;
+    let dir = dht.direct_transfer_dir().await;
+    if let Err(e) = state.storage.check_transfer_capacity(&dir, offer.size) {
+        let _ = dht.reject_file_offer(&transfer_id, e.to_string()).await;
+        return Err(e.to_string());
+    }
+    dht.accept_file_offer(&transfer_id).await
+}
+
+#[tauri::command]
+pub async fn reject_file_transfer(
+    state: State<'_, AppState>,
+    transfer_id: String,
+    reason: Option<String>,
+) -> Result<(), String> {
+    let dht = state
+        .dht
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "DHT not running".to_string())?;
+
+    dht.reject_file_offer(&transfer_id, reason.unwrap_or_else(|| "declined".to_string()))
+        .await
+}
+
+/// Logical vs. on-the-wire bytes of direct transfers since startup
+#[tauri::command]
+pub async fn get_transfer_compression_stats(
+    state: State<'_, AppState>,
+) -> Result<TransferCompressionStats, String> {
+    let dht = state
+        .dht
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "DHT not running".to_string())?;
+
+    Ok(dht.transfer_compression_stats().await)
+}
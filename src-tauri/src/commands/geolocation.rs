@@ -0,0 +1,84 @@
+// Tauri commands for the connected peer list and the network map
+
+use crate::geolocation::{self, CountryPeers, GeolocationStatus, PeerGeolocation, PeerLocation};
+use crate::AppState;
+use libp2p::multiaddr::Protocol;
+use serde::Serialize;
+use std::sync::Arc;
+use tauri::State;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectedPeer {
+    pub peer_id: String,
+    /// Remote address of each open connection
+    pub addresses: Vec<String>,
+    /// Every connection goes through a relay
+    pub relayed: bool,
+    /// Only with peer geolocation turned on and a direct connection to a
+    /// public IP
+    pub location: Option<PeerLocation>,
+}
+
+/// Every connected peer with its addresses and approximate location
+#[tauri::command]
+pub async fn list_connected_peers(
+    state: State<'_, AppState>,
+    geo: State<'_, Arc<PeerGeolocation>>,
+) -> Result<Vec<ConnectedPeer>, String> {
+    let Some(dht) = state.dht.lock().await.as_ref().cloned() else {
+        return Ok(Vec::new());
+    };
+    // A missing database only leaves the locations out here
+    let db = match geo.is_enabled() {
+        true => geo.database().await.ok(),
+        false => None,
+    };
+    let mut peers: Vec<ConnectedPeer> = dht
+        .connected_peer_addresses()
+        .await
+        .into_iter()
+        .map(|(peer_id, addrs)| ConnectedPeer {
+            location: db.as_ref().and_then(|db| db.locate(&addrs)),
+            relayed: !addrs.is_empty()
+                && addrs
+                    .iter()
+                    .all(|a| a.iter().any(|p| matches!(p, Protocol::P2pCircuit))),
+            addresses: addrs.iter().map(|a| a.to_string()).collect(),
+            peer_id,
+        })
+        .collect();
+    peers.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+    Ok(peers)
+}
+
+/// Located peers per country for the map; empty while geolocation is off
+#[tauri::command]
+pub async fn get_peer_locations(
+    state: State<'_, AppState>,
+    geo: State<'_, Arc<PeerGeolocation>>,
+) -> Result<Vec<CountryPeers>, String> {
+    if !geo.is_enabled() {
+        return Ok(Vec::new());
+    }
+    // Report a missing database instead of an empty map
+    let db = geo.database().await?;
+    let Some(dht) = state.dht.lock().await.as_ref().cloned() else {
+        return Ok(Vec::new());
+    };
+    let peers = dht.connected_peer_addresses().await;
+    Ok(geolocation::aggregate(
+        peers.iter().filter_map(|(_, addrs)| db.locate(addrs)),
+    ))
+}
+
+/// The settings toggle; turning it off frees the database. Turning it on
+/// reads the database, so Settings can show why it is missing.
+#[tauri::command]
+pub async fn set_peer_geolocation_enabled(
+    geo: State<'_, Arc<PeerGeolocation>>,
+    enabled: bool,
+) -> Result<GeolocationStatus, String> {
+    geo.set_enabled(enabled);
+    Ok(geo.status().await)
+}
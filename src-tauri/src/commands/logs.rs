@@ -0,0 +1,58 @@
+// Tauri commands for the live log view in the settings page
+
+use crate::diagnostics_bundle::SecretRedactor;
+use crate::log_buffer::{self, LogBuffer, LogLine, LOG_LINE_EVENT};
+use crate::DataDirs;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, State};
+use tokio::sync::broadcast::error::RecvError;
+
+/// The newest `lines` lines logged, oldest first, at `level_filter` or more
+/// severe; secrets are redacted as in a diagnostics bundle
+#[tauri::command]
+pub fn tail_logs(
+    buffer: State<'_, Arc<LogBuffer>>,
+    lines: u32,
+    level_filter: Option<String>,
+) -> Result<Vec<LogLine>, String> {
+    let min_level = level_filter
+        .as_deref()
+        .filter(|level| !level.trim().is_empty())
+        .map(log_buffer::parse_level)
+        .transpose()?;
+    let redactor = SecretRedactor::for_identity(&DataDirs::current());
+    Ok(buffer
+        .tail(lines as usize, min_level)
+        .into_iter()
+        .map(|mut line| {
+            line.message = redactor.redact_str(&line.message);
+            line
+        })
+        .collect())
+}
+
+/// Emit every new line as `log-line` while `enabled`
+#[tauri::command]
+pub fn set_log_streaming(app: AppHandle, buffer: State<'_, Arc<LogBuffer>>, enabled: bool) {
+    let Some(generation) = buffer.set_streaming(enabled) else {
+        return;
+    };
+    let buffer = buffer.inner().clone();
+    let mut lines = buffer.subscribe();
+    tauri::async_runtime::spawn(async move {
+        let redactor = SecretRedactor::for_identity(&DataDirs::current());
+        loop {
+            match lines.recv().await {
+                Ok(mut line) => {
+                    if !buffer.is_current_stream(generation) {
+                        break;
+                    }
+                    line.message = redactor.redact_str(&line.message);
+                    let _ = app.emit(LOG_LINE_EVENT, line);
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
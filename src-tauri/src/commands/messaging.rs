@@ -0,0 +1,332 @@
+// Tauri commands for direct and channel messages
+
+use crate::commands::{limited, RateLimiter};
+use crate::messaging::{
+    receipts, FilterMode, MessageId, MessagePage, MessageReaction, MessageStore,
+    PendingMessage, ReactionAction, ReadReceipt, RetransmissionQueue, StoredMessage,
+    TopicFilterState,
+};
+use crate::node_commands;
+use crate::AppState;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+/// How often the retransmission queue is checked for due messages
+const RETRANSMIT_TICK: Duration = Duration::from_secs(1);
+
+/// Topic used when deriving ids for direct messages
+const DIRECT_MESSAGE_TOPIC: &str = "direct";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectMessageReceipt {
+    pub message_id: MessageId,
+    /// False if the message was queued for retransmission
+    pub delivered: bool,
+}
+
+/// Payload of the `message-send-failed` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageSendFailed {
+    pub message_id: MessageId,
+    pub target_peer: String,
+    pub attempts: u8,
+    pub error: String,
+}
+
+/// Send `payload` to `peer_id`; the peer's reply acknowledges the message.
+///
+/// If the first attempt fails the message is queued and retried in the
+/// background; a `message-send-failed` event is emitted if it never gets through.
+#[tauri::command]
+pub async fn send_direct_message(
+    state: State<'_, AppState>,
+    queue: State<'_, Mutex<RetransmissionQueue>>,
+    peer_id: String,
+    payload: Vec<u8>,
+) -> Result<DirectMessageReceipt, String> {
+    let dht = state
+        .dht
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "DHT not running".to_string())?;
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let message_id = MessageId::for_message(
+        DIRECT_MESSAGE_TOPIC,
+        &dht.get_peer_id().await,
+        timestamp,
+        &payload,
+    );
+
+    match dht.echo(peer_id.clone(), payload.clone()).await {
+        Ok(_) => Ok(DirectMessageReceipt {
+            message_id,
+            delivered: true,
+        }),
+        Err(e) => {
+            debug!("Direct message {} to {} failed, queueing: {}", message_id, peer_id, e);
+            queue
+                .lock()
+                .await
+                .enqueue(message_id.clone(), peer_id, payload, Instant::now());
+            Ok(DirectMessageReceipt {
+                message_id,
+                delivered: false,
+            })
+        }
+    }
+}
+
+/// Start receiving reactions published on `channel`
+#[tauri::command]
+pub async fn join_message_channel(state: State<'_, AppState>, channel: String) -> Result<(), String> {
+    let dht = state
+        .dht
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "DHT not running".to_string())?;
+    dht.join_channel(&channel).await
+}
+
+/// Mute or unmute a channel, block or unblock a peer, or restrict the
+/// channels received to an allow-list. Blocked messages are dropped on arrival.
+#[tauri::command]
+pub async fn set_topic_filter_command(
+    state: State<'_, AppState>,
+    mode: FilterMode,
+    topic_or_peer: String,
+) -> Result<TopicFilterState, String> {
+    let dht = state
+        .dht
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "DHT not running".to_string())?;
+    dht.set_topic_filter(mode, &topic_or_peer).await
+}
+
+/// Post `payload` on `channel`, optionally as a reply to an earlier message
+#[tauri::command]
+pub async fn publish_message_command(
+    state: State<'_, AppState>,
+    store: State<'_, Arc<MessageStore>>,
+    rate_limiter: State<'_, Mutex<RateLimiter>>,
+    channel: String,
+    payload: Vec<u8>,
+    reply_to: Option<String>,
+) -> Result<StoredMessage, String> {
+    limited(&rate_limiter, "publish_message", async {
+        let reply_to = reply_to
+            .map(|id| id.parse::<MessageId>())
+            .transpose()
+            .map_err(|e| format!("{}", e))?;
+        let dht = state
+            .dht
+            .lock()
+            .await
+            .as_ref()
+            .cloned()
+            .ok_or_else(|| "DHT not running".to_string())?;
+
+        let message = node_commands::channel_message(&dht, channel, payload, reply_to).await;
+        // Gossipsub does not deliver our own messages back, so record it here
+        let id = store.store_message(&message).map_err(|e| e.to_string())?;
+        node_commands::publish_message(&dht, message).await?;
+        store
+            .get_message(id)
+            .ok_or_else(|| "Message was evicted right after storing".to_string())
+    })
+    .await
+}
+
+/// A thread's root message and its replies, oldest first
+#[tauri::command]
+pub async fn get_thread_command(
+    store: State<'_, Arc<MessageStore>>,
+    root_id: String,
+    limit: u32,
+    offset: u32,
+) -> Result<MessagePage, String> {
+    let root_id: MessageId = root_id.parse().map_err(|e| format!("{}", e))?;
+    store
+        .get_thread(&root_id, limit, offset)
+        .map_err(|e| e.to_string())
+}
+
+/// Tell the authors of `message_ids` that we have read them.
+///
+/// Receipts are grouped into one batch per author. Our own messages and ids
+/// not in the store are skipped. Returns the number of receipts sent, which
+/// is 0 when `send_read_receipts` is turned off.
+#[tauri::command]
+pub async fn mark_messages_read(
+    state: State<'_, AppState>,
+    store: State<'_, Arc<MessageStore>>,
+    message_ids: Vec<String>,
+) -> Result<u32, String> {
+    let dht = state
+        .dht
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "DHT not running".to_string())?;
+    let local_peer = dht.get_peer_id().await;
+    let read_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let mut pending = Vec::new();
+    for id in message_ids {
+        let id: MessageId = id.parse().map_err(|e| format!("{}", e))?;
+        let Some(message) = store.get_message(id) else {
+            continue;
+        };
+        if message.from_peer == local_peer {
+            continue;
+        }
+        pending.push((
+            message.from_peer,
+            ReadReceipt {
+                message_id: message.id,
+                reader_peer_id: local_peer.clone(),
+                read_at,
+            },
+        ));
+    }
+
+    let mut sent = 0;
+    for (author, batch) in receipts::batch_by_author(pending) {
+        let count = batch.receipts.len() as u32;
+        match dht.send_read_receipts(&author, batch).await {
+            Ok(true) => sent += count,
+            Ok(false) => return Ok(0),
+            Err(e) => warn!("Failed to send read receipts to {}: {}", author, e),
+        }
+    }
+    Ok(sent)
+}
+
+/// Add or remove our `emoji` reaction on a message posted in `channel`
+#[tauri::command]
+pub async fn react_to_message(
+    state: State<'_, AppState>,
+    store: State<'_, Arc<MessageStore>>,
+    channel: String,
+    message_id: String,
+    emoji: String,
+    remove: Option<bool>,
+) -> Result<MessageReaction, String> {
+    let message_id: MessageId = message_id.parse().map_err(|e| format!("{}", e))?;
+    let emoji = emoji.trim().to_string();
+    if emoji.is_empty() {
+        return Err("Reaction emoji must not be empty".to_string());
+    }
+    let dht = state
+        .dht
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "DHT not running".to_string())?;
+
+    let reaction = MessageReaction {
+        message_id,
+        reactor_peer_id: dht.get_peer_id().await,
+        emoji,
+        action: if remove.unwrap_or(false) {
+            ReactionAction::Remove
+        } else {
+            ReactionAction::Add
+        },
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    };
+    // Gossipsub does not deliver our own messages back, so record it here
+    store.apply_reaction(&reaction).map_err(|e| e.to_string())?;
+    dht.publish_reaction(&channel, reaction.clone()).await?;
+    Ok(reaction)
+}
+
+/// Current reactions on a message, oldest first
+#[tauri::command]
+pub async fn get_message_reactions_command(
+    store: State<'_, Arc<MessageStore>>,
+    message_id: String,
+) -> Result<Vec<MessageReaction>, String> {
+    let message_id: MessageId = message_id.parse().map_err(|e| format!("{}", e))?;
+    Ok(store.get_reactions(&message_id))
+}
+
+async fn retransmit(app: &AppHandle, message: &PendingMessage) -> Result<(), String> {
+    let state = app.state::<AppState>();
+    let dht = state
+        .dht
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "DHT not running".to_string())?;
+    dht.echo(message.target_peer.clone(), message.payload.clone())
+        .await
+        .map(|_| ())
+}
+
+/// Retry queued direct messages as they fall due, for the lifetime of the app
+pub async fn run_retransmission_loop(app: AppHandle) {
+    let mut interval = tokio::time::interval(RETRANSMIT_TICK);
+    loop {
+        interval.tick().await;
+        let queue = app.state::<Mutex<RetransmissionQueue>>();
+        let due = queue.lock().await.due(Instant::now());
+
+        for message in due {
+            let result = retransmit(&app, &message).await;
+            let mut queue = queue.lock().await;
+            match result {
+                Ok(()) => {
+                    queue.acknowledge(&message.message_id);
+                    debug!(
+                        "Direct message {} delivered after {} attempts",
+                        message.message_id,
+                        message.attempts + 1
+                    );
+                }
+                Err(e) => {
+                    if let Some(failed) = queue.record_failure(&message.message_id, Instant::now()) {
+                        warn!(
+                            "Giving up on direct message {} to {} after {} attempts: {}",
+                            failed.message_id, failed.target_peer, failed.attempts, e
+                        );
+                        let _ = app.emit(
+                            "message-send-failed",
+                            MessageSendFailed {
+                                message_id: failed.message_id,
+                                target_peer: failed.target_peer,
+                                attempts: failed.attempts,
+                                error: e,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,24 @@
 pub mod auth;
+pub mod bandwidth;
+pub mod bundle;
 pub mod bootstrap;
+pub mod call;
+pub mod events;
+pub mod file_transfer;
+pub mod geolocation;
+pub mod logs;
 pub mod proxy;
+pub mod messaging;
 pub mod network;
+pub mod presence;
+pub mod protocol;
+pub mod rate_limit;
+pub mod relay;
+pub mod search;
+pub mod security;
+pub mod shared_files;
+pub mod storage;
+pub mod transfer_history;
+pub mod watch_dir;
+
+pub use rate_limit::{limited, RateLimiter};
@@ -0,0 +1,29 @@
+// Tauri commands for typing indicators
+
+use crate::dht::DhtService;
+use crate::AppState;
+use std::sync::Arc;
+use tauri::State;
+
+async fn running_dht(state: &State<'_, AppState>) -> Result<Arc<DhtService>, String> {
+    state
+        .dht
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "DHT not running".to_string())
+}
+
+/// Call on every keypress in `channel`; peers see `peer-typing` until the
+/// keypresses pause for a few seconds
+#[tauri::command]
+pub async fn start_typing(state: State<'_, AppState>, channel: String) -> Result<(), String> {
+    running_dht(&state).await?.start_typing(&channel).await
+}
+
+/// Clear the indicator right away, e.g. once the message was sent
+#[tauri::command]
+pub async fn stop_typing(state: State<'_, AppState>, channel: String) -> Result<(), String> {
+    running_dht(&state).await?.stop_typing(&channel).await
+}
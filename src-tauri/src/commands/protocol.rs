@@ -0,0 +1,10 @@
+// Protocol versions supported by this node, for version display and diagnostics
+
+use crate::protocol::protocol_versions;
+use std::collections::HashMap;
+use tauri::command;
+
+#[command]
+pub fn get_protocol_versions_command() -> HashMap<String, String> {
+    protocol_versions()
+}
@@ -0,0 +1,182 @@
+// Per-command rate limiting so a misbehaving frontend cannot flood the swarm
+//
+// Each limited command has its own token bucket. Commands without a configured
+// limit are never throttled. Every name in `DEFAULT_LIMITS` is a registered
+// command that runs its body through `limited` under that name.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// Default limits: command name, calls allowed per window, window length
+const DEFAULT_LIMITS: &[(&str, u32, Duration)] = &[
+    ("connect_to_peer", 5, MINUTE),
+    ("publish_message", 100, MINUTE),
+    ("find_peer", 10, MINUTE),
+];
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(calls: u32, window: Duration, now: Instant) -> Self {
+        let capacity = calls as f64;
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_sec: capacity / window.as_secs_f64(),
+            last_refill: now,
+        }
+    }
+
+    /// Take one token, or return how long until one is available
+    fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.refill_per_sec))
+        }
+    }
+}
+
+/// Token-bucket rate limiter keyed by command name.
+///
+/// Managed by Tauri as `Mutex<RateLimiter>`; commands wrap their body in
+/// [`limited`] so the check happens before any work.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: HashMap<String, (u32, Duration)>,
+    buckets: HashMap<String, TokenBucket>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        let mut limiter = Self::unlimited();
+        for (command, calls, window) in DEFAULT_LIMITS {
+            limiter.set_limit(command, *calls, *window);
+        }
+        limiter
+    }
+}
+
+impl RateLimiter {
+    /// A limiter with no limits configured
+    pub fn unlimited() -> Self {
+        Self {
+            limits: HashMap::new(),
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Allow `calls` invocations of `command` per `window`
+    pub fn set_limit(&mut self, command: &str, calls: u32, window: Duration) {
+        self.limits.insert(command.to_string(), (calls, window));
+        self.buckets.remove(command);
+    }
+
+    /// Consume one call of `command`, failing if its bucket is empty
+    pub fn check(&mut self, command: &str) -> Result<(), String> {
+        self.check_at(command, Instant::now())
+    }
+
+    fn check_at(&mut self, command: &str, now: Instant) -> Result<(), String> {
+        let (calls, window) = match self.limits.get(command) {
+            Some(limit) => *limit,
+            None => return Ok(()),
+        };
+        self.buckets
+            .entry(command.to_string())
+            .or_insert_with(|| TokenBucket::new(calls, window, now))
+            .try_take(now)
+            .map_err(|wait| {
+                // Round up to whole seconds, ignoring sub-millisecond float noise
+                let secs = (wait.as_millis() + 999) / 1000;
+                format!("rate limit exceeded, retry after {}s", secs)
+            })
+    }
+}
+
+/// Run `work` if `command` is within its limit, or fail without running it
+pub async fn limited<T, Fut>(limiter: &Mutex<RateLimiter>, command: &str, work: Fut) -> Result<T, String>
+where
+    Fut: Future<Output = Result<T, String>>,
+{
+    limiter.lock().await.check(command)?;
+    work.await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_limits_calls_per_window() {
+        let mut limiter = RateLimiter::default();
+        let now = Instant::now();
+        for _ in 0..5 {
+            assert!(limiter.check_at("connect_to_peer", now).is_ok());
+        }
+        assert_eq!(
+            limiter.check_at("connect_to_peer", now).unwrap_err(),
+            "rate limit exceeded, retry after 12s"
+        );
+        // Other commands have independent buckets
+        assert!(limiter.check_at("find_peer", now).is_ok());
+    }
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let mut limiter = RateLimiter::default();
+        let now = Instant::now();
+        for _ in 0..5 {
+            assert!(limiter.check_at("connect_to_peer", now).is_ok());
+        }
+        assert!(limiter.check_at("connect_to_peer", now).is_err());
+        assert!(limiter
+            .check_at("connect_to_peer", now + Duration::from_secs(13))
+            .is_ok());
+        assert!(limiter
+            .check_at("connect_to_peer", now + Duration::from_secs(13))
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_command_past_its_limit_does_no_work() {
+        let limiter = Mutex::new(RateLimiter::default());
+        let runs = AtomicU32::new(0);
+        let find_peer = || async {
+            runs.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        };
+
+        for _ in 0..10 {
+            limited(&limiter, "find_peer", find_peer()).await.unwrap();
+        }
+        let err = limited(&limiter, "find_peer", find_peer()).await.unwrap_err();
+        assert!(err.starts_with("rate limit exceeded"), "{err}");
+        assert_eq!(runs.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn test_unconfigured_commands_are_not_limited() {
+        let mut limiter = RateLimiter::default();
+        let now = Instant::now();
+        for _ in 0..1000 {
+            assert!(limiter.check_at("get_dht_events", now).is_ok());
+        }
+    }
+}
@@ -0,0 +1,30 @@
+// Tauri commands for the peers this node's relay serves under
+// `known_peers_only`
+
+use crate::relay_consent::{TrustReason, TrustedPeer, TrustedPeers};
+use libp2p::PeerId;
+use std::sync::Arc;
+use tauri::State;
+
+/// Trusted peers, added by the user or from completed downloads
+#[tauri::command]
+pub fn list_trusted_peers(trusted: State<'_, Arc<TrustedPeers>>) -> Vec<TrustedPeer> {
+    trusted.list()
+}
+
+#[tauri::command]
+pub fn add_trusted_peer(trusted: State<'_, Arc<TrustedPeers>>, peer_id: String) -> Result<(), String> {
+    let peer: PeerId = peer_id
+        .parse()
+        .map_err(|e| format!("Invalid peer id {}: {}", peer_id, e))?;
+    trusted.trust(peer, TrustReason::Added)
+}
+
+/// True if the peer was trusted
+#[tauri::command]
+pub fn remove_trusted_peer(trusted: State<'_, Arc<TrustedPeers>>, peer_id: String) -> Result<bool, String> {
+    let peer: PeerId = peer_id
+        .parse()
+        .map_err(|e| format!("Invalid peer id {}: {}", peer_id, e))?;
+    trusted.remove(&peer)
+}
@@ -0,0 +1,131 @@
+// Tauri commands for searching files known to this node
+
+use crate::search_ranking::{
+    self, ProviderCache, RankedSearchResult, ReachabilityProbe, MAX_PROBED_RESULTS, PROBE_TIMEOUT,
+    RANKED_PAGE_SIZE,
+};
+use crate::AppState;
+use std::time::Instant;
+use tauri::State;
+use tokio::sync::Mutex;
+
+/// Search the metadata this node has seen by file name.
+///
+/// Only the best `RANKED_PAGE_SIZE` name matches get a provider lookup, and
+/// only the top `MAX_PROBED_RESULTS` of those a reachability probe (skipped
+/// with `probe: false`). Results are sorted by `search_ranking::score`.
+#[tauri::command]
+pub async fn search_files(
+    state: State<'_, AppState>,
+    providers: State<'_, Mutex<ProviderCache>>,
+    query: String,
+    probe: Option<bool>,
+) -> Result<Vec<RankedSearchResult>, String> {
+    let dht = state
+        .dht
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "DHT not running".to_string())?;
+
+    let mut matches: Vec<_> = dht
+        .get_all_file_metadata()
+        .await?
+        .into_iter()
+        .map(|metadata| {
+            let quality = search_ranking::name_match_quality(&query, &metadata.file_name);
+            (metadata, quality)
+        })
+        .filter(|(_, quality)| *quality > 0.0)
+        .collect();
+    matches.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+
+    // Provider lookups for the first page only, reusing recent ones
+    let page = matches.len().min(RANKED_PAGE_SIZE);
+    let now = Instant::now();
+    let cached: Vec<Option<Vec<String>>> = {
+        let cache = providers.lock().await;
+        matches[..page]
+            .iter()
+            .map(|(metadata, _)| cache.get(&metadata.merkle_root, now))
+            .collect()
+    };
+    let lookups = futures::future::join_all(matches[..page].iter().zip(cached).map(
+        |((metadata, _), cached)| {
+            let dht = dht.clone();
+            async move {
+                match cached {
+                    Some(peers) => (peers, false),
+                    None => (dht.get_seeders_for_file(&metadata.merkle_root).await, true),
+                }
+            }
+        },
+    ))
+    .await;
+    {
+        let mut cache = providers.lock().await;
+        for ((metadata, _), (peers, fresh)) in matches.iter().zip(&lookups) {
+            if *fresh {
+                cache.insert(metadata.merkle_root.clone(), peers.clone(), now);
+            }
+        }
+    }
+
+    let mut results: Vec<RankedSearchResult> = matches
+        .iter()
+        .enumerate()
+        .map(|(i, (metadata, quality))| {
+            let provider_count = lookups.get(i).map(|(peers, _)| peers.len());
+            RankedSearchResult {
+                file_hash: metadata.merkle_root.clone(),
+                file_name: metadata.file_name.clone(),
+                file_size: metadata.file_size,
+                provider_count,
+                probe: ReachabilityProbe::NotProbed,
+                score: search_ranking::score(provider_count, ReachabilityProbe::NotProbed, *quality),
+            }
+        })
+        .collect();
+    search_ranking::rank(&mut results);
+
+    if probe.unwrap_or(true) {
+        let first_provider = |hash: &str| {
+            matches
+                .iter()
+                .position(|(metadata, _)| metadata.merkle_root == hash)
+                .and_then(|i| lookups.get(i))
+                .and_then(|(peers, _)| peers.first().cloned())
+        };
+        let targets: Vec<(usize, String)> = results
+            .iter()
+            .enumerate()
+            .filter_map(|(i, result)| first_provider(&result.file_hash).map(|peer| (i, peer)))
+            .take(MAX_PROBED_RESULTS)
+            .collect();
+        let probes = futures::future::join_all(targets.iter().map(|(_, peer)| {
+            let dht = dht.clone();
+            let peer = peer.clone();
+            async move {
+                let started = Instant::now();
+                match tokio::time::timeout(PROBE_TIMEOUT, dht.echo(peer, b"ping".to_vec())).await {
+                    Ok(Ok(_)) => ReachabilityProbe::Reachable {
+                        latency_ms: started.elapsed().as_millis() as u64,
+                    },
+                    _ => ReachabilityProbe::Unreachable,
+                }
+            }
+        }))
+        .await;
+
+        for ((i, _), probe) in targets.iter().zip(probes) {
+            let result = &mut results[*i];
+            let quality = search_ranking::name_match_quality(&query, &result.file_name);
+            result.probe = probe;
+            result.score = search_ranking::score(result.provider_count, probe, quality);
+        }
+        search_ranking::rank(&mut results);
+    }
+
+    Ok(results)
+}
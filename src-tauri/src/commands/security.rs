@@ -0,0 +1,47 @@
+// Tauri commands managing the incoming connection filter
+
+use crate::security::{FilterRuleReport, FilterRuleSpec};
+use crate::AppState;
+use tauri::State;
+
+async fn running_dht(state: &AppState) -> Result<std::sync::Arc<crate::dht::DhtService>, String> {
+    state
+        .dht
+        .lock()
+        .await
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "DHT not running".to_string())
+}
+
+/// Add a rule, checked before the configured ones; returns it with its id
+#[tauri::command]
+pub async fn add_filter_rule_command(
+    state: State<'_, AppState>,
+    rule: FilterRuleSpec,
+) -> Result<FilterRuleReport, String> {
+    let dht = running_dht(&state).await?;
+    dht.connection_filter().add(rule)
+}
+
+/// Remove the rule `rule_id`; a rule from `chiral.toml` returns on the next
+/// start
+#[tauri::command]
+pub async fn remove_filter_rule_command(
+    state: State<'_, AppState>,
+    rule_id: String,
+) -> Result<(), String> {
+    let dht = running_dht(&state).await?;
+    dht.connection_filter().remove(rule_id.trim())
+}
+
+/// Every rule in the order they are checked, with hit counts
+#[tauri::command]
+pub async fn get_filter_rules_command(
+    state: State<'_, AppState>,
+) -> Result<Vec<FilterRuleReport>, String> {
+    Ok(match state.dht.lock().await.as_ref() {
+        Some(dht) => dht.connection_filter().list(),
+        None => Vec::new(),
+    })
+}
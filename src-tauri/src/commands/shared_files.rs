@@ -0,0 +1,294 @@
+// Tauri commands for the shared files registry
+
+use crate::shared_files::{SeedingLimitChange, SeedingLimits, SharedFileEntry, VerificationReport};
+use crate::upload_slots::UploadSlotStats;
+use crate::AppState;
+use serde::Serialize;
+use std::path::Path;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+/// How often time-window seeding limits are re-checked
+const SEEDING_LIMIT_TICK: Duration = Duration::from_secs(60);
+
+/// Payload of the `shared-file-corrupted` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedFileCorrupted {
+    pub content_hash: String,
+    pub file_name: String,
+    pub damaged_chunks: usize,
+    /// Other peers seeding the content; `repair_shared_file` needs at least one
+    pub other_providers: usize,
+}
+
+/// Seeders of `content_hash` other than this node, according to the DHT
+async fn other_providers(state: &AppState, content_hash: &str) -> Result<Vec<String>, String> {
+    let dht = { state.dht.lock().await.as_ref().cloned() }
+        .ok_or_else(|| "DHT not running".to_string())?;
+    let own_id = dht.get_peer_id().await;
+    let metadata = dht
+        .synchronous_search_metadata(content_hash.to_string(), 35000)
+        .await?;
+    Ok(metadata
+        .map(|m| m.seeders)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|seeder| *seeder != own_id)
+        .collect())
+}
+
+async fn report_verification(app: &AppHandle, state: &AppState, report: &VerificationReport) {
+    if report.intact {
+        return;
+    }
+    let file_name = state
+        .shared_files
+        .get(&report.content_hash)
+        .await
+        .map(|e| e.file_name)
+        .unwrap_or_default();
+    let other_providers = other_providers(state, &report.content_hash)
+        .await
+        .map(|p| p.len())
+        .unwrap_or(0);
+    let _ = app.emit(
+        "shared-file-corrupted",
+        SharedFileCorrupted {
+            content_hash: report.content_hash.clone(),
+            file_name,
+            damaged_chunks: report.damaged_chunks.len(),
+            other_providers,
+        },
+    );
+}
+
+/// Re-hash every active shared file once per `every` to catch silent disk
+/// corruption. Reads are throttled by the registry's verify rate.
+pub async fn run_verification_loop(app: AppHandle, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    // The first tick fires immediately; leave startup alone
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let state = app.state::<AppState>();
+        for entry in state.shared_files.list().await {
+            if entry.status.is_stale() {
+                continue;
+            }
+            match state.shared_files.verify(&entry.content_hash).await {
+                Ok(report) => report_verification(&app, &state, &report).await,
+                Err(e) => warn!("Verification of {} failed: {}", entry.content_hash, e),
+            }
+        }
+    }
+}
+
+/// List everything this node is sharing. Files that were moved or modified
+/// since publishing are reported with a `stale` status.
+#[tauri::command]
+pub async fn list_shared_files(state: State<'_, AppState>) -> Result<Vec<SharedFileEntry>, String> {
+    Ok(state.shared_files.list().await)
+}
+
+/// Stop sharing a file: withdraw the DHT announcement and refuse new requests.
+///
+/// With `path`, only that path reference is removed and the content stays
+/// shared while other paths still reference it. Uploads already in progress
+/// finish unless `finish_in_flight` is `false`.
+#[tauri::command]
+pub async fn unshare_file(
+    state: State<'_, AppState>,
+    content_hash: String,
+    path: Option<String>,
+    finish_in_flight: Option<bool>,
+) -> Result<(), String> {
+    let entry = state
+        .shared_files
+        .get(&content_hash)
+        .await
+        .ok_or_else(|| format!("File {} is not shared", content_hash))?;
+
+    if let Some(path) = path {
+        let remaining = state
+            .shared_files
+            .remove_reference(&content_hash, Path::new(&path))
+            .await?;
+        if remaining > 0 {
+            info!(
+                "Removed reference {} to {}; still referenced by {} path(s)",
+                path, content_hash, remaining
+            );
+            return Ok(());
+        }
+    }
+
+    if entry.announced {
+        let dht = { state.dht.lock().await.as_ref().cloned() };
+        match dht {
+            Some(dht) => {
+                if let Err(e) = dht.stop_publishing_file(content_hash.clone()).await {
+                    warn!("Failed to stop announcing {}: {}", content_hash, e);
+                }
+            }
+            None => warn!("DHT not running; {} stays announced until its record expires", content_hash),
+        }
+    }
+
+    // Unregister every HTTP entry backed by this content (lookups may be keyed by merkle root)
+    let http_keys: Vec<String> = {
+        let files = state.http_server_state.files.read().await;
+        files
+            .iter()
+            .filter(|(_, meta)| meta.file_hash == content_hash)
+            .map(|(key, _)| key.clone())
+            .collect()
+    };
+    for key in http_keys {
+        state.http_server_state.unregister_file(&key).await;
+    }
+
+    state
+        .shared_files
+        .unshare(&content_hash, finish_in_flight.unwrap_or(true))
+        .await?;
+    Ok(())
+}
+
+/// Re-hash a stale shared file and resume serving it if the content is unchanged
+#[tauri::command]
+pub async fn reverify_shared_file(
+    state: State<'_, AppState>,
+    content_hash: String,
+) -> Result<SharedFileEntry, String> {
+    state.shared_files.reverify(&content_hash).await
+}
+
+async fn apply_seeding_change(app: &AppHandle, state: &AppState, change: SeedingLimitChange) {
+    if let Some(announce) = change.announce {
+        let dht = { state.dht.lock().await.as_ref().cloned() };
+        match dht {
+            Some(dht) => {
+                let result = if announce {
+                    dht.republish_file(&change.content_hash).await
+                } else {
+                    dht.stop_publishing_file(change.content_hash.clone()).await
+                };
+                if let Err(e) = result {
+                    warn!(
+                        "Failed to update announcement of {} for its seeding limit: {}",
+                        change.content_hash, e
+                    );
+                }
+            }
+            None => warn!(
+                "DHT not running; announcement of {} not updated for its seeding limit",
+                change.content_hash
+            ),
+        }
+    }
+    let _ = app.emit("seeding-limit-changed", change);
+}
+
+/// Withdraw and restore DHT announcements as shared files reach or leave
+/// their seeding limits, and expire time-window limits as they run out
+pub async fn run_seeding_limit_loop(app: AppHandle) {
+    let state = app.state::<AppState>();
+    let mut changes = state.shared_files.subscribe_seeding_changes();
+    let mut interval = tokio::time::interval(SEEDING_LIMIT_TICK);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => state.shared_files.enforce_seeding_limits().await,
+            change = changes.recv() => match change {
+                Ok(change) => apply_seeding_change(&app, &state, change).await,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Missed {} seeding limit change(s)", missed);
+                }
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+}
+
+/// Limits applied to shared files that have none of their own
+#[tauri::command]
+pub async fn get_seeding_limits(state: State<'_, AppState>) -> Result<SeedingLimits, String> {
+    Ok(state.shared_files.seeding_limits().await)
+}
+
+/// Replace the global seeding limits; an empty object means seed forever
+#[tauri::command]
+pub async fn set_seeding_limits(
+    state: State<'_, AppState>,
+    limits: SeedingLimits,
+) -> Result<(), String> {
+    state.shared_files.set_seeding_limits(limits).await
+}
+
+/// Give one shared file its own seeding limits, or `None` to use the global ones
+#[tauri::command]
+pub async fn set_content_seeding_limits(
+    state: State<'_, AppState>,
+    content_hash: String,
+    limits: Option<SeedingLimits>,
+) -> Result<SharedFileEntry, String> {
+    state
+        .shared_files
+        .set_content_seeding_limits(&content_hash, limits)
+        .await
+}
+
+/// Re-hash a shared file now. A corrupted file stops being served and a
+/// `shared-file-corrupted` event is emitted.
+#[tauri::command]
+pub async fn verify_shared_file(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    content_hash: String,
+) -> Result<VerificationReport, String> {
+    let report = state.shared_files.verify(&content_hash).await?;
+    report_verification(&app, &state, &report).await;
+    Ok(report)
+}
+
+/// Re-download the damaged chunks of a corrupted shared file from other
+/// providers and patch the local copy
+#[tauri::command]
+pub async fn repair_shared_file(
+    state: State<'_, AppState>,
+    content_hash: String,
+) -> Result<SharedFileEntry, String> {
+    if other_providers(&state, &content_hash).await?.is_empty() {
+        return Err(format!("No other providers are seeding {}", content_hash));
+    }
+    let ms = { state.multi_source_download.lock().await.as_ref().cloned() }
+        .ok_or_else(|| "Multi-source download service not available".to_string())?;
+    state
+        .shared_files
+        .repair(&content_hash, |offset, len| {
+            let ms = ms.clone();
+            let content_hash = content_hash.clone();
+            async move { ms.fetch_range(&content_hash, offset, len).await }
+        })
+        .await
+}
+
+/// Upload slot usage with per-peer serving statistics
+#[tauri::command]
+pub async fn get_upload_slot_stats(state: State<'_, AppState>) -> Result<UploadSlotStats, String> {
+    Ok(state.http_server_state.upload_slots.stats())
+}
+
+/// Let `peer_id` use (or stop using) the reserved upload slots. Only
+/// authenticated WebRTC requests from it can; HTTP requests never do.
+#[tauri::command]
+pub async fn set_upload_peer_trusted(
+    state: State<'_, AppState>,
+    peer_id: String,
+    trusted: bool,
+) -> Result<(), String> {
+    state.http_server_state.upload_slots.set_trusted(&peer_id, trusted);
+    Ok(())
+}
@@ -0,0 +1,57 @@
+// Tauri commands for Chiral-managed storage (download directory, quota, cleanup)
+
+use crate::storage::{CleanupInput, CleanupReport, StorageSettings, StorageUsage};
+use crate::AppState;
+use tauri::State;
+
+#[tauri::command]
+pub async fn get_storage_settings(state: State<'_, AppState>) -> Result<StorageSettings, String> {
+    Ok(state.storage.settings())
+}
+
+#[tauri::command]
+pub async fn update_storage_settings(
+    state: State<'_, AppState>,
+    settings: StorageSettings,
+) -> Result<(), String> {
+    state
+        .storage
+        .update_settings(settings)
+        .map_err(|e| e.to_string())
+}
+
+/// Usage of partial downloads, chunk cache, manifests and shared files
+#[tauri::command]
+pub async fn get_storage_usage(state: State<'_, AppState>) -> Result<StorageUsage, String> {
+    let storage = state.storage.clone();
+    let mut usage = tokio::task::spawn_blocking(move || storage.usage())
+        .await
+        .map_err(|e| format!("Storage usage scan failed: {}", e))?;
+    usage.deduplicated_savings = state.shared_files.dedup_savings().await;
+    Ok(usage)
+}
+
+/// Remove orphaned partial downloads and shared-file data no longer referenced
+#[tauri::command]
+pub async fn cleanup_storage(state: State<'_, AppState>) -> Result<CleanupReport, String> {
+    let mut input = CleanupInput::default();
+
+    for entry in state.shared_files.list().await {
+        input.referenced_hashes.insert(entry.content_hash);
+    }
+    for metadata in state.http_server_state.files.read().await.values() {
+        input.referenced_hashes.insert(metadata.file_hash.clone());
+    }
+    for session in state.download_sessions.lock().await.values() {
+        input.active_partials.insert(session.temp_path.clone());
+    }
+    let ms = state.multi_source_download.lock().await.as_ref().cloned();
+    if let Some(ms) = ms {
+        input.active_partials.extend(ms.partial_paths().await);
+    }
+
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || storage.cleanup(&input))
+        .await
+        .map_err(|e| format!("Storage cleanup failed: {}", e))
+}
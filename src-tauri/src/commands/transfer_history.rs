@@ -0,0 +1,27 @@
+// Tauri commands for the persistent transfer history
+
+use crate::transfer_history::{HistoryFilter, TransferHistory, TransferRecord};
+use std::sync::Arc;
+use tauri::State;
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Finished transfers matching `filter`, newest first
+#[tauri::command]
+pub async fn get_transfer_history(
+    history: State<'_, Arc<TransferHistory>>,
+    filter: Option<HistoryFilter>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> Result<Vec<TransferRecord>, String> {
+    Ok(history.query(
+        &filter.unwrap_or_default(),
+        limit.unwrap_or(DEFAULT_PAGE_SIZE),
+        offset.unwrap_or(0),
+    ))
+}
+
+#[tauri::command]
+pub async fn clear_transfer_history(history: State<'_, Arc<TransferHistory>>) -> Result<(), String> {
+    history.clear()
+}
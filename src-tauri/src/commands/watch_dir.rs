@@ -0,0 +1,308 @@
+// Tauri commands for the watch directory
+
+use crate::commands::shared_files::unshare_file;
+use crate::shared_files::hash_file;
+use crate::watch_dir::{
+    self, scan, WatchActions, WatchDirConfig, WatchEvent, WatchFilter, WatchSet, WatchStatus,
+    WatchTracker,
+};
+use crate::AppState;
+use async_trait::async_trait;
+use notify::RecommendedWatcher;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::{mpsc, Mutex};
+use tracing::{info, warn};
+
+/// Payload of the `watch-file-published`, `watch-file-failed` and
+/// `watch-file-removed` events
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WatchFileEvent {
+    path: String,
+    content_hash: Option<String>,
+    error: Option<String>,
+}
+
+struct WatchRuntime {
+    config: WatchDirConfig,
+    watcher: Option<RecommendedWatcher>,
+    error: Option<String>,
+}
+
+/// Managed state of the watch directory. Lock `runtime` before `files` when
+/// both are needed.
+pub struct WatchDirState {
+    runtime: Mutex<WatchRuntime>,
+    files: Mutex<WatchSet>,
+    events_tx: mpsc::UnboundedSender<WatchEvent>,
+    events_rx: Mutex<Option<mpsc::UnboundedReceiver<WatchEvent>>>,
+    state_path: PathBuf,
+}
+
+impl WatchDirState {
+    /// Restore the configuration and the files published before a restart;
+    /// watching starts with `run_watch_dir_loop`
+    pub fn load(state_path: PathBuf) -> Self {
+        let (config, published) = watch_dir::load(&state_path);
+        let (filter, error) = match WatchFilter::new(&config.include, &config.exclude) {
+            Ok(filter) => (filter, None),
+            Err(e) => (WatchFilter::new(&[], &[]).expect("empty filter"), Some(e)),
+        };
+        let mut tracker = WatchTracker::new(Duration::from_secs(config.settle_secs));
+        tracker.restore(published, Instant::now());
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        Self {
+            files: Mutex::new(WatchSet {
+                roots: config.path.iter().cloned().collect(),
+                filter,
+                tracker,
+            }),
+            runtime: Mutex::new(WatchRuntime {
+                config,
+                watcher: None,
+                error,
+            }),
+            events_tx,
+            events_rx: Mutex::new(Some(events_rx)),
+            state_path,
+        }
+    }
+
+    async fn status(&self) -> WatchStatus {
+        let runtime = self.runtime.lock().await;
+        WatchStatus {
+            config: runtime.config.clone(),
+            watching: runtime.watcher.is_some(),
+            error: runtime.error.clone(),
+            files: self.files.lock().await.tracker.report(),
+        }
+    }
+
+    async fn persist(&self) {
+        let (config, published) = {
+            let runtime = self.runtime.lock().await;
+            (runtime.config.clone(), self.files.lock().await.tracker.published())
+        };
+        if let Err(e) = watch_dir::save(&self.state_path, &config, published) {
+            warn!("{}", e);
+        }
+    }
+
+    /// (Re)start watching the configured directory and reconcile the tracked
+    /// files with what is on disk
+    async fn start(&self, app: &AppHandle) {
+        let dir = {
+            let mut runtime = self.runtime.lock().await;
+            runtime.watcher = None;
+            let Some(dir) = runtime.config.path.clone() else {
+                runtime.error = None;
+                return;
+            };
+            match watch_dir::watch_recursive(&dir, self.events_tx.clone()) {
+                Ok(watcher) => {
+                    info!("Watching {} for files to publish", dir.display());
+                    runtime.watcher = Some(watcher);
+                    runtime.error = None;
+                }
+                Err(e) => {
+                    warn!("Failed to watch {}: {}", dir.display(), e);
+                    runtime.error = Some(format!("Failed to watch {}: {}", dir.display(), e));
+                    return;
+                }
+            }
+            // Files from a previous directory or filter stay shared but are
+            // no longer tracked
+            let mut files = self.files.lock().await;
+            let untracked: Vec<PathBuf> = files
+                .tracker
+                .paths()
+                .into_iter()
+                .filter(|path| !files.relevant(path))
+                .collect();
+            for path in untracked {
+                files.tracker.remove(&path);
+            }
+            dir
+        };
+
+        let mut actions = AppWatchActions { app, watch: self };
+        let on_disk = tokio::task::spawn_blocking(move || scan(&dir))
+            .await
+            .unwrap_or_default();
+        let tracked = self.files.lock().await.tracker.paths();
+        for path in tracked.iter().filter(|p| !on_disk.contains(p)) {
+            watch_dir::refresh(&self.files, path, &mut actions).await;
+        }
+        for path in &on_disk {
+            watch_dir::refresh(&self.files, path, &mut actions).await;
+        }
+        self.persist().await;
+    }
+}
+
+/// Publishes watched files into the app's shared files
+struct AppWatchActions<'a> {
+    app: &'a AppHandle,
+    watch: &'a WatchDirState,
+}
+
+#[async_trait]
+impl WatchActions for AppWatchActions<'_> {
+    async fn publish(&mut self, path: &Path) -> Result<String, String> {
+        publish(self.app, path).await
+    }
+
+    async fn unannounce(&mut self, path: &Path, content_hash: String) {
+        unannounce(self.app, path, content_hash).await;
+    }
+
+    async fn published(&mut self, path: &Path, result: &Result<String, String>) {
+        let path_str = path.to_string_lossy().to_string();
+        match result {
+            Ok(content_hash) => {
+                info!("Published {} from the watch directory", path.display());
+                self.watch.persist().await;
+                let _ = self.app.emit(
+                    "watch-file-published",
+                    WatchFileEvent {
+                        path: path_str,
+                        content_hash: Some(content_hash.clone()),
+                        error: None,
+                    },
+                );
+            }
+            Err(e) => {
+                warn!("Failed to publish {} from the watch directory: {}", path.display(), e);
+                let _ = self.app.emit(
+                    "watch-file-failed",
+                    WatchFileEvent {
+                        path: path_str,
+                        content_hash: None,
+                        error: Some(e.clone()),
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Publish a settled file. The file is copied into storage so the original
+/// stays in the watch directory, and the shared entry references the original.
+async fn publish(app: &AppHandle, path: &Path) -> Result<String, String> {
+    let state = app.state::<AppState>();
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown")
+        .to_string();
+    let staging = state
+        .http_server_state
+        .storage_dir
+        .join(format!(".watch-{}", uuid::Uuid::new_v4()));
+    tokio::fs::copy(path, &staging)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    let result = async {
+        let content_hash = hash_file(&staging)
+            .await
+            .map_err(|e| format!("Failed to hash {}: {}", path.display(), e))?;
+        crate::upload_file_to_network(
+            app.clone(),
+            app.state::<AppState>(),
+            staging.to_string_lossy().to_string(),
+            None,
+            None,
+            Some(file_name),
+            None,
+        )
+        .await?;
+        state
+            .shared_files
+            .replace_reference(&content_hash, &staging, path.to_path_buf())
+            .await?;
+        Ok(content_hash)
+    }
+    .await;
+
+    if tokio::fs::try_exists(&staging).await.unwrap_or(false) {
+        let _ = tokio::fs::remove_file(&staging).await;
+    }
+    result
+}
+
+/// Stop sharing content published from `path`
+async fn unannounce(app: &AppHandle, path: &Path, content_hash: String) {
+    let path_str = path.to_string_lossy().to_string();
+    info!("{} left the watch directory; un-announcing {}", path.display(), content_hash);
+    if let Err(e) = unshare_file(
+        app.state::<AppState>(),
+        content_hash.clone(),
+        Some(path_str.clone()),
+        Some(true),
+    )
+    .await
+    {
+        warn!("Failed to un-announce {}: {}", content_hash, e);
+    }
+    let _ = app.emit(
+        "watch-file-removed",
+        WatchFileEvent {
+            path: path_str,
+            content_hash: Some(content_hash),
+            error: None,
+        },
+    );
+}
+
+/// Configure the watch directory; `path: None` stops watching. Files
+/// published from a previous directory stay shared.
+#[tauri::command]
+pub async fn set_watch_directory(
+    app: AppHandle,
+    watch: State<'_, WatchDirState>,
+    config: WatchDirConfig,
+) -> Result<WatchStatus, String> {
+    if let Some(dir) = &config.path {
+        if !dir.is_dir() {
+            return Err(format!("{} is not a directory", dir.display()));
+        }
+    }
+    let filter = WatchFilter::new(&config.include, &config.exclude)?;
+    {
+        let mut runtime = watch.runtime.lock().await;
+        let mut files = watch.files.lock().await;
+        let settled = files.tracker.published();
+        files.tracker = WatchTracker::new(Duration::from_secs(config.settle_secs));
+        files.tracker.restore(settled, Instant::now());
+        files.roots = config.path.iter().cloned().collect();
+        files.filter = filter;
+        runtime.config = config;
+    }
+    watch.start(&app).await;
+    watch.persist().await;
+    Ok(watch.status().await)
+}
+
+/// Watched directory, per-file publish state and errors
+#[tauri::command]
+pub async fn get_watch_status(watch: State<'_, WatchDirState>) -> Result<WatchStatus, String> {
+    Ok(watch.status().await)
+}
+
+/// Watch the configured directory, publishing files as they settle and
+/// un-announcing files that are deleted
+pub async fn run_watch_dir_loop(app: AppHandle) {
+    let state = app.state::<WatchDirState>();
+    let watch = state.inner();
+    let Some(events) = watch.events_rx.lock().await.take() else {
+        return;
+    };
+    watch.start(&app).await;
+
+    let mut actions = AppWatchActions { app: &app, watch };
+    watch_dir::run_watch_loop(&watch.files, events, &mut actions).await;
+}
@@ -0,0 +1,296 @@
+//! Protocol version compatibility between Chiral nodes.
+//!
+//! Peers advertise `chiral-network/<version>` as their Identify agent version.
+//! Nodes whose versions are incompatible are disconnected as soon as they are
+//! identified instead of failing later in confusing ways.
+//!
+//! Compatibility follows semver: the major version must match, and while the
+//! major version is 0 the minor version must match as well.
+//!
+//! Within a compatible range, optional features are negotiated per peer with
+//! a `PeerCapabilitySet`. Stream features show up in the Identify protocol
+//! list. Channel features ride gossipsub, which has no protocol id of its
+//! own, so they are listed after the version in the agent string
+//! (`chiral-network/0.1.0 features=reactions,threads`). Older nodes send the
+//! bare version and get neither.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Agent version prefix used by Chiral nodes
+pub const AGENT_PREFIX: &str = "chiral-network/";
+
+/// Close reason reported when a peer is dropped for an incompatible version
+pub const VERSION_MISMATCH: &str = "VERSION_MISMATCH";
+
+/// Emoji reactions on channel messages
+pub const FEATURE_REACTIONS: &str = "reactions";
+
+/// Reply-to threads on channel messages
+pub const FEATURE_THREADS: &str = "threads";
+
+/// Channel features this build supports, in agent string order
+const LOCAL_FEATURES: &[&str] = &[FEATURE_REACTIONS, FEATURE_THREADS];
+
+/// Identify agent string of this build
+pub fn agent_version() -> String {
+    format!(
+        "{}{} features={}",
+        AGENT_PREFIX,
+        env!("CARGO_PKG_VERSION"),
+        LOCAL_FEATURES.join(",")
+    )
+}
+
+/// A `major.minor.patch` version. Pre-release and build suffixes are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct SemVer {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl SemVer {
+    pub const fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Version of this build
+    pub fn current() -> Self {
+        env!("CARGO_PKG_VERSION")
+            .parse()
+            .expect("CARGO_PKG_VERSION is valid semver")
+    }
+}
+
+impl fmt::Display for SemVer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for SemVer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let core = s
+            .trim()
+            .trim_start_matches('v')
+            .split(&['-', '+'][..])
+            .next()
+            .unwrap_or_default();
+        let mut parts = core.split('.');
+        let mut next = |name: &str| -> Result<u64, String> {
+            parts
+                .next()
+                .ok_or_else(|| format!("missing {} version in '{}'", name, s))?
+                .parse()
+                .map_err(|_| format!("invalid {} version in '{}'", name, s))
+        };
+        let version = SemVer::new(next("major")?, next("minor")?, next("patch")?);
+        if parts.next().is_some() {
+            return Err(format!("too many version components in '{}'", s));
+        }
+        Ok(version)
+    }
+}
+
+/// Inclusive range of versions a node can talk to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionRange {
+    pub min: SemVer,
+    pub max: SemVer,
+}
+
+impl VersionRange {
+    /// Versions compatible with `local` under semver rules
+    pub fn compatible_with(local: SemVer) -> Self {
+        let max = if local.major == 0 {
+            SemVer::new(0, local.minor, u64::MAX)
+        } else {
+            SemVer::new(local.major, u64::MAX, u64::MAX)
+        };
+        let min = if local.major == 0 {
+            SemVer::new(0, local.minor, 0)
+        } else {
+            SemVer::new(local.major, 0, 0)
+        };
+        Self { min, max }
+    }
+
+    pub fn contains(&self, version: SemVer) -> bool {
+        self.min <= version && version <= self.max
+    }
+}
+
+/// Outcome of comparing a remote version against ours
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum CompatibilityResult {
+    Compatible,
+    Incompatible {
+        local: SemVer,
+        remote: SemVer,
+        supported: VersionRange,
+    },
+}
+
+impl CompatibilityResult {
+    pub fn is_compatible(&self) -> bool {
+        matches!(self, CompatibilityResult::Compatible)
+    }
+}
+
+/// Check whether a peer running `remote` can talk to a node running `local`
+pub fn check_compatibility(local: SemVer, remote: SemVer) -> CompatibilityResult {
+    let supported = VersionRange::compatible_with(local);
+    if supported.contains(remote) {
+        CompatibilityResult::Compatible
+    } else {
+        CompatibilityResult::Incompatible {
+            local,
+            remote,
+            supported,
+        }
+    }
+}
+
+/// Extract the version from a `chiral-network/<version>` agent string.
+///
+/// Returns `None` for agents that are not Chiral nodes (relays, other tools)
+/// or carry an unparseable version; those peers are not version-checked.
+pub fn parse_agent_version(agent_version: &str) -> Option<SemVer> {
+    agent_version
+        .strip_prefix(AGENT_PREFIX)
+        .and_then(|v| v.split_whitespace().next())
+        .and_then(|v| v.parse().ok())
+}
+
+/// What a peer can handle, from its Identify info
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerCapabilitySet {
+    pub supports_reactions: bool,
+    pub supports_threads: bool,
+    pub supports_file_transfer: bool,
+    pub supports_voice: bool,
+    pub protocol_version: SemVer,
+}
+
+impl PeerCapabilitySet {
+    pub fn from_identify<P: AsRef<str>>(agent_version: &str, protocols: &[P]) -> Self {
+        let speaks = |id: &str| protocols.iter().any(|p| p.as_ref() == id);
+        let features: Vec<&str> = agent_version
+            .split_whitespace()
+            .find_map(|part| part.strip_prefix("features="))
+            .map(|list| list.split(',').collect())
+            .unwrap_or_default();
+        Self {
+            supports_reactions: features.contains(&FEATURE_REACTIONS),
+            supports_threads: features.contains(&FEATURE_THREADS),
+            supports_file_transfer: speaks(crate::protocol::FILE_TRANSFER_PROTOCOL),
+            supports_voice: speaks(crate::protocol::CALL_SIGNALING_PROTOCOL),
+            protocol_version: parse_agent_version(agent_version).unwrap_or(SemVer::new(0, 0, 0)),
+        }
+    }
+
+    /// Assumed for peers not identified yet, so nothing is withheld from
+    /// them by mistake
+    pub fn assumed() -> Self {
+        Self {
+            supports_reactions: true,
+            supports_threads: true,
+            supports_file_transfer: true,
+            supports_voice: true,
+            protocol_version: SemVer::current(),
+        }
+    }
+
+    /// Features at least one of `peers` supports; None without peers
+    pub fn any_of(peers: impl IntoIterator<Item = Self>) -> Option<Self> {
+        peers.into_iter().reduce(|a, b| Self {
+            supports_reactions: a.supports_reactions || b.supports_reactions,
+            supports_threads: a.supports_threads || b.supports_threads,
+            supports_file_transfer: a.supports_file_transfer || b.supports_file_transfer,
+            supports_voice: a.supports_voice || b.supports_voice,
+            protocol_version: a.protocol_version.max(b.protocol_version),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_semver() {
+        assert_eq!("1.2.3".parse::<SemVer>().unwrap(), SemVer::new(1, 2, 3));
+        assert_eq!("v0.1.0-beta.2".parse::<SemVer>().unwrap(), SemVer::new(0, 1, 0));
+        assert!("1.2".parse::<SemVer>().is_err());
+        assert!("1.2.3.4".parse::<SemVer>().is_err());
+        assert!("a.b.c".parse::<SemVer>().is_err());
+    }
+
+    #[test]
+    fn test_parse_agent_version() {
+        assert_eq!(
+            parse_agent_version("chiral-network/0.1.0"),
+            Some(SemVer::new(0, 1, 0))
+        );
+        assert_eq!(parse_agent_version("rust-libp2p/0.54.0"), None);
+        assert_eq!(parse_agent_version("chiral-network/garbage"), None);
+    }
+
+    #[test]
+    fn test_major_version_must_match() {
+        let local = SemVer::new(2, 3, 0);
+        assert!(check_compatibility(local, SemVer::new(2, 0, 9)).is_compatible());
+        assert!(check_compatibility(local, SemVer::new(2, 9, 0)).is_compatible());
+        assert!(!check_compatibility(local, SemVer::new(1, 9, 0)).is_compatible());
+        assert!(!check_compatibility(local, SemVer::new(3, 0, 0)).is_compatible());
+    }
+
+    #[test]
+    fn test_pre_1_0_minor_version_must_match() {
+        let local = SemVer::new(0, 4, 2);
+        assert!(check_compatibility(local, SemVer::new(0, 4, 0)).is_compatible());
+        match check_compatibility(local, SemVer::new(0, 5, 0)) {
+            CompatibilityResult::Incompatible { supported, .. } => {
+                assert_eq!(supported.min, SemVer::new(0, 4, 0));
+            }
+            other => panic!("expected incompatible, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_capabilities_from_identify() {
+        let current = PeerCapabilitySet::from_identify(
+            &agent_version(),
+            &[crate::protocol::FILE_TRANSFER_PROTOCOL, crate::protocol::KADEMLIA_PROTOCOL],
+        );
+        assert!(current.supports_reactions && current.supports_threads);
+        assert!(current.supports_file_transfer);
+        assert!(!current.supports_voice);
+        assert_eq!(current.protocol_version, SemVer::current());
+        assert_eq!(parse_agent_version(&agent_version()), Some(SemVer::current()));
+
+        let old = PeerCapabilitySet::from_identify("chiral-network/0.1.0", &[crate::protocol::CALL_SIGNALING_PROTOCOL]);
+        assert!(!old.supports_reactions && !old.supports_threads);
+        assert!(old.supports_voice);
+
+        let either = PeerCapabilitySet::any_of([old, current]).unwrap();
+        assert!(either.supports_reactions && either.supports_voice);
+        assert!(PeerCapabilitySet::any_of([]).is_none());
+    }
+
+    #[test]
+    fn test_current_version_parses() {
+        let current = SemVer::current();
+        assert!(check_compatibility(current, current).is_compatible());
+    }
+}
@@ -0,0 +1,173 @@
+// Transparent compression of transfer chunks
+//
+// Text-heavy content (CSV, JSON, logs) shrinks several times under zstd, while
+// media and archives are already compressed and only cost CPU. A publisher
+// samples its content once and records whether it compressed well; a sender
+// only offers compression for such content, and compresses a chunk on the wire
+// only when that chunk actually shrinks. Hashes are always computed over the
+// uncompressed bytes, so content identity does not depend on the codec.
+
+use serde::Serialize;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Codec id negotiated in the transfer handshake
+pub const ZSTD: &str = "zstd";
+
+/// Codecs this node can compress and decompress chunks with
+pub const SUPPORTED_CODECS: &[&str] = &[ZSTD];
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Bytes read from each sampled region of a file
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Regions sampled: start, middle and end
+const SAMPLE_REGIONS: u64 = 3;
+
+/// A sample must compress to at most this fraction of its size to count
+const MAX_COMPRESSED_RATIO: f64 = 0.9;
+
+/// Formats that are already compressed; files with these extensions are not sampled
+const PRECOMPRESSED_EXTENSIONS: &[&str] = &[
+    "7z", "aac", "apk", "avi", "avif", "br", "bz2", "docx", "epub", "flac", "gif", "gz", "heic",
+    "jar", "jpeg", "jpg", "lz4", "m4a", "m4v", "mkv", "mov", "mp3", "mp4", "ogg", "opus", "png",
+    "pptx", "rar", "tgz", "webm", "webp", "xlsx", "xz", "zip", "zst",
+];
+
+/// Whether `file_name` is a format that is compressed already
+pub fn is_precompressed(file_name: &str) -> bool {
+    Path::new(file_name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| PRECOMPRESSED_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+fn worth_compressing(sample: &[u8]) -> bool {
+    if sample.is_empty() {
+        return false;
+    }
+    match zstd::bulk::compress(sample, ZSTD_LEVEL) {
+        Ok(compressed) => (compressed.len() as f64) <= sample.len() as f64 * MAX_COMPRESSED_RATIO,
+        Err(_) => false,
+    }
+}
+
+/// Compress samples of the file at `path` and report whether it is worth
+/// compressing on the wire. `file_name` is the user-facing name, whose
+/// extension may differ from that of `path` in storage.
+pub fn sample_is_compressible(path: &Path, file_name: &str) -> std::io::Result<bool> {
+    if is_precompressed(file_name) {
+        return Ok(false);
+    }
+    let mut file = std::fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE * SAMPLE_REGIONS as usize);
+    let mut buf = vec![0u8; SAMPLE_SIZE];
+    let last_start = size.saturating_sub(SAMPLE_SIZE as u64);
+    let mut previous_end = 0;
+    for region in 0..SAMPLE_REGIONS {
+        let start = (last_start * region / (SAMPLE_REGIONS - 1)).max(previous_end);
+        if start >= size && region > 0 {
+            break;
+        }
+        file.seek(SeekFrom::Start(start))?;
+        let n = file.read(&mut buf)?;
+        sample.extend_from_slice(&buf[..n]);
+        previous_end = start + n as u64;
+    }
+    Ok(worth_compressing(&sample))
+}
+
+/// `data` compressed, or `None` when compressing does not make it smaller
+pub fn compress(data: &[u8]) -> Option<Vec<u8>> {
+    zstd::bulk::compress(data, ZSTD_LEVEL)
+        .ok()
+        .filter(|compressed| compressed.len() < data.len())
+}
+
+/// Decompress a chunk that is at most `max_len` bytes uncompressed
+pub fn decompress(data: &[u8], max_len: usize) -> std::io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    zstd::stream::read::Decoder::new(data)?
+        .take(max_len as u64 + 1)
+        .read_to_end(&mut out)?;
+    if out.len() > max_len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("chunk decompresses to more than {} bytes", max_len),
+        ));
+    }
+    Ok(out)
+}
+
+/// Logical bytes moved vs. bytes that crossed the wire
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompressionStats {
+    pub logical_bytes: u64,
+    pub wire_bytes: u64,
+}
+
+impl CompressionStats {
+    pub fn record(&mut self, logical_bytes: usize, wire_bytes: usize) {
+        self.logical_bytes += logical_bytes as u64;
+        self.wire_bytes += wire_bytes as u64;
+    }
+
+    pub fn saved_bytes(&self) -> u64 {
+        self.logical_bytes.saturating_sub(self.wire_bytes)
+    }
+}
+
+/// Totals for direct transfers in both directions since startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferCompressionStats {
+    pub sent: CompressionStats,
+    pub received: CompressionStats,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn text_is_compressed_and_noise_is_not() {
+        let csv: Vec<u8> = (0..4000)
+            .flat_map(|i| format!("{},sensor-{},{}.5\n", i, i % 7, i * 3).into_bytes())
+            .collect();
+        let compressed = compress(&csv).expect("csv shrinks");
+        assert!(compressed.len() * 3 < csv.len());
+        assert_eq!(decompress(&compressed, csv.len()).unwrap(), csv);
+        assert!(decompress(&compressed, csv.len() - 1).is_err());
+
+        assert!(compress(&noise(SAMPLE_SIZE)).is_none());
+    }
+
+    #[test]
+    fn sampling_skips_media_and_incompressible_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = dir.path().join("stored");
+        std::fs::write(&text, "{\"key\": \"value\"}\n".repeat(20_000)).unwrap();
+        assert!(sample_is_compressible(&text, "data.json").unwrap());
+        assert!(!sample_is_compressible(&text, "holiday.MP4").unwrap());
+
+        let random = dir.path().join("random");
+        std::fs::write(&random, noise(3 * SAMPLE_SIZE)).unwrap();
+        assert!(!sample_is_compressible(&random, "blob.bin").unwrap());
+    }
+}
@@ -0,0 +1,442 @@
+//! Node-wide Chiral configuration.
+//!
+//! Settings that are not specific to a single protocol. Values come from the
+//! environment so that headless deployments can configure them without a GUI.
+//! A headless node started with a `chiral.toml` installs the file's values as
+//! the base the environment is applied on top of.
+
+use crate::chunk_pipeline::DEFAULT_PIPELINE_DEPTH;
+use crate::discovery::{
+    BootstrapMode, DEFAULT_BOOTSTRAP_MIN_CONFIRMATIONS, DEFAULT_KAD_INITIAL_BURST, DEFAULT_KAD_QUERIES_PER_SECOND,
+    DEFAULT_PRUNE_AFTER_FAILURES,
+};
+use crate::integrations::WebhookEventKind;
+use crate::relay_consent::RelayConsentPolicy;
+use crate::security::FilterRuleSpec;
+use crate::stall_recovery::{DEFAULT_MAX_STALL_RECOVERIES, DEFAULT_STALL_TIMEOUT};
+use crate::upload_slots::{UploadSlotConfig, DEFAULT_UPLOAD_QUEUE, DEFAULT_UPLOAD_SLOTS};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use url::Url;
+
+/// Values from the headless configuration file, if one was loaded
+static FILE_BASE: OnceLock<ChiralConfig> = OnceLock::new();
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChiralConfig {
+    /// URL of a signed CBOR bootstrap manifest (`CHIRAL_BOOTSTRAP_MANIFEST_URL`)
+    #[serde(default)]
+    pub bootstrap_manifest_url: Option<String>,
+
+    /// Hex-encoded Ed25519 public key the manifest must be signed with
+    /// (`CHIRAL_MANIFEST_TRUSTED_KEY`)
+    #[serde(default)]
+    pub manifest_trusted_key: Option<String>,
+
+    /// `[storage]` section
+    #[serde(default)]
+    pub storage: StorageConfig,
+
+    /// `[uploads]` section
+    #[serde(default)]
+    pub uploads: UploadsConfig,
+
+    /// `[downloads]` section
+    #[serde(default)]
+    pub downloads: DownloadsConfig,
+
+    /// `[swarm]` section
+    #[serde(default)]
+    pub swarm: SwarmConfig,
+
+    /// `[security]` section
+    #[serde(default)]
+    pub security: SecurityConfig,
+}
+
+/// Local persistence settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StorageConfig {
+    /// Encrypt the peer address cache with a key derived from the node
+    /// identity (`CHIRAL_ENCRYPT_PEER_STORE`). Needs a persistent identity;
+    /// with a freshly generated one the previous cache cannot be read.
+    /// A plain cache from before is moved into the encrypted one and deleted.
+    #[serde(default)]
+    pub encrypt_peer_store: bool,
+}
+
+/// Limits on serving files to other peers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UploadsConfig {
+    /// Requests served at once (`CHIRAL_UPLOAD_SLOTS`)
+    pub upload_slots: usize,
+
+    /// Extra slots only trusted peers may use (`CHIRAL_RESERVED_UPLOAD_SLOTS`)
+    pub reserved_slots: usize,
+
+    /// Requests allowed to wait for a slot (`CHIRAL_UPLOAD_QUEUE`)
+    pub queue_size: usize,
+
+    /// Peer IDs allowed to use the reserved slots (`CHIRAL_TRUSTED_PEERS`,
+    /// comma-separated)
+    pub trusted_peers: Vec<String>,
+
+    /// Hours between background re-hashes of shared files; 0 disables them
+    /// (`CHIRAL_VERIFY_INTERVAL_HOURS`)
+    pub verify_interval_hours: usize,
+
+    /// Disk read rate for verification in MiB/s; 0 is unthrottled
+    /// (`CHIRAL_VERIFY_RATE_MIB`)
+    pub verify_rate_mib: usize,
+}
+
+impl Default for UploadsConfig {
+    fn default() -> Self {
+        Self {
+            upload_slots: DEFAULT_UPLOAD_SLOTS,
+            reserved_slots: 0,
+            queue_size: DEFAULT_UPLOAD_QUEUE,
+            trusted_peers: Vec::new(),
+            verify_interval_hours: 24,
+            verify_rate_mib: 32,
+        }
+    }
+}
+
+/// Where downloads may fetch data from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DownloadsConfig {
+    /// Fetch from the HTTP(S) web seeds listed in file metadata. Off means no
+    /// HTTP egress for downloads (`CHIRAL_DISABLE_WEB_SEEDS`).
+    pub web_seeds: bool,
+    /// Queue interrupted downloads again on startup. Applies to downloads
+    /// started from now on (`CHIRAL_DISABLE_AUTO_RESUME`).
+    pub auto_resume: bool,
+    /// Chunk requests kept outstanding per source; the largest window in
+    /// adaptive mode (`CHIRAL_PIPELINE_DEPTH`)
+    pub pipeline_depth: usize,
+    /// Size the request window per source from its throughput and timeouts
+    /// (`CHIRAL_DISABLE_ADAPTIVE_PIPELINE`)
+    pub adaptive_pipeline: bool,
+    /// MiB requested from one source and not yet received
+    /// (`CHIRAL_MAX_IN_FLIGHT_MIB`)
+    pub max_in_flight_mib: usize,
+    /// Keep the partial data of downloads encrypted with a session key held
+    /// only in memory. Such downloads start over after a restart
+    /// (`CHIRAL_ENCRYPT_PARTIALS`).
+    pub encrypt_partials: bool,
+    /// Seconds without verified data before a download counts as stalled
+    /// and its silent sources are replaced (`CHIRAL_STALL_TIMEOUT_SECS`)
+    pub stall_timeout_secs: u64,
+    /// Recoveries tried without progress before a stalled download fails
+    /// (`CHIRAL_MAX_STALL_RECOVERIES`)
+    pub max_stall_recoveries: u32,
+}
+
+impl Default for DownloadsConfig {
+    fn default() -> Self {
+        Self {
+            web_seeds: true,
+            auto_resume: true,
+            pipeline_depth: DEFAULT_PIPELINE_DEPTH,
+            adaptive_pipeline: true,
+            max_in_flight_mib: 8,
+            encrypt_partials: false,
+            stall_timeout_secs: DEFAULT_STALL_TIMEOUT.as_secs(),
+            max_stall_recoveries: DEFAULT_MAX_STALL_RECOVERIES,
+        }
+    }
+}
+
+/// Who may connect to this node
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityConfig {
+    /// Allow, deny and throttle rules for incoming connections, checked in
+    /// order (`[[security.filter_rules]]`)
+    pub filter_rules: Vec<FilterRuleSpec>,
+}
+
+/// Behaviour of the node towards the peers it talks to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SwarmConfig {
+    /// Tell message authors when we have seen their messages
+    /// (`CHIRAL_DISABLE_READ_RECEIPTS`)
+    pub send_read_receipts: bool,
+    /// Compress chunks of compressible content in direct transfers when the
+    /// other side supports it (`CHIRAL_DISABLE_TRANSFER_COMPRESSION`)
+    pub compress_transfers: bool,
+    /// Bytes bound into every Noise handshake. Peers with a different
+    /// prologue cannot connect, so this is only set for private networks;
+    /// empty, the default, is the plain handshake the relay daemon and older
+    /// nodes use (`CHIRAL_NOISE_PROLOGUE`).
+    pub noise_prologue: Vec<u8>,
+    /// Write every swarm event to this file as JSON lines, rotated daily
+    /// (`CHIRAL_EVENT_LOG_PATH`)
+    pub event_log_path: Option<PathBuf>,
+    /// Try to upgrade relayed connections to direct ones by hole punching
+    /// (`CHIRAL_DISABLE_DCUTR`; `nat.dcutr` in `chiral.toml`)
+    #[serde(skip)]
+    pub dcutr: bool,
+    /// Multiaddrs listened on besides the TCP port (`CHIRAL_LISTEN_ADDRS`,
+    /// comma-separated; `network.listen_addrs` in `chiral.toml`)
+    #[serde(skip)]
+    pub listen_addrs: Vec<String>,
+    /// Kademlia queries this node starts per second; more wait in a queue
+    /// (`CHIRAL_KAD_QUERIES_PER_SECOND`)
+    pub kad_queries_per_second: u32,
+    /// Queries per second allowed for the first 30 seconds, while the node
+    /// bootstraps (`CHIRAL_KAD_INITIAL_BURST`)
+    pub kad_initial_burst: u32,
+    /// Bootstrap nodes that must know a peer before its addresses enter the
+    /// routing table; 0 or 1 turns the check off
+    /// (`CHIRAL_BOOTSTRAP_MIN_CONFIRMATIONS`)
+    pub bootstrap_min_confirmations: u32,
+    /// Who may open circuits through this node's relay server
+    /// (`CHIRAL_RELAY_CONSENT`: open, known_peers_only, explicit_allow_list)
+    pub relay_consent: RelayConsentPolicy,
+    /// Peer ids always admitted by the relay consent policy
+    /// (`CHIRAL_RELAY_ALLOW_LIST`, comma-separated)
+    pub relay_allow_list: Vec<String>,
+    /// POST peer and NAT events here as JSON (`CHIRAL_WEBHOOK_URL`)
+    pub webhook_url: Option<Url>,
+    /// Key of the `X-Chiral-Signature` HMAC on webhook bodies
+    /// (`CHIRAL_WEBHOOK_SECRET`)
+    pub webhook_secret: Option<String>,
+    /// Events sent to the webhook (`CHIRAL_WEBHOOK_EVENTS`, comma-separated)
+    pub webhook_events: Vec<WebhookEventKind>,
+    /// Record key generation, sessions and signature checks in
+    /// `crypto_audit.db` (`CHIRAL_ENABLE_CRYPTO_AUDIT`)
+    pub enable_crypto_audit: bool,
+    /// Run as network infrastructure: DHT server, AutoNAT server and relay
+    /// server with high limits, without gossip or file transfers
+    /// (`CHIRAL_INFRA_MODE`)
+    pub infra_mode: bool,
+    /// Where the first peers come from (`CHIRAL_BOOTSTRAP_MODE`: bootstrap,
+    /// mdns_only, or static:ADDR,ADDR for a fixed list of peers)
+    pub bootstrap_mode: BootstrapMode,
+    /// MiB shared by the Kademlia store, address book, file caches and
+    /// chunks in flight; 0 leaves each cache at its own limits
+    /// (`CHIRAL_MEMORY_BUDGET_MB`)
+    pub memory_budget_mb: u64,
+    /// Peers GossipSub keeps in the mesh of each topic
+    /// (`CHIRAL_MESH_N`)
+    pub mesh_n: usize,
+    /// Below this many mesh peers a topic is degraded and GossipSub grafts
+    /// more (`CHIRAL_MESH_N_LOW`)
+    pub mesh_n_low: usize,
+    /// Above this many GossipSub prunes the mesh (`CHIRAL_MESH_N_HIGH`)
+    pub mesh_n_high: usize,
+    /// Failed health checks in a row before a bootstrap node is quarantined
+    /// for the rest of the run; 0 never quarantines
+    /// (`CHIRAL_BOOTSTRAP_PRUNE_AFTER_FAILURES`)
+    pub bootstrap_prune_after_failures: u32,
+}
+
+/// Prologue used by public Chiral nodes: none, so they can connect to
+/// relays and nodes that build a plain `noise::Config`
+pub const DEFAULT_NOISE_PROLOGUE: &[u8] = b"";
+
+impl Default for SwarmConfig {
+    fn default() -> Self {
+        Self {
+            send_read_receipts: true,
+            compress_transfers: true,
+            noise_prologue: DEFAULT_NOISE_PROLOGUE.to_vec(),
+            event_log_path: None,
+            dcutr: true,
+            listen_addrs: Vec::new(),
+            kad_queries_per_second: DEFAULT_KAD_QUERIES_PER_SECOND,
+            kad_initial_burst: DEFAULT_KAD_INITIAL_BURST,
+            bootstrap_min_confirmations: DEFAULT_BOOTSTRAP_MIN_CONFIRMATIONS,
+            relay_consent: RelayConsentPolicy::Open,
+            relay_allow_list: Vec::new(),
+            webhook_url: None,
+            webhook_secret: None,
+            webhook_events: WebhookEventKind::ALL.to_vec(),
+            enable_crypto_audit: false,
+            infra_mode: false,
+            bootstrap_mode: BootstrapMode::Bootstrap,
+            memory_budget_mb: 0,
+            mesh_n: 6,
+            mesh_n_low: 5,
+            mesh_n_high: 12,
+            bootstrap_prune_after_failures: DEFAULT_PRUNE_AFTER_FAILURES,
+        }
+    }
+}
+
+/// Put in place of secrets in copies that are logged or exported
+pub(super) const REDACTED: &str = "<redacted>";
+
+impl SwarmConfig {
+    /// Copy with the Noise prologue and webhook secret redacted
+    pub fn redacted(&self) -> Self {
+        let mut copy = self.clone();
+        copy.noise_prologue = REDACTED.as_bytes().to_vec();
+        if copy.webhook_secret.is_some() {
+            copy.webhook_secret = Some(REDACTED.to_string());
+        }
+        copy
+    }
+}
+
+impl UploadsConfig {
+    pub fn slot_config(&self) -> UploadSlotConfig {
+        UploadSlotConfig {
+            slots: self.upload_slots.max(1),
+            reserved_slots: self.reserved_slots,
+            queue_capacity: self.queue_size,
+            trusted_peers: self.trusted_peers.iter().cloned().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Verification read rate in bytes per second
+    pub fn verify_rate(&self) -> u64 {
+        self.verify_rate_mib as u64 * 1024 * 1024
+    }
+}
+
+pub(super) fn env_var(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+pub(super) fn env_number<T: std::str::FromStr>(name: &str) -> Option<T> {
+    env_var(name).and_then(|v| v.parse().ok())
+}
+
+/// `name` set to a yes or no value; anything else counts as unset
+pub(super) fn env_flag(name: &str) -> Option<bool> {
+    match env_var(name)?.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// The setting a `CHIRAL_DISABLE_*` or `CHIRAL_NO_*` flag turns off, when
+/// the flag is set
+pub(super) fn env_disable_flag(name: &str) -> Option<bool> {
+    env_flag(name).map(|disabled| !disabled)
+}
+
+pub(super) fn env_list(name: &str) -> Option<Vec<String>> {
+    env_var(name).map(|v| {
+        v.split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    })
+}
+
+impl ChiralConfig {
+    /// Build the configuration from environment variables, on top of the
+    /// configuration file when one was installed
+    pub fn from_env() -> Self {
+        FILE_BASE.get().cloned().unwrap_or_default().with_env()
+    }
+
+    /// Use `config` as the base of every later `from_env`. Only the first
+    /// call has an effect.
+    pub fn install_file_base(config: ChiralConfig) {
+        let _ = FILE_BASE.set(config);
+    }
+
+    /// Override values set in the environment. A flag set to a false value
+    /// turns its setting off again (and `CHIRAL_DISABLE_*` ones back on).
+    pub fn with_env(self) -> Self {
+        let Self {
+            bootstrap_manifest_url,
+            manifest_trusted_key,
+            storage,
+            uploads,
+            downloads,
+            swarm,
+            security,
+        } = self;
+        Self {
+            bootstrap_manifest_url: env_var("CHIRAL_BOOTSTRAP_MANIFEST_URL").or(bootstrap_manifest_url),
+            manifest_trusted_key: env_var("CHIRAL_MANIFEST_TRUSTED_KEY").or(manifest_trusted_key),
+            storage: StorageConfig {
+                encrypt_peer_store: env_flag("CHIRAL_ENCRYPT_PEER_STORE")
+                    .unwrap_or(storage.encrypt_peer_store),
+            },
+            uploads: UploadsConfig {
+                upload_slots: env_number("CHIRAL_UPLOAD_SLOTS").unwrap_or(uploads.upload_slots),
+                reserved_slots: env_number("CHIRAL_RESERVED_UPLOAD_SLOTS")
+                    .unwrap_or(uploads.reserved_slots),
+                queue_size: env_number("CHIRAL_UPLOAD_QUEUE").unwrap_or(uploads.queue_size),
+                trusted_peers: env_list("CHIRAL_TRUSTED_PEERS").unwrap_or(uploads.trusted_peers),
+                verify_interval_hours: env_number("CHIRAL_VERIFY_INTERVAL_HOURS")
+                    .unwrap_or(uploads.verify_interval_hours),
+                verify_rate_mib: env_number("CHIRAL_VERIFY_RATE_MIB")
+                    .unwrap_or(uploads.verify_rate_mib),
+            },
+            downloads: DownloadsConfig {
+                web_seeds: env_disable_flag("CHIRAL_DISABLE_WEB_SEEDS")
+                    .unwrap_or(downloads.web_seeds),
+                auto_resume: env_disable_flag("CHIRAL_DISABLE_AUTO_RESUME")
+                    .unwrap_or(downloads.auto_resume),
+                pipeline_depth: env_number("CHIRAL_PIPELINE_DEPTH")
+                    .unwrap_or(downloads.pipeline_depth),
+                adaptive_pipeline: env_disable_flag("CHIRAL_DISABLE_ADAPTIVE_PIPELINE")
+                    .unwrap_or(downloads.adaptive_pipeline),
+                max_in_flight_mib: env_number("CHIRAL_MAX_IN_FLIGHT_MIB")
+                    .unwrap_or(downloads.max_in_flight_mib),
+                encrypt_partials: env_flag("CHIRAL_ENCRYPT_PARTIALS")
+                    .unwrap_or(downloads.encrypt_partials),
+                stall_timeout_secs: env_number("CHIRAL_STALL_TIMEOUT_SECS")
+                    .unwrap_or(downloads.stall_timeout_secs),
+                max_stall_recoveries: env_number("CHIRAL_MAX_STALL_RECOVERIES")
+                    .unwrap_or(downloads.max_stall_recoveries),
+            },
+            swarm: SwarmConfig {
+                send_read_receipts: env_disable_flag("CHIRAL_DISABLE_READ_RECEIPTS")
+                    .unwrap_or(swarm.send_read_receipts),
+                compress_transfers: env_disable_flag("CHIRAL_DISABLE_TRANSFER_COMPRESSION")
+                    .unwrap_or(swarm.compress_transfers),
+                noise_prologue: env_var("CHIRAL_NOISE_PROLOGUE")
+                    .map(String::into_bytes)
+                    .unwrap_or(swarm.noise_prologue),
+                event_log_path: env_var("CHIRAL_EVENT_LOG_PATH")
+                    .map(PathBuf::from)
+                    .or(swarm.event_log_path),
+                dcutr: env_disable_flag("CHIRAL_DISABLE_DCUTR").unwrap_or(swarm.dcutr),
+                listen_addrs: env_list("CHIRAL_LISTEN_ADDRS").unwrap_or(swarm.listen_addrs),
+                kad_queries_per_second: env_number("CHIRAL_KAD_QUERIES_PER_SECOND")
+                    .unwrap_or(swarm.kad_queries_per_second),
+                kad_initial_burst: env_number("CHIRAL_KAD_INITIAL_BURST")
+                    .unwrap_or(swarm.kad_initial_burst),
+                bootstrap_min_confirmations: env_number("CHIRAL_BOOTSTRAP_MIN_CONFIRMATIONS")
+                    .unwrap_or(swarm.bootstrap_min_confirmations),
+                relay_consent: env_number("CHIRAL_RELAY_CONSENT").unwrap_or(swarm.relay_consent),
+                relay_allow_list: env_list("CHIRAL_RELAY_ALLOW_LIST").unwrap_or(swarm.relay_allow_list),
+                webhook_url: env_number("CHIRAL_WEBHOOK_URL").or(swarm.webhook_url),
+                webhook_secret: env_var("CHIRAL_WEBHOOK_SECRET").or(swarm.webhook_secret),
+                webhook_events: env_list("CHIRAL_WEBHOOK_EVENTS")
+                    .map(|kinds| kinds.iter().filter_map(|kind| kind.parse().ok()).collect())
+                    .unwrap_or(swarm.webhook_events),
+                enable_crypto_audit: env_flag("CHIRAL_ENABLE_CRYPTO_AUDIT")
+                    .unwrap_or(swarm.enable_crypto_audit),
+                infra_mode: env_flag("CHIRAL_INFRA_MODE").unwrap_or(swarm.infra_mode),
+                bootstrap_mode: env_number("CHIRAL_BOOTSTRAP_MODE").unwrap_or(swarm.bootstrap_mode),
+                memory_budget_mb: env_number("CHIRAL_MEMORY_BUDGET_MB").unwrap_or(swarm.memory_budget_mb),
+                mesh_n: env_number("CHIRAL_MESH_N").unwrap_or(swarm.mesh_n),
+                mesh_n_low: env_number("CHIRAL_MESH_N_LOW").unwrap_or(swarm.mesh_n_low),
+                mesh_n_high: env_number("CHIRAL_MESH_N_HIGH").unwrap_or(swarm.mesh_n_high),
+                bootstrap_prune_after_failures: env_number("CHIRAL_BOOTSTRAP_PRUNE_AFTER_FAILURES")
+                    .unwrap_or(swarm.bootstrap_prune_after_failures),
+            },
+            security,
+        }
+    }
+}
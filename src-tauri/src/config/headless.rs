@@ -0,0 +1,614 @@
+//! `chiral.toml` for headless nodes.
+//!
+//! Every setting has a built-in default. The file overrides the defaults,
+//! command-line flags override the file and environment variables override
+//! both. A `--profile` is applied on top of the file, before the other flags. The `[uploads]`, `[downloads]`, `[swarm]` and `[security]` sections are the ones
+//! of `ChiralConfig`; the other sections cover what the headless node hands
+//! to `DhtService` at startup.
+//!
+//! ```toml
+//! [network]
+//! port = 4001
+//! bootstrap = ["/ip4/203.0.113.10/tcp/4001/p2p/12D3KooW..."]
+//!
+//! [nat]
+//! relay_server = true
+//!
+//! [logging]
+//! level = "debug"
+//! ```
+
+use super::chiral::{env_disable_flag, env_flag, env_list, env_number, env_var, REDACTED};
+use super::{ChiralConfig, DownloadsConfig, SecurityConfig, StorageConfig, SwarmConfig, UploadsConfig};
+use crate::log_format::LogFormat;
+use crate::startup_error::StartupError;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+/// File name looked up in the data directory when `--config` is not given
+pub const CONFIG_FILE_NAME: &str = "chiral.toml";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeadlessConfig {
+    pub network: NetworkSection,
+    pub nat: NatSection,
+    pub dht: DhtSection,
+    pub bandwidth: BandwidthSection,
+    pub storage: StorageSection,
+    pub logging: LoggingSection,
+    pub metrics: MetricsSection,
+    pub health: HealthSection,
+    pub api: ApiSection,
+    pub uploads: UploadsConfig,
+    pub downloads: DownloadsConfig,
+    pub swarm: SwarmConfig,
+    pub security: SecurityConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkSection {
+    /// TCP port of the DHT (`CHIRAL_DHT_PORT`, `--port`); 0 lets the OS pick
+    pub port: u16,
+    /// Use a free port when `port` or a metrics, health or API port is busy,
+    /// instead of failing (`CHIRAL_PORT_FALLBACK`, `--port-fallback`)
+    pub port_fallback: bool,
+    /// Bootstrap multiaddrs (`CHIRAL_BOOTSTRAP_NODES`, `--bootstrap`)
+    pub bootstrap: Vec<String>,
+    /// With no `bootstrap` nodes, use the signed manifest or the built-in
+    /// list (`CHIRAL_NO_DEFAULT_BOOTSTRAP`, `--no-default-bootstrap`)
+    pub default_bootstrap: bool,
+    /// Multiaddrs listened on besides the TCP port (`CHIRAL_LISTEN_ADDRS`,
+    /// `--listen-addr`)
+    pub listen_addrs: Vec<String>,
+    /// Run as a bootstrap node (`CHIRAL_IS_BOOTSTRAP`, `--is-bootstrap`)
+    pub is_bootstrap: bool,
+    /// Seed of a stable peer id (`CHIRAL_SECRET`, `--secret`)
+    pub secret: Option<String>,
+    /// File holding the seed, created with a random one if missing; unused
+    /// when `secret` is set (`CHIRAL_IDENTITY_FILE`, `--identity-file`)
+    pub identity_file: Option<PathBuf>,
+    /// SOCKS5 proxy for outgoing connections (`CHIRAL_SOCKS5_PROXY`,
+    /// `--socks5-proxy`)
+    pub socks5_proxy: Option<String>,
+    /// URL of a signed bootstrap manifest (`CHIRAL_BOOTSTRAP_MANIFEST_URL`)
+    pub bootstrap_manifest_url: Option<String>,
+    /// Key the manifest must be signed with (`CHIRAL_MANIFEST_TRUSTED_KEY`)
+    pub manifest_trusted_key: Option<String>,
+    /// Seconds an orderly shutdown may take before the node exits anyway
+    /// (`CHIRAL_SHUTDOWN_GRACE_SECS`, `--shutdown-grace-secs`)
+    pub shutdown_grace_secs: u64,
+}
+
+impl Default for NetworkSection {
+    fn default() -> Self {
+        Self {
+            port: 4001,
+            port_fallback: false,
+            bootstrap: Vec::new(),
+            default_bootstrap: true,
+            listen_addrs: Vec::new(),
+            is_bootstrap: false,
+            secret: None,
+            identity_file: None,
+            socks5_proxy: None,
+            bootstrap_manifest_url: None,
+            manifest_trusted_key: None,
+            shutdown_grace_secs: 15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NatSection {
+    /// AutoNAT reachability probes (`CHIRAL_DISABLE_AUTONAT`, `--no-autonat`)
+    pub autonat: bool,
+    /// Seconds between AutoNAT probes (`CHIRAL_AUTONAT_PROBE_INTERVAL_SECS`,
+    /// `--autonat-probe-interval`)
+    pub autonat_probe_interval_secs: u64,
+    /// Extra AutoNAT servers (`CHIRAL_AUTONAT_SERVERS`, `--autonat-server`)
+    pub autonat_servers: Vec<String>,
+    /// Reserve circuits on relays when not publicly reachable
+    /// (`CHIRAL_DISABLE_AUTORELAY`, `--disable-autorelay`)
+    pub autorelay: bool,
+    /// Preferred relays (`CHIRAL_RELAYS`, `--relay`)
+    pub relays: Vec<String>,
+    /// Relay traffic for other peers (`CHIRAL_ENABLE_RELAY_SERVER`,
+    /// `--relay-server`)
+    pub relay_server: bool,
+    /// Hole punching (`CHIRAL_DISABLE_DCUTR`, `--no-dcutr`)
+    pub dcutr: bool,
+    /// Port mapping on the local gateway (`CHIRAL_DISABLE_UPNP`)
+    pub upnp: bool,
+}
+
+impl Default for NatSection {
+    fn default() -> Self {
+        Self {
+            autonat: true,
+            autonat_probe_interval_secs: 30,
+            autonat_servers: Vec::new(),
+            autorelay: true,
+            relays: Vec::new(),
+            relay_server: false,
+            dcutr: true,
+            upnp: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DhtSection {
+    /// Chunk size of published files in KiB (`CHIRAL_CHUNK_SIZE_KB`)
+    pub chunk_size_kb: usize,
+    /// Block cache size in MiB (`CHIRAL_CACHE_SIZE_MB`)
+    pub cache_size_mb: usize,
+}
+
+impl Default for DhtSection {
+    fn default() -> Self {
+        Self {
+            chunk_size_kb: 256,
+            cache_size_mb: 1024,
+        }
+    }
+}
+
+/// Transfer rate limits in KiB/s; 0 is unlimited
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BandwidthSection {
+    /// `CHIRAL_UPLOAD_LIMIT_KBPS`
+    pub upload_kbps: u64,
+    /// `CHIRAL_DOWNLOAD_LIMIT_KBPS`
+    pub download_kbps: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageSection {
+    /// Block store database; the DHT keeps blocks in memory without one
+    /// unless `--data-dir` is given (`CHIRAL_BLOCKSTORE_PATH`)
+    pub blockstore_path: Option<PathBuf>,
+    /// Geth data directory (`CHIRAL_GETH_DATA_DIR`, `--geth-data-dir`)
+    pub geth_data_dir: PathBuf,
+    /// Encrypt the peer address cache (`CHIRAL_ENCRYPT_PEER_STORE`)
+    pub encrypt_peer_store: bool,
+}
+
+impl Default for StorageSection {
+    fn default() -> Self {
+        Self {
+            blockstore_path: None,
+            geth_data_dir: PathBuf::from("./bin/geth-data"),
+            encrypt_peer_store: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingSection {
+    /// Level of the node's own log lines (`CHIRAL_LOG_LEVEL`, `--log-level`)
+    pub level: String,
+    /// `text` or `json` (`CHIRAL_LOG_FORMAT`, `--log-format`)
+    pub format: LogFormat,
+    /// Also write logs to `logs/` in the data directory (`CHIRAL_LOG_FILE`,
+    /// `--log-file`)
+    pub file: bool,
+    /// Size at which the log file is rotated (`CHIRAL_LOG_MAX_SIZE_MB`)
+    pub max_file_size_mb: u64,
+    /// Rotated log files kept (`CHIRAL_LOG_MAX_FILES`)
+    pub max_files: usize,
+}
+
+impl Default for LoggingSection {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+            file: false,
+            max_file_size_mb: 10,
+            max_files: crate::logger::DEFAULT_MAX_LOG_FILES,
+        }
+    }
+}
+
+/// Prometheus endpoint; off unless `addr` is set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsSection {
+    /// Address of the `/metrics` listener (`CHIRAL_METRICS_ADDR`,
+    /// `--metrics-addr`)
+    pub addr: Option<SocketAddr>,
+    /// Allow `addr` to be other than a loopback address
+    /// (`CHIRAL_METRICS_ALLOW_PUBLIC`, `--metrics-allow-public`)
+    pub allow_public: bool,
+}
+
+/// `/healthz` and `/readyz`; off unless `addr` is set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthSection {
+    /// Address of the health listener (`CHIRAL_HEALTH_ADDR`, `--health-addr`)
+    pub addr: Option<SocketAddr>,
+}
+
+/// Local control API; off unless `addr` is set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiSection {
+    /// Address of the control API listener (`CHIRAL_API_ADDR`, `--api-addr`)
+    pub addr: Option<SocketAddr>,
+    /// Allow `addr` to be other than a loopback address
+    /// (`CHIRAL_API_ALLOW_PUBLIC`, `--api-allow-public`)
+    pub allow_public: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigFileError {
+    #[error("failed to read {}: {source}", .path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("{}:{line}: invalid value for `{key}`: {message}", .path.display())]
+    Invalid {
+        path: PathBuf,
+        line: usize,
+        key: String,
+        message: String,
+    },
+}
+
+/// Bytes of randomness in a generated identity seed
+const IDENTITY_SEED_LEN: usize = 32;
+
+pub fn default_path() -> PathBuf {
+    crate::data_dirs::DataDirs::current().config_file()
+}
+
+/// Preset for a kind of node, applied over the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Profile {
+    /// An ordinary peer; the defaults
+    Client,
+    /// A bootstrap node that also relays for peers behind NAT
+    Bootstrap,
+    /// A publicly reachable peer that relays for others
+    Relay,
+}
+
+impl Profile {
+    pub fn apply(self, config: &mut HeadlessConfig) {
+        match self {
+            Profile::Client => {}
+            Profile::Bootstrap => {
+                config.network.is_bootstrap = true;
+                config.nat.relay_server = true;
+                config.nat.autorelay = false;
+            }
+            Profile::Relay => {
+                config.nat.relay_server = true;
+                config.nat.autorelay = false;
+            }
+        }
+    }
+}
+
+impl NetworkSection {
+    /// `secret`, or the seed in `identity_file`, which is created on first use
+    ///
+    /// A file that is empty or not text is `KeypairCorrupt`; one that cannot
+    /// be read or created is `DataDirUnavailable`.
+    pub fn identity_secret(&self) -> Result<Option<String>, StartupError> {
+        if self.secret.is_some() {
+            return Ok(self.secret.clone());
+        }
+        let Some(path) = &self.identity_file else {
+            return Ok(None);
+        };
+        let corrupt = |detail: String| StartupError::KeypairCorrupt {
+            path: path.clone(),
+            detail,
+        };
+        let unavailable = |path: &Path, e: std::io::Error| StartupError::DataDirUnavailable {
+            path: path.to_path_buf(),
+            detail: e.to_string(),
+        };
+        match std::fs::read_to_string(path) {
+            Ok(text) if !text.trim().is_empty() => return Ok(Some(text.trim().to_string())),
+            Ok(_) => return Err(corrupt("the file is empty".to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => return Err(corrupt(e.to_string())),
+            Err(e) => return Err(unavailable(path, e)),
+        }
+
+        let seed = hex::encode(rand::random::<[u8; IDENTITY_SEED_LEN]>());
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| unavailable(parent, e))?;
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path).map_err(|e| unavailable(path, e))?;
+        std::io::Write::write_all(&mut file, seed.as_bytes()).map_err(|e| unavailable(path, e))?;
+        Ok(Some(seed))
+    }
+}
+
+impl HeadlessConfig {
+    /// Read `path`. A missing file gives the defaults unless it is `required`.
+    pub fn load(path: &Path, required: bool) -> Result<Self, ConfigFileError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(path, &text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => Ok(Self::default()),
+            Err(source) => Err(ConfigFileError::Read {
+                path: path.to_path_buf(),
+                source,
+            }),
+        }
+    }
+
+    /// Parse `text`, read from `path`
+    pub fn parse(path: &Path, text: &str) -> Result<Self, ConfigFileError> {
+        toml::from_str(text).map_err(|e| {
+            let offset = e.span().map(|span| span.start).unwrap_or(0).min(text.len());
+            let (line, key) = locate(text, offset);
+            ConfigFileError::Invalid {
+                path: path.to_path_buf(),
+                line,
+                key,
+                message: e.message().to_string(),
+            }
+        })
+    }
+
+    /// Override values set in the environment. A flag set to a false value
+    /// turns its setting off again (and `CHIRAL_DISABLE_*` ones back on).
+    pub fn with_env(mut self) -> Self {
+        let network = &mut self.network;
+        network.port = env_number("CHIRAL_DHT_PORT").unwrap_or(network.port);
+        network.port_fallback = env_flag("CHIRAL_PORT_FALLBACK").unwrap_or(network.port_fallback);
+        if let Some(nodes) = env_list("CHIRAL_BOOTSTRAP_NODES") {
+            network.bootstrap = nodes;
+        }
+        network.default_bootstrap = env_disable_flag("CHIRAL_NO_DEFAULT_BOOTSTRAP")
+            .unwrap_or(network.default_bootstrap);
+        network.is_bootstrap = env_flag("CHIRAL_IS_BOOTSTRAP").unwrap_or(network.is_bootstrap);
+        network.secret = env_var("CHIRAL_SECRET").or(network.secret.take());
+        network.identity_file = env_var("CHIRAL_IDENTITY_FILE")
+            .map(PathBuf::from)
+            .or(network.identity_file.take());
+        network.socks5_proxy = env_var("CHIRAL_SOCKS5_PROXY").or(network.socks5_proxy.take());
+        network.shutdown_grace_secs =
+            env_number("CHIRAL_SHUTDOWN_GRACE_SECS").unwrap_or(network.shutdown_grace_secs);
+
+        let nat = &mut self.nat;
+        nat.autonat = env_disable_flag("CHIRAL_DISABLE_AUTONAT").unwrap_or(nat.autonat);
+        nat.autonat_probe_interval_secs = env_number("CHIRAL_AUTONAT_PROBE_INTERVAL_SECS")
+            .unwrap_or(nat.autonat_probe_interval_secs);
+        if let Some(servers) = env_list("CHIRAL_AUTONAT_SERVERS") {
+            nat.autonat_servers = servers;
+        }
+        nat.autorelay = env_disable_flag("CHIRAL_DISABLE_AUTORELAY").unwrap_or(nat.autorelay);
+        if let Some(relays) = env_list("CHIRAL_RELAYS") {
+            nat.relays = relays;
+        }
+        nat.relay_server = env_flag("CHIRAL_ENABLE_RELAY_SERVER").unwrap_or(nat.relay_server);
+        nat.upnp = env_disable_flag("CHIRAL_DISABLE_UPNP").unwrap_or(nat.upnp);
+
+        let dht = &mut self.dht;
+        dht.chunk_size_kb = env_number("CHIRAL_CHUNK_SIZE_KB").unwrap_or(dht.chunk_size_kb);
+        dht.cache_size_mb = env_number("CHIRAL_CACHE_SIZE_MB").unwrap_or(dht.cache_size_mb);
+
+        let bandwidth = &mut self.bandwidth;
+        bandwidth.upload_kbps = env_number("CHIRAL_UPLOAD_LIMIT_KBPS").unwrap_or(bandwidth.upload_kbps);
+        bandwidth.download_kbps =
+            env_number("CHIRAL_DOWNLOAD_LIMIT_KBPS").unwrap_or(bandwidth.download_kbps);
+
+        let storage = &mut self.storage;
+        storage.blockstore_path = env_var("CHIRAL_BLOCKSTORE_PATH")
+            .map(PathBuf::from)
+            .or(storage.blockstore_path.take());
+        if let Some(dir) = env_var("CHIRAL_GETH_DATA_DIR") {
+            storage.geth_data_dir = PathBuf::from(dir);
+        }
+
+        self.logging.level = env_var("CHIRAL_LOG_LEVEL").unwrap_or(self.logging.level);
+        self.logging.format = env_number("CHIRAL_LOG_FORMAT").unwrap_or(self.logging.format);
+        self.logging.file = env_flag("CHIRAL_LOG_FILE").unwrap_or(self.logging.file);
+        self.logging.max_file_size_mb =
+            env_number("CHIRAL_LOG_MAX_SIZE_MB").unwrap_or(self.logging.max_file_size_mb);
+        self.logging.max_files = env_number("CHIRAL_LOG_MAX_FILES").unwrap_or(self.logging.max_files);
+        self.metrics.addr = env_number("CHIRAL_METRICS_ADDR").or(self.metrics.addr);
+        self.metrics.allow_public = env_flag("CHIRAL_METRICS_ALLOW_PUBLIC")
+            .unwrap_or(self.metrics.allow_public);
+        self.health.addr = env_number("CHIRAL_HEALTH_ADDR").or(self.health.addr);
+        self.api.addr = env_number("CHIRAL_API_ADDR").or(self.api.addr);
+        self.api.allow_public = env_flag("CHIRAL_API_ALLOW_PUBLIC")
+            .unwrap_or(self.api.allow_public);
+
+        // The shared sections, with the keys this file keeps elsewhere
+        let chiral = self.chiral().with_env();
+        self.network.bootstrap_manifest_url = chiral.bootstrap_manifest_url;
+        self.network.manifest_trusted_key = chiral.manifest_trusted_key;
+        self.storage.encrypt_peer_store = chiral.storage.encrypt_peer_store;
+        self.nat.dcutr = chiral.swarm.dcutr;
+        self.network.listen_addrs = chiral.swarm.listen_addrs.clone();
+        self.uploads = chiral.uploads;
+        self.downloads = chiral.downloads;
+        self.swarm = chiral.swarm;
+        self.security = chiral.security;
+        self
+    }
+
+    /// The `ChiralConfig` part of the file
+    pub fn chiral(&self) -> ChiralConfig {
+        ChiralConfig {
+            bootstrap_manifest_url: self.network.bootstrap_manifest_url.clone(),
+            manifest_trusted_key: self.network.manifest_trusted_key.clone(),
+            storage: StorageConfig {
+                encrypt_peer_store: self.storage.encrypt_peer_store,
+            },
+            uploads: self.uploads.clone(),
+            downloads: self.downloads.clone(),
+            swarm: SwarmConfig {
+                dcutr: self.nat.dcutr,
+                listen_addrs: self.network.listen_addrs.clone(),
+                ..self.swarm.clone()
+            },
+            security: self.security.clone(),
+        }
+    }
+
+    /// Copy that is safe to log
+    pub fn redacted(&self) -> Self {
+        let mut copy = self.clone();
+        if copy.network.secret.is_some() {
+            copy.network.secret = Some(REDACTED.to_string());
+        }
+        if let Some(proxy) = copy.network.socks5_proxy.as_mut() {
+            // Credentials in `user:pass@host:port`
+            if let Some((_, host)) = proxy.rsplit_once('@') {
+                *proxy = format!("{}@{}", REDACTED, host);
+            }
+        }
+        copy.swarm = copy.swarm.redacted();
+        copy
+    }
+
+    /// The configuration as TOML, secrets redacted
+    pub fn to_redacted_toml(&self) -> String {
+        toml::to_string_pretty(&self.redacted()).unwrap_or_else(|e| format!("<unprintable: {}>", e))
+    }
+}
+
+/// Line number and dotted key at byte `offset` of `text`
+fn locate(text: &str, offset: usize) -> (usize, String) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let current = text[line_start..].lines().next().unwrap_or("").trim();
+
+    let header = |l: &str| {
+        let l = l.trim();
+        (l.starts_with('[') && l.ends_with(']'))
+            .then(|| l.trim_matches(|c| c == '[' || c == ']').trim().to_string())
+    };
+    if let Some(table) = header(current) {
+        return (line, table);
+    }
+    let table = text[..line_start].lines().rev().find_map(header);
+    let key = current
+        .split_once('=')
+        .map(|(key, _)| key.trim().trim_matches('"').to_string())
+        .unwrap_or_default();
+    let key = match (table, key.is_empty()) {
+        (Some(table), false) => format!("{}.{}", table, key),
+        (Some(table), true) => table,
+        (None, _) => key,
+    };
+    (line, key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_values_and_defaults() {
+        let config = HeadlessConfig::parse(
+            Path::new("chiral.toml"),
+            r#"
+[network]
+port = 4100
+secret = "hunter2"
+
+[nat]
+relay_server = true
+dcutr = false
+
+[downloads]
+pipeline_depth = 4
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.network.port, 4100);
+        assert!(config.nat.relay_server);
+        assert!(config.nat.autonat);
+        assert_eq!(config.downloads.pipeline_depth, 4);
+        assert!(!config.chiral().swarm.dcutr);
+
+        let logged = config.to_redacted_toml();
+        assert!(!logged.contains("hunter2"));
+        assert!(logged.contains(REDACTED));
+    }
+
+    #[test]
+    fn test_env_turns_file_settings_off_and_on() {
+        let text = "[network]\nis_bootstrap = true\n\n[nat]\nautonat = false\n";
+        let config = HeadlessConfig::parse(Path::new("chiral.toml"), text).unwrap();
+        std::env::set_var("CHIRAL_IS_BOOTSTRAP", "false");
+        std::env::set_var("CHIRAL_DISABLE_AUTONAT", "0");
+        let config = config.with_env();
+        std::env::remove_var("CHIRAL_IS_BOOTSTRAP");
+        std::env::remove_var("CHIRAL_DISABLE_AUTONAT");
+        assert!(!config.network.is_bootstrap);
+        assert!(config.nat.autonat);
+        // Unset flags leave the value alone
+        assert!(!config.with_env().network.is_bootstrap);
+    }
+
+    #[test]
+    fn test_identity_file_is_created_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut network = NetworkSection {
+            identity_file: Some(dir.path().join("keys").join("identity")),
+            ..Default::default()
+        };
+        let first = network.identity_secret().unwrap().unwrap();
+        assert_eq!(first.len(), IDENTITY_SEED_LEN * 2);
+        assert_eq!(network.identity_secret().unwrap(), Some(first));
+
+        network.secret = Some("explicit".to_string());
+        assert_eq!(network.identity_secret().unwrap().as_deref(), Some("explicit"));
+
+        // A damaged file is reported as such, not replaced
+        network.secret = None;
+        let path = network.identity_file.clone().unwrap();
+        std::fs::write(&path, [0xff, 0xfe, 0x00]).unwrap();
+        assert!(matches!(
+            network.identity_secret(),
+            Err(StartupError::KeypairCorrupt { path: p, .. }) if p == path
+        ));
+        std::fs::write(&path, "\n").unwrap();
+        assert!(matches!(
+            network.identity_secret(),
+            Err(StartupError::KeypairCorrupt { .. })
+        ));
+    }
+
+    #[test]
+    fn test_errors_name_key_and_line() {
+        let text = "[network]\nport = 4001\n\n[nat]\nrelay_server = \"yes\"\n";
+        let err = HeadlessConfig::parse(Path::new("chiral.toml"), text).unwrap_err();
+        match &err {
+            ConfigFileError::Invalid { line, key, .. } => {
+                assert_eq!(*line, 5);
+                assert_eq!(key, "nat.relay_server");
+            }
+            other => panic!("unexpected error {other:?}"),
+        }
+        assert!(err.to_string().starts_with("chiral.toml:5: invalid value for `nat.relay_server`"));
+
+        let err = HeadlessConfig::parse(Path::new("chiral.toml"), "[nat]\nrelay_sever = true\n")
+            .unwrap_err();
+        assert!(matches!(err, ConfigFileError::Invalid { line: 2, ref key, .. } if key == "nat.relay_sever"));
+    }
+}
@@ -9,6 +9,9 @@ use std::io::Read;
 use std::path::PathBuf;
 
 pub mod bittorrent;
+pub mod chiral;
+pub mod headless;
+pub mod reload;
 
 pub use bittorrent::{
     BitTorrentConfig, BitTorrentConfigManager, NetworkConfig, RateLimitConfig,
@@ -18,6 +21,8 @@ pub use bittorrent::{
     get_bittorrent_config, update_bittorrent_config, reset_bittorrent_config,
     update_network_config, update_rate_limits,
 };
+pub use chiral::{ChiralConfig, DownloadsConfig, SecurityConfig, StorageConfig, SwarmConfig, UploadsConfig};
+pub use headless::{ConfigFileError, HeadlessConfig};
 
 // ============================================================================
 // Chain ID Configuration (from genesis.json)
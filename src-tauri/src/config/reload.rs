@@ -0,0 +1,205 @@
+//! Applying a changed `chiral.toml` to a running headless node.
+//!
+//! On SIGHUP, or `POST /api/v1/reload`, the node loads its configuration
+//! again the way it did at startup. A configuration that fails `validate`
+//! is rejected as a whole and the node keeps every old value. Otherwise
+//! `ConfigDiff::between` names the keys that changed, dotted as in the file:
+//! those in `LIVE_KEYS` are applied at once, the others (listen addresses,
+//! identity, Noise prologue and the rest) are logged and wait for a restart.
+
+use super::{ChiralConfig, HeadlessConfig};
+use crate::relay_consent::RelayConsentPolicy;
+use libp2p::{Multiaddr, PeerId};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use tokio::sync::{mpsc, oneshot};
+
+/// Keys a running node applies without a restart
+pub const LIVE_KEYS: &[&str] = &[
+    "logging.level",
+    "network.bootstrap",
+    "security.filter_rules",
+    "swarm.relay_consent",
+    "swarm.relay_allow_list",
+];
+
+/// Keys that changed between two configurations
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigDiff {
+    /// Applied to the running node
+    pub applied: Vec<String>,
+    /// Kept at the old value until the node restarts
+    pub needs_restart: Vec<String>,
+}
+
+impl ConfigDiff {
+    pub fn between(old: &HeadlessConfig, new: &HeadlessConfig) -> Self {
+        let (old, new) = (flatten(old), flatten(new));
+        let mut diff = Self::default();
+        for key in old.keys().chain(new.keys().filter(|key| !old.contains_key(*key))) {
+            if old.get(key) == new.get(key) {
+                continue;
+            }
+            if LIVE_KEYS.contains(&key.as_str()) {
+                diff.applied.push(key.clone());
+            } else {
+                diff.needs_restart.push(key.clone());
+            }
+        }
+        diff.applied.sort();
+        diff.needs_restart.sort();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.needs_restart.is_empty()
+    }
+
+    pub fn changed(&self, key: &str) -> bool {
+        self.applied.iter().any(|applied| applied == key)
+    }
+
+    /// `current` with the live keys of `new`; the other keys keep their
+    /// values, matching what the running node does
+    pub fn merge(&self, current: &HeadlessConfig, new: &HeadlessConfig) -> HeadlessConfig {
+        let mut merged = current.clone();
+        merged.logging.level = new.logging.level.clone();
+        merged.network.bootstrap = new.network.bootstrap.clone();
+        merged.swarm.relay_consent = new.swarm.relay_consent;
+        merged.swarm.relay_allow_list = new.swarm.relay_allow_list.clone();
+        merged.security.filter_rules = new.security.filter_rules.clone();
+        merged
+    }
+}
+
+/// Every value as a dotted key; lists are compared whole
+fn flatten(config: &HeadlessConfig) -> BTreeMap<String, Value> {
+    fn walk(prefix: &str, value: Value, out: &mut BTreeMap<String, Value>) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map {
+                    let key = if prefix.is_empty() { key } else { format!("{}.{}", prefix, key) };
+                    walk(&key, value, out);
+                }
+            }
+            value => {
+                out.insert(prefix.to_string(), value);
+            }
+        }
+    }
+    let mut out = BTreeMap::new();
+    if let Ok(value) = serde_json::to_value(config) {
+        walk("", value, &mut out);
+    }
+    out
+}
+
+/// Problems a configuration file can parse with but a node cannot use
+pub fn validate(config: &HeadlessConfig) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+    let addr_lists = [
+        ("network.bootstrap", &config.network.bootstrap),
+        ("network.listen_addrs", &config.network.listen_addrs),
+        ("nat.relays", &config.nat.relays),
+        ("nat.autonat_servers", &config.nat.autonat_servers),
+    ];
+    for (key, addrs) in addr_lists {
+        for addr in addrs.iter().filter(|addr| addr.parse::<Multiaddr>().is_err()) {
+            errors.push(format!("{}: '{}' is not a multiaddr", key, addr));
+        }
+    }
+    if config.logging.level.parse::<tracing_subscriber::filter::LevelFilter>().is_err() {
+        errors.push(format!("logging.level: '{}' is not a log level", config.logging.level));
+    }
+    errors.extend(validate_swarm(&config.chiral()));
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// The part of `validate` the swarm checks for itself, so the desktop app's
+/// node refuses the same settings as a headless one
+pub fn validate_swarm(config: &ChiralConfig) -> Vec<String> {
+    let mut errors = Vec::new();
+    for peer in config.swarm.relay_allow_list.iter().filter(|peer| peer.parse::<PeerId>().is_err()) {
+        errors.push(format!("swarm.relay_allow_list: '{}' is not a peer id", peer));
+    }
+    if config.swarm.relay_consent == RelayConsentPolicy::ExplicitAllowList
+        && config.swarm.relay_allow_list.is_empty()
+    {
+        errors.push("swarm.relay_consent: explicit_allow_list with an empty allow list".to_string());
+    }
+    errors.extend(crate::security::validate_rules(&config.security.filter_rules));
+    errors
+}
+
+/// Answered with the keys that changed, or why the new file was rejected
+pub type ReloadRequest = oneshot::Sender<Result<ConfigDiff, String>>;
+
+/// Asks the node's reloader task to read the configuration again
+#[derive(Clone)]
+pub struct ReloadHandle(mpsc::Sender<ReloadRequest>);
+
+impl ReloadHandle {
+    pub fn channel() -> (Self, mpsc::Receiver<ReloadRequest>) {
+        let (tx, rx) = mpsc::channel(4);
+        (Self(tx), rx)
+    }
+
+    pub async fn reload(&self) -> Result<ConfigDiff, String> {
+        let (tx, rx) = oneshot::channel();
+        self.0
+            .send(tx)
+            .await
+            .map_err(|_| "configuration reloader is not running".to_string())?;
+        rx.await
+            .map_err(|_| "configuration reloader stopped".to_string())?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_splits_live_and_restart_keys() {
+        let old = HeadlessConfig::default();
+        assert!(ConfigDiff::between(&old, &old.clone()).is_empty());
+
+        let mut new = old.clone();
+        new.bandwidth.upload_kbps = 512;
+        new.logging.level = "debug".to_string();
+        new.network.bootstrap = vec!["/ip4/10.0.0.1/tcp/4001".to_string()];
+        new.network.listen_addrs = vec!["/ip4/0.0.0.0/udp/4001/quic-v1".to_string()];
+        new.network.secret = Some("new identity".to_string());
+        new.swarm.noise_prologue = b"private-net".to_vec();
+        let diff = ConfigDiff::between(&old, &new);
+        assert_eq!(diff.applied, ["logging.level", "network.bootstrap"]);
+        assert_eq!(
+            diff.needs_restart,
+            ["bandwidth.upload_kbps", "network.listen_addrs", "network.secret", "swarm.noise_prologue"]
+        );
+        assert!(diff.changed("logging.level"));
+
+        let merged = diff.merge(&old, &new);
+        assert_eq!(merged.bandwidth.upload_kbps, 0);
+        assert_eq!(merged.logging.level, "debug");
+        assert!(merged.network.listen_addrs.is_empty());
+        assert_eq!(merged.network.secret, None);
+        assert!(ConfigDiff::between(&merged, &new).applied.is_empty());
+
+        assert!(validate(&new).is_ok());
+        let mut invalid = new.clone();
+        invalid.network.bootstrap.push("not an address".to_string());
+        invalid.logging.level = "loud".to_string();
+        invalid.swarm.relay_consent = RelayConsentPolicy::ExplicitAllowList;
+        let errors = validate(&invalid).unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].starts_with("network.bootstrap"));
+        assert_eq!(validate_swarm(&invalid.chiral()).len(), 1);
+    }
+}
@@ -0,0 +1,382 @@
+//! Local REST API for operating a headless node.
+//!
+//! The desktop app drives its node through Tauri commands; a headless node
+//! offers the same operations over HTTP when started with `--api-addr`.
+//! Handlers only parse the request and call `node_commands`, which the Tauri
+//! commands call too. Every route lives under `/api/v1` and needs
+//! `Authorization: Bearer <token>`, where the token is read from `api.token`
+//! in the data directory, generated on first start.
+//!
+//! | Method | Path                           | Operation                       |
+//! | ------ | ------------------------------ | ------------------------------- |
+//! | GET    | `/node`                        | peer id and multiaddrs          |
+//! | GET    | `/peers`                       | connected peers                 |
+//! | POST   | `/peers`                       | dial `{"address": ...}`         |
+//! | GET    | `/bootstrap`                   | bootstrap connections           |
+//! | GET    | `/health`                      | the DHT metrics snapshot        |
+//! | GET    | `/stats`                       | aggregate network statistics    |
+//! | GET    | `/nat`                         | reachability and port mappings  |
+//! | POST   | `/files`                       | publish a local file            |
+//! | DELETE | `/files/{hash}`                | stop publishing                 |
+//! | GET    | `/downloads`                   | all restartable downloads       |
+//! | POST   | `/downloads`                   | start one                       |
+//! | GET    | `/downloads/{id}`              | its status                      |
+//! | POST   | `/downloads/{id}/pause`        | pause it                        |
+//! | POST   | `/downloads/{id}/resume`       | resume it                       |
+//! | POST   | `/channels/{channel}`          | join a message channel          |
+//! | GET    | `/channels/{channel}/messages` | messages received, newest first |
+//! | POST   | `/channels/{channel}/messages` | post `{"payload": [...]}`       |
+//! | GET    | `/settings`                    | effective settings, redacted    |
+//! | POST   | `/reload`                      | re-read the configuration file  |
+//!
+//! Failed operations answer 400 with `{"error": "..."}`.
+//! `rpc` serves the same `ControlApi` as JSON-RPC on a Unix socket.
+
+use crate::config::reload::ReloadHandle;
+use crate::dht::DhtService;
+use crate::download_restart::{DownloadRestartService, StartDownloadRequest};
+use crate::file_transfer::FileTransferService;
+use crate::messaging::{MessageId, MessageStore};
+use crate::node_commands::{self, PublishFileRequest};
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::Path as FsPath;
+use std::sync::Arc;
+
+/// File in the data directory holding the bearer token
+pub const TOKEN_FILE_NAME: &str = "api.token";
+
+/// Random bytes in a generated token
+const TOKEN_LEN: usize = 32;
+
+/// Messages listed when the request names no `limit`
+const DEFAULT_MESSAGE_LIMIT: u32 = 50;
+
+/// What the API operates on
+pub struct ControlApi {
+    pub dht: Arc<DhtService>,
+    /// `None` on infra nodes, which download nothing
+    pub downloads: Option<Arc<DownloadRestartService>>,
+    pub file_transfer: Option<Arc<FileTransferService>>,
+    /// Channel messages the node received; `None` on infra nodes, which
+    /// carry no gossip
+    pub messages: Option<Arc<MessageStore>>,
+    /// Served as is by `GET /settings`; secrets must already be redacted
+    pub settings: serde_json::Value,
+    pub token: String,
+    /// Reloads the configuration; `None` where there is no file to reload
+    pub reload: Option<ReloadHandle>,
+}
+
+impl ControlApi {
+    pub fn downloads(&self) -> Result<&DownloadRestartService, String> {
+        self.downloads
+            .as_deref()
+            .ok_or_else(|| "Infra nodes download nothing".to_string())
+    }
+
+    pub fn messages(&self) -> Result<&MessageStore, String> {
+        self.messages
+            .as_deref()
+            .ok_or_else(|| "Infra nodes keep no messages".to_string())
+    }
+
+    /// Post `payload` on `channel` and return the message id
+    pub async fn post_message(
+        &self,
+        channel: String,
+        payload: Vec<u8>,
+        reply_to: Option<MessageId>,
+    ) -> Result<MessageId, String> {
+        let message = node_commands::channel_message(&self.dht, channel, payload, reply_to).await;
+        // Gossipsub does not deliver our own messages back, so record it here
+        if let Some(store) = &self.messages {
+            store.store_message(&message).map_err(|e| e.to_string())?;
+        }
+        node_commands::publish_message(&self.dht, message).await
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ApiError {
+    error: String,
+}
+
+fn failed(error: String) -> Response {
+    (StatusCode::BAD_REQUEST, Json(ApiError { error })).into_response()
+}
+
+fn respond<T: Serialize>(result: Result<T, String>) -> Response {
+    match result {
+        Ok(value) => Json(value).into_response(),
+        Err(error) => failed(error),
+    }
+}
+
+/// The token in `dir`, created on first use and readable only by the owner
+pub fn load_or_create_token(dir: &FsPath) -> Result<String, String> {
+    let path = dir.join(TOKEN_FILE_NAME);
+    match std::fs::read_to_string(&path) {
+        Ok(text) if !text.trim().is_empty() => return Ok(text.trim().to_string()),
+        Ok(_) => return Err(format!("API token file {} is empty", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(format!("Failed to read API token {}: {}", path.display(), e)),
+    }
+
+    let token = hex::encode(rand::random::<[u8; TOKEN_LEN]>());
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(&path)
+        .map_err(|e| format!("Failed to create API token {}: {}", path.display(), e))?;
+    std::io::Write::write_all(&mut file, token.as_bytes())
+        .map_err(|e| format!("Failed to write API token {}: {}", path.display(), e))?;
+    Ok(token)
+}
+
+/// Compare without returning early, so timing does not reveal the prefix
+fn token_matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn require_token(State(api): State<Arc<ControlApi>>, request: Request, next: Next) -> Response {
+    let given = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match given {
+        Some(token) if token_matches(&api.token, token.trim()) => next.run(request).await,
+        _ => (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            Json(ApiError {
+                error: "missing or wrong bearer token".to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ConnectRequest {
+    address: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PostMessageRequest {
+    payload: Vec<u8>,
+    #[serde(default)]
+    reply_to: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesQuery {
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+async fn node(State(api): State<Arc<ControlApi>>) -> Response {
+    Json(node_commands::node_info(&api.dht).await).into_response()
+}
+
+async fn peers(State(api): State<Arc<ControlApi>>) -> Response {
+    Json(node_commands::connected_peers(&api.dht).await).into_response()
+}
+
+async fn connect(State(api): State<Arc<ControlApi>>, Json(request): Json<ConnectRequest>) -> Response {
+    respond(node_commands::connect_peer(&api.dht, request.address).await)
+}
+
+async fn bootstrap(State(api): State<Arc<ControlApi>>) -> Response {
+    Json(node_commands::bootstrap_status(&api.dht).await).into_response()
+}
+
+async fn health(State(api): State<Arc<ControlApi>>) -> Response {
+    Json(node_commands::health(&api.dht).await).into_response()
+}
+
+async fn stats(State(api): State<Arc<ControlApi>>) -> Response {
+    Json(node_commands::network_stats(&api.dht).await).into_response()
+}
+
+async fn nat(State(api): State<Arc<ControlApi>>) -> Response {
+    Json(node_commands::nat_status(&api.dht).await).into_response()
+}
+
+async fn publish(State(api): State<Arc<ControlApi>>, Json(request): Json<PublishFileRequest>) -> Response {
+    respond(node_commands::publish_file(&api.dht, api.file_transfer.as_deref(), request, None).await)
+}
+
+async fn unpublish(State(api): State<Arc<ControlApi>>, Path(file_hash): Path<String>) -> Response {
+    respond(node_commands::stop_publishing(&api.dht, file_hash).await)
+}
+
+async fn downloads(State(api): State<Arc<ControlApi>>) -> Response {
+    let statuses = match &api.downloads {
+        Some(downloads) => node_commands::list_downloads(downloads).await,
+        None => Vec::new(),
+    };
+    Json(statuses).into_response()
+}
+
+async fn start_download(
+    State(api): State<Arc<ControlApi>>,
+    Json(request): Json<StartDownloadRequest>,
+) -> Response {
+    match api.downloads() {
+        Ok(downloads) => respond(node_commands::start_download(downloads, request).await),
+        Err(e) => failed(e),
+    }
+}
+
+async fn download_status(State(api): State<Arc<ControlApi>>, Path(id): Path<String>) -> Response {
+    match api.downloads() {
+        Ok(downloads) => respond(node_commands::download_status(downloads, &id).await),
+        Err(e) => failed(e),
+    }
+}
+
+async fn pause_download(State(api): State<Arc<ControlApi>>, Path(id): Path<String>) -> Response {
+    match api.downloads() {
+        Ok(downloads) => respond(node_commands::pause_download(downloads, &id).await),
+        Err(e) => failed(e),
+    }
+}
+
+async fn resume_download(State(api): State<Arc<ControlApi>>, Path(id): Path<String>) -> Response {
+    match api.downloads() {
+        Ok(downloads) => respond(node_commands::resume_download(downloads, &id).await),
+        Err(e) => failed(e),
+    }
+}
+
+async fn join_channel(State(api): State<Arc<ControlApi>>, Path(channel): Path<String>) -> Response {
+    respond(api.dht.join_channel(&channel).await)
+}
+
+async fn channel_messages(
+    State(api): State<Arc<ControlApi>>,
+    Path(channel): Path<String>,
+    Query(query): Query<MessagesQuery>,
+) -> Response {
+    let limit = query.limit.unwrap_or(DEFAULT_MESSAGE_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    respond(
+        api.messages()
+            .map(|store| store.list_messages(&channel, limit, offset)),
+    )
+}
+
+async fn post_message(
+    State(api): State<Arc<ControlApi>>,
+    Path(channel): Path<String>,
+    Json(request): Json<PostMessageRequest>,
+) -> Response {
+    let reply_to = match request.reply_to.map(|id| id.parse::<MessageId>()).transpose() {
+        Ok(reply_to) => reply_to,
+        Err(e) => return failed(e.to_string()),
+    };
+    respond(api.post_message(channel, request.payload, reply_to).await)
+}
+
+async fn settings(State(api): State<Arc<ControlApi>>) -> Response {
+    Json(api.settings.clone()).into_response()
+}
+
+async fn reload(State(api): State<Arc<ControlApi>>) -> Response {
+    match &api.reload {
+        Some(handle) => respond(handle.reload().await),
+        None => failed("this node cannot reload its configuration".to_string()),
+    }
+}
+
+pub fn router(api: Arc<ControlApi>) -> Router {
+    let routes = Router::new()
+        .route("/node", get(node))
+        .route("/peers", get(peers).post(connect))
+        .route("/bootstrap", get(bootstrap))
+        .route("/health", get(health))
+        .route("/stats", get(stats))
+        .route("/nat", get(nat))
+        .route("/files", post(publish))
+        .route("/files/:hash", delete(unpublish))
+        .route("/downloads", get(downloads).post(start_download))
+        .route("/downloads/:id", get(download_status))
+        .route("/downloads/:id/pause", post(pause_download))
+        .route("/downloads/:id/resume", post(resume_download))
+        .route("/channels/:channel", post(join_channel))
+        .route("/channels/:channel/messages", get(channel_messages).post(post_message))
+        .route("/settings", get(settings))
+        .route("/reload", post(reload))
+        .route_layer(middleware::from_fn_with_state(api.clone(), require_token))
+        .with_state(api);
+    Router::new().nest("/api/v1", routes)
+}
+
+/// Refuse addresses other than loopback unless `allow_public`
+pub fn check_bind_addr(addr: SocketAddr, allow_public: bool) -> Result<(), String> {
+    if addr.ip().is_loopback() || allow_public {
+        Ok(())
+    } else {
+        Err(format!(
+            "refusing to serve the control API on non-loopback address {}; allow it with --api-allow-public",
+            addr
+        ))
+    }
+}
+
+/// Serve the API on `addr` for the life of the process
+///
+/// Returns the bound address (useful if port 0 was used)
+pub async fn start_server(api: Arc<ControlApi>, addr: SocketAddr, allow_public: bool) -> Result<SocketAddr, String> {
+    check_bind_addr(addr, allow_public)?;
+    let app = router(api);
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| format!("Failed to bind control API {}: {}", addr, e))?;
+    let bound_addr = listener.local_addr().map_err(|e| e.to_string())?;
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!("Control API error: {}", e);
+        }
+    });
+    Ok(bound_addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_is_created_once_and_compared() {
+        let dir = tempfile::tempdir().unwrap();
+        let token = load_or_create_token(&dir.path().join("data")).unwrap();
+        assert_eq!(token.len(), TOKEN_LEN * 2);
+        assert_eq!(load_or_create_token(&dir.path().join("data")).unwrap(), token);
+
+        assert!(token_matches(&token, &token));
+        assert!(!token_matches(&token, &token[1..]));
+        assert!(!token_matches(&token, &"0".repeat(token.len())));
+
+        assert!(check_bind_addr("127.0.0.1:5001".parse().unwrap(), false).is_ok());
+        assert!(check_bind_addr("0.0.0.0:5001".parse().unwrap(), false).is_err());
+    }
+}
@@ -244,8 +244,13 @@ impl<F: JwksFetcher> ResumeTokenVerifier<F> {
                 .map_err(|_| ResumeTokenError::Signature)?,
         );
         let key = self.cache.get_key(&header.kid).await?;
-        key.verify_strict(signing_input.as_bytes(), &signature)
-            .map_err(|_| ResumeTokenError::Signature)?;
+        let verified = key.verify_strict(signing_input.as_bytes(), &signature);
+        crate::crypto::audit::record(
+            crate::crypto::CryptoOperation::verification(verified.is_ok()),
+            None,
+            format!("resume token for {} (key {})", expected_file_id, header.kid),
+        );
+        verified.map_err(|_| ResumeTokenError::Signature)?;
         let claims: ResumeTokenClaims = decode_json_part(parts[1])?;
         if claims.kid != header.kid {
             return Err(ResumeTokenError::Invalid("kid mismatch"));
@@ -0,0 +1,228 @@
+//! Append-only log of key generation, session setup and signature checks,
+//! for security review.
+//!
+//! Off by default: every new connection adds a row. With
+//! `SwarmConfig::enable_crypto_audit` the DHT service installs a log at
+//! `crypto_audit.db` in the data directory, and `record` calls made anywhere
+//! in the node are written to it from a background thread. Triggers on the
+//! table refuse updates and deletes.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Log installed for this process, if auditing is on
+static AUDIT_LOG: OnceLock<AuditLog> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CryptoOperation {
+    KeyGenerated,
+    SessionEstablished,
+    SignatureVerified,
+    SignatureVerificationFailed,
+    KeyRotated,
+}
+
+impl CryptoOperation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::KeyGenerated => "KeyGenerated",
+            Self::SessionEstablished => "SessionEstablished",
+            Self::SignatureVerified => "SignatureVerified",
+            Self::SignatureVerificationFailed => "SignatureVerificationFailed",
+            Self::KeyRotated => "KeyRotated",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        [
+            Self::KeyGenerated,
+            Self::SessionEstablished,
+            Self::SignatureVerified,
+            Self::SignatureVerificationFailed,
+            Self::KeyRotated,
+        ]
+        .into_iter()
+        .find(|op| op.as_str() == s)
+    }
+
+    /// `SignatureVerified` or `SignatureVerificationFailed`
+    pub fn verification(ok: bool) -> Self {
+        if ok {
+            Self::SignatureVerified
+        } else {
+            Self::SignatureVerificationFailed
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    /// Unix milliseconds
+    pub timestamp: u64,
+    pub operation: CryptoOperation,
+    /// Remote peer the operation involved
+    pub peer_id: Option<String>,
+    pub details: String,
+}
+
+impl AuditEntry {
+    pub fn now(operation: CryptoOperation, peer_id: Option<String>, details: impl Into<String>) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            operation,
+            peer_id,
+            details: details.into(),
+        }
+    }
+}
+
+pub struct AuditLog {
+    conn: Arc<Mutex<Connection>>,
+    queue: Mutex<Option<mpsc::Sender<AuditEntry>>>,
+}
+
+fn lock(conn: &Mutex<Connection>) -> std::sync::MutexGuard<'_, Connection> {
+    conn.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn insert(conn: &Connection, entry: &AuditEntry) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO crypto_audit (timestamp, operation, peer_id, details) VALUES (?1, ?2, ?3, ?4)",
+        params![entry.timestamp as i64, entry.operation.as_str(), entry.peer_id, entry.details],
+    )?;
+    Ok(())
+}
+
+impl AuditLog {
+    /// `crypto_audit.db` in the application data directory
+    pub fn default_path() -> PathBuf {
+        crate::data_dirs::DataDirs::current().crypto_audit()
+    }
+
+    /// Open (or create) the log at `db_path`
+    pub fn open(db_path: &Path) -> rusqlite::Result<Self> {
+        if let Some(parent) = db_path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                warn!("Failed to create crypto audit directory {:?}: {}", parent, e);
+            }
+        }
+        let conn = Connection::open(db_path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS crypto_audit (
+                id        INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp INTEGER NOT NULL,
+                operation TEXT NOT NULL,
+                peer_id   TEXT,
+                details   TEXT NOT NULL
+            );
+            CREATE TRIGGER IF NOT EXISTS crypto_audit_no_update BEFORE UPDATE ON crypto_audit
+            BEGIN SELECT RAISE(ABORT, 'crypto_audit is append-only'); END;
+            CREATE TRIGGER IF NOT EXISTS crypto_audit_no_delete BEFORE DELETE ON crypto_audit
+            BEGIN SELECT RAISE(ABORT, 'crypto_audit is append-only'); END;",
+        )?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            queue: Mutex::new(None),
+        })
+    }
+
+    pub fn append(&self, entry: &AuditEntry) -> rusqlite::Result<()> {
+        insert(&lock(&self.conn), entry)
+    }
+
+    /// The latest `limit` entries, newest first
+    pub fn recent(&self, limit: u32) -> rusqlite::Result<Vec<AuditEntry>> {
+        let conn = lock(&self.conn);
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, operation, peer_id, details FROM crypto_audit
+             ORDER BY id DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(params![limit], |row| {
+            let operation: String = row.get(1)?;
+            Ok((row.get::<_, i64>(0)?, operation, row.get(2)?, row.get(3)?))
+        })?;
+        let mut entries = Vec::new();
+        for row in rows {
+            let (timestamp, operation, peer_id, details) = row?;
+            // Rows written by a newer version may name unknown operations
+            if let Some(operation) = CryptoOperation::parse(&operation) {
+                entries.push(AuditEntry {
+                    timestamp: timestamp as u64,
+                    operation,
+                    peer_id,
+                    details,
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Queue `entry` for the writer thread, starting it on first use
+    fn enqueue(&self, entry: AuditEntry) {
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        let sender = queue.get_or_insert_with(|| {
+            let (tx, rx) = mpsc::channel::<AuditEntry>();
+            let conn = self.conn.clone();
+            std::thread::spawn(move || {
+                for entry in rx {
+                    if let Err(e) = insert(&lock(&conn), &entry) {
+                        warn!("Failed to write crypto audit entry: {}", e);
+                    }
+                }
+            });
+            tx
+        });
+        let _ = sender.send(entry);
+    }
+
+    /// Make `log` the process-wide log; false if one was installed already
+    pub fn install(log: AuditLog) -> bool {
+        AUDIT_LOG.set(log).is_ok()
+    }
+
+    pub fn global() -> Option<&'static AuditLog> {
+        AUDIT_LOG.get()
+    }
+}
+
+/// Append an entry to the installed log; does nothing while auditing is off
+pub fn record(operation: CryptoOperation, peer_id: Option<String>, details: impl Into<String>) {
+    if let Some(log) = AUDIT_LOG.get() {
+        log.enqueue(AuditEntry::now(operation, peer_id, details));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_are_append_only_and_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::open(&dir.path().join("audit.db")).unwrap();
+        let first = AuditEntry::now(CryptoOperation::KeyGenerated, None, "node identity");
+        let second = AuditEntry::now(
+            CryptoOperation::verification(false),
+            Some("12D3KooWpeer".to_string()),
+            "bootstrap manifest",
+        );
+        log.append(&first).unwrap();
+        log.append(&second).unwrap();
+
+        assert_eq!(log.recent(10).unwrap(), vec![second.clone(), first]);
+        assert_eq!(log.recent(1).unwrap(), vec![second]);
+
+        let conn = lock(&log.conn);
+        assert!(conn.execute("DELETE FROM crypto_audit", []).is_err());
+        assert!(conn.execute("UPDATE crypto_audit SET details = ''", []).is_err());
+    }
+}
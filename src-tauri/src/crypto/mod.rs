@@ -0,0 +1,5 @@
+//! Records of the node's cryptographic operations.
+
+pub mod audit;
+
+pub use audit::{AuditEntry, AuditLog, CryptoOperation};
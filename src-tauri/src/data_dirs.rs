@@ -0,0 +1,465 @@
+//! Where a node keeps its state.
+//!
+//! Everything a node persists lives under one data directory: the headless
+//! configuration and identity, the peer caches, registries, the block store,
+//! logs and diagnostics. Subsystems ask `DataDirs::current()` for their paths
+//! instead of building them, so the directory can be moved as a whole and
+//! two nodes with different directories share nothing; the instance lock is
+//! taken on the directory, not on the machine.
+//!
+//! The directory is, in order:
+//!
+//! 1. `--data-dir`, installed by `main` before anything opens a file;
+//! 2. for the desktop app, the directory chosen in the settings, which
+//!    `set_data_dir` records in `location.json` in the default directory;
+//! 3. the platform data directory of `directories::ProjectDirs`.
+//!
+//! `create` makes the tree on first run. Directories it creates are private to
+//! the owner on Unix, since they hold the identity and the wallet keystore.
+//!
+//! Moving to another directory with `set_data_dir(.., true)` takes effect on
+//! the next start: `resolve_desktop` moves the entries of `STATE_ENTRIES` over
+//! before the node opens them, leaving any that already exist at the new
+//! place untouched.
+//!
+//! Releases before the data directory kept the desktop app's settings,
+//! chunk storage, logs, diagnostics and 2FA secrets in Tauri's app data
+//! directory. `migrate` moves them on the first start that finds them there,
+//! so uploads encrypted before stay decryptable.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// File in the default directory pointing the desktop app elsewhere
+pub const LOCATION_FILE_NAME: &str = "location.json";
+
+/// Files and directories that make up the state of a node, moved by `migrate`
+pub const STATE_ENTRIES: &[&str] = &[
+    "chiral.toml",
+    "identity",
+    "keystore.json",
+    "peer_addresses.db",
+    "peer_addresses.enc.db",
+    "crypto_audit.db",
+    "download_resume.json",
+    "shared_files.json",
+    "messages.db",
+    "watch_dir.json",
+    "storage_settings.json",
+    "transfer_history.jsonl",
+    "trusted_peers.json",
+    "settings.json",
+    "2fa_secrets",
+    "geoip.csv",
+    "blockstore_db",
+    "files",
+    "chunk_storage",
+    "logs",
+    "diagnostics",
+];
+
+/// Subdirectories created with the data directory
+const SUBDIRECTORIES: &[&str] = &["files", "logs", "diagnostics"];
+
+static INSTALLED: OnceLock<DataDirs> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataDirs {
+    root: PathBuf,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    data_dir: Option<PathBuf>,
+    /// State to move into `data_dir` on the next start
+    migrate_from: Option<PathBuf>,
+}
+
+/// What `migrate` did
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationReport {
+    pub moved: Vec<String>,
+    /// Entries left behind because the new directory already had them
+    pub skipped: Vec<String>,
+}
+
+impl DataDirs {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The platform data directory, or the working directory without one
+    pub fn default_root() -> PathBuf {
+        directories::ProjectDirs::from("com", "chiral-network", "chiral-network")
+            .map(|dirs| dirs.data_dir().to_path_buf())
+            .unwrap_or_else(|| PathBuf::from("."))
+    }
+
+    /// Use these directories for the rest of the process. Only the first call
+    /// has an effect; returns whether it was this one.
+    pub fn install(self) -> bool {
+        INSTALLED.set(self).is_ok()
+    }
+
+    /// Where the user's stored files go by default: next to the platform
+    /// data directory, not inside it
+    pub fn default_storage() -> PathBuf {
+        let root = Self::default_root();
+        root.parent()
+            .unwrap_or(&root)
+            .join("Chiral-Network-Storage")
+    }
+
+    /// The installed directories, or the default ones
+    pub fn current() -> Self {
+        INSTALLED
+            .get()
+            .cloned()
+            .unwrap_or_else(|| Self::new(Self::default_root()))
+    }
+
+    /// The directories of the desktop app, after finishing a move that
+    /// `set_data_dir` scheduled. `default_root` is where `location.json` is.
+    pub fn resolve_desktop(default_root: &Path) -> Result<(Self, Option<MigrationReport>), String> {
+        let location_path = default_root.join(LOCATION_FILE_NAME);
+        let mut location = read_location(&location_path)?;
+        let dirs = Self::new(
+            location
+                .data_dir
+                .clone()
+                .unwrap_or_else(|| default_root.to_path_buf()),
+        );
+        let Some(from) = location.migrate_from.take() else {
+            return Ok((dirs, None));
+        };
+        dirs.create()?;
+        let report = migrate(&from, dirs.root())?;
+        write_location(&location_path, &location)?;
+        Ok((dirs, Some(report)))
+    }
+
+    /// Point the desktop app at `dir` from its next start, moving the state
+    /// of `self` there when `move_state` is set
+    pub fn set_data_dir(
+        &self,
+        default_root: &Path,
+        dir: &Path,
+        move_state: bool,
+    ) -> Result<(), String> {
+        if !dir.is_absolute() {
+            return Err(format!(
+                "The data directory must be an absolute path: {}",
+                dir.display()
+            ));
+        }
+        Self::new(dir).create()?;
+        let location = Location {
+            data_dir: (dir != default_root).then(|| dir.to_path_buf()),
+            migrate_from: (move_state && dir != self.root()).then(|| self.root.clone()),
+        };
+        std::fs::create_dir_all(default_root)
+            .map_err(|e| format!("Failed to create {}: {}", default_root.display(), e))?;
+        write_location(&default_root.join(LOCATION_FILE_NAME), &location)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Create the directory and its subdirectories where missing
+    pub fn create(&self) -> Result<(), String> {
+        create_private_dir(&self.root)?;
+        for name in SUBDIRECTORIES {
+            create_private_dir(&self.root.join(name))?;
+        }
+        Ok(())
+    }
+
+    /// `chiral.toml` of headless nodes
+    pub fn config_file(&self) -> PathBuf {
+        self.root.join(crate::config::headless::CONFIG_FILE_NAME)
+    }
+
+    /// Seed of the peer id, used by headless nodes given `--data-dir`
+    pub fn identity_file(&self) -> PathBuf {
+        self.root.join("identity")
+    }
+
+    /// Wallet accounts
+    pub fn keystore(&self) -> PathBuf {
+        self.root.join("keystore.json")
+    }
+
+    pub fn peer_addresses(&self) -> PathBuf {
+        self.root.join("peer_addresses.db")
+    }
+
+    pub fn encrypted_peer_addresses(&self) -> PathBuf {
+        self.root.join("peer_addresses.enc.db")
+    }
+
+    pub fn crypto_audit(&self) -> PathBuf {
+        self.root.join("crypto_audit.db")
+    }
+
+    pub fn download_resume(&self) -> PathBuf {
+        self.root.join("download_resume.json")
+    }
+
+    pub fn shared_files(&self) -> PathBuf {
+        self.root.join("shared_files.json")
+    }
+
+    pub fn messages(&self) -> PathBuf {
+        self.root.join("messages.db")
+    }
+
+    pub fn watch_dir(&self) -> PathBuf {
+        self.root.join("watch_dir.json")
+    }
+
+    pub fn storage_settings(&self) -> PathBuf {
+        self.root.join("storage_settings.json")
+    }
+
+    pub fn transfer_history(&self) -> PathBuf {
+        self.root.join("transfer_history.jsonl")
+    }
+
+    /// Peers the relay server serves under `known_peers_only`
+    pub fn trusted_peers(&self) -> PathBuf {
+        self.root.join("trusted_peers.json")
+    }
+
+    /// Settings saved by the desktop app's settings page
+    pub fn settings_file(&self) -> PathBuf {
+        self.root.join("settings.json")
+    }
+
+    /// One encrypted TOTP secret per wallet account
+    pub fn two_fa_secrets(&self) -> PathBuf {
+        self.root.join("2fa_secrets")
+    }
+
+    /// IP ranges for the network map, see `geolocation`
+    pub fn geoip_database(&self) -> PathBuf {
+        self.root.join("geoip.csv")
+    }
+
+    pub fn blockstore(&self) -> PathBuf {
+        self.root.join("blockstore_db")
+    }
+
+    /// Files this node serves
+    pub fn files(&self) -> PathBuf {
+        self.root.join("files")
+    }
+
+    pub fn chunk_storage(&self) -> PathBuf {
+        self.root.join("chunk_storage")
+    }
+
+    /// Downloads of the desktop app; `suffix` tells instances apart
+    pub fn downloads(&self, suffix: &str) -> PathBuf {
+        self.root.join(format!("downloads{}", suffix))
+    }
+
+    pub fn logs(&self) -> PathBuf {
+        self.root.join("logs")
+    }
+
+    pub fn diagnostics(&self) -> PathBuf {
+        self.root.join("diagnostics")
+    }
+}
+
+/// `path`, unless only `legacy` exists yet; for state that used to live
+/// outside the data directory and is read where it is until it is moved
+pub fn existing_or_legacy(path: PathBuf, legacy: Option<PathBuf>) -> PathBuf {
+    match legacy {
+        Some(legacy) if !path.exists() && legacy.exists() => legacy,
+        _ => path,
+    }
+}
+
+fn create_private_dir(dir: &Path) -> Result<(), String> {
+    if dir.is_dir() {
+        return Ok(());
+    }
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+        .create(dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))
+}
+
+fn read_location(path: &Path) -> Result<Location, String> {
+    match std::fs::read(path) {
+        Ok(bytes) => {
+            serde_json::from_slice(&bytes).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Location::default()),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+fn write_location(path: &Path, location: &Location) -> Result<(), String> {
+    let tmp = path.with_extension("tmp");
+    let json = serde_json::to_vec_pretty(location).map_err(|e| e.to_string())?;
+    std::fs::write(&tmp, json)
+        .and_then(|()| std::fs::rename(&tmp, path))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn copy_recursively(from: &Path, to: &Path) -> std::io::Result<()> {
+    if !from.is_dir() {
+        return std::fs::copy(from, to).map(|_| ());
+    }
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        copy_recursively(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// Move what `to` lacks from the directory `from` into the directory `to`,
+/// entry by entry; `from` is removed once empty
+fn merge_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let (source, target) = (entry.path(), to.join(entry.file_name()));
+        if source.is_dir() && target.is_dir() {
+            merge_dir(&source, &target)?;
+        } else if !target.exists() {
+            move_entry(&source, &target)?;
+        }
+    }
+    let _ = std::fs::remove_dir(from);
+    Ok(())
+}
+
+fn move_entry(source: &Path, target: &Path) -> std::io::Result<()> {
+    std::fs::rename(source, target).or_else(|_| {
+        copy_recursively(source, target)?;
+        if source.is_dir() {
+            std::fs::remove_dir_all(source)
+        } else {
+            std::fs::remove_file(source)
+        }
+    })
+}
+
+/// Move the entries of `STATE_ENTRIES` from `from` to `to`. A directory `to`
+/// already has gets the files it lacks; other entries `to` already has are
+/// skipped. Renames that cross file systems fall back to a copy and delete.
+pub fn migrate(from: &Path, to: &Path) -> Result<MigrationReport, String> {
+    let mut report = MigrationReport::default();
+    if from == to {
+        return Ok(report);
+    }
+    for name in STATE_ENTRIES {
+        let (source, target) = (from.join(name), to.join(name));
+        if !source.exists() {
+            continue;
+        }
+        let failed = |e: std::io::Error| {
+            format!(
+                "Failed to move {} to {}: {}",
+                source.display(),
+                target.display(),
+                e
+            )
+        };
+        if source.is_dir() && target.is_dir() {
+            // Such as the empty ones `create` leaves; what both have stays
+            // in `from`
+            merge_dir(&source, &target).map_err(failed)?;
+            if source.exists() {
+                report.skipped.push(name.to_string());
+            } else {
+                report.moved.push(name.to_string());
+            }
+            continue;
+        }
+        if target.exists() {
+            report.skipped.push(name.to_string());
+            continue;
+        }
+        move_entry(&source, &target).map_err(failed)?;
+        report.moved.push(name.to_string());
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_move_to_another_directory() {
+        let base = tempfile::tempdir().unwrap();
+        let default_root = base.path().join("default");
+        let old = DataDirs::new(&default_root);
+        old.create().unwrap();
+        std::fs::write(old.peer_addresses(), b"peers").unwrap();
+        std::fs::write(old.logs().join("chiral.log"), b"log").unwrap();
+        std::fs::write(old.identity_file(), b"seed").unwrap();
+
+        let new_root = base.path().join("moved");
+        std::fs::create_dir_all(&new_root).unwrap();
+        std::fs::write(new_root.join("identity"), b"other seed").unwrap();
+        old.set_data_dir(&default_root, &new_root, true).unwrap();
+
+        let (dirs, report) = DataDirs::resolve_desktop(&default_root).unwrap();
+        assert_eq!(dirs.root(), new_root);
+        let report = report.unwrap();
+        assert!(report.moved.contains(&"peer_addresses.db".to_string()));
+        assert!(report.moved.contains(&"logs".to_string()));
+        assert_eq!(report.skipped, vec!["identity".to_string()]);
+        assert_eq!(std::fs::read(dirs.peer_addresses()).unwrap(), b"peers");
+        assert_eq!(
+            std::fs::read(dirs.logs().join("chiral.log")).unwrap(),
+            b"log"
+        );
+        assert_eq!(std::fs::read(dirs.identity_file()).unwrap(), b"other seed");
+        assert!(!old.peer_addresses().exists());
+
+        // The move is done once; later starts only follow the location
+        let (again, report) = DataDirs::resolve_desktop(&default_root).unwrap();
+        assert_eq!(again, dirs);
+        assert_eq!(report, None);
+        assert!(old
+            .set_data_dir(&default_root, Path::new("relative"), false)
+            .is_err());
+
+        // State of older releases in Tauri's app data directory joins what
+        // is already there
+        let legacy = base.path().join("com.chiralnetwork");
+        std::fs::create_dir_all(legacy.join("chunk_storage")).unwrap();
+        std::fs::write(legacy.join("chunk_storage").join("old-chunk"), b"old").unwrap();
+        std::fs::write(legacy.join("settings.json"), b"{}").unwrap();
+        std::fs::create_dir_all(dirs.chunk_storage()).unwrap();
+        std::fs::write(dirs.chunk_storage().join("new-chunk"), b"new").unwrap();
+        let report = migrate(&legacy, dirs.root()).unwrap();
+        assert_eq!(report.moved, ["settings.json", "chunk_storage"]);
+        assert_eq!(
+            std::fs::read(dirs.chunk_storage().join("old-chunk")).unwrap(),
+            b"old"
+        );
+        assert_eq!(
+            std::fs::read(dirs.chunk_storage().join("new-chunk")).unwrap(),
+            b"new"
+        );
+        assert!(!legacy.join("chunk_storage").exists());
+        assert_eq!(
+            migrate(&legacy, dirs.root()).unwrap(),
+            MigrationReport::default()
+        );
+    }
+}
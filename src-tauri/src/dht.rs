@@ -4,8 +4,28 @@ use self::models::*;
 use rand::seq::SliceRandom;
 
 // use self::protocol::*;
-use crate::config::CHAIN_ID;
-use crate::download_source::HttpSourceInfo;
+use crate::compatibility;
+use crate::diagnostics;
+use crate::memory_budget::{self, BudgetPressure, BudgetedStore, CacheKind, MemoryBudget, MemoryUsage, MemoryUsageReport};
+use crate::discovery::{
+    announce_topic, Admission, BootstrapContributionStats, BootstrapContributionTracker,
+    BootstrapFallbackChain, BootstrapMode, KadQuery, KadRateLimitConfig, KadRateLimiter,
+    LocalDiscoveryCache, NodeAnnouncement, PeerDiscoveryEvent, PeerDiscoveryFeed, PeerSource,
+    MultiBootstrapConsensus, NodeAnnouncementBroadcast, NodeAnnouncementStore, PeerAddrMatch,
+    PeerStore, validate_bootstrap_addr, ANNOUNCE_INTERVAL,
+};
+use crate::encrypted_peer_store::EncryptedPeerStore;
+use crate::monitoring::{
+    self, CloseReason, ConnectionKinds, ConnectionQualityClassifier, DashboardStats,
+    DetailedNetworkStats, FullPeerInfo, MeshHealthMonitor, MeshTopicHealth,
+    NetworkStats, PeerEvent, PeerEventLog, QualityDegraded, StatsCollector, StatsCounters,
+    TransportStats,
+};
+use crate::nat::{AutoNATConfidence, AutoNATProbeScheduler, ScheduledAutoNATClient};
+use crate::swarm_event_log::SwarmEventLogger;
+use crate::protocol;
+use crate::config::{ChiralConfig, CHAIN_ID};
+use crate::download_source::{HttpSourceInfo, WebSeeds};
 use crate::encryption::EncryptedAesKeyBundle;
 use serde_bytes;
 use x25519_dalek::PublicKey;
@@ -66,6 +86,13 @@ fn merge_file_metadata(existing: crate::dht::models::FileMetadata, new: crate::d
         merged.http_sources = existing.http_sources.clone();
     }
 
+    // Web seeds are signed as a whole, so never union them; only their
+    // publisher may replace them
+    merged.web_seeds = match (&existing.web_seeds, &new.web_seeds) {
+        (Some(old), Some(seeds)) if old.publisher != seeds.publisher => Some(old.clone()),
+        (old, seeds) => seeds.clone().or_else(|| old.clone()),
+    };
+
     // Merge CIDs (IPFS content identifiers)
     if let (Some(existing_cids), Some(new_cids)) = (&existing.cids, &new.cids) {
         let mut merged_cids = existing_cids.clone();
@@ -97,7 +124,7 @@ pub struct KeyRequestProtocol;
 
 impl AsRef<str> for KeyRequestProtocol {
     fn as_ref(&self) -> &str {
-        "/chiral/key-request/1.0.0"
+        protocol::KEY_REQUEST_PROTOCOL
     }
 }
 
@@ -206,8 +233,9 @@ use std::{
     path::PathBuf,
     str::FromStr,
 };
-use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::sync::{broadcast, mpsc, oneshot, Mutex};
 use tokio_util::compat::TokioAsyncReadCompatExt;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, trace, warn};
 
 use crate::manager::Sha256Hasher;
@@ -220,7 +248,36 @@ use std::pin::Pin;
 use std::task::{Context, Poll};
 
 // Import the missing types
-use crate::file_transfer::FileTransferService;
+use crate::file_transfer::{
+    DirectTransferProgress, FileOffer, FileTransferCodec, FileTransferProtocol, FileTransferRequest,
+    FileTransferResponse, FileTransferService, Handled, IncomingFileTransfers,
+    DIRECT_TRANSFER_CHUNK_SIZE, MAX_CHUNK_RETRANSMITS, OFFER_TIMEOUT, TRANSFER_WIRE_VERSION,
+};
+use crate::compression::{self, CompressionStats, TransferCompressionStats};
+use crate::call::{
+    CallDirection, CallEvent, CallEventKind, CallInfo, CallRequest, CallResponse,
+    CallSignalingCodec, CallSignalingProtocol, CallState, CallStateManager, RING_TIMEOUT,
+};
+use crate::presence::{presence_topic, PeerTyping, TypingEvent, TypingIndicator};
+use crate::messaging::receipts::{ReadReceiptAck, ReadReceiptCodec, ReadReceiptProtocol};
+use crate::integrations::WebhookNotifier;
+use crate::relay_consent::{self, RelayConsent, RelayConsentPolicy, TrustedPeers, NO_CONSENT};
+use crate::security::{FilterDenied, IncomingConnectionFilter};
+use crate::port_forwarding::{PortForwardingMonitor, PortForwardingStatus};
+use crate::crypto::{self, AuditLog, CryptoOperation};
+use crate::replication::{self as record_replication, RecordReplicator};
+use crate::peer_lookup::{
+    PeerLookupCodec, PeerLookupProtocol, PeerLookupRequest, PeerLookupResponse, MAX_LOOKUP_ADDRESSES,
+};
+use crate::reachability_update::{
+    NodeReachabilityUpdate, ReachabilityAck, ReachabilityCodec, ReachabilityProtocol,
+    RelayReservations,
+};
+use crate::messaging::{
+    ChannelEnvelope, CompressionSample, FilterMode, IncomingMessage, MessageCompressor, MessageReaction, ReadReceiptBatch, TopicFilter,
+    TopicFilterState,
+};
+use libp2p::gossipsub::TopicHash;
 use crate::manager::ChunkManager;
 use std::error::Error;
 
@@ -239,11 +296,11 @@ use libp2p::{
         // FIXED E0432: ListenerEvent is removed, only import what is available.
         transport::{Boxed, DialOpts, ListenerId, Transport, TransportError, TransportEvent},
     },
-    dcutr,
+    dcutr, gossipsub,
     identify::{self, Event as IdentifyEvent},
     identity,
     kad::{
-        self, store::MemoryStore, Behaviour as Kademlia, Config as KademliaConfig,
+        self, Behaviour as Kademlia, Config as KademliaConfig,
         Event as KademliaEvent, GetRecordOk, Mode, PutRecordOk, QueryResult, Record,
     },
     mdns::{tokio::Behaviour as Mdns, Event as MdnsEvent},
@@ -256,7 +313,7 @@ use libp2p::{
     Multiaddr, PeerId, StreamProtocol, Swarm, SwarmBuilder,
 };
 use rand::rngs::OsRng;
-const EXPECTED_PROTOCOL_VERSION: &str = "/chiral/1.0.0";
+const EXPECTED_PROTOCOL_VERSION: &str = protocol::IDENTIFY_PROTOCOL;
 const MAX_MULTIHASH_LENGHT: usize = 64;
 /// Prefix for DHT records that map a torrent info_hash to a Chiral Merkle root.
 const INFO_HASH_PREFIX: &str = "info_hash_idx::";
@@ -265,20 +322,32 @@ pub const RAW_CODEC: u64 = 0x55;
 const FILE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15); // More frequent updates
 /// File seeder TTL – if no heartbeat lands within this window, drop the entry.
 const FILE_HEARTBEAT_TTL: Duration = Duration::from_secs(90); // Longer TTL with grace period
+/// Time given to open connections to close when the node shuts down
+const CONNECTION_CLOSE_TIMEOUT: Duration = Duration::from_secs(3);
 
 /// thread-safe, mutable block store
 
 #[derive(NetworkBehaviour)]
 struct DhtBehaviour {
-    kademlia: Kademlia<MemoryStore>,
+    /// First, so filtered connections are refused before the others see them
+    connection_filter: IncomingConnectionFilter,
+    kademlia: Kademlia<BudgetedStore>,
     identify: identify::Behaviour,
     mdns: toggle::Toggle<Mdns>,
-    bitswap: beetswap::Behaviour<MAX_MULTIHASH_LENGHT, RedbBlockstore>,
+    /// This and the transfer and call protocols are off on infra nodes, so
+    /// they are not even advertised there
+    bitswap: toggle::Toggle<beetswap::Behaviour<MAX_MULTIHASH_LENGHT, RedbBlockstore>>,
     ping: ping::Behaviour,
-    proxy_rr: rr::Behaviour<ProxyCodec>,
-    webrtc_signaling_rr: rr::Behaviour<WebRTCSignalingCodec>,
-    key_request: rr::Behaviour<KeyRequestCodec>,
-    autonat_client: toggle::Toggle<v2::client::Behaviour>,
+    proxy_rr: toggle::Toggle<rr::Behaviour<ProxyCodec>>,
+    webrtc_signaling_rr: toggle::Toggle<rr::Behaviour<WebRTCSignalingCodec>>,
+    key_request: toggle::Toggle<rr::Behaviour<KeyRequestCodec>>,
+    file_transfer: toggle::Toggle<rr::Behaviour<FileTransferCodec>>,
+    call_signaling: toggle::Toggle<rr::Behaviour<CallSignalingCodec>>,
+    read_receipts: rr::Behaviour<ReadReceiptCodec>,
+    reachability: rr::Behaviour<ReachabilityCodec>,
+    peer_lookup: rr::Behaviour<PeerLookupCodec>,
+    gossipsub: toggle::Toggle<gossipsub::Behaviour>,
+    autonat_client: toggle::Toggle<ScheduledAutoNATClient>,
     autonat_server: toggle::Toggle<v2::server::Behaviour>,
     relay_client: relay::client::Behaviour,
     relay_server: toggle::Toggle<relay::Behaviour>,
@@ -308,6 +377,24 @@ pub enum DhtCommand {
         addresses: Vec<String>,
     },
     GetPeerCount(oneshot::Sender<usize>),
+    /// Aggregate statistics, copied from atomics without taking a lock
+    GetStats(oneshot::Sender<NetworkStats>),
+    /// `GetStats` with the metrics snapshot and peer list; locks the metrics
+    GetDetailedStats(oneshot::Sender<DetailedNetworkStats>),
+    /// `GetStats` with what the dashboard shows besides, without the metrics
+    GetDashboardStats(oneshot::Sender<DashboardStats>),
+    GetMeshHealth(oneshot::Sender<Vec<MeshTopicHealth>>),
+    /// Peers matching a full or partial peer id, with their addresses
+    FindPeerAddrs {
+        query: String,
+        sender: oneshot::Sender<Vec<PeerAddrMatch>>,
+    },
+    /// Make sure AutoNAT servers are connected so reachability gets re-tested
+    ProbeNat,
+    /// Bootstrap nodes the pruner quarantined; `ProbeNat` does not dial them
+    SetQuarantinedBootstrap(Vec<Multiaddr>),
+    /// Advertise an address mapped outside the swarm, e.g. by NAT-PMP
+    AddExternalAddress(Multiaddr),
     Echo {
         peer: PeerId,
         payload: Vec<u8>,
@@ -327,6 +414,43 @@ pub enum DhtCommand {
         offer_request: WebRTCOfferRequest,
         sender: oneshot::Sender<Result<WebRTCAnswerResponse, String>>,
     },
+    SendFileTransferRequest {
+        peer: PeerId,
+        request: FileTransferRequest,
+        sender: oneshot::Sender<Result<FileTransferResponse, String>>,
+    },
+    /// Accept or reject a direct transfer offer the user was asked about
+    AnswerFileOffer {
+        transfer_id: String,
+        accept: bool,
+        reason: String,
+        sender: oneshot::Sender<Result<(), String>>,
+    },
+    /// Invite or hang up; the outcome arrives as a `DhtEvent::Call`
+    SendCallRequest {
+        peer: PeerId,
+        request: CallRequest,
+    },
+    /// Answer an incoming invite that is still ringing
+    AnswerCall {
+        session_id: String,
+        response: CallResponse,
+        sender: oneshot::Sender<Result<(), String>>,
+    },
+    /// Publish a typing indicator on the presence topic
+    PublishPresence(TypingEvent),
+    /// Subscribe to a channel's gossipsub topic
+    JoinChannel(String),
+    /// Publish on a channel's gossipsub topic, joining it first if needed
+    PublishToChannel {
+        channel: String,
+        envelope: ChannelEnvelope,
+    },
+    /// Tell a message author which of their messages we have read
+    SendReadReceipts {
+        peer: PeerId,
+        batch: ReadReceiptBatch,
+    },
     StoreBlock {
         cid: Cid,
         data: Vec<u8>,
@@ -354,6 +478,13 @@ pub enum DhtCommand {
         key: String,
         sender: oneshot::Sender<Result<Option<Vec<u8>>, String>>,
     },
+    /// Store a record on up to `replication_factor` of the closest peers
+    PutReplicated {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        replication_factor: u8,
+        sender: oneshot::Sender<Result<Vec<PeerId>, String>>,
+    },
     /// Re-bootstrap the DHT to discover new peers
     ReBootstrap {
         sender: oneshot::Sender<Result<usize, String>>,
@@ -449,6 +580,33 @@ pub enum DhtEvent {
         from_peer: String,
         payload: serde_json::Value,
     },
+    /// A peer was disconnected because its agent version is incompatible
+    PeerVersionIncompatible {
+        peer_id: String,
+        local_version: String,
+        remote_version: String,
+        reason: String,
+    },
+    /// A peer offered us a file; it waits for `accept_file_offer` or
+    /// `reject_file_offer`
+    FileOffer(FileOffer),
+    /// Bytes of a direct file transfer from a peer were written
+    FileTransferProgress(DirectTransferProgress),
+    /// A voice call was offered, answered or ended
+    Call(CallEvent),
+    /// A peer started or stopped typing in a channel
+    PeerTyping(PeerTyping),
+    /// Reaction or other envelope received on a joined channel
+    Channel {
+        channel: String,
+        envelope: ChannelEnvelope,
+    },
+    /// A peer read some of our messages; every receipt names that peer as reader
+    MessagesRead(ReadReceiptBatch),
+    /// The connection to a peer dropped below `Fair` quality
+    QualityDegraded(QualityDegraded),
+    /// The GossipSub mesh of a topic dropped below `mesh_n_low`
+    MeshHealthDegraded(MeshTopicHealth),
 }
 
 struct RelayState {
@@ -826,6 +984,16 @@ fn merge_dht_metadata(existing: &serde_json::Value, new: &serde_json::Value) ->
                 merged_obj.insert(key.clone(), value.clone());
             }
         }
+
+        // Signed web seeds may only be replaced by their own publisher
+        let publisher = |obj: &serde_json::Map<String, serde_json::Value>| {
+            obj.get("web_seeds").and_then(|seeds| seeds.get("publisher")).cloned()
+        };
+        if let Some(old) = publisher(existing_obj) {
+            if publisher(new_obj).is_some_and(|new| new != old) {
+                merged_obj.insert("web_seeds".to_string(), existing_obj["web_seeds"].clone());
+            }
+        }
     }
 
     merged
@@ -1024,6 +1192,18 @@ impl DhtMetricsSnapshot {
             dcutr_hole_punch_failures,
             last_dcutr_success,
             last_dcutr_failure,
+            gossip_messages_blocked,
+            gossip_bytes_uncompressed,
+            gossip_bytes_compressed,
+            connections_opened,
+            connections_closed,
+            kad_requests_served,
+            kad_records_stored,
+            kad_routing_table_size,
+            relay_circuits_accepted,
+            relay_circuits_active,
+            transfers_received,
+            transfers_failed,
             ..
         } = metrics;
 
@@ -1083,11 +1263,28 @@ impl DhtMetricsSnapshot {
             dcutr_hole_punch_failures,
             last_dcutr_success: last_dcutr_success.and_then(to_secs),
             last_dcutr_failure: last_dcutr_failure.and_then(to_secs),
+            gossip_messages_blocked,
+            gossip_bytes_uncompressed,
+            gossip_bytes_compressed,
+            connections_opened,
+            connections_closed,
+            kad_requests_served,
+            kad_records_stored,
+            kad_routing_table_size,
+            relay_circuits_accepted,
+            relay_circuits_active,
+            transfers_received,
+            transfers_failed,
         }
     }
 }
 
 impl DhtMetrics {
+    fn record_gossip_compression(&mut self, sample: CompressionSample) {
+        self.gossip_bytes_uncompressed += sample.original_bytes as u64;
+        self.gossip_bytes_compressed += sample.compressed_bytes as u64;
+    }
+
     fn record_listen_addr(&mut self, addr: &Multiaddr) {
         let addr_str = addr.to_string();
         if !self
@@ -1240,7 +1437,49 @@ async fn run_dht_node(
     relay_candidates: HashSet<String>,
     chunk_size: usize,
     bootstrap_peer_ids: HashSet<PeerId>,
+    peer_events: Arc<Mutex<PeerEventLog>>,
+    discovery_cache: Option<Arc<LocalDiscoveryCache>>,
+    nat_scheduler: Option<AutoNATProbeScheduler>,
+    autonat_servers: Vec<Multiaddr>,
+    pending_file_transfers: Arc<
+        Mutex<
+            HashMap<rr::OutboundRequestId, oneshot::Sender<Result<FileTransferResponse, String>>>,
+        >,
+    >,
+    incoming_file_transfers: Arc<Mutex<IncomingFileTransfers>>,
+    call_state: Arc<Mutex<CallStateManager>>,
+    typing: Arc<Mutex<TypingIndicator>>,
+    mut bootstrap_chain: BootstrapFallbackChain,
+    mut announcer: NodeAnnouncementBroadcast,
+    node_announcements: Arc<Mutex<NodeAnnouncementStore>>,
+    connection_quality: Arc<Mutex<ConnectionQualityClassifier>>,
+    topic_filter: Arc<Mutex<TopicFilter>>,
+    bootstrap_contributions: Arc<Mutex<BootstrapContributionTracker>>,
+    kad_rate_limit: KadRateLimitConfig,
+    periodic_bootstrap: Option<Duration>,
+    relay_consent: RelayConsent,
+    mut bootstrap_consensus: Option<MultiBootstrapConsensus>,
+    port_forwarding: PortForwardingMonitor,
+    replication: Arc<Mutex<RecordReplicator>>,
+    bandwidth_metrics: Arc<libp2p::metrics::Registry>,
+    stats_counters: Arc<StatsCounters>,
+    mut peer_discovery: PeerDiscoveryFeed,
+    memory_usage: Arc<MemoryUsage>,
+    mut mesh_health: MeshHealthMonitor,
+    connected_addrs: Arc<Mutex<HashMap<PeerId, Vec<Multiaddr>>>>,
+    // Signs the web seeds of published files
+    local_key: identity::Keypair,
 ) {
+    // Outstanding call requests, and incoming invites waiting for the user to answer
+    let mut pending_call_requests: HashMap<rr::OutboundRequestId, (PeerId, String)> =
+        HashMap::new();
+    let mut call_answer_channels: HashMap<String, rr::ResponseChannel<CallResponse>> =
+        HashMap::new();
+    // Direct transfer offers waiting for the user, by transfer id
+    let mut file_offer_channels: HashMap<String, rr::ResponseChannel<FileTransferResponse>> =
+        HashMap::new();
+    // Peer lookups sent to bootstrap nodes, by the peer looked up
+    let mut peer_lookups: HashMap<rr::OutboundRequestId, PeerId> = HashMap::new();
     // Track peers that support relay (discovered via identify protocol)
     let relay_capable_peers: Arc<Mutex<HashMap<PeerId, Vec<Multiaddr>>>> =
         Arc::new(Mutex::new(HashMap::new()));
@@ -1249,6 +1488,28 @@ async fn run_dht_node(
     // fast heartbeat-driven updater: run at FILE_HEARTBEAT_INTERVAL to keep provider records fresh
     let mut heartbeat_maintenance_interval = tokio::time::interval(FILE_HEARTBEAT_INTERVAL);
     heartbeat_maintenance_interval.tick().await;
+    // Sends idle StopTyping events and expires stale remote StartTyping events
+    let mut presence_interval = tokio::time::interval(Duration::from_secs(1));
+    // Moves the bootstrap fallback chain on when a node times out
+    let mut bootstrap_chain_interval = tokio::time::interval(Duration::from_secs(1));
+    // Announces our capabilities; the first tick fires at startup
+    let mut announce_interval = tokio::time::interval(ANNOUNCE_INTERVAL);
+    // Starts the Kademlia queries held back by the rate limiter
+    let mut kad_limiter = KadRateLimiter::new(kad_rate_limit, Instant::now());
+    let mut kad_limiter_interval = tokio::time::interval(Duration::from_millis(100));
+    // Introduction lookups -> the bootstrap node whose peers they return
+    let mut introduction_queries: HashMap<kad::QueryId, PeerId> = HashMap::new();
+    // Re-bootstraps the routing table; off for bootstrap and standalone nodes
+    let mut periodic_bootstrap_interval =
+        tokio::time::interval(periodic_bootstrap.unwrap_or(Duration::from_secs(1)));
+    // Samples the sizes of the local record store and routing table for the metrics endpoint
+    let mut record_count_interval = tokio::time::interval(Duration::from_secs(15));
+    // Reads the byte counters and publishes the lock-free statistics
+    let mut stats_collector = StatsCollector::new();
+    let mut stats_interval = tokio::time::interval(monitoring::stats::SAMPLE_INTERVAL);
+    // Stores replicated records again before they expire
+    let mut republish_interval = tokio::time::interval(record_replication::REPUBLISH_CHECK_INTERVAL);
+    let mut quarantined_bootstrap: HashSet<Multiaddr> = HashSet::new();
     // Periodic bootstrap interval
 
     /// Creates a proper circuit relay address for connecting through a relay peer
@@ -1316,7 +1577,22 @@ async fn run_dht_node(
     }
 
     let mut shutdown_ack: Option<oneshot::Sender<()>> = None;
+    let mut peer_store = PeerStore::new(discovery_cache.clone());
+    peer_store.set_max_bytes(
+        memory_usage
+            .budget()
+            .map(|budget| budget.share(CacheKind::AddressBook)),
+    );
     let mut ping_failures: HashMap<PeerId, u8> = HashMap::new();
+    // Bootstrap side: peers we hold a relay reservation for
+    let mut relay_reservations = RelayReservations::new();
+    // Client side: circuit listeners reserved through relays, with the
+    // circuit address advertised for each
+    let mut relay_listeners: HashMap<ListenerId, Multiaddr> = HashMap::new();
+    // Every open listener, closed first on shutdown
+    let mut listeners: HashSet<ListenerId> = HashSet::new();
+    let mut connection_kinds = ConnectionKinds::default();
+    let started_at = Instant::now();
     let mut relay_blacklist: HashSet<PeerId> = HashSet::new();
     let mut relay_cooldown: HashMap<PeerId, Instant> = HashMap::new();
     let mut last_tried_relay: Option<PeerId> = None;
@@ -1354,6 +1630,89 @@ async fn run_dht_node(
 
     'outer: loop {
         tokio::select! {
+                    _ = announce_interval.tick() => {
+                        publish_announcement(&mut swarm, &metrics, &peer_events, &mut announcer).await;
+                        node_announcements.lock().await.expire(unix_timestamp());
+                    }
+                    _ = record_count_interval.tick() => {
+                        use libp2p::kad::store::RecordStore;
+                        let records = swarm.behaviour_mut().kademlia.store_mut().records().count();
+                        let routing_table_size: usize = swarm
+                            .behaviour_mut()
+                            .kademlia
+                            .kbuckets()
+                            .map(|bucket| bucket.num_entries())
+                            .sum();
+                        let mut metrics = metrics.lock().await;
+                        metrics.kad_records_stored = records as u64;
+                        metrics.kad_routing_table_size = routing_table_size as u64;
+                        // The file caches are measured by `trim_file_caches`
+                        memory_usage.store(
+                            CacheKind::KadStore,
+                            swarm.behaviour_mut().kademlia.store_mut().used_bytes(),
+                        );
+                        memory_usage.store(CacheKind::AddressBook, peer_store.approx_bytes());
+                    }
+                    _ = stats_interval.tick() => {
+                        let transports = monitoring::stats::transport_bytes(&bandwidth_metrics);
+                        stats_collector.record(Instant::now(), transports);
+                        let reachability = metrics.lock().await.reachability_state;
+                        let active_transfers = active_downloads.lock().await.len()
+                            + incoming_file_transfers.lock().await.active_count();
+                        stats_counters.store(&stats_collector, reachability, active_transfers);
+                        observe_mesh(&swarm, &mut mesh_health, &event_tx).await;
+                    }
+                    _ = republish_interval.tick() => {
+                        let mut replication = replication.lock().await;
+                        for key in replication.due_for_republish(Instant::now()) {
+                            debug!("Re-publishing replicated record {}", hex::encode(key.as_ref()));
+                            // Started now or once the rate limiter allows it
+                            if let Some(query) = kad_limiter.submit(KadQuery::Republish(key.clone()), Instant::now()) {
+                                if let Some(query_id) = start_kad_query(&mut swarm, query) {
+                                    replication.lookup_started(query_id, key, None);
+                                }
+                            }
+                        }
+                    }
+                    _ = periodic_bootstrap_interval.tick(), if periodic_bootstrap.is_some() => {
+                        if let Some(query) = kad_limiter.submit(KadQuery::Bootstrap, Instant::now()) {
+                            start_kad_query(&mut swarm, query);
+                        }
+                    }
+                    _ = kad_limiter_interval.tick(), if kad_limiter.queued() > 0 => {
+                        for query in kad_limiter.poll(Instant::now()) {
+                            let Some(query_id) = start_kad_query(&mut swarm, query.clone()) else {
+                                continue;
+                            };
+                            match query {
+                                KadQuery::Republish(key) => {
+                                    replication.lock().await.lookup_started(query_id, key, None);
+                                }
+                                KadQuery::Introductions(bootstrap) => {
+                                    introduction_queries.insert(query_id, bootstrap);
+                                }
+                                KadQuery::Bootstrap | KadQuery::ClosestPeers(_) => {}
+                            }
+                        }
+                    }
+                    _ = bootstrap_chain_interval.tick(), if !bootstrap_chain.is_empty() => {
+                        if let Some(addr) = bootstrap_chain.poll(std::time::Instant::now()) {
+                            dial_bootstrap_node(&mut swarm, addr);
+                        }
+                    }
+                    _ = presence_interval.tick() => {
+                        let now = std::time::Instant::now();
+                        let (stops, expired) = {
+                            let mut typing = typing.lock().await;
+                            (typing.poll_idle(now), typing.expire(now))
+                        };
+                        for event in &stops {
+                            publish_presence(&mut swarm, &metrics, &peer_events, event).await;
+                        }
+                        for change in expired {
+                            let _ = event_tx.send(DhtEvent::PeerTyping(change)).await;
+                        }
+                    }
                     // periodic maintenance tick - prune expired seeder heartbeats and update DHT
                     // Fast heartbeat tick — refresh DHT records for files this node is actively seeding
                     _ = heartbeat_maintenance_interval.tick(), if !is_bootstrap => {
@@ -1450,6 +1809,7 @@ async fn run_dht_node(
                                                 price: json_val.get("price").and_then(|v| v.as_f64()).unwrap_or(0.0),
                                                 uploader_address: json_val.get("uploader_address").and_then(|v| v.as_str()).map(|s| s.to_string()),
                                                 http_sources: json_val.get("http_sources").and_then(|v| {serde_json::from_value::<Option<Vec<HttpSourceInfo>>>(v.clone()).unwrap_or(None)}),
+                                                web_seeds: json_val.get("web_seeds").and_then(|v| serde_json::from_value::<Option<WebSeeds>>(v.clone()).ok()).unwrap_or(None),
                                                 ed2k_sources: json_val.get("ed2k_sources").and_then(|v| {serde_json::from_value::<Option<Vec<Ed2kSourceInfo>>>(v.clone()).unwrap_or(None)}),
                                                 ..Default::default()
                                             };
@@ -1467,6 +1827,11 @@ async fn run_dht_node(
                                 break 'outer;
                             }
                             Some(DhtCommand::PublishFile { mut metadata, response_tx }) => {
+                                // A failed block store below ends the whole swarm task
+                                if !swarm.behaviour().bitswap.is_enabled() {
+                                    warn!("Not publishing {}: infra nodes keep no files", metadata.merkle_root);
+                                    continue;
+                                }
                                 // If file_data is NOT empty (non-encrypted files or inline data),
                                 // create blocks, generate a Merkle root, and a root CID.
                                 if !metadata.file_data.is_empty() {
@@ -1486,7 +1851,7 @@ async fn run_dht_node(
                                         // Also hash the original data for the Merkle root
                                         original_chunk_hashes.push(Sha256Hasher::hash(block.data()));
 
-                                        match swarm.behaviour_mut().bitswap.insert_block::<MAX_MULTIHASH_LENGHT>(cid.clone(), block.data().to_vec()){
+                                        match insert_block(&mut swarm, cid.clone(), block.data().to_vec()) {
                                             Ok(_) => {
                                             },
                                             Err(e) => {
@@ -1519,7 +1884,7 @@ async fn run_dht_node(
 
                                     // Store root block in Bitswap
                                     let root_cid = Cid::new_v1(RAW_CODEC, Code::Sha2_256.digest(&root_block_data));
-                                    match swarm.behaviour_mut().bitswap.insert_block::<MAX_MULTIHASH_LENGHT>(root_cid.clone(), root_block_data.clone()) {
+                                    match insert_block(&mut swarm, root_cid.clone(), root_block_data.clone()) {
                                         Ok(_) => {
                                         },
                                         Err(e) => {
@@ -1564,6 +1929,14 @@ async fn run_dht_node(
                                 let active_heartbeats = prune_heartbeats(heartbeat_entries, now);
                                 metadata.seeders = heartbeats_to_peer_list(&active_heartbeats);
 
+                                // Sign web seeds now that the file hash is final
+                                if let Some(seeds) = metadata.web_seeds.as_mut().filter(|s| !s.is_signed()) {
+                                    if let Err(e) = seeds.sign(&local_key, &metadata.merkle_root, metadata.file_size) {
+                                        warn!("Publishing {} without web seeds: {}", metadata.merkle_root, e);
+                                        metadata.web_seeds = None;
+                                    }
+                                }
+
                                 // Store minimal metadata in DHT
                                 let dht_metadata = serde_json::json!({
                                     "file_hash":metadata.merkle_root,
@@ -1586,6 +1959,7 @@ async fn run_dht_node(
                                     "price": metadata.price,
                                     "uploader_address": metadata.uploader_address,
                                     "http_sources": metadata.http_sources,
+                                    "web_seeds": metadata.web_seeds,
                                     "ed2k_sources": metadata.ed2k_sources,
                                 });
 
@@ -1701,7 +2075,7 @@ async fn run_dht_node(
                             Some(DhtCommand::StoreBlocks { blocks, root_cid, mut metadata }) => {
                                 // 1. Store all encrypted data blocks in bitswap
                                 for (cid, data) in blocks {
-                                    if let Err(e) = swarm.behaviour_mut().bitswap.insert_block::<MAX_MULTIHASH_LENGHT>(cid.clone(), data) {
+                                    if let Err(e) = insert_block(&mut swarm, cid.clone(), data) {
                                         error!("Failed to store encrypted block {} in bitswap: {}", cid, e);
                                         let _ = event_tx.send(DhtEvent::Error(format!("Failed to store block {}: {}", cid, e))).await;
                                         continue 'outer; // Abort this publish operation
@@ -1865,7 +2239,11 @@ async fn run_dht_node(
                                 };
 
                                 // Request the root block which contains the CIDs
-                                let root_query_id = swarm.behaviour_mut().bitswap.get_from(&root_cid, peer_id);
+                                let Some(bitswap) = swarm.behaviour_mut().bitswap.as_mut() else {
+                                    let _ = event_tx.send(DhtEvent::Error("Downloads are off in infra mode".to_string())).await;
+                                    continue;
+                                };
+                                let root_query_id = bitswap.get_from(&root_cid, peer_id);
 
                                 file_metadata.download_path = Some(download_path);
                                 // Store the root query ID to handle when we get the root block
@@ -1873,43 +2251,40 @@ async fn run_dht_node(
                                 root_query_mapping.lock().await.insert(root_query_id, file_metadata);
                             }
                             Some(DhtCommand::StopPublish(file_hash)) => {
+                                // Only this node's provider entry goes; other seeders keep theirs
                                 let key = kad::RecordKey::new(&file_hash);
-                                let removed = swarm.behaviour_mut().kademlia.remove_record(&key);
-                                debug!(
-                                    "StopPublish: removed record for {} (removed={:?})",
-                                    file_hash, removed
-                                );
-
-                                // Ask Kademlia to stop providing this file (so provider records are removed)
                                 swarm
                                     .behaviour_mut()
                                     .kademlia
                                     .stop_providing(&key);
 
-                                // Also proactively publish an updated DHT record with no seeders so remote nodes
-                                // that fetch the JSON record see that there are no seeders immediately.
-                                // Build minimal "empty" metadata
-                                let empty_meta = serde_json::json!({
-                                    "merkle_root": file_hash,
-                                    "file_name": serde_json::Value::Null,
-                                    "file_size": 0u64,
-                                    "created_at": unix_timestamp(),
-                                    "seeders": Vec::<String>::new(),
-                                    "seederHeartbeats": Vec::<SeederHeartbeat>::new()
-                                });
-                                if let Ok(bytes) = serde_json::to_vec(&empty_meta) {
-                                    let record = Record {
-                                        key: kad::RecordKey::new(&file_hash.as_bytes()),
-                                        value: bytes,
-                                        publisher: Some(peer_id.clone()),
-                                        expires: None,
-                                    };
-                                    if let Err(e) =
-                                        swarm.behaviour_mut().kademlia.put_record(record, kad::Quorum::One)
-                                    {
-                                        warn!("Failed to publish empty record for {}: {}", file_hash, e);
-                                    } else {
-                                        debug!("Published empty seeder record for {}", file_hash);
+                                // Republish the metadata record without this node among its
+                                // seeders, so fetchers stop asking it right away
+                                let cached = seeder_heartbeats_cache
+                                    .lock()
+                                    .await
+                                    .get(&file_hash)
+                                    .map(|entry| entry.metadata.clone());
+                                let stored = || {
+                                    let record = swarm.behaviour_mut().kademlia.store_mut().get(&key)?;
+                                    serde_json::from_slice::<serde_json::Value>(&record.value).ok()
+                                };
+                                if let Some(mut metadata) = cached.or_else(stored) {
+                                    remove_seeder(&mut metadata, &peer_id.to_string());
+                                    if let Ok(bytes) = serde_json::to_vec(&metadata) {
+                                        let record = Record {
+                                            key: key.clone(),
+                                            value: bytes,
+                                            publisher: Some(peer_id),
+                                            expires: None,
+                                        };
+                                        if let Err(e) =
+                                            swarm.behaviour_mut().kademlia.put_record(record, kad::Quorum::One)
+                                        {
+                                            warn!("Failed to republish record for {} without this seeder: {}", file_hash, e);
+                                        } else {
+                                            debug!("Republished {} without this node as a seeder", file_hash);
+                                        }
                                     }
                                 }
 
@@ -2148,6 +2523,9 @@ async fn run_dht_node(
                                             None
                                         }
                                     });
+                                    if let Some(peer) = &maybe_peer_id {
+                                        peer_discovery.found(peer, PeerSource::Manual);
+                                    }
 
                                     if let Some(peer_id) = maybe_peer_id.clone() {
                                         // Check if the address contains a private IP
@@ -2385,7 +2763,11 @@ async fn run_dht_node(
 
                                 // Query the DHT for known addresses of this peer
                                 info!("Querying DHT for addresses of peer {}", peer_id);
-                                let _query_id = swarm.behaviour_mut().kademlia.get_closest_peers(peer_id);
+                                if let Some(query) =
+                                    kad_limiter.submit(KadQuery::ClosestPeers(peer_id), Instant::now())
+                                {
+                                    start_kad_query(&mut swarm, query);
+                                }
 
                                 // Connection attempts will be handled when GetClosestPeers results are received
                                 let _ = event_tx.send(DhtEvent::Info(format!("Searching for peer {} addresses...", peer_id))).await;
@@ -2398,8 +2780,101 @@ async fn run_dht_node(
                                 let count = connected_peers.lock().await.len();
                                 let _ = tx.send(count);
                             }
+                            Some(DhtCommand::GetStats(sender)) => {
+                                let _ = sender.send(current_network_stats(&mut swarm, &stats_counters));
+                            }
+                            Some(DhtCommand::GetMeshHealth(sender)) => {
+                                let _ = sender.send(mesh_health.topics());
+                            }
+                            Some(DhtCommand::GetDashboardStats(sender)) => {
+                                let mut dashboard =
+                                    DashboardStats::from_stats(current_network_stats(&mut swarm, &stats_counters));
+                                dashboard.peer_id = Some(swarm.local_peer_id().to_string());
+                                dashboard.listen_addrs = swarm.listeners().map(|addr| addr.to_string()).collect();
+                                dashboard.external_addrs =
+                                    swarm.external_addresses().map(|addr| addr.to_string()).collect();
+                                (dashboard.direct_peers, dashboard.relayed_peers) = connection_kinds.peer_counts();
+                                {
+                                    let tracker = bootstrap_contributions.lock().await;
+                                    dashboard.bootstrap_connected = tracker.connected();
+                                    dashboard.bootstrap_configured = tracker.len();
+                                }
+                                dashboard.dht_ready = crate::health_check::readiness(
+                                    dashboard.bootstrap_connected,
+                                    dashboard.bootstrap_configured,
+                                    dashboard.listen_addrs.len(),
+                                )
+                                .is_ok();
+                                dashboard.uptime_secs = started_at.elapsed().as_secs();
+                                let _ = sender.send(dashboard);
+                            }
+                            Some(DhtCommand::GetDetailedStats(sender)) => {
+                                let stats = current_network_stats(&mut swarm, &stats_counters);
+                                let kbucket_sizes = swarm
+                                    .behaviour_mut()
+                                    .kademlia
+                                    .kbuckets()
+                                    .map(|bucket| bucket.num_entries())
+                                    .filter(|entries| *entries > 0)
+                                    .collect();
+                                let connected: Vec<String> = connected_peers
+                                    .lock()
+                                    .await
+                                    .iter()
+                                    .map(|peer| peer.to_string())
+                                    .collect();
+                                let snapshot = DhtMetricsSnapshot::from(metrics.lock().await.clone(), connected.len());
+                                let _ = sender.send(DetailedNetworkStats {
+                                    stats,
+                                    metrics: snapshot,
+                                    connected_peers: connected,
+                                    kbucket_sizes,
+                                });
+                            }
+                            Some(DhtCommand::FindPeerAddrs { query, sender }) => {
+                                let mut routing_table: HashMap<PeerId, Vec<Multiaddr>> = HashMap::new();
+                                for bucket in swarm.behaviour_mut().kademlia.kbuckets() {
+                                    for entry in bucket.iter() {
+                                        routing_table.insert(
+                                            *entry.node.key.preimage(),
+                                            entry.node.value.iter().cloned().collect(),
+                                        );
+                                    }
+                                }
+                                let _ = sender.send(peer_store.find(&query, &routing_table));
+                            }
+                            Some(DhtCommand::AddExternalAddress(addr)) => {
+                                swarm.add_external_address(addr);
+                            }
+                            Some(DhtCommand::ProbeNat) => {
+                                if let Some(client) = swarm.behaviour_mut().autonat_client.as_mut() {
+                                    client.probe();
+                                }
+                                // AutoNAT v2 tests our addresses against connected servers,
+                                // so redial any server we have lost
+                                for addr in autonat_servers.iter().filter(|a| !quarantined_bootstrap.contains(*a)) {
+                                    let connected = match addr.iter().last() {
+                                        Some(Protocol::P2p(pid)) => swarm.is_connected(&pid),
+                                        _ => false,
+                                    };
+                                    if connected {
+                                        continue;
+                                    }
+                                    if let Err(e) = swarm.dial(addr.clone()) {
+                                        debug!("AutoNAT probe: failed to dial {}: {}", addr, e);
+                                    }
+                                }
+                            }
+                            Some(DhtCommand::SetQuarantinedBootstrap(addrs)) => {
+                                quarantined_bootstrap = addrs.into_iter().collect();
+                            }
                             Some(DhtCommand::Echo { peer, payload, tx }) => {
-                                let id = swarm.behaviour_mut().proxy_rr.send_request(&peer, EchoRequest(payload));
+                                let Some(proxy_rr) = swarm.behaviour_mut().proxy_rr.as_mut() else {
+                                    let _ = tx.send(Err("The proxy protocol is off in infra mode".to_string()));
+                                    continue;
+                                };
+                                let id = proxy_rr.send_request(&peer, EchoRequest(payload));
+                                diagnostics::sent_request("ProxyRr", &peer);
                                 pending_echo.lock().await.insert(id, PendingEcho { peer, tx });
                             }
                             Some(DhtCommand::GetProviders { file_hash, sender }) => {
@@ -2419,11 +2894,169 @@ async fn run_dht_node(
                                 pending_provider_queries.lock().await.insert(file_hash, pending_query);
                             }
                             Some(DhtCommand::SendWebRTCOffer { peer, offer_request, sender }) => {
-                                let id = swarm.behaviour_mut().webrtc_signaling_rr.send_request(&peer, offer_request);
+                                let Some(webrtc_signaling_rr) = swarm.behaviour_mut().webrtc_signaling_rr.as_mut() else {
+                                    let _ = sender.send(Err("WebRTC transfers are off in infra mode".to_string()));
+                                    continue;
+                                };
+                                let id = webrtc_signaling_rr.send_request(&peer, offer_request);
+                                diagnostics::sent_request("WebrtcSignalingRr", &peer);
                                 pending_webrtc_offers.lock().await.insert(id, sender);
                             }
+                            Some(DhtCommand::SendFileTransferRequest { peer, request, sender }) => {
+                                if !peer_store.capabilities(&peer).supports_file_transfer {
+                                    let _ = sender.send(Err(format!("Peer {} does not support direct file transfer", peer)));
+                                    continue;
+                                }
+                                let Some(file_transfer) = swarm.behaviour_mut().file_transfer.as_mut() else {
+                                    let _ = sender.send(Err("Direct file transfer is off in infra mode".to_string()));
+                                    continue;
+                                };
+                                let id = file_transfer.send_request(&peer, request);
+                                diagnostics::sent_request("FileTransfer", &peer);
+                                pending_file_transfers.lock().await.insert(id, sender);
+                            }
+                            Some(DhtCommand::AnswerFileOffer { transfer_id, accept, reason, sender }) => {
+                                let result = match file_offer_channels.remove(&transfer_id) {
+                                    Some(channel) => {
+                                        let mut incoming = incoming_file_transfers.lock().await;
+                                        let sender_peer = incoming
+                                            .pending_offer(&transfer_id)
+                                            .map(|offer| offer.peer_id.clone());
+                                        let (response, progress) = if accept {
+                                            incoming.accept(&transfer_id).await
+                                        } else {
+                                            (incoming.decline(&transfer_id, reason), None)
+                                        };
+                                        let failed = match &response {
+                                            FileTransferResponse::Reject { reason } if accept => Some(reason.clone()),
+                                            _ => None,
+                                        };
+                                        let sent = send_response(&mut swarm.behaviour_mut().file_transfer, channel, response).is_ok();
+                                        if !sent {
+                                            // The sender gave up or disconnected while the user decided
+                                            if let Some(peer_id) = &sender_peer {
+                                                incoming.abort(peer_id).await;
+                                            }
+                                        }
+                                        drop(incoming);
+                                        match (sent, failed) {
+                                            (false, _) => Err("the sender is no longer waiting".to_string()),
+                                            (true, Some(reason)) => Err(reason),
+                                            (true, None) => {
+                                                if let Some(progress) = progress {
+                                                    let _ = event_tx.send(DhtEvent::FileTransferProgress(progress)).await;
+                                                }
+                                                Ok(())
+                                            }
+                                        }
+                                    }
+                                    None => Err(format!("no pending file offer {}", transfer_id)),
+                                };
+                                let _ = sender.send(result);
+                            }
+                            Some(DhtCommand::SendCallRequest { peer, request }) => {
+                                let session_id = match &request {
+                                    CallRequest::Invite { session_id, .. }
+                                    | CallRequest::Hangup { session_id } => session_id.clone(),
+                                };
+                                let Some(call_signaling) = swarm.behaviour_mut().call_signaling.as_mut() else {
+                                    warn!("Not calling {}: calls are off in infra mode", peer);
+                                    continue;
+                                };
+                                let id = call_signaling.send_request(&peer, request);
+                                diagnostics::sent_request("CallSignaling", &peer);
+                                pending_call_requests.insert(id, (peer, session_id));
+                            }
+                            Some(DhtCommand::AnswerCall { session_id, response, sender }) => {
+                                let result = match call_answer_channels.remove(&session_id) {
+                                    Some(channel) => {
+                                        let accepted = matches!(response, CallResponse::Accept { .. });
+                                        // Ringing channels come only from an enabled behaviour
+                                        let sent = match swarm.behaviour_mut().call_signaling.as_mut() {
+                                            Some(call_signaling) => call_signaling.send_response(channel, response).map_err(|_| ()),
+                                            None => Err(()),
+                                        };
+                                        match sent {
+                                            Ok(()) if accepted => call_state
+                                                .lock()
+                                                .await
+                                                .mark_active(&session_id)
+                                                .map(|_| ())
+                                                .map_err(|e| e.to_string()),
+                                            Ok(()) => {
+                                                call_state.lock().await.end(&session_id);
+                                                Ok(())
+                                            }
+                                            Err(_) => {
+                                                // The caller gave up or disconnected while we rang
+                                                if let Some(call) = call_state.lock().await.end(&session_id) {
+                                                    let _ = event_tx
+                                                        .send(DhtEvent::Call(
+                                                            CallEvent::new(CallEventKind::Ended, &session_id, &call.peer_id)
+                                                                .with_reason("caller is no longer waiting"),
+                                                        ))
+                                                        .await;
+                                                }
+                                                Err("caller is no longer waiting".to_string())
+                                            }
+                                        }
+                                    }
+                                    None => Err(format!("no ringing call {}", session_id)),
+                                };
+                                let _ = sender.send(result);
+                            }
+                            Some(DhtCommand::PublishPresence(event)) => {
+                                publish_presence(&mut swarm, &metrics, &peer_events, &event).await;
+                            }
+                            Some(DhtCommand::JoinChannel(channel)) => {
+                                let Some(gossip) = swarm.behaviour_mut().gossipsub.as_mut() else {
+                                    warn!("Cannot join channel {}: gossip is off in infra mode", channel);
+                                    continue;
+                                };
+                                if let Err(e) = gossip.subscribe(&gossipsub::IdentTopic::new(channel.as_str())) {
+                                    warn!("Failed to join channel {}: {e:?}", channel);
+                                }
+                            }
+                            Some(DhtCommand::PublishToChannel { channel, envelope }) => {
+                                let topic = gossipsub::IdentTopic::new(channel.as_str());
+                                let Some(gossip) = swarm.behaviour_mut().gossipsub.as_mut() else {
+                                    debug!("Not publishing to channel {}: gossip is off in infra mode", channel);
+                                    continue;
+                                };
+                                let _ = gossip.subscribe(&topic);
+                                // One publish reaches every subscriber, so features are
+                                // withheld only when no subscriber has them
+                                let hash = topic.hash();
+                                let capabilities = compatibility::PeerCapabilitySet::any_of(
+                                    swarm
+                                        .behaviour()
+                                        .gossipsub
+                                        .as_ref()
+                                        .into_iter()
+                                        .flat_map(|gossip| gossip.all_peers())
+                                        .filter(|(_, topics)| topics.contains(&&hash))
+                                        .map(|(peer, _)| peer_store.capabilities(peer)),
+                                );
+                                let envelope = match capabilities {
+                                    Some(capabilities) => match envelope.for_capabilities(&capabilities) {
+                                        Some(envelope) => envelope,
+                                        None => {
+                                            debug!("No peer on channel {} supports reactions; not publishing", channel);
+                                            continue;
+                                        }
+                                    },
+                                    None => envelope,
+                                };
+                                if let Err(e) = publish_gossip(&mut swarm, &metrics, &peer_events, topic, &envelope.encode()).await {
+                                    debug!("Channel {} message not published: {e:?}", channel);
+                                }
+                            }
+                            Some(DhtCommand::SendReadReceipts { peer, batch }) => {
+                                swarm.behaviour_mut().read_receipts.send_request(&peer, batch);
+                                diagnostics::sent_request("ReadReceipts", &peer);
+                            }
                             Some(DhtCommand::StoreBlock { cid, data }) => {
-                                match swarm.behaviour_mut().bitswap.insert_block::<MAX_MULTIHASH_LENGHT>(cid, data) {
+                                match insert_block(&mut swarm, cid, data) {
                                     Ok(_) => {
                                         debug!("Successfully stored block in Bitswap");
                                     }
@@ -2445,7 +3078,12 @@ async fn run_dht_node(
                                 };
 
                                 // Send the request using the key_request behavior
-                                let request_id = swarm.behaviour_mut().key_request.send_request(&seeder, key_request);
+                                let Some(key_request_rr) = swarm.behaviour_mut().key_request.as_mut() else {
+                                    let _ = sender.send(Err("Encrypted file access is off in infra mode".to_string()));
+                                    continue;
+                                };
+                                let request_id = key_request_rr.send_request(&seeder, key_request);
+                                diagnostics::sent_request("KeyRequest", &seeder);
 
                                 // Store the pending request
                                 pending_key_requests.lock().await.insert(request_id, sender);
@@ -2495,6 +3133,20 @@ async fn run_dht_node(
                                 // Store the sender to respond when we get the Kademlia result
                                 pending_dht_queries.lock().await.insert(query_id, sender);
                             }
+                            Some(DhtCommand::PutReplicated { key, value, replication_factor, sender }) => {
+                                use libp2p::kad::store::RecordStore;
+                                let record_key = kad::RecordKey::new(&key);
+                                let now = Instant::now();
+                                let mut replication = replication.lock().await;
+                                replication.track(record_key.clone(), value, replication_factor, now);
+                                if let Some(record) = replication.record(&record_key, now) {
+                                    if let Err(e) = swarm.behaviour_mut().kademlia.store_mut().put(record) {
+                                        warn!("Failed to keep a local copy of replicated record: {}", e);
+                                    }
+                                }
+                                let query_id = swarm.behaviour_mut().kademlia.get_closest_peers(key);
+                                replication.lookup_started(query_id, record_key, Some(sender));
+                            }
                             Some(DhtCommand::ReBootstrap { sender }) => {
                                 info!("🔄 Re-bootstrapping DHT to discover new peers...");
                                 let initial_peer_count = connected_peers.lock().await.len();
@@ -2562,11 +3214,13 @@ async fn run_dht_node(
                                 // Auto-recover if unhealthy and requested
                                 if !healthy && auto_recover {
                                     info!("🔄 Auto-recovery: triggering re-bootstrap (peers: {}, min: {})", peer_count, min_peers);
-                                    if let Ok(_) = swarm.behaviour_mut().kademlia.bootstrap() {
-                                        recovery_triggered = true;
-                                        let mut m = metrics.lock().await;
-                                        m.last_bootstrap = Some(SystemTime::now());
+                                    // Started now or once the rate limiter allows it
+                                    if let Some(query) = kad_limiter.submit(KadQuery::Bootstrap, Instant::now()) {
+                                        start_kad_query(&mut swarm, query);
                                     }
+                                    recovery_triggered = true;
+                                    let mut m = metrics.lock().await;
+                                    m.last_bootstrap = Some(SystemTime::now());
                                 }
                                 
                                 let _ = sender.send(DhtHealthStatus {
@@ -2586,9 +3240,38 @@ async fn run_dht_node(
                         }
                     }
 
-                    event = swarm.next() => if let Some(event) = event {
+                    event = swarm.next() => if let Some(event) = event.map(diagnostics::traced) {
+                        match &event {
+                            SwarmEvent::NewListenAddr { listener_id, .. } => {
+                                listeners.insert(*listener_id);
+                            }
+                            SwarmEvent::ListenerClosed { listener_id, .. } => {
+                                listeners.remove(listener_id);
+                            }
+                            _ => {}
+                        }
                         match event {
                             SwarmEvent::Behaviour(DhtBehaviourEvent::Kademlia(kad_event)) => {
+                                if matches!(kad_event, KademliaEvent::InboundRequest { .. }) {
+                                    metrics.lock().await.kad_requests_served += 1;
+                                }
+                                if let KademliaEvent::RoutingUpdated { peer, is_new_peer: true, .. } = &kad_event {
+                                    peer_discovery.found(peer, PeerSource::Kademlia);
+                                }
+                                if let KademliaEvent::OutboundQueryProgressed { id, result, step, .. } = &kad_event {
+                                    if let (Some(bootstrap), QueryResult::GetClosestPeers(Ok(ok))) =
+                                        (introduction_queries.get(id), result)
+                                    {
+                                        bootstrap_contributions.lock().await.on_introduced(
+                                            bootstrap,
+                                            ok.peers.iter().map(|peer| peer.peer_id),
+                                            unix_timestamp(),
+                                        );
+                                    }
+                                    if step.last {
+                                        introduction_queries.remove(id);
+                                    }
+                                }
                                 handle_kademlia_event(
                                     kad_event,
                                     &mut swarm,
@@ -2603,10 +3286,29 @@ async fn run_dht_node(
                                     &pending_infohash_searches,
                                     &file_metadata_cache,
                                     &pending_dht_queries,
+                                    &mut bootstrap_consensus,
+                                    &mut peer_lookups,
+                                    &replication,
                                 )
                                 .await;
                             }
                             SwarmEvent::Behaviour(DhtBehaviourEvent::Identify(identify_event)) => {
+                                if let IdentifyEvent::Received { peer_id, info, .. } = &identify_event {
+                                    let listen_addrs: Vec<Multiaddr> =
+                                        info.listen_addrs.iter().filter(|a| not_loopback(a)).cloned().collect();
+                                    peer_discovery.addresses(peer_id, &listen_addrs);
+                                    peer_store.record_identify(*peer_id, listen_addrs);
+                                    peer_store.record_capabilities(
+                                        *peer_id,
+                                        compatibility::PeerCapabilitySet::from_identify(&info.agent_version, &info.protocols),
+                                    );
+                                    if let Some(consensus) = bootstrap_consensus.as_mut() {
+                                        consensus.set_supports_lookup(
+                                            *peer_id,
+                                            info.protocols.iter().any(|p| p.as_ref() == crate::protocol::PEER_LOOKUP_PROTOCOL),
+                                        );
+                                    }
+                                }
                                 handle_identify_event(
                                     identify_event,
                                     &mut swarm,
@@ -2618,12 +3320,36 @@ async fn run_dht_node(
                                     &peer_selection,
                                     relay_capable_peers.clone(),
                                     &peer_id,
+                                    &peer_events,
+                                    discovery_cache.as_deref(),
+                                    &mut relay_listeners,
                                 )
                                 .await;
                             }
                             SwarmEvent::Behaviour(DhtBehaviourEvent::Mdns(mdns_event)) if !is_bootstrap => {
                                 if !is_bootstrap{
-                                    handle_mdns_event(mdns_event, &mut swarm, &event_tx, &peer_id).await;
+                                    if let MdnsEvent::Discovered(list) = &mdns_event {
+                                        bootstrap_contributions
+                                            .lock()
+                                            .await
+                                            .mark_local(list.iter().map(|(peer, _)| *peer));
+                                        let found: HashSet<PeerId> = list
+                                            .iter()
+                                            .map(|(peer, _)| *peer)
+                                            .filter(|peer| *peer != peer_id)
+                                            .collect();
+                                        for peer in &found {
+                                            peer_discovery.found(peer, PeerSource::Mdns);
+                                        }
+                                    }
+                                    handle_mdns_event(
+                                        mdns_event,
+                                        &mut swarm,
+                                        &event_tx,
+                                        &peer_id,
+                                        discovery_cache.as_deref(),
+                                    )
+                                    .await;
                                 }
                             }
                             SwarmEvent::Behaviour(DhtBehaviourEvent::RelayClient(relay_event)) if !is_bootstrap => {
@@ -2688,6 +3414,18 @@ async fn run_dht_node(
                                     }
                                 }
                             }
+                            SwarmEvent::Behaviour(DhtBehaviourEvent::RelayServer(relay_server_event)) if is_bootstrap => {
+                                use relay::Event as RelayEvent;
+                                match relay_server_event {
+                                    RelayEvent::ReservationReqAccepted { src_peer_id, .. } => {
+                                        relay_reservations.on_accepted(src_peer_id);
+                                    }
+                                    RelayEvent::ReservationTimedOut { src_peer_id } => {
+                                        relay_reservations.on_ended(&src_peer_id);
+                                    }
+                                    _ => {}
+                                }
+                            }
                             SwarmEvent::Behaviour(DhtBehaviourEvent::RelayServer(relay_server_event)) if !is_bootstrap => {
                                 use relay::Event as RelayEvent;
                                 match relay_server_event {
@@ -2754,7 +3492,12 @@ async fn run_dht_node(
                                             .await;
                                     }
                                     RelayEvent::CircuitReqDenied { src_peer_id, dst_peer_id, .. } => {
-                                        debug!("🔁 Relay server: Denied circuit from {} to {}", src_peer_id, dst_peer_id);
+                                        let reason = if relay_consent.take_refusal(&src_peer_id) {
+                                            NO_CONSENT
+                                        } else {
+                                            "circuit_denied"
+                                        };
+                                        debug!("🔁 Relay server: Denied circuit from {} to {} ({})", src_peer_id, dst_peer_id, reason);
 
                                         // Emit reputation event
                                         let _ = event_tx
@@ -2763,7 +3506,7 @@ async fn run_dht_node(
                                                 event_type: "RelayRefused".to_string(),
                                                 impact: -2.0,
                                                 data: serde_json::json!({
-                                                    "reason": "circuit_denied",
+                                                    "reason": reason,
                                                     "dst_peer_id": dst_peer_id.to_string(),
                                                     "timestamp": SystemTime::now()
                                                         .duration_since(UNIX_EPOCH)
@@ -2775,6 +3518,11 @@ async fn run_dht_node(
                                     }
                                     RelayEvent::CircuitReqAccepted { src_peer_id, dst_peer_id, .. } => {
                                         info!("🔁 Relay server: Established circuit from {} to {}", src_peer_id, dst_peer_id);
+                                        {
+                                            let mut m = metrics.lock().await;
+                                            m.relay_circuits_accepted += 1;
+                                            m.relay_circuits_active += 1;
+                                        }
                                         let _ = event_tx
                                             .send(DhtEvent::Info(format!(
                                                 "Relaying traffic from {} to {}",
@@ -2800,6 +3548,10 @@ async fn run_dht_node(
                                     }
                                     RelayEvent::CircuitClosed { src_peer_id, dst_peer_id, .. } => {
                                         debug!("🔁 Relay server: Circuit closed between {} and {}", src_peer_id, dst_peer_id);
+                                        {
+                                            let mut m = metrics.lock().await;
+                                            m.relay_circuits_active = m.relay_circuits_active.saturating_sub(1);
+                                        }
 
                                         // Emit reputation event
                                         let _ = event_tx
@@ -2840,9 +3592,13 @@ async fn run_dht_node(
                                                     Err(e) => {let _ = event_tx.send(DhtEvent::Error(e.to_string())).await; continue; }
                                                 };
 
+                                                // Bitswap events come only from an enabled Bitswap
+                                                let Some(bitswap) = swarm.behaviour_mut().bitswap.as_mut() else {
+                                                    continue;
+                                                };
                                                 for (i, cid) in cids.iter().enumerate() {
                                                     // Request the root block which contains the CIDs
-                                                    let block_query_id = swarm.behaviour_mut().bitswap.get_from(&cid, peer_id);
+                                                    let block_query_id = bitswap.get_from(&cid, peer_id);
                                                     file_queries.insert(block_query_id, i as u32);
                                                 }
 
@@ -3064,14 +3820,22 @@ async fn run_dht_node(
                             SwarmEvent::Behaviour(DhtBehaviourEvent::Ping(ev)) => {
                                 match ev {
                                     libp2p::ping::Event { peer, result: Ok(rtt), .. } => {
+                                        peer_events.lock().await.record(peer, PeerEvent::PingSuccess(rtt));
                                         let is_connected = connected_peers.lock().await.contains(&peer);
                                         let rtt_ms = rtt.as_millis() as u64;
                                         debug!("Ping from peer {}: {} ms (connected: {})", peer, rtt_ms, is_connected);
 
                                         // Update peer selection metrics with latency
-                                        {
+                                        let degraded = {
                                             let mut selection = peer_selection.lock().await;
                                             selection.update_peer_latency(&peer.to_string(), rtt_ms);
+                                            match selection.get_peer_metrics(&peer.to_string()) {
+                                                Some(m) => connection_quality.lock().await.observe(m),
+                                                None => None,
+                                            }
+                                        };
+                                        if let Some(degraded) = degraded {
+                                            let _ = event_tx.send(DhtEvent::QualityDegraded(degraded)).await;
                                         }
 
                                         let show = proxy_mgr.lock().await.is_proxy(&peer);
@@ -3090,6 +3854,7 @@ async fn run_dht_node(
                                         }
                                     }
                                     libp2p::ping::Event { peer, result: Err(libp2p::ping::Failure::Timeout), .. } => {
+                                        peer_events.lock().await.record(peer, PeerEvent::PingFailure);
                                         let _ = event_tx
                                             .send(DhtEvent::Error(format!("Ping timeout {}", peer)))
                                             .await;
@@ -3105,6 +3870,7 @@ async fn run_dht_node(
                                     }
                                     libp2p::ping::Event { peer, result: Err(e), .. } => {
                                         warn!("ping error with {}: {}", peer, e);
+                                        peer_events.lock().await.record(peer, PeerEvent::PingFailure);
                                         let count = ping_failures.entry(peer).or_insert(0);
                                         *count += 1;
                                         if *count >= 3 {
@@ -3118,7 +3884,30 @@ async fn run_dht_node(
                                 }
                             }
                             SwarmEvent::Behaviour(DhtBehaviourEvent::AutonatClient(ev)) if !is_bootstrap => {
-                                handle_autonat_client_event(&mut swarm, ev, &metrics, &event_tx).await;
+                                let update = handle_autonat_client_event(
+                                    &mut swarm,
+                                    ev,
+                                    &metrics,
+                                    &event_tx,
+                                    nat_scheduler.as_ref(),
+                                )
+                                .await;
+                                if let Some(update) = update {
+                                    if update.is_public && !relay_listeners.is_empty() {
+                                        // Closing the circuit listeners frees the reservations on
+                                        // every relay, including ones that do not take updates
+                                        info!("Publicly reachable, releasing {} relay reservation(s)", relay_listeners.len());
+                                        for (listener, circuit_addr) in relay_listeners.drain() {
+                                            swarm.remove_listener(listener);
+                                            swarm.remove_external_address(&circuit_addr);
+                                        }
+                                    }
+                                    // Let bootstrap nodes release relay capacity held for us
+                                    for peer in bootstrap_peer_ids.iter().filter(|p| swarm.is_connected(p)) {
+                                        swarm.behaviour_mut().reachability.send_request(peer, update.clone());
+                                        diagnostics::sent_request("Reachability", peer);
+                                    }
+                                }
                             }
                             SwarmEvent::Behaviour(DhtBehaviourEvent::AutonatServer(ev)) if !is_bootstrap => {
                                 debug!(?ev, "AutoNAT server event");
@@ -3127,7 +3916,7 @@ async fn run_dht_node(
                                 handle_dcutr_event(ev, &metrics, &event_tx).await;
                             }
                             SwarmEvent::Behaviour(DhtBehaviourEvent::Upnp(upnp_event)) => {
-                                handle_upnp_event(upnp_event, &mut swarm, &event_tx).await;
+                                handle_upnp_event(upnp_event, &mut swarm, &event_tx, &port_forwarding).await;
                             }
                             SwarmEvent::ExternalAddrConfirmed { address, .. } if !is_bootstrap => {
                                 handle_external_addr_confirmed(&mut swarm, &address, &metrics, &event_tx, &proxy_mgr)
@@ -3139,7 +3928,29 @@ async fn run_dht_node(
                             }
                             SwarmEvent::ConnectionEstablished { peer_id, endpoint, num_established, .. } => {
                                 let remote_addr = endpoint.get_remote_address().clone();
+                                metrics.lock().await.connections_opened += 1;
                                 let is_relay = remote_addr.iter().any(|p| matches!(p, Protocol::P2pCircuit));
+                                connection_kinds.opened(peer_id, is_relay);
+                                peer_events
+                                    .lock()
+                                    .await
+                                    .record(peer_id, PeerEvent::Connected(remote_addr.clone()));
+                                if num_established.get() == 1 {
+                                    if bootstrap_peer_ids.contains(&peer_id) {
+                                        peer_discovery.found(&peer_id, PeerSource::Bootstrap);
+                                    }
+                                    peer_discovery.connected(&peer_id, &remote_addr);
+                                }
+                                // Every connection is Noise-authenticated against the peer's identity key
+                                crypto::audit::record(
+                                    CryptoOperation::SessionEstablished,
+                                    Some(peer_id.to_string()),
+                                    format!(
+                                        "noise session {} {}",
+                                        if endpoint.is_dialer() { "to" } else { "from" },
+                                        remote_addr
+                                    ),
+                                );
 
                                 // Initialize peer metrics for smart selection
                                 {
@@ -3157,11 +3968,45 @@ async fn run_dht_node(
                                     .kademlia
                                     .add_address(&peer_id, remote_addr.clone());
 
+                                let is_bootstrap_node = bootstrap_contributions.lock().await.on_connected(
+                                    peer_id,
+                                    &remote_addr,
+                                    endpoint.is_dialer(),
+                                    unix_timestamp(),
+                                );
+                                if is_bootstrap_node && num_established.get() == 1 {
+                                    // Peers this lookup returns are credited to the node
+                                    if let Some(query) =
+                                        kad_limiter.submit(KadQuery::Introductions(peer_id), Instant::now())
+                                    {
+                                        if let Some(query_id) = start_kad_query(&mut swarm, query) {
+                                            introduction_queries.insert(query_id, peer_id);
+                                        }
+                                    }
+                                }
+
+                                // First bootstrap node up: add the others and bootstrap the DHT
+                                if let Some(others) = bootstrap_chain.on_connected(&peer_id, &remote_addr) {
+                                    for addr in others {
+                                        dial_bootstrap_node(&mut swarm, addr);
+                                    }
+                                    info!("✓ Starting Kademlia bootstrap via {}", peer_id);
+                                    if let Some(query) = kad_limiter.submit(KadQuery::Bootstrap, Instant::now()) {
+                                        start_kad_query(&mut swarm, query);
+                                    }
+                                }
+
                                 let peers_count = {
                                     let mut peers = connected_peers.lock().await;
                                     peers.insert(peer_id);
                                     peers.len()
                                 };
+                                connected_addrs
+                                    .lock()
+                                    .await
+                                    .entry(peer_id)
+                                    .or_default()
+                                    .push(remote_addr.clone());
                                 if let Ok(mut m) = metrics.try_lock() {
                                     m.last_success = Some(SystemTime::now());
                                 }
@@ -3182,16 +4027,75 @@ async fn run_dht_node(
                                     })
                                     .await;
                             }
-                            SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                            SwarmEvent::ConnectionClosed { peer_id, cause, num_established, endpoint, .. } => {
+                                connection_kinds.closed(
+                                    &peer_id,
+                                    endpoint.get_remote_address().iter().any(|p| matches!(p, Protocol::P2pCircuit)),
+                                );
                                 warn!("❌ DISCONNECTED from peer: {}", peer_id);
                                 warn!("   Cause: {:?}", cause);
+                                metrics.lock().await.connections_closed += 1;
+                                if num_established == 0 {
+                                    bootstrap_contributions.lock().await.on_disconnected(&peer_id);
+                                    if let Some(consensus) = bootstrap_consensus.as_mut() {
+                                        consensus.on_disconnected(&peer_id);
+                                    }
+                                    relay_reservations.on_ended(&peer_id);
+                                    if let Some(addr) =
+                                        bootstrap_chain.on_disconnected(&peer_id, std::time::Instant::now())
+                                    {
+                                        dial_bootstrap_node(&mut swarm, addr);
+                                    }
+                                    {
+                                        let mut incoming = incoming_file_transfers.lock().await;
+                                        incoming.abort(&peer_id.to_string()).await;
+                                        file_offer_channels.retain(|id, _| incoming.pending_offer(id).is_some());
+                                    }
+                                    let dropped = call_state.lock().await.end_with_peer(&peer_id.to_string());
+                                    for call in dropped {
+                                        call_answer_channels.remove(&call.session_id);
+                                        let _ = event_tx
+                                            .send(DhtEvent::Call(
+                                                CallEvent::new(CallEventKind::Ended, &call.session_id, &call.peer_id)
+                                                    .with_reason("connection lost"),
+                                            ))
+                                            .await;
+                                    }
+                                }
                                 swarm.behaviour_mut().kademlia.remove_peer(&peer_id);
+                                let reason = cause
+                                    .as_ref()
+                                    .map(|e| CloseReason::Error(e.to_string()))
+                                    .unwrap_or(CloseReason::Graceful);
+                                if num_established == 0 {
+                                    let reason = match &reason {
+                                        CloseReason::Graceful => "graceful".to_string(),
+                                        CloseReason::Error(error) => error.clone(),
+                                    };
+                                    peer_discovery.disconnected(&peer_id, reason);
+                                }
+                                peer_events
+                                    .lock()
+                                    .await
+                                    .record(peer_id, PeerEvent::Disconnected(reason));
 
                                 let peers_count = {
                                     let mut peers = connected_peers.lock().await;
                                     peers.remove(&peer_id);
                                     peers.len()
                                 };
+                                {
+                                    let mut addrs = connected_addrs.lock().await;
+                                    if let Some(peer_addrs) = addrs.get_mut(&peer_id) {
+                                        let closed = endpoint.get_remote_address();
+                                        if let Some(i) = peer_addrs.iter().position(|a| a == closed) {
+                                            peer_addrs.remove(i);
+                                        }
+                                        if num_established == 0 || peer_addrs.is_empty() {
+                                            addrs.remove(&peer_id);
+                                        }
+                                    }
+                                }
                                 if !is_bootstrap{
                                 // Remove proxy state
                                 proxy_mgr.lock().await.remove_all(&peer_id);
@@ -3290,6 +4194,7 @@ async fn run_dht_node(
                                                 price: json_val.get("price").and_then(|v| v.as_f64()).unwrap_or(0.0),
                                                 uploader_address: json_val.get("uploader_address").and_then(|v| v.as_str()).map(|s| s.to_string()),
                                                 http_sources: json_val.get("http_sources").and_then(|v| {serde_json::from_value::<Option<Vec<HttpSourceInfo>>>(v.clone()).unwrap_or(None)}),
+                                                web_seeds: json_val.get("web_seeds").and_then(|v| serde_json::from_value::<Option<WebSeeds>>(v.clone()).ok()).unwrap_or(None),
                                                 ed2k_sources: json_val.get("ed2k_sources").and_then(|v| {serde_json::from_value::<Option<Vec<Ed2kSourceInfo>>>(v.clone()).unwrap_or(None)}),
                                                 ..Default::default()
                                             };
@@ -3306,6 +4211,9 @@ async fn run_dht_node(
                                 info!("   Remaining connected peers: {}", peers_count);
                         }
                             SwarmEvent::NewListenAddr { address, .. } if !is_bootstrap => {
+                                if let Some(scheduler) = &nat_scheduler {
+                                    scheduler.notify_interface_change();
+                                }
                                 // Always record in metrics for monitoring/debugging
 
                                   if let Some(Protocol::Ip4(v4)) = address.iter().find(|p| matches!(p, Protocol::Ip4(_))) {
@@ -3328,6 +4236,11 @@ async fn run_dht_node(
                                 }
                                 if let Some(pid) = peer_id {
                                     swarm.behaviour_mut().kademlia.remove_peer(&pid);
+                                    if let Some(addr) =
+                                        bootstrap_chain.on_dial_failed(&pid, std::time::Instant::now())
+                                    {
+                                        dial_bootstrap_node(&mut swarm, addr);
+                                    }
                                     // Only log error for addresses that should be reachable
                                         // Rate limit connection errors to once every 30 seconds
                                         let now = SystemTime::now()
@@ -3388,6 +4301,13 @@ async fn run_dht_node(
                                                 error: None,
                                             }).await;
                                             let EchoRequest(data) = request;
+                                            peer_events.lock().await.record(
+                                                peer,
+                                                PeerEvent::MessageReceived(
+                                                    protocol::PROXY_PROTOCOL.to_string(),
+                                                    data.len(),
+                                                ),
+                                            );
 
                                             // Check if this is a payment notification
                                             if let Ok(json_str) = std::str::from_utf8(&data) {
@@ -3414,8 +4334,14 @@ async fn run_dht_node(
                                             }).await;
 
                                             // 3) Echo response
-                                            swarm.behaviour_mut().proxy_rr
-                                                .send_response(channel, EchoResponse(data))
+                                            peer_events.lock().await.record(
+                                                peer,
+                                                PeerEvent::MessageSent(
+                                                    protocol::PROXY_PROTOCOL.to_string(),
+                                                    data.len(),
+                                                ),
+                                            );
+                                            send_response(&mut swarm.behaviour_mut().proxy_rr, channel, EchoResponse(data))
                                                 .unwrap_or_else(|e| error!("send_response failed: {e:?}"));
                                         }
                                         // Client response
@@ -3489,23 +4415,20 @@ async fn run_dht_node(
                                                 match webrtc_service.establish_connection_with_offer(peer.to_string(), offer_sdp).await {
                                                     Ok(answer_sdp) => {
                                                         info!("Created WebRTC answer for peer {}", peer);
-                                                        swarm.behaviour_mut().webrtc_signaling_rr
-                                                            .send_response(channel, WebRTCAnswerResponse { answer_sdp })
+                                                        send_response(&mut swarm.behaviour_mut().webrtc_signaling_rr, channel, WebRTCAnswerResponse { answer_sdp })
                                                             .unwrap_or_else(|e| error!("send_response failed: {e:?}"));
                                                     }
                                                     Err(e) => {
                                                         error!("Failed to create WebRTC answer for peer {}: {}", peer, e);
                                                         let error_answer = "error:failed-to-create-answer".to_string();
-                                                        swarm.behaviour_mut().webrtc_signaling_rr
-                                                            .send_response(channel, WebRTCAnswerResponse { answer_sdp: error_answer })
+                                                        send_response(&mut swarm.behaviour_mut().webrtc_signaling_rr, channel, WebRTCAnswerResponse { answer_sdp: error_answer })
                                                             .unwrap_or_else(|e| error!("send_response failed: {e:?}"));
                                                     }
                                                 }
                                             } else {
                                                 error!("WebRTC service not available for handling offer from peer {}", peer);
                                                 let error_answer = "error:webrtc-service-unavailable".to_string();
-                                                swarm.behaviour_mut().webrtc_signaling_rr
-                                                    .send_response(channel, WebRTCAnswerResponse { answer_sdp: error_answer })
+                                                send_response(&mut swarm.behaviour_mut().webrtc_signaling_rr, channel, WebRTCAnswerResponse { answer_sdp: error_answer })
                                                     .unwrap_or_else(|e| error!("send_response failed: {e:?}"));
                                             }
                                         }
@@ -3531,6 +4454,13 @@ async fn run_dht_node(
                                     RREvent::ResponseSent { .. } => {}
                                 }
                             }
+                            SwarmEvent::IncomingConnectionError {
+                                error: libp2p::swarm::ListenError::Denied { cause },
+                                send_back_addr,
+                                ..
+                            } if cause.downcast_ref::<FilterDenied>().is_some() => {
+                                debug!("Refused connection from {}: {}", send_back_addr, cause);
+                            }
                             SwarmEvent::IncomingConnectionError { error, .. } if !is_bootstrap => {
 
                                     if let Ok(mut m) = metrics.try_lock() {
@@ -3539,6 +4469,444 @@ async fn run_dht_node(
                                         m.bootstrap_failures = m.bootstrap_failures.saturating_add(1);
                                     }
                           }
+                            SwarmEvent::Behaviour(DhtBehaviourEvent::FileTransfer(ev)) => {
+                                use libp2p::request_response::{Event as RREvent, Message};
+                                match ev {
+                                    // Incoming offer or chunk (we're the receiver)
+                                    RREvent::Message { peer, message } => match message {
+                                        Message::Request { request, channel, .. } => {
+                                            let handled = incoming_file_transfers
+                                                .lock()
+                                                .await
+                                                .handle(&peer.to_string(), request)
+                                                .await;
+                                            match handled {
+                                                // Answered by `DhtCommand::AnswerFileOffer`
+                                                Handled::Offered(offer) => {
+                                                    file_offer_channels.insert(offer.transfer_id.clone(), channel);
+                                                    let _ = event_tx.send(DhtEvent::FileOffer(offer)).await;
+                                                }
+                                                Handled::Answer(response, progress) => {
+                                                    if let Some(progress) = progress {
+                                                        if progress.bytes_received >= progress.total_bytes {
+                                                            metrics.lock().await.transfers_received += 1;
+                                                            info!(
+                                                                peer_id = %peer,
+                                                                file = %progress.filename,
+                                                                bytes = progress.total_bytes,
+                                                                outcome = "completed",
+                                                                "Direct file transfer received"
+                                                            );
+                                                        }
+                                                        let _ = event_tx.send(DhtEvent::FileTransferProgress(progress)).await;
+                                                    }
+                                                    send_response(&mut swarm.behaviour_mut().file_transfer, channel, response)
+                                                        .unwrap_or_else(|e| error!("Failed to send file transfer response: {e:?}"));
+                                                }
+                                            }
+                                        }
+                                        // Answer to our offer or chunk (we're the sender)
+                                        Message::Response { request_id, response } => {
+                                            if let Some(tx) = pending_file_transfers.lock().await.remove(&request_id) {
+                                                let _ = tx.send(Ok(response));
+                                            }
+                                        }
+                                    },
+                                    RREvent::OutboundFailure { request_id, error, .. } => {
+                                        warn!("File transfer outbound failure: {error:?}");
+                                        if let Some(tx) = pending_file_transfers.lock().await.remove(&request_id) {
+                                            let _ = tx.send(Err(format!("Outbound failure: {error:?}")));
+                                        }
+                                    }
+                                    RREvent::InboundFailure { peer, error, .. } => {
+                                        warn!(peer_id = %peer, error = ?error, outcome = "failed", "File transfer inbound failure");
+                                        metrics.lock().await.transfers_failed += 1;
+                                        // Also drops an offer the user did not answer in time
+                                        let mut incoming = incoming_file_transfers.lock().await;
+                                        incoming.abort(&peer.to_string()).await;
+                                        file_offer_channels.retain(|id, _| incoming.pending_offer(id).is_some());
+                                    }
+                                    RREvent::ResponseSent { .. } => {}
+                                }
+                            }
+                            SwarmEvent::Behaviour(DhtBehaviourEvent::Gossipsub(gossipsub::Event::Subscribed { topic, .. })) => {
+                                // The startup announcement usually found no mesh peers
+                                if topic == announce_topic().hash() && announcer.pending() {
+                                    publish_announcement(&mut swarm, &metrics, &peer_events, &mut announcer).await;
+                                }
+                                observe_mesh(&swarm, &mut mesh_health, &event_tx).await;
+                            }
+                            SwarmEvent::Behaviour(DhtBehaviourEvent::Gossipsub(
+                                gossipsub::Event::Unsubscribed { .. } | gossipsub::Event::GossipsubNotSupported { .. },
+                            )) => {
+                                observe_mesh(&swarm, &mut mesh_health, &event_tx).await;
+                            }
+                            SwarmEvent::Behaviour(DhtBehaviourEvent::Gossipsub(gossipsub::Event::Message { propagation_source, message_id, mut message })) => {
+                                use gossipsub::MessageAcceptance;
+                                message.data = match MessageCompressor::default().decompress(&message.data) {
+                                    Ok(data) => data,
+                                    Err(e) => {
+                                        debug!("Dropping gossip message on {}: {}", message.topic, e);
+                                        report_gossip(&mut swarm, &message_id, &propagation_source, MessageAcceptance::Reject);
+                                        continue;
+                                    }
+                                };
+                                peer_events.lock().await.record(
+                                    propagation_source,
+                                    PeerEvent::MessageReceived(message.topic.to_string(), message.data.len()),
+                                );
+                                if message.topic == announce_topic().hash() {
+                                    let Some(announcement) = NodeAnnouncement::decode(&message.data) else {
+                                        debug!("Dropping undecodable node announcement");
+                                        report_gossip(&mut swarm, &message_id, &propagation_source, MessageAcceptance::Reject);
+                                        continue;
+                                    };
+                                    let Some(source) = message.source else {
+                                        report_gossip(&mut swarm, &message_id, &propagation_source, MessageAcceptance::Reject);
+                                        continue;
+                                    };
+                                    if source.to_string() != announcement.peer_id || source == *swarm.local_peer_id() {
+                                        debug!("Dropping node announcement with mismatched peer {}", announcement.peer_id);
+                                        report_gossip(&mut swarm, &message_id, &propagation_source, MessageAcceptance::Reject);
+                                        continue;
+                                    }
+                                    if !node_announcements.lock().await.apply(announcement) {
+                                        // Older than the one we have
+                                        report_gossip(&mut swarm, &message_id, &propagation_source, MessageAcceptance::Ignore);
+                                        continue;
+                                    }
+                                    report_gossip(&mut swarm, &message_id, &propagation_source, MessageAcceptance::Accept);
+                                    // The announcer is alive; make sure it is routable even
+                                    // if we have never been connected to it
+                                    if let Some(cache) = discovery_cache.as_deref() {
+                                        for addr in cache.known_addresses(&source) {
+                                            swarm.behaviour_mut().kademlia.add_address(&source, addr);
+                                        }
+                                    }
+                                    continue;
+                                }
+                                // Muted channels and blocked peers never reach the store
+                                let allowed = {
+                                    let filter = topic_filter.lock().await;
+                                    if message.topic == presence_topic().hash() {
+                                        filter.allows_peer(message.source.as_ref())
+                                    } else {
+                                        filter.allows(message.topic.as_str(), message.source.as_ref())
+                                    }
+                                };
+                                if !allowed {
+                                    metrics.lock().await.gossip_messages_blocked += 1;
+                                    report_gossip(&mut swarm, &message_id, &propagation_source, MessageAcceptance::Ignore);
+                                    continue;
+                                }
+                                if message.topic != presence_topic().hash() {
+                                    let Some(envelope) = ChannelEnvelope::decode(&message.data) else {
+                                        debug!("Dropping undecodable message on {}", message.topic);
+                                        report_gossip(&mut swarm, &message_id, &propagation_source, MessageAcceptance::Reject);
+                                        continue;
+                                    };
+                                    if message.source.map(|p| p.to_string()).as_deref() != Some(envelope.author()) {
+                                        debug!("Dropping channel message with mismatched author {}", envelope.author());
+                                        report_gossip(&mut swarm, &message_id, &propagation_source, MessageAcceptance::Reject);
+                                        continue;
+                                    }
+                                    report_gossip(&mut swarm, &message_id, &propagation_source, MessageAcceptance::Accept);
+                                    let _ = event_tx
+                                        .send(DhtEvent::Channel {
+                                            channel: message.topic.into_string(),
+                                            envelope,
+                                        })
+                                        .await;
+                                    continue;
+                                }
+                                let Some(event) = TypingEvent::decode(&message.data) else {
+                                    debug!("Dropping undecodable presence message");
+                                    report_gossip(&mut swarm, &message_id, &propagation_source, MessageAcceptance::Reject);
+                                    continue;
+                                };
+                                // Messages are signed, so the source is the peer that typed
+                                if message.source.map(|p| p.to_string()).as_deref() != Some(event.from_peer.as_str()) {
+                                    debug!("Dropping presence message with mismatched sender {}", event.from_peer);
+                                    report_gossip(&mut swarm, &message_id, &propagation_source, MessageAcceptance::Reject);
+                                    continue;
+                                }
+                                report_gossip(&mut swarm, &message_id, &propagation_source, MessageAcceptance::Accept);
+                                let change = typing.lock().await.on_event(&event, std::time::Instant::now());
+                                if let Some(change) = change {
+                                    let _ = event_tx.send(DhtEvent::PeerTyping(change)).await;
+                                }
+                            }
+                            SwarmEvent::Behaviour(DhtBehaviourEvent::Reachability(ev)) => {
+                                use libp2p::request_response::{Event as RREvent, Message};
+                                match ev {
+                                    RREvent::Message {
+                                        peer,
+                                        message: Message::Request { request, channel, .. },
+                                    } => {
+                                        swarm.behaviour_mut().reachability
+                                            .send_response(channel, ReachabilityAck)
+                                            .unwrap_or_else(|e| debug!("Failed to ack reachability update: {e:?}"));
+                                        debug!(
+                                            "Peer {} reports reachability public={} ({:?})",
+                                            peer, request.is_public, request.external_addr
+                                        );
+                                        if is_bootstrap && relay_reservations.on_update(&peer, &request) {
+                                            // Relay v2 frees a reservation only with its connection
+                                            info!("🔁 Releasing relay reservation of now-public peer {}", peer);
+                                            let _ = swarm.disconnect_peer_id(peer);
+                                        }
+                                    }
+                                    RREvent::OutboundFailure { peer, error, .. } => {
+                                        // Sent again on the next reachability change
+                                        debug!("Reachability update to {} not delivered: {error:?}", peer);
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            SwarmEvent::Behaviour(DhtBehaviourEvent::PeerLookup(ev)) => {
+                                use libp2p::request_response::{Event as RREvent, Message};
+                                match ev {
+                                    RREvent::Message {
+                                        message: Message::Request { request, channel, .. },
+                                        ..
+                                    } => {
+                                        // Answered from the routing table; empty for unknown peers
+                                        let addrs: Vec<String> = request
+                                            .peer_id
+                                            .parse::<PeerId>()
+                                            .ok()
+                                            .and_then(|target| {
+                                                let bucket = swarm.behaviour_mut().kademlia.kbucket(target)?;
+                                                let entry = bucket.iter().find(|e| *e.node.key.preimage() == target)?;
+                                                Some(
+                                                    entry.node.value.iter()
+                                                        .take(MAX_LOOKUP_ADDRESSES)
+                                                        .map(|a| a.to_string())
+                                                        .collect(),
+                                                )
+                                            })
+                                            .unwrap_or_default();
+                                        let response = PeerLookupResponse { peer_id: request.peer_id, addrs };
+                                        swarm.behaviour_mut().peer_lookup
+                                            .send_response(channel, response)
+                                            .unwrap_or_else(|e| debug!("Failed to answer peer lookup: {e:?}"));
+                                    }
+                                    RREvent::Message {
+                                        peer,
+                                        message: Message::Response { request_id, response },
+                                    } => {
+                                        // Answers are only taken for the peer that was asked about
+                                        let (Some(consensus), Some(target)) =
+                                            (bootstrap_consensus.as_mut(), peer_lookups.remove(&request_id))
+                                        else {
+                                            continue;
+                                        };
+                                        if response.peer_id != target.to_string() {
+                                            debug!("Bootstrap node {} answered a lookup for another peer", peer);
+                                            continue;
+                                        }
+                                        let addrs: Vec<Multiaddr> = response
+                                            .addrs
+                                            .iter()
+                                            .take(MAX_LOOKUP_ADDRESSES)
+                                            .filter_map(|a| a.parse().ok())
+                                            .filter(ma_plausibly_reachable)
+                                            .collect();
+                                        if !addrs.is_empty() {
+                                            bootstrap_contributions.lock().await.on_introduced(
+                                                &peer,
+                                                [target],
+                                                unix_timestamp(),
+                                            );
+                                        }
+                                        let released = consensus.on_lookup(peer, target, addrs);
+                                        if !released.is_empty() {
+                                            info!(
+                                                "Bootstrap nodes settled peer {}; adding {} address(es)",
+                                                target, released.len()
+                                            );
+                                            add_released_addresses(&mut swarm, target, released);
+                                        }
+                                    }
+                                    RREvent::OutboundFailure { peer, request_id, error, .. } => {
+                                        debug!("Peer lookup on bootstrap node {} failed: {error:?}", peer);
+                                        let (Some(consensus), Some(target)) =
+                                            (bootstrap_consensus.as_mut(), peer_lookups.remove(&request_id))
+                                        else {
+                                            continue;
+                                        };
+                                        if matches!(error, rr::OutboundFailure::UnsupportedProtocols) {
+                                            consensus.set_supports_lookup(peer, false);
+                                        }
+                                        let released = consensus.on_lookup_failed(peer, target);
+                                        if !released.is_empty() {
+                                            info!(
+                                                "Too few bootstrap nodes answered for peer {}; adding {} address(es) unconfirmed",
+                                                target, released.len()
+                                            );
+                                            add_released_addresses(&mut swarm, target, released);
+                                        }
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            SwarmEvent::Behaviour(DhtBehaviourEvent::ReadReceipts(ev)) => {
+                                use libp2p::request_response::{Event as RREvent, Message};
+                                match ev {
+                                    RREvent::Message {
+                                        peer,
+                                        message: Message::Request { request, channel, .. },
+                                    } => {
+                                        swarm.behaviour_mut().read_receipts
+                                            .send_response(channel, ReadReceiptAck)
+                                            .unwrap_or_else(|e| debug!("Failed to ack read receipts: {e:?}"));
+                                        // A peer can only report its own reads
+                                        let peer_str = peer.to_string();
+                                        let receipts: Vec<_> = request
+                                            .receipts
+                                            .into_iter()
+                                            .filter(|r| r.reader_peer_id == peer_str)
+                                            .collect();
+                                        if !receipts.is_empty() {
+                                            let _ = event_tx
+                                                .send(DhtEvent::MessagesRead(ReadReceiptBatch { receipts }))
+                                                .await;
+                                        }
+                                    }
+                                    RREvent::OutboundFailure { peer, error, .. } => {
+                                        // Receipts are best effort and not retried
+                                        debug!("Read receipts to {} not delivered: {error:?}", peer);
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            SwarmEvent::Behaviour(DhtBehaviourEvent::CallSignaling(ev)) => {
+                                use libp2p::request_response::{Event as RREvent, Message};
+                                match ev {
+                                    RREvent::Message { peer, message } => match message {
+                                        Message::Request { request, channel, .. } => {
+                                            let peer_str = peer.to_string();
+                                            match request {
+                                                CallRequest::Invite { caller_id, session_id, sdp_offer } => {
+                                                    let admitted = if caller_id != peer_str {
+                                                        Err("caller id does not match the connection".to_string())
+                                                    } else {
+                                                        call_state
+                                                            .lock()
+                                                            .await
+                                                            .start_incoming(&session_id, &peer_str)
+                                                            .map_err(|e| e.to_string())
+                                                    };
+                                                    match admitted {
+                                                        Ok(()) => {
+                                                            info!("Incoming call {} from {}", session_id, peer);
+                                                            call_answer_channels.insert(session_id.clone(), channel);
+                                                            let _ = event_tx
+                                                                .send(DhtEvent::Call(
+                                                                    CallEvent::new(CallEventKind::Incoming, &session_id, &peer_str)
+                                                                        .with_sdp(sdp_offer),
+                                                                ))
+                                                                .await;
+                                                        }
+                                                        Err(reason) => {
+                                                            send_response(&mut swarm.behaviour_mut().call_signaling, channel, CallResponse::Reject { reason })
+                                                                .unwrap_or_else(|e| error!("Failed to send call response: {e:?}"));
+                                                        }
+                                                    }
+                                                }
+                                                CallRequest::Hangup { session_id } => {
+                                                    let ended = {
+                                                        let mut calls = call_state.lock().await;
+                                                        let from_peer = calls
+                                                            .get(&session_id)
+                                                            .is_some_and(|c| c.peer_id == peer_str);
+                                                        from_peer && calls.end(&session_id).is_some()
+                                                    };
+                                                    if ended {
+                                                        // Hanging up while we ring cancels the invite
+                                                        call_answer_channels.remove(&session_id);
+                                                        let _ = event_tx
+                                                            .send(DhtEvent::Call(
+                                                                CallEvent::new(CallEventKind::Ended, &session_id, &peer_str)
+                                                                    .with_reason("remote hung up"),
+                                                            ))
+                                                            .await;
+                                                    }
+                                                    send_response(&mut swarm.behaviour_mut().call_signaling, channel, CallResponse::Ack)
+                                                        .unwrap_or_else(|e| error!("Failed to send call response: {e:?}"));
+                                                }
+                                            }
+                                        }
+                                        // Answer to our invite or hangup
+                                        Message::Response { request_id, response } => {
+                                            if let Some((peer, session_id)) = pending_call_requests.remove(&request_id) {
+                                                let peer_str = peer.to_string();
+                                                let event = match response {
+                                                    CallResponse::Accept { sdp_answer } => {
+                                                        match call_state.lock().await.mark_active(&session_id) {
+                                                            Ok(_) => Some(
+                                                                CallEvent::new(CallEventKind::Accepted, &session_id, &peer_str)
+                                                                    .with_sdp(sdp_answer),
+                                                            ),
+                                                            // We hung up before the answer arrived
+                                                            Err(e) => {
+                                                                debug!("Ignoring call answer: {}", e);
+                                                                None
+                                                            }
+                                                        }
+                                                    }
+                                                    CallResponse::Reject { reason } => {
+                                                        call_state.lock().await.end(&session_id).map(|_| {
+                                                            CallEvent::new(CallEventKind::Rejected, &session_id, &peer_str)
+                                                                .with_reason(reason)
+                                                        })
+                                                    }
+                                                    CallResponse::Ack => None,
+                                                };
+                                                if let Some(event) = event {
+                                                    let _ = event_tx.send(DhtEvent::Call(event)).await;
+                                                }
+                                            }
+                                        }
+                                    },
+                                    RREvent::OutboundFailure { request_id, error, .. } => {
+                                        warn!("Call signaling outbound failure: {error:?}");
+                                        if let Some((peer, session_id)) = pending_call_requests.remove(&request_id) {
+                                            // Only an unanswered invite is still tracked at this point
+                                            if call_state.lock().await.end(&session_id).is_some() {
+                                                let _ = event_tx
+                                                    .send(DhtEvent::Call(
+                                                        CallEvent::new(CallEventKind::Ended, &session_id, &peer.to_string())
+                                                            .with_reason(format!("{error}")),
+                                                    ))
+                                                    .await;
+                                            }
+                                        }
+                                    }
+                                    RREvent::InboundFailure { peer, error, .. } => {
+                                        warn!("Call signaling inbound failure from {}: {error:?}", peer);
+                                        // An invite we never answered timed out: the call was missed
+                                        let missed: Vec<String> = call_answer_channels
+                                            .iter()
+                                            .filter(|(_, channel)| !channel.is_open())
+                                            .map(|(session_id, _)| session_id.clone())
+                                            .collect();
+                                        for session_id in missed {
+                                            call_answer_channels.remove(&session_id);
+                                            let call = call_state.lock().await.end(&session_id);
+                                            if let Some(call) = call {
+                                                let _ = event_tx
+                                                    .send(DhtEvent::Call(
+                                                        CallEvent::new(CallEventKind::Ended, &session_id, &call.peer_id)
+                                                            .with_reason("missed"),
+                                                    ))
+                                                    .await;
+                                            }
+                                        }
+                                    }
+                                    RREvent::ResponseSent { .. } => {}
+                                }
+                            }
                             SwarmEvent::Behaviour(DhtBehaviourEvent::KeyRequest(ev)) => {
                                 use libp2p::request_response::{Event as RREvent, Message};
                                 match ev {
@@ -3578,8 +4946,7 @@ async fn run_dht_node(
                                             // Send response
                                             match result {
                                                 Ok(response) => {
-                                                    swarm.behaviour_mut().key_request
-                                                        .send_response(channel, response)
+                                                    send_response(&mut swarm.behaviour_mut().key_request, channel, response)
                                                         .unwrap_or_else(|e| error!("Failed to send key response: {e:?}"));
                                                 }
                                                 Err(e) => {
@@ -3588,8 +4955,7 @@ async fn run_dht_node(
                                                         encrypted_bundle: None,
                                                         error: Some(e),
                                                     };
-                                                    swarm.behaviour_mut().key_request
-                                                        .send_response(channel, error_response)
+                                                    send_response(&mut swarm.behaviour_mut().key_request, channel, error_response)
                                                         .unwrap_or_else(|e| error!("Failed to send error response: {e:?}"));
                                                 }
                                             }
@@ -3634,7 +5000,8 @@ async fn run_dht_node(
                                     RREvent::ResponseSent { .. } => {}
                                 }
                             }
-                            SwarmEvent::ListenerClosed { reason, .. } if !is_bootstrap => {
+                            SwarmEvent::ListenerClosed { listener_id, reason, .. } if !is_bootstrap => {
+                                relay_listeners.remove(&listener_id);
                                 if !is_bootstrap{
                                 if reason.is_ok() {
                                     trace!("ListenerClosed Ok; ignoring");
@@ -3654,6 +5021,12 @@ async fn run_dht_node(
                                     }
                                 }}
                             }
+                            SwarmEvent::ExpiredListenAddr { address, .. } if !is_bootstrap => {
+                                debug!("Listen address expired: {}", address);
+                                if let Some(scheduler) = &nat_scheduler {
+                                    scheduler.notify_interface_change();
+                                }
+                            }
                             _ => {}
                         }
                     } else {
@@ -3663,13 +5036,141 @@ async fn run_dht_node(
                 }
     }
 
+    // Stop accepting connections, then close the open ones so peers and
+    // relays drop them (and our circuit reservations) now instead of when
+    // they time out
+    for listener in listeners.drain() {
+        swarm.remove_listener(listener);
+    }
+    let open_peers: Vec<PeerId> = swarm.connected_peers().copied().collect();
+    for peer in open_peers {
+        let _ = swarm.disconnect_peer_id(peer);
+    }
+    let _ = tokio::time::timeout(CONNECTION_CLOSE_TIMEOUT, async {
+        while swarm.network_info().num_peers() > 0 {
+            if swarm.next().await.is_none() {
+                break;
+            }
+        }
+    })
+    .await;
+    if let Some(cache) = discovery_cache.as_deref() {
+        if let Err(e) = cache.checkpoint() {
+            warn!("Failed to checkpoint the peer address cache: {}", e);
+        }
+    }
+
     connected_peers.lock().await.clear();
+    connected_addrs.lock().await.clear();
     info!("DHT node task exiting");
     if let Some(ack) = shutdown_ack {
         let _ = ack.send(());
     }
 }
 
+/// Mesh size of every topic this node is subscribed to
+fn mesh_sizes(swarm: &Swarm<DhtBehaviour>) -> Vec<(TopicHash, usize)> {
+    let Some(gossipsub) = swarm.behaviour().gossipsub.as_ref() else {
+        return Vec::new();
+    };
+    gossipsub
+        .topics()
+        .map(|topic| (topic.clone(), gossipsub.mesh_peers(topic).count()))
+        .collect()
+}
+
+/// Sample the meshes and report the topics that just degraded
+async fn observe_mesh(
+    swarm: &Swarm<DhtBehaviour>,
+    mesh_health: &mut MeshHealthMonitor,
+    event_tx: &mpsc::Sender<DhtEvent>,
+) {
+    for degraded in mesh_health.update(mesh_sizes(swarm)) {
+        warn!(
+            topic = %degraded.topic,
+            mesh_peers = degraded.mesh_peers,
+            mesh_n_low = degraded.mesh_n_low,
+            "GossipSub mesh below mesh_n_low"
+        );
+        let _ = event_tx.send(DhtEvent::MeshHealthDegraded(degraded)).await;
+    }
+}
+
+/// Publish this node's announcement; with no mesh peers yet it stays pending
+/// and is retried when a peer subscribes to the announce topic
+async fn publish_announcement(
+    swarm: &mut Swarm<DhtBehaviour>,
+    metrics: &Mutex<DhtMetrics>,
+    peer_events: &Mutex<PeerEventLog>,
+    announcer: &mut NodeAnnouncementBroadcast,
+) {
+    match publish_gossip(swarm, metrics, peer_events, announce_topic(), &announcer.announcement().encode()).await {
+        Ok(_) => announcer.mark_delivered(),
+        Err(e) => debug!("Node announcement not published: {e:?}"),
+    }
+}
+
+/// Presence is best effort: with no subscribed peers there is nobody to tell
+async fn publish_presence(
+    swarm: &mut Swarm<DhtBehaviour>,
+    metrics: &Mutex<DhtMetrics>,
+    peer_events: &Mutex<PeerEventLog>,
+    event: &TypingEvent,
+) {
+    if let Err(e) = publish_gossip(swarm, metrics, peer_events, presence_topic(), &event.encode()).await {
+        debug!("Presence event not published: {e:?}");
+    }
+}
+
+/// Publish `payload`, compressed when it is large, and note it in the event
+/// timeline of every peer subscribed to the topic
+async fn publish_gossip(
+    swarm: &mut Swarm<DhtBehaviour>,
+    metrics: &Mutex<DhtMetrics>,
+    peer_events: &Mutex<PeerEventLog>,
+    topic: gossipsub::IdentTopic,
+    payload: &[u8],
+) -> Result<gossipsub::MessageId, gossipsub::PublishError> {
+    let (data, sample) = MessageCompressor::default().compress(payload);
+    let gossip = swarm
+        .behaviour_mut()
+        .gossipsub
+        .as_mut()
+        .ok_or(gossipsub::PublishError::InsufficientPeers)?;
+    let topic_name = topic.to_string();
+    let topic_hash = topic.hash();
+    let bytes = data.len();
+    let id = gossip.publish(topic, data)?;
+    diagnostics::sent_message(&topic_name, bytes);
+    let recipients: Vec<PeerId> = gossip
+        .all_peers()
+        .filter(|(_, topics)| topics.contains(&&topic_hash))
+        .map(|(peer, _)| *peer)
+        .collect();
+    let mut log = peer_events.lock().await;
+    for peer in recipients {
+        log.record(peer, PeerEvent::MessageSent(topic_name.clone(), bytes));
+    }
+    drop(log);
+    if let Some(sample) = sample {
+        metrics.lock().await.record_gossip_compression(sample);
+    }
+    Ok(id)
+}
+
+/// Gossip is only forwarded once validated here; ignored messages are
+/// dropped without penalising the peer that sent them
+fn report_gossip(
+    swarm: &mut Swarm<DhtBehaviour>,
+    message_id: &gossipsub::MessageId,
+    source: &PeerId,
+    acceptance: gossipsub::MessageAcceptance,
+) {
+    if let Some(gossip) = swarm.behaviour_mut().gossipsub.as_mut() {
+        let _ = gossip.report_message_validation_result(message_id, source, acceptance);
+    }
+}
+
 // Helper function to convert Multiaddr to SocketAddr
 fn addr_to_socket_addr(addr: &libp2p::Multiaddr) -> Option<SocketAddr> {
     use libp2p::multiaddr::Protocol;
@@ -3804,6 +5305,77 @@ fn filter_relay_candidates(
     out
 }
 
+/// Every 15 s, trim the file caches to their memory budget shares and
+/// measure them with the transfer buffers into `usage`, warning when the
+/// whole budget stays full. Runs beside the swarm task, so sizing the
+/// metadata never holds up swarm events.
+async fn trim_file_caches(
+    file_metadata_cache: Arc<Mutex<HashMap<String, FileMetadata>>>,
+    seeder_heartbeats_cache: Arc<Mutex<HashMap<String, FileHeartbeatCacheEntry>>>,
+    file_heartbeat_state: Arc<Mutex<HashMap<String, FileHeartbeatState>>>,
+    usage: Arc<MemoryUsage>,
+    event_tx: mpsc::Sender<DhtEvent>,
+    shutdown: CancellationToken,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(15));
+    let mut pressure = BudgetPressure::default();
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.cancelled() => return,
+        }
+        // Files this node seeds are never dropped
+        let provided: HashSet<String> = file_heartbeat_state.lock().await.keys().cloned().collect();
+        let share = |cache| usage.budget().map_or(u64::MAX, |budget| budget.share(cache));
+        let keep = |hash: &String| provided.contains(hash);
+        let (metadata_bytes, evicted) = memory_budget::evict_to_fit(
+            &mut *file_metadata_cache.lock().await,
+            share(CacheKind::FileMetadata),
+            memory_budget::file_metadata_bytes,
+            keep,
+        );
+        if evicted > 0 {
+            debug!("Evicted {} file metadata entries over the memory budget", evicted);
+        }
+        usage.store(CacheKind::FileMetadata, metadata_bytes);
+        let (heartbeat_bytes, evicted) = memory_budget::evict_to_fit(
+            &mut *seeder_heartbeats_cache.lock().await,
+            share(CacheKind::SeederHeartbeats),
+            |entry| {
+                memory_budget::json_bytes(&entry.metadata)
+                    + (entry.heartbeats.len() * std::mem::size_of::<SeederHeartbeat>()) as u64
+                    + entry.heartbeats.iter().map(|hb| hb.peer_id.len() as u64).sum::<u64>()
+            },
+            keep,
+        );
+        if evicted > 0 {
+            debug!("Evicted {} seeder heartbeat entries over the memory budget", evicted);
+        }
+        usage.store(CacheKind::SeederHeartbeats, heartbeat_bytes);
+        usage.store(CacheKind::TransferBuffers, memory_budget::in_flight_bytes());
+        if let Some(warning) = pressure.observe(&usage.report()) {
+            warn!("{}", warning);
+            let _ = event_tx.send(DhtEvent::Warning(warning)).await;
+        }
+    }
+}
+
+/// The lock-free statistics, with what the swarm itself knows
+fn current_network_stats(swarm: &mut Swarm<DhtBehaviour>, counters: &StatsCounters) -> NetworkStats {
+    let peers_connected = swarm.network_info().num_peers();
+    let relay_reservation = swarm
+        .listeners()
+        .find_map(extract_relay_peer)
+        .map(|peer| peer.to_string());
+    let dht_table_size: usize = swarm
+        .behaviour_mut()
+        .kademlia
+        .kbuckets()
+        .map(|bucket| bucket.num_entries())
+        .sum();
+    counters.snapshot(peers_connected, relay_reservation, dht_table_size as u64)
+}
+
 fn extract_relay_peer(address: &Multiaddr) -> Option<PeerId> {
     use libp2p::multiaddr::Protocol;
 
@@ -3980,6 +5552,116 @@ fn heartbeats_to_peer_list(entries: &[SeederHeartbeat]) -> Vec<String> {
     entries.iter().map(|hb| hb.peer_id.clone()).collect()
 }
 
+/// Drop `peer_id` from the seeder list and heartbeats of a file's metadata
+/// record, leaving every other seeder in place
+fn remove_seeder(metadata: &mut serde_json::Value, peer_id: &str) {
+    if let Some(seeders) = metadata.get_mut("seeders").and_then(|v| v.as_array_mut()) {
+        seeders.retain(|seeder| seeder.as_str() != Some(peer_id));
+    }
+    if let Some(heartbeats) = metadata
+        .get_mut("seederHeartbeats")
+        .and_then(|v| v.as_array_mut())
+    {
+        heartbeats.retain(|hb| hb.get("peerId").and_then(|v| v.as_str()) != Some(peer_id));
+    }
+}
+
+/// Dial a bootstrap node and add it to the routing table if it names its peer
+fn dial_bootstrap_node(swarm: &mut Swarm<DhtBehaviour>, addr: Multiaddr) {
+    if let Err(e) = swarm.dial(addr.clone()) {
+        warn!("✗ Failed to dial bootstrap {}: {}", addr, e);
+        return;
+    }
+    if let Some(peer_id) = addr.iter().find_map(|p| match p {
+        Protocol::P2p(peer) => Some(peer),
+        _ => None,
+    }) {
+        swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
+    }
+}
+
+/// Lookups and stores started by `put_replicated` or the re-publish tick
+async fn handle_replication_query(
+    swarm: &mut Swarm<DhtBehaviour>,
+    replication: &Arc<Mutex<RecordReplicator>>,
+    id: kad::QueryId,
+    result: QueryResult,
+) {
+    let mut replication = replication.lock().await;
+    let now = Instant::now();
+    match result {
+        QueryResult::GetClosestPeers(result) => {
+            let peers = match result {
+                Ok(ok) => ok.peers,
+                // Store on whatever was found before the timeout
+                Err(kad::GetClosestPeersError::Timeout { peers, .. }) => peers,
+            };
+            let closest = peers.into_iter().map(|peer| peer.peer_id).collect();
+            if let Some((record, targets)) = replication.lookup_finished(&id, closest, now) {
+                let store_id =
+                    swarm
+                        .behaviour_mut()
+                        .kademlia
+                        .put_record_to(record, targets.into_iter(), kad::Quorum::All);
+                replication.store_started(&id, store_id);
+            }
+        }
+        QueryResult::PutRecord(Ok(_)) => replication.store_finished(&id, None, now),
+        QueryResult::PutRecord(Err(err)) => {
+            let stored = match err {
+                kad::PutRecordError::QuorumFailed { success, .. }
+                | kad::PutRecordError::Timeout { success, .. } => success,
+            };
+            debug!("Replicated record reached {} peers", stored.len());
+            replication.store_finished(&id, Some(stored), now);
+        }
+        other => debug!("Unexpected replication query result: {:?}", other),
+    }
+}
+
+/// Relay limits; infra nodes exist to relay, so theirs are far higher
+fn relay_server_config(infra_mode: bool) -> relay::Config {
+    if !infra_mode {
+        return relay::Config::default();
+    }
+    relay::Config {
+        max_reservations: 1024,
+        max_reservations_per_peer: 8,
+        max_circuits: 512,
+        max_circuits_per_peer: 16,
+        max_circuit_duration: Duration::from_secs(10 * 60),
+        max_circuit_bytes: 64 * 1024 * 1024,
+        ..relay::Config::default()
+    }
+}
+
+/// Answer a request on a protocol that is off on infra nodes; requests only
+/// arrive while it is on, so `Err` here means the requester is gone
+fn send_response<C>(
+    behaviour: &mut toggle::Toggle<rr::Behaviour<C>>,
+    channel: rr::ResponseChannel<C::Response>,
+    response: C::Response,
+) -> Result<(), C::Response>
+where
+    C: rr::Codec + Clone + Send + 'static,
+{
+    match behaviour.as_mut() {
+        Some(behaviour) => behaviour.send_response(channel, response),
+        None => Err(response),
+    }
+}
+
+/// Store a block for Bitswap; infra nodes have no Bitswap and store nothing
+fn insert_block(swarm: &mut Swarm<DhtBehaviour>, cid: Cid, data: Vec<u8>) -> Result<(), String> {
+    match swarm.behaviour_mut().bitswap.as_mut() {
+        Some(bitswap) => bitswap
+            .insert_block::<MAX_MULTIHASH_LENGHT>(cid, data)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        None => Err("file storage is off in infra mode".to_string()),
+    }
+}
+
 fn extract_bootstrap_peer_ids(bootstrap_nodes: &[String]) -> HashSet<PeerId> {
     use libp2p::multiaddr::Protocol;
     use libp2p::{Multiaddr, PeerId};
@@ -4015,6 +5697,9 @@ async fn handle_kademlia_event(
     pending_dht_queries: &Arc<
         Mutex<HashMap<kad::QueryId, oneshot::Sender<Result<Option<Vec<u8>>, String>>>>,
     >,
+    bootstrap_consensus: &mut Option<MultiBootstrapConsensus>,
+    peer_lookups: &mut HashMap<rr::OutboundRequestId, PeerId>,
+    replication: &Arc<Mutex<RecordReplicator>>,
 ) {
     match event {
         KademliaEvent::RoutingUpdated { peer, .. } => {
@@ -4027,6 +5712,10 @@ async fn handle_kademlia_event(
             debug!("Peer {} became routable", peer);
         }
         KademliaEvent::OutboundQueryProgressed { id, result, .. } => {
+            if replication.lock().await.is_query(&id) {
+                handle_replication_query(swarm, replication, id, result).await;
+                return;
+            }
             match result {
                 QueryResult::GetRecord(Ok(ok)) => match ok {
                     GetRecordOk::FoundRecord(peer_record) => {
@@ -4313,6 +6002,10 @@ async fn handle_kademlia_event(
                                         )
                                         .unwrap_or(None)
                                     }),
+                                    web_seeds: metadata_json.get("web_seeds").and_then(|v| {
+                                        serde_json::from_value::<Option<WebSeeds>>(v.clone())
+                                            .unwrap_or(None)
+                                    }),
                                     ed2k_sources: metadata_json.get("ed2k_sources").and_then(|v| {
                                         serde_json::from_value::<Option<Vec<Ed2kSourceInfo>>>(
                                             v.clone(),
@@ -4479,13 +6172,40 @@ async fn handle_kademlia_event(
                             }
 
                             // Try to connect using parallel address strategy for better success rate
-                            let reachable_addrs: Vec<_> = peer_info.addrs.iter()
+                            let mut reachable_addrs: Vec<_> = peer_info.addrs.iter()
                                 .filter(|addr| ma_plausibly_reachable(addr))
+                                .cloned()
                                 .collect();
 
-                            if !reachable_addrs.is_empty() {
-                                info!(
-                                    "Attempting {} parallel connections to peer {}",
+                            if let Some(consensus) =
+                                bootstrap_consensus.as_mut().filter(|_| !reachable_addrs.is_empty())
+                            {
+                                match consensus.admit(peer_info.peer_id, reachable_addrs, Instant::now()) {
+                                    Admission::Admitted(addrs) => reachable_addrs = addrs,
+                                    Admission::Quarantined { ask } => {
+                                        debug!(
+                                            "Quarantined addresses of {} until bootstrap nodes confirm it",
+                                            peer_info.peer_id
+                                        );
+                                        let request = PeerLookupRequest {
+                                            peer_id: peer_info.peer_id.to_string(),
+                                        };
+                                        for bootstrap in ask {
+                                            let request_id = swarm
+                                                .behaviour_mut()
+                                                .peer_lookup
+                                                .send_request(&bootstrap, request.clone());
+                                            peer_lookups.insert(request_id, peer_info.peer_id);
+                                            diagnostics::sent_request("PeerLookup", &bootstrap);
+                                        }
+                                        continue;
+                                    }
+                                }
+                            }
+
+                            if !reachable_addrs.is_empty() {
+                                info!(
+                                    "Attempting {} parallel connections to peer {}",
                                     reachable_addrs.len(), peer_info.peer_id
                                 );
 
@@ -4494,7 +6214,7 @@ async fn handle_kademlia_event(
                                     swarm
                                         .behaviour_mut()
                                         .kademlia
-                                        .add_address(&peer_info.peer_id, (*addr).clone());
+                                        .add_address(&peer_info.peer_id, addr.clone());
                                 }
 
                                 // Dial all reachable addresses in parallel - libp2p will use fastest
@@ -4648,6 +6368,7 @@ async fn handle_kademlia_event(
                                                     price: metadata_json.get("price").and_then(|v| v.as_f64()).unwrap_or(0.0),
                                                     uploader_address: metadata_json.get("uploader_address").and_then(|v| v.as_str()).map(|s| s.to_string()),
                                                     http_sources: metadata_json.get("http_sources").and_then(|v| {serde_json::from_value::<Option<Vec<HttpSourceInfo>>>(v.clone()).unwrap_or(None)}),
+                                                    web_seeds: metadata_json.get("web_seeds").and_then(|v| serde_json::from_value::<Option<WebSeeds>>(v.clone()).ok()).unwrap_or(None),
                                                     ..Default::default()
                                                 };
                                                 info!("Emitting file discovery event from provider query with seeder_heartbeats_cache");
@@ -4741,10 +6462,45 @@ async fn handle_identify_event(
     peer_selection: &Arc<Mutex<PeerSelectionService>>,
     relay_capable_peers: Arc<Mutex<HashMap<PeerId, Vec<Multiaddr>>>>,
     local_peer_id: &PeerId,
+    peer_events: &Arc<Mutex<PeerEventLog>>,
+    discovery_cache: Option<&LocalDiscoveryCache>,
+    relay_listeners: &mut HashMap<ListenerId, Multiaddr>,
 ) {
     match event {
         IdentifyEvent::Received { peer_id, info, .. } => {
             info!("Identified peer {}: {:?}", peer_id, info.protocol_version);
+            // Drop peers running an incompatible Chiral release before they
+            // enter the routing table
+            if let Some(remote_version) = compatibility::parse_agent_version(&info.agent_version) {
+                let local_version = compatibility::SemVer::current();
+                if let compatibility::CompatibilityResult::Incompatible { supported, .. } =
+                    compatibility::check_compatibility(local_version, remote_version)
+                {
+                    warn!(
+                        "Disconnecting peer {} ({}): version {} is outside supported range {}..={}",
+                        peer_id,
+                        compatibility::VERSION_MISMATCH,
+                        remote_version,
+                        supported.min,
+                        supported.max
+                    );
+                    peer_events.lock().await.record(
+                        peer_id,
+                        PeerEvent::ProtocolNegotiationFailed(info.agent_version.clone()),
+                    );
+                    swarm.behaviour_mut().kademlia.remove_peer(&peer_id);
+                    let _ = swarm.disconnect_peer_id(peer_id);
+                    let _ = event_tx
+                        .send(DhtEvent::PeerVersionIncompatible {
+                            peer_id: peer_id.to_string(),
+                            local_version: local_version.to_string(),
+                            remote_version: remote_version.to_string(),
+                            reason: compatibility::VERSION_MISMATCH.to_string(),
+                        })
+                        .await;
+                    return;
+                }
+            }
             // Add identified peer to Kademlia routing table
             if info.protocol_version != EXPECTED_PROTOCOL_VERSION {
                 warn!(
@@ -4753,27 +6509,48 @@ async fn handle_identify_event(
                     info.protocol_version,
                     EXPECTED_PROTOCOL_VERSION
                 );
+                peer_events.lock().await.record(
+                    peer_id,
+                    PeerEvent::ProtocolNegotiationFailed(info.protocol_version.clone()),
+                );
                 swarm.behaviour_mut().kademlia.remove_peer(&peer_id);
             } else {
-                for addr in info.listen_addrs.clone() {
-                    if not_loopback(&addr) {
-                        swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
+                let routable: Vec<Multiaddr> = info
+                    .listen_addrs
+                    .iter()
+                    .filter(|addr| not_loopback(addr))
+                    .cloned()
+                    .collect();
+                if let Some(cache) = discovery_cache {
+                    if let Err(e) = cache.record_addresses(&peer_id, &routable) {
+                        warn!("Failed to cache addresses for {}: {}", peer_id, e);
                     }
                 }
+                for addr in routable {
+                    swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
+                }
             }
             // Skip processing our own peer info to prevent self-connection attempts
             if &peer_id == local_peer_id {
                 return;
             }
 
-            let hop_proto = "/libp2p/circuit/relay/0.2.0/hop";
+            let hop_proto = protocol::RELAY_HOP_PROTOCOL;
             let supports_relay = info
                 .protocols
                 .clone()
                 .iter()
                 .any(|p| p.as_ref() == hop_proto);
 
-            if supports_relay {
+            // AutoNAT (which the relays also serve) found us reachable, so a
+            // reservation would only take up relay capacity
+            let publicly_reachable =
+                metrics.lock().await.reachability_state == NatReachabilityState::Public;
+            if supports_relay && publicly_reachable {
+                debug!("Publicly reachable, not reserving a relay slot on {}", peer_id);
+            }
+
+            if supports_relay && !publicly_reachable {
                 // Store this peer as relay-capable with its listen addresses
                 let reachable_addrs: Vec<Multiaddr> = info
                     .listen_addrs
@@ -4809,13 +6586,13 @@ async fn handle_identify_event(
                         .with(Protocol::P2pCircuit);
 
                     match swarm.listen_on(relay_addr.clone()) {
-                        Ok(_) => {
+                        Ok(listener) => {
                             info!("Success: Listening on relay address {}: {}", i + 1, addr);
 
                             // Advertise this circuit address to others
-                            swarm.add_external_address(
-                                relay_addr.with(Protocol::P2p(*local_peer_id)),
-                            );
+                            let circuit_addr = relay_addr.with(Protocol::P2p(*local_peer_id));
+                            swarm.add_external_address(circuit_addr.clone());
+                            relay_listeners.insert(listener, circuit_addr);
 
                             success = true;
                             break; // Exit the loop immediately on success
@@ -4910,9 +6687,11 @@ async fn handle_mdns_event(
     swarm: &mut Swarm<DhtBehaviour>,
     event_tx: &mpsc::Sender<DhtEvent>,
     local_peer_id: &PeerId,
+    discovery_cache: Option<&LocalDiscoveryCache>,
 ) {
     match event {
         MdnsEvent::Discovered(list) => {
+            let mut announced: HashMap<PeerId, Vec<Multiaddr>> = HashMap::new();
             let mut discovered: HashMap<PeerId, Vec<String>> = HashMap::new();
             for (peer_id, multiaddr) in list {
                 info!("mDNS discovered peer {} at {}", peer_id, multiaddr);
@@ -4920,6 +6699,7 @@ async fn handle_mdns_event(
                 if peer_id == *local_peer_id {
                     continue;
                 }
+                announced.entry(peer_id).or_default().push(multiaddr.clone());
                 match swarm.dial(multiaddr.clone()) {
                     Ok(_) => {
                         swarm
@@ -4934,6 +6714,32 @@ async fn handle_mdns_event(
                     Err(e) => warn!("✗ Failed to dial bootstrap {}: {}", multiaddr, e),
                 }
             }
+            // mDNS may only have resolved part of a LAN peer's addresses; add
+            // the rest we learned earlier so the dial can fall back to them
+            if let Some(cache) = discovery_cache {
+                use libp2p::swarm::dial_opts::{DialOpts as SwarmDialOpts, PeerCondition};
+                for (peer_id, mdns_addrs) in &announced {
+                    let extra = cache.supplement(peer_id, mdns_addrs);
+                    if extra.is_empty() {
+                        continue;
+                    }
+                    debug!("Adding {} cached address(es) for mDNS peer {}", extra.len(), peer_id);
+                    for addr in &extra {
+                        swarm.behaviour_mut().kademlia.add_address(peer_id, addr.clone());
+                    }
+                    let opts = SwarmDialOpts::peer_id(*peer_id)
+                        .addresses(extra.clone())
+                        .condition(PeerCondition::DisconnectedAndNotDialing)
+                        .build();
+                    if let Err(e) = swarm.dial(opts) {
+                        debug!("Cached-address dial to {} skipped: {}", peer_id, e);
+                    }
+                    discovered
+                        .entry(*peer_id)
+                        .or_default()
+                        .extend(extra.iter().map(|a| a.to_string()));
+                }
+            }
             for (peer_id, addresses) in discovered {
                 let _ = event_tx
                     .send(DhtEvent::PeerDiscovered {
@@ -4955,6 +6761,24 @@ async fn handle_mdns_event(
     }
 }
 
+fn start_kad_query(swarm: &mut Swarm<DhtBehaviour>, query: KadQuery) -> Option<kad::QueryId> {
+    match query {
+        KadQuery::Bootstrap => match swarm.behaviour_mut().kademlia.bootstrap() {
+            Ok(query_id) => Some(query_id),
+            Err(e) => {
+                debug!("Kademlia bootstrap not started: {:?}", e);
+                None
+            }
+        },
+        KadQuery::ClosestPeers(peer_id) => Some(swarm.behaviour_mut().kademlia.get_closest_peers(peer_id)),
+        KadQuery::Republish(key) => Some(swarm.behaviour_mut().kademlia.get_closest_peers(key.to_vec())),
+        KadQuery::Introductions(_) => {
+            let local_peer_id = *swarm.local_peer_id();
+            Some(swarm.behaviour_mut().kademlia.get_closest_peers(local_peer_id))
+        }
+    }
+}
+
 async fn handle_ping_event(event: PingEvent) {
     match event {
         ping::Event { result, .. } => {
@@ -4968,7 +6792,8 @@ async fn handle_autonat_client_event(
     event: v2::client::Event,
     metrics: &Arc<Mutex<DhtMetrics>>,
     event_tx: &mpsc::Sender<DhtEvent>,
-) {
+    nat_scheduler: Option<&AutoNATProbeScheduler>,
+) -> Option<NodeReachabilityUpdate> {
     let v2::client::Event {
         tested_addr,
         server,
@@ -4978,7 +6803,7 @@ async fn handle_autonat_client_event(
 
     let mut metrics_guard = metrics.lock().await;
     if !metrics_guard.autonat_enabled {
-        return;
+        return None;
     }
     swarm.add_external_address(tested_addr.clone());
     info!(
@@ -4995,6 +6820,7 @@ async fn handle_autonat_client_event(
                 server = %server_str,
                 address = %addr_str,
                 bytes = bytes_sent,
+                outcome = "success",
                 "AutoNAT probe succeeded"
             );
             (
@@ -5011,6 +6837,7 @@ async fn handle_autonat_client_event(
                 address = %addr_str,
                 error = %err_msg,
                 bytes = bytes_sent,
+                outcome = "failure",
                 "AutoNAT probe failed"
             );
             (
@@ -5022,7 +6849,11 @@ async fn handle_autonat_client_event(
         }
     };
 
+    let previous_state = metrics_guard.reachability_state;
     metrics_guard.update_reachability(state, summary.clone());
+    if let Some(scheduler) = nat_scheduler {
+        scheduler.record_result(state);
+    }
     let nat_state = metrics_guard.reachability_state;
     let confidence = metrics_guard.reachability_confidence;
     let last_error = metrics_guard.last_reachability_error.clone();
@@ -5036,6 +6867,11 @@ async fn handle_autonat_client_event(
             summary,
         })
         .await;
+
+    (nat_state != previous_state).then(|| NodeReachabilityUpdate {
+        is_public: nat_state == NatReachabilityState::Public,
+        external_addr: (nat_state == NatReachabilityState::Public).then_some(addr_str),
+    })
 }
 
 async fn handle_dcutr_event(
@@ -5066,7 +6902,8 @@ async fn handle_dcutr_event(
                 0.0
             };
             info!(
-                peer = %remote_peer_id,
+                peer_id = %remote_peer_id,
+                outcome = "success",
                 successes = metrics_guard.dcutr_hole_punch_successes,
                 attempts = metrics_guard.dcutr_hole_punch_attempts,
                 success_rate = format!("{:.1}%", success_rate),
@@ -5095,7 +6932,8 @@ async fn handle_dcutr_event(
             // Only log as warning if this is a repeated failure
             if failures % 3 == 0 {
                 warn!(
-                    peer = %remote_peer_id,
+                    peer_id = %remote_peer_id,
+                    outcome = "failure",
                     error = %error,
                     failures = failures,
                     success_rate = format!("{:.1}%", success_rate),
@@ -5103,7 +6941,8 @@ async fn handle_dcutr_event(
                 );
             } else {
                 debug!(
-                    peer = %remote_peer_id,
+                    peer_id = %remote_peer_id,
+                    outcome = "failure",
                     error = %error,
                     "DCUtR: hole-punch attempt failed, using relay fallback"
                 );
@@ -5126,10 +6965,12 @@ async fn handle_upnp_event(
     event: upnp::Event,
     swarm: &mut Swarm<DhtBehaviour>,
     event_tx: &mpsc::Sender<DhtEvent>,
+    port_forwarding: &PortForwardingMonitor,
 ) {
     match event {
         upnp::Event::NewExternalAddr(addr) => {
             info!("🌐 UPnP: Successfully mapped external address: {}", addr);
+            port_forwarding.on_upnp_mapped(&addr);
             
             // Add the external address to the swarm
             swarm.add_external_address(addr.clone());
@@ -5144,6 +6985,7 @@ async fn handle_upnp_event(
         }
         upnp::Event::ExpiredExternalAddr(addr) => {
             warn!("⏰ UPnP: External address expired: {}", addr);
+            port_forwarding.on_upnp_lost();
             
             let _ = event_tx
                 .send(DhtEvent::Warning(format!(
@@ -5154,6 +6996,7 @@ async fn handle_upnp_event(
         }
         upnp::Event::GatewayNotFound => {
             warn!("⚠️  UPnP: No UPnP gateway found on network");
+            port_forwarding.on_upnp_lost();
             warn!("    - Make sure your router supports UPnP/IGD");
             warn!("    - Check if UPnP is enabled in router settings");
             warn!("    - Falling back to relay connections");
@@ -5166,6 +7009,7 @@ async fn handle_upnp_event(
         }
         upnp::Event::NonRoutableGateway => {
             warn!("⚠️  UPnP: Gateway is not routable");
+            port_forwarding.on_upnp_lost();
             warn!("    - Your router may be behind another NAT (carrier-grade NAT)");
             warn!("    - Direct connections may not be possible");
             
@@ -5306,6 +7150,29 @@ impl Socks5Transport {
     }
 }
 
+/// The keypair of a node: derived from SHA-256(`secret`) so the peer id is
+/// stable, or a fresh random one without a secret
+pub fn keypair_from_secret(secret: Option<&str>) -> Result<identity::Keypair, identity::DecodingError> {
+    let Some(secret) = secret else {
+        return Ok(identity::Keypair::generate_ed25519());
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(secret.as_bytes());
+    let digest = hasher.finalize();
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(&digest[..32]);
+    identity::Keypair::ed25519_from_bytes(seed)
+}
+
+/// Noise configuration with `prologue` mixed into the handshake.
+///
+/// Both sides must use the same prologue or the handshake fails, which keeps
+/// sessions negotiated by other applications from being accepted
+/// (`SwarmConfig::noise_prologue`).
+pub fn noise_config(keypair: &identity::Keypair, prologue: &[u8]) -> Result<noise::Config, noise::Error> {
+    Ok(noise::Config::new(keypair)?.with_prologue(prologue.to_vec()))
+}
+
 /// Build a libp2p transport, optionally tunneling through a SOCKS5 proxy.
 /// - Output type is unified to (PeerId, StreamMuxerBox).
 /// - Dial preference: Relay first, then Direct TCP (or SOCKS5 TCP if proxy is set).
@@ -5313,6 +7180,7 @@ pub fn build_transport_with_relay(
     keypair: &identity::Keypair,
     relay_transport: relay::client::Transport,
     proxy_address: Option<String>,
+    noise_prologue: &[u8],
 ) -> Result<Boxed<(PeerId, StreamMuxerBox)>, Box<dyn Error>> {
     use libp2p::{
         core::{muxing::StreamMuxerBox, transport::Boxed, upgrade::Version},
@@ -5321,7 +7189,7 @@ pub fn build_transport_with_relay(
     use std::{io, net::SocketAddr, time::Duration};
 
     // === Upgrade stack for direct TCP/SOCKS5 paths ===
-    let noise_cfg = noise::Config::new(keypair)?;
+    let noise_cfg = noise_config(keypair, noise_prologue)?;
     let yamux_cfg = yamux::Config::default();
 
     // TCP/SOCKS5 → (PeerId, StreamMuxerBox)
@@ -5406,10 +7274,14 @@ impl DhtService {
 // Public API for the DHT
 pub struct DhtService {
     cmd_tx: mpsc::Sender<DhtCommand>,
+    /// Events raised outside the swarm task, such as quality drops after a
+    /// failed transfer
+    event_tx: mpsc::Sender<DhtEvent>,
     event_rx: Arc<Mutex<mpsc::Receiver<DhtEvent>>>,
     peer_id: String,
     connected_peers: Arc<Mutex<HashSet<PeerId>>>,
-    connected_addrs: HashMap<PeerId, Vec<Multiaddr>>,
+    /// Remote address of each open connection, per connected peer
+    connected_addrs: Arc<Mutex<HashMap<PeerId, Vec<Multiaddr>>>>,
     metrics: Arc<Mutex<DhtMetrics>>,
     pending_echo: Arc<Mutex<HashMap<rr::OutboundRequestId, PendingEcho>>>,
     pending_searches: Arc<Mutex<HashMap<String, Vec<PendingSearch>>>>,
@@ -5438,6 +7310,43 @@ pub struct DhtService {
     file_heartbeat_state: Arc<Mutex<HashMap<String, FileHeartbeatState>>>,
     seeder_heartbeats_cache: Arc<Mutex<HashMap<String, FileHeartbeatCacheEntry>>>,
     pending_heartbeat_updates: Arc<Mutex<HashSet<String>>>,
+    peer_events: Arc<Mutex<PeerEventLog>>,
+    nat_scheduler: Option<AutoNATProbeScheduler>,
+    /// Stops the AutoNAT probe scheduler, port forwarding and cache
+    /// trimming tasks
+    nat_probe_shutdown: CancellationToken,
+    /// UPnP and NAT-PMP status, shared with the swarm task
+    port_forwarding: PortForwardingMonitor,
+    /// Records stored with `put_replicated` and the peers holding them
+    replication: Arc<Mutex<RecordReplicator>>,
+    incoming_file_transfers: Arc<Mutex<IncomingFileTransfers>>,
+    call_state: Arc<Mutex<CallStateManager>>,
+    typing: Arc<Mutex<TypingIndicator>>,
+    /// Latest capability announcement from each node on the mesh
+    node_announcements: Arc<Mutex<NodeAnnouncementStore>>,
+    /// Last connection quality of each peer, shared with the swarm task
+    connection_quality: Arc<Mutex<ConnectionQualityClassifier>>,
+    /// Gossip messages dropped on arrival, shared with the swarm task
+    topic_filter: Arc<Mutex<TopicFilter>>,
+    /// Peers found through each bootstrap node, shared with the swarm task
+    bootstrap_contributions: Arc<Mutex<BootstrapContributionTracker>>,
+    /// `SwarmConfig::send_read_receipts`
+    send_read_receipts: bool,
+    /// `SwarmConfig::compress_transfers`
+    compress_transfers: bool,
+    /// Bytes sent in direct transfers, logical and on the wire
+    sent_compression: Arc<Mutex<CompressionStats>>,
+    /// Transport bandwidth counters; read-only once the swarm is built
+    bandwidth_metrics: Arc<libp2p::metrics::Registry>,
+    role: NodeRole,
+    /// Shares its rules with the relay server's circuit limiter
+    relay_consent: RelayConsent,
+    connection_filter: IncomingConnectionFilter,
+    /// Published by the swarm task every `SAMPLE_INTERVAL`
+    stats_counters: Arc<StatsCounters>,
+    peer_discovery: broadcast::Sender<PeerDiscoveryEvent>,
+    /// Cache sizes measured by the swarm task every 15 s
+    memory_usage: Arc<MemoryUsage>,
 }
 use memmap2::MmapMut;
 use std::fs::OpenOptions;
@@ -5673,7 +7582,12 @@ impl DhtService {
         // Convert chunk size from KB to bytes
         let chunk_size = chunk_size_kb.unwrap_or(256) * 1024; // Default 256 KB
         let cache_size = cache_size_mb.unwrap_or(1024); // Default 1024 MB
-        let blockstore = if let Some(path) = blockstore_db_path {
+        let chiral_config = ChiralConfig::from_env();
+        let swarm_config = chiral_config.swarm.clone();
+        // Infra nodes store no blocks, so they open no blockstore either
+        let blockstore = if swarm_config.infra_mode {
+            None
+        } else if let Some(path) = blockstore_db_path {
             if let Some(path_str) = path.to_str() {
                 info!("Attempting to use blockstore from disk: {}", path_str);
             }
@@ -5681,61 +7595,94 @@ impl DhtService {
             match RedbBlockstore::open(path).await {
                 Ok(store) => {
                     info!("Successfully opened blockstore from disk");
-                    Arc::new(store)
+                    Some(Arc::new(store))
                 }
                 Err(e) => {
                     warn!("Failed to open blockstore from disk ({}), falling back to in-memory storage", e);
-                    Arc::new(RedbBlockstore::in_memory()?)
+                    Some(Arc::new(RedbBlockstore::in_memory()?))
                 }
             }
         } else {
             info!("Using in-memory blockstore");
-            Arc::new(RedbBlockstore::in_memory()?)
-        };
-        // Generate a new keypair for this node
-        // If a secret is provided, derive a stable 32-byte seed via SHA-256(secret)
-        // Otherwise, generate a fresh random key.
-        let local_key = match secret {
-            Some(secret_str) => {
-                let mut hasher = Sha256::new();
-                hasher.update(secret_str.as_bytes());
-                let digest = hasher.finalize();
-                let mut seed = [0u8; 32];
-                seed.copy_from_slice(&digest[..32]);
-                identity::Keypair::ed25519_from_bytes(seed)?
-            }
-            None => identity::Keypair::generate_ed25519(),
+            Some(Arc::new(RedbBlockstore::in_memory()?))
         };
+        let identity_source = if secret.is_some() { "derived from secret" } else { "random" };
+        let local_key = keypair_from_secret(secret.as_deref())?;
         let local_peer_id = PeerId::from(local_key.public());
+        let role = if swarm_config.infra_mode {
+            NodeRole::Infra
+        } else if is_bootstrap {
+            NodeRole::Bootstrap
+        } else {
+            NodeRole::User
+        };
+        // Infrastructure nodes bootstrap, relay and answer AutoNAT probes only
+        let is_bootstrap = is_bootstrap || swarm_config.infra_mode;
+        let enable_relay_server = enable_relay_server || swarm_config.infra_mode;
+        let bootstrap_mode = swarm_config.bootstrap_mode.clone();
+        let bootstrap_nodes = bootstrap_mode.startup_peers(bootstrap_nodes);
+        let autonat_servers = bootstrap_mode.dialable(autonat_servers);
+        let preferred_relays = bootstrap_mode.dialable(preferred_relays);
+        if bootstrap_mode != BootstrapMode::Bootstrap {
+            info!("Bootstrap mode {}: dialing {} startup peer(s)", bootstrap_mode, bootstrap_nodes.len());
+        }
+        if swarm_config.infra_mode {
+            info!("Infra mode: DHT server, AutoNAT server and relay server; no gossip, transfers, calls or proxying");
+            final_enable_autorelay = false;
+        }
+        if swarm_config.enable_crypto_audit {
+            let path = AuditLog::default_path();
+            match AuditLog::open(&path) {
+                Ok(log) => {
+                    if AuditLog::install(log) {
+                        info!("Recording cryptographic operations in {}", path.display());
+                    }
+                }
+                Err(e) => warn!("Crypto audit log unavailable at {}: {}", path.display(), e),
+            }
+        }
+        crypto::audit::record(
+            CryptoOperation::KeyGenerated,
+            Some(local_peer_id.to_string()),
+            format!("ed25519 node identity ({})", identity_source),
+        );
+        // Derived now because the keypair moves into the swarm; never written to disk
+        let peer_store_key = chiral_config
+            .storage
+            .encrypt_peer_store
+            .then(|| EncryptedPeerStore::key_from_keypair(&local_key));
         let peer_id_str = local_peer_id.to_string();
 
         // Create a Kademlia behaviour with tuned configuration
-        let store = MemoryStore::new(local_peer_id);
-        let mut kad_cfg = KademliaConfig::new(StreamProtocol::new("/chiral/kad/1.0.0"));
+        let memory_budget = MemoryBudget::from_mb(swarm_config.memory_budget_mb);
+        let store = BudgetedStore::new(local_peer_id, memory_budget);
+        let mut kad_cfg = KademliaConfig::new(StreamProtocol::new(protocol::KADEMLIA_PROTOCOL));
         let bootstrap_interval = Duration::from_secs(1);
         if is_bootstrap {
             // These settings result in node to not provide files, only acts as a router
             kad_cfg.set_record_ttl(Some(Duration::from_secs(0)));
             kad_cfg.set_provider_record_ttl(Some(Duration::from_secs(0)));
-
+        }
+        // The swarm loop bootstraps periodically instead, so those queries
+        // go through the Kademlia rate limiter like every other one
+        kad_cfg.set_periodic_bootstrap_interval(None);
+        let periodic_bootstrap = if is_bootstrap {
             // ensures bootstrap node only keeps active peers in its routing table
-            kad_cfg.set_periodic_bootstrap_interval(None);
-        } else {
+            None
+        } else if bootstrap_nodes.is_empty() {
             // Only enable periodic bootstrap if we have bootstrap nodes
             // This prevents "No known peers" warnings when running standalone
-            if !bootstrap_nodes.is_empty() {
-                kad_cfg.set_periodic_bootstrap_interval(Some(bootstrap_interval));
-            } else {
-                kad_cfg.set_periodic_bootstrap_interval(None);
-                info!("Periodic bootstrap disabled - no bootstrap nodes configured");
-            }
-        }
+            info!("Periodic bootstrap disabled - no bootstrap nodes configured");
+            None
+        } else {
+            Some(bootstrap_interval)
+        };
 
         // Align with docs: shorter queries, higher replication
         kad_cfg.set_query_timeout(Duration::from_secs(30));
 
-        // Replication factor of 3 (as per spec table)
-        if let Some(nz) = std::num::NonZeroUsize::new(3) {
+        // Replication factor of 3 (as per spec table); also caps `put_replicated`
+        if let Some(nz) = std::num::NonZeroUsize::new(record_replication::MAX_REPLICATION_FACTOR as usize) {
             kad_cfg.set_replication_factor(nz);
         }
 
@@ -5759,7 +7706,7 @@ impl DhtService {
         // Create identify behaviour with proactive push updates
         let identify_config =
             identify::Config::new(EXPECTED_PROTOCOL_VERSION.to_string(), local_key.public())
-                .with_agent_version(format!("chiral-network/{}", env!("CARGO_PKG_VERSION")))
+                .with_agent_version(compatibility::agent_version())
                 .with_push_listen_addr_updates(true);
         let identify = identify::Behaviour::new(identify_config);
 
@@ -5767,6 +7714,12 @@ impl DhtService {
         let disable_mdns_env = std::env::var("CHIRAL_DISABLE_MDNS").ok().as_deref() == Some("1");
         let mdns_opt = if disable_mdns_env {
             tracing::info!("mDNS disabled via env CHIRAL_DISABLE_MDNS=1");
+            if bootstrap_mode == BootstrapMode::MdnsOnly {
+                warn!("Bootstrap mode mdns_only without mDNS finds no peers by itself");
+            }
+            None
+        } else if !bootstrap_mode.uses_mdns() {
+            info!("mDNS disabled: only static peers are dialed");
             None
         } else {
             Some(Mdns::new(Default::default(), local_peer_id)?)
@@ -5775,39 +7728,94 @@ impl DhtService {
         // Request-Response behaviours
         let rr_cfg = rr::Config::default();
         let proxy_protocols =
-            std::iter::once(("/chiral/proxy/1.0.0".to_string(), rr::ProtocolSupport::Full));
-        let proxy_rr = rr::Behaviour::new(proxy_protocols, rr_cfg.clone());
+            std::iter::once((protocol::PROXY_PROTOCOL.to_string(), rr::ProtocolSupport::Full));
+        // Infra nodes only route, so they neither offer nor use these
+        let user_node = !swarm_config.infra_mode;
+        let proxy_rr = user_node.then(|| rr::Behaviour::new(proxy_protocols, rr_cfg.clone()));
 
         let webrtc_protocols = std::iter::once((
-            "/chiral/webrtc-signaling/1.0.0".to_string(),
+            protocol::WEBRTC_SIGNALING_PROTOCOL.to_string(),
             rr::ProtocolSupport::Full,
         ));
-        let webrtc_signaling_rr = rr::Behaviour::new(webrtc_protocols, rr_cfg.clone());
+        let webrtc_signaling_rr =
+            user_node.then(|| rr::Behaviour::new(webrtc_protocols, rr_cfg.clone()));
 
         let key_request_protocols =
             std::iter::once((KeyRequestProtocol, rr::ProtocolSupport::Full));
-        let key_request = rr::Behaviour::new(key_request_protocols, rr_cfg);
+        let key_request = user_node.then(|| rr::Behaviour::new(key_request_protocols, rr_cfg));
+        let file_transfer = user_node.then(|| {
+            rr::Behaviour::new(
+                std::iter::once((FileTransferProtocol, rr::ProtocolSupport::Full)),
+                // Offers stay open while the user decides
+                rr::Config::default().with_request_timeout(OFFER_TIMEOUT),
+            )
+        });
+        // Invites stay open while the callee's phone rings
+        let call_signaling = user_node.then(|| {
+            rr::Behaviour::new(
+                std::iter::once((CallSignalingProtocol, rr::ProtocolSupport::Full)),
+                rr::Config::default().with_request_timeout(RING_TIMEOUT),
+            )
+        });
+        let read_receipts = rr::Behaviour::new(
+            std::iter::once((ReadReceiptProtocol, rr::ProtocolSupport::Full)),
+            rr::Config::default(),
+        );
+        let reachability = rr::Behaviour::new(
+            std::iter::once((ReachabilityProtocol, rr::ProtocolSupport::Full)),
+            rr::Config::default(),
+        );
+        let peer_lookup = rr::Behaviour::new(
+            std::iter::once((PeerLookupProtocol, rr::ProtocolSupport::Full)),
+            rr::Config::default(),
+        );
+        let gossipsub_config = gossipsub::ConfigBuilder::default()
+            .validation_mode(gossipsub::ValidationMode::Strict)
+            // Blocked topics and peers must not be relayed either
+            .validate_messages()
+            .mesh_n(swarm_config.mesh_n)
+            .mesh_n_low(swarm_config.mesh_n_low)
+            .mesh_n_high(swarm_config.mesh_n_high)
+            // GossipSub requires at most mesh_n_low and half of mesh_n
+            .mesh_outbound_min(2usize.min(swarm_config.mesh_n_low).min(swarm_config.mesh_n / 2))
+            .build()
+            .map_err(|e| format!("gossipsub config: {e:?}"))?;
+        let gossipsub = if swarm_config.infra_mode {
+            info!("Gossip disabled in infra mode");
+            None
+        } else {
+            Some(
+                gossipsub::Behaviour::new(
+                    gossipsub::MessageAuthenticity::Signed(local_key.clone()),
+                    gossipsub_config,
+                )
+                .map_err(|e| format!("gossipsub: {e}"))?,
+            )
+        };
+        let gossipsub = toggle::Toggle::from(gossipsub);
 
         let probe_interval = autonat_probe_interval.unwrap_or(Duration::from_secs(1));
         let autonat_client_behaviour = if enable_autonat {
+            // The client's own interval only paces probes within a window the
+            // scheduler opens, see `ScheduledAutoNATClient`
             info!(
-                "AutoNAT enabled (probe interval: {}s)",
+                "AutoNAT enabled (adaptive probing, {}s between addresses in a round)",
                 probe_interval.as_secs()
             );
-            Some(v2::client::Behaviour::new(
+            Some(ScheduledAutoNATClient::new(v2::client::Behaviour::new(
                 OsRng,
                 v2::client::Config::default().with_probe_interval(probe_interval),
-            ))
+            )))
         } else {
             None
         };
-        let autonat_server_behaviour = if is_bootstrap && enable_autonat {
+        let autonat_server_behaviour = if (is_bootstrap && enable_autonat) || swarm_config.infra_mode {
             Some(v2::server::Behaviour::new(OsRng))
         } else {
             None
         };
 
-        let bitswap = beetswap::Behaviour::new(blockstore);
+        let bitswap = toggle::Toggle::from(blockstore.map(beetswap::Behaviour::new));
         let (relay_transport, relay_client_behaviour) = relay::client::new(local_peer_id);
         let autonat_client_toggle = toggle::Toggle::from(autonat_client_behaviour);
         let autonat_server_toggle = toggle::Toggle::from(autonat_server_behaviour);
@@ -5815,19 +7823,63 @@ impl DhtService {
 
         // DCUtR with optimized configuration for better hole-punching success
         // Key improvements:
-        // - Always enabled for maximum connectivity
+        // - Enabled unless `SwarmConfig::dcutr` turns it off
         // - Works in conjunction with relay for coordination
         // - Attempts direct connection upgrade after relay establishment
-        info!("🔓 DCUtR enabled with enhanced hole-punching strategy");
-        let dcutr_toggle = toggle::Toggle::from(Some(dcutr::Behaviour::new(local_peer_id)));
+        let dcutr_toggle = if swarm_config.dcutr {
+            info!("🔓 DCUtR enabled with enhanced hole-punching strategy");
+            toggle::Toggle::from(Some(dcutr::Behaviour::new(local_peer_id)))
+        } else {
+            info!("DCUtR disabled by configuration");
+            toggle::Toggle::from(None)
+        };
+
+        // With the encrypted store enabled the plain cache is never opened for
+        // use: its addresses are moved over and the plaintext file is deleted,
+        // even when no key could be derived and the node runs without a cache
+        let plain_cache_path = LocalDiscoveryCache::default_path();
+        let discovery_cache = match peer_store_key {
+            Some(Ok(key)) => LocalDiscoveryCache::open_encrypted(&EncryptedPeerStore::default_path(), &key)
+                .map(|cache| {
+                    match cache.migrate_plain(&plain_cache_path) {
+                        Ok(0) => {}
+                        Ok(n) => info!("Moved {} cached peer addresses into the encrypted store", n),
+                        Err(e) => warn!("Failed to migrate the plain peer cache: {}", e),
+                    }
+                    cache
+                })
+                .map_err(|e| e.to_string()),
+            Some(Err(e)) => Err(format!("cannot derive the peer store key: {}", e)),
+            None => LocalDiscoveryCache::open(&plain_cache_path).map_err(|e| e.to_string()),
+        };
+        if chiral_config.storage.encrypt_peer_store {
+            if let Err(e) = LocalDiscoveryCache::remove_database(&plain_cache_path) {
+                warn!("Failed to delete the plain peer cache {:?}: {}", plain_cache_path, e);
+            }
+        }
+        let discovery_cache = match discovery_cache {
+            Ok(cache) => Some(Arc::new(cache)),
+            Err(e) => {
+                warn!("Local discovery cache unavailable: {}", e);
+                None
+            }
+        };
 
         // Relay server configuration
+        let relay_consent = RelayConsent::new(
+            swarm_config.relay_consent,
+            relay_consent::parse_allow_list(&swarm_config.relay_allow_list),
+            Some(TrustedPeers::shared()),
+        );
         let relay_server_behaviour = if enable_relay_server {
             info!("🔁 Relay server enabled - this node can relay traffic for others");
-            Some(relay::Behaviour::new(
-                local_peer_id,
-                relay::Config::default(),
-            ))
+            let mut relay_config = relay_server_config(swarm_config.infra_mode);
+            if relay_consent.policy() != RelayConsentPolicy::Open {
+                info!("🔁 Relay circuits limited by consent policy {:?}", relay_consent.policy());
+            }
+            // Installed for `Open` too, so a reload can narrow the policy
+            relay_config.circuit_src_rate_limiters.push(Box::new(relay_consent.clone()));
+            Some(relay::Behaviour::new(local_peer_id, relay_config))
         } else {
             None
         };
@@ -5902,26 +7954,55 @@ impl DhtService {
             HashSet::new()
         };
 
+        // As a headless node refuses them at startup and on a reload; a
+        // filter rule that does not compile would silently admit what it was
+        // meant to stop
+        let config_errors = crate::config::reload::validate_swarm(&chiral_config);
+        if !config_errors.is_empty() {
+            return Err(format!("Invalid configuration: {}", config_errors.join("; ")).into());
+        }
+        let connection_filter = IncomingConnectionFilter::new(&chiral_config.security.filter_rules);
+        let filter_rules = connection_filter.list().len();
+        if filter_rules > 0 {
+            info!("🛡️ Filtering incoming connections with {} rule(s)", filter_rules);
+        }
+        let behaviour_filter = connection_filter.clone();
+
+        // Bytes in and out per transport stack, for the metrics endpoint
+        let mut bandwidth_registry = libp2p::metrics::Registry::with_prefix("chiral_libp2p");
+
         // Create the swarm
+        let signing_key = local_key.clone();
         let mut swarm = SwarmBuilder::with_existing_identity(local_key)
             .with_tokio()
             .with_tcp(
                 tcp::Config::default().nodelay(true),
-                noise::Config::new,
+                |key: &identity::Keypair| noise_config(key, &swarm_config.noise_prologue),
                 yamux::Config::default,
             )?
             // .with_quic() seems to destablize peer connect/download, disabled for now until solution
-            .with_relay_client(noise::Config::new, yamux::Config::default)?
+            .with_relay_client(
+                |key: &identity::Keypair| noise_config(key, &swarm_config.noise_prologue),
+                yamux::Config::default,
+            )?
+            .with_bandwidth_metrics(&mut bandwidth_registry)
             .with_behaviour(move |_, relay_client_behaviour: relay::client::Behaviour| {
                 DhtBehaviour {
+                    connection_filter: behaviour_filter,
                     kademlia,
                     identify,
                     mdns: mdns_toggle,
                     bitswap,
                     ping: Ping::new(ping::Config::new()),
-                    proxy_rr,
-                    webrtc_signaling_rr,
-                    key_request,
+                    proxy_rr: toggle::Toggle::from(proxy_rr),
+                    webrtc_signaling_rr: toggle::Toggle::from(webrtc_signaling_rr),
+                    key_request: toggle::Toggle::from(key_request),
+                    file_transfer: toggle::Toggle::from(file_transfer),
+                    call_signaling: toggle::Toggle::from(call_signaling),
+                    read_receipts,
+                    reachability,
+                    peer_lookup,
+                    gossipsub,
                     autonat_client: autonat_client_toggle,
                     autonat_server: autonat_server_toggle,
                     relay_client: relay_client_behaviour,
@@ -5938,6 +8019,25 @@ impl DhtService {
         // Always listen on the specified port
         let tcp_addr: Multiaddr = format!("/ip4/0.0.0.0/tcp/{}", port).parse()?;
         swarm.listen_on(tcp_addr)?;
+        for addr in &swarm_config.listen_addrs {
+            let addr: Multiaddr = addr
+                .parse()
+                .map_err(|e| format!("invalid listen address {}: {}", addr, e))?;
+            swarm.listen_on(addr)?;
+        }
+        if let Some(gossip) = swarm.behaviour_mut().gossipsub.as_mut() {
+            gossip
+                .subscribe(&presence_topic())
+                .map_err(|e| format!("subscribe to presence topic: {e:?}"))?;
+            gossip
+                .subscribe(&announce_topic())
+                .map_err(|e| format!("subscribe to announce topic: {e:?}"))?;
+        }
+        let announcer = NodeAnnouncementBroadcast::new(
+            &local_peer_id,
+            enable_relay_server
+                .then(|| relay_server_config(swarm_config.infra_mode).max_reservations as u32),
+        );
 
         // QUIC also bound to the same port (udp), seems to destablize peer connect/download, disabled for now until solution
         // let quic_addr: Multiaddr = format!("/ip4/0.0.0.0/udp/{}/quic-v1", port).parse()?;
@@ -5991,10 +8091,9 @@ impl DhtService {
         // Connect to bootstrap nodes
         // NOTE: Bootstrap nodes are explicitly configured, so we trust them
         // and don't filter based on reachability (important for relay servers and local testing)
-        let mut successful_connections = 0;
-        let total_bootstrap_nodes = bootstrap_nodes.len();
+        let mut chain_nodes = Vec::new();
         for bootstrap_addr in &bootstrap_nodes {
-            if let Ok(addr) = bootstrap_addr.parse::<Multiaddr>() {
+            if let Ok(addr) = validate_bootstrap_addr(bootstrap_addr) {
                 // WAN Mode: skip unroutable bootstrap addresses
                 // LAN Mode: allow private/loopback addresses for local development and testing
                 let wan_mode = enable_autonat || enable_autorelay;
@@ -6006,29 +8105,20 @@ impl DhtService {
                     continue;
                 }
 
-                match swarm.dial(addr.clone()) {
-                    Ok(_) => {
-                        successful_connections += 1;
-                        // Add bootstrap nodes to Kademlia routing table if it has a peer ID
-                        if let Some(peer_id) = addr.iter().find_map(|p| {
-                            if let libp2p::multiaddr::Protocol::P2p(peer) = p {
-                                Some(peer)
-                            } else {
-                                None
-                            }
-                        }) {
-                            swarm
-                                .behaviour_mut()
-                                .kademlia
-                                .add_address(&peer_id, addr.clone());
-                        }
-                    }
-                    Err(e) => warn!("✗ Failed to dial bootstrap {}: {}", bootstrap_addr, e),
-                }
+                chain_nodes.push(addr);
             } else {
                 warn!("✗ Invalid bootstrap address format: {}", bootstrap_addr);
             }
         }
+        // Nodes are tried one at a time in the configured order; the rest are
+        // dialed once the first connects (see `run_dht_node`)
+        let bootstrap_contributions =
+            Arc::new(Mutex::new(BootstrapContributionTracker::new(&chain_nodes)));
+        let mut bootstrap_chain = BootstrapFallbackChain::new(chain_nodes);
+        let first_bootstrap = bootstrap_chain.start(std::time::Instant::now());
+        if let Some(addr) = &first_bootstrap {
+            dial_bootstrap_node(&mut swarm, addr.clone());
+        }
 
         if enable_autonat {
             for server_addr in &autonat_targets {
@@ -6049,15 +8139,11 @@ impl DhtService {
             }
         }
 
-        // Trigger initial bootstrap only if we successfully connected to at least one bootstrap node
-        // Kademlia bootstrap requires at least one peer in the routing table to work
+        // Kademlia bootstrap requires at least one peer in the routing table,
+        // so it is triggered once the first bootstrap node connects
         if !bootstrap_nodes.is_empty() {
-            if successful_connections > 0 {
-                let _ = swarm.behaviour_mut().kademlia.bootstrap();
-                info!(
-                    "✓ Starting Kademlia bootstrap with {} bootstrap connection(s)",
-                    successful_connections
-                );
+            if let Some(addr) = &first_bootstrap {
+                info!("Dialing bootstrap node {} first", addr);
             } else {
                 warn!("⚠ No bootstrap connections succeeded - cannot bootstrap DHT");
                 warn!("  Node will operate in standalone mode until peers connect");
@@ -6067,124 +8153,734 @@ impl DhtService {
             info!("No bootstrap nodes provided - starting in standalone mode");
         }
 
-        let (cmd_tx, cmd_rx) = mpsc::channel(100);
-        let (event_tx, event_rx) = mpsc::channel(100);
-        let connected_peers = Arc::new(Mutex::new(HashSet::new()));
-        let metrics = Arc::new(Mutex::new(DhtMetrics::default()));
-        let pending_echo = Arc::new(Mutex::new(HashMap::new()));
-        let pending_searches = Arc::new(Mutex::new(HashMap::new()));
-        let search_counter = Arc::new(AtomicU64::new(1));
-        let proxy_mgr: ProxyMgr = Arc::new(Mutex::new(ProxyManager::default()));
-        let peer_selection = Arc::new(Mutex::new(PeerSelectionService::new()));
-        let pending_webrtc_offers = Arc::new(Mutex::new(HashMap::new()));
-        let pending_key_requests = Arc::new(Mutex::new(HashMap::new()));
-        let pending_provider_queries: Arc<Mutex<HashMap<String, PendingProviderQuery>>> =
-            Arc::new(Mutex::new(HashMap::new()));
-        let root_query_mapping: Arc<Mutex<HashMap<beetswap::QueryId, FileMetadata>>> =
-            Arc::new(Mutex::new(HashMap::new()));
-        let active_downloads: Arc<Mutex<HashMap<String, Arc<Mutex<ActiveDownload>>>>> =
-            Arc::new(Mutex::new(HashMap::new()));
-        let get_providers_queries_local: Arc<
-            Mutex<HashMap<kad::QueryId, (String, std::time::Instant)>>,
-        > = Arc::new(Mutex::new(HashMap::new()));
-        let seeder_heartbeats_cache: Arc<Mutex<HashMap<String, FileHeartbeatCacheEntry>>> =
-            Arc::new(Mutex::new(HashMap::new()));
-        let file_heartbeat_state: Arc<Mutex<HashMap<String, FileHeartbeatState>>> =
-            Arc::new(Mutex::new(HashMap::new()));
-        let pending_heartbeat_updates: Arc<Mutex<HashSet<String>>> =
-            Arc::new(Mutex::new(HashSet::new()));
-        let pending_infohash_searches: Arc<Mutex<HashMap<kad::QueryId, PendingInfohashSearch>>> =
-            Arc::new(Mutex::new(HashMap::new()));
-        let pending_dht_queries: Arc<
-            Mutex<HashMap<kad::QueryId, oneshot::Sender<Result<Option<Vec<u8>>, String>>>>,
-        > = Arc::new(Mutex::new(HashMap::new()));
+        let (cmd_tx, cmd_rx) = mpsc::channel(100);
+        let (event_tx, event_rx) = mpsc::channel(100);
+        let connected_peers = Arc::new(Mutex::new(HashSet::new()));
+        let connected_addrs = Arc::new(Mutex::new(HashMap::new()));
+        let metrics = Arc::new(Mutex::new(DhtMetrics::default()));
+        let stats_counters = Arc::new(StatsCounters::new());
+        let memory_usage = Arc::new(MemoryUsage::new(memory_budget));
+        let peer_discovery = PeerDiscoveryFeed::new();
+        let peer_discovery_tx = peer_discovery.sender();
+        let pending_echo = Arc::new(Mutex::new(HashMap::new()));
+        let pending_searches = Arc::new(Mutex::new(HashMap::new()));
+        let search_counter = Arc::new(AtomicU64::new(1));
+        let proxy_mgr: ProxyMgr = Arc::new(Mutex::new(ProxyManager::default()));
+        let peer_selection = Arc::new(Mutex::new(PeerSelectionService::new()));
+        let pending_webrtc_offers = Arc::new(Mutex::new(HashMap::new()));
+        let pending_key_requests = Arc::new(Mutex::new(HashMap::new()));
+        let pending_file_transfers = Arc::new(Mutex::new(HashMap::new()));
+        let mut incoming_transfers =
+            IncomingFileTransfers::new(IncomingFileTransfers::default_download_dir());
+        incoming_transfers.set_compression(swarm_config.compress_transfers);
+        incoming_transfers.set_accepting(!swarm_config.infra_mode);
+        let incoming_file_transfers = Arc::new(Mutex::new(incoming_transfers));
+        let call_state = Arc::new(Mutex::new(CallStateManager::new()));
+        let typing = Arc::new(Mutex::new(TypingIndicator::new()));
+        let node_announcements = Arc::new(Mutex::new(NodeAnnouncementStore::new()));
+        let connection_quality = Arc::new(Mutex::new(ConnectionQualityClassifier::new()));
+        let topic_filter = Arc::new(Mutex::new(TopicFilter::new()));
+        let pending_provider_queries: Arc<Mutex<HashMap<String, PendingProviderQuery>>> =
+            Arc::new(Mutex::new(HashMap::new()));
+        let root_query_mapping: Arc<Mutex<HashMap<beetswap::QueryId, FileMetadata>>> =
+            Arc::new(Mutex::new(HashMap::new()));
+        let active_downloads: Arc<Mutex<HashMap<String, Arc<Mutex<ActiveDownload>>>>> =
+            Arc::new(Mutex::new(HashMap::new()));
+        let get_providers_queries_local: Arc<
+            Mutex<HashMap<kad::QueryId, (String, std::time::Instant)>>,
+        > = Arc::new(Mutex::new(HashMap::new()));
+        let seeder_heartbeats_cache: Arc<Mutex<HashMap<String, FileHeartbeatCacheEntry>>> =
+            Arc::new(Mutex::new(HashMap::new()));
+        let file_heartbeat_state: Arc<Mutex<HashMap<String, FileHeartbeatState>>> =
+            Arc::new(Mutex::new(HashMap::new()));
+        let pending_heartbeat_updates: Arc<Mutex<HashSet<String>>> =
+            Arc::new(Mutex::new(HashSet::new()));
+        let pending_infohash_searches: Arc<Mutex<HashMap<kad::QueryId, PendingInfohashSearch>>> =
+            Arc::new(Mutex::new(HashMap::new()));
+        let pending_dht_queries: Arc<
+            Mutex<HashMap<kad::QueryId, oneshot::Sender<Result<Option<Vec<u8>>, String>>>>,
+        > = Arc::new(Mutex::new(HashMap::new()));
+
+        {
+            let mut guard = metrics.lock().await;
+            guard.autonat_enabled = enable_autonat;
+            guard.autorelay_enabled = final_enable_autorelay;
+            guard.last_autorelay_enabled_at = last_autorelay_enabled_at;
+            guard.last_autorelay_disabled_at = last_autorelay_disabled_at;
+            // DCUtR counts as enabled when AutoNAT is enabled
+            guard.dcutr_enabled = enable_autonat && swarm_config.dcutr;
+            let now = SystemTime::now();
+            if final_enable_autorelay {
+                // Always record a fresh enable time when AutoRelay is turned on
+                guard.last_autorelay_enabled_at = Some(now);
+            } else {
+                guard.last_autorelay_disabled_at = Some(now);
+            }
+        }
+
+        // Spawn the Dht node task
+        let received_chunks_clone = Arc::new(Mutex::new(HashMap::new()));
+        let bootstrap_peer_ids = extract_bootstrap_peer_ids(&bootstrap_nodes);
+        let webhook_bootstrap_peers = bootstrap_peer_ids.clone();
+        let bootstrap_consensus = if is_bootstrap {
+            None
+        } else {
+            MultiBootstrapConsensus::new(
+                swarm_config.bootstrap_min_confirmations,
+                bootstrap_peer_ids.iter().copied(),
+            )
+        };
+        match &bootstrap_consensus {
+            Some(consensus) => info!(
+                "Peers found through Kademlia need {} of {} bootstrap nodes to confirm them",
+                consensus.min_confirmations(),
+                bootstrap_peer_ids.len()
+            ),
+            None if swarm_config.bootstrap_min_confirmations > 1 && !is_bootstrap => debug!(
+                "Bootstrap consensus off: {} bootstrap node(s) configured, {} confirmations required",
+                bootstrap_peer_ids.len(),
+                swarm_config.bootstrap_min_confirmations
+            ),
+            None => {}
+        }
+        let file_metadata_cache_local: Arc<Mutex<HashMap<String, FileMetadata>>> =
+            Arc::new(Mutex::new(HashMap::new()));
+        let peer_events = Arc::new(Mutex::new(PeerEventLog::default()));
+
+        // Bootstrap nodes serve AutoNAT rather than probing their own reachability
+        let nat_scheduler = (enable_autonat && !is_bootstrap).then(AutoNATProbeScheduler::new);
+        let nat_probe_shutdown = CancellationToken::new();
+        if let Some(scheduler) = nat_scheduler.clone() {
+            let probe_tx = cmd_tx.clone();
+            tokio::spawn(scheduler.run(
+                move || {
+                    let probe_tx = probe_tx.clone();
+                    async move {
+                        let _ = probe_tx.send(DhtCommand::ProbeNat).await;
+                    }
+                },
+                nat_probe_shutdown.clone(),
+            ));
+        }
+        let port_forwarding = PortForwardingMonitor::new(enable_upnp, port);
+        let replication = Arc::new(Mutex::new(RecordReplicator::new()));
+        if enable_upnp {
+            let mapping_tx = cmd_tx.clone();
+            tokio::spawn(port_forwarding.clone().run(
+                move |addr| {
+                    let mapping_tx = mapping_tx.clone();
+                    async move {
+                        let _ = mapping_tx.send(DhtCommand::AddExternalAddress(addr)).await;
+                    }
+                },
+                nat_probe_shutdown.clone(),
+            ));
+        }
+        tokio::spawn(trim_file_caches(
+            file_metadata_cache_local.clone(),
+            seeder_heartbeats_cache.clone(),
+            file_heartbeat_state.clone(),
+            memory_usage.clone(),
+            event_tx.clone(),
+            nat_probe_shutdown.clone(),
+        ));
+        let autonat_server_addrs: Vec<Multiaddr> = autonat_targets
+            .iter()
+            .filter_map(|addr| addr.parse().ok())
+            .collect();
+
+        let bandwidth_metrics = Arc::new(bandwidth_registry);
+        tokio::spawn(run_dht_node(
+            swarm,
+            local_peer_id,
+            cmd_rx,
+            event_tx.clone(),
+            connected_peers.clone(),
+            metrics.clone(),
+            pending_echo.clone(),
+            pending_searches.clone(),
+            proxy_mgr.clone(),
+            pending_infohash_searches.clone(),
+            peer_selection.clone(),
+            received_chunks_clone.clone(),
+            file_transfer_service.clone(),
+            chunk_manager,
+            pending_webrtc_offers.clone(),
+            pending_provider_queries.clone(),
+            root_query_mapping.clone(),
+            active_downloads.clone(),
+            get_providers_queries_local.clone(),
+            seeder_heartbeats_cache.clone(),
+            pending_heartbeat_updates.clone(),
+            file_metadata_cache_local.clone(),
+            pending_dht_queries.clone(),
+            pending_key_requests.clone(),
+            is_bootstrap,
+            final_enable_autorelay,
+            relay_candidates,
+            chunk_size,
+            bootstrap_peer_ids,
+            peer_events.clone(),
+            discovery_cache,
+            nat_scheduler.clone(),
+            autonat_server_addrs,
+            pending_file_transfers,
+            incoming_file_transfers.clone(),
+            call_state.clone(),
+            typing.clone(),
+            bootstrap_chain,
+            announcer,
+            node_announcements.clone(),
+            connection_quality.clone(),
+            topic_filter.clone(),
+            bootstrap_contributions.clone(),
+            KadRateLimitConfig::from(&swarm_config),
+            periodic_bootstrap,
+            relay_consent.clone(),
+            bootstrap_consensus,
+            port_forwarding.clone(),
+            replication.clone(),
+            bandwidth_metrics.clone(),
+            stats_counters.clone(),
+            peer_discovery,
+            memory_usage.clone(),
+            MeshHealthMonitor::new(&swarm_config),
+            connected_addrs.clone(),
+            signing_key,
+        ));
+
+        let event_rx = match &swarm_config.event_log_path {
+            Some(path) => match SwarmEventLogger::new(path) {
+                Ok(logger) => {
+                    info!("Logging swarm events to {}", path.display());
+                    logger.tee(event_rx)
+                }
+                Err(e) => {
+                    warn!("Swarm event log disabled: {}", e);
+                    event_rx
+                }
+            },
+            None => event_rx,
+        };
+
+        let event_rx = match WebhookNotifier::from_config(&swarm_config, webhook_bootstrap_peers) {
+            Some(Ok(notifier)) => {
+                // The URL may carry a token, so only the host is logged
+                let host = swarm_config.webhook_url.as_ref().and_then(|u| u.host_str()).unwrap_or_default();
+                info!("Sending peer events to webhook on {}", host);
+                notifier.tee(event_rx)
+            }
+            Some(Err(e)) => {
+                warn!("Webhook disabled: {}", e);
+                event_rx
+            }
+            None => event_rx,
+        };
+
+        Ok(DhtService {
+            cmd_tx,
+            event_tx,
+            event_rx: Arc::new(Mutex::new(event_rx)),
+            peer_id: peer_id_str,
+            connected_peers,
+            connected_addrs,
+            metrics,
+            pending_echo,
+            pending_searches,
+            search_counter,
+            proxy_mgr,
+            peer_selection,
+            file_metadata_cache: file_metadata_cache_local,
+            received_chunks: received_chunks_clone,
+            file_transfer_service,
+            // chunk_manager is not stored in DhtService, only passed to the task
+            pending_webrtc_offers,
+            pending_key_requests,
+            pending_provider_queries,
+            root_query_mapping,
+            active_downloads,
+            get_providers_queries: get_providers_queries_local,
+            chunk_size,
+            file_heartbeat_state,
+            seeder_heartbeats_cache,
+            pending_heartbeat_updates,
+            peer_events,
+            nat_scheduler,
+            nat_probe_shutdown,
+            port_forwarding,
+            replication,
+            incoming_file_transfers,
+            call_state,
+            typing,
+            node_announcements,
+            connection_quality,
+            topic_filter,
+            bootstrap_contributions,
+            send_read_receipts: swarm_config.send_read_receipts,
+            compress_transfers: swarm_config.compress_transfers,
+            sent_compression: Arc::new(Mutex::new(CompressionStats::default())),
+            bandwidth_metrics,
+            role,
+            relay_consent,
+            connection_filter,
+            stats_counters,
+            peer_discovery: peer_discovery_tx,
+            memory_usage,
+        })
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        // Note: This might need to be adjusted if chunk_manager is the source of truth
+        self.chunk_size
+    }
+
+    /// Directory files sent to us over the direct transfer protocol are saved in
+    pub async fn set_direct_transfer_dir(&self, download_dir: PathBuf) {
+        self.incoming_file_transfers
+            .lock()
+            .await
+            .set_download_dir(download_dir);
+    }
+
+    pub async fn direct_transfer_dir(&self) -> PathBuf {
+        self.incoming_file_transfers
+            .lock()
+            .await
+            .download_dir()
+            .to_path_buf()
+    }
+
+    /// A direct transfer offer still waiting for the user
+    pub async fn file_offer(&self, transfer_id: &str) -> Option<FileOffer> {
+        self.incoming_file_transfers
+            .lock()
+            .await
+            .pending_offer(transfer_id)
+            .cloned()
+    }
+
+    async fn answer_file_offer(&self, transfer_id: &str, accept: bool, reason: String) -> Result<(), String> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_tx
+            .send(DhtCommand::AnswerFileOffer {
+                transfer_id: transfer_id.to_string(),
+                accept,
+                reason,
+                sender: tx,
+            })
+            .await
+            .map_err(|e| format!("send answer file offer cmd: {e}"))?;
+        rx.await
+            .map_err(|_| "answer file offer response channel closed".to_string())?
+    }
+
+    /// Start receiving a `DhtEvent::FileOffer`. Callers check the offered
+    /// size against disk space and quota first.
+    pub async fn accept_file_offer(&self, transfer_id: &str) -> Result<(), String> {
+        self.answer_file_offer(transfer_id, true, String::new()).await
+    }
+
+    pub async fn reject_file_offer(&self, transfer_id: &str, reason: String) -> Result<(), String> {
+        self.answer_file_offer(transfer_id, false, reason).await
+    }
+
+    async fn file_transfer_request(
+        &self,
+        peer: PeerId,
+        request: FileTransferRequest,
+    ) -> Result<FileTransferResponse, String> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_tx
+            .send(DhtCommand::SendFileTransferRequest {
+                peer,
+                request,
+                sender: tx,
+            })
+            .await
+            .map_err(|e| format!("send file transfer cmd: {e}"))?;
+        rx.await
+            .map_err(|_| "file transfer response channel closed".to_string())?
+    }
+
+    /// Direct transfer bytes sent and received since startup, logical and on the wire
+    pub async fn transfer_compression_stats(&self) -> TransferCompressionStats {
+        TransferCompressionStats {
+            sent: *self.sent_compression.lock().await,
+            received: self.incoming_file_transfers.lock().await.compression_stats(),
+        }
+    }
+
+    /// Whether chunks of the file at `path` are worth compressing. Files we
+    /// published were sampled then; anything else is sampled now.
+    async fn is_compressible(&self, path: &std::path::Path, filename: &str, hash: &[u8; 32]) -> bool {
+        if let Some(metadata) = self.file_metadata_cache.lock().await.get(&hex::encode(hash)) {
+            return metadata.compressible;
+        }
+        let path = path.to_path_buf();
+        let filename = filename.to_string();
+        tokio::task::spawn_blocking(move || compression::sample_is_compressible(&path, &filename))
+            .await
+            .ok()
+            .and_then(Result::ok)
+            .unwrap_or(false)
+    }
+
+    /// Send the file at `path` directly to `peer_id`.
+    ///
+    /// The peer is offered the file first; once it accepts, the file is
+    /// streamed in `DIRECT_TRANSFER_CHUNK_SIZE` chunks, compressed when both
+    /// sides agreed on a codec. A chunk the peer NACKs, or whose request
+    /// fails, is resent up to `MAX_CHUNK_RETRANSMITS` times if the peer speaks
+    /// wire version 2. Returns the number of bytes the peer confirmed.
+    pub async fn send_file_to_peer(
+        &self,
+        peer_id: &str,
+        path: &std::path::Path,
+    ) -> Result<u64, String> {
+        use tokio::io::AsyncReadExt;
+
+        let peer: PeerId = peer_id.parse().map_err(|e| format!("invalid peer id: {e}"))?;
+        let filename = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| format!("{:?} has no file name", path))?
+            .to_string();
+
+        // Hash first so the receiver can verify what it assembles
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| format!("open {:?}: {}", path, e))?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; DIRECT_TRANSFER_CHUNK_SIZE];
+        let mut size = 0u64;
+        loop {
+            let n = file.read(&mut buf).await.map_err(|e| format!("read {:?}: {}", path, e))?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            size += n as u64;
+        }
+        let hash: [u8; 32] = hasher.finalize().into();
+
+        let offered_codecs = if self.compress_transfers && self.is_compressible(path, &filename, &hash).await {
+            compression::SUPPORTED_CODECS.iter().map(|c| c.to_string()).collect()
+        } else {
+            Vec::new()
+        };
+        let offer = FileTransferRequest::Offer {
+            filename: filename.clone(),
+            size,
+            hash,
+            compression: offered_codecs,
+            version: TRANSFER_WIRE_VERSION,
+        };
+        let (version, compress) = match self.file_transfer_request(peer, offer).await? {
+            FileTransferResponse::Reject { reason } => {
+                return Err(format!("{} rejected {}: {}", peer_id, filename, reason))
+            }
+            answer => match answer.negotiated() {
+                Some((version, None)) => (version, false),
+                Some((version, Some(compression::ZSTD))) => (version, true),
+                _ => return Err(format!("unexpected answer to offer: {:?}", answer)),
+            },
+        };
+        // Older receivers neither check frames nor NACK them
+        let framed = version >= TRANSFER_WIRE_VERSION;
+
+        let mut wire_bytes = 0u64;
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| format!("open {:?}: {}", path, e))?;
+        let mut offset = 0u64;
+        let mut index = 0u32;
+        while offset < size {
+            // Fill a whole chunk unless the file ends first
+            let mut filled = 0;
+            while filled < buf.len() {
+                let n = file
+                    .read(&mut buf[filled..])
+                    .await
+                    .map_err(|e| format!("read {:?}: {}", path, e))?;
+                if n == 0 {
+                    break;
+                }
+                filled += n;
+            }
+            if filled == 0 {
+                return Err(format!("{:?} shrank while it was being sent", path));
+            }
+            // Chunks that do not shrink go out as they are
+            let (data, compressed) = match compress.then(|| compression::compress(&buf[..filled])).flatten() {
+                Some(data) => (data, true),
+                None => (buf[..filled].to_vec(), false),
+            };
+            let chunk_wire_bytes = data.len();
+            let mut retransmits = 0;
+            loop {
+                let chunk = FileTransferRequest::chunk(index, offset, data.clone(), compressed);
+                let failure = match self.file_transfer_request(peer, chunk).await {
+                    Ok(FileTransferResponse::Ack { bytes_received })
+                        if bytes_received == offset + filled as u64 =>
+                    {
+                        offset = bytes_received;
+                        wire_bytes += chunk_wire_bytes as u64;
+                        self.sent_compression.lock().await.record(filled, chunk_wire_bytes);
+                        break;
+                    }
+                    Ok(FileTransferResponse::Reject { reason }) => {
+                        return Err(format!("{} aborted {}: {}", peer_id, filename, reason))
+                    }
+                    Ok(FileTransferResponse::Nack { reason, .. }) if framed => reason,
+                    Ok(other) => return Err(format!("unexpected answer to chunk: {:?}", other)),
+                    Err(e) if framed => e,
+                    Err(e) => return Err(e),
+                };
+                if retransmits == MAX_CHUNK_RETRANSMITS {
+                    return Err(format!(
+                        "chunk {} of {} failed {} times: {}",
+                        index,
+                        filename,
+                        retransmits + 1,
+                        failure
+                    ));
+                }
+                retransmits += 1;
+                warn!("Resending chunk {} of {} to {}: {}", index, filename, peer_id, failure);
+            }
+            index += 1;
+        }
+
+        match self.file_transfer_request(peer, FileTransferRequest::Complete).await? {
+            FileTransferResponse::Ack { bytes_received } => {
+                info!(
+                    "Sent {} ({} bytes, {} on the wire) to {}",
+                    filename, bytes_received, wire_bytes, peer_id
+                );
+                Ok(bytes_received)
+            }
+            FileTransferResponse::Reject { reason } => {
+                Err(format!("{} refused {}: {}", peer_id, filename, reason))
+            }
+            other => Err(format!("unexpected answer to completion: {:?}", other)),
+        }
+    }
+
+    /// Invite `peer_id` to a voice call with our SDP offer. Returns the session
+    /// id; the answer arrives later as a `DhtEvent::Call`.
+    pub async fn start_call(&self, peer_id: &str, sdp_offer: String) -> Result<String, String> {
+        let peer: PeerId = peer_id.parse().map_err(|e| format!("invalid peer id: {}", e))?;
+        let session_id = uuid::Uuid::new_v4().to_string();
+        self.call_state
+            .lock()
+            .await
+            .start_outgoing(&session_id, peer_id)
+            .map_err(|e| e.to_string())?;
+
+        let request = CallRequest::Invite {
+            caller_id: self.peer_id.clone(),
+            session_id: session_id.clone(),
+            sdp_offer,
+        };
+        if let Err(e) = self
+            .cmd_tx
+            .send(DhtCommand::SendCallRequest { peer, request })
+            .await
+        {
+            self.call_state.lock().await.end(&session_id);
+            return Err(format!("send call cmd: {e}"));
+        }
+        Ok(session_id)
+    }
+
+    async fn answer_call(&self, session_id: &str, response: CallResponse) -> Result<(), String> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_tx
+            .send(DhtCommand::AnswerCall {
+                session_id: session_id.to_string(),
+                response,
+                sender: tx,
+            })
+            .await
+            .map_err(|e| format!("send answer call cmd: {e}"))?;
+        rx.await
+            .map_err(|_| "answer call response channel closed".to_string())?
+    }
+
+    /// Accept a ringing incoming call with our SDP answer
+    pub async fn accept_call(&self, session_id: &str, sdp_answer: String) -> Result<(), String> {
+        self.answer_call(session_id, CallResponse::Accept { sdp_answer })
+            .await
+    }
+
+    pub async fn reject_call(&self, session_id: &str, reason: String) -> Result<(), String> {
+        self.answer_call(session_id, CallResponse::Reject { reason })
+            .await
+    }
+
+    /// Hang up a call. An incoming call that is still ringing is declined instead.
+    pub async fn end_call(&self, session_id: &str) -> Result<(), String> {
+        let call = self
+            .call_state
+            .lock()
+            .await
+            .get(session_id)
+            .cloned()
+            .ok_or_else(|| format!("unknown call session {}", session_id))?;
+        if call.direction == CallDirection::Incoming && call.state == CallState::Ringing {
+            return self.reject_call(session_id, "declined".to_string()).await;
+        }
+
+        self.call_state.lock().await.end(session_id);
+        let peer: PeerId = call
+            .peer_id
+            .parse()
+            .map_err(|e| format!("invalid peer id: {}", e))?;
+        self.cmd_tx
+            .send(DhtCommand::SendCallRequest {
+                peer,
+                request: CallRequest::Hangup {
+                    session_id: session_id.to_string(),
+                },
+            })
+            .await
+            .map_err(|e| format!("send call cmd: {e}"))
+    }
+
+    /// Calls that are ringing or active
+    pub async fn active_calls(&self) -> Vec<CallInfo> {
+        self.call_state.lock().await.calls()
+    }
+
+    /// Record a keypress in `channel`. Peers are told once and then receive
+    /// `StopTyping` automatically when the keypresses pause.
+    pub async fn start_typing(&self, channel: &str) -> Result<(), String> {
+        let peer: PeerId = self
+            .peer_id
+            .parse()
+            .map_err(|e| format!("invalid local peer id: {}", e))?;
+        let event = self
+            .typing
+            .lock()
+            .await
+            .start_typing(peer, TopicHash::from_raw(channel));
+        match event {
+            Some(event) => self.publish_presence(event).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Stop typing in `channel` right away, e.g. after the message was sent
+    pub async fn stop_typing(&self, channel: &str) -> Result<(), String> {
+        let event = self
+            .typing
+            .lock()
+            .await
+            .stop_typing(&TopicHash::from_raw(channel));
+        match event {
+            Some(event) => self.publish_presence(event).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Receive reactions and other channel traffic published on `channel`
+    pub async fn join_channel(&self, channel: &str) -> Result<(), String> {
+        self.cmd_tx
+            .send(DhtCommand::JoinChannel(channel.to_string()))
+            .await
+            .map_err(|e| format!("send join channel cmd: {e}"))
+    }
 
-        {
-            let mut guard = metrics.lock().await;
-            guard.autonat_enabled = enable_autonat;
-            guard.autorelay_enabled = final_enable_autorelay;
-            guard.last_autorelay_enabled_at = last_autorelay_enabled_at;
-            guard.last_autorelay_disabled_at = last_autorelay_disabled_at;
-            guard.dcutr_enabled = enable_autonat; // DCUtR enabled when AutoNAT is enabled
-            let now = SystemTime::now();
-            if final_enable_autorelay {
-                // Always record a fresh enable time when AutoRelay is turned on
-                guard.last_autorelay_enabled_at = Some(now);
-            } else {
-                guard.last_autorelay_disabled_at = Some(now);
-            }
+    /// Send one batch of read receipts to the author of the messages.
+    /// Returns false without sending when read receipts are disabled.
+    pub async fn send_read_receipts(
+        &self,
+        author: &str,
+        batch: ReadReceiptBatch,
+    ) -> Result<bool, String> {
+        if !self.send_read_receipts {
+            return Ok(false);
         }
+        let peer: PeerId = author.parse().map_err(|e| format!("invalid peer id: {e}"))?;
+        self.cmd_tx
+            .send(DhtCommand::SendReadReceipts { peer, batch })
+            .await
+            .map_err(|e| format!("send read receipts cmd: {e}"))?;
+        Ok(true)
+    }
 
-        // Spawn the Dht node task
-        let received_chunks_clone = Arc::new(Mutex::new(HashMap::new()));
-        let bootstrap_peer_ids = extract_bootstrap_peer_ids(&bootstrap_nodes);
-        let file_metadata_cache_local: Arc<Mutex<HashMap<String, FileMetadata>>> =
-            Arc::new(Mutex::new(HashMap::new()));
+    /// Publish a message on the channel topic it was written for
+    pub async fn publish_message(&self, message: IncomingMessage) -> Result<(), String> {
+        self.cmd_tx
+            .send(DhtCommand::PublishToChannel {
+                channel: message.topic.clone(),
+                envelope: ChannelEnvelope::Message(message),
+            })
+            .await
+            .map_err(|e| format!("send channel cmd: {e}"))
+    }
 
-        tokio::spawn(run_dht_node(
-            swarm,
-            local_peer_id,
-            cmd_rx,
-            event_tx,
-            connected_peers.clone(),
-            metrics.clone(),
-            pending_echo.clone(),
-            pending_searches.clone(),
-            proxy_mgr.clone(),
-            pending_infohash_searches.clone(),
-            peer_selection.clone(),
-            received_chunks_clone.clone(),
-            file_transfer_service.clone(),
-            chunk_manager,
-            pending_webrtc_offers.clone(),
-            pending_provider_queries.clone(),
-            root_query_mapping.clone(),
-            active_downloads.clone(),
-            get_providers_queries_local.clone(),
-            seeder_heartbeats_cache.clone(),
-            pending_heartbeat_updates.clone(),
-            file_metadata_cache_local.clone(),
-            pending_dht_queries.clone(),
-            pending_key_requests.clone(),
-            is_bootstrap,
-            final_enable_autorelay,
-            relay_candidates,
-            chunk_size,
-            bootstrap_peer_ids,
-        ));
+    /// Publish a reaction on the channel its message was posted in
+    pub async fn publish_reaction(
+        &self,
+        channel: &str,
+        reaction: MessageReaction,
+    ) -> Result<(), String> {
+        self.cmd_tx
+            .send(DhtCommand::PublishToChannel {
+                channel: channel.to_string(),
+                envelope: ChannelEnvelope::Reaction(reaction),
+            })
+            .await
+            .map_err(|e| format!("send channel cmd: {e}"))
+    }
 
-        Ok(DhtService {
-            cmd_tx,
-            event_rx: Arc::new(Mutex::new(event_rx)),
-            peer_id: peer_id_str,
-            connected_peers,
-            connected_addrs: HashMap::new(),
-            metrics,
-            pending_echo,
-            pending_searches,
-            search_counter,
-            proxy_mgr,
-            peer_selection,
-            file_metadata_cache: file_metadata_cache_local,
-            received_chunks: received_chunks_clone,
-            file_transfer_service,
-            // chunk_manager is not stored in DhtService, only passed to the task
-            pending_webrtc_offers,
-            pending_key_requests,
-            pending_provider_queries,
-            root_query_mapping,
-            active_downloads,
-            get_providers_queries: get_providers_queries_local,
-            chunk_size,
-            file_heartbeat_state,
-            seeder_heartbeats_cache,
-            pending_heartbeat_updates,
-        })
+    async fn publish_presence(&self, event: TypingEvent) -> Result<(), String> {
+        self.cmd_tx
+            .send(DhtCommand::PublishPresence(event))
+            .await
+            .map_err(|e| format!("send presence cmd: {e}"))
     }
 
-    pub fn chunk_size(&self) -> usize {
-        // Note: This might need to be adjusted if chunk_manager is the source of truth
-        self.chunk_size
+    /// How confident the AutoNAT probe scheduler is in the current NAT status
+    pub fn nat_probe_confidence(&self) -> Option<AutoNATConfidence> {
+        self.nat_scheduler.as_ref().map(AutoNATProbeScheduler::confidence)
+    }
+
+    /// Record an event that happened outside the swarm loop (e.g. a reputation
+    /// change). IDs that do not parse as peer IDs have no timeline and are skipped.
+    pub async fn record_peer_event(&self, peer_id: &str, event: PeerEvent) {
+        if let Ok(peer_id) = peer_id.parse::<PeerId>() {
+            self.peer_events.lock().await.record(peer_id, event);
+        }
+    }
+
+    /// The last `limit` events seen for `peer_id`, oldest first
+    pub async fn peer_event_history(
+        &self,
+        peer_id: &str,
+        limit: usize,
+    ) -> Result<Vec<PeerEvent>, String> {
+        let peer_id: PeerId = peer_id
+            .parse()
+            .map_err(|e| format!("Invalid peer ID: {}", e))?;
+        Ok(self.peer_events.lock().await.recent(&peer_id, limit))
+    }
+
+    /// Capabilities announced by other nodes, most recent first
+    /// Block or unblock a peer, or mute, unmute or allow-list a channel
+    pub async fn set_topic_filter(
+        &self,
+        mode: FilterMode,
+        topic_or_peer: &str,
+    ) -> Result<TopicFilterState, String> {
+        let mut filter = self.topic_filter.lock().await;
+        filter.apply(mode, topic_or_peer)?;
+        Ok(filter.state())
+    }
+
+    pub async fn node_announcements(&self) -> Vec<NodeAnnouncement> {
+        self.node_announcements.lock().await.list()
     }
 
     async fn start_file_heartbeat(&self, file_hash: &str) -> Result<(), String> {
@@ -6272,6 +8968,7 @@ impl DhtService {
         mut metadata: FileMetadata,
         ftp_sources: Option<Vec<FtpSourceInfo>>,
     ) -> Result<(), String> {
+        self.keeps_files()?;
         // Add FTP sources to metadata before publishing
         if let Some(sources) = ftp_sources {
             metadata.ftp_sources = Some(sources.into_iter().map(|s| s.for_dht_storage()).collect());
@@ -6299,6 +8996,18 @@ impl DhtService {
         Ok(())
     }
 
+    /// Announce a previously published file again from the cached metadata
+    pub async fn republish_file(&self, file_hash: &str) -> Result<(), String> {
+        let metadata = self
+            .file_metadata_cache
+            .lock()
+            .await
+            .get(file_hash)
+            .cloned()
+            .ok_or_else(|| format!("No cached metadata for {}", file_hash))?;
+        self.publish_file(metadata, None).await
+    }
+
     pub async fn stop_publishing_file(&self, file_hash: String) -> Result<(), String> {
         let file_hash_clone = file_hash.clone();
 
@@ -6358,9 +9067,11 @@ impl DhtService {
             uploader_address,
             ftp_sources: None,
             http_sources: None,
+            web_seeds: None,
             info_hash: None,
             trackers: None,
             ed2k_sources: None,
+            compressible: false,
         })
     }
 
@@ -6369,6 +9080,7 @@ impl DhtService {
         file_metadata: FileMetadata,
         download_path: String,
     ) -> Result<(), String> {
+        self.keeps_files()?;
         self.cmd_tx
             .send(DhtCommand::DownloadFile(file_metadata, download_path))
             .await
@@ -6380,6 +9092,7 @@ impl DhtService {
         metadata: FileMetadata,
         blocks: Vec<(Cid, Vec<u8>)>,
     ) -> Result<(), String> {
+        self.keeps_files()?;
         let file_hash = metadata.merkle_root.clone();
         // The root CID is the CID of the list of block CIDs.
         // This needs to be computed before calling the command.
@@ -6494,6 +9207,15 @@ impl DhtService {
         }
     }
 
+    /// Keep AutoNAT probes from dialing bootstrap nodes the pruner took out
+    /// of the active list
+    pub async fn set_quarantined_bootstrap_nodes(&self, addrs: Vec<Multiaddr>) {
+        let _ = self
+            .cmd_tx
+            .send(DhtCommand::SetQuarantinedBootstrap(addrs))
+            .await;
+    }
+
     pub async fn connect_peer(&self, addr: String) -> Result<(), String> {
         self.cmd_tx
             .send(DhtCommand::ConnectPeer(addr))
@@ -6545,6 +9267,28 @@ impl DhtService {
             .collect()
     }
 
+    /// Peers whose id contains `query` (up to ten), each with every address
+    /// known for it from the routing table, Identify and the local peer store
+    pub async fn find_peer_addrs(&self, query: String) -> Result<Vec<PeerAddrMatch>, String> {
+        let (sender, rx) = oneshot::channel();
+        self.cmd_tx
+            .send(DhtCommand::FindPeerAddrs { query, sender })
+            .await
+            .map_err(|e| e.to_string())?;
+        rx.await.map_err(|e| e.to_string())
+    }
+
+    /// Every known address of `peer_id`, best first
+    pub async fn get_peer_addrs(&self, peer_id: &PeerId) -> Result<Vec<Multiaddr>, String> {
+        Ok(self
+            .find_peer_addrs(peer_id.to_string())
+            .await?
+            .into_iter()
+            .next()
+            .map(|m| m.addrs.iter().filter_map(|a| a.parse().ok()).collect())
+            .unwrap_or_default())
+    }
+
     pub async fn get_peer_count(&self) -> usize {
         let (tx, rx) = oneshot::channel();
         if self.cmd_tx.send(DhtCommand::GetPeerCount(tx)).await.is_ok() {
@@ -6554,6 +9298,12 @@ impl DhtService {
         }
     }
 
+    /// Peers found, connecting, disconnecting and changing addresses, from
+    /// now on; an alternative to polling `get_connected_peers`
+    pub fn peer_discovery_stream(&self) -> impl futures::Stream<Item = PeerDiscoveryEvent> {
+        crate::discovery::peer_discovery_stream(self.peer_discovery.subscribe())
+    }
+
     pub async fn get_connected_peers(&self) -> Vec<String> {
         let connected_peers = self.connected_peers.lock().await;
         connected_peers
@@ -6561,6 +9311,22 @@ impl DhtService {
             .map(|peer_id| peer_id.to_string())
             .collect()
     }
+
+    /// Connected peers with the remote address of each open connection;
+    /// a relayed connection's address is the relay circuit
+    pub async fn connected_peer_addresses(&self) -> Vec<(String, Vec<Multiaddr>)> {
+        let connected_peers = self.connected_peers.lock().await;
+        let addrs = self.connected_addrs.lock().await;
+        connected_peers
+            .iter()
+            .map(|peer_id| {
+                (
+                    peer_id.to_string(),
+                    addrs.get(peer_id).cloned().unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
     
     /// Trigger a re-bootstrap to discover new peers
     /// Returns the number of new peers discovered
@@ -6840,12 +9606,109 @@ impl DhtService {
         }
     }
 
+    /// libp2p bandwidth counters, named `chiral_libp2p_*`
+    pub fn bandwidth_metrics(&self) -> &libp2p::metrics::Registry {
+        &self.bandwidth_metrics
+    }
+
+    pub fn role(&self) -> NodeRole {
+        self.role
+    }
+
+    /// Infra nodes publish, store and download no files
+    fn keeps_files(&self) -> Result<(), String> {
+        match self.role {
+            NodeRole::Infra => Err("Infra nodes keep no files".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Rules checked against incoming connections; changes apply to
+    /// connections accepted from then on
+    pub fn connection_filter(&self) -> &IncomingConnectionFilter {
+        &self.connection_filter
+    }
+
+    /// Replace the relay consent policy and allow list; open circuits stay
+    pub fn set_relay_consent(&self, policy: RelayConsentPolicy, allow_list: &[String]) {
+        self.relay_consent.update(policy, relay_consent::parse_allow_list(allow_list));
+        info!("Relay consent policy is now {:?}", policy);
+    }
+
+    /// Cached UPnP and NAT-PMP status, refreshed every five minutes
+    pub fn port_forwarding_status(&self) -> PortForwardingStatus {
+        self.port_forwarding.status()
+    }
+
     pub async fn metrics_snapshot(&self) -> DhtMetricsSnapshot {
         let metrics = self.metrics.lock().await.clone();
         let peer_count = self.connected_peers.lock().await.len();
         DhtMetricsSnapshot::from(metrics, peer_count)
     }
 
+    /// Peers, reachability, bandwidth, transfers and routing table in one
+    /// structure; what the UI and the headless status line show. Takes no
+    /// lock, so it can be polled often. All zero once the DHT has stopped.
+    pub async fn network_stats(&self) -> NetworkStats {
+        let (sender, receiver) = oneshot::channel();
+        if self.cmd_tx.send(DhtCommand::GetStats(sender)).await.is_err() {
+            return NetworkStats::default();
+        }
+        let mut stats = receiver.await.unwrap_or_default();
+        stats.memory = self.memory_usage.report();
+        stats
+    }
+
+    /// Everything the dashboard shows, in one round trip; the defaults once
+    /// the swarm task is gone
+    pub async fn dashboard_stats(&self) -> DashboardStats {
+        let (sender, receiver) = oneshot::channel();
+        if self.cmd_tx.send(DhtCommand::GetDashboardStats(sender)).await.is_err() {
+            return DashboardStats::default();
+        }
+        receiver.await.unwrap_or_default()
+    }
+
+    /// GossipSub mesh size of every subscribed topic as of the last
+    /// sample; empty once the swarm task is gone
+    pub async fn mesh_health(&self) -> Vec<MeshTopicHealth> {
+        let (sender, receiver) = oneshot::channel();
+        if self.cmd_tx.send(DhtCommand::GetMeshHealth(sender)).await.is_err() {
+            return Vec::new();
+        }
+        receiver.await.unwrap_or_default()
+    }
+
+    /// Whether the swarm task is still taking commands
+    pub fn is_running(&self) -> bool {
+        !self.cmd_tx.is_closed()
+    }
+
+    /// Resolves once the swarm task has stopped taking commands
+    pub async fn stopped(&self) {
+        self.cmd_tx.closed().await
+    }
+
+    /// Cache sizes at the last measurement, with their budget shares
+    pub fn memory_usage(&self) -> MemoryUsageReport {
+        self.memory_usage.report()
+    }
+
+    /// Bytes per transport at the last sample; zero before the first one
+    pub fn transport_stats(&self) -> TransportStats {
+        self.stats_counters.transports()
+    }
+
+    /// `network_stats` with the metrics snapshot and peer list
+    pub async fn detailed_network_stats(&self) -> Result<DetailedNetworkStats, String> {
+        let (sender, receiver) = oneshot::channel();
+        self.cmd_tx
+            .send(DhtCommand::GetDetailedStats(sender))
+            .await
+            .map_err(|e| e.to_string())?;
+        receiver.await.map_err(|e| e.to_string())
+    }
+
     pub async fn autorelay_history(
         &self,
     ) -> (Option<SystemTime>, Option<SystemTime>) {
@@ -6901,12 +9764,24 @@ impl DhtService {
     pub async fn record_transfer_success(&self, peer_id: &str, bytes: u64, duration_ms: u64) {
         let mut peer_selection = self.peer_selection.lock().await;
         peer_selection.record_transfer_success(peer_id, bytes, duration_ms);
+        if let Some(metrics) = peer_selection.get_peer_metrics(peer_id) {
+            self.connection_quality.lock().await.observe(metrics);
+        }
     }
 
     /// Record failed transfer for peer metrics
     pub async fn record_transfer_failure(&self, peer_id: &str, error: &str) {
-        let mut peer_selection = self.peer_selection.lock().await;
-        peer_selection.record_transfer_failure(peer_id, error);
+        let degraded = {
+            let mut peer_selection = self.peer_selection.lock().await;
+            peer_selection.record_transfer_failure(peer_id, error);
+            match peer_selection.get_peer_metrics(peer_id) {
+                Some(metrics) => self.connection_quality.lock().await.observe(metrics),
+                None => None,
+            }
+        };
+        if let Some(degraded) = degraded {
+            let _ = self.event_tx.send(DhtEvent::QualityDegraded(degraded)).await;
+        }
     }
 
     /// Update peer encryption support
@@ -6927,6 +9802,36 @@ impl DhtService {
         peer_selection.get_all_metrics()
     }
 
+    /// Peer metrics labelled with their connection quality
+    pub async fn get_full_peer_info(&self) -> Vec<FullPeerInfo> {
+        self.get_peer_metrics()
+            .await
+            .into_iter()
+            .map(FullPeerInfo::from)
+            .collect()
+    }
+
+    /// Connected and configured bootstrap nodes
+    pub async fn bootstrap_connections(&self) -> (usize, usize) {
+        let tracker = self.bootstrap_contributions.lock().await;
+        (tracker.connected(), tracker.len())
+    }
+
+    /// Whether the swarm task answers a command within `timeout`
+    pub async fn is_responsive(&self, timeout: Duration) -> bool {
+        let (tx, rx) = oneshot::channel();
+        let round_trip = async {
+            self.cmd_tx.send(DhtCommand::GetPeerCount(tx)).await.ok()?;
+            rx.await.ok()
+        };
+        matches!(tokio::time::timeout(timeout, round_trip).await, Ok(Some(_)))
+    }
+
+    /// Peers found through each configured bootstrap node, most helpful first
+    pub async fn get_bootstrap_contribution_stats(&self) -> Vec<BootstrapContributionStats> {
+        self.bootstrap_contributions.lock().await.stats()
+    }
+
     /// Select best peers using a specific strategy
     pub async fn select_peers_with_strategy(
         &self,
@@ -7072,6 +9977,7 @@ impl DhtService {
 
     /// Shutdown the Dht service
     pub async fn shutdown(&self) -> Result<(), String> {
+        self.nat_probe_shutdown.cancel();
         let (tx, rx) = oneshot::channel();
         self.cmd_tx
             .send(DhtCommand::Shutdown(tx))
@@ -7372,7 +10278,40 @@ impl DhtService {
             .map_err(|e| e.to_string())?;
         receiver.await.map_err(|e| e.to_string())?
     }
-}
+
+    /// Store a record on up to `replication_factor` (at most
+    /// `MAX_REPLICATION_FACTOR`, the Kademlia replication factor of 3) of the
+    /// peers closest to `key`, returning the peers that took a copy. The
+    /// record is stored again before it expires for as long as the node runs.
+    pub async fn put_replicated(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        replication_factor: u8,
+    ) -> Result<Vec<PeerId>, String> {
+        let (sender, receiver) = oneshot::channel();
+        self.cmd_tx
+            .send(DhtCommand::PutReplicated {
+                key: key.to_vec(),
+                value: value.to_vec(),
+                replication_factor,
+                sender,
+            })
+            .await
+            .map_err(|e| e.to_string())?;
+        receiver.await.map_err(|e| e.to_string())?
+    }
+
+    /// Peers last known to hold a copy of a record stored with `put_replicated`
+    pub async fn replica_holders(&self, key: &[u8]) -> Vec<String> {
+        self.replication
+            .lock()
+            .await
+            .holders(&kad::RecordKey::new(&key))
+            .iter()
+            .map(|peer| peer.to_string())
+            .collect()
+    }
 
 impl DhtService {
     /// Finds Chiral peers in the DHT that are seeding a torrent with the given info_hash.
@@ -7532,6 +10471,17 @@ fn ipv4_in_same_subnet(target: Ipv4Addr, iface_ip: Ipv4Addr, iface_mask: Ipv4Add
 /// - Relay paths (p2p-circuit) are allowed
 /// - IPv4 loopback (127.0.0.1) is REJECTED (not reachable from remote peers)
 /// - For WAN intent, only public IPv4 addresses are allowed (not private ranges)
+/// Add addresses `MultiBootstrapConsensus` released to the routing table
+/// and dial them
+fn add_released_addresses(swarm: &mut Swarm<DhtBehaviour>, peer: PeerId, addrs: Vec<Multiaddr>) {
+    for addr in addrs {
+        swarm.behaviour_mut().kademlia.add_address(&peer, addr.clone());
+        if let Err(e) = swarm.dial(addr.clone()) {
+            debug!("Failed to dial released peer {} at {}: {}", peer, addr, e);
+        }
+    }
+}
+
 fn ma_plausibly_reachable(ma: &Multiaddr) -> bool {
     // Relay paths are allowed
     if ma.iter().any(|p| matches!(p, Protocol::P2pCircuit)) {
@@ -7843,6 +10793,23 @@ mod tests {
         assert_eq!(snapshot.reachability, NatReachabilityState::Unknown);
     }
 
+    #[test]
+    fn remove_seeder_keeps_other_seeders() {
+        let mut metadata = serde_json::json!({
+            "merkle_root": "abc",
+            "seeders": ["me", "other"],
+            "seederHeartbeats": [
+                {"peerId": "me", "expiresAt": 10, "lastHeartbeat": 1},
+                {"peerId": "other", "expiresAt": 10, "lastHeartbeat": 1}
+            ]
+        });
+        remove_seeder(&mut metadata, "me");
+        assert_eq!(metadata["seeders"], serde_json::json!(["other"]));
+        assert_eq!(metadata["seederHeartbeats"].as_array().unwrap().len(), 1);
+        assert_eq!(metadata["seederHeartbeats"][0]["peerId"], "other");
+        assert_eq!(metadata["merkle_root"], "abc");
+    }
+
     #[test]
     fn metrics_snapshot_carries_listen_addrs() {
         let mut metrics = DhtMetrics::default();
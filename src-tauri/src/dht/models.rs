@@ -4,7 +4,7 @@ use std::collections::VecDeque;
 use std::time::SystemTime;
 
 // internal crate imports - assumed to exist based on original file
-use crate::download_source::HttpSourceInfo;
+use crate::download_source::{HttpSourceInfo, WebSeeds};
 use crate::encryption::EncryptedAesKeyBundle;
 
 // =========================================================================
@@ -92,6 +92,12 @@ pub struct FileMetadata {
     #[serde(default, skip_serializing_if = "Option::is_none", rename = "httpSources")]
     pub http_sources: Option<Vec<HttpSourceInfo>>,
 
+    /// Plain HTTP(S) URLs serving the whole file, used as an extra download
+    /// source so new content is available before any peer seeds it. Signed
+    /// by the publisher together with per-chunk hashes
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "webSeeds")]
+    pub web_seeds: Option<WebSeeds>,
+
     #[serde(default)]
     pub is_root: bool,
 
@@ -113,6 +119,11 @@ pub struct FileMetadata {
     /// A list of BitTorrent tracker URLs.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub trackers: Option<Vec<String>>,
+
+    /// Whether a sample of the content compressed well when it was
+    /// published, i.e. whether chunks are worth compressing on the wire
+    #[serde(default)]
+    pub compressible: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -258,6 +269,27 @@ pub struct MagnetData {
 // NAT & Network Metrics
 // =========================================================================
 
+/// What the node is run for; the `role` label of `chiral_node_info`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeRole {
+    /// Desktop or headless node of a user
+    User,
+    Bootstrap,
+    /// `--infra-mode`: DHT, AutoNAT and relay server only
+    Infra,
+}
+
+impl NodeRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeRole::User => "user",
+            NodeRole::Bootstrap => "bootstrap",
+            NodeRole::Infra => "infra",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum NatReachabilityState {
@@ -338,6 +370,26 @@ pub struct DhtMetrics {
     pub dcutr_hole_punch_failures: u64,
     pub last_dcutr_success: Option<SystemTime>,
     pub last_dcutr_failure: Option<SystemTime>,
+    /// Gossip messages dropped by the topic filter
+    pub gossip_messages_blocked: u64,
+    /// Size before and after compression of the gossip payloads published
+    /// compressed
+    pub gossip_bytes_uncompressed: u64,
+    pub gossip_bytes_compressed: u64,
+    // Exported counters
+    pub connections_opened: u64,
+    pub connections_closed: u64,
+    /// Kademlia requests answered for other peers
+    pub kad_requests_served: u64,
+    /// Records in the local Kademlia store, sampled
+    pub kad_records_stored: u64,
+    /// Peers in the Kademlia routing table, sampled
+    pub kad_routing_table_size: u64,
+    pub relay_circuits_accepted: u64,
+    pub relay_circuits_active: u64,
+    /// Direct transfers received in full or aborted by a failure
+    pub transfers_received: u64,
+    pub transfers_failed: u64,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -376,4 +428,16 @@ pub struct DhtMetricsSnapshot {
     pub dcutr_hole_punch_failures: u64,
     pub last_dcutr_success: Option<u64>,
     pub last_dcutr_failure: Option<u64>,
+    pub gossip_messages_blocked: u64,
+    pub gossip_bytes_uncompressed: u64,
+    pub gossip_bytes_compressed: u64,
+    pub connections_opened: u64,
+    pub connections_closed: u64,
+    pub kad_requests_served: u64,
+    pub kad_records_stored: u64,
+    pub kad_routing_table_size: u64,
+    pub relay_circuits_accepted: u64,
+    pub relay_circuits_active: u64,
+    pub transfers_received: u64,
+    pub transfers_failed: u64,
 }
@@ -0,0 +1,457 @@
+//! Time-boxed traces of what the swarm does, for attaching to bug reports.
+//!
+//! `start_trace` records, for the given duration, every swarm event the DHT
+//! loop handles, the protocol messages it sends and receives, connection
+//! state changes and errors. Only metadata is kept: the event name, the peer,
+//! the direction of a message and, for connections and errors, the address
+//! and reason. Request and response bodies, records and gossip data are never
+//! copied; behaviour events are named from the first few hundred characters
+//! of their `Debug` output, which is cut off before any payload is formatted.
+//! Recording costs one atomic load per event while no trace is running.
+//!
+//! Several traces may run at once; each sees every event from its start.
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use libp2p::swarm::SwarmEvent;
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Write as _};
+use std::future::Future;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Entries kept per trace; later ones are counted in `dropped`
+const MAX_ENTRIES: usize = 200_000;
+
+/// Longest trace `start_trace_command` accepts
+pub const MAX_TRACE_DURATION: Duration = Duration::from_secs(600);
+
+/// Characters of a behaviour event's `Debug` output that are looked at
+const DEBUG_PREFIX: usize = 512;
+
+static TRACES: Mutex<Vec<Recording>> = Mutex::new(Vec::new());
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TraceKind {
+    /// Listen addresses, external addresses and behaviour events
+    Swarm,
+    /// A protocol message sent or received
+    Message,
+    /// Dials and connections opening or closing
+    Connection,
+    /// Failed dials, connections, listeners and requests
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceEntry {
+    /// Milliseconds since the trace started
+    pub at_ms: u64,
+    pub kind: TraceKind,
+    /// e.g. `ConnectionEstablished` or `FileTransfer::Message`
+    pub event: String,
+    pub peer_id: Option<String>,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticTrace {
+    /// Unix milliseconds
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub entries: Vec<TraceEntry>,
+    /// Entries beyond `MAX_ENTRIES`, not kept
+    pub dropped: u64,
+}
+
+impl DiagnosticTrace {
+    /// Write the trace to `path` as gzipped JSON
+    pub fn write_compressed(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let file = std::fs::File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        serde_json::to_writer(&mut encoder, self)
+            .map_err(|e| e.to_string())
+            .and_then(|()| encoder.finish().map(drop).map_err(|e| e.to_string()))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn read_compressed(path: &Path) -> Result<Self, String> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        serde_json::from_reader(GzDecoder::new(file))
+            .map_err(|e| format!("Invalid trace {}: {}", path.display(), e))
+    }
+}
+
+struct Recording {
+    id: u64,
+    started: Instant,
+    entries: Vec<TraceEntry>,
+    dropped: u64,
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn take(id: u64) -> Option<Recording> {
+    let mut traces = TRACES.lock().unwrap_or_else(|e| e.into_inner());
+    let index = traces.iter().position(|recording| recording.id == id)?;
+    ACTIVE.fetch_sub(1, Ordering::Relaxed);
+    Some(traces.swap_remove(index))
+}
+
+/// Stops the recording when the trace future is dropped early
+struct Registration(u64);
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        take(self.0);
+    }
+}
+
+/// Record for `duration`, then return what was seen
+///
+/// Recording starts when this is called, not when the future is first
+/// polled.
+pub fn start_trace(duration: Duration) -> impl Future<Output = DiagnosticTrace> {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let started_at = unix_ms();
+    TRACES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(Recording {
+            id,
+            started: Instant::now(),
+            entries: Vec::new(),
+            dropped: 0,
+        });
+    ACTIVE.fetch_add(1, Ordering::Relaxed);
+    let registration = Registration(id);
+
+    async move {
+        tokio::time::sleep(duration).await;
+        let (entries, dropped) = take(registration.0)
+            .map(|recording| (recording.entries, recording.dropped))
+            .unwrap_or_default();
+        DiagnosticTrace {
+            started_at,
+            duration_ms: duration.as_millis() as u64,
+            entries,
+            dropped,
+        }
+    }
+}
+
+/// Whether a trace is running, so callers can skip building an entry
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed) > 0
+}
+
+pub fn record(
+    kind: TraceKind,
+    event: impl Into<String>,
+    peer_id: Option<String>,
+    detail: Option<String>,
+) {
+    if !is_active() {
+        return;
+    }
+    let event = event.into();
+    let mut traces = TRACES.lock().unwrap_or_else(|e| e.into_inner());
+    for recording in traces.iter_mut() {
+        if recording.entries.len() >= MAX_ENTRIES {
+            recording.dropped += 1;
+            continue;
+        }
+        recording.entries.push(TraceEntry {
+            at_ms: recording.started.elapsed().as_millis() as u64,
+            kind,
+            event: event.clone(),
+            peer_id: peer_id.clone(),
+            detail: detail.clone(),
+        });
+    }
+}
+
+/// Record a request the node sent; `protocol` is the behaviour name as in
+/// `DhtBehaviourEvent`, e.g. `FileTransfer`
+pub fn sent_request(protocol: &str, peer: &PeerId) {
+    if is_active() {
+        record(
+            TraceKind::Message,
+            format!("{}::Request", protocol),
+            Some(peer.to_string()),
+            Some("sent request".to_string()),
+        );
+    }
+}
+
+/// Record a gossip message the node published on `topic`
+pub fn sent_message(topic: &str, bytes: usize) {
+    if is_active() {
+        record(
+            TraceKind::Message,
+            "Gossipsub::Publish",
+            None,
+            Some(format!("sent on {}, {} bytes", topic, bytes)),
+        );
+    }
+}
+
+/// Pass a swarm event through, recording it if a trace is running
+pub fn traced<E: fmt::Debug>(event: SwarmEvent<E>) -> SwarmEvent<E> {
+    if is_active() {
+        record_swarm_event(&event);
+    }
+    event
+}
+
+fn record_swarm_event<E: fmt::Debug>(event: &SwarmEvent<E>) {
+    let peer = |peer_id: &PeerId| Some(peer_id.to_string());
+    match event {
+        SwarmEvent::Behaviour(event) => {
+            let (kind, name, peer_id, detail) = describe_behaviour_event(&debug_prefix(event));
+            record(kind, name, peer_id, detail);
+        }
+        SwarmEvent::ConnectionEstablished {
+            peer_id,
+            endpoint,
+            num_established,
+            ..
+        } => record(
+            TraceKind::Connection,
+            "ConnectionEstablished",
+            peer(peer_id),
+            Some(format!(
+                "{} {}, {} open",
+                if endpoint.is_dialer() {
+                    "dialed"
+                } else {
+                    "accepted"
+                },
+                endpoint.get_remote_address(),
+                num_established
+            )),
+        ),
+        SwarmEvent::ConnectionClosed {
+            peer_id,
+            endpoint,
+            num_established,
+            cause,
+            ..
+        } => record(
+            TraceKind::Connection,
+            "ConnectionClosed",
+            peer(peer_id),
+            Some(format!(
+                "{}: {}, {} open",
+                endpoint.get_remote_address(),
+                cause
+                    .as_ref()
+                    .map(|e| e.to_string())
+                    .unwrap_or_else(|| "graceful".to_string()),
+                num_established
+            )),
+        ),
+        SwarmEvent::IncomingConnection { send_back_addr, .. } => record(
+            TraceKind::Connection,
+            "IncomingConnection",
+            None,
+            Some(send_back_addr.to_string()),
+        ),
+        SwarmEvent::Dialing { peer_id, .. } => record(
+            TraceKind::Connection,
+            "Dialing",
+            peer_id.as_ref().and_then(peer),
+            None,
+        ),
+        SwarmEvent::IncomingConnectionError {
+            send_back_addr,
+            error,
+            ..
+        } => record(
+            TraceKind::Error,
+            "IncomingConnectionError",
+            None,
+            Some(format!("{}: {}", send_back_addr, error)),
+        ),
+        SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => record(
+            TraceKind::Error,
+            "OutgoingConnectionError",
+            peer_id.as_ref().and_then(peer),
+            Some(error.to_string()),
+        ),
+        SwarmEvent::ListenerError { error, .. } => record(
+            TraceKind::Error,
+            "ListenerError",
+            None,
+            Some(error.to_string()),
+        ),
+        SwarmEvent::ListenerClosed {
+            reason: Err(error), ..
+        } => record(
+            TraceKind::Error,
+            "ListenerClosed",
+            None,
+            Some(error.to_string()),
+        ),
+        SwarmEvent::NewListenAddr { address, .. }
+        | SwarmEvent::ExpiredListenAddr { address, .. }
+        | SwarmEvent::NewExternalAddrCandidate { address }
+        | SwarmEvent::ExternalAddrConfirmed { address }
+        | SwarmEvent::ExternalAddrExpired { address } => {
+            let name = describe_behaviour_event(&debug_prefix(event)).1;
+            record(TraceKind::Swarm, name, None, Some(address.to_string()))
+        }
+        other => {
+            let name = describe_behaviour_event(&debug_prefix(other)).1;
+            record(TraceKind::Swarm, name, None, None)
+        }
+    }
+}
+
+/// Collects `Debug` output up to `DEBUG_PREFIX` and then fails, which makes
+/// the formatter stop before reaching large payloads
+struct Prefix(String);
+
+impl fmt::Write for Prefix {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let room = DEBUG_PREFIX - self.0.len();
+        if s.len() <= room {
+            self.0.push_str(s);
+            return Ok(());
+        }
+        let mut end = room;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.0.push_str(&s[..end]);
+        Err(fmt::Error)
+    }
+}
+
+fn debug_prefix(value: &impl fmt::Debug) -> String {
+    let mut prefix = Prefix(String::new());
+    let _ = write!(prefix, "{:?}", value);
+    prefix.0
+}
+
+fn leading_name(s: &str) -> &str {
+    let s = s.trim_start();
+    let end = s
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(s.len());
+    &s[..end]
+}
+
+/// Kind, name, peer and direction of a behaviour event, from the start of
+/// its `Debug` output, e.g. `FileTransfer(Message { peer: PeerId("12D3…"),
+/// …, message: Request { … } })`
+fn describe_behaviour_event(debug: &str) -> (TraceKind, String, Option<String>, Option<String>) {
+    let behaviour = leading_name(debug);
+    let rest = &debug[behaviour.len()..];
+    let inner = rest
+        .strip_prefix('(')
+        .map(leading_name)
+        .filter(|inner| !inner.is_empty());
+    let name = match inner {
+        Some(inner) => format!("{}::{}", behaviour, inner),
+        None => behaviour.to_string(),
+    };
+    let peer_id = debug
+        .split_once("PeerId(\"")
+        .and_then(|(_, rest)| rest.split_once('"'))
+        .map(|(peer, _)| peer.to_string());
+
+    let inner = inner.unwrap_or_default();
+    let (kind, detail) = if inner == "Message" {
+        let direction = match debug
+            .split_once("message: ")
+            .map(|(_, rest)| leading_name(rest))
+        {
+            Some("Request") => "received request",
+            Some("Response") => "received response",
+            _ => "received",
+        };
+        (TraceKind::Message, Some(direction.to_string()))
+    } else if inner == "ResponseSent" {
+        (TraceKind::Message, Some("sent response".to_string()))
+    } else if inner.contains("Failure") || inner.contains("Error") {
+        (TraceKind::Error, None)
+    } else {
+        (TraceKind::Swarm, None)
+    };
+    (kind, name, peer_id, detail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_trace_keeps_metadata_only() {
+        let peer = PeerId::random();
+        let secret = "x".repeat(4 * DEBUG_PREFIX);
+        let debug = format!(
+            "FileTransfer(Message {{ peer: PeerId(\"{}\"), message: Request {{ data: \"{}\" }} }})",
+            peer, secret
+        );
+        let prefix = debug_prefix(&debug);
+        assert!(prefix.len() <= DEBUG_PREFIX);
+
+        let (kind, name, peer_id, detail) = describe_behaviour_event(&debug);
+        assert_eq!(kind, TraceKind::Message);
+        assert_eq!(name, "FileTransfer::Message");
+        assert_eq!(peer_id, Some(peer.to_string()));
+        assert_eq!(detail.as_deref(), Some("received request"));
+        assert_eq!(
+            describe_behaviour_event("Ping(Event { result: Err(Timeout) })").1,
+            "Ping::Event"
+        );
+        assert_eq!(
+            describe_behaviour_event("KeyRequest(OutboundFailure { .. })").0,
+            TraceKind::Error
+        );
+
+        let trace = start_trace(Duration::from_millis(50));
+        sent_request("FileTransfer", &peer);
+        sent_message("chiral/status", 42);
+        let trace = trace.await;
+        assert!(!is_active());
+        let entry = trace
+            .entries
+            .iter()
+            .find(|entry| entry.peer_id == peer_id)
+            .expect("the request should be traced");
+        assert_eq!(entry.event, "FileTransfer::Request");
+        assert!(trace
+            .entries
+            .iter()
+            .any(|entry| entry.event == "Gossipsub::Publish"
+                && entry.detail.as_deref() == Some("sent on chiral/status, 42 bytes")));
+        assert!(!serde_json::to_string(&trace).unwrap().contains(&secret));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.json.gz");
+        trace.write_compressed(&path).unwrap();
+        assert_eq!(DiagnosticTrace::read_compressed(&path).unwrap(), trace);
+    }
+}
@@ -0,0 +1,579 @@
+//! One zip file with what a bug report needs.
+//!
+//! `collect` gathers the app and OS version, the effective configuration
+//! with secrets redacted, the newest log files, the network stats, DHT
+//! metrics (reachability history, bootstrap and hole punching), a routing
+//! table summary and the active transfers. Transfers are listed by content
+//! hash; file names and paths, including paths in the configuration, are
+//! only kept with `include_paths`. File contents are never added.
+//!
+//! The identity key never ends up in a bundle. Nothing is read from the
+//! data directory except log files, and before the zip is written every
+//! entry is searched for the identity seed, the private key derived from it
+//! (raw, hex and base64, alone and in its protobuf encoding) and the API
+//! token. Any occurrence is replaced with `<redacted>`. `SecretRedactor`
+//! applies the same rules to lines returned by `tail_logs`.
+
+use crate::config::ChiralConfig;
+use crate::data_dirs::DataDirs;
+use crate::dht::{keypair_from_secret, DhtService};
+use crate::download_restart::{DownloadState, DownloadStatus};
+use crate::multi_source_download::MultiSourceProgress;
+use crate::node_commands;
+use base64::Engine;
+use serde::Serialize;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Newest log files added to a bundle
+pub const MAX_LOG_FILES: usize = 3;
+
+/// Bytes kept from the end of each log file
+pub const MAX_LOG_BYTES: u64 = 4 * 1024 * 1024;
+
+const REDACTED: &[u8] = b"<redacted>";
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BundleOptions {
+    /// Keep file names and paths of transfers and in the configuration
+    pub include_paths: bool,
+}
+
+/// What `export_diagnostics` wrote, or would write
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleReport {
+    pub path: String,
+    /// Size of the zip file
+    pub size_bytes: u64,
+    pub files: Vec<BundleFile>,
+    /// Occurrences of secrets replaced while building the bundle
+    pub redactions: usize,
+    pub written: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleFile {
+    pub name: String,
+    /// Uncompressed size
+    pub size_bytes: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SystemInfo {
+    app_version: &'static str,
+    os: &'static str,
+    arch: &'static str,
+    os_version: Option<String>,
+    kernel_version: Option<String>,
+    generated_at: u64,
+}
+
+/// A transfer by content hash
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TransferSummary {
+    file_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_name: Option<String>,
+    downloaded_bytes: u64,
+    total_bytes: u64,
+    completed_chunks: u32,
+    total_chunks: u32,
+    active_sources: usize,
+    download_speed_bps: f64,
+}
+
+/// A restartable HTTP download by id and expected hash; its URL and
+/// destination are never added, and its last error only with
+/// `include_paths` since it can name either
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RestartableSummary {
+    download_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_hash: Option<String>,
+    state: DownloadState,
+    downloaded_bytes: u64,
+    total_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RoutingTableSummary {
+    routing_table_size: u64,
+    connected_peers: usize,
+    /// Entries in each non-empty k-bucket, nearest bucket first
+    kbucket_sizes: Vec<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HolePunchStats {
+    dcutr_enabled: bool,
+    attempts: u64,
+    successes: u64,
+    failures: u64,
+    last_success: Option<u64>,
+    last_failure: Option<u64>,
+}
+
+/// Secrets that must never leave the node, and where they are replaced
+/// with `<redacted>` in text that does: bundles and `tail_logs`
+#[derive(Debug, Clone, Default)]
+pub struct SecretRedactor {
+    /// Longest first, so an encoding is replaced whole before the key
+    /// inside it is
+    forbidden: Vec<Vec<u8>>,
+}
+
+impl SecretRedactor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The identity and API token of `data_dirs`
+    pub fn for_identity(data_dirs: &DataDirs) -> Self {
+        let mut redactor = Self::new();
+        redactor.forbid_identity(data_dirs);
+        redactor
+    }
+
+    /// Never let `secret` through
+    pub fn forbid(&mut self, secret: impl AsRef<[u8]>) {
+        let secret = secret.as_ref();
+        // Shorter strings would match by chance
+        if secret.len() >= 8 && !self.forbidden.iter().any(|s| s == secret) {
+            let at = self.forbidden.partition_point(|s| s.len() >= secret.len());
+            self.forbidden.insert(at, secret.to_vec());
+        }
+    }
+
+    /// Forbid the identity seed of `data_dirs`, the private key derived
+    /// from it in every encoding a log line could show, and the API token
+    pub fn forbid_identity(&mut self, data_dirs: &DataDirs) {
+        if let Ok(seed) = std::fs::read_to_string(data_dirs.identity_file()) {
+            self.forbid_seed(seed.trim());
+        }
+        if let Ok(token) = std::fs::read_to_string(data_dirs.root().join("api.token")) {
+            self.forbid(token.trim());
+        }
+    }
+
+    /// Forbid `seed` and the keypair `keypair_from_secret` derives from it
+    pub fn forbid_seed(&mut self, seed: &str) {
+        if seed.is_empty() {
+            return;
+        }
+        self.forbid(seed);
+        let Ok(keypair) = keypair_from_secret(Some(seed)) else {
+            return;
+        };
+        let b64 = base64::engine::general_purpose::STANDARD;
+        if let Ok(ed25519) = keypair.clone().try_into_ed25519() {
+            let secret = ed25519.secret();
+            let secret = secret.as_ref();
+            self.forbid(secret);
+            self.forbid(hex::encode(secret));
+            self.forbid(b64.encode(secret));
+        }
+        if let Ok(encoded) = keypair.to_protobuf_encoding() {
+            self.forbid(hex::encode(&encoded));
+            self.forbid(b64.encode(&encoded));
+            self.forbid(encoded);
+        }
+    }
+
+    /// Replace every forbidden secret in `bytes`; returns how many
+    /// occurrences were replaced
+    pub fn redact(&self, bytes: &mut Vec<u8>) -> usize {
+        self.forbidden
+            .iter()
+            .map(|secret| redact(bytes, secret))
+            .sum()
+    }
+
+    /// `text` with every forbidden secret replaced
+    pub fn redact_str(&self, text: &str) -> String {
+        if self.forbidden.is_empty() {
+            return text.to_string();
+        }
+        let mut bytes = text.as_bytes().to_vec();
+        if self.redact(&mut bytes) == 0 {
+            return text.to_string();
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+#[derive(Default)]
+pub struct DiagnosticsBundle {
+    entries: Vec<(String, Vec<u8>)>,
+    redactor: SecretRedactor,
+}
+
+impl DiagnosticsBundle {
+    pub fn new(redactor: SecretRedactor) -> Self {
+        Self {
+            entries: Vec::new(),
+            redactor,
+        }
+    }
+
+    pub fn add_json(&mut self, name: &str, value: &impl Serialize) {
+        let bytes = serde_json::to_vec_pretty(value)
+            .unwrap_or_else(|e| format!("<unserializable: {}>", e).into_bytes());
+        self.entries.push((name.to_string(), bytes));
+    }
+
+    pub fn add_bytes(&mut self, name: &str, bytes: Vec<u8>) {
+        self.entries.push((name.to_string(), bytes));
+    }
+
+    /// The last `MAX_LOG_BYTES` of each of the newest `MAX_LOG_FILES` log
+    /// files in `logs_dir`, under `logs/`
+    pub fn add_logs(&mut self, logs_dir: &Path) {
+        let files = crate::logger::recent_log_files(logs_dir).unwrap_or_default();
+        for path in files.into_iter().take(MAX_LOG_FILES) {
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            match read_tail(&path, MAX_LOG_BYTES) {
+                Ok(bytes) => self.add_bytes(&format!("logs/{}", name), bytes),
+                Err(e) => self.add_bytes(
+                    &format!("logs/{}.error", name),
+                    format!("unreadable: {}", e).into_bytes(),
+                ),
+            }
+        }
+    }
+
+    /// Entry names with their uncompressed sizes
+    pub fn files(&self) -> Vec<BundleFile> {
+        self.entries
+            .iter()
+            .map(|(name, bytes)| BundleFile {
+                name: name.clone(),
+                size_bytes: bytes.len() as u64,
+            })
+            .collect()
+    }
+
+    /// The zip file, with every secret of the redactor replaced; also returns
+    /// the number of replacements
+    pub fn finish(mut self) -> Result<(Vec<u8>, usize), String> {
+        let mut redactions = 0;
+        for (_, bytes) in &mut self.entries {
+            redactions += self.redactor.redact(bytes);
+        }
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (name, bytes) in &self.entries {
+            zip.start_file(name.as_str(), options)
+                .map_err(|e| format!("Failed to add {}: {}", name, e))?;
+            zip.write_all(bytes)
+                .map_err(|e| format!("Failed to add {}: {}", name, e))?;
+        }
+        let cursor = zip
+            .finish()
+            .map_err(|e| format!("Failed to finish the bundle: {}", e))?;
+        Ok((cursor.into_inner(), redactions))
+    }
+}
+
+/// Gather a bundle from the running node, if any, and the data directory
+pub async fn collect(
+    dht: Option<&DhtService>,
+    downloads: Vec<DownloadStatus>,
+    transfers: Vec<MultiSourceProgress>,
+    config: &ChiralConfig,
+    data_dirs: &DataDirs,
+    options: BundleOptions,
+) -> DiagnosticsBundle {
+    let mut bundle = DiagnosticsBundle::new(SecretRedactor::for_identity(data_dirs));
+
+    bundle.add_json(
+        "system.json",
+        &SystemInfo {
+            app_version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            os_version: sysinfo::System::long_os_version(),
+            kernel_version: sysinfo::System::kernel_version(),
+            generated_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        },
+    );
+
+    let mut config = config.clone();
+    config.swarm = config.swarm.redacted();
+    if !options.include_paths {
+        config.swarm.event_log_path = None;
+    }
+    bundle.add_json("config.json", &config);
+
+    if let Some(dht) = dht {
+        bundle.add_json(
+            "network/stats.json",
+            &node_commands::dashboard_stats(dht).await,
+        );
+        bundle.add_json(
+            "network/bootstrap.json",
+            &node_commands::bootstrap_status(dht).await,
+        );
+        let metrics = node_commands::health(dht).await;
+        bundle.add_json(
+            "network/reachability_history.json",
+            &metrics.reachability_history,
+        );
+        bundle.add_json(
+            "network/hole_punch.json",
+            &HolePunchStats {
+                dcutr_enabled: metrics.dcutr_enabled,
+                attempts: metrics.dcutr_hole_punch_attempts,
+                successes: metrics.dcutr_hole_punch_successes,
+                failures: metrics.dcutr_hole_punch_failures,
+                last_success: metrics.last_dcutr_success,
+                last_failure: metrics.last_dcutr_failure,
+            },
+        );
+        if let Ok(detailed) = node_commands::detailed_network_stats(dht).await {
+            bundle.add_json(
+                "network/routing_table.json",
+                &RoutingTableSummary {
+                    routing_table_size: detailed.metrics.kad_routing_table_size,
+                    connected_peers: detailed.connected_peers.len(),
+                    kbucket_sizes: detailed.kbucket_sizes,
+                },
+            );
+        }
+        bundle.add_json("network/metrics.json", &metrics);
+    }
+
+    let transfers: Vec<TransferSummary> = transfers
+        .into_iter()
+        .map(|progress| TransferSummary {
+            file_hash: progress.file_hash,
+            file_name: options.include_paths.then_some(progress.file_name),
+            downloaded_bytes: progress.downloaded_size,
+            total_bytes: progress.total_size,
+            completed_chunks: progress.completed_chunks,
+            total_chunks: progress.total_chunks,
+            active_sources: progress.active_sources,
+            download_speed_bps: progress.download_speed_bps,
+        })
+        .collect();
+    let downloads: Vec<RestartableSummary> = downloads
+        .into_iter()
+        .map(|status| RestartableSummary {
+            download_id: status.download_id,
+            file_hash: status.expected_sha256,
+            state: status.state,
+            downloaded_bytes: status.bytes_downloaded,
+            total_bytes: status.expected_size,
+            last_error: status.last_error.filter(|_| options.include_paths),
+        })
+        .collect();
+    bundle.add_json(
+        "transfers.json",
+        &serde_json::json!({ "multiSource": transfers, "restartable": downloads }),
+    );
+
+    bundle.add_logs(&data_dirs.logs());
+    bundle
+}
+
+/// Write `bundle` to `path` as a zip file, or with `dry_run` only report
+/// what would be written
+pub fn export(
+    bundle: DiagnosticsBundle,
+    path: &Path,
+    dry_run: bool,
+) -> Result<BundleReport, String> {
+    let files = bundle.files();
+    let (zip, redactions) = bundle.finish()?;
+    if !dry_run {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options
+            .open(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        file.write_all(&zip)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(BundleReport {
+        path: path.display().to_string(),
+        size_bytes: zip.len() as u64,
+        files,
+        redactions,
+        written: !dry_run,
+    })
+}
+
+fn read_tail(path: &Path, max_bytes: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(max_bytes)))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Replace every occurrence of `secret` in `bytes`; returns how many
+fn redact(bytes: &mut Vec<u8>, secret: &[u8]) -> usize {
+    let mut count = 0;
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(secret) {
+            out.extend_from_slice(REDACTED);
+            i += secret.len();
+            count += 1;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    if count > 0 {
+        *bytes = out;
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    #[tokio::test]
+    async fn test_bundle_never_contains_identity_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dirs = DataDirs::new(dir.path());
+        data_dirs.create().unwrap();
+        let seed = "5f1c0de9a7b34e2c8d6f0a1b2c3d4e5f60718293a4b5c6d7e8f9011223344556";
+        std::fs::write(data_dirs.identity_file(), format!("{}\n", seed)).unwrap();
+        std::fs::write(dir.path().join("keystore.json"), seed).unwrap();
+
+        let keypair = keypair_from_secret(Some(seed)).unwrap();
+        let secret = keypair
+            .clone()
+            .try_into_ed25519()
+            .unwrap()
+            .secret()
+            .as_ref()
+            .to_vec();
+        let protobuf = keypair.to_protobuf_encoding().unwrap();
+        let b64 = base64::engine::general_purpose::STANDARD;
+        // A log that leaked the key in every spelling
+        std::fs::write(
+            data_dirs.logs().join("chiral_20260101_000000.log"),
+            format!(
+                "seed={} key={} pb={} pb64={} before-raw:",
+                seed,
+                hex::encode(&secret),
+                hex::encode(&protobuf),
+                b64.encode(&protobuf)
+            )
+            .into_bytes()
+            .into_iter()
+            .chain(secret.iter().copied())
+            .collect::<Vec<u8>>(),
+        )
+        .unwrap();
+        // Not a log file, so never read
+        std::fs::write(data_dirs.logs().join("identity"), seed).unwrap();
+
+        let bundle = collect(
+            None,
+            Vec::new(),
+            Vec::new(),
+            &ChiralConfig::default(),
+            &data_dirs,
+            BundleOptions {
+                include_paths: true,
+            },
+        )
+        .await;
+        let names: Vec<String> = bundle.files().into_iter().map(|file| file.name).collect();
+        assert!(names.contains(&"system.json".to_string()));
+        assert!(names.contains(&"logs/chiral_20260101_000000.log".to_string()));
+        assert!(!names
+            .iter()
+            .any(|name| name.contains("identity") || name.contains("keystore")));
+
+        let path = dir.path().join("bundle.zip");
+        let preview = export(bundle, &path, true).unwrap();
+        assert!(!preview.written && !path.exists());
+        assert!(preview.size_bytes > 0);
+        assert_eq!(preview.redactions, 5);
+
+        let download = DownloadStatus {
+            download_id: "download-1".to_string(),
+            state: DownloadState::Failed,
+            bytes_downloaded: 10,
+            expected_size: Some(100),
+            etag: None,
+            lease_exp: None,
+            last_error: Some("source error: https://example.com/private.iso".to_string()),
+            expected_sha256: Some("ab".repeat(32)),
+        };
+        let bundle = collect(
+            None,
+            vec![download],
+            Vec::new(),
+            &ChiralConfig::default(),
+            &data_dirs,
+            BundleOptions::default(),
+        )
+        .await;
+        let report = export(bundle, &path, false).unwrap();
+        assert_eq!(report.size_bytes, std::fs::metadata(&path).unwrap().len());
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let forbidden: Vec<Vec<u8>> = vec![
+            seed.as_bytes().to_vec(),
+            secret.clone(),
+            hex::encode(&secret).into_bytes(),
+            b64.encode(&secret).into_bytes(),
+            protobuf.clone(),
+            hex::encode(&protobuf).into_bytes(),
+            b64.encode(&protobuf).into_bytes(),
+        ];
+        let mut transfers = String::new();
+        archive
+            .by_name("transfers.json")
+            .unwrap()
+            .read_to_string(&mut transfers)
+            .unwrap();
+        assert!(transfers.contains("download-1") && transfers.contains(&"ab".repeat(32)));
+        assert!(!transfers.contains("example.com"));
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).unwrap();
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).unwrap();
+            for secret in &forbidden {
+                assert!(
+                    !contains(&contents, secret),
+                    "{} holds key material",
+                    entry.name()
+                );
+            }
+        }
+    }
+}
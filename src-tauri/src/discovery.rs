@@ -0,0 +1,2375 @@
+// Local peer address cache
+//
+// mDNS only advertises the addresses a peer announced on the LAN segment,
+// which is often incomplete (one interface, or a resolution that raced the
+// announcement). Every address learned for a peer through identify is kept in
+// a small SQLite database; when mDNS discovers a peer again the cache
+// supplies the full known set so the dial can fall back to the others.
+// With `[storage] encrypt_peer_store` the same data is kept in an
+// `EncryptedPeerStore` instead, and any plain database is migrated into it
+// and deleted.
+//
+// `PeerStore` answers "where can I dial this peer" from the routing table,
+// Identify and the cache together, and finds peers by partial id.
+//
+// `BootstrapMode` picks where the first peers come from: the bootstrap
+// nodes, mDNS alone (for networks without any bootstrap node) or a fixed
+// list of peers that are the only ones dialed (for air-gapped networks).
+//
+// `BootstrapFallbackChain` decides which bootstrap node to dial next: nodes
+// are tried one at a time in priority order until one connects, then the
+// rest are dialed in parallel as extra connections.
+// `BootstrapContributionTracker` counts the peers each node's lookups
+// returned, so nodes that never help can be dropped from the config.
+// `BootstrapNodePruner` quarantines nodes that stay unreachable for many
+// health checks, so they are no longer dialed and only probed now and then.
+//
+// `KadRateLimiter` meters the Kademlia queries this node starts (periodic
+// and recovery bootstraps, closest-peer lookups, record republishing) with a token bucket, so a burst of discovered
+// peers does not turn into a burst of FIND_NODE requests. Queries over the
+// limit wait in a queue; the limit is higher for the first seconds of the
+// run so the initial bootstrap is not slowed down.
+//
+// `PeerDiscoveryFeed` reports peers being found, connecting, disconnecting
+// and announcing new addresses as they happen, to any number of
+// subscribers, so callers need not poll the connected peer list.
+//
+// Nodes also announce their capabilities (protocols, relay capacity) on the
+// `chiral/announce/v1` gossipsub topic. This carries application-level
+// detail that Identify does not, and reaches peers we are not connected to.
+
+use crate::compatibility::PeerCapabilitySet;
+use crate::encrypted_peer_store::{self, EncryptedPeerStore, PeerStoreError};
+use libp2p::gossipsub::IdentTopic;
+use libp2p::kad::RecordKey;
+use libp2p::{Multiaddr, PeerId};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+
+/// Addresses kept per peer; the least recently seen are dropped first
+pub const DEFAULT_MAX_ADDRESSES_PER_PEER: u32 = 16;
+
+/// Addresses not seen for this long are pruned on open
+pub const DEFAULT_ADDRESS_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+enum Backend {
+    Plain(Mutex<Connection>),
+    Encrypted(EncryptedPeerStore),
+}
+
+/// SQLite-backed store of every address learned for a peer
+pub struct LocalDiscoveryCache {
+    backend: Backend,
+    max_addresses_per_peer: u32,
+}
+
+/// Surface an encrypted store error through the plain cache's error type
+fn to_sqlite_error(e: PeerStoreError) -> rusqlite::Error {
+    match e {
+        PeerStoreError::Storage(e) => e,
+        other => rusqlite::Error::ToSqlConversionFailure(Box::new(other)),
+    }
+}
+
+impl LocalDiscoveryCache {
+    /// Default location inside the application data directory
+    pub fn default_path() -> PathBuf {
+        crate::data_dirs::DataDirs::current().peer_addresses()
+    }
+
+    /// Open (or create) the cache at `db_path`, pruning stale addresses
+    pub fn open(db_path: &Path) -> rusqlite::Result<Self> {
+        if let Some(parent) = db_path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                warn!("Failed to create discovery cache directory {:?}: {}", parent, e);
+            }
+        }
+
+        let conn = Connection::open(db_path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS peer_addresses (
+                peer_id   TEXT NOT NULL,
+                address   TEXT NOT NULL,
+                last_seen INTEGER NOT NULL,
+                PRIMARY KEY (peer_id, address)
+            );",
+        )?;
+
+        let cache = Self {
+            backend: Backend::Plain(Mutex::new(conn)),
+            max_addresses_per_peer: DEFAULT_MAX_ADDRESSES_PER_PEER,
+        };
+        let pruned = cache.prune(DEFAULT_ADDRESS_TTL)?;
+        debug!("Opened discovery cache at {:?} ({} stale addresses pruned)", db_path, pruned);
+        Ok(cache)
+    }
+
+    /// Open (or create) an encrypted cache at `db_path`, pruning stale addresses
+    pub fn open_encrypted(db_path: &Path, key: &[u8; 32]) -> encrypted_peer_store::Result<Self> {
+        let store = EncryptedPeerStore::open(db_path, key)?;
+        let pruned = store.prune(DEFAULT_ADDRESS_TTL)?;
+        debug!(
+            "Opened encrypted discovery cache at {:?} ({} stale addresses pruned)",
+            db_path, pruned
+        );
+        Ok(Self {
+            backend: Backend::Encrypted(store),
+            max_addresses_per_peer: DEFAULT_MAX_ADDRESSES_PER_PEER,
+        })
+    }
+
+    fn lock(conn: &Mutex<Connection>) -> std::sync::MutexGuard<'_, Connection> {
+        conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Copy every address of the plain cache at `plain_path` into this one,
+    /// returning how many were copied. The plain database is left in place;
+    /// remove it with [`Self::remove_database`].
+    pub fn migrate_plain(&self, plain_path: &Path) -> rusqlite::Result<usize> {
+        if !plain_path.exists() {
+            return Ok(0);
+        }
+        let plain = Self::open(plain_path)?;
+        let Backend::Plain(conn) = &plain.backend else {
+            return Ok(0);
+        };
+        // Oldest first, so the per-peer cap keeps the most recently seen
+        let rows = Self::lock(conn)
+            .prepare("SELECT peer_id, address FROM peer_addresses ORDER BY last_seen ASC, rowid ASC")?
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut migrated = 0;
+        for (peer, address) in rows {
+            let (Ok(peer), Ok(address)) = (peer.parse::<PeerId>(), address.parse::<Multiaddr>()) else {
+                continue;
+            };
+            self.record_addresses(&peer, &[address])?;
+            migrated += 1;
+        }
+        Ok(migrated)
+    }
+
+    /// Delete the SQLite database at `db_path` with its WAL and shared-memory files
+    pub fn remove_database(db_path: &Path) -> std::io::Result<()> {
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = db_path.as_os_str().to_owned();
+            path.push(suffix);
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Remember `addresses` for `peer_id`, refreshing ones already known
+    pub fn record_addresses(&self, peer_id: &PeerId, addresses: &[Multiaddr]) -> rusqlite::Result<()> {
+        if addresses.is_empty() {
+            return Ok(());
+        }
+        let addresses = crate::multiaddr::normalize_all(addresses);
+        let conn = match &self.backend {
+            Backend::Plain(conn) => conn,
+            Backend::Encrypted(store) => {
+                return store
+                    .record_addresses(peer_id, &addresses)
+                    .map_err(to_sqlite_error)
+            }
+        };
+        let peer = peer_id.to_string();
+        let now = now_secs();
+
+        let mut conn = Self::lock(conn);
+        let tx = conn.transaction()?;
+        for address in &addresses {
+            tx.execute(
+                "INSERT INTO peer_addresses (peer_id, address, last_seen) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(peer_id, address) DO UPDATE SET last_seen = excluded.last_seen",
+                params![peer, address.to_string(), now],
+            )?;
+        }
+        tx.execute(
+            "DELETE FROM peer_addresses
+             WHERE peer_id = ?1 AND address NOT IN (
+                 SELECT address FROM peer_addresses WHERE peer_id = ?1
+                 ORDER BY last_seen DESC, rowid DESC LIMIT ?2
+             )",
+            params![peer, self.max_addresses_per_peer as i64],
+        )?;
+        tx.commit()
+    }
+
+    /// Every known address for `peer_id`, most recently seen first
+    pub fn known_addresses(&self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.known_addresses_seen(peer_id)
+            .into_iter()
+            .map(|(address, _)| address)
+            .collect()
+    }
+
+    /// Every known address for `peer_id` with when it was last seen (unix
+    /// seconds), most recently seen first
+    pub fn known_addresses_seen(&self, peer_id: &PeerId) -> Vec<(Multiaddr, i64)> {
+        let conn = match &self.backend {
+            Backend::Plain(conn) => Self::lock(conn),
+            Backend::Encrypted(store) => return store.known_addresses_seen(peer_id),
+        };
+        let result = conn
+            .prepare(
+                "SELECT address, last_seen FROM peer_addresses WHERE peer_id = ?1
+                 ORDER BY last_seen DESC, rowid DESC",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_map(params![peer_id.to_string()], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+            });
+
+        match result {
+            Ok(rows) => rows
+                .into_iter()
+                .filter_map(|(a, last_seen)| a.parse().ok().map(|a| (a, last_seen)))
+                .collect(),
+            Err(e) => {
+                warn!("Failed to load cached addresses for {}: {}", peer_id, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Fold the write-ahead log into the database file, e.g. before exiting
+    pub fn checkpoint(&self) -> rusqlite::Result<()> {
+        match &self.backend {
+            Backend::Plain(conn) => Self::lock(conn).execute_batch("PRAGMA wal_checkpoint(TRUNCATE);"),
+            Backend::Encrypted(store) => store.checkpoint().map_err(to_sqlite_error),
+        }
+    }
+
+    /// Up to `limit` cached peers whose id contains `partial`
+    pub fn find_peers(&self, partial: &str, limit: usize) -> Vec<PeerId> {
+        let conn = match &self.backend {
+            Backend::Plain(conn) => Self::lock(conn),
+            Backend::Encrypted(store) => {
+                let mut peers: Vec<PeerId> = store
+                    .peer_ids()
+                    .into_iter()
+                    .filter(|p| p.to_string().contains(partial))
+                    .collect();
+                peers.sort_by_key(|p| p.to_string());
+                peers.truncate(limit);
+                return peers;
+            }
+        };
+        let result = conn
+            .prepare(
+                "SELECT DISTINCT peer_id FROM peer_addresses WHERE instr(peer_id, ?1) > 0
+                 ORDER BY peer_id LIMIT ?2",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_map(params![partial, limit as i64], |row| row.get::<_, String>(0))?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            });
+        match result {
+            Ok(rows) => rows.into_iter().filter_map(|p| p.parse().ok()).collect(),
+            Err(e) => {
+                warn!("Failed to search cached peers: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Known addresses for a peer that mDNS did not report
+    pub fn supplement(&self, peer_id: &PeerId, discovered: &[Multiaddr]) -> Vec<Multiaddr> {
+        self.known_addresses(peer_id)
+            .into_iter()
+            .filter(|addr| !discovered.contains(addr))
+            .collect()
+    }
+
+    /// Drop addresses not seen within `max_age`
+    pub fn prune(&self, max_age: Duration) -> rusqlite::Result<usize> {
+        let conn = match &self.backend {
+            Backend::Plain(conn) => conn,
+            Backend::Encrypted(store) => return store.prune(max_age).map_err(to_sqlite_error),
+        };
+        let cutoff = now_secs() - max_age.as_secs() as i64;
+        Self::lock(conn).execute(
+            "DELETE FROM peer_addresses WHERE last_seen < ?1",
+            params![cutoff],
+        )
+    }
+}
+
+/// Where a node finds its first peers
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BootstrapMode {
+    /// Dial the bootstrap nodes the node was started with
+    #[default]
+    Bootstrap,
+    /// Dial no bootstrap node and find peers on the local network with mDNS
+    MdnsOnly,
+    /// Dial these multiaddrs and no other address; mDNS is off
+    Static(Vec<String>),
+}
+
+impl BootstrapMode {
+    /// The peers to dial at startup in place of `bootstrap_nodes`
+    pub fn startup_peers(&self, bootstrap_nodes: Vec<String>) -> Vec<String> {
+        match self {
+            BootstrapMode::Bootstrap => bootstrap_nodes,
+            BootstrapMode::MdnsOnly => Vec::new(),
+            BootstrapMode::Static(peers) => peers.clone(),
+        }
+    }
+
+    /// The addresses of `addrs` that may be dialed, such as relays and
+    /// AutoNAT servers; in `Static` mode only those in the list
+    pub fn dialable(&self, addrs: Vec<String>) -> Vec<String> {
+        match self {
+            BootstrapMode::Static(peers) => addrs.into_iter().filter(|a| peers.contains(a)).collect(),
+            _ => addrs,
+        }
+    }
+
+    pub fn uses_mdns(&self) -> bool {
+        !matches!(self, BootstrapMode::Static(_))
+    }
+}
+
+impl std::fmt::Display for BootstrapMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BootstrapMode::Bootstrap => f.write_str("bootstrap"),
+            BootstrapMode::MdnsOnly => f.write_str("mdns_only"),
+            BootstrapMode::Static(peers) => write!(f, "static:{}", peers.join(",")),
+        }
+    }
+}
+
+/// Parse a configured bootstrap address into the form it is dialed and
+/// stored in, so `/ip6/::ffff:1.2.3.4/...` and `/ip4/1.2.3.4/...` are one node
+pub fn validate_bootstrap_addr(addr: &str) -> Result<Multiaddr, String> {
+    let parsed: Multiaddr = addr
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid bootstrap address '{}': {}", addr, e))?;
+    Ok(crate::multiaddr::normalize(&parsed))
+}
+
+/// `bootstrap`, `mdns_only` or `static:ADDR,ADDR,...`
+impl std::str::FromStr for BootstrapMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(list) = s.strip_prefix("static:") {
+            let peers: Vec<String> = list
+                .split(',')
+                .map(str::trim)
+                .filter(|addr| !addr.is_empty())
+                .map(String::from)
+                .collect();
+            if let Some(bad) = peers.iter().find(|addr| validate_bootstrap_addr(addr).is_err()) {
+                return Err(format!("invalid static peer address '{}'", bad));
+            }
+            return Ok(BootstrapMode::Static(peers));
+        }
+        match s.to_ascii_lowercase().replace('-', "_").as_str() {
+            "bootstrap" => Ok(BootstrapMode::Bootstrap),
+            "mdns_only" => Ok(BootstrapMode::MdnsOnly),
+            other => Err(format!("unknown bootstrap mode '{}'", other)),
+        }
+    }
+}
+
+/// How long a bootstrap node gets to connect before the next one is tried
+pub const BOOTSTRAP_NODE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause before starting over once every bootstrap node failed
+pub const BOOTSTRAP_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+struct ChainNode {
+    addr: Multiaddr,
+    peer_id: Option<PeerId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChainState {
+    /// Nothing in flight; a new attempt starts at `retry_at`, if set
+    Idle { retry_at: Option<Instant> },
+    /// Waiting for `nodes[index]` until `deadline`, with `remaining` nodes
+    /// left to try in this attempt
+    Trying {
+        index: usize,
+        deadline: Instant,
+        remaining: usize,
+    },
+    /// At least one bootstrap node is connected
+    Connected,
+}
+
+/// Bootstrap nodes in priority order, dialed one at a time with a per-node
+/// timeout until one of them connects.
+///
+/// Each attempt starts one node further down the list than the previous one,
+/// so a node that is down does not delay every reconnect.
+pub struct BootstrapFallbackChain {
+    nodes: Vec<ChainNode>,
+    timeout: Duration,
+    next_start: usize,
+    state: ChainState,
+    connected: HashSet<usize>,
+}
+
+impl BootstrapFallbackChain {
+    pub fn new(nodes: Vec<Multiaddr>) -> Self {
+        let nodes = nodes
+            .into_iter()
+            .map(|addr| ChainNode {
+                peer_id: addr.iter().find_map(|p| match p {
+                    libp2p::multiaddr::Protocol::P2p(peer_id) => Some(peer_id),
+                    _ => None,
+                }),
+                addr,
+            })
+            .collect();
+        Self {
+            nodes,
+            timeout: BOOTSTRAP_NODE_TIMEOUT,
+            next_start: 0,
+            state: ChainState::Idle { retry_at: None },
+            connected: HashSet::new(),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state == ChainState::Connected
+    }
+
+    fn position(&self, peer_id: &PeerId, addr: Option<&Multiaddr>) -> Option<usize> {
+        self.nodes.iter().position(|node| match node.peer_id {
+            Some(id) => id == *peer_id,
+            None => addr == Some(&node.addr),
+        })
+    }
+
+    fn try_node(&mut self, index: usize, remaining: usize, now: Instant) -> Option<Multiaddr> {
+        self.state = ChainState::Trying {
+            index,
+            deadline: now + self.timeout,
+            remaining,
+        };
+        Some(self.nodes[index].addr.clone())
+    }
+
+    /// Begin a new attempt unless one is running or a node is connected;
+    /// returns the node to dial
+    pub fn start(&mut self, now: Instant) -> Option<Multiaddr> {
+        if self.nodes.is_empty() || !matches!(self.state, ChainState::Idle { .. }) {
+            return None;
+        }
+        let index = self.next_start % self.nodes.len();
+        self.next_start = (index + 1) % self.nodes.len();
+        self.try_node(index, self.nodes.len() - 1, now)
+    }
+
+    /// Move on to the next node of the current attempt
+    fn advance(&mut self, now: Instant) -> Option<Multiaddr> {
+        let ChainState::Trying { index, remaining, .. } = self.state else {
+            return None;
+        };
+        if remaining == 0 {
+            warn!(
+                "No bootstrap node reachable, trying again in {}s",
+                BOOTSTRAP_RETRY_INTERVAL.as_secs()
+            );
+            self.state = ChainState::Idle {
+                retry_at: Some(now + BOOTSTRAP_RETRY_INTERVAL),
+            };
+            return None;
+        }
+        let next = (index + 1) % self.nodes.len();
+        debug!(
+            "Bootstrap node {} did not connect, trying {}",
+            self.nodes[index].addr, self.nodes[next].addr
+        );
+        self.try_node(next, remaining - 1, now)
+    }
+
+    /// Called periodically; returns the next node to dial once the current
+    /// one timed out or a retry is due
+    pub fn poll(&mut self, now: Instant) -> Option<Multiaddr> {
+        match self.state {
+            ChainState::Trying { deadline, .. } if now >= deadline => self.advance(now),
+            ChainState::Idle { retry_at: Some(at) } if now >= at => {
+                self.state = ChainState::Idle { retry_at: None };
+                self.start(now)
+            }
+            _ => None,
+        }
+    }
+
+    /// A dial failed; skips ahead if it was the node currently being tried
+    pub fn on_dial_failed(&mut self, peer_id: &PeerId, now: Instant) -> Option<Multiaddr> {
+        match self.state {
+            ChainState::Trying { index, .. } if self.position(peer_id, None) == Some(index) => {
+                self.advance(now)
+            }
+            _ => None,
+        }
+    }
+
+    /// A connection was established. When it is the first bootstrap node to
+    /// connect, returns the other nodes to dial in parallel.
+    pub fn on_connected(&mut self, peer_id: &PeerId, addr: &Multiaddr) -> Option<Vec<Multiaddr>> {
+        let index = self.position(peer_id, Some(addr))?;
+        self.connected.insert(index);
+        if self.state == ChainState::Connected {
+            return None;
+        }
+        info!("Bootstrap node {} connected", self.nodes[index].addr);
+        self.state = ChainState::Connected;
+        Some(
+            self.nodes
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !self.connected.contains(i))
+                .map(|(_, node)| node.addr.clone())
+                .collect(),
+        )
+    }
+
+    /// The last connection to a peer closed. Once no bootstrap node is left
+    /// connected a new attempt starts; returns the node to dial.
+    pub fn on_disconnected(&mut self, peer_id: &PeerId, now: Instant) -> Option<Multiaddr> {
+        let index = self.position(peer_id, None)?;
+        self.connected.remove(&index);
+        if self.state != ChainState::Connected || !self.connected.is_empty() {
+            return None;
+        }
+        info!("Lost all bootstrap connections, reconnecting");
+        self.state = ChainState::Idle { retry_at: None };
+        self.start(now)
+    }
+}
+
+/// Addresses not seen for this long are listed after fresher ones
+pub const STALE_ADDRESS_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Matches returned for a partial peer id
+pub const MAX_PEER_MATCHES: usize = 10;
+
+/// Peers whose Identify addresses are kept in memory
+const MAX_IDENTIFY_PEERS: usize = 1024;
+
+/// Where `PeerStore` found an address, in the order it prefers them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AddressSource {
+    RoutingTable,
+    Identify,
+    LocalStore,
+}
+
+/// A peer matching a (partial) peer id, with its addresses best first
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerAddrMatch {
+    pub peer_id: String,
+    pub addrs: Vec<String>,
+}
+
+/// Order candidate addresses by source priority, keeping the first
+/// occurrence of each. Addresses whose newest sighting is older than
+/// `STALE_ADDRESS_AGE` go last; an address without a sighting time (the
+/// routing table keeps none) counts as fresh.
+pub fn rank_addresses(
+    mut candidates: Vec<(AddressSource, Multiaddr, Option<i64>)>,
+    now: i64,
+) -> Vec<Multiaddr> {
+    candidates.sort_by_key(|(source, _, _)| *source);
+    let mut newest: HashMap<Multiaddr, Option<i64>> = HashMap::new();
+    for (_, addr, seen) in &candidates {
+        let entry = newest.entry(addr.clone()).or_insert(*seen);
+        *entry = match (*entry, *seen) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            _ => None,
+        };
+    }
+    let cutoff = now - STALE_ADDRESS_AGE.as_secs() as i64;
+    let mut seen = HashSet::new();
+    let mut ranked: Vec<(bool, Multiaddr)> = candidates
+        .into_iter()
+        .filter(|(_, addr, _)| seen.insert(addr.clone()))
+        .map(|(_, addr, _)| {
+            let stale = newest[&addr].is_some_and(|at| at < cutoff);
+            (stale, addr)
+        })
+        .collect();
+    // Stable, so source priority is kept within fresh and stale addresses
+    ranked.sort_by_key(|(stale, _)| *stale);
+    ranked.into_iter().map(|(_, addr)| addr).collect()
+}
+
+/// Reverse lookup from a peer id to the addresses it can be dialed at,
+/// across the Kademlia routing table, addresses learned through Identify this
+/// session and the local peer store.
+///
+/// The routing table lives in the swarm, so callers pass its entries in.
+pub struct PeerStore {
+    identify: HashMap<PeerId, (Vec<Multiaddr>, i64)>,
+    /// What each identified peer supports, kept alongside `identify`
+    capabilities: HashMap<PeerId, PeerCapabilitySet>,
+    local: Option<Arc<LocalDiscoveryCache>>,
+    /// Estimated size of `identify` and `capabilities`
+    bytes: u64,
+    /// Share of the memory budget, if there is one
+    max_bytes: Option<u64>,
+}
+
+/// Estimated heap bytes of one peer's entries
+fn identify_entry_bytes(addrs: &[Multiaddr]) -> u64 {
+    let fixed = std::mem::size_of::<(PeerId, Vec<Multiaddr>, i64)>()
+        + std::mem::size_of::<(PeerId, PeerCapabilitySet)>();
+    (fixed + addrs.iter().map(|a| a.len() + std::mem::size_of::<Multiaddr>()).sum::<usize>()) as u64
+}
+
+impl PeerStore {
+    pub fn new(local: Option<Arc<LocalDiscoveryCache>>) -> Self {
+        Self {
+            identify: HashMap::new(),
+            capabilities: HashMap::new(),
+            local,
+            bytes: 0,
+            max_bytes: None,
+        }
+    }
+
+    /// Keep the Identify addresses within `max_bytes`, dropping the oldest
+    /// peers first
+    pub fn set_max_bytes(&mut self, max_bytes: Option<u64>) {
+        self.max_bytes = max_bytes;
+    }
+
+    /// Estimated memory held for Identify addresses and capabilities
+    pub fn approx_bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Remember the listen addresses a peer reported through Identify
+    pub fn record_identify(&mut self, peer_id: PeerId, addrs: Vec<Multiaddr>) {
+        let addrs = crate::multiaddr::normalize_all(&addrs);
+        if let Some((old, _)) = self.identify.remove(&peer_id) {
+            self.bytes = self.bytes.saturating_sub(identify_entry_bytes(&old));
+        }
+        let entry_bytes = identify_entry_bytes(&addrs);
+        while self.identify.len() >= MAX_IDENTIFY_PEERS
+            || self.max_bytes.is_some_and(|max| self.bytes + entry_bytes > max)
+        {
+            let Some(oldest) = self
+                .identify
+                .iter()
+                .min_by_key(|(_, (_, at))| *at)
+                .map(|(peer, _)| *peer)
+            else {
+                break;
+            };
+            if let Some((old, _)) = self.identify.remove(&oldest) {
+                self.bytes = self.bytes.saturating_sub(identify_entry_bytes(&old));
+            }
+            self.capabilities.remove(&oldest);
+        }
+        self.bytes += entry_bytes;
+        self.identify.insert(peer_id, (addrs, now_secs()));
+    }
+
+    /// Remember what an identified peer supports; call after `record_identify`
+    pub fn record_capabilities(&mut self, peer_id: PeerId, capabilities: PeerCapabilitySet) {
+        if self.identify.contains_key(&peer_id) {
+            self.capabilities.insert(peer_id, capabilities);
+        }
+    }
+
+    /// Capabilities of `peer_id`, or what is assumed before it identifies
+    pub fn capabilities(&self, peer_id: &PeerId) -> PeerCapabilitySet {
+        self.capabilities
+            .get(peer_id)
+            .copied()
+            .unwrap_or_else(PeerCapabilitySet::assumed)
+    }
+
+    /// Every known address of `peer_id`, best first
+    pub fn get_addrs(&self, peer_id: &PeerId, routing_table: &[Multiaddr]) -> Vec<Multiaddr> {
+        let mut candidates: Vec<(AddressSource, Multiaddr, Option<i64>)> = routing_table
+            .iter()
+            .map(|a| (AddressSource::RoutingTable, a.clone(), None))
+            .collect();
+        if let Some((addrs, at)) = self.identify.get(peer_id) {
+            candidates.extend(addrs.iter().map(|a| (AddressSource::Identify, a.clone(), Some(*at))));
+        }
+        if let Some(local) = &self.local {
+            candidates.extend(
+                local
+                    .known_addresses_seen(peer_id)
+                    .into_iter()
+                    .map(|(a, at)| (AddressSource::LocalStore, a, Some(at))),
+            );
+        }
+        rank_addresses(candidates, now_secs())
+    }
+
+    /// Peers whose id contains `partial`, at most `MAX_PEER_MATCHES`, with
+    /// their addresses. A complete peer id matches only itself.
+    pub fn find(
+        &self,
+        partial: &str,
+        routing_table: &HashMap<PeerId, Vec<Multiaddr>>,
+    ) -> Vec<PeerAddrMatch> {
+        let partial = partial.trim();
+        if partial.is_empty() {
+            return Vec::new();
+        }
+        let mut peers: Vec<PeerId> = match partial.parse::<PeerId>() {
+            Ok(peer_id) => vec![peer_id],
+            Err(_) => {
+                let mut peers: HashSet<PeerId> = routing_table
+                    .keys()
+                    .chain(self.identify.keys())
+                    .filter(|p| p.to_string().contains(partial))
+                    .copied()
+                    .collect();
+                if let Some(local) = &self.local {
+                    peers.extend(local.find_peers(partial, MAX_PEER_MATCHES));
+                }
+                peers.into_iter().collect()
+            }
+        };
+        peers.sort_by_key(|p| p.to_string());
+        peers.truncate(MAX_PEER_MATCHES);
+        peers
+            .into_iter()
+            .map(|peer_id| {
+                let routing = routing_table.get(&peer_id).map(Vec::as_slice).unwrap_or(&[]);
+                PeerAddrMatch {
+                    peer_id: peer_id.to_string(),
+                    addrs: self
+                        .get_addrs(&peer_id, routing)
+                        .iter()
+                        .map(|a| a.to_string())
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// How often `BootstrapNodeMonitor` probes each bootstrap node
+pub const BOOTSTRAP_MONITOR_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Upper bound on one bootstrap probe
+pub const BOOTSTRAP_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Consecutive failed probes before a node counts as down, so a single lost
+/// probe does not flap the status
+pub const BOOTSTRAP_FAILURES_BEFORE_DOWN: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootstrapNodeStatus {
+    pub addr: Multiaddr,
+    /// Last successful probe; `None` until one succeeds
+    pub last_success: Option<Instant>,
+    /// Consecutive failed probes
+    pub failure_count: u32,
+    pub is_reachable: bool,
+}
+
+/// A change in a bootstrap node's reachability
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BootstrapTransition {
+    Down(Multiaddr),
+    Recovered(Multiaddr),
+}
+
+/// `BootstrapNodeStatus` as reported to the frontend
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapNodeReport {
+    pub addr: String,
+    pub is_reachable: bool,
+    pub failure_count: u32,
+    pub last_success_secs_ago: Option<u64>,
+}
+
+/// Reachability of the bootstrap nodes, kept up to date by periodic probes.
+///
+/// Nodes start out reachable, so only a node that fails
+/// `BOOTSTRAP_FAILURES_BEFORE_DOWN` probes in a row is reported down, and only
+/// a node that was down is reported as recovered.
+#[derive(Debug, Default)]
+pub struct BootstrapNodeMonitor {
+    nodes: HashMap<Multiaddr, BootstrapNodeStatus>,
+}
+
+impl BootstrapNodeMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the monitored nodes; the status of nodes still in the list is kept
+    pub fn set_nodes(&mut self, addrs: Vec<Multiaddr>) {
+        let mut previous = std::mem::take(&mut self.nodes);
+        for addr in addrs {
+            let status = previous.remove(&addr).unwrap_or_else(|| BootstrapNodeStatus {
+                addr: addr.clone(),
+                last_success: None,
+                failure_count: 0,
+                is_reachable: true,
+            });
+            self.nodes.insert(addr, status);
+        }
+    }
+
+    pub fn addrs(&self) -> Vec<Multiaddr> {
+        self.nodes.keys().cloned().collect()
+    }
+
+    pub fn status(&self, addr: &Multiaddr) -> Option<&BootstrapNodeStatus> {
+        self.nodes.get(addr)
+    }
+
+    pub fn record_success(&mut self, addr: &Multiaddr, now: Instant) -> Option<BootstrapTransition> {
+        let status = self.nodes.get_mut(addr)?;
+        status.last_success = Some(now);
+        status.failure_count = 0;
+        if status.is_reachable {
+            return None;
+        }
+        status.is_reachable = true;
+        Some(BootstrapTransition::Recovered(addr.clone()))
+    }
+
+    pub fn record_failure(&mut self, addr: &Multiaddr) -> Option<BootstrapTransition> {
+        let status = self.nodes.get_mut(addr)?;
+        status.failure_count = status.failure_count.saturating_add(1);
+        if !status.is_reachable || status.failure_count < BOOTSTRAP_FAILURES_BEFORE_DOWN {
+            return None;
+        }
+        status.is_reachable = false;
+        Some(BootstrapTransition::Down(addr.clone()))
+    }
+
+    pub fn report(&self, now: Instant) -> Vec<BootstrapNodeReport> {
+        let mut report: Vec<BootstrapNodeReport> = self
+            .nodes
+            .values()
+            .map(|status| BootstrapNodeReport {
+                addr: status.addr.to_string(),
+                is_reachable: status.is_reachable,
+                failure_count: status.failure_count,
+                last_success_secs_ago: status
+                    .last_success
+                    .map(|at| now.saturating_duration_since(at).as_secs()),
+            })
+            .collect();
+        report.sort_by(|a, b| a.addr.cmp(&b.addr));
+        report
+    }
+}
+
+/// Consecutive failed health checks before `BootstrapNodePruner`
+/// quarantines a bootstrap node
+pub const DEFAULT_PRUNE_AFTER_FAILURES: u32 = 10;
+
+/// Quarantined nodes are probed on one health check in this many
+pub const QUARANTINED_PROBE_EVERY: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BootstrapNodeState {
+    Active,
+    /// Unreachable for too long; not dialed, and probed less often, until
+    /// restored
+    Quarantined,
+}
+
+/// A quarantined bootstrap node as reported to the frontend
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuarantinedBootstrapNode {
+    pub addr: String,
+    pub consecutive_failures: u32,
+    /// Unix seconds the node was quarantined
+    pub quarantined_at: u64,
+}
+
+struct PrunedNode {
+    state: BootstrapNodeState,
+    consecutive_failures: u32,
+    quarantined_at: u64,
+}
+
+/// Nodes whose state one health check changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneOutcome {
+    pub quarantined: Vec<Multiaddr>,
+    /// Quarantined nodes that answered again
+    pub restored: Vec<Multiaddr>,
+}
+
+impl PruneOutcome {
+    pub fn is_empty(&self) -> bool {
+        self.quarantined.is_empty() && self.restored.is_empty()
+    }
+}
+
+/// Takes bootstrap nodes that fail `prune_after_failures` health checks in a
+/// row out of the active list.
+///
+/// Pruned nodes are kept as `Quarantined` for the rest of the run rather
+/// than forgotten, and the configuration is never changed. They are probed
+/// on every `QUARANTINED_PROBE_EVERY`th check and return to the active list
+/// when one answers, or on `restore`. A limit of 0 turns pruning off.
+///
+/// A check in which no active node answered says more about this node's
+/// own network than about the bootstrap nodes, so it is not counted; the
+/// last active node is never quarantined either way.
+pub struct BootstrapNodePruner {
+    prune_after_failures: u32,
+    /// In configured order
+    nodes: Vec<(Multiaddr, PrunedNode)>,
+    checks: u32,
+}
+
+impl Default for BootstrapNodePruner {
+    fn default() -> Self {
+        Self::new(DEFAULT_PRUNE_AFTER_FAILURES)
+    }
+}
+
+impl BootstrapNodePruner {
+    pub fn new(prune_after_failures: u32) -> Self {
+        Self {
+            prune_after_failures,
+            nodes: Vec::new(),
+            checks: 0,
+        }
+    }
+
+    /// Replace the configured nodes; nodes still in the list keep their
+    /// failure count and quarantine
+    pub fn set_nodes(&mut self, addrs: Vec<Multiaddr>) {
+        let mut previous = std::mem::take(&mut self.nodes);
+        for addr in addrs {
+            if self.nodes.iter().any(|(a, _)| *a == addr) {
+                continue;
+            }
+            let node = match previous.iter().position(|(a, _)| *a == addr) {
+                Some(i) => previous.swap_remove(i).1,
+                None => PrunedNode {
+                    state: BootstrapNodeState::Active,
+                    consecutive_failures: 0,
+                    quarantined_at: 0,
+                },
+            };
+            self.nodes.push((addr, node));
+        }
+    }
+
+    /// Configured nodes that are not quarantined
+    pub fn active(&self) -> Vec<Multiaddr> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.state == BootstrapNodeState::Active)
+            .map(|(addr, _)| addr.clone())
+            .collect()
+    }
+
+    pub fn state(&self, addr: &Multiaddr) -> Option<BootstrapNodeState> {
+        self.nodes
+            .iter()
+            .find(|(a, _)| a == addr)
+            .map(|(_, node)| node.state)
+    }
+
+    /// Nodes to probe in the next health check: the active ones, and the
+    /// quarantined ones on every `QUARANTINED_PROBE_EVERY`th check
+    pub fn due_for_probe(&mut self) -> Vec<Multiaddr> {
+        self.checks = self.checks.wrapping_add(1);
+        let with_quarantined = self.checks % QUARANTINED_PROBE_EVERY == 0;
+        self.nodes
+            .iter()
+            .filter(|(_, node)| with_quarantined || node.state == BootstrapNodeState::Active)
+            .map(|(addr, _)| addr.clone())
+            .collect()
+    }
+
+    /// Record one health check of the nodes `due_for_probe` returned
+    pub fn record_round(&mut self, results: &[(Multiaddr, bool)], now: u64) -> PruneOutcome {
+        let mut outcome = PruneOutcome::default();
+        let is_active = |nodes: &[(Multiaddr, PrunedNode)], addr: &Multiaddr| {
+            nodes
+                .iter()
+                .any(|(a, node)| a == addr && node.state == BootstrapNodeState::Active)
+        };
+        let any_active_answered = results
+            .iter()
+            .any(|(addr, reachable)| *reachable && is_active(&self.nodes, addr));
+        for (addr, reachable) in results {
+            let Some((_, node)) = self.nodes.iter_mut().find(|(a, _)| a == addr) else {
+                continue;
+            };
+            if *reachable {
+                if node.state == BootstrapNodeState::Quarantined {
+                    outcome.restored.push(addr.clone());
+                }
+                node.state = BootstrapNodeState::Active;
+                node.consecutive_failures = 0;
+                node.quarantined_at = 0;
+            } else if node.state == BootstrapNodeState::Active && any_active_answered {
+                node.consecutive_failures = node.consecutive_failures.saturating_add(1);
+            }
+        }
+        if self.prune_after_failures == 0 {
+            return outcome;
+        }
+        let limit = self.prune_after_failures;
+        for (addr, _) in results.iter().filter(|(_, reachable)| !reachable) {
+            let active = self.active().len();
+            let Some((_, node)) = self.nodes.iter_mut().find(|(a, _)| a == addr) else {
+                continue;
+            };
+            if node.state == BootstrapNodeState::Active
+                && node.consecutive_failures >= limit
+                && active > 1
+            {
+                node.state = BootstrapNodeState::Quarantined;
+                node.quarantined_at = now;
+                outcome.quarantined.push(addr.clone());
+            }
+        }
+        outcome
+    }
+
+    /// Put a quarantined node back in the active list with a clean record
+    pub fn restore(&mut self, addr: &Multiaddr) -> Result<(), String> {
+        let (_, node) = self
+            .nodes
+            .iter_mut()
+            .find(|(a, _)| a == addr)
+            .ok_or_else(|| format!("{} is not a configured bootstrap node", addr))?;
+        if node.state != BootstrapNodeState::Quarantined {
+            return Err(format!("bootstrap node {} is not quarantined", addr));
+        }
+        node.state = BootstrapNodeState::Active;
+        node.consecutive_failures = 0;
+        node.quarantined_at = 0;
+        Ok(())
+    }
+
+    pub fn quarantined_addrs(&self) -> Vec<Multiaddr> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.state == BootstrapNodeState::Quarantined)
+            .map(|(addr, _)| addr.clone())
+            .collect()
+    }
+
+    pub fn quarantined(&self) -> Vec<QuarantinedBootstrapNode> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.state == BootstrapNodeState::Quarantined)
+            .map(|(addr, node)| QuarantinedBootstrapNode {
+                addr: addr.to_string(),
+                consecutive_failures: node.consecutive_failures,
+                quarantined_at: node.quarantined_at,
+            })
+            .collect()
+    }
+}
+
+/// Peers a bootstrap node helped this node find
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapContributionStats {
+    pub node_addr: String,
+    pub peers_introduced: u64,
+    pub successful_dials_to_discovered_peers: u64,
+    /// Unix seconds of the last introduction or dial; 0 if none yet
+    pub last_contribution_time: u64,
+}
+
+/// Discovered peers whose introducers are remembered for dial credit
+const MAX_INTRODUCED_PEERS: usize = 4096;
+
+struct ContributingNode {
+    addr: Multiaddr,
+    peer_id: Option<PeerId>,
+    connected: bool,
+    stats: BootstrapContributionStats,
+}
+
+/// Which bootstrap node each peer of this node was found through.
+///
+/// Kademlia does not say whose response named a peer, so the node keeps
+/// its own record: the peers returned by the closest-peer lookup started
+/// when a bootstrap node connects, and the peers a bootstrap node knows
+/// addresses for when asked, are credited to that node. A peer is credited
+/// to the first node that returned it. Peers found through mDNS are never
+/// credited.
+#[derive(Default)]
+pub struct BootstrapContributionTracker {
+    nodes: Vec<ContributingNode>,
+    /// Discovered peer -> index of the node credited with it
+    introduced: HashMap<PeerId, usize>,
+    dialed: HashSet<PeerId>,
+    local: HashSet<PeerId>,
+}
+
+impl BootstrapContributionTracker {
+    pub fn new(nodes: &[Multiaddr]) -> Self {
+        let nodes = nodes
+            .iter()
+            .map(|addr| ContributingNode {
+                addr: addr.clone(),
+                peer_id: addr.iter().find_map(|p| match p {
+                    libp2p::multiaddr::Protocol::P2p(peer_id) => Some(peer_id),
+                    _ => None,
+                }),
+                connected: false,
+                stats: BootstrapContributionStats {
+                    node_addr: addr.to_string(),
+                    peers_introduced: 0,
+                    successful_dials_to_discovered_peers: 0,
+                    last_contribution_time: 0,
+                },
+            })
+            .collect();
+        Self {
+            nodes,
+            ..Default::default()
+        }
+    }
+
+    fn position(&self, peer_id: &PeerId, addr: Option<&Multiaddr>) -> Option<usize> {
+        self.nodes.iter().position(|node| match node.peer_id {
+            Some(id) => id == *peer_id,
+            None => addr == Some(&node.addr),
+        })
+    }
+
+    /// Peers found on the local network, which no bootstrap node introduced
+    pub fn mark_local(&mut self, peers: impl IntoIterator<Item = PeerId>) {
+        self.local.extend(peers);
+    }
+
+    /// A connection to `peer_id` was established; `dialer` is true when we
+    /// opened it. True when `peer_id` is one of the bootstrap nodes.
+    pub fn on_connected(&mut self, peer_id: PeerId, addr: &Multiaddr, dialer: bool, now: u64) -> bool {
+        if let Some(index) = self.position(&peer_id, Some(addr)) {
+            let node = &mut self.nodes[index];
+            node.peer_id.get_or_insert(peer_id);
+            node.connected = true;
+            return true;
+        }
+        if let Some(&i) = self.introduced.get(&peer_id) {
+            if dialer && self.dialed.insert(peer_id) {
+                let stats = &mut self.nodes[i].stats;
+                stats.successful_dials_to_discovered_peers += 1;
+                stats.last_contribution_time = now;
+            }
+        }
+        false
+    }
+
+    /// `bootstrap` returned `peers`, from a lookup it answered
+    pub fn on_introduced(&mut self, bootstrap: &PeerId, peers: impl IntoIterator<Item = PeerId>, now: u64) {
+        let Some(index) = self.position(bootstrap, None) else {
+            return;
+        };
+        for peer in peers {
+            if self.local.contains(&peer)
+                || self.introduced.contains_key(&peer)
+                || self.position(&peer, None).is_some()
+            {
+                continue;
+            }
+            if self.introduced.len() >= MAX_INTRODUCED_PEERS {
+                // Forget an arbitrary peer; its dial credit is lost
+                if let Some(old) = self.introduced.keys().next().copied() {
+                    self.introduced.remove(&old);
+                    self.dialed.remove(&old);
+                }
+            }
+            let stats = &mut self.nodes[index].stats;
+            stats.peers_introduced += 1;
+            stats.last_contribution_time = now;
+            self.introduced.insert(peer, index);
+        }
+    }
+
+    /// The last connection to `peer_id` closed
+    pub fn on_disconnected(&mut self, peer_id: &PeerId) {
+        if let Some(index) = self.position(peer_id, None) {
+            self.nodes[index].connected = false;
+        }
+    }
+
+    /// Configured bootstrap nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Bootstrap nodes with an open connection
+    pub fn connected(&self) -> usize {
+        self.nodes.iter().filter(|node| node.connected).count()
+    }
+
+    /// Most helpful nodes first
+    pub fn stats(&self) -> Vec<BootstrapContributionStats> {
+        let mut stats: Vec<BootstrapContributionStats> =
+            self.nodes.iter().map(|node| node.stats.clone()).collect();
+        stats.sort_by(|a, b| {
+            b.successful_dials_to_discovered_peers
+                .cmp(&a.successful_dials_to_discovered_peers)
+                .then(b.peers_introduced.cmp(&a.peers_introduced))
+                .then_with(|| a.node_addr.cmp(&b.node_addr))
+        });
+        stats
+    }
+}
+
+/// Host and TCP port a multiaddr can be probed on
+fn tcp_endpoint(addr: &Multiaddr) -> Option<(String, u16)> {
+    use libp2p::multiaddr::Protocol;
+    let mut host = None;
+    let mut port = None;
+    for protocol in addr.iter() {
+        match protocol {
+            Protocol::Ip4(ip) => host = Some(ip.to_string()),
+            Protocol::Ip6(ip) => host = Some(ip.to_string()),
+            Protocol::Dns(name) | Protocol::Dns4(name) | Protocol::Dns6(name) => {
+                host = Some(name.to_string())
+            }
+            Protocol::Tcp(p) => port = Some(p),
+            _ => {}
+        }
+    }
+    Some((host?, port?))
+}
+
+/// Whether a TCP connection to the bootstrap node opens within `timeout`.
+/// This is independent of the swarm, so it also tells when a node that the
+/// swarm gave up on is back. Nodes without a TCP address are never reachable.
+pub async fn probe_bootstrap_node(addr: &Multiaddr, timeout: Duration) -> bool {
+    let Some((host, port)) = tcp_endpoint(addr) else {
+        return false;
+    };
+    matches!(
+        tokio::time::timeout(timeout, tokio::net::TcpStream::connect((host.as_str(), port))).await,
+        Ok(Ok(_))
+    )
+}
+
+/// Kademlia queries started per second once the initial burst window is over
+pub const DEFAULT_KAD_QUERIES_PER_SECOND: u32 = 5;
+
+/// Queries per second allowed during the initial burst window
+pub const DEFAULT_KAD_INITIAL_BURST: u32 = 20;
+
+/// Length of the initial burst window
+pub const KAD_BURST_WINDOW: Duration = Duration::from_secs(30);
+
+/// Kademlia query started by this node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KadQuery {
+    Bootstrap,
+    ClosestPeers(PeerId),
+    /// Closest-peer lookup that stores a replicated record again
+    Republish(RecordKey),
+    /// Lookup of our own id for a bootstrap node that just connected; the
+    /// peers it returns are credited to that node
+    Introductions(PeerId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KadRateLimitConfig {
+    pub max_queries_per_second: u32,
+    pub initial_burst: u32,
+    pub burst_window: Duration,
+}
+
+impl Default for KadRateLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_queries_per_second: DEFAULT_KAD_QUERIES_PER_SECOND,
+            initial_burst: DEFAULT_KAD_INITIAL_BURST,
+            burst_window: KAD_BURST_WINDOW,
+        }
+    }
+}
+
+impl From<&crate::config::SwarmConfig> for KadRateLimitConfig {
+    fn from(config: &crate::config::SwarmConfig) -> Self {
+        Self {
+            max_queries_per_second: config.kad_queries_per_second.max(1),
+            initial_burst: config.kad_initial_burst,
+            burst_window: KAD_BURST_WINDOW,
+        }
+    }
+}
+
+/// Token bucket in front of the queries passed to Kademlia
+#[derive(Debug)]
+pub struct KadRateLimiter {
+    config: KadRateLimitConfig,
+    started: Instant,
+    tokens: f64,
+    last_refill: Instant,
+    queue: VecDeque<KadQuery>,
+}
+
+impl KadRateLimiter {
+    pub fn new(config: KadRateLimitConfig, now: Instant) -> Self {
+        let mut limiter = Self {
+            config,
+            started: now,
+            tokens: 0.0,
+            last_refill: now,
+            queue: VecDeque::new(),
+        };
+        limiter.tokens = limiter.rate(now);
+        limiter
+    }
+
+    /// Queries per second allowed at `now`; one second's worth can be saved up
+    fn rate(&self, now: Instant) -> f64 {
+        let limit = self.config.max_queries_per_second.max(1);
+        if now.saturating_duration_since(self.started) < self.config.burst_window {
+            limit.max(self.config.initial_burst) as f64
+        } else {
+            limit as f64
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let rate = self.rate(now);
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.last_refill = now;
+    }
+
+    /// Returns `query` when it may start now; otherwise it is queued behind
+    /// the others. A query equal to one already queued is not queued twice.
+    pub fn submit(&mut self, query: KadQuery, now: Instant) -> Option<KadQuery> {
+        self.refill(now);
+        if self.queue.is_empty() && self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Some(query);
+        }
+        if !self.queue.contains(&query) {
+            self.queue.push_back(query);
+        }
+        None
+    }
+
+    /// Queued queries that may start now, oldest first
+    pub fn poll(&mut self, now: Instant) -> Vec<KadQuery> {
+        self.refill(now);
+        let mut ready = Vec::new();
+        while self.tokens >= 1.0 {
+            let Some(query) = self.queue.pop_front() else {
+                break;
+            };
+            self.tokens -= 1.0;
+            ready.push(query);
+        }
+        ready
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+/// Bootstrap nodes that must know a peer before its addresses are used
+pub const DEFAULT_BOOTSTRAP_MIN_CONFIRMATIONS: u32 = 2;
+
+/// How long addresses wait for confirmation before they are dropped
+pub const PENDING_ADDRESS_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Peers kept in quarantine, and peers remembered as confirmed
+const MAX_PENDING_PEERS: usize = 1024;
+
+struct PendingPeer {
+    /// Address -> bootstrap nodes that returned it
+    addrs: HashMap<Multiaddr, HashSet<PeerId>>,
+    /// Bootstrap nodes that know the peer at all
+    vouched: HashSet<PeerId>,
+    /// Bootstrap nodes asked that have not answered yet
+    awaiting: HashSet<PeerId>,
+    /// Bootstrap nodes that answered, whether they know the peer or not
+    answered: HashSet<PeerId>,
+    since: Instant,
+}
+
+/// Addresses of found peers that not enough bootstrap nodes have confirmed
+#[derive(Default)]
+pub struct PendingAddressStore {
+    peers: HashMap<PeerId, PendingPeer>,
+}
+
+impl PendingAddressStore {
+    /// Quarantine `addrs`; true for a peer that was not pending before
+    pub fn insert(&mut self, peer: PeerId, addrs: impl IntoIterator<Item = Multiaddr>, now: Instant) -> bool {
+        let is_new = !self.peers.contains_key(&peer);
+        if is_new && self.peers.len() >= MAX_PENDING_PEERS {
+            if let Some(oldest) = self.peers.iter().min_by_key(|(_, p)| p.since).map(|(id, _)| *id) {
+                self.peers.remove(&oldest);
+            }
+        }
+        let pending = self.peers.entry(peer).or_insert_with(|| PendingPeer {
+            addrs: HashMap::new(),
+            vouched: HashSet::new(),
+            awaiting: HashSet::new(),
+            answered: HashSet::new(),
+            since: now,
+        });
+        for addr in addrs {
+            pending.addrs.entry(addr).or_default();
+        }
+        is_new
+    }
+
+    pub fn contains(&self, peer: &PeerId) -> bool {
+        self.peers.contains_key(peer)
+    }
+
+    /// Quarantined addresses of `peer`
+    pub fn addresses(&self, peer: &PeerId) -> Vec<Multiaddr> {
+        self.peers
+            .get(peer)
+            .map(|p| p.addrs.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// Drop peers that have waited longer than `PENDING_ADDRESS_TTL`
+    pub fn expire(&mut self, now: Instant) {
+        self.peers
+            .retain(|_, p| now.saturating_duration_since(p.since) < PENDING_ADDRESS_TTL);
+    }
+}
+
+/// Result of `MultiBootstrapConsensus::admit`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Admission {
+    /// The addresses may be added to the routing table now
+    Admitted(Vec<Multiaddr>),
+    /// The addresses are quarantined; `ask` are the bootstrap nodes to send
+    /// a lookup to, empty when they were asked before
+    Quarantined { ask: Vec<PeerId> },
+}
+
+/// Keeps peers found through Kademlia out of the routing table until at
+/// least `min_confirmations` bootstrap nodes know them.
+///
+/// Kademlia does not say which node returned a result, so agreement is
+/// checked with a `PeerLookupRequest` to each connected bootstrap node that
+/// lists `/chiral/peer-lookup/1.0.0` in identify. The relay daemon and older
+/// nodes do not, so they are never asked and never counted. Only addresses
+/// returned by `min_confirmations` of them are released; the rest stay in
+/// the `PendingAddressStore` until they expire.
+///
+/// The check only holds peers back when it can be decided: with fewer
+/// supporting bootstrap nodes connected than would have to agree, or fewer
+/// answering the lookups, the addresses are used as they are.
+pub struct MultiBootstrapConsensus {
+    min_confirmations: u32,
+    bootstrap_peers: HashSet<PeerId>,
+    /// Connected bootstrap nodes that speak the lookup protocol
+    lookup_capable: HashSet<PeerId>,
+    pending: PendingAddressStore,
+    confirmed: HashSet<PeerId>,
+    confirmed_order: VecDeque<PeerId>,
+}
+
+impl MultiBootstrapConsensus {
+    /// `None` when `min_confirmations` is 0 or 1, or fewer bootstrap nodes
+    /// are configured than would have to agree
+    pub fn new(min_confirmations: u32, bootstrap_peers: impl IntoIterator<Item = PeerId>) -> Option<Self> {
+        let bootstrap_peers: HashSet<PeerId> = bootstrap_peers.into_iter().collect();
+        if min_confirmations <= 1 || bootstrap_peers.len() < min_confirmations as usize {
+            return None;
+        }
+        Some(Self {
+            min_confirmations,
+            bootstrap_peers,
+            lookup_capable: HashSet::new(),
+            pending: PendingAddressStore::default(),
+            confirmed: HashSet::new(),
+            confirmed_order: VecDeque::new(),
+        })
+    }
+
+    pub fn min_confirmations(&self) -> u32 {
+        self.min_confirmations
+    }
+
+    pub fn is_bootstrap(&self, peer: &PeerId) -> bool {
+        self.bootstrap_peers.contains(peer)
+    }
+
+    pub fn pending(&self) -> &PendingAddressStore {
+        &self.pending
+    }
+
+    /// Identify of `peer` said whether it speaks the lookup protocol
+    pub fn set_supports_lookup(&mut self, peer: PeerId, supported: bool) {
+        if !self.is_bootstrap(&peer) {
+            return;
+        }
+        if supported {
+            self.lookup_capable.insert(peer);
+        } else {
+            self.lookup_capable.remove(&peer);
+        }
+    }
+
+    /// The last connection to `peer` closed
+    pub fn on_disconnected(&mut self, peer: &PeerId) {
+        self.lookup_capable.remove(peer);
+    }
+
+    /// Addresses of `peer` found by a Kademlia query. Bootstrap nodes and
+    /// peers confirmed before are admitted as they are, and so is every peer
+    /// while too few bootstrap nodes could confirm it.
+    pub fn admit(&mut self, peer: PeerId, addrs: Vec<Multiaddr>, now: Instant) -> Admission {
+        if self.is_bootstrap(&peer)
+            || self.confirmed.contains(&peer)
+            || self.lookup_capable.len() < self.min_confirmations as usize
+        {
+            return Admission::Admitted(addrs);
+        }
+        self.pending.expire(now);
+        if !self.pending.insert(peer, addrs, now) {
+            return Admission::Quarantined { ask: Vec::new() };
+        }
+        let ask: Vec<PeerId> = self.lookup_capable.iter().copied().collect();
+        if let Some(pending) = self.pending.peers.get_mut(&peer) {
+            pending.awaiting = ask.iter().copied().collect();
+        }
+        Admission::Quarantined { ask }
+    }
+
+    /// `bootstrap` answered a lookup for `peer` with `addrs`, empty when it
+    /// does not know the peer. Returns the addresses released to the routing
+    /// table, empty while the peer stays quarantined.
+    pub fn on_lookup(&mut self, bootstrap: PeerId, peer: PeerId, addrs: Vec<Multiaddr>) -> Vec<Multiaddr> {
+        if !self.is_bootstrap(&bootstrap) {
+            return Vec::new();
+        }
+        let Some(pending) = self.pending.peers.get_mut(&peer) else {
+            return Vec::new();
+        };
+        pending.awaiting.remove(&bootstrap);
+        pending.answered.insert(bootstrap);
+        if !addrs.is_empty() {
+            pending.vouched.insert(bootstrap);
+            for addr in addrs {
+                pending.addrs.entry(addr).or_default().insert(bootstrap);
+            }
+        }
+        self.settle(peer)
+    }
+
+    /// The lookup for `peer` on `bootstrap` failed: it timed out, the
+    /// connection closed or the node does not speak the protocol after all
+    pub fn on_lookup_failed(&mut self, bootstrap: PeerId, peer: PeerId) -> Vec<Multiaddr> {
+        let Some(pending) = self.pending.peers.get_mut(&peer) else {
+            return Vec::new();
+        };
+        pending.awaiting.remove(&bootstrap);
+        self.settle(peer)
+    }
+
+    /// Release the addresses of `peer` that enough bootstrap nodes returned,
+    /// or all of them once too few answered to decide
+    fn settle(&mut self, peer: PeerId) -> Vec<Multiaddr> {
+        let needed = self.min_confirmations as usize;
+        let Some(pending) = self.pending.peers.get(&peer) else {
+            return Vec::new();
+        };
+        if pending.vouched.len() >= needed {
+            let released: Vec<Multiaddr> = pending
+                .addrs
+                .iter()
+                .filter(|(_, sources)| sources.len() >= needed)
+                .map(|(addr, _)| addr.clone())
+                .collect();
+            if !released.is_empty() {
+                self.pending.peers.remove(&peer);
+                self.confirm(peer);
+                return released;
+            }
+        }
+        if pending.awaiting.is_empty() && pending.answered.len() < needed {
+            let released = self.pending.addresses(&peer);
+            self.pending.peers.remove(&peer);
+            return released;
+        }
+        Vec::new()
+    }
+
+    fn confirm(&mut self, peer: PeerId) {
+        if self.confirmed.insert(peer) {
+            self.confirmed_order.push_back(peer);
+            if self.confirmed_order.len() > MAX_PENDING_PEERS {
+                if let Some(oldest) = self.confirmed_order.pop_front() {
+                    self.confirmed.remove(&oldest);
+                }
+            }
+        }
+    }
+}
+
+/// Gossipsub topic carrying `NodeAnnouncement`s
+pub const ANNOUNCE_TOPIC: &str = "chiral/announce/v1";
+
+/// How often a node announces itself after the one at startup
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Announcements not refreshed for this long are forgotten
+pub const ANNOUNCEMENT_TTL: Duration = Duration::from_secs(3 * 5 * 60);
+
+pub fn announce_topic() -> IdentTopic {
+    IdentTopic::new(ANNOUNCE_TOPIC)
+}
+
+/// What a node tells the mesh about itself
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeAnnouncement {
+    pub peer_id: String,
+    pub protocols: Vec<String>,
+    pub agent_version: String,
+    pub uptime_secs: u64,
+    /// Circuit reservations offered, if the node runs a relay server
+    pub relay_capacity: Option<u32>,
+    /// Unix seconds
+    pub timestamp: u64,
+}
+
+impl NodeAnnouncement {
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        serde_json::from_slice(data).ok()
+    }
+}
+
+/// Builds this node's announcements and remembers whether one got out
+pub struct NodeAnnouncementBroadcast {
+    peer_id: String,
+    protocols: Vec<String>,
+    agent_version: String,
+    relay_capacity: Option<u32>,
+    started_at: Instant,
+    delivered: bool,
+}
+
+impl NodeAnnouncementBroadcast {
+    pub fn new(peer_id: &PeerId, relay_capacity: Option<u32>) -> Self {
+        Self {
+            peer_id: peer_id.to_string(),
+            protocols: crate::protocol::advertised_protocols(relay_capacity.is_some()),
+            agent_version: format!("chiral-network/{}", env!("CARGO_PKG_VERSION")),
+            relay_capacity,
+            started_at: Instant::now(),
+            delivered: false,
+        }
+    }
+
+    pub fn announcement(&self) -> NodeAnnouncement {
+        NodeAnnouncement {
+            peer_id: self.peer_id.clone(),
+            protocols: self.protocols.clone(),
+            agent_version: self.agent_version.clone(),
+            uptime_secs: self.started_at.elapsed().as_secs(),
+            relay_capacity: self.relay_capacity,
+            timestamp: now_secs() as u64,
+        }
+    }
+
+    /// No announcement has reached a mesh peer yet; the startup one
+    /// typically goes out before any peer is connected
+    pub fn pending(&self) -> bool {
+        !self.delivered
+    }
+
+    pub fn mark_delivered(&mut self) {
+        self.delivered = true;
+    }
+}
+
+/// Latest announcement received from each peer
+#[derive(Default)]
+pub struct NodeAnnouncementStore {
+    nodes: HashMap<String, NodeAnnouncement>,
+}
+
+impl NodeAnnouncementStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep `announcement` unless we already hold a newer one from that peer;
+    /// returns whether it was stored
+    pub fn apply(&mut self, announcement: NodeAnnouncement) -> bool {
+        match self.nodes.get(&announcement.peer_id) {
+            Some(known) if known.timestamp >= announcement.timestamp => false,
+            _ => {
+                self.nodes.insert(announcement.peer_id.clone(), announcement);
+                true
+            }
+        }
+    }
+
+    pub fn get(&self, peer_id: &str) -> Option<&NodeAnnouncement> {
+        self.nodes.get(peer_id)
+    }
+
+    /// All known nodes, most recently announced first
+    pub fn list(&self) -> Vec<NodeAnnouncement> {
+        let mut nodes: Vec<_> = self.nodes.values().cloned().collect();
+        nodes.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        nodes
+    }
+
+    /// Drop announcements older than `ANNOUNCEMENT_TTL` at `now` (Unix seconds)
+    pub fn expire(&mut self, now: u64) {
+        let cutoff = now.saturating_sub(ANNOUNCEMENT_TTL.as_secs());
+        self.nodes.retain(|_, a| a.timestamp >= cutoff);
+    }
+}
+
+/// How a peer came to be known
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerSource {
+    Kademlia,
+    Mdns,
+    Bootstrap,
+    /// Dialed on request, e.g. through `connect_peer`
+    Manual,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PeerDiscoveryEvent {
+    #[serde(rename_all = "camelCase")]
+    PeerFound { peer_id: String, source: PeerSource },
+    /// First connection to the peer
+    #[serde(rename_all = "camelCase")]
+    PeerConnected { peer_id: String, addr: String },
+    /// Last connection to the peer closed; `graceful` or the error
+    #[serde(rename_all = "camelCase")]
+    PeerDisconnected { peer_id: String, reason: String },
+    /// The peer listens on other addresses than it last announced
+    #[serde(rename_all = "camelCase")]
+    PeerAddressUpdated { peer_id: String, new_addrs: Vec<String> },
+}
+
+/// Events a lagging subscriber may fall behind by before it misses some
+pub const PEER_DISCOVERY_CAPACITY: usize = 256;
+
+/// Sends the swarm task's `PeerDiscoveryEvent`s to every subscriber; each
+/// receives the events sent after it subscribed
+pub struct PeerDiscoveryFeed {
+    tx: broadcast::Sender<PeerDiscoveryEvent>,
+    /// Addresses last reported per connected peer
+    addrs: HashMap<PeerId, Vec<String>>,
+}
+
+impl Default for PeerDiscoveryFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PeerDiscoveryFeed {
+    pub fn new() -> Self {
+        Self {
+            tx: broadcast::channel(PEER_DISCOVERY_CAPACITY).0,
+            addrs: HashMap::new(),
+        }
+    }
+
+    pub fn sender(&self) -> broadcast::Sender<PeerDiscoveryEvent> {
+        self.tx.clone()
+    }
+
+    fn send(&self, event: PeerDiscoveryEvent) {
+        // No subscribers is fine
+        let _ = self.tx.send(event);
+    }
+
+    pub fn found(&self, peer: &PeerId, source: PeerSource) {
+        self.send(PeerDiscoveryEvent::PeerFound {
+            peer_id: peer.to_string(),
+            source,
+        });
+    }
+
+    pub fn connected(&self, peer: &PeerId, addr: &Multiaddr) {
+        self.send(PeerDiscoveryEvent::PeerConnected {
+            peer_id: peer.to_string(),
+            addr: addr.to_string(),
+        });
+    }
+
+    pub fn disconnected(&mut self, peer: &PeerId, reason: String) {
+        self.addrs.remove(peer);
+        self.send(PeerDiscoveryEvent::PeerDisconnected {
+            peer_id: peer.to_string(),
+            reason,
+        });
+    }
+
+    /// Report `addrs` unless they are the ones last reported for `peer`
+    pub fn addresses(&mut self, peer: &PeerId, addrs: &[Multiaddr]) {
+        let mut new_addrs: Vec<String> = addrs.iter().map(|a| a.to_string()).collect();
+        new_addrs.sort();
+        new_addrs.dedup();
+        if self.addrs.get(peer) == Some(&new_addrs) {
+            return;
+        }
+        self.addrs.insert(*peer, new_addrs.clone());
+        self.send(PeerDiscoveryEvent::PeerAddressUpdated {
+            peer_id: peer.to_string(),
+            new_addrs,
+        });
+    }
+}
+
+/// The events of `rx` as a stream, ending when the feed is dropped. A
+/// subscriber that falls too far behind skips the events it missed.
+pub fn peer_discovery_stream(
+    rx: broadcast::Receiver<PeerDiscoveryEvent>,
+) -> impl futures::Stream<Item = PeerDiscoveryEvent> {
+    futures::stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Some((event, rx)),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    debug!("Peer discovery subscriber missed {} events", missed);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> Multiaddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_supplements_mdns_addresses() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = LocalDiscoveryCache::open(&dir.path().join("peers.db")).unwrap();
+        let peer = PeerId::random();
+        let lan = addr("/ip4/192.168.1.20/tcp/4001");
+        let wifi = addr("/ip4/10.0.0.7/tcp/4001");
+
+        cache.record_addresses(&peer, &[lan.clone(), wifi.clone()]).unwrap();
+        cache.record_addresses(&peer, &[lan.clone()]).unwrap();
+
+        assert_eq!(cache.known_addresses(&peer).len(), 2);
+        assert_eq!(cache.supplement(&peer, &[lan]), vec![wifi]);
+        assert!(cache.known_addresses(&PeerId::random()).is_empty());
+    }
+
+    #[test]
+    fn test_caps_addresses_per_peer_and_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("peers.db");
+        let peer = PeerId::random();
+        {
+            let cache = LocalDiscoveryCache::open(&path).unwrap();
+            for port in 0..(DEFAULT_MAX_ADDRESSES_PER_PEER + 4) {
+                let a = addr(&format!("/ip4/192.168.1.20/tcp/{}", 4000 + port));
+                cache.record_addresses(&peer, &[a]).unwrap();
+            }
+        }
+
+        let cache = LocalDiscoveryCache::open(&path).unwrap();
+        let known = cache.known_addresses(&peer);
+        assert_eq!(known.len(), DEFAULT_MAX_ADDRESSES_PER_PEER as usize);
+        assert_eq!(
+            known[0],
+            addr(&format!("/ip4/192.168.1.20/tcp/{}", 4000 + DEFAULT_MAX_ADDRESSES_PER_PEER + 3))
+        );
+        assert!(!known.contains(&addr("/ip4/192.168.1.20/tcp/4000")));
+    }
+
+    #[test]
+    fn test_plain_cache_migrates_into_the_encrypted_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain_path = dir.path().join("peers.db");
+        let peer = PeerId::random();
+        let lan = addr("/ip4/192.168.1.20/tcp/4001");
+        {
+            let plain = LocalDiscoveryCache::open(&plain_path).unwrap();
+            plain.record_addresses(&peer, &[lan.clone()]).unwrap();
+        }
+
+        let key = [7u8; 32];
+        let encrypted =
+            LocalDiscoveryCache::open_encrypted(&dir.path().join("peers.enc.db"), &key).unwrap();
+        assert_eq!(encrypted.migrate_plain(&plain_path).unwrap(), 1);
+        assert_eq!(encrypted.known_addresses(&peer), vec![lan]);
+
+        LocalDiscoveryCache::remove_database(&plain_path).unwrap();
+        assert!(!plain_path.exists());
+        assert_eq!(encrypted.migrate_plain(&plain_path).unwrap(), 0);
+        // Removing what is already gone is not an error
+        LocalDiscoveryCache::remove_database(&plain_path).unwrap();
+    }
+
+    #[test]
+    fn test_peer_store_merges_sources_and_matches_partial_ids() {
+        let now = 1_000_000_000;
+        let fresh = now - 60;
+        let stale = now - STALE_ADDRESS_AGE.as_secs() as i64 - 1;
+        let routed = addr("/ip4/10.0.0.1/tcp/4001");
+        let identified = addr("/ip4/10.0.0.2/tcp/4001");
+        let old = addr("/ip4/10.0.0.3/tcp/4001");
+        let recent = addr("/ip4/10.0.0.4/tcp/4001");
+        let ranked = rank_addresses(
+            vec![
+                (AddressSource::LocalStore, old.clone(), Some(stale)),
+                (AddressSource::LocalStore, recent.clone(), Some(fresh)),
+                (AddressSource::LocalStore, routed.clone(), Some(stale)),
+                (AddressSource::Identify, identified.clone(), Some(fresh)),
+                (AddressSource::RoutingTable, routed.clone(), None),
+            ],
+            now,
+        );
+        assert_eq!(ranked, vec![routed, identified, recent, old]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(LocalDiscoveryCache::open(&dir.path().join("peers.db")).unwrap());
+        let cached_peer = PeerId::random();
+        cache.record_addresses(&cached_peer, &[addr("/ip4/192.168.1.9/tcp/4001")]).unwrap();
+        let mut store = PeerStore::new(Some(cache));
+        let identified_peer = PeerId::random();
+        store.record_identify(identified_peer, vec![addr("/ip4/192.168.1.8/tcp/4001")]);
+
+        let cached_id = cached_peer.to_string();
+        let matches = store.find(&cached_id[cached_id.len() - 8..], &HashMap::new());
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].addrs, vec!["/ip4/192.168.1.9/tcp/4001".to_string()]);
+        assert_eq!(store.find(&identified_peer.to_string(), &HashMap::new()).len(), 1);
+        assert!(store.find("  ", &HashMap::new()).is_empty());
+    }
+
+    #[test]
+    fn test_bootstrap_chain_falls_back_and_rotates() {
+        let peers: Vec<PeerId> = (0..3).map(|_| PeerId::random()).collect();
+        let nodes: Vec<Multiaddr> = peers
+            .iter()
+            .enumerate()
+            .map(|(i, p)| addr(&format!("/ip4/10.0.0.{}/tcp/4001/p2p/{}", i + 1, p)))
+            .collect();
+        let mut chain = BootstrapFallbackChain::new(nodes.clone());
+        let t0 = Instant::now();
+
+        assert_eq!(chain.start(t0), Some(nodes[0].clone()));
+        assert_eq!(chain.poll(t0 + Duration::from_secs(9)), None);
+        assert_eq!(chain.poll(t0 + BOOTSTRAP_NODE_TIMEOUT), Some(nodes[1].clone()));
+        assert_eq!(
+            chain.on_dial_failed(&peers[1], t0 + BOOTSTRAP_NODE_TIMEOUT),
+            Some(nodes[2].clone())
+        );
+
+        // The first connection brings in the rest as extra connections
+        let others = chain.on_connected(&peers[2], &nodes[2]).unwrap();
+        assert_eq!(others, vec![nodes[0].clone(), nodes[1].clone()]);
+        assert!(chain.on_connected(&peers[0], &nodes[0]).is_none());
+
+        // Reconnects start one node further down than the previous attempt
+        assert_eq!(chain.on_disconnected(&peers[2], t0), None);
+        assert_eq!(chain.on_disconnected(&peers[0], t0), Some(nodes[1].clone()));
+    }
+
+    #[test]
+    fn test_bootstrap_mode_chooses_the_peers_dialed() {
+        let configured = vec!["/ip4/10.0.0.1/tcp/4001".to_string()];
+        let peer = "/ip4/192.168.1.9/tcp/4001".to_string();
+
+        assert_eq!(BootstrapMode::Bootstrap.startup_peers(configured.clone()), configured);
+        assert!(BootstrapMode::MdnsOnly.startup_peers(configured.clone()).is_empty());
+        assert!(BootstrapMode::MdnsOnly.uses_mdns());
+
+        let mode: BootstrapMode = format!("static:{}", peer).parse().unwrap();
+        assert_eq!(mode, BootstrapMode::Static(vec![peer.clone()]));
+        assert_eq!(mode.startup_peers(configured.clone()), vec![peer.clone()]);
+        assert_eq!(mode.dialable(vec![configured[0].clone(), peer.clone()]), vec![peer]);
+        assert!(!mode.uses_mdns());
+
+        assert_eq!("mdns-only".parse(), Ok(BootstrapMode::MdnsOnly));
+        assert!("static:not-an-address".parse::<BootstrapMode>().is_err());
+        assert!("everyone".parse::<BootstrapMode>().is_err());
+    }
+
+    #[test]
+    fn test_bootstrap_monitor_reports_down_and_recovered_once() {
+        let node = addr("/ip4/10.0.0.1/tcp/4001");
+        let mut monitor = BootstrapNodeMonitor::new();
+        monitor.set_nodes(vec![node.clone()]);
+        let t0 = Instant::now();
+
+        assert_eq!(monitor.record_failure(&node), None);
+        assert_eq!(
+            monitor.record_failure(&node),
+            Some(BootstrapTransition::Down(node.clone()))
+        );
+        assert_eq!(monitor.record_failure(&node), None);
+        assert_eq!(monitor.status(&node).unwrap().failure_count, 3);
+
+        assert_eq!(
+            monitor.record_success(&node, t0),
+            Some(BootstrapTransition::Recovered(node.clone()))
+        );
+        assert_eq!(monitor.record_success(&node, t0), None);
+        let status = monitor.status(&node).unwrap();
+        assert!(status.is_reachable);
+        assert_eq!((status.failure_count, status.last_success), (0, Some(t0)));
+
+        // Status survives a refresh of the node list
+        monitor.record_failure(&node);
+        monitor.set_nodes(vec![node.clone(), addr("/ip4/10.0.0.2/tcp/4001")]);
+        assert_eq!(monitor.status(&node).unwrap().failure_count, 1);
+        assert_eq!(tcp_endpoint(&node), Some(("10.0.0.1".to_string(), 4001)));
+        assert_eq!(tcp_endpoint(&addr("/ip4/10.0.0.1/udp/4001/quic-v1")), None);
+    }
+
+    #[test]
+    fn test_bootstrap_pruner_quarantines_and_restores() {
+        let up = addr("/ip4/10.0.0.1/tcp/4001");
+        let gone = addr("/ip4/10.0.0.2/tcp/4001");
+        let mut pruner = BootstrapNodePruner::new(3);
+        pruner.set_nodes(vec![up.clone(), gone.clone()]);
+        let check = |pruner: &mut BootstrapNodePruner, up_reachable, gone_reachable, now| {
+            let results: Vec<(Multiaddr, bool)> = pruner
+                .due_for_probe()
+                .into_iter()
+                .map(|a| {
+                    let reachable = if a == up { up_reachable } else { gone_reachable };
+                    (a, reachable)
+                })
+                .collect();
+            pruner.record_round(&results, now)
+        };
+
+        // A success resets the count, and checks nobody answered don't count
+        assert!(check(&mut pruner, true, false, 1).is_empty());
+        assert!(check(&mut pruner, true, false, 2).is_empty());
+        assert!(check(&mut pruner, true, true, 3).is_empty());
+        assert!(check(&mut pruner, true, false, 4).is_empty());
+        assert!(check(&mut pruner, false, false, 5).is_empty());
+        assert!(check(&mut pruner, true, false, 6).is_empty());
+        assert_eq!(check(&mut pruner, true, false, 7).quarantined, vec![gone.clone()]);
+        assert!(check(&mut pruner, true, false, 8).is_empty(), "reported once");
+
+        assert_eq!(pruner.active(), vec![up.clone()]);
+        assert_eq!(pruner.state(&gone), Some(BootstrapNodeState::Quarantined));
+        assert_eq!(
+            pruner.quarantined(),
+            vec![QuarantinedBootstrapNode {
+                addr: gone.to_string(),
+                consecutive_failures: 3,
+                quarantined_at: 7,
+            }]
+        );
+        // Probed again on the tenth check, and back once it answers
+        assert_eq!(pruner.due_for_probe(), vec![up.clone()]);
+        assert_eq!(pruner.due_for_probe(), vec![up.clone(), gone.clone()]);
+        let outcome = pruner.record_round(&[(up.clone(), true), (gone.clone(), true)], 10);
+        assert_eq!(outcome.restored, vec![gone.clone()]);
+        assert_eq!(pruner.active(), vec![up.clone(), gone.clone()]);
+        for now in 11..14 {
+            pruner.record_round(&[(up.clone(), true), (gone.clone(), false)], now);
+        }
+        assert_eq!(pruner.quarantined_addrs(), vec![gone.clone()]);
+
+        // Restarting the DHT keeps the quarantine
+        pruner.set_nodes(vec![up.clone(), gone.clone()]);
+        assert_eq!(pruner.active(), vec![up.clone()]);
+
+        assert!(pruner.restore(&up).is_err());
+        pruner.restore(&gone).unwrap();
+        assert_eq!(pruner.active(), vec![up, gone.clone()]);
+        assert!(pruner.quarantined().is_empty());
+        assert!(pruner
+            .record_round(&[(up.clone(), true), (gone.clone(), false)], 14)
+            .is_empty(), "count starts over");
+
+        // The last active node stays, however long it fails
+        let mut single = BootstrapNodePruner::new(1);
+        single.set_nodes(vec![up.clone(), gone.clone()]);
+        single.record_round(&[(up.clone(), true), (gone.clone(), false)], 1);
+        assert_eq!(single.active(), vec![up.clone()]);
+        for now in 2..5 {
+            single.record_round(&[(up.clone(), false)], now);
+        }
+        assert_eq!(single.active(), vec![up]);
+
+        let mut disabled = BootstrapNodePruner::new(0);
+        disabled.set_nodes(vec![gone.clone()]);
+        assert!((0..20).all(|now| disabled.record_round(&[(gone.clone(), false)], now).is_empty()));
+    }
+
+    #[test]
+    fn test_bootstrap_contributions_credit_the_returning_node() {
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let nodes = vec![
+            addr(&format!("/ip4/10.0.0.1/tcp/4001/p2p/{}", a)),
+            addr(&format!("/ip4/10.0.0.2/tcp/4001/p2p/{}", b)),
+        ];
+        let mut tracker = BootstrapContributionTracker::new(&nodes);
+        let peer_addr = addr("/ip4/10.0.1.1/tcp/4001");
+
+        assert!(tracker.on_connected(a, &nodes[0], true, 1));
+        assert!(tracker.on_connected(b, &nodes[1], true, 1));
+        // Peers nobody returned are not credited
+        assert!(!tracker.on_connected(PeerId::random(), &peer_addr, true, 2));
+
+        let (first, second, local) = (PeerId::random(), PeerId::random(), PeerId::random());
+        tracker.mark_local([local]);
+        tracker.on_introduced(&a, [first, second, local, b], 5);
+        // Only the first node to return a peer is credited with it
+        tracker.on_introduced(&b, [second], 6);
+        tracker.on_connected(first, &peer_addr, true, 7);
+        tracker.on_connected(second, &peer_addr, false, 8);
+        // Reconnects and later dials are counted once
+        tracker.on_connected(first, &peer_addr, true, 9);
+        tracker.on_connected(second, &peer_addr, true, 10);
+
+        let stats = tracker.stats();
+        assert_eq!(stats[0].node_addr, nodes[0].to_string());
+        assert_eq!(stats[0].peers_introduced, 2);
+        assert_eq!(stats[0].successful_dials_to_discovered_peers, 2);
+        assert_eq!(stats[0].last_contribution_time, 10);
+        assert_eq!(stats[1].peers_introduced, 0);
+        assert_eq!(stats[1].successful_dials_to_discovered_peers, 0);
+    }
+
+    #[test]
+    fn test_consensus_releases_addresses_two_bootstraps_agree_on() {
+        let (a, b, c) = (PeerId::random(), PeerId::random(), PeerId::random());
+        assert!(MultiBootstrapConsensus::new(2, [a]).is_none());
+        assert!(MultiBootstrapConsensus::new(1, [a, b]).is_none());
+
+        let mut consensus = MultiBootstrapConsensus::new(2, [a, b, c]).unwrap();
+        let now = Instant::now();
+        let (peer, good, bogus) = (
+            PeerId::random(),
+            addr("/ip4/203.0.113.5/tcp/4001"),
+            addr("/ip4/198.51.100.9/tcp/4001"),
+        );
+        // Until two bootstrap nodes that speak the lookup protocol are
+        // connected, nothing could confirm the peer
+        consensus.set_supports_lookup(a, true);
+        consensus.set_supports_lookup(b, false);
+        consensus.set_supports_lookup(PeerId::random(), true);
+        assert_eq!(consensus.admit(peer, vec![good.clone()], now), Admission::Admitted(vec![good.clone()]));
+        consensus.set_supports_lookup(b, true);
+        consensus.set_supports_lookup(c, true);
+
+        assert_eq!(consensus.admit(a, vec![good.clone()], now), Admission::Admitted(vec![good.clone()]));
+        let Admission::Quarantined { ask } = consensus.admit(peer, vec![good.clone(), bogus.clone()], now) else {
+            panic!("peer admitted unconfirmed");
+        };
+        assert_eq!(ask.len(), 3);
+        assert_eq!(consensus.admit(peer, vec![good.clone()], now), Admission::Quarantined { ask: vec![] });
+
+        // One bootstrap node, or a stranger, is not enough; an empty answer
+        // never confirms
+        assert!(consensus.on_lookup(a, peer, vec![good.clone(), bogus.clone()]).is_empty());
+        assert!(consensus.on_lookup(PeerId::random(), peer, vec![good.clone()]).is_empty());
+        assert!(consensus.on_lookup(c, peer, vec![]).is_empty());
+        assert_eq!(consensus.on_lookup(b, peer, vec![good.clone()]), vec![good.clone()]);
+        assert!(!consensus.pending().contains(&peer));
+        assert_eq!(consensus.admit(peer, vec![bogus.clone()], now), Admission::Admitted(vec![bogus.clone()]));
+
+        // Too few answers to decide: the addresses are used as they are
+        let unanswered = PeerId::random();
+        consensus.admit(unanswered, vec![bogus.clone()], now);
+        assert!(consensus.on_lookup(a, unanswered, vec![]).is_empty());
+        assert!(consensus.on_lookup_failed(b, unanswered).is_empty());
+        assert_eq!(consensus.on_lookup_failed(c, unanswered), vec![bogus.clone()]);
+        assert!(!consensus.pending().contains(&unanswered));
+
+        // Two bootstrap nodes that do not know the peer keep it out
+        let unknown = PeerId::random();
+        consensus.admit(unknown, vec![bogus.clone()], now);
+        consensus.on_lookup(a, unknown, vec![]);
+        consensus.on_lookup(b, unknown, vec![]);
+        assert!(consensus.on_lookup_failed(c, unknown).is_empty());
+        assert!(consensus.pending().contains(&unknown));
+
+        consensus.on_disconnected(&b);
+        consensus.on_disconnected(&c);
+        assert_eq!(
+            consensus.admit(PeerId::random(), vec![good.clone()], now),
+            Admission::Admitted(vec![good.clone()])
+        );
+        consensus.set_supports_lookup(b, true);
+
+        let stale = PeerId::random();
+        consensus.admit(stale, vec![good.clone()], now);
+        consensus.admit(PeerId::random(), vec![good], now + PENDING_ADDRESS_TTL);
+        assert!(!consensus.pending().contains(&stale));
+        assert_eq!(consensus.pending().len(), 1);
+    }
+
+    #[test]
+    fn test_kad_limiter_queues_excess_queries() {
+        let start = Instant::now();
+        let mut limiter = KadRateLimiter::new(KadRateLimitConfig::default(), start);
+        let peers: Vec<PeerId> = (0..40).map(|_| PeerId::random()).collect();
+
+        // The initial burst lets 20 through, the rest wait
+        let started = peers
+            .iter()
+            .filter_map(|p| limiter.submit(KadQuery::ClosestPeers(*p), start))
+            .count();
+        assert_eq!(started, 20);
+        assert_eq!(limiter.queued(), 20);
+        assert!(limiter.submit(KadQuery::ClosestPeers(peers[39]), start).is_none());
+        assert_eq!(limiter.queued(), 20);
+
+        // Queued queries come out in order as tokens refill
+        let ready = limiter.poll(start + Duration::from_millis(500));
+        assert_eq!(ready.len(), 10);
+        assert_eq!(ready[0], KadQuery::ClosestPeers(peers[20]));
+
+        // After the burst window, 5 per second
+        let later = start + KAD_BURST_WINDOW + Duration::from_secs(10);
+        assert_eq!(limiter.poll(later).len(), 5);
+        assert_eq!(limiter.poll(later + Duration::from_millis(400)).len(), 2);
+
+        // Periodic bootstraps and republishing wait their turn as well
+        let key = RecordKey::new(&b"record");
+        assert!(limiter.submit(KadQuery::Bootstrap, later).is_none());
+        assert!(limiter.submit(KadQuery::Republish(key.clone()), later).is_none());
+        assert!(limiter.submit(KadQuery::Republish(key.clone()), later).is_none());
+        assert_eq!(limiter.queued(), 5);
+        let ready = limiter.poll(later + Duration::from_secs(2));
+        assert_eq!(ready[3..], [KadQuery::Bootstrap, KadQuery::Republish(key)]);
+    }
+
+    #[test]
+    fn test_announcement_store_keeps_newest() {
+        let peer = PeerId::random();
+        let announcement = NodeAnnouncementBroadcast::new(&peer, Some(128)).announcement();
+        assert!(announcement
+            .protocols
+            .iter()
+            .any(|p| p == crate::protocol::RELAY_HOP_PROTOCOL));
+        assert_eq!(NodeAnnouncement::decode(&announcement.encode()), Some(announcement.clone()));
+
+        let mut store = NodeAnnouncementStore::new();
+        assert!(store.apply(announcement.clone()));
+        let stale = NodeAnnouncement {
+            timestamp: announcement.timestamp - 1,
+            uptime_secs: 0,
+            ..announcement.clone()
+        };
+        assert!(!store.apply(stale));
+        assert_eq!(store.get(&peer.to_string()), Some(&announcement));
+
+        store.expire(announcement.timestamp + ANNOUNCEMENT_TTL.as_secs() + 1);
+        assert!(store.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_peer_discovery_subscribers_see_later_events() {
+        use futures::StreamExt;
+
+        let mut feed = PeerDiscoveryFeed::new();
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let early = peer_discovery_stream(feed.sender().subscribe());
+        feed.found(&a, PeerSource::Mdns);
+        let late = peer_discovery_stream(feed.sender().subscribe());
+        let addr: Multiaddr = "/ip4/192.168.1.5/tcp/4001".parse().unwrap();
+        feed.connected(&b, &addr);
+        feed.addresses(&b, &[addr.clone()]);
+        feed.addresses(&b, &[addr.clone()]);
+        feed.disconnected(&b, "graceful".to_string());
+        drop(feed);
+
+        let early: Vec<_> = early.collect().await;
+        assert_eq!(early.len(), 4);
+        assert_eq!(
+            early[0],
+            PeerDiscoveryEvent::PeerFound {
+                peer_id: a.to_string(),
+                source: PeerSource::Mdns
+            }
+        );
+        let late: Vec<_> = late.collect().await;
+        assert_eq!(late, early[1..]);
+        assert_eq!(
+            late[1],
+            PeerDiscoveryEvent::PeerAddressUpdated {
+                peer_id: b.to_string(),
+                new_addrs: vec![addr.to_string()]
+            }
+        );
+    }
+}
@@ -99,6 +99,8 @@ pub struct DownloadStatus {
     pub etag: Option<String>,
     pub lease_exp: Option<i64>, // Unix timestamp
     pub last_error: Option<String>,
+    /// SHA-256 the finished file is checked against, if one was given
+    pub expected_sha256: Option<String>,
 }
 
 /// Download error types
@@ -344,6 +346,26 @@ impl DownloadRestartService {
         Self::persist_metadata(&metadata_path, &metadata).await
     }
 
+    /// Write the metadata of every download to disk; returns how many were
+    /// saved
+    pub async fn persist_all(&self) -> usize {
+        let snapshots: Vec<_> = {
+            let downloads = self.downloads.lock().await;
+            downloads
+                .values()
+                .map(|task| (task.metadata.clone(), task.metadata_path.clone()))
+                .collect()
+        };
+        let mut saved = 0;
+        for (metadata, metadata_path) in snapshots {
+            match Self::persist_metadata(&metadata_path, &metadata).await {
+                Ok(()) => saved += 1,
+                Err(e) => warn!("Failed to save {}: {}", metadata_path.display(), e),
+            }
+        }
+        saved
+    }
+
     async fn status_snapshot(&self, download_id: &str) -> Result<DownloadStatus, DownloadError> {
         let downloads = self.downloads.lock().await;
         downloads
@@ -939,6 +961,14 @@ impl DownloadRestartService {
             .ok_or(DownloadError::NotFound)
     }
 
+    /// Status of every download this service knows about
+    pub async fn list_statuses(&self) -> Vec<DownloadStatus> {
+        let downloads = self.downloads.lock().await;
+        let mut statuses: Vec<DownloadStatus> = downloads.values().map(|task| task.status.clone()).collect();
+        statuses.sort_by(|a, b| a.download_id.cmp(&b.download_id));
+        statuses
+    }
+
     /// Start a new download
     pub async fn start_download(
         &self,
@@ -978,6 +1008,7 @@ impl DownloadRestartService {
             etag: None,
             lease_exp: None,
             last_error: None,
+            expected_sha256: request.expected_sha256.clone(),
         };
 
         let metadata_path = Self::metadata_path_for(&dest_path);
@@ -0,0 +1,503 @@
+// Automatic resumption of interrupted downloads
+//
+// Every multi-source download keeps a resume record: the manifest it was
+// started from, its chunk layout and the SHA-256 of each chunk already written
+// to the download's `.chiral-partial` file, which only replaces the real
+// destination once the whole file has been verified. Records survive a crash
+// or restart of the app; on the next start the ones marked auto-resume are
+// queued again once the network is up, with provider discovery run afresh.
+//
+// Before a download continues, a sample of its completed chunks is re-read
+// from the partial file and checked against the recorded digests. A missing
+// partial file, a changed manifest or a failed spot check falls back to
+// downloading the whole file again.
+//
+// Downloads of sensitive content can keep their partial file encrypted with a
+// session key that only ever lives in memory; the plaintext is written once,
+// when the verified file is moved into place. Such a download cannot be
+// continued after a restart, since the key is gone, and starts over instead.
+
+use aes::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+use aes::Aes256;
+use ctr::Ctr128BE;
+use rand::{thread_rng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::RwLock;
+use tracing::warn;
+
+type Aes256Ctr = Ctr128BE<Aes256>;
+
+const RECORDS_VERSION: u32 = 1;
+
+/// Completed chunks re-hashed before an interrupted download is continued
+pub const SPOT_CHECK_SAMPLE: usize = 8;
+
+/// Where a download's verified chunks are written while it is in progress
+pub fn partial_path(output_path: &str) -> PathBuf {
+    crate::storage::download_partial_path(Path::new(output_path))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Session key for a partial file kept encrypted at rest. AES-256-CTR keeps
+/// every byte at its offset, so chunks are encrypted independently and in
+/// any order.
+#[derive(Clone)]
+pub struct PartialCipher {
+    key: [u8; 32],
+    iv: [u8; 16],
+}
+
+impl PartialCipher {
+    pub fn generate() -> Self {
+        let mut key = [0u8; 32];
+        let mut iv = [0u8; 16];
+        thread_rng().fill_bytes(&mut key);
+        thread_rng().fill_bytes(&mut iv);
+        Self { key, iv }
+    }
+
+    /// Encrypt, or decrypt, `data` stored at `offset` of the partial file
+    pub fn apply(&self, offset: u64, data: &mut [u8]) {
+        let mut cipher = Aes256Ctr::new(&self.key.into(), &self.iv.into());
+        cipher.seek(offset);
+        cipher.apply_keystream(data);
+    }
+}
+
+impl std::fmt::Debug for PartialCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PartialCipher(..)")
+    }
+}
+
+/// Persisted state of one unfinished download
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResumeRecord {
+    pub file_hash: String,
+    pub file_name: String,
+    pub output_path: String,
+    /// Merkle root of the manifest the download was started from
+    pub manifest_root: String,
+    pub file_size: u64,
+    pub chunk_size: usize,
+    pub sequential: bool,
+    /// Replace an existing file at `output_path` on completion
+    #[serde(default)]
+    pub overwrite: bool,
+    /// Queue the download again on startup
+    pub auto_resume: bool,
+    /// The partial file is encrypted with a session key that does not
+    /// survive a restart
+    #[serde(default)]
+    pub encrypt_partials: bool,
+    /// Chunk id -> SHA-256 (hex) of the data written to the partial file
+    #[serde(default)]
+    pub completed_chunks: BTreeMap<u32, String>,
+    /// Unix seconds
+    pub updated_at: u64,
+}
+
+impl ResumeRecord {
+    pub fn partial_path(&self) -> PathBuf {
+        partial_path(&self.output_path)
+    }
+
+    /// Byte range of `chunk_id` within the file
+    fn chunk_range(&self, chunk_id: u32) -> (u64, usize) {
+        let offset = chunk_id as u64 * self.chunk_size as u64;
+        let len = self.file_size.saturating_sub(offset).min(self.chunk_size as u64);
+        (offset, len as usize)
+    }
+
+    /// Whether the download was started from a different manifest or layout
+    pub fn manifest_changed(&self, manifest_root: &str, file_size: u64, chunk_size: usize) -> bool {
+        self.manifest_root != manifest_root
+            || self.file_size != file_size
+            || self.chunk_size != chunk_size
+    }
+}
+
+/// Why an interrupted download starts over from the beginning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RestartReason {
+    PartialMissing,
+    ManifestChanged,
+    SpotCheckFailed,
+    /// The partial file was encrypted with a session key of an earlier run
+    SessionKeyLost,
+}
+
+/// How an interrupted download was picked up again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", rename_all = "camelCase")]
+pub enum ResumeOutcome {
+    /// No partial data; the download started from scratch
+    Fresh,
+    #[serde(rename_all = "camelCase")]
+    Continued { chunks_kept: u32, bytes_kept: u64 },
+    Restarted { reason: RestartReason },
+    Failed { error: String },
+}
+
+/// One entry of the `downloads-resumed` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResumedDownload {
+    pub file_hash: String,
+    pub file_name: String,
+    pub output_path: String,
+    #[serde(flatten)]
+    pub outcome: ResumeOutcome,
+}
+
+/// What to do with a download's partial data
+#[derive(Debug)]
+pub enum ResumePlan {
+    /// Keep these chunks (id, data) and fetch the rest
+    Continue(Vec<(u32, Vec<u8>)>),
+    Restart(RestartReason),
+}
+
+/// Evenly spread sample of at most `n` items
+fn spot_check_sample<T>(items: &[T], n: usize) -> Vec<&T> {
+    if items.len() <= n {
+        return items.iter().collect();
+    }
+    (0..n).map(|i| &items[i * items.len() / n]).collect()
+}
+
+/// Decide how to pick up `record` given the manifest found for it now.
+///
+/// Chunks beyond the end of the partial file (written after the record was
+/// last flushed, or lost with the tail of the file) are simply dropped.
+pub async fn plan_resume(
+    record: &ResumeRecord,
+    manifest_root: &str,
+    file_size: u64,
+    chunk_size: usize,
+) -> ResumePlan {
+    if record.encrypt_partials {
+        return ResumePlan::Restart(RestartReason::SessionKeyLost);
+    }
+    if record.manifest_changed(manifest_root, file_size, chunk_size) {
+        return ResumePlan::Restart(RestartReason::ManifestChanged);
+    }
+    let mut file = match tokio::fs::File::open(record.partial_path()).await {
+        Ok(file) => file,
+        Err(_) => return ResumePlan::Restart(RestartReason::PartialMissing),
+    };
+    let partial_len = file.metadata().await.map(|m| m.len()).unwrap_or(0);
+
+    let mut kept = Vec::new();
+    for chunk_id in record.completed_chunks.keys().copied() {
+        let (offset, len) = record.chunk_range(chunk_id);
+        if len == 0 || offset + len as u64 > partial_len {
+            continue;
+        }
+        let mut data = vec![0u8; len];
+        let read = async {
+            file.seek(std::io::SeekFrom::Start(offset)).await?;
+            file.read_exact(&mut data).await
+        };
+        if read.await.is_err() {
+            return ResumePlan::Restart(RestartReason::PartialMissing);
+        }
+        kept.push((chunk_id, data));
+    }
+
+    for (chunk_id, data) in spot_check_sample(&kept, SPOT_CHECK_SAMPLE) {
+        if record.completed_chunks.get(chunk_id) != Some(&sha256_hex(data)) {
+            return ResumePlan::Restart(RestartReason::SpotCheckFailed);
+        }
+    }
+    ResumePlan::Continue(kept)
+}
+
+/// Write a verified chunk at its offset in the partial file
+pub async fn write_partial_chunk(path: &Path, offset: u64, data: &[u8]) -> std::io::Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .await?;
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+    file.write_all(data).await?;
+    file.flush().await
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordsFile {
+    version: u32,
+    records: Vec<ResumeRecord>,
+}
+
+/// Resume records of all unfinished downloads.
+///
+/// Completed chunks are recorded in memory and written out by `flush`, which
+/// the download monitor calls periodically; a chunk that made it into the
+/// partial file but not the record is downloaded again after a crash.
+pub struct ResumeStore {
+    path: PathBuf,
+    records: RwLock<HashMap<String, ResumeRecord>>,
+    dirty: AtomicBool,
+}
+
+impl ResumeStore {
+    pub fn load(path: PathBuf) -> Self {
+        let records = match std::fs::read(&path) {
+            Ok(bytes) => match serde_json::from_slice::<RecordsFile>(&bytes) {
+                Ok(file) if file.version == RECORDS_VERSION => file
+                    .records
+                    .into_iter()
+                    .map(|r| (r.file_hash.clone(), r))
+                    .collect(),
+                Ok(file) => {
+                    warn!("Ignoring resume records with unsupported version {}", file.version);
+                    HashMap::new()
+                }
+                Err(e) => {
+                    warn!("Resume records are corrupted, starting empty: {}", e);
+                    HashMap::new()
+                }
+            },
+            Err(_) => HashMap::new(),
+        };
+        Self {
+            path,
+            records: RwLock::new(records),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    pub fn default_path() -> PathBuf {
+        crate::data_dirs::DataDirs::current().download_resume()
+    }
+
+    pub async fn get(&self, file_hash: &str) -> Option<ResumeRecord> {
+        self.records.read().await.get(file_hash).cloned()
+    }
+
+    pub async fn records(&self) -> Vec<ResumeRecord> {
+        let mut records: Vec<_> = self.records.read().await.values().cloned().collect();
+        records.sort_by_key(|r| r.updated_at);
+        records
+    }
+
+    pub async fn insert(&self, mut record: ResumeRecord) {
+        record.updated_at = now_secs();
+        self.records
+            .write()
+            .await
+            .insert(record.file_hash.clone(), record);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Note that `chunk_id` was written to the partial file with this digest
+    pub async fn record_chunk(&self, file_hash: &str, chunk_id: u32, sha256: String) {
+        if let Some(record) = self.records.write().await.get_mut(file_hash) {
+            record.completed_chunks.insert(chunk_id, sha256);
+            record.updated_at = now_secs();
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns false if there is no record for `file_hash`
+    pub async fn set_auto_resume(&self, file_hash: &str, auto_resume: bool) -> bool {
+        match self.records.write().await.get_mut(file_hash) {
+            Some(record) => {
+                record.auto_resume = auto_resume;
+                self.dirty.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forget a download that finished or was cancelled, with its partial file
+    pub async fn remove(&self, file_hash: &str) -> Option<ResumeRecord> {
+        let record = self.records.write().await.remove(file_hash)?;
+        self.dirty.store(true, Ordering::Relaxed);
+        let _ = tokio::fs::remove_file(record.partial_path()).await;
+        Some(record)
+    }
+
+    /// Write the records out if they changed since the last flush
+    pub async fn flush(&self) -> Result<(), String> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let snapshot = RecordsFile {
+            version: RECORDS_VERSION,
+            records: self.records.read().await.values().cloned().collect(),
+        };
+        let result = async {
+            let json = serde_json::to_vec(&snapshot)
+                .map_err(|e| format!("Failed to serialize resume records: {}", e))?;
+            if let Some(parent) = self.path.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| format!("Failed to create resume records directory: {}", e))?;
+            }
+            // Atomic write: temp file then rename
+            let tmp_path = self.path.with_extension("json.tmp");
+            tokio::fs::write(&tmp_path, json)
+                .await
+                .map_err(|e| format!("Failed to write resume records: {}", e))?;
+            tokio::fs::rename(&tmp_path, &self.path)
+                .await
+                .map_err(|e| format!("Failed to replace resume records: {}", e))
+        }
+        .await;
+        if result.is_err() {
+            // Try again on the next flush
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn record(dir: &TempDir, chunk_size: usize, file_size: u64) -> ResumeRecord {
+        ResumeRecord {
+            file_hash: "hash".to_string(),
+            file_name: "file.bin".to_string(),
+            output_path: dir.path().join("file.bin").to_string_lossy().into_owned(),
+            manifest_root: "root".to_string(),
+            file_size,
+            chunk_size,
+            sequential: false,
+            overwrite: false,
+            auto_resume: true,
+            encrypt_partials: false,
+            completed_chunks: BTreeMap::new(),
+            updated_at: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resume_plan_checks_partial_data() {
+        let dir = TempDir::new().unwrap();
+        let mut record = record(&dir, 4, 10);
+        assert!(matches!(
+            plan_resume(&record, "root", 10, 4).await,
+            ResumePlan::Restart(RestartReason::PartialMissing)
+        ));
+
+        let partial = record.partial_path();
+        write_partial_chunk(&partial, 0, b"abcd").await.unwrap();
+        write_partial_chunk(&partial, 8, b"ij").await.unwrap();
+        record.completed_chunks.insert(0, sha256_hex(b"abcd"));
+        record.completed_chunks.insert(2, sha256_hex(b"ij"));
+        match plan_resume(&record, "root", 10, 4).await {
+            ResumePlan::Continue(chunks) => {
+                assert_eq!(chunks, vec![(0, b"abcd".to_vec()), (2, b"ij".to_vec())])
+            }
+            other => panic!("expected to continue, got {:?}", other),
+        }
+        assert!(matches!(
+            plan_resume(&record, "other-root", 10, 4).await,
+            ResumePlan::Restart(RestartReason::ManifestChanged)
+        ));
+
+        write_partial_chunk(&partial, 0, b"xbcd").await.unwrap();
+        assert!(matches!(
+            plan_resume(&record, "root", 10, 4).await,
+            ResumePlan::Restart(RestartReason::SpotCheckFailed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_partials_hide_data_and_restart() {
+        let dir = TempDir::new().unwrap();
+        let mut record = record(&dir, 4, 10);
+        record.encrypt_partials = true;
+        let cipher = PartialCipher::generate();
+
+        // Chunks written out of order decrypt at their own offsets
+        let partial = record.partial_path();
+        for (offset, chunk) in [(8u64, &b"ij"[..]), (0, &b"abcd"[..]), (4, &b"efgh"[..])] {
+            let mut data = chunk.to_vec();
+            cipher.apply(offset, &mut data);
+            write_partial_chunk(&partial, offset, &data).await.unwrap();
+            record.completed_chunks.insert((offset / 4) as u32, sha256_hex(chunk));
+        }
+        let mut on_disk = tokio::fs::read(&partial).await.unwrap();
+        assert_ne!(on_disk, b"abcdefghij");
+        cipher.apply(0, &mut on_disk);
+        assert_eq!(on_disk, b"abcdefghij");
+
+        assert!(matches!(
+            plan_resume(&record, "root", 10, 4).await,
+            ResumePlan::Restart(RestartReason::SessionKeyLost)
+        ));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn bench_encrypted_partial_writes() {
+        // cargo test --release bench_encrypted_partial_writes -- --ignored --nocapture
+        let dir = TempDir::new().unwrap();
+        let chunk = vec![7u8; 256 * 1024];
+        let chunks = 256u64;
+        let cipher = PartialCipher::generate();
+        for encrypted in [false, true] {
+            let path = dir.path().join(format!("bench-{}", encrypted));
+            let started = std::time::Instant::now();
+            for i in 0..chunks {
+                let offset = i * chunk.len() as u64;
+                let mut data = chunk.clone();
+                if encrypted {
+                    cipher.apply(offset, &mut data);
+                }
+                write_partial_chunk(&path, offset, &data).await.unwrap();
+            }
+            let mib = (chunks * chunk.len() as u64) as f64 / (1024.0 * 1024.0);
+            println!(
+                "encrypted={}: {:.1} MiB/s",
+                encrypted,
+                mib / started.elapsed().as_secs_f64()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_records_survive_reload() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("download_resume.json");
+        let store = ResumeStore::load(path.clone());
+        store.insert(record(&dir, 4, 10)).await;
+        store.record_chunk("hash", 1, sha256_hex(b"efgh")).await;
+        assert!(store.set_auto_resume("hash", false).await);
+        store.flush().await.unwrap();
+
+        let reloaded = ResumeStore::load(path);
+        let record = reloaded.get("hash").await.unwrap();
+        assert!(!record.auto_resume);
+        assert_eq!(record.completed_chunks.len(), 1);
+        assert!(reloaded.remove("hash").await.is_some());
+        assert!(reloaded.records().await.is_empty());
+    }
+}
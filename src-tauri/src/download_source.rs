@@ -4,7 +4,16 @@
 // This module defines a unified interface for different download sources
 // (P2P, HTTP, FTP, etc.) that can be used throughout the application.
 
+use crate::geolocation::is_public_ip;
+use libp2p::identity::{Keypair, PublicKey};
+use libp2p::PeerId;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+
+/// Bytes per hashed chunk of a web-seeded file, the chunk size of
+/// multi-source downloads
+pub const WEB_SEED_CHUNK_SIZE: u64 = 256 * 1024;
 
 /// Represents different types of download sources
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -259,6 +268,211 @@ impl std::fmt::Display for DownloadSource {
     }
 }
 
+impl HttpSourceInfo {
+    /// Source for one of a file's web seed URLs
+    pub fn web_seed(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            auth_header: None,
+            verify_ssl: true,
+            headers: None,
+            timeout_secs: Some(30),
+        }
+    }
+}
+
+/// HTTP(S) copies of a file as its publisher listed them.
+///
+/// The publishing node signs the URLs together with a SHA-256 hash of every
+/// chunk, so other nodes can neither add URLs nor swap what they serve:
+/// downloaders use web seeds only when `verify_web_seeds` accepts them, and
+/// check each chunk fetched over HTTP against `chunk_hashes`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSeeds {
+    pub urls: Vec<String>,
+    /// Bytes per entry of `chunk_hashes`; the last chunk may be shorter
+    pub chunk_size: u64,
+    /// Hex SHA-256 of each chunk, in file order
+    pub chunk_hashes: Vec<String>,
+    /// Peer id of the node that signed; it must also seed the file
+    #[serde(default)]
+    pub publisher: String,
+    /// Hex signature of `publisher` over the file hash, size and the above
+    #[serde(default)]
+    pub signature: String,
+}
+
+impl WebSeeds {
+    /// Not yet signed; `DhtService::publish_file` signs it
+    pub fn new(urls: Vec<String>, chunk_hashes: Vec<String>) -> Self {
+        Self {
+            urls,
+            chunk_size: WEB_SEED_CHUNK_SIZE,
+            chunk_hashes,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        !self.signature.is_empty()
+    }
+
+    fn signing_bytes(&self, file_hash: &str, file_size: u64) -> Vec<u8> {
+        serde_json::to_vec(&(
+            file_hash,
+            file_size,
+            &self.urls,
+            self.chunk_size,
+            &self.chunk_hashes,
+            &self.publisher,
+        ))
+        .unwrap_or_default()
+    }
+
+    /// Sign as the publisher of the file `file_hash`
+    pub fn sign(
+        &mut self,
+        keypair: &Keypair,
+        file_hash: &str,
+        file_size: u64,
+    ) -> Result<(), String> {
+        self.publisher = PeerId::from(keypair.public()).to_string();
+        let signature = keypair
+            .sign(&self.signing_bytes(file_hash, file_size))
+            .map_err(|e| format!("Failed to sign web seeds: {}", e))?;
+        self.signature = hex::encode(signature);
+        Ok(())
+    }
+}
+
+/// Hashes a file chunk by chunk while it is read, for `WebSeeds`
+#[derive(Debug, Clone, Default)]
+pub struct ChunkHasher {
+    current: Sha256,
+    filled: u64,
+    hashes: Vec<String>,
+}
+
+impl ChunkHasher {
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let take = ((WEB_SEED_CHUNK_SIZE - self.filled) as usize).min(data.len());
+            self.current.update(&data[..take]);
+            self.filled += take as u64;
+            data = &data[take..];
+            if self.filled == WEB_SEED_CHUNK_SIZE {
+                self.hashes.push(hex::encode(self.current.finalize_reset()));
+                self.filled = 0;
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<String> {
+        if self.filled > 0 {
+            self.hashes.push(hex::encode(self.current.finalize_reset()));
+        }
+        self.hashes
+    }
+}
+
+/// The public key inside an Ed25519 or Secp256k1 peer id
+fn public_key_of(peer_id: &PeerId) -> Option<PublicKey> {
+    let multihash = peer_id.as_ref();
+    // Identity multihash: the digest is the encoded key itself
+    if multihash.code() != 0 {
+        return None;
+    }
+    PublicKey::try_decode_protobuf(multihash.digest()).ok()
+}
+
+/// The web seeds of a file with `file_hash`, `file_size` and `seeders`, if
+/// they may be used: signed by a node that seeds the file, with one hash
+/// per chunk and only URLs `validate_web_seed` accepts
+pub fn verify_web_seeds(
+    seeds: &WebSeeds,
+    file_hash: &str,
+    file_size: u64,
+    seeders: &[String],
+) -> Result<(), String> {
+    if !seeds.is_signed() {
+        return Err("web seeds are not signed".to_string());
+    }
+    if !seeders.contains(&seeds.publisher) {
+        return Err(format!(
+            "web seeds are signed by {}, which does not seed the file",
+            seeds.publisher
+        ));
+    }
+    let key = seeds
+        .publisher
+        .parse::<PeerId>()
+        .ok()
+        .as_ref()
+        .and_then(public_key_of)
+        .ok_or_else(|| format!("no public key in peer id {}", seeds.publisher))?;
+    let signature =
+        hex::decode(&seeds.signature).map_err(|e| format!("invalid web seed signature: {}", e))?;
+    if !key.verify(&seeds.signing_bytes(file_hash, file_size), &signature) {
+        return Err(format!(
+            "web seed signature of {} does not verify",
+            seeds.publisher
+        ));
+    }
+    let chunks = match seeds.chunk_size {
+        0 => None,
+        size => Some((file_size + size - 1) / size),
+    };
+    if chunks != Some(seeds.chunk_hashes.len() as u64) {
+        return Err("web seed chunk hashes do not cover the file".to_string());
+    }
+    seeds.urls.iter().try_for_each(|url| validate_web_seed(url))
+}
+
+/// Check that `url` can be a web seed: an absolute HTTP(S) URL whose host is
+/// neither a private, loopback or link-local address nor a local name, so a
+/// published file cannot point downloaders at their own network
+pub fn validate_web_seed(url: &str) -> Result<(), String> {
+    let parsed = url::Url::parse(url).map_err(|e| format!("invalid web seed {}: {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("web seed {} must use http or https", url));
+    }
+    let local = match parsed.host() {
+        Some(url::Host::Ipv4(ip)) => !is_public_ip(IpAddr::V4(ip)),
+        Some(url::Host::Ipv6(ip)) => !is_public_ip(IpAddr::V6(ip)),
+        Some(url::Host::Domain(host)) if !host.is_empty() => {
+            let host = host.trim_end_matches('.').to_ascii_lowercase();
+            host == "localhost"
+                || host.ends_with(".localhost")
+                || host.ends_with(".local")
+                || !host.contains('.')
+        }
+        _ => return Err(format!("web seed {} has no host", url)),
+    };
+    if local {
+        return Err(format!("web seed {} points to a local address", url));
+    }
+    Ok(())
+}
+
+/// Resolve the host of `url` and refuse it if any address is not public,
+/// for names that resolve into a private network
+pub async fn check_web_seed_resolves_publicly(url: &str) -> Result<(), String> {
+    let parsed = url::Url::parse(url).map_err(|e| format!("invalid web seed {}: {}", url, e))?;
+    let (Some(host), Some(port)) = (parsed.host_str(), parsed.port_or_known_default()) else {
+        return Err(format!("web seed {} has no host", url));
+    };
+    let addrs: Vec<_> =
+        tokio::net::lookup_host((host.trim_matches(|c| c == '[' || c == ']'), port))
+            .await
+            .map_err(|e| format!("cannot resolve web seed {}: {}", url, e))?
+            .collect();
+    if addrs.is_empty() || addrs.iter().any(|addr| !is_public_ip(addr.ip())) {
+        return Err(format!("web seed {} resolves to a local address", url));
+    }
+    Ok(())
+}
+
 // Helper functions
 
 /// Extract domain/host from URL
@@ -280,6 +494,54 @@ fn extract_domain(url: &str) -> Option<String> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_validate_web_seed() {
+        assert!(validate_web_seed("https://cdn.example.com/files/video.mkv").is_ok());
+        assert!(validate_web_seed("http://93.184.216.34:8080/a.bin").is_ok());
+        assert!(validate_web_seed("http://10.0.0.2:8080/a.bin").is_err());
+        assert!(validate_web_seed("http://127.0.0.1/a.bin").is_err());
+        assert!(validate_web_seed("http://[::1]/a.bin").is_err());
+        assert!(validate_web_seed("http://[fd00::1]/a.bin").is_err());
+        assert!(validate_web_seed("http://169.254.169.254/latest").is_err());
+        assert!(validate_web_seed("http://localhost:8080/a.bin").is_err());
+        assert!(validate_web_seed("http://nas.local/a.bin").is_err());
+        assert!(validate_web_seed("http://intranet/a.bin").is_err());
+        assert!(validate_web_seed("ftp://example.com/a.bin").is_err());
+        assert!(validate_web_seed("example.com/a.bin").is_err());
+    }
+
+    #[test]
+    fn test_web_seeds_need_the_publishers_signature() {
+        let data = vec![7u8; WEB_SEED_CHUNK_SIZE as usize + 10];
+        let mut hasher = ChunkHasher::default();
+        hasher.update(&data[..100]);
+        hasher.update(&data[100..]);
+        let hashes = hasher.finish();
+        assert_eq!(hashes.len(), 2);
+        assert_eq!(
+            hashes[1],
+            hex::encode(Sha256::digest(&data[WEB_SEED_CHUNK_SIZE as usize..]))
+        );
+
+        let keypair = Keypair::generate_ed25519();
+        let mut seeds = WebSeeds::new(vec!["https://cdn.example.com/a.bin".to_string()], hashes);
+        let size = data.len() as u64;
+        assert!(verify_web_seeds(&seeds, "root", size, &[]).is_err());
+        seeds.sign(&keypair, "root", size).unwrap();
+        let seeders = vec![seeds.publisher.clone()];
+        assert!(verify_web_seeds(&seeds, "root", size, &seeders).is_ok());
+
+        // Not a seeder, another file, or URLs added after signing
+        let other = PeerId::random().to_string();
+        assert!(verify_web_seeds(&seeds, "root", size, &[other]).is_err());
+        assert!(verify_web_seeds(&seeds, "other-root", size, &seeders).is_err());
+        let mut extended = seeds.clone();
+        extended
+            .urls
+            .push("https://evil.example.com/a.bin".to_string());
+        assert!(verify_web_seeds(&extended, "root", size, &seeders).is_err());
+    }
+
     #[test]
     fn test_p2p_source_creation() {
         let source = DownloadSource::P2p(P2pSourceInfo {
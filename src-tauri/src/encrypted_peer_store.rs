@@ -0,0 +1,384 @@
+// Encrypted SQLite storage for peer addresses
+//
+// Peer identifiers and the addresses they were reached at reveal who this node
+// talks to. This store keeps the same data as the plain discovery cache but
+// encrypts every peer id and address with AES-256-GCM before it is written.
+// Rows are found through keyed HMAC indexes, so lookups never need the
+// plaintext on disk. The key is supplied by the caller on every open (derived
+// from a password or from the node's own keypair) and is never persisted;
+// only a check value encrypted under it is stored, so a wrong key is rejected
+// instead of silently returning nothing.
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use libp2p::{identity, Multiaddr, PeerId};
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+const NONCE_LEN: usize = 12;
+const CHECK_PLAINTEXT: &[u8] = b"chiral-peer-store-v1";
+
+/// Addresses kept per peer; the least recently seen are dropped first
+pub const DEFAULT_MAX_ADDRESSES_PER_PEER: u32 = 16;
+
+/// Errors produced by the encrypted peer store
+#[derive(Debug, thiserror::Error)]
+pub enum PeerStoreError {
+    #[error("storage error: {0}")]
+    Storage(#[from] rusqlite::Error),
+
+    #[error("encryption error: {0}")]
+    Crypto(String),
+
+    #[error("the key does not match the one this store was created with")]
+    WrongKey,
+
+    #[error("cannot derive a key: {0}")]
+    KeyDerivation(String),
+}
+
+pub type Result<T> = std::result::Result<T, PeerStoreError>;
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+fn expand(key: &[u8; 32], info: &[u8]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    Hkdf::<Sha256>::new(None, key)
+        .expand(info, &mut out)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    out
+}
+
+/// SQLite peer address store with every row value encrypted
+pub struct EncryptedPeerStore {
+    conn: Mutex<Connection>,
+    cipher: Aes256Gcm,
+    index_key: [u8; 32],
+    max_addresses_per_peer: u32,
+}
+
+impl EncryptedPeerStore {
+    /// Default location inside the application data directory
+    pub fn default_path() -> PathBuf {
+        crate::data_dirs::DataDirs::current().encrypted_peer_addresses()
+    }
+
+    /// Derive a store key from a user-supplied password
+    pub fn key_from_password(password: &str, salt: &[u8]) -> [u8; 32] {
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, 100_000, &mut key);
+        key
+    }
+
+    /// Derive a store key from the node's Ed25519 identity with HKDF
+    pub fn key_from_keypair(keypair: &identity::Keypair) -> Result<[u8; 32]> {
+        let ed25519 = keypair
+            .clone()
+            .try_into_ed25519()
+            .map_err(|e| PeerStoreError::KeyDerivation(e.to_string()))?;
+        let secret = ed25519.secret();
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(Some(b"chiral-network"), secret.as_ref())
+            .expand(b"peer store key", &mut key)
+            .map_err(|e| PeerStoreError::KeyDerivation(e.to_string()))?;
+        Ok(key)
+    }
+
+    /// Open (or create) the store at `db_path` with `key`
+    pub fn open(db_path: &Path, key: &[u8; 32]) -> Result<Self> {
+        if let Some(parent) = db_path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                warn!("Failed to create peer store directory {:?}: {}", parent, e);
+            }
+        }
+
+        let conn = Connection::open(db_path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS store_meta (
+                name  TEXT PRIMARY KEY,
+                value BLOB NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS peer_addresses (
+                entry_index TEXT PRIMARY KEY,
+                peer_index  TEXT NOT NULL,
+                peer_id     BLOB NOT NULL,
+                address     BLOB NOT NULL,
+                last_seen   INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_peer_addresses_peer
+                ON peer_addresses (peer_index, last_seen);",
+        )?;
+
+        let store = Self {
+            conn: Mutex::new(conn),
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&expand(key, b"encryption"))),
+            index_key: expand(key, b"index"),
+            max_addresses_per_peer: DEFAULT_MAX_ADDRESSES_PER_PEER,
+        };
+        store.verify_key()?;
+        Ok(store)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Check the key against the stored check value, writing one for a new store
+    fn verify_key(&self) -> Result<()> {
+        let conn = self.lock();
+        let check: Option<Vec<u8>> = conn
+            .query_row(
+                "SELECT value FROM store_meta WHERE name = 'key_check'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match check {
+            Some(value) => match self.decrypt(&value, b"key_check") {
+                Ok(plain) if plain == CHECK_PLAINTEXT => Ok(()),
+                _ => Err(PeerStoreError::WrongKey),
+            },
+            None => {
+                let value = self.encrypt(CHECK_PLAINTEXT, b"key_check")?;
+                conn.execute(
+                    "INSERT INTO store_meta (name, value) VALUES ('key_check', ?1)",
+                    params![value],
+                )?;
+                Ok(())
+            }
+        }
+    }
+
+    fn blind_index(&self, parts: &[&[u8]]) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.index_key)
+            .expect("HMAC accepts keys of any length");
+        for part in parts {
+            mac.update(&(part.len() as u32).to_be_bytes());
+            mac.update(part);
+        }
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// Nonce followed by the ciphertext; `column` is bound as associated data
+    fn encrypt(&self, plaintext: &[u8], column: &[u8]) -> Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, Payload { msg: plaintext, aad: column })
+            .map_err(|e| PeerStoreError::Crypto(e.to_string()))?;
+        let mut out = nonce.to_vec();
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+
+    fn decrypt(&self, data: &[u8], column: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < NONCE_LEN {
+            return Err(PeerStoreError::Crypto("ciphertext too short".to_string()));
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: column })
+            .map_err(|e| PeerStoreError::Crypto(e.to_string()))
+    }
+
+    /// Remember `addresses` for `peer_id`, refreshing ones already known
+    pub fn record_addresses(&self, peer_id: &PeerId, addresses: &[Multiaddr]) -> Result<()> {
+        if addresses.is_empty() {
+            return Ok(());
+        }
+        let peer_bytes = peer_id.to_bytes();
+        let peer_index = self.blind_index(&[&peer_bytes]);
+        let now = now_secs();
+
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+        for address in addresses {
+            let address_bytes = address.to_vec();
+            let entry_index = self.blind_index(&[&peer_bytes, &address_bytes]);
+            tx.execute(
+                "INSERT INTO peer_addresses (entry_index, peer_index, peer_id, address, last_seen)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(entry_index) DO UPDATE SET last_seen = excluded.last_seen",
+                params![
+                    entry_index,
+                    peer_index,
+                    self.encrypt(&peer_bytes, b"peer_id")?,
+                    self.encrypt(&address_bytes, b"address")?,
+                    now
+                ],
+            )?;
+        }
+        tx.execute(
+            "DELETE FROM peer_addresses
+             WHERE peer_index = ?1 AND entry_index NOT IN (
+                 SELECT entry_index FROM peer_addresses WHERE peer_index = ?1
+                 ORDER BY last_seen DESC, rowid DESC LIMIT ?2
+             )",
+            params![peer_index, self.max_addresses_per_peer as i64],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Every known address for `peer_id`, most recently seen first
+    pub fn known_addresses(&self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.known_addresses_seen(peer_id)
+            .into_iter()
+            .map(|(address, _)| address)
+            .collect()
+    }
+
+    /// Every known address for `peer_id` with when it was last seen (unix
+    /// seconds), most recently seen first
+    pub fn known_addresses_seen(&self, peer_id: &PeerId) -> Vec<(Multiaddr, i64)> {
+        let peer_index = self.blind_index(&[&peer_id.to_bytes()]);
+        let conn = self.lock();
+        let rows = conn
+            .prepare(
+                "SELECT address, last_seen FROM peer_addresses WHERE peer_index = ?1
+                 ORDER BY last_seen DESC, rowid DESC",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_map(params![peer_index], |row| {
+                    Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, i64>(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+            });
+
+        match rows {
+            Ok(rows) => rows
+                .iter()
+                .filter_map(|(data, last_seen)| match self.decrypt(data, b"address") {
+                    Ok(bytes) => Multiaddr::try_from(bytes).ok().map(|a| (a, *last_seen)),
+                    Err(e) => {
+                        warn!("Skipping unreadable peer store row for {}: {}", peer_id, e);
+                        None
+                    }
+                })
+                .collect(),
+            Err(e) => {
+                warn!("Failed to load stored addresses for {}: {}", peer_id, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Every peer with a stored address. Peer ids are only stored encrypted,
+    /// so this decrypts each row.
+    pub fn peer_ids(&self) -> Vec<PeerId> {
+        let conn = self.lock();
+        let rows = conn
+            .prepare("SELECT peer_id FROM peer_addresses GROUP BY peer_index")
+            .and_then(|mut stmt| {
+                stmt.query_map([], |row| row.get::<_, Vec<u8>>(0))?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            });
+        match rows {
+            Ok(rows) => rows
+                .iter()
+                .filter_map(|data| self.decrypt(data, b"peer_id").ok())
+                .filter_map(|bytes| PeerId::from_bytes(&bytes).ok())
+                .collect(),
+            Err(e) => {
+                warn!("Failed to list stored peers: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Fold the write-ahead log into the database file
+    pub fn checkpoint(&self) -> Result<()> {
+        self.lock().execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+        Ok(())
+    }
+
+    /// Drop addresses not seen within `max_age`
+    pub fn prune(&self, max_age: Duration) -> Result<usize> {
+        let cutoff = now_secs() - max_age.as_secs() as i64;
+        Ok(self.lock().execute(
+            "DELETE FROM peer_addresses WHERE last_seen < ?1",
+            params![cutoff],
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> Multiaddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_without_plaintext_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("peers.db");
+        let key = [7u8; 32];
+        let peer = PeerId::random();
+        let lan = addr("/ip4/192.168.1.20/tcp/4001");
+        let wifi = addr("/ip4/10.0.0.7/tcp/4001");
+
+        {
+            let store = EncryptedPeerStore::open(&path, &key).unwrap();
+            store.record_addresses(&peer, &[lan.clone(), wifi.clone()]).unwrap();
+            store.record_addresses(&peer, &[lan.clone()]).unwrap();
+        }
+
+        let store = EncryptedPeerStore::open(&path, &key).unwrap();
+        let known = store.known_addresses(&peer);
+        assert_eq!(known.len(), 2);
+        assert!(known.contains(&lan) && known.contains(&wifi));
+        assert!(store.known_addresses(&PeerId::random()).is_empty());
+
+        let conn = Connection::open(&path).unwrap();
+        let raw: Vec<(Vec<u8>, Vec<u8>)> = conn
+            .prepare("SELECT peer_id, address FROM peer_addresses")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(raw.len(), 2);
+        let contains = |haystack: &[u8], needle: &[u8]| {
+            haystack.windows(needle.len()).any(|w| w == needle)
+        };
+        for (peer_col, address_col) in &raw {
+            assert!(!contains(peer_col, &peer.to_bytes()));
+            assert!(!contains(address_col, &lan.to_vec()));
+            assert!(!contains(address_col, &wifi.to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_wrong_key_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("peers.db");
+        EncryptedPeerStore::open(&path, &[1u8; 32]).unwrap();
+
+        assert!(matches!(
+            EncryptedPeerStore::open(&path, &[2u8; 32]),
+            Err(PeerStoreError::WrongKey)
+        ));
+
+        let keypair = identity::Keypair::generate_ed25519();
+        let a = EncryptedPeerStore::key_from_keypair(&keypair).unwrap();
+        assert_eq!(a, EncryptedPeerStore::key_from_keypair(&keypair).unwrap());
+        assert_ne!(a, EncryptedPeerStore::key_from_password("pw", b"salt"));
+    }
+}
@@ -54,6 +54,11 @@ impl FileEncryption {
     pub fn generate_random_key() -> [u8; 32] {
         let mut key = [0u8; 32];
         OsRng.fill_bytes(&mut key);
+        crate::crypto::audit::record(
+            crate::crypto::CryptoOperation::KeyGenerated,
+            None,
+            format!("AES-256 file key {}", Self::generate_key_fingerprint(&key)),
+        );
         key
     }
 
@@ -517,7 +522,13 @@ pub fn verify_message(signed_message: &SignedMessage) -> Result<bool, String> {
 
     // 2. Verify the signature against the message.
     // The `verify` method will hash the message internally before checking.
-    Ok(signer_public_key.verify(&message_bytes, &signature).is_ok())
+    let valid = signer_public_key.verify(&message_bytes, &signature).is_ok();
+    crate::crypto::audit::record(
+        crate::crypto::CryptoOperation::verification(valid),
+        None,
+        format!("message signed by {}", signed_message.signer_public_key),
+    );
+    Ok(valid)
 }
 
 #[cfg(test)]
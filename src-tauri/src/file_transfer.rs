@@ -1,13 +1,13 @@
+use crate::compression::{self, CompressionStats};
 use crate::encryption;
 use crate::transfer_events::{
     TransferEventBus, TransferCompletedEvent, TransferFailedEvent,
     TransferStartedEvent, SourceInfo, SourceType, SourceSummary, ErrorCategory,
     current_timestamp_ms,
 };
-use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use tauri::AppHandle;
@@ -37,6 +37,671 @@ pub struct FileResponse {
     pub file_size: u64,
 }
 
+// ------ Direct file transfer protocol ------
+//
+// Sends a file straight to one peer over request-response: the sender offers
+// the file, and once the receiver accepts it streams fixed-size chunks in
+// order and finishes with `Complete`, at which point the receiver checks the
+// SHA-256 hash and moves the file into its download directory.
+//
+// For compressible content the offer lists the codecs the sender supports;
+// if the receiver picks one, chunks that shrink are sent compressed. Offsets,
+// acks and the hash always refer to the uncompressed file.
+//
+// The wire format is versioned in the offer. From version 2 every chunk frame
+// carries its index, length and a CRC32 of the bytes on the wire; a receiver
+// that finds a damaged frame answers `Nack` and the sender resends just that
+// frame. Both sides fall back to version 1 when the other end is older: new
+// fields are ignored by old decoders, and an old receiver's plain `Accept`
+// tells the sender not to expect NACKs.
+
+/// Bytes carried by each `FileTransferRequest::Chunk`
+pub const DIRECT_TRANSFER_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Largest frame accepted from the wire: one chunk plus encoding overhead
+const MAX_TRANSFER_FRAME: usize = DIRECT_TRANSFER_CHUNK_SIZE + 4096;
+
+/// Newest direct transfer wire format this node speaks
+pub const TRANSFER_WIRE_VERSION: u32 = 2;
+
+/// Version assumed for offers from nodes that predate versioning
+const LEGACY_WIRE_VERSION: u32 = 1;
+
+/// Times one chunk is resent after a NACK or a failed request
+pub const MAX_CHUNK_RETRANSMITS: u32 = 3;
+
+/// Offers waiting for the user plus transfers being received, from all
+/// peers together
+pub const MAX_INCOMING_TRANSFERS: usize = 4;
+
+/// Request timeout of the protocol. Offers stay open this long while the
+/// user decides.
+pub const OFFER_TIMEOUT: Duration = Duration::from_secs(120);
+
+fn legacy_wire_version() -> u32 {
+    LEGACY_WIRE_VERSION
+}
+
+/// Checksum carried in version 2 chunk frames, over the bytes on the wire
+pub fn chunk_checksum(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileTransferProtocol;
+
+impl AsRef<str> for FileTransferProtocol {
+    fn as_ref(&self) -> &str {
+        crate::protocol::FILE_TRANSFER_PROTOCOL
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileTransferRequest {
+    Offer {
+        filename: String,
+        size: u64,
+        /// SHA-256 of the whole file
+        hash: [u8; 32],
+        /// Codecs the sender would compress chunks with; empty for content
+        /// that does not compress
+        #[serde(default)]
+        compression: Vec<String>,
+        /// Newest wire format the sender speaks
+        #[serde(default = "legacy_wire_version")]
+        version: u32,
+    },
+    Chunk {
+        offset: u64,
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>,
+        /// `data` is compressed with the codec picked in `AcceptCompressed`
+        #[serde(default)]
+        compressed: bool,
+        /// Position of the chunk in the transfer (version 2)
+        #[serde(default)]
+        index: u32,
+        /// Length of `data` as sent (version 2)
+        #[serde(default)]
+        length: u32,
+        /// `chunk_checksum` of `data` as sent (version 2)
+        #[serde(default)]
+        checksum: Option<u32>,
+    },
+    Complete,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileTransferResponse {
+    /// The offer was accepted; chunks may follow
+    Accept,
+    /// The offer was accepted, and chunks may be compressed with `codec`
+    AcceptCompressed { codec: String },
+    /// The offer, a chunk or the completed file was refused; the transfer is over
+    Reject { reason: String },
+    /// A chunk (or the completed file) was written
+    Ack { bytes_received: u64 },
+    /// The offer was accepted in wire format `version` (2 or later), with
+    /// chunks compressed with `codec` if set
+    AcceptVersioned { version: u32, codec: Option<String> },
+    /// Chunk `index` arrived damaged and was dropped; the sender should
+    /// resend it. Only sent in version 2 transfers.
+    Nack { index: u32, reason: String },
+}
+
+impl FileTransferRequest {
+    /// A chunk frame with its index, length and checksum filled in. `data`
+    /// is what goes on the wire, i.e. already compressed if `compressed`.
+    pub fn chunk(index: u32, offset: u64, data: Vec<u8>, compressed: bool) -> Self {
+        FileTransferRequest::Chunk {
+            offset,
+            index,
+            length: data.len() as u32,
+            checksum: Some(chunk_checksum(&data)),
+            data,
+            compressed,
+        }
+    }
+}
+
+impl FileTransferResponse {
+    /// Wire version and codec agreed in the answer to an offer, or `None`
+    /// if the offer was not accepted
+    pub fn negotiated(&self) -> Option<(u32, Option<&str>)> {
+        match self {
+            FileTransferResponse::Accept => Some((LEGACY_WIRE_VERSION, None)),
+            FileTransferResponse::AcceptCompressed { codec } => {
+                Some((LEGACY_WIRE_VERSION, Some(codec.as_str())))
+            }
+            FileTransferResponse::AcceptVersioned { version, codec } => {
+                Some((*version, codec.as_deref()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Payload of the `file-transfer-progress` event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DirectTransferProgress {
+    /// Assigned when the offer is accepted; the same file name can arrive
+    /// twice
+    pub transfer_id: String,
+    pub peer_id: String,
+    pub filename: String,
+    pub bytes_received: u64,
+    pub total_bytes: u64,
+    /// Bytes that crossed the wire for `bytes_received`
+    pub wire_bytes: u64,
+}
+
+/// Payload of the `file-transfer-offer` event: a peer wants to send us a
+/// file and waits for `accept_file_transfer` or `reject_file_transfer`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileOffer {
+    pub transfer_id: String,
+    pub peer_id: String,
+    pub filename: String,
+    /// Size the sender declared; chunks beyond it are refused
+    pub size: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FileTransferCodec;
+
+async fn read_transfer_frame<T, M>(io: &mut T) -> std::io::Result<M>
+where
+    T: futures::AsyncRead + Unpin + Send,
+    M: serde::de::DeserializeOwned,
+{
+    use futures::AsyncReadExt;
+    let mut len_buf = [0u8; 4];
+    io.read_exact(&mut len_buf).await?;
+    let len = u32::from_le_bytes(len_buf) as usize;
+    if len > MAX_TRANSFER_FRAME {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("file transfer frame of {} bytes exceeds the limit", len),
+        ));
+    }
+    let mut data = vec![0u8; len];
+    io.read_exact(&mut data).await?;
+    ciborium::from_reader(data.as_slice())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+}
+
+async fn write_transfer_frame<T, M>(io: &mut T, message: &M) -> std::io::Result<()>
+where
+    T: futures::AsyncWrite + Unpin + Send,
+    M: Serialize,
+{
+    use futures::AsyncWriteExt;
+    let mut data = Vec::new();
+    ciborium::into_writer(message, &mut data)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+    io.write_all(&(data.len() as u32).to_le_bytes()).await?;
+    io.write_all(&data).await?;
+    io.flush().await
+}
+
+#[async_trait::async_trait]
+impl libp2p::request_response::Codec for FileTransferCodec {
+    type Protocol = FileTransferProtocol;
+    type Request = FileTransferRequest;
+    type Response = FileTransferResponse;
+
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> std::io::Result<Self::Request>
+    where
+        T: futures::AsyncRead + Unpin + Send,
+    {
+        read_transfer_frame(io).await
+    }
+
+    async fn read_response<T>(&mut self, _: &Self::Protocol, io: &mut T) -> std::io::Result<Self::Response>
+    where
+        T: futures::AsyncRead + Unpin + Send,
+    {
+        read_transfer_frame(io).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        request: Self::Request,
+    ) -> std::io::Result<()>
+    where
+        T: futures::AsyncWrite + Unpin + Send,
+    {
+        write_transfer_frame(io, &request).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        response: Self::Response,
+    ) -> std::io::Result<()>
+    where
+        T: futures::AsyncWrite + Unpin + Send,
+    {
+        write_transfer_frame(io, &response).await
+    }
+}
+
+struct IncomingTransfer {
+    id: String,
+    filename: String,
+    size: u64,
+    hash: [u8; 32],
+    part_path: PathBuf,
+    file: tokio::fs::File,
+    hasher: sha2::Sha256,
+    received: u64,
+    /// Codec picked for this transfer, if any
+    codec: Option<String>,
+    wire_bytes: u64,
+    /// Wire format agreed for this transfer
+    version: u32,
+    /// Index of the next chunk expected (version 2)
+    next_index: u32,
+    /// Size of the last chunk written, to recognise a resend of it
+    last_chunk_len: u64,
+}
+
+/// An offer the user has not answered yet, with what was negotiated for it
+struct PendingOffer {
+    offer: FileOffer,
+    hash: [u8; 32],
+    codec: Option<String>,
+    version: u32,
+}
+
+/// What to do with a request `IncomingFileTransfers::handle` was given
+#[derive(Debug)]
+pub enum Handled {
+    /// Send the response now, reporting the progress if bytes were accepted
+    Answer(FileTransferResponse, Option<DirectTransferProgress>),
+    /// Hold the response until the user accepts or rejects the offer
+    Offered(FileOffer),
+}
+
+/// Receiving side of direct transfers, one transfer per sending peer
+pub struct IncomingFileTransfers {
+    download_dir: PathBuf,
+    active: std::collections::HashMap<String, IncomingTransfer>,
+    /// Offers waiting for the user, by transfer id
+    pending: std::collections::HashMap<String, PendingOffer>,
+    /// Accept compressed chunks when the sender offers them
+    compression: bool,
+    /// Off on nodes that never store files, such as infra nodes
+    accepting: bool,
+    stats: CompressionStats,
+}
+
+/// `filename` without any directory components, if anything is left
+fn sanitize_filename(filename: &str) -> Option<String> {
+    let name = std::path::Path::new(filename).file_name()?.to_str()?.trim();
+    if name.is_empty() || name == "." || name == ".." {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+async fn is_taken(path: &Path) -> bool {
+    tokio::fs::try_exists(path).await.unwrap_or(false)
+}
+
+/// `dir/name`, or `dir/name (n).ext` for the first `n` that is not taken
+async fn unique_destination(dir: &std::path::Path, name: &str) -> PathBuf {
+    let candidate = dir.join(name);
+    if !is_taken(&candidate).await {
+        return candidate;
+    }
+    let path = std::path::Path::new(name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+    let ext = path.extension().and_then(|e| e.to_str());
+    let mut n = 1;
+    loop {
+        let candidate = match ext {
+            Some(ext) => dir.join(format!("{} ({}).{}", stem, n, ext)),
+            None => dir.join(format!("{} ({})", stem, n)),
+        };
+        if !is_taken(&candidate).await {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+fn refusal(reason: impl Into<String>) -> FileTransferResponse {
+    FileTransferResponse::Reject {
+        reason: reason.into(),
+    }
+}
+
+impl IncomingFileTransfers {
+    pub fn new(download_dir: PathBuf) -> Self {
+        Self {
+            download_dir,
+            active: std::collections::HashMap::new(),
+            pending: std::collections::HashMap::new(),
+            compression: true,
+            accepting: true,
+            stats: CompressionStats::default(),
+        }
+    }
+
+    pub fn set_compression(&mut self, enabled: bool) {
+        self.compression = enabled;
+    }
+
+    /// Reject every offer when `accepting` is false
+    pub fn set_accepting(&mut self, accepting: bool) {
+        self.accepting = accepting;
+    }
+
+    /// Bytes received so far, logical and on the wire
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.stats
+    }
+
+    /// The user's download directory, falling back to the working directory
+    pub fn default_download_dir() -> PathBuf {
+        directories::UserDirs::new()
+            .and_then(|dirs| dirs.download_dir().map(|d| d.to_path_buf()))
+            .unwrap_or_else(|| PathBuf::from("."))
+    }
+
+    pub fn set_download_dir(&mut self, download_dir: PathBuf) {
+        self.download_dir = download_dir;
+    }
+
+    pub fn download_dir(&self) -> &Path {
+        &self.download_dir
+    }
+
+    /// Transfers being received
+    pub fn active_count(&self) -> usize {
+        self.active.len()
+    }
+
+    /// The offer `transfer_id`, if it still waits for the user
+    pub fn pending_offer(&self, transfer_id: &str) -> Option<&FileOffer> {
+        self.pending.get(transfer_id).map(|pending| &pending.offer)
+    }
+
+    /// Drop the transfer or offer of a sender that went away, removing its
+    /// partial file
+    pub async fn abort(&mut self, peer_id: &str) {
+        self.pending.retain(|_, pending| pending.offer.peer_id != peer_id);
+        if let Some(transfer) = self.active.remove(peer_id) {
+            drop(transfer.file);
+            let _ = tokio::fs::remove_file(&transfer.part_path).await;
+        }
+    }
+
+    async fn reject(&mut self, peer_id: &str, reason: impl Into<String>) -> FileTransferResponse {
+        self.abort(peer_id).await;
+        refusal(reason)
+    }
+
+    /// Start receiving the offer `transfer_id`, creating its partial file.
+    /// The response goes to the sender either way; the progress is set when
+    /// the transfer started.
+    pub async fn accept(&mut self, transfer_id: &str) -> (FileTransferResponse, Option<DirectTransferProgress>) {
+        use sha2::Digest;
+
+        let Some(PendingOffer {
+            offer,
+            hash,
+            codec,
+            version,
+        }) = self.pending.remove(transfer_id)
+        else {
+            return (refusal("the offer is no longer pending"), None);
+        };
+        if let Err(e) = tokio::fs::create_dir_all(&self.download_dir).await {
+            return (refusal(format!("cannot create download directory: {}", e)), None);
+        }
+        let part_path = unique_destination(&self.download_dir, &format!("{}.part", offer.filename)).await;
+        let file = match tokio::fs::File::create(&part_path).await {
+            Ok(file) => file,
+            Err(e) => return (refusal(format!("cannot create file: {}", e)), None),
+        };
+        info!(
+            "Accepted direct transfer of {} ({} bytes, compression: {}) from {}",
+            offer.filename,
+            offer.size,
+            codec.as_deref().unwrap_or("none"),
+            offer.peer_id
+        );
+        let progress = DirectTransferProgress {
+            transfer_id: offer.transfer_id.clone(),
+            peer_id: offer.peer_id.clone(),
+            filename: offer.filename.clone(),
+            bytes_received: 0,
+            total_bytes: offer.size,
+            wire_bytes: 0,
+        };
+        let response = match (&codec, version) {
+            (_, v) if v > LEGACY_WIRE_VERSION => FileTransferResponse::AcceptVersioned {
+                version,
+                codec: codec.clone(),
+            },
+            (Some(codec), _) => FileTransferResponse::AcceptCompressed {
+                codec: codec.clone(),
+            },
+            (None, _) => FileTransferResponse::Accept,
+        };
+        self.active.insert(
+            offer.peer_id,
+            IncomingTransfer {
+                id: offer.transfer_id,
+                filename: offer.filename,
+                size: offer.size,
+                hash,
+                part_path,
+                file,
+                hasher: sha2::Sha256::new(),
+                received: 0,
+                codec,
+                wire_bytes: 0,
+                version,
+                next_index: 0,
+                last_chunk_len: 0,
+            },
+        );
+        (response, Some(progress))
+    }
+
+    /// Refuse the offer `transfer_id` before anything was written
+    pub fn decline(&mut self, transfer_id: &str, reason: impl Into<String>) -> FileTransferResponse {
+        self.pending.remove(transfer_id);
+        refusal(reason)
+    }
+
+    /// Handle one request from `peer_id`. Offers are checked and held for the
+    /// user; chunks and completions are answered at once.
+    pub async fn handle(&mut self, peer_id: &str, request: FileTransferRequest) -> Handled {
+        use sha2::Digest;
+        use tokio::io::AsyncWriteExt;
+
+        match request {
+            FileTransferRequest::Offer {
+                filename,
+                size,
+                hash,
+                compression: offered_codecs,
+                version,
+            } => {
+                if !self.accepting {
+                    return Handled::Answer(refusal("this node does not accept transfers"), None);
+                }
+                if self.active.contains_key(peer_id)
+                    || self.pending.values().any(|pending| pending.offer.peer_id == peer_id)
+                {
+                    return Handled::Answer(refusal("a transfer from this peer is already in progress"), None);
+                }
+                if self.active.len() + self.pending.len() >= MAX_INCOMING_TRANSFERS {
+                    return Handled::Answer(refusal("too many incoming transfers"), None);
+                }
+                let Some(filename) = sanitize_filename(&filename) else {
+                    return Handled::Answer(refusal("invalid file name"), None);
+                };
+                let codec = offered_codecs
+                    .into_iter()
+                    .find(|codec| self.compression && compression::SUPPORTED_CODECS.contains(&codec.as_str()));
+                info!("{} offers {} ({} bytes) for direct transfer", peer_id, filename, size);
+                let offer = FileOffer {
+                    transfer_id: uuid::Uuid::new_v4().to_string(),
+                    peer_id: peer_id.to_string(),
+                    filename,
+                    size,
+                };
+                self.pending.insert(
+                    offer.transfer_id.clone(),
+                    PendingOffer {
+                        offer: offer.clone(),
+                        hash,
+                        codec,
+                        version: version.clamp(LEGACY_WIRE_VERSION, TRANSFER_WIRE_VERSION),
+                    },
+                );
+                Handled::Offered(offer)
+            }
+            FileTransferRequest::Chunk {
+                offset,
+                data,
+                compressed,
+                index,
+                length,
+                checksum,
+            } => {
+                let Some(transfer) = self.active.get_mut(peer_id) else {
+                    return Handled::Answer(self.reject(peer_id, "no transfer in progress").await, None);
+                };
+                let framed = transfer.version > LEGACY_WIRE_VERSION;
+                if framed {
+                    let damage = if length as usize != data.len() {
+                        Some(format!("frame carries {} of {} bytes", data.len(), length))
+                    } else if checksum != Some(chunk_checksum(&data)) {
+                        Some("checksum mismatch".to_string())
+                    } else {
+                        None
+                    };
+                    if let Some(reason) = damage {
+                        warn!("Damaged chunk {} from {}: {}", index, peer_id, reason);
+                        return Handled::Answer(FileTransferResponse::Nack { index, reason }, None);
+                    }
+                    // The ack for the previous chunk got lost and it was resent
+                    if index + 1 == transfer.next_index
+                        && offset + transfer.last_chunk_len == transfer.received
+                    {
+                        let response = FileTransferResponse::Ack {
+                            bytes_received: transfer.received,
+                        };
+                        return Handled::Answer(response, None);
+                    }
+                    if index != transfer.next_index {
+                        let reason = format!("expected chunk {}, got {}", transfer.next_index, index);
+                        return Handled::Answer(self.reject(peer_id, reason).await, None);
+                    }
+                }
+                let wire_len = data.len();
+                let data = if !compressed {
+                    data
+                } else if transfer.codec.is_none() {
+                    let reason = "compressed chunk without a negotiated codec";
+                    return Handled::Answer(self.reject(peer_id, reason).await, None);
+                } else {
+                    match compression::decompress(&data, DIRECT_TRANSFER_CHUNK_SIZE) {
+                        Ok(data) => data,
+                        Err(e) if framed => {
+                            let reason = format!("bad compressed chunk: {}", e);
+                            return Handled::Answer(FileTransferResponse::Nack { index, reason }, None);
+                        }
+                        Err(e) => {
+                            let reason = format!("bad compressed chunk: {}", e);
+                            return Handled::Answer(self.reject(peer_id, reason).await, None);
+                        }
+                    }
+                };
+                if offset != transfer.received {
+                    let reason = format!("expected offset {}, got {}", transfer.received, offset);
+                    return Handled::Answer(self.reject(peer_id, reason).await, None);
+                }
+                if data.len() > DIRECT_TRANSFER_CHUNK_SIZE
+                    || transfer.received + data.len() as u64 > transfer.size
+                {
+                    return Handled::Answer(self.reject(peer_id, "chunk exceeds the offered size").await, None);
+                }
+                if let Err(e) = transfer.file.write_all(&data).await {
+                    return Handled::Answer(self.reject(peer_id, format!("write failed: {}", e)).await, None);
+                }
+                transfer.hasher.update(&data);
+                transfer.received += data.len() as u64;
+                transfer.wire_bytes += wire_len as u64;
+                transfer.next_index += 1;
+                transfer.last_chunk_len = data.len() as u64;
+                let progress = DirectTransferProgress {
+                    transfer_id: transfer.id.clone(),
+                    peer_id: peer_id.to_string(),
+                    filename: transfer.filename.clone(),
+                    bytes_received: transfer.received,
+                    total_bytes: transfer.size,
+                    wire_bytes: transfer.wire_bytes,
+                };
+                self.stats.record(data.len(), wire_len);
+                Handled::Answer(
+                    FileTransferResponse::Ack {
+                        bytes_received: transfer.received,
+                    },
+                    Some(progress),
+                )
+            }
+            FileTransferRequest::Complete => {
+                let Some(mut transfer) = self.active.remove(peer_id) else {
+                    return Handled::Answer(self.reject(peer_id, "no transfer in progress").await, None);
+                };
+                let finished = transfer.file.flush().await;
+                let digest: [u8; 32] = transfer.hasher.finalize().into();
+                let failure = if transfer.received != transfer.size {
+                    Some(format!(
+                        "received {} of {} bytes",
+                        transfer.received, transfer.size
+                    ))
+                } else if digest != transfer.hash {
+                    Some("hash mismatch".to_string())
+                } else {
+                    finished.err().map(|e| format!("write failed: {}", e))
+                };
+                drop(transfer.file);
+                if let Some(reason) = failure {
+                    warn!("Direct transfer of {} from {} failed: {}", transfer.filename, peer_id, reason);
+                    let _ = tokio::fs::remove_file(&transfer.part_path).await;
+                    return Handled::Answer(FileTransferResponse::Reject { reason }, None);
+                }
+
+                let destination = unique_destination(&self.download_dir, &transfer.filename).await;
+                if let Err(e) = tokio::fs::rename(&transfer.part_path, &destination).await {
+                    let _ = tokio::fs::remove_file(&transfer.part_path).await;
+                    let reason = format!("cannot save file: {}", e);
+                    return Handled::Answer(FileTransferResponse::Reject { reason }, None);
+                }
+                info!("Saved direct transfer from {} to {:?}", peer_id, destination);
+                Handled::Answer(
+                    FileTransferResponse::Ack {
+                        bytes_received: transfer.received,
+                    },
+                    None,
+                )
+            }
+        }
+    }
+}
+
 // Simplified file transfer service without complex libp2p request-response
 // This provides basic file storage and retrieval functionality
 
@@ -403,9 +1068,7 @@ impl FileTransferService {
     }
 
     fn get_storage_dir() -> Result<PathBuf, String> {
-        let proj_dirs = ProjectDirs::from("com", "chiral-network", "chiral-network")
-            .ok_or("Failed to get project directories")?;
-        Ok(proj_dirs.data_dir().join("files"))
+        Ok(crate::data_dirs::DataDirs::current().files())
     }
 
     async fn run_file_transfer_service(
@@ -941,17 +1604,39 @@ impl FileTransferService {
         events
     }
 
+    /// Directory `store_file_data` writes to, one file per hash
+    pub fn storage_dir(&self) -> &Path {
+        &self.storage_dir
+    }
+
     pub async fn store_file_data(&self, file_hash: String, file_name: String, file_data: Vec<u8>) {
         let file_path = self.storage_dir.join(&file_hash);
         if let Err(e) = tokio::fs::write(&file_path, &file_data).await {
             error!("Failed to store file data: {}", e);
             return;
         }
+        self.store_file_meta(&file_hash, &file_name, file_data.len() as u64)
+            .await;
+    }
 
-        // Store metadata
+    /// `store_file_data` for a file on disk, copied without loading it
+    pub async fn store_file_copy(
+        &self,
+        file_hash: &str,
+        file_name: &str,
+        source: &Path,
+    ) -> Result<(), String> {
+        let size = tokio::fs::copy(source, self.storage_dir.join(file_hash))
+            .await
+            .map_err(|e| format!("Failed to store {}: {}", source.display(), e))?;
+        self.store_file_meta(file_hash, file_name, size).await;
+        Ok(())
+    }
+
+    async fn store_file_meta(&self, file_hash: &str, file_name: &str, file_size: u64) {
         let metadata = serde_json::json!({
             "file_name": file_name,
-            "file_size": file_data.len(),
+            "file_size": file_size,
             "uploaded_at": SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap_or_default()
@@ -986,6 +1671,310 @@ impl FileTransferService {
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        use sha2::Digest;
+        sha2::Sha256::digest(data).into()
+    }
+
+    /// The answer to `request` from "peer", accepting it at once if it is an offer
+    async fn answer(
+        incoming: &mut IncomingFileTransfers,
+        request: FileTransferRequest,
+    ) -> (FileTransferResponse, Option<DirectTransferProgress>) {
+        match incoming.handle("peer", request).await {
+            Handled::Answer(response, progress) => (response, progress),
+            Handled::Offered(offer) => incoming.accept(&offer.transfer_id).await,
+        }
+    }
+
+    #[tokio::test]
+    async fn direct_transfer_is_saved_after_hash_check() {
+        let dir = tempdir().expect("temp dir");
+        let mut incoming = IncomingFileTransfers::new(dir.path().to_path_buf());
+        let data: Vec<u8> = (0..(DIRECT_TRANSFER_CHUNK_SIZE + 10)).map(|i| i as u8).collect();
+
+        let offer = FileTransferRequest::Offer {
+            filename: "../../notes.txt".to_string(),
+            size: data.len() as u64,
+            hash: sha256(&data),
+            compression: vec![],
+            version: 1,
+        };
+        assert_eq!(answer(&mut incoming, offer.clone()).await.0, FileTransferResponse::Accept);
+        assert!(matches!(
+            answer(&mut incoming, offer).await.0,
+            FileTransferResponse::Reject { .. }
+        ));
+
+        let mut offset = 0;
+        for chunk in data.chunks(DIRECT_TRANSFER_CHUNK_SIZE) {
+            let (response, progress) =
+                answer(&mut incoming, FileTransferRequest::chunk(0, offset, chunk.to_vec(), false)).await;
+            offset += chunk.len() as u64;
+            assert_eq!(response, FileTransferResponse::Ack { bytes_received: offset });
+            assert_eq!(progress.unwrap().total_bytes, data.len() as u64);
+        }
+        let (response, _) = answer(&mut incoming, FileTransferRequest::Complete).await;
+        assert_eq!(response, FileTransferResponse::Ack { bytes_received: offset });
+
+        // The name was stripped of directories and the partial file is gone
+        assert_eq!(std::fs::read(dir.path().join("notes.txt")).unwrap(), data);
+        assert!(!dir.path().join("notes.txt.part").exists());
+    }
+
+    #[tokio::test]
+    async fn direct_transfer_with_bad_hash_or_offset_is_rejected() {
+        let dir = tempdir().expect("temp dir");
+        let mut incoming = IncomingFileTransfers::new(dir.path().to_path_buf());
+        let offer = |hash| FileTransferRequest::Offer {
+            filename: "a.bin".to_string(),
+            size: 4,
+            hash,
+            compression: vec![],
+            version: 1,
+        };
+        let chunk = |offset| FileTransferRequest::chunk(0, offset, vec![1, 2, 3, 4], false);
+
+        answer(&mut incoming, offer([0u8; 32])).await;
+        answer(&mut incoming, chunk(0)).await;
+        assert!(matches!(
+            answer(&mut incoming, FileTransferRequest::Complete).await.0,
+            FileTransferResponse::Reject { .. }
+        ));
+
+        answer(&mut incoming, offer(sha256(&[1, 2, 3, 4]))).await;
+        assert!(matches!(
+            answer(&mut incoming, chunk(2)).await.0,
+            FileTransferResponse::Reject { .. }
+        ));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn offers_wait_for_the_user_and_are_capped() {
+        let dir = tempdir().expect("temp dir");
+        let mut incoming = IncomingFileTransfers::new(dir.path().to_path_buf());
+        let offer = || FileTransferRequest::Offer {
+            filename: "big.iso".to_string(),
+            size: 1 << 40,
+            hash: [0u8; 32],
+            compression: vec![],
+            version: TRANSFER_WIRE_VERSION,
+        };
+
+        let mut offers = Vec::new();
+        for n in 0..MAX_INCOMING_TRANSFERS {
+            match incoming.handle(&format!("peer{}", n), offer()).await {
+                Handled::Offered(offer) => offers.push(offer),
+                other => panic!("offer answered without the user: {:?}", other),
+            }
+        }
+        // Nothing is written before the user accepts
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+        assert!(matches!(
+            incoming.handle("one-more", offer()).await,
+            Handled::Answer(FileTransferResponse::Reject { .. }, None)
+        ));
+
+        // Declining frees a slot; accepting creates the partial file, once
+        incoming.decline(&offers[0].transfer_id, "declined");
+        assert!(incoming.pending_offer(&offers[0].transfer_id).is_none());
+        let (response, _) = incoming.accept(&offers[1].transfer_id).await;
+        assert_eq!(response.negotiated(), Some((TRANSFER_WIRE_VERSION, None)));
+        assert!(dir.path().join("big.iso.part").exists());
+        assert!(matches!(
+            incoming.accept(&offers[1].transfer_id).await.0,
+            FileTransferResponse::Reject { .. }
+        ));
+        assert!(matches!(incoming.handle("one-more", offer()).await, Handled::Offered(_)));
+
+        incoming.abort("peer1").await;
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn compressed_chunks_are_hashed_uncompressed() {
+        let dir = tempdir().expect("temp dir");
+        let mut incoming = IncomingFileTransfers::new(dir.path().to_path_buf());
+        let data = "id,name,value\n1,alpha,0.5\n".repeat(2000).into_bytes();
+        let offer = FileTransferRequest::Offer {
+            filename: "table.csv".to_string(),
+            size: data.len() as u64,
+            hash: sha256(&data),
+            compression: vec!["brotli".to_string(), compression::ZSTD.to_string()],
+            version: 1,
+        };
+        assert_eq!(
+            answer(&mut incoming, offer).await.0,
+            FileTransferResponse::AcceptCompressed {
+                codec: compression::ZSTD.to_string()
+            }
+        );
+
+        let wire = compression::compress(&data).expect("csv shrinks");
+        let (response, progress) =
+            answer(&mut incoming, FileTransferRequest::chunk(0, 0, wire.clone(), true)).await;
+        assert_eq!(response, FileTransferResponse::Ack { bytes_received: data.len() as u64 });
+        assert_eq!(progress.unwrap().wire_bytes, wire.len() as u64);
+        let (response, _) = answer(&mut incoming, FileTransferRequest::Complete).await;
+        assert_eq!(response, FileTransferResponse::Ack { bytes_received: data.len() as u64 });
+        assert_eq!(std::fs::read(dir.path().join("table.csv")).unwrap(), data);
+        assert_eq!(incoming.compression_stats().saved_bytes(), (data.len() - wire.len()) as u64);
+    }
+
+    /// The wire types as nodes before versioned chunk frames define them
+    mod v1 {
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+        pub enum FileTransferRequest {
+            Offer {
+                filename: String,
+                size: u64,
+                hash: [u8; 32],
+                #[serde(default)]
+                compression: Vec<String>,
+            },
+            Chunk {
+                offset: u64,
+                #[serde(with = "serde_bytes")]
+                data: Vec<u8>,
+                #[serde(default)]
+                compressed: bool,
+            },
+            Complete,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+        pub enum FileTransferResponse {
+            Accept,
+            AcceptCompressed { codec: String },
+            Reject { reason: String },
+            Ack { bytes_received: u64 },
+        }
+    }
+
+    /// Send `message` through the CBOR frame encoding and decode it as `M`,
+    /// the way the other end of a stream would
+    fn over_the_wire<M: serde::de::DeserializeOwned>(message: &impl Serialize) -> M {
+        futures::executor::block_on(async {
+            let mut wire = futures::io::Cursor::new(Vec::new());
+            write_transfer_frame(&mut wire, message).await.unwrap();
+            wire.set_position(0);
+            read_transfer_frame(&mut wire).await.unwrap()
+        })
+    }
+
+    #[tokio::test]
+    async fn legacy_sender_interoperates_with_current_receiver() {
+        let dir = tempdir().expect("temp dir");
+        let mut incoming = IncomingFileTransfers::new(dir.path().to_path_buf());
+        let data = vec![7u8; 100];
+
+        let offer = v1::FileTransferRequest::Offer {
+            filename: "old.bin".to_string(),
+            size: data.len() as u64,
+            hash: sha256(&data),
+            compression: vec![],
+        };
+        let (response, _) = answer(&mut incoming, over_the_wire(&offer)).await;
+        let response: v1::FileTransferResponse = over_the_wire(&response);
+        assert_eq!(response, v1::FileTransferResponse::Accept);
+
+        let chunk = v1::FileTransferRequest::Chunk {
+            offset: 0,
+            data: data.clone(),
+            compressed: false,
+        };
+        let (response, _) = answer(&mut incoming, over_the_wire(&chunk)).await;
+        let response: v1::FileTransferResponse = over_the_wire(&response);
+        assert_eq!(response, v1::FileTransferResponse::Ack { bytes_received: 100 });
+
+        let (response, _) = answer(&mut incoming, over_the_wire(&v1::FileTransferRequest::Complete)).await;
+        let response: v1::FileTransferResponse = over_the_wire(&response);
+        assert_eq!(response, v1::FileTransferResponse::Ack { bytes_received: 100 });
+        assert_eq!(std::fs::read(dir.path().join("old.bin")).unwrap(), data);
+    }
+
+    #[test]
+    fn current_sender_falls_back_for_legacy_receiver() {
+        let offer = FileTransferRequest::Offer {
+            filename: "new.csv".to_string(),
+            size: 3,
+            hash: [1u8; 32],
+            compression: vec![compression::ZSTD.to_string()],
+            version: TRANSFER_WIRE_VERSION,
+        };
+        // An old receiver reads the offer and chunks, ignoring the new fields
+        assert_eq!(
+            over_the_wire::<v1::FileTransferRequest>(&offer),
+            v1::FileTransferRequest::Offer {
+                filename: "new.csv".to_string(),
+                size: 3,
+                hash: [1u8; 32],
+                compression: vec![compression::ZSTD.to_string()],
+            }
+        );
+        assert_eq!(
+            over_the_wire::<v1::FileTransferRequest>(&FileTransferRequest::chunk(4, 9, vec![1, 2, 3], false)),
+            v1::FileTransferRequest::Chunk {
+                offset: 9,
+                data: vec![1, 2, 3],
+                compressed: false,
+            }
+        );
+
+        // ... and its answers tell the sender to stay on version 1
+        let accept: FileTransferResponse = over_the_wire(&v1::FileTransferResponse::Accept);
+        assert_eq!(accept.negotiated(), Some((1, None)));
+        let accept: FileTransferResponse = over_the_wire(&v1::FileTransferResponse::AcceptCompressed {
+            codec: compression::ZSTD.to_string(),
+        });
+        assert_eq!(accept.negotiated(), Some((1, Some(compression::ZSTD))));
+    }
+
+    #[tokio::test]
+    async fn damaged_frames_are_nacked_and_resent() {
+        let dir = tempdir().expect("temp dir");
+        let mut incoming = IncomingFileTransfers::new(dir.path().to_path_buf());
+        let data: Vec<u8> = (0..(DIRECT_TRANSFER_CHUNK_SIZE + 10)).map(|i| (i % 251) as u8).collect();
+        let chunks: Vec<&[u8]> = data.chunks(DIRECT_TRANSFER_CHUNK_SIZE).collect();
+
+        let offer = FileTransferRequest::Offer {
+            filename: "framed.bin".to_string(),
+            size: data.len() as u64,
+            hash: sha256(&data),
+            compression: vec![],
+            version: TRANSFER_WIRE_VERSION,
+        };
+        let (response, _) = answer(&mut incoming, over_the_wire(&offer)).await;
+        assert_eq!(response.negotiated(), Some((TRANSFER_WIRE_VERSION, None)));
+
+        // A bit flipped in transit is caught by the checksum
+        let mut damaged = FileTransferRequest::chunk(0, 0, chunks[0].to_vec(), false);
+        if let FileTransferRequest::Chunk { data, .. } = &mut damaged {
+            data[17] ^= 0x40;
+        }
+        assert!(matches!(
+            answer(&mut incoming, over_the_wire(&damaged)).await.0,
+            FileTransferResponse::Nack { index: 0, .. }
+        ));
+
+        // The transfer survives; the resent frame and a duplicate are acked
+        let first = FileTransferRequest::chunk(0, 0, chunks[0].to_vec(), false);
+        let acked = FileTransferResponse::Ack {
+            bytes_received: chunks[0].len() as u64,
+        };
+        assert_eq!(answer(&mut incoming, first.clone()).await.0, acked);
+        assert_eq!(answer(&mut incoming, first).await.0, acked);
+
+        let second = FileTransferRequest::chunk(1, chunks[0].len() as u64, chunks[1].to_vec(), false);
+        answer(&mut incoming, second).await;
+        let (response, _) = answer(&mut incoming, FileTransferRequest::Complete).await;
+        assert_eq!(response, FileTransferResponse::Ack { bytes_received: data.len() as u64 });
+        assert_eq!(std::fs::read(dir.path().join("framed.bin")).unwrap(), data);
+    }
     use std::sync::Arc;
     use tempfile::tempdir;
     use tokio::sync::{mpsc, Mutex};
@@ -0,0 +1,488 @@
+//! Approximate location of connected peers for the network map.
+//!
+//! Off by default. When turned on, peers' public IPs are looked up in a local
+//! IP range database, `geoip.csv` in the data directory; nothing is sent to
+//! an external service. The file is the layout of DB-IP's free "IP to City
+//! Lite" CSV:
+//!
+//! ```text
+//! start_ip,end_ip,continent,country,region,city,latitude,longitude
+//! ```
+//!
+//! Fields may be quoted, as DB-IP quotes region and city names with commas.
+//! The database is read off the async runtime on the first lookup after the
+//! feature is turned on and dropped when it is turned off, so nodes that
+//! never use the map do not hold it in memory. A file that is missing or
+//! fails to parse is not retried until the toggle changes; Settings shows
+//! the error. Coordinates are rounded to `COORDINATE_PRECISION`.
+//!
+//! A peer is only located from the address of a direct connection to a
+//! public IP: relayed peers, whose real IP we never see, and peers on a
+//! private network have no location.
+
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+/// Coordinates are rounded to this many degrees, about 10 km
+pub const COORDINATE_PRECISION: f64 = 0.1;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerLocation {
+    /// ISO 3166-1 alpha-2, e.g. `DE`
+    pub country_code: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Located peers of one country, for `get_peer_locations`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CountryPeers {
+    pub country_code: String,
+    pub peers: usize,
+    /// Mean of the peers' coordinates
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Range<T> {
+    start: T,
+    end: T,
+    place: u32,
+}
+
+/// IP ranges sorted by start address; places are shared between ranges
+#[derive(Debug, Default)]
+pub struct GeoDatabase {
+    v4: Vec<Range<u32>>,
+    v6: Vec<Range<u128>>,
+    places: Vec<PeerLocation>,
+}
+
+impl GeoDatabase {
+    /// Read a CSV in the layout described in the module docs; blank lines
+    /// and lines starting with `#` are skipped
+    pub fn parse(reader: impl BufRead) -> Result<Self, String> {
+        let mut db = GeoDatabase::default();
+        let mut place_ids: HashMap<(String, i64, i64), u32> = HashMap::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| format!("Failed to read the GeoIP database: {}", e))?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = |what: &str| format!("GeoIP database line {}: {}", i + 1, what);
+            let fields = csv_fields(line).ok_or_else(|| bad("unterminated quote"))?;
+            let fields: Vec<&str> = fields.iter().map(|f| f.trim()).collect();
+            if fields.len() < 8 {
+                return Err(bad("expected 8 columns"));
+            }
+            let start: IpAddr = fields[0].parse().map_err(|_| bad("invalid start IP"))?;
+            let end: IpAddr = fields[1].parse().map_err(|_| bad("invalid end IP"))?;
+            let country_code = fields[3].to_ascii_uppercase();
+            // `ZZ` marks ranges DB-IP could not place
+            if country_code.len() != 2 || country_code == "ZZ" {
+                continue;
+            }
+            let latitude: f64 = fields[6].parse().map_err(|_| bad("invalid latitude"))?;
+            let longitude: f64 = fields[7].parse().map_err(|_| bad("invalid longitude"))?;
+            let (latitude, longitude) = (round(latitude), round(longitude));
+
+            let key = (
+                country_code.clone(),
+                (latitude / COORDINATE_PRECISION).round() as i64,
+                (longitude / COORDINATE_PRECISION).round() as i64,
+            );
+            let place = *place_ids.entry(key).or_insert_with(|| {
+                db.places.push(PeerLocation {
+                    country_code,
+                    latitude,
+                    longitude,
+                });
+                (db.places.len() - 1) as u32
+            });
+
+            match (start, end) {
+                (IpAddr::V4(start), IpAddr::V4(end)) if start <= end => db.v4.push(Range {
+                    start: start.into(),
+                    end: end.into(),
+                    place,
+                }),
+                (IpAddr::V6(start), IpAddr::V6(end)) if start <= end => db.v6.push(Range {
+                    start: start.into(),
+                    end: end.into(),
+                    place,
+                }),
+                _ => return Err(bad("range mixes IPv4 and IPv6 or ends before it starts")),
+            }
+        }
+        db.v4.sort_by_key(|r| r.start);
+        db.v6.sort_by_key(|r| r.start);
+        Ok(db)
+    }
+
+    /// Read `path`, for `PeerGeolocation`; blocks on file I/O
+    pub fn load(path: &std::path::Path) -> Result<Self, String> {
+        let file = std::fs::File::open(path).map_err(|e| {
+            format!(
+                "No GeoIP database at {} ({}). Save DB-IP's free \"IP to City Lite\" CSV there.",
+                path.display(),
+                e
+            )
+        })?;
+        let db = Self::parse(std::io::BufReader::new(file))?;
+        info!("Loaded {} GeoIP ranges from {}", db.len(), path.display());
+        Ok(db)
+    }
+
+    /// The location of the first of `addrs` with a public IP
+    pub fn locate(&self, addrs: &[Multiaddr]) -> Option<PeerLocation> {
+        let ip = addrs.iter().find_map(public_ip)?;
+        self.lookup(ip).cloned()
+    }
+
+    pub fn lookup(&self, ip: IpAddr) -> Option<&PeerLocation> {
+        let place = match ip {
+            IpAddr::V4(ip) => find(&self.v4, u32::from(ip)),
+            IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+                Some(ip) => find(&self.v4, u32::from(ip)),
+                None => find(&self.v6, u128::from(ip)),
+            },
+        }?;
+        self.places.get(place as usize)
+    }
+
+    /// Number of IP ranges
+    pub fn len(&self) -> usize {
+        self.v4.len() + self.v6.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Fields of one CSV line: commas inside double quotes are part of the
+/// field and `""` is a literal quote. `None` for an unterminated quote.
+fn csv_fields(line: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            (c, _) => field.push(c),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    fields.push(field);
+    Some(fields)
+}
+
+fn find<T: Copy + Ord>(ranges: &[Range<T>], ip: T) -> Option<u32> {
+    let i = ranges.partition_point(|r| r.start <= ip);
+    let range = ranges.get(i.checked_sub(1)?)?;
+    (ip <= range.end).then_some(range.place)
+}
+
+fn round(degrees: f64) -> f64 {
+    (degrees / COORDINATE_PRECISION).round() * COORDINATE_PRECISION
+}
+
+/// The public IP of a direct connection to `addr`; `None` for relay
+/// circuits, DNS names and private, loopback or link-local addresses
+pub fn public_ip(addr: &Multiaddr) -> Option<IpAddr> {
+    if addr.iter().any(|p| matches!(p, Protocol::P2pCircuit)) {
+        return None;
+    }
+    let ip = addr.iter().find_map(|p| match p {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(match ip.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(ip),
+        }),
+        _ => None,
+    })?;
+    is_public_ip(ip).then_some(ip)
+}
+
+/// Neither private, loopback, link-local, shared (CGNAT) nor reserved
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_public_v4(v4),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    let shared = a == 100 && (64..128).contains(&b);
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || shared)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    let unique_local = first & 0xfe00 == 0xfc00;
+    let link_local = first & 0xffc0 == 0xfe80;
+    let documentation = first == 0x2001 && ip.segments()[1] == 0x0db8;
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || unique_local
+        || link_local
+        || documentation)
+}
+
+/// The peers of each country, most peers first
+pub fn aggregate(locations: impl IntoIterator<Item = PeerLocation>) -> Vec<CountryPeers> {
+    let mut countries: HashMap<String, (usize, f64, f64)> = HashMap::new();
+    for location in locations {
+        let entry = countries.entry(location.country_code).or_default();
+        entry.0 += 1;
+        entry.1 += location.latitude;
+        entry.2 += location.longitude;
+    }
+    let mut countries: Vec<CountryPeers> = countries
+        .into_iter()
+        .map(
+            |(country_code, (peers, latitude, longitude))| CountryPeers {
+                country_code,
+                peers,
+                latitude: round(latitude / peers as f64),
+                longitude: round(longitude / peers as f64),
+            },
+        )
+        .collect();
+    countries.sort_by(|a, b| {
+        b.peers
+            .cmp(&a.peers)
+            .then_with(|| a.country_code.cmp(&b.country_code))
+    });
+    countries
+}
+
+/// The toggle and the database, for Settings
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeolocationStatus {
+    pub enabled: bool,
+    /// Where the database is read from
+    pub path: String,
+    /// IP ranges of the loaded database
+    pub ranges: Option<usize>,
+    /// Why the database could not be read
+    pub error: Option<String>,
+}
+
+/// The settings toggle and the lazily loaded database
+pub struct PeerGeolocation {
+    path: PathBuf,
+    enabled: AtomicBool,
+    /// The database, or why it could not be read, until the toggle changes
+    db: Mutex<Option<Result<Arc<GeoDatabase>, String>>>,
+    /// Held while the file is read so it is read once
+    loading: tokio::sync::Mutex<()>,
+    /// Bumped by the toggle so a load started before is not kept
+    generation: AtomicU64,
+}
+
+impl PeerGeolocation {
+    /// Disabled; `path` is read on the first lookup once enabled
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            enabled: AtomicBool::new(false),
+            db: Mutex::new(None),
+            loading: tokio::sync::Mutex::new(()),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Turning it off frees the database; either way a failed load is
+    /// retried on the next lookup
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.db.lock().unwrap_or_else(|e| e.into_inner()).take();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        matches!(self.cached(), Some(Ok(_)))
+    }
+
+    fn cached(&self) -> Option<Result<Arc<GeoDatabase>, String>> {
+        self.db.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// The database, read on a blocking thread if this is the first lookup
+    /// since the toggle changed
+    pub async fn database(&self) -> Result<Arc<GeoDatabase>, String> {
+        if !self.is_enabled() {
+            return Err("Peer geolocation is turned off".to_string());
+        }
+        let _loading = self.loading.lock().await;
+        if let Some(cached) = self.cached() {
+            return cached;
+        }
+        let generation = self.generation.load(Ordering::SeqCst);
+        let path = self.path.clone();
+        let loaded = tokio::task::spawn_blocking(move || GeoDatabase::load(&path))
+            .await
+            .map_err(|e| format!("Failed to read the GeoIP database: {}", e))
+            .and_then(|db| db.map(Arc::new));
+        if let Err(e) = &loaded {
+            warn!("Peer geolocation unavailable: {}", e);
+        }
+        let mut db = self.db.lock().unwrap_or_else(|e| e.into_inner());
+        if self.generation.load(Ordering::SeqCst) == generation {
+            *db = Some(loaded.clone());
+        }
+        loaded
+    }
+
+    /// Loads the database when the feature is on
+    pub async fn status(&self) -> GeolocationStatus {
+        let enabled = self.is_enabled();
+        let loaded = match enabled {
+            true => Some(self.database().await),
+            false => None,
+        };
+        GeolocationStatus {
+            enabled,
+            path: self.path.display().to_string(),
+            ranges: loaded
+                .as_ref()
+                .and_then(|db| db.as_ref().ok())
+                .map(|db| db.len()),
+            error: loaded.and_then(|db| db.err()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DB: &str = "\
+# start,end,continent,country,region,city,lat,lon
+1.0.0.0,1.0.0.255,OC,AU,Queensland,Brisbane,-27.4679,153.0281
+81.0.0.0,81.255.255.255,EU,DE,Berlin,Berlin,52.5244,13.4105
+82.0.0.0,82.0.255.255,EU,DE,Berlin,Berlin,52.5200,13.4050
+2a00:1450::,2a00:1450:ffff:ffff:ffff:ffff:ffff:ffff,EU,IE,Leinster,Dublin,53.3498,-6.2603
+90.0.0.0,90.0.0.255,ZZ,ZZ,,,0,0
+5.0.0.0,5.0.0.255,NA,US,\"Washington, D.C.\",\"Washington, \"\"Foggy Bottom\"\"\",38.9,-77.0
+";
+
+    fn addr(s: &str) -> Multiaddr {
+        s.parse().unwrap()
+    }
+
+    async fn locate(geo: &PeerGeolocation, addrs: &[Multiaddr]) -> Option<PeerLocation> {
+        geo.database().await.ok()?.locate(addrs)
+    }
+
+    #[tokio::test]
+    async fn test_locates_direct_public_peers_only() {
+        let path = std::env::temp_dir().join(format!("chiral-geoip-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let geo = PeerGeolocation::new(&path);
+
+        let berlin = [addr("/ip4/81.2.3.4/tcp/4001")];
+        assert_eq!(locate(&geo, &berlin).await, None, "off by default");
+        assert!(!geo.is_loaded());
+
+        // A missing file is reported and not retried until the toggle changes
+        geo.set_enabled(true);
+        let status = geo.status().await;
+        assert!(status.error.unwrap().contains("No GeoIP database"));
+        std::fs::write(&path, DB).unwrap();
+        assert_eq!(locate(&geo, &berlin).await, None);
+        geo.set_enabled(true);
+
+        assert!(!geo.is_loaded(), "loaded on the first lookup");
+        let location = locate(&geo, &berlin).await.unwrap();
+        assert!(geo.is_loaded());
+        assert_eq!(location.country_code, "DE");
+        assert!((location.latitude - 52.5).abs() < 1e-9);
+        assert_eq!(geo.status().await.ranges, Some(5));
+
+        let dublin = locate(&geo, &[addr("/ip6/2a00:1450::1/udp/4001/quic-v1")]).await;
+        assert_eq!(dublin.unwrap().country_code, "IE");
+        let mapped = locate(&geo, &[addr("/ip6/::ffff:1.0.0.7/tcp/4001")]).await;
+        assert_eq!(mapped.unwrap().country_code, "AU");
+        let quoted = locate(&geo, &[addr("/ip4/5.0.0.9/tcp/4001")]).await;
+        assert_eq!(quoted.unwrap().country_code, "US");
+
+        // The real IP of a relayed peer is unknown; the relay's is not the peer's
+        let db = geo.database().await.unwrap();
+        let relay = libp2p::PeerId::random();
+        let relayed = addr(&format!("/ip4/81.2.3.4/tcp/4001/p2p/{}/p2p-circuit", relay));
+        assert_eq!(db.locate(&[relayed]), None);
+        assert_eq!(db.locate(&[addr("/ip4/192.168.1.20/tcp/4001")]), None);
+        assert_eq!(db.locate(&[addr("/ip4/100.64.0.1/tcp/4001")]), None);
+        assert_eq!(db.locate(&[addr("/dns4/example.com/tcp/4001")]), None);
+        assert_eq!(db.locate(&[addr("/ip4/90.0.0.1/tcp/4001")]), None);
+        assert_eq!(db.locate(&[addr("/ip4/83.0.0.1/tcp/4001")]), None);
+        // A private address first does not hide a public one
+        let both = [
+            addr("/ip4/10.0.0.2/tcp/4001"),
+            addr("/ip4/82.0.1.1/tcp/4001"),
+        ];
+        assert_eq!(db.locate(&both).unwrap().country_code, "DE");
+
+        let countries = aggregate(
+            [&berlin[..], &both[..], &[addr("/ip4/1.0.0.1/tcp/1")][..]]
+                .iter()
+                .filter_map(|addrs| db.locate(addrs)),
+        );
+        assert_eq!(countries.len(), 2);
+        assert_eq!(countries[0].country_code, "DE");
+        assert_eq!(countries[0].peers, 2);
+        assert_eq!(countries[1].country_code, "AU");
+
+        geo.set_enabled(false);
+        assert!(!geo.is_loaded(), "turning it off frees the database");
+        assert_eq!(locate(&geo, &berlin).await, None);
+
+        assert!(GeoDatabase::parse("1.0.0.0,::1,OC,AU,,,0,0".as_bytes()).is_err());
+        assert!(GeoDatabase::parse("1.0.0.0,1.0.0.1,OC".as_bytes()).is_err());
+        assert!(GeoDatabase::parse("1.0.0.0,1.0.0.1,OC,AU,\"Queensland,0,0".as_bytes()).is_err());
+        let _ = std::fs::remove_file(path);
+    }
+}
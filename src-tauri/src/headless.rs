@@ -1,96 +1,249 @@
 // Headless mode for running as a bootstrap node on servers
-use crate::commands::bootstrap::get_bootstrap_nodes;
-use crate::dht::{models::DhtMetricsSnapshot, models::FileMetadata, DhtService};
+use crate::commands::bootstrap::{get_bootstrap_nodes, run_bootstrap_pruner};
+use chiral_network::bootstrap_manifest::fetch_signed_bootstrap_list;
+use chiral_network::config::headless::Profile;
+use chiral_network::config::reload::{self, ConfigDiff, ReloadHandle, ReloadRequest};
+use chiral_network::config::{ChiralConfig, ConfigFileError, HeadlessConfig};
+use chiral_network::control_api::{self, ControlApi};
+use chiral_network::data_dirs::DataDirs;
+use chiral_network::discovery::BootstrapMode;
+use chiral_network::health_check;
+use chiral_network::instance_lock::InstanceLock;
+use chiral_network::listen_ports::{self, ListenPorts};
+use chiral_network::log_format::LogFormat;
+use chiral_network::messaging::{ChannelEnvelope, MessageStore};
+use chiral_network::metrics_exporter::{self, MetricsRegistry};
+use chiral_network::monitoring::stats;
+#[cfg(unix)]
+use chiral_network::rpc::HeadlessRPCServer;
+use chiral_network::run_report::{self, RunRecorder};
+use chiral_network::seed_dir::{self, Seeder};
+use chiral_network::shared_files::SharedFilesRegistry;
+use chiral_network::systemd;
+use crate::dht::{keypair_from_secret, models::DhtMetricsSnapshot, models::FileMetadata, DhtEvent, DhtService};
 use crate::download_restart::{DownloadRestartService, StartDownloadRequest};
 use crate::ethereum::GethProcess;
 use crate::file_transfer::FileTransferService;
 use clap::Parser;
-use std::{sync::Arc, time::Duration};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 use tokio::signal;
+use tokio::sync::mpsc;
+use tracing_subscriber::EnvFilter;
 
 use tracing::{error, info, warn};
 
-#[derive(Parser, Debug)]
-#[command(name = "chiral-network")]
+#[derive(Parser, Debug, Clone)]
+#[command(name = "chiral-network", version)]
 #[command(about = "Chiral Network - P2P File Sharing", long_about = None)]
+#[command(after_help = "Settings not given as flags come from chiral.toml, then the built-in defaults. \
+CHIRAL_* environment variables override both the file and the flags. Switches such as \
+--is-bootstrap take =false to turn off a setting the file turns on.")]
 pub struct CliArgs {
     /// Run in headless mode (no GUI)
     #[arg(long)]
     pub headless: bool,
 
-    /// DHT port to listen on
-    #[arg(long, default_value = "4001")]
-    pub dht_port: u16,
+    /// Configuration file [default: chiral.toml in the data directory]
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
 
-    /// Bootstrap nodes to connect to (can be specified multiple times)
+    /// Directory for all node state: chiral.toml, identity, caches, block store and logs
+    #[arg(long, value_name = "PATH")]
+    pub data_dir: Option<PathBuf>,
+
+    /// Preset for the kind of node, applied over the configuration file
+    #[arg(long, value_enum)]
+    pub profile: Option<Profile>,
+
+    /// Print the effective configuration (secrets redacted) and exit
     #[arg(long)]
+    pub dump_config: bool,
+
+    /// TCP port of the DHT, 0 for any free port [default: 4001]
+    #[arg(long = "port", visible_alias = "dht-port", value_name = "PORT")]
+    pub dht_port: Option<u16>,
+
+    /// Use a free port instead of failing when a configured port is busy
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true)]
+    #[arg(default_missing_value = "true")]
+    pub port_fallback: Option<bool>,
+
+    /// Extra multiaddr to listen on (repeatable)
+    #[arg(long = "listen-addr", value_name = "MULTIADDR")]
+    pub listen_addr: Vec<String>,
+
+    /// Bootstrap node multiaddr (repeatable)
+    #[arg(long, value_name = "MULTIADDR")]
     pub bootstrap: Vec<String>,
 
+    /// Without --bootstrap nodes, start alone instead of using the built-in list
+    #[arg(long)]
+    pub no_default_bootstrap: bool,
+
     /// Enable geth node
     #[arg(long)]
     pub enable_geth: bool,
 
-    /// Geth data directory
-    #[arg(long, default_value = "./bin/geth-data")]
-    pub geth_data_dir: String,
+    /// Geth data directory [default: ./bin/geth-data]
+    #[arg(long, value_name = "PATH")]
+    pub geth_data_dir: Option<String>,
 
     /// Miner address for geth
     #[arg(long)]
     pub miner_address: Option<String>,
 
-    /// Log level (trace, debug, info, warn, error)
-    #[arg(long, default_value = "info")]
-    pub log_level: String,
+    /// Log level (trace, debug, info, warn, error) [default: info]
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Log output format (text, json) [default: text]
+    #[arg(long, value_name = "FORMAT")]
+    pub log_format: Option<LogFormat>,
+
+    /// Also write rotated log files to logs/ in the data directory
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true)]
+    #[arg(default_missing_value = "true")]
+    pub log_file: Option<bool>,
 
     /// Generate multiaddr for this node (shows the address others can connect to)
     #[arg(long)]
     pub show_multiaddr: bool,
 
-    // Generate consistent peerid
+    /// Seed of a stable peer id
     #[arg(long)]
     pub secret: Option<String>,
 
-    // Runs in bootstrap mode
-    #[arg(long)]
-    pub is_bootstrap: bool,
+    /// File holding the peer id seed; created with a random one if missing
+    #[arg(long, value_name = "PATH")]
+    pub identity_file: Option<PathBuf>,
+
+    /// Run as a bootstrap node
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true)]
+    #[arg(default_missing_value = "true")]
+    pub is_bootstrap: Option<bool>,
 
     /// Disable AutoNAT reachability probes
+    #[arg(long, visible_alias = "disable-autonat")]
+    pub no_autonat: bool,
+
+    /// Disable DCUtR hole punching
     #[arg(long)]
-    pub disable_autonat: bool,
+    pub no_dcutr: bool,
+
+    /// Relay traffic for peers behind NAT
+    #[arg(long, visible_alias = "enable-relay", value_name = "BOOL", num_args = 0..=1, require_equals = true)]
+    #[arg(default_missing_value = "true")]
+    pub relay_server: Option<bool>,
+
+    /// Where the first peers come from: bootstrap, mdns-only, or
+    /// static:MULTIADDR,... to dial only those peers
+    #[arg(long, value_name = "MODE")]
+    pub bootstrap_mode: Option<BootstrapMode>,
+
+    /// Run as network infrastructure: DHT, AutoNAT and relay server only,
+    /// without transfers, gossip or user state
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true)]
+    #[arg(default_missing_value = "true")]
+    pub infra_mode: Option<bool>,
+
+    /// Memory in MiB shared by the caches and chunks in flight; 0 for no
+    /// budget
+    #[arg(long, value_name = "MB")]
+    pub memory_budget_mb: Option<u64>,
+
+    /// Interval in seconds between AutoNAT probes [default: 30]
+    #[arg(long, value_name = "SECS")]
+    pub autonat_probe_interval: Option<u64>,
+
+    /// Additional AutoNAT server multiaddr (repeatable)
+    #[arg(long, value_name = "MULTIADDR")]
+    pub autonat_server: Vec<String>,
 
+    /// Print reachability snapshot at startup (and periodically)
     #[arg(long)]
-    pub enable_relay: bool,
+    pub show_reachability: bool,
+
+    /// Serve Prometheus metrics on this address, e.g. 127.0.0.1:9464
+    #[arg(long, value_name = "ADDR")]
+    pub metrics_addr: Option<std::net::SocketAddr>,
+
+    /// Allow --metrics-addr to be other than a loopback address
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true)]
+    #[arg(default_missing_value = "true")]
+    pub metrics_allow_public: Option<bool>,
+
+    /// Serve /healthz and /readyz on this address, e.g. 0.0.0.0:9465
+    #[arg(long, value_name = "ADDR")]
+    pub health_addr: Option<std::net::SocketAddr>,
+
+    /// Serve the local control API on this address, e.g. 127.0.0.1:5001
+    #[arg(long, value_name = "ADDR")]
+    pub api_addr: Option<std::net::SocketAddr>,
+
+    /// Allow --api-addr to be other than a loopback address
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true)]
+    #[arg(default_missing_value = "true")]
+    pub api_allow_public: Option<bool>,
 
-    /// Interval in seconds between AutoNAT probes
-    #[arg(long, default_value = "30")]
-    pub autonat_probe_interval: u64,
+    /// Serve JSON-RPC on this Unix socket, e.g. rpc.sock in the data directory
+    #[arg(long, value_name = "PATH")]
+    pub rpc_socket: Option<PathBuf>,
 
-    /// Additional AutoNAT servers to dial (multiaddr form)
+    /// Log a one-line status summary every SECS seconds
+    #[arg(long, value_name = "SECS")]
+    pub status_interval: Option<u64>,
+
+    /// Unix socket answering status queries [default: status.sock in the data directory]
+    #[arg(long, value_name = "PATH")]
+    pub status_socket: Option<PathBuf>,
+
+    /// Print the status of the node running on this data directory and exit
     #[arg(long)]
-    pub autonat_server: Vec<String>,
+    pub status: bool,
 
-    /// Print reachability snapshot at startup (and periodically)
+    /// Print the peer id and exit; needs --identity-file or --secret
     #[arg(long)]
-    pub show_reachability: bool,
+    pub print_peer_id: bool,
+
+    /// Print the multiaddrs the node would listen on, one per line, and exit
+    #[arg(long)]
+    pub print_multiaddrs: bool,
 
     /// Print DCUtR hole-punching metrics at startup
     #[arg(long)]
     pub show_dcutr: bool,
 
-    // SOCKS5 Proxy address (e.g., 127.0.0.1:9050 for Tor or a private VPN SOCKS endpoint)
-    #[arg(long)]
+    /// SOCKS5 proxy address (e.g. 127.0.0.1:9050 for Tor or a private VPN SOCKS endpoint)
+    #[arg(long, value_name = "HOST:PORT")]
     pub socks5_proxy: Option<String>,
 
+    /// Seconds allowed for an orderly shutdown on SIGTERM or SIGINT [default: 15]
+    #[arg(long, value_name = "SECS")]
+    pub shutdown_grace_secs: Option<u64>,
+
+    /// Stop gracefully after this long, e.g. 90s, 10m or 1h30m
+    #[arg(long, value_name = "DURATION", value_parser = run_report::parse_duration)]
+    pub run_for: Option<Duration>,
+
+    /// Write a JSON summary of the run here when the node stops
+    #[arg(long, value_name = "PATH")]
+    pub run_report: Option<PathBuf>,
+
     /// Print local download metrics snapshot at startup
     #[arg(long)]
     pub show_downloads: bool,
 
+    /// Publish every file under this directory once the network is reached,
+    /// and files added to it later (repeatable)
+    #[arg(long = "seed-dir", value_name = "PATH")]
+    pub seed_dir: Vec<PathBuf>,
+
     /// Disable AutoRelay behavior
     #[arg(long)]
     pub disable_autorelay: bool,
 
-    /// Preferred relay nodes (multiaddr form, can be specified multiple times)
-    #[arg(long)]
+    /// Preferred relay multiaddr (repeatable)
+    #[arg(long, value_name = "MULTIADDR")]
     pub relay: Vec<String>,
 
     /// Start a restartable HTTP download when the node boots
@@ -118,10 +271,263 @@ pub struct CliArgs {
     pub resume_download: Option<String>,
 }
 
-pub async fn run_headless(args: CliArgs) -> Result<(), Box<dyn std::error::Error>> {
-    use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+impl CliArgs {
+    /// Override the values of `config` given on the command line
+    fn apply_to(&self, config: &mut HeadlessConfig) {
+        if let Some(profile) = self.profile {
+            profile.apply(config);
+        }
+
+        let network = &mut config.network;
+        if let Some(port) = self.dht_port {
+            network.port = port;
+        }
+        if let Some(fallback) = self.port_fallback {
+            network.port_fallback = fallback;
+        }
+        if !self.listen_addr.is_empty() {
+            network.listen_addrs = self.listen_addr.clone();
+        }
+        if !self.bootstrap.is_empty() {
+            network.bootstrap = self.bootstrap.clone();
+        }
+        if self.no_default_bootstrap {
+            network.default_bootstrap = false;
+        }
+        if let Some(is_bootstrap) = self.is_bootstrap {
+            network.is_bootstrap = is_bootstrap;
+        }
+        if self.secret.is_some() {
+            network.secret = self.secret.clone();
+        }
+        if self.identity_file.is_some() {
+            network.identity_file = self.identity_file.clone();
+        }
+        if self.socks5_proxy.is_some() {
+            network.socks5_proxy = self.socks5_proxy.clone();
+        }
+        if let Some(secs) = self.shutdown_grace_secs {
+            network.shutdown_grace_secs = secs;
+        }
+
+        let nat = &mut config.nat;
+        if self.no_autonat {
+            nat.autonat = false;
+        }
+        if self.no_dcutr {
+            nat.dcutr = false;
+        }
+        if let Some(interval) = self.autonat_probe_interval {
+            nat.autonat_probe_interval_secs = interval;
+        }
+        if !self.autonat_server.is_empty() {
+            nat.autonat_servers = self.autonat_server.clone();
+        }
+        if self.disable_autorelay {
+            nat.autorelay = false;
+        }
+        if !self.relay.is_empty() {
+            nat.relays = self.relay.clone();
+        }
+        if let Some(relay_server) = self.relay_server {
+            nat.relay_server = relay_server;
+        }
+        if let Some(infra_mode) = self.infra_mode {
+            config.swarm.infra_mode = infra_mode;
+        }
+        if let Some(mb) = self.memory_budget_mb {
+            config.swarm.memory_budget_mb = mb;
+        }
+        if let Some(mode) = &self.bootstrap_mode {
+            config.swarm.bootstrap_mode = mode.clone();
+        }
+
+        if let Some(dir) = &self.data_dir {
+            let dirs = DataDirs::new(dir);
+            if config.storage.blockstore_path.is_none() {
+                config.storage.blockstore_path = Some(dirs.blockstore());
+            }
+            let network = &mut config.network;
+            if network.identity_file.is_none() && network.secret.is_none() {
+                network.identity_file = Some(dirs.identity_file());
+            }
+        }
+        if let Some(dir) = &self.geth_data_dir {
+            config.storage.geth_data_dir = PathBuf::from(dir);
+        }
+        if let Some(level) = &self.log_level {
+            config.logging.level = level.clone();
+        }
+        if let Some(format) = self.log_format {
+            config.logging.format = format;
+        }
+        if let Some(file) = self.log_file {
+            config.logging.file = file;
+        }
+        if self.metrics_addr.is_some() {
+            config.metrics.addr = self.metrics_addr;
+        }
+        if let Some(allow) = self.metrics_allow_public {
+            config.metrics.allow_public = allow;
+        }
+        if self.health_addr.is_some() {
+            config.health.addr = self.health_addr;
+        }
+        if self.api_addr.is_some() {
+            config.api.addr = self.api_addr;
+        }
+        if let Some(allow) = self.api_allow_public {
+            config.api.allow_public = allow;
+        }
+    }
+}
+
+/// `--data-dir`, or the installed data directory
+pub fn data_dirs(args: &CliArgs) -> DataDirs {
+    match &args.data_dir {
+        Some(dir) => DataDirs::new(dir),
+        None => DataDirs::current(),
+    }
+}
+
+pub fn data_dir(args: &CliArgs) -> PathBuf {
+    data_dirs(args).root().to_path_buf()
+}
+
+/// `logs/` in the data directory
+pub fn logs_dir(args: &CliArgs) -> PathBuf {
+    data_dirs(args).logs()
+}
+
+/// `--status-socket`, or `status.sock` in the data directory
+pub fn status_socket_path(args: &CliArgs) -> PathBuf {
+    args.status_socket
+        .clone()
+        .unwrap_or_else(|| data_dir(args).join(stats::STATUS_SOCKET_NAME))
+}
+
+/// Print the summary line of the running node; the process exit code
+pub fn print_status(args: &CliArgs) -> i32 {
+    #[cfg(unix)]
+    {
+        let path = status_socket_path(args);
+        let runtime = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
+        match runtime.block_on(stats::query_status_socket(&path)) {
+            Ok(line) => {
+                println!("{}", line);
+                0
+            }
+            Err(e) => {
+                eprintln!("No node answered on {}: {}", path.display(), e);
+                1
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = args;
+        eprintln!("--status needs a Unix socket, which this platform lacks");
+        1
+    }
+}
+
+/// `--print-peer-id` and `--print-multiaddrs`: bare values on stdout, one
+/// per line. Only the identity file is read or created; the data directory
+/// is not locked, so this works next to a running node.
+pub fn print_identity(args: &CliArgs) -> i32 {
+    let config = match load_config(args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            return 1;
+        }
+    };
+    let secret = match config.network.identity_secret() {
+        Ok(Some(secret)) => secret,
+        Ok(None) => {
+            eprintln!("The node has a random identity on every start; pass --identity-file, --data-dir or --secret");
+            return 2;
+        }
+        Err(e) => {
+            eprintln!("{}", e.message());
+            return 1;
+        }
+    };
+    let keypair = match keypair_from_secret(Some(&secret)) {
+        Ok(keypair) => keypair,
+        Err(e) => {
+            eprintln!("Invalid identity: {}", e);
+            return 1;
+        }
+    };
+    let peer_id = keypair.public().to_peer_id();
+    if args.print_peer_id {
+        println!("{}", peer_id);
+    }
+    if args.print_multiaddrs {
+        let runtime = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
+        let addrs = runtime.block_on(listen_ports::advertised_addrs(
+            keypair,
+            config.network.port,
+            &config.network.listen_addrs,
+            &config.chiral().swarm.noise_prologue,
+        ));
+        match addrs {
+            Ok(addrs) => {
+                for addr in addrs {
+                    println!("{}/p2p/{}", addr, peer_id);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        }
+    }
+    0
+}
+
+/// Swaps the log filter `main` installed, on a reload of `logging.level`
+pub type LogFilterHandle = tracing_subscriber::reload::Handle<EnvFilter, tracing_subscriber::Registry>;
+
+/// The node's log lines at `level`, libp2p's at `warn`; `RUST_LOG` comes first
+pub fn log_filter(level: &str) -> EnvFilter {
+    let mut filter = EnvFilter::from_default_env();
+    let directives = [
+        format!("chiral_network={}", level),
+        "libp2p=warn".to_string(),
+        "libp2p_kad=warn".to_string(),
+        "libp2p_swarm=warn".to_string(),
+        "libp2p_mdns=warn".to_string(),
+    ];
+    for directive in directives {
+        // Skip a directive that does not parse instead of failing
+        if let Ok(directive) = directive.parse() {
+            filter = filter.add_directive(directive);
+        }
+    }
+    filter
+}
+
+/// Defaults, then the configuration file, then the command line, then the
+/// environment
+pub fn load_config(args: &CliArgs) -> Result<HeadlessConfig, ConfigFileError> {
+    let mut config = match &args.config {
+        Some(path) => HeadlessConfig::load(path, true)?,
+        None => HeadlessConfig::load(&data_dirs(args).config_file(), false)?,
+    };
+    args.apply_to(&mut config);
+    Ok(config.with_env())
+}
+
+pub async fn run_headless(
+    args: CliArgs,
+    config: HeadlessConfig,
+    filter_handle: LogFilterHandle,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use tracing_subscriber::prelude::*;
     let _ = tracing_subscriber::registry()
-        .with(fmt::layer())
+        .with(config.logging.format.layer(std::io::stdout))
         .with(
             EnvFilter::from_default_env()
                 .add_directive("chiral_network=info".parse().unwrap())
@@ -132,23 +538,57 @@ pub async fn run_headless(args: CliArgs) -> Result<(), Box<dyn std::error::Error
         .try_init();
 
     info!("Starting Chiral Network in headless mode");
-    info!("CLI args: {:#?}", args);
+    // What a reload would refuse, the node does not start with
+    reload::validate(&config)
+        .map_err(|errors| format!("Invalid configuration: {}", errors.join("; ")))?;
+    data_dirs(&args).create()?;
+    // Held until this function returns, after the orderly shutdown
+    let instance_lock = InstanceLock::acquire(&data_dir(&args))?;
+    info!("Locked data directory with {}", instance_lock.path().display());
+    info!("Effective configuration:\n{}", config.to_redacted_toml());
+    ChiralConfig::install_file_base(config.chiral());
+    if config.bandwidth.upload_kbps > 0 || config.bandwidth.download_kbps > 0 {
+        // Only WebRTC transfers are throttled, and the headless node runs none
+        warn!("Bandwidth limits are set but the headless node has no throttled transfers");
+    }
 
-    let download_restart_service = Arc::new(DownloadRestartService::new(None));
+    let infra_mode = config.swarm.infra_mode;
+    if infra_mode {
+        info!("Running in infra mode: no transfers, downloads, file registry or gossip");
+    }
+    let download_restart_service = (!infra_mode).then(|| Arc::new(DownloadRestartService::new(None)));
 
     // Add default bootstrap nodes if no custom ones specified
-    let mut bootstrap_nodes = args.bootstrap.clone();
-    let provided_bootstrap = !bootstrap_nodes.is_empty();
-    if !provided_bootstrap {
+    let bootstrap_mode = config.swarm.bootstrap_mode.clone();
+    let mut bootstrap_nodes = bootstrap_mode.startup_peers(config.network.bootstrap.clone());
+    // Without bootstrap nodes by choice the node is not a primary bootstrap node
+    let provided_bootstrap = !bootstrap_nodes.is_empty() || bootstrap_mode != BootstrapMode::Bootstrap;
+    if bootstrap_mode == BootstrapMode::MdnsOnly {
+        info!("Bootstrap mode mdns_only: finding peers on the local network only");
+    } else if matches!(bootstrap_mode, BootstrapMode::Static(_)) {
+        info!("Bootstrap mode static: dialing only {:?}", bootstrap_nodes);
+    } else if !provided_bootstrap && !config.network.default_bootstrap {
+        info!("No bootstrap nodes configured; starting without any");
+    } else if !provided_bootstrap {
         // Use reliable IP-based bootstrap nodes so fresh nodes can join the mesh
-        // Using the same comprehensive set as the frontend for network consistency
-        bootstrap_nodes.extend(get_bootstrap_nodes());
-        info!("Using default bootstrap nodes: {:?}", bootstrap_nodes);
+        // Using the same comprehensive set as the frontend for network consistency.
+        // A configured signed manifest takes precedence; a manifest that fails
+        // verification aborts startup instead of silently falling back.
+        match fetch_signed_bootstrap_list(&ChiralConfig::from_env()).await? {
+            Some(list) => {
+                bootstrap_nodes.extend(list.multiaddrs());
+                info!("Using signed manifest bootstrap nodes: {:?}", bootstrap_nodes);
+            }
+            None => {
+                bootstrap_nodes.extend(get_bootstrap_nodes());
+                info!("Using default bootstrap nodes: {:?}", bootstrap_nodes);
+            }
+        }
     }
 
-    let enable_autonat = !args.disable_autonat;
+    let enable_autonat = config.nat.autonat;
     let probe_interval = if enable_autonat {
-        Some(Duration::from_secs(args.autonat_probe_interval))
+        Some(Duration::from_secs(config.nat.autonat_probe_interval_secs))
     } else {
         None
     };
@@ -156,21 +596,23 @@ pub async fn run_headless(args: CliArgs) -> Result<(), Box<dyn std::error::Error
     if enable_autonat {
         info!(
             "AutoNAT probes enabled (interval: {}s)",
-            args.autonat_probe_interval
+            config.nat.autonat_probe_interval_secs
         );
-        if !args.autonat_server.is_empty() {
-            info!("AutoNAT servers: {:?}", args.autonat_server);
+        if !config.nat.autonat_servers.is_empty() {
+            info!("AutoNAT servers: {:?}", config.nat.autonat_servers);
         }
     } else {
-        info!("AutoNAT probes disabled via CLI");
+        info!("AutoNAT probes disabled by configuration");
     }
 
-    if args.download_url.is_some()
+    let download_commands = args.download_url.is_some()
         || args.pause_download.is_some()
-        || args.resume_download.is_some()
-    {
+        || args.resume_download.is_some();
+    if download_commands && infra_mode {
+        warn!("Ignoring download arguments in infra mode");
+    } else if let (true, Some(downloads)) = (download_commands, &download_restart_service) {
         if let Err(err) = handle_download_cli_commands(
-            download_restart_service.clone(),
+            downloads.clone(),
             args.download_url.as_deref(),
             args.download_dest.as_deref(),
             args.download_sha256.as_deref(),
@@ -184,25 +626,33 @@ pub async fn run_headless(args: CliArgs) -> Result<(), Box<dyn std::error::Error
         }
     }
 
-    // Optionally start local file-transfer service for metrics insight
-    let file_transfer_service = if args.show_downloads {
+    if let Some(dir) = args.seed_dir.iter().find(|dir| !dir.is_dir()) {
+        return Err(format!("--seed-dir {} is not a directory", dir.display()).into());
+    }
+    let seeding = !args.seed_dir.is_empty() && !infra_mode;
+    if !args.seed_dir.is_empty() && infra_mode {
+        warn!("Ignoring --seed-dir in infra mode");
+    }
+
+    // Optionally start local file-transfer service for metrics insight, or
+    // to serve the seed directories
+    let file_transfer_service = if args.show_downloads && infra_mode {
+        warn!("--show-downloads has no effect in infra mode");
+        None
+    } else if args.show_downloads || seeding {
         Some(Arc::new(FileTransferService::new().await.map_err(|e| {
             format!("Failed to start file transfer service: {}", e)
         })?))
     } else {
         None
     };
-    // ---- finalize AutoRelay flag (bootstrap OFF + ENV OFF)
-    let mut final_enable_autorelay = !args.disable_autorelay;
-    if std::env::var("CHIRAL_DISABLE_AUTORELAY").ok().as_deref() == Some("1") {
-        final_enable_autorelay = false;
-        info!("AutoRelay disabled via env CHIRAL_DISABLE_AUTORELAY=1");
-    }
+    // CHIRAL_DISABLE_AUTORELAY is already part of the configuration
+    let final_enable_autorelay = config.nat.autorelay;
     if final_enable_autorelay {
-        if !args.relay.is_empty() {
+        if !config.nat.relays.is_empty() {
             info!(
                 "AutoRelay enabled with {} preferred relays",
-                args.relay.len()
+                config.nat.relays.len()
             );
         } else {
             info!("AutoRelay enabled, will discover relays from bootstrap nodes");
@@ -212,29 +662,42 @@ pub async fn run_headless(args: CliArgs) -> Result<(), Box<dyn std::error::Error
     }
 
     // Start DHT node
+    let blockstore_path = config
+        .storage
+        .blockstore_path
+        .as_ref()
+        .map(|path| async_std::path::Path::new(path.as_os_str()));
+    let port_fallback = config.network.port_fallback;
+    let dht_port = listen_ports::resolve_dht_port(config.network.port, port_fallback)?;
     let dht_service = DhtService::new(
-        args.dht_port,
+        dht_port,
         bootstrap_nodes.clone(),
-        args.secret,
-        args.is_bootstrap,
+        config.network.identity_secret()?,
+        config.network.is_bootstrap,
         enable_autonat,
         probe_interval,
-        args.autonat_server.clone(),
-        args.socks5_proxy,
+        config.nat.autonat_servers.clone(),
+        config.network.socks5_proxy.clone(),
         file_transfer_service.clone(),
         None, // chunk_manager
-        None, // chunk_size_kb: use default
-        None, // cache_size_mb: use default
+        Some(config.dht.chunk_size_kb),
+        Some(config.dht.cache_size_mb),
         final_enable_autorelay,
-        args.relay.clone(),
-        args.enable_relay,
-        true,
-        None,
+        config.nat.relays.clone(),
+        config.nat.relay_server,
+        config.nat.upnp,
+        blockstore_path,
         None,
         None,
     )
     .await?;
     let peer_id = dht_service.get_peer_id().await;
+    info!(local_peer_id = %peer_id, port = dht_port, "DHT node started");
+    info!("📡 Listening for peers on TCP port {}", dht_port);
+    if infra_mode {
+        // Stable line for scripts that need the id; do not reword
+        println!("CHIRAL_PEER_ID={}", peer_id);
+    }
 
     // DHT is already running in a spawned background task
 
@@ -256,15 +719,18 @@ pub async fn run_headless(args: CliArgs) -> Result<(), Box<dyn std::error::Error
         // Get local IP addresses
         let local_ip = get_local_ip().unwrap_or_else(|| "127.0.0.1".to_string());
         info!("🔗 Multiaddr for other nodes to connect:");
-        info!("   /ip4/{}/tcp/{}/p2p/{}", local_ip, args.dht_port, peer_id);
-        info!("   /ip4/127.0.0.1/tcp/{}/p2p/{}", args.dht_port, peer_id);
+        info!("   /ip4/{}/tcp/{}/p2p/{}", local_ip, dht_port, peer_id);
+        info!("   /ip4/127.0.0.1/tcp/{}/p2p/{}", dht_port, peer_id);
     }
 
     // Optionally start geth
     let geth_handle = if args.enable_geth {
         info!("Starting geth node...");
         let mut geth = GethProcess::new();
-        geth.start(&args.geth_data_dir, args.miner_address.as_deref())?;
+        geth.start(
+            &config.storage.geth_data_dir.to_string_lossy(),
+            args.miner_address.as_deref(),
+        )?;
         info!("✅ Geth node started");
         Some(geth)
     } else {
@@ -275,38 +741,43 @@ pub async fn run_headless(args: CliArgs) -> Result<(), Box<dyn std::error::Error
     if !provided_bootstrap {
         info!("Running as primary bootstrap node (no peers specified)");
 
-        // Publish some example metadata to seed the network
-        let example_metadata = FileMetadata {
-            merkle_root: "QmBootstrap123Example".to_string(),
-            file_name: "welcome.txt".to_string(),
-            file_size: 1024,
-            file_data: b"Hello, world!".to_vec(),
-            seeders: vec![peer_id.clone()],
-            created_at: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            mime_type: Some("text/plain".to_string()),
-            is_encrypted: false,
-            encryption_method: None,
-            key_fingerprint: None,
-            parent_hash: None,
-            cids: None,
-            is_root: true,
-            encrypted_key_bundle: None,
-            download_path: None,
-            price: 0.0,
-            uploader_address: None,
-            ftp_sources: None,
-            http_sources: None,
-            info_hash: None,
-            trackers: None,
-            ed2k_sources: None,
-        };
-
-        dht_service.publish_file(example_metadata, None).await?;
-        info!("Published bootstrap file metadata");
-    } else {
+        // Publish some example metadata to seed the network; infra nodes
+        // keep no file registry
+        if !infra_mode {
+            let example_metadata = FileMetadata {
+                merkle_root: "QmBootstrap123Example".to_string(),
+                file_name: "welcome.txt".to_string(),
+                file_size: 1024,
+                file_data: b"Hello, world!".to_vec(),
+                seeders: vec![peer_id.clone()],
+                created_at: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs(),
+                mime_type: Some("text/plain".to_string()),
+                is_encrypted: false,
+                encryption_method: None,
+                key_fingerprint: None,
+                parent_hash: None,
+                cids: None,
+                is_root: true,
+                encrypted_key_bundle: None,
+                download_path: None,
+                price: 0.0,
+                uploader_address: None,
+                ftp_sources: None,
+                http_sources: None,
+                web_seeds: None,
+                info_hash: None,
+                trackers: None,
+                ed2k_sources: None,
+                compressible: false,
+            };
+
+            dht_service.publish_file(example_metadata, None).await?;
+            info!("Published bootstrap file metadata");
+        }
+    } else if !bootstrap_nodes.is_empty() {
         info!("Connecting to bootstrap nodes: {:?}", bootstrap_nodes);
         for bootstrap_addr in &bootstrap_nodes {
             match dht_service.connect_peer(bootstrap_addr.clone()).await {
@@ -329,6 +800,125 @@ pub async fn run_headless(args: CliArgs) -> Result<(), Box<dyn std::error::Error
 
     info!("Bootstrap node is running. Press Ctrl+C to stop.");
     let dht_arc = Arc::new(dht_service);
+    let recorder = RunRecorder::new();
+    recorder.watch_peers(&dht_arc);
+    // A static list is dialed as given; only bootstrap nodes are pruned
+    if bootstrap_mode == BootstrapMode::Bootstrap && !bootstrap_nodes.is_empty() {
+        tokio::spawn(run_bootstrap_pruner(
+            dht_arc.clone(),
+            bootstrap_nodes.iter().filter_map(|a| a.parse().ok()).collect(),
+            config.swarm.bootstrap_prune_after_failures,
+        ));
+    }
+    let (reload_handle, reload_requests) = ReloadHandle::channel();
+    tokio::spawn(run_reloader(
+        args.clone(),
+        config.clone(),
+        dht_arc.clone(),
+        filter_handle,
+        reload_requests,
+    ));
+    #[cfg(unix)]
+    tokio::spawn(reload_on_sighup(reload_handle.clone()));
+
+    let mut ports = ListenPorts {
+        dht: dht_port,
+        ..Default::default()
+    };
+    if let Some(addr) = config.metrics.addr {
+        let addr = listen_ports::resolve_addr("Metrics", addr, port_fallback)?;
+        let mut registry = MetricsRegistry::new();
+        registry.register(dht_arc.clone());
+        let bound = metrics_exporter::start_server(Arc::new(registry), addr, config.metrics.allow_public).await?;
+        info!("📈 Prometheus metrics on http://{}/metrics", bound);
+        ports.metrics = Some(bound.port());
+    }
+    if let Some(addr) = config.health.addr {
+        let addr = listen_ports::resolve_addr("Health", addr, port_fallback)?;
+        let bound = health_check::start_server(dht_arc.clone(), addr).await?;
+        info!("🩺 Health checks on http://{}/healthz and /readyz", bound);
+        ports.health = Some(bound.port());
+    }
+    // Channel messages received are kept for the control API; infra nodes
+    // carry no gossip
+    let message_store = if infra_mode {
+        None
+    } else {
+        let path = data_dirs(&args).messages();
+        match MessageStore::open(&path) {
+            Ok(store) => Some(Arc::new(store)),
+            Err(e) => {
+                warn!("Message history {} disabled: {}", path.display(), e);
+                None
+            }
+        }
+    };
+    let control = if config.api.addr.is_some() || args.rpc_socket.is_some() {
+        Some(Arc::new(ControlApi {
+            dht: dht_arc.clone(),
+            downloads: download_restart_service.clone(),
+            file_transfer: file_transfer_service.clone(),
+            messages: message_store.clone(),
+            settings: serde_json::to_value(config.redacted()).map_err(|e| e.to_string())?,
+            token: control_api::load_or_create_token(&data_dir(&args))?,
+            reload: Some(reload_handle.clone()),
+        }))
+    } else {
+        None
+    };
+    if let (Some(addr), Some(api)) = (config.api.addr, &control) {
+        let addr = listen_ports::resolve_addr("Control API", addr, port_fallback)?;
+        let bound = control_api::start_server(api.clone(), addr, config.api.allow_public).await?;
+        info!(
+            "🛠️ Control API on http://{}/api/v1 (bearer token in {})",
+            bound,
+            data_dir(&args).join(control_api::TOKEN_FILE_NAME).display()
+        );
+        ports.api = Some(bound.port());
+    }
+    #[cfg(unix)]
+    let rpc_server = match (&args.rpc_socket, &control) {
+        (Some(path), Some(node)) => {
+            let server = HeadlessRPCServer::start(path, node.clone())?;
+            info!("🔌 JSON-RPC on {}", path.display());
+            Some(server)
+        }
+        _ => None,
+    };
+    if let Err(e) = ports.write(&data_dir(&args)) {
+        warn!("{}", e);
+    }
+    if let (true, Some(ft)) = (seeding, &file_transfer_service) {
+        let registry = Arc::new(SharedFilesRegistry::load(data_dirs(&args).shared_files()));
+        let mut seeder = Seeder::new(dht_arc.clone(), ft.clone(), registry);
+        let dht = dht_arc.clone();
+        let dirs = args.seed_dir.clone();
+        tokio::spawn(async move {
+            if !seed_dir::wait_until_ready(&dht, seed_dir::READY_TIMEOUT).await {
+                warn!("No bootstrap node reached; seeding anyway");
+            }
+            let seeded = seeder.seed_all(&dirs).await;
+            // Stable table for test harnesses; do not reword
+            println!("{}", seed_dir::summary_table(&seeded));
+            seeder.watch(dirs, &seeded).await;
+        });
+    }
+    if let Some(secs) = args.status_interval.filter(|secs| *secs > 0) {
+        tokio::spawn(stats::log_status(dht_arc.clone(), Duration::from_secs(secs)));
+    }
+    #[cfg(unix)]
+    {
+        let path = status_socket_path(&args);
+        match stats::serve_status_socket(dht_arc.clone(), &path).await {
+            Ok(()) => info!("Status queries on {}", path.display()),
+            Err(e) => warn!("Status socket {} disabled: {}", path.display(), e),
+        }
+    }
+    // Bootstrap attempts are over, so systemd can be told how the start went
+    let sd_notifier = systemd::SdNotifier::from_env().map(Arc::new);
+    if let Some(notifier) = &sd_notifier {
+        tokio::spawn(systemd::run(notifier.clone(), dht_arc.clone()));
+    }
 
     if args.show_reachability {
         let snapshot = dht_arc.metrics_snapshot().await;
@@ -378,11 +968,27 @@ pub async fn run_headless(args: CliArgs) -> Result<(), Box<dyn std::error::Error
 
     // Spawn the event pump
     let dht_clone_for_pump = Arc::clone(&dht_arc);
+    let recorder_for_pump = recorder.clone();
 
     tokio::spawn(async move {
         loop {
             // If the DHT service has been shut down, the weak reference will be None
             let events = dht_clone_for_pump.drain_events(100).await;
+            for event in &events {
+                recorder_for_pump.on_event(event);
+                if let (
+                    Some(store),
+                    DhtEvent::Channel {
+                        envelope: ChannelEnvelope::Message(message),
+                        ..
+                    },
+                ) = (&message_store, event)
+                {
+                    if let Err(e) = store.store_message(message) {
+                        warn!("Failed to store channel message: {}", e);
+                    }
+                }
+            }
             if events.is_empty() {
                 // Avoid busy-waiting
                 tokio::time::sleep(Duration::from_millis(200)).await;
@@ -396,13 +1002,218 @@ pub async fn run_headless(args: CliArgs) -> Result<(), Box<dyn std::error::Error
             }
         }
     });
-    // Keep the service running
-    signal::ctrl_c().await?;
+    // Keep the service running until asked to stop or the network task dies
+    if let Some(run_for) = args.run_for {
+        info!("Running for {}s", run_for.as_secs());
+    }
+    let run_for = async {
+        match args.run_for {
+            Some(run_for) => tokio::time::sleep(run_for).await,
+            None => std::future::pending().await,
+        }
+    };
+    let stop_reason = tokio::select! {
+        _ = run_for => "run-for elapsed",
+        signal_name = wait_for_shutdown_signal() => signal_name?,
+        _ = dht_arc.stopped() => {
+            error!("The network task stopped unexpectedly");
+            "network task stopped"
+        }
+    };
 
-    info!("Shutting down...");
+    let grace = Duration::from_secs(config.network.shutdown_grace_secs);
+    info!("{}; shutting down (grace period {}s)", stop_reason, grace.as_secs());
+    // Removes the socket, so scripts see the node is going away
+    #[cfg(unix)]
+    drop(rpc_server);
+    if let Some(notifier) = &sd_notifier {
+        notifier.stopping();
+    }
+    if !dht_arc.is_running() {
+        recorder.fatal("the network task stopped before the node was asked to");
+    }
+    // Read before the DHT stops answering
+    let mut report = recorder.report(&dht_arc, stop_reason).await;
+    let orderly = shutdown(&dht_arc, download_restart_service.as_deref(), geth_handle);
+    report.clean_shutdown = tokio::time::timeout(grace, orderly).await.is_ok();
+    if !report.clean_shutdown {
+        error!("Shutdown did not finish within {}s", grace.as_secs());
+        report.fatal_errors.push(GracePeriodExpired(grace).to_string());
+    }
+    if let Some(path) = &args.run_report {
+        report.write(path)?;
+        info!("Wrote the run report to {}", path.display());
+    }
+    if !report.clean_shutdown {
+        return Err(Box::new(GracePeriodExpired(grace)));
+    }
+    if !report.succeeded() {
+        return Err(Box::new(RunFailed(report.fatal_errors)));
+    }
+    info!("Shutdown complete");
     Ok(())
 }
 
+/// Process exit code when shutdown outlasts its grace period
+pub const EXIT_GRACE_PERIOD_EXPIRED: i32 = 3;
+
+/// Process exit code of a run that had fatal errors
+pub const EXIT_RUN_FAILED: i32 = 4;
+
+/// Fatal errors happened while the node ran
+#[derive(Debug)]
+pub struct RunFailed(pub Vec<String>);
+
+impl std::fmt::Display for RunFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the run failed: {}", self.0.join("; "))
+    }
+}
+
+impl std::error::Error for RunFailed {}
+
+/// Shutdown was cut short by the grace period
+#[derive(Debug)]
+pub struct GracePeriodExpired(pub Duration);
+
+impl std::fmt::Display for GracePeriodExpired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "shutdown grace period of {}s expired", self.0.as_secs())
+    }
+}
+
+impl std::error::Error for GracePeriodExpired {}
+
+/// Name of the signal that asked the node to stop
+async fn wait_for_shutdown_signal() -> std::io::Result<&'static str> {
+    #[cfg(unix)]
+    {
+        use signal::unix::{signal as unix_signal, SignalKind};
+        let mut terminate = unix_signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = signal::ctrl_c() => result.map(|_| "SIGINT"),
+            _ = terminate.recv() => Ok("SIGTERM"),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        signal::ctrl_c().await.map(|_| "Ctrl-C")
+    }
+}
+
+/// Read the configuration again on every SIGHUP
+#[cfg(unix)]
+async fn reload_on_sighup(reload: ReloadHandle) {
+    use signal::unix::{signal as unix_signal, SignalKind};
+    let mut hangup = match unix_signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            warn!("Configuration reload on SIGHUP disabled: {}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        info!("Received SIGHUP; reloading the configuration");
+        // The reloader logs the outcome
+        let _ = reload.reload().await;
+    }
+}
+
+/// Apply reload requests one at a time, starting from the configuration the
+/// node was started with
+async fn run_reloader(
+    args: CliArgs,
+    mut current: HeadlessConfig,
+    dht: Arc<DhtService>,
+    filter_handle: LogFilterHandle,
+    mut requests: mpsc::Receiver<ReloadRequest>,
+) {
+    while let Some(response) = requests.recv().await {
+        let result = reload_config(&args, &mut current, &dht, &filter_handle).await;
+        match &result {
+            Ok(diff) if diff.is_empty() => info!("Configuration reloaded; nothing changed"),
+            Ok(diff) => {
+                for key in &diff.applied {
+                    info!(key = %key, "Configuration change applied");
+                }
+                for key in &diff.needs_restart {
+                    warn!(key = %key, "Configuration change needs a restart; keeping the old value");
+                }
+            }
+            Err(e) => error!("Configuration reload rejected; keeping the old values: {}", e),
+        }
+        let _ = response.send(result);
+    }
+}
+
+/// Load the configuration as at startup and apply what can change live
+async fn reload_config(
+    args: &CliArgs,
+    current: &mut HeadlessConfig,
+    dht: &DhtService,
+    filter_handle: &LogFilterHandle,
+) -> Result<ConfigDiff, String> {
+    let new = load_config(args).map_err(|e| e.to_string())?;
+    reload::validate(&new).map_err(|errors| errors.join("; "))?;
+    let diff = ConfigDiff::between(current, &new);
+
+    // The only step that can fail goes first, so nothing is half applied
+    if diff.changed("logging.level") {
+        filter_handle
+            .reload(log_filter(&new.logging.level))
+            .map_err(|e| format!("failed to change the log level: {}", e))?;
+    }
+    if diff.changed("network.bootstrap") {
+        // Nodes no longer listed stay connected until they disconnect
+        let added = new
+            .swarm
+            .bootstrap_mode
+            .startup_peers(new.network.bootstrap.clone())
+            .into_iter()
+            .filter(|addr| !current.network.bootstrap.contains(addr));
+        for addr in added {
+            match dht.connect_peer(addr.clone()).await {
+                Ok(()) => info!("Dialing new bootstrap node {}", addr),
+                Err(e) => warn!("Failed to dial new bootstrap node {}: {}", addr, e),
+            }
+        }
+    }
+    if diff.changed("swarm.relay_consent") || diff.changed("swarm.relay_allow_list") {
+        dht.set_relay_consent(new.swarm.relay_consent, &new.swarm.relay_allow_list);
+    }
+    if diff.changed("security.filter_rules") {
+        dht.connection_filter().set_configured(&new.security.filter_rules);
+        info!("Connection filter rules reloaded");
+    }
+    *current = diff.merge(current, &new);
+    Ok(diff)
+}
+
+/// Save what a restart needs, then stop the network
+async fn shutdown(
+    dht: &DhtService,
+    downloads: Option<&DownloadRestartService>,
+    geth: Option<GethProcess>,
+) {
+    // Transfer state first: it matters most if a later step hangs
+    if let Some(downloads) = downloads {
+        let saved = downloads.persist_all().await;
+        info!("Saved the state of {} downloads", saved);
+    }
+
+    // Closes every connection, which also releases relay reservations, and
+    // checkpoints the peer address cache
+    if let Err(e) = dht.shutdown().await {
+        warn!("DHT shutdown failed: {}", e);
+    }
+
+    if let Some(mut geth) = geth {
+        if let Err(e) = geth.stop() {
+            warn!("Failed to stop geth: {}", e);
+        }
+    }
+}
+
 fn log_reachability_snapshot(snapshot: &DhtMetricsSnapshot) {
     info!(
         "📡 Reachability: {:?} (confidence {:?})",
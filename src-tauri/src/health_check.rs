@@ -0,0 +1,142 @@
+//! Liveness and readiness endpoints for containerized nodes.
+//!
+//! `GET /healthz` passes while the DHT task answers commands; a node whose
+//! event loop is stuck fails it and can be restarted. `GET /readyz` passes
+//! once the node can serve: it holds a connection to a bootstrap node (when
+//! any are configured) and has bound at least one listen address. Both
+//! answer 200 or 503 with the result of every check, e.g.
+//!
+//! ```json
+//! {"status":"fail","checks":[{"name":"bootstrap_connected","ok":false,"detail":"0 of 2 connected"}]}
+//! ```
+
+use crate::dht::DhtService;
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long the DHT task has to answer the liveness round trip
+pub const LIVENESS_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HealthReport {
+    /// `pass` or `fail`
+    pub status: &'static str,
+    pub checks: Vec<CheckResult>,
+}
+
+impl HealthReport {
+    pub fn new(checks: Vec<CheckResult>) -> Self {
+        let status = if checks.iter().all(|c| c.ok) { "pass" } else { "fail" };
+        Self { status, checks }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.status == "pass"
+    }
+}
+
+pub fn liveness(responsive: bool) -> HealthReport {
+    HealthReport::new(vec![CheckResult {
+        name: "network_task",
+        ok: responsive,
+        detail: if responsive {
+            "responsive".to_string()
+        } else {
+            format!("no answer within {}s", LIVENESS_TIMEOUT.as_secs())
+        },
+    }])
+}
+
+/// A node with no bootstrap nodes configured (a bootstrap node itself) is
+/// ready without one
+pub fn readiness(bootstrap_connected: usize, bootstrap_configured: usize, listen_addrs: usize) -> HealthReport {
+    HealthReport::new(vec![
+        CheckResult {
+            name: "bootstrap_connected",
+            ok: bootstrap_configured == 0 || bootstrap_connected > 0,
+            detail: if bootstrap_configured == 0 {
+                "no bootstrap nodes configured".to_string()
+            } else {
+                format!("{} of {} connected", bootstrap_connected, bootstrap_configured)
+            },
+        },
+        CheckResult {
+            name: "listen_addr_bound",
+            ok: listen_addrs > 0,
+            detail: format!("{} listen addresses", listen_addrs),
+        },
+    ])
+}
+
+fn respond(report: HealthReport) -> (StatusCode, Json<HealthReport>) {
+    let status = if report.is_ok() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report))
+}
+
+async fn healthz(State(dht): State<Arc<DhtService>>) -> (StatusCode, Json<HealthReport>) {
+    respond(liveness(dht.is_responsive(LIVENESS_TIMEOUT).await))
+}
+
+async fn readyz(State(dht): State<Arc<DhtService>>) -> (StatusCode, Json<HealthReport>) {
+    let (connected, configured) = dht.bootstrap_connections().await;
+    let listen_addrs = dht.metrics_snapshot().await.listen_addrs.len();
+    respond(readiness(connected, configured, listen_addrs))
+}
+
+/// Serve the health endpoints on `addr` for the life of the process
+///
+/// Returns the bound address (useful if port 0 was used)
+pub async fn start_server(dht: Arc<DhtService>, addr: SocketAddr) -> Result<SocketAddr, String> {
+    let app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(dht);
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| format!("Failed to bind health endpoint {}: {}", addr, e))?;
+    let bound_addr = listener.local_addr().map_err(|e| e.to_string())?;
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!("Health endpoint error: {}", e);
+        }
+    });
+    Ok(bound_addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readiness_checks() {
+        let ready = readiness(1, 2, 1);
+        assert!(ready.is_ok());
+
+        let waiting = readiness(0, 2, 1);
+        assert!(!waiting.is_ok());
+        let failed: Vec<_> = waiting.checks.iter().filter(|c| !c.ok).map(|c| c.name).collect();
+        assert_eq!(failed, vec!["bootstrap_connected"]);
+
+        // A bootstrap node has nobody to connect to
+        assert!(readiness(0, 0, 1).is_ok());
+        assert!(!readiness(0, 0, 0).is_ok());
+
+        let body = serde_json::to_value(liveness(false)).unwrap();
+        assert_eq!(body["status"], "fail");
+        assert_eq!(body["checks"][0]["name"], "network_task");
+    }
+}
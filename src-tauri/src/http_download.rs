@@ -768,7 +768,9 @@ impl HttpDownloadClient {
                             progress_percentage: calculate_progress(new_bytes, file_size),
                             download_speed_bps: speed,
                             upload_speed_bps: 0.0,
+                            instantaneous_speed_bps: speed,
                             eta_seconds: eta,
+                            stalled: false,
                             active_sources: 1,
                             timestamp: current_timestamp_ms(),
                         });
@@ -1,5 +1,5 @@
 use axum::{
-    extract::{Path, State},
+    extract::{ConnectInfo, Path, State},
     http::StatusCode,
     response::{IntoResponse, Response},
     routing::get,
@@ -15,6 +15,8 @@ use tower_http::cors::{Any, CorsLayer};
 
 // Import DhtService for metrics tracking
 use crate::dht::DhtService;
+use crate::shared_files::SharedFilesRegistry;
+use crate::upload_slots::{UploadRefusal, UploadSlotLimiter};
 
 /// HTTP Server for serving files via Range requests
 ///
@@ -51,6 +53,12 @@ pub struct HttpServerState {
     
     /// DHT service for recording provider-side metrics
     pub dht: Arc<Mutex<Option<Arc<DhtService>>>>,
+
+    /// Registry of shared files; stale or unshared content is not served
+    pub shared_files: Option<Arc<SharedFilesRegistry>>,
+
+    /// Bounds concurrent file requests and queues the rest fairly per peer
+    pub upload_slots: UploadSlotLimiter,
 }
 
 impl HttpServerState {
@@ -63,8 +71,24 @@ impl HttpServerState {
             storage_dir,
             files: Arc::new(RwLock::new(HashMap::new())),
             dht: Arc::new(Mutex::new(None)),
+            shared_files: None,
+            upload_slots: UploadSlotLimiter::default(),
         }
     }
+
+    /// Serve under `limiter`, the upload slot pool shared with the WebRTC
+    /// serving path
+    pub fn with_upload_slots(mut self, limiter: UploadSlotLimiter) -> Self {
+        self.upload_slots = limiter;
+        self
+    }
+
+    /// Attach the shared files registry used to gate and account uploads
+    pub fn with_shared_files(mut self, registry: Arc<SharedFilesRegistry>) -> Self {
+        self.upload_slots = self.upload_slots.with_shared_files(registry.clone());
+        self.shared_files = Some(registry);
+        self
+    }
     
     /// Set DHT service for metrics tracking
     pub async fn set_dht(&self, dht: Arc<DhtService>) {
@@ -159,11 +183,13 @@ async fn serve_metadata(
 async fn serve_file(
     Path(file_hash): Path<String>,
     State(state): State<Arc<HttpServerState>>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
     headers: axum::http::HeaderMap,
 ) -> Response {
     tracing::debug!("Serving file: {}", file_hash);
 
-    // Extract downloader peer ID from headers for metrics tracking
+    // Extract downloader peer ID from headers for metrics tracking. Anyone can
+    // send any ID here, so it plays no part in admitting the request.
     let downloader_peer_id = headers
         .get("X-Downloader-Peer-ID")
         .and_then(|v| v.to_str().ok())
@@ -207,6 +233,43 @@ async fn serve_file(
             .into_response();
     }
 
+    // Requests are queued fairly per client address, never in the reserved
+    // slots. Content that was modified on disk since publishing or is past
+    // its seeding limit is refused.
+    let requester = remote.ip().to_string();
+    let mut upload = match state
+        .upload_slots
+        .acquire_anonymous_upload(&requester, &metadata.file_hash)
+        .await
+    {
+        Ok(upload) => upload,
+        Err(UploadRefusal::Refused(refusal)) => {
+            tracing::warn!("Refusing to serve {}: {}", file_hash, refusal);
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(ErrorResponse {
+                    error: refusal.to_string(),
+                }),
+            )
+                .into_response();
+        }
+        Err(UploadRefusal::Busy(busy)) => {
+            tracing::debug!("Upload slots busy, asking {} to retry later", requester);
+            let mut response = (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(ErrorResponse {
+                    error: busy.to_string(),
+                }),
+            )
+                .into_response();
+            response.headers_mut().insert(
+                axum::http::header::RETRY_AFTER,
+                axum::http::HeaderValue::from(busy.retry_after.as_secs()),
+            );
+            return response;
+        }
+    };
+
     // Check for Range header
     let range_header = headers
         .get("range")
@@ -219,7 +282,29 @@ async fn serve_file(
         // Serve entire file
         serve_entire_file(&file_path, metadata.size).await
     };
-    
+
+    let bytes_served = response
+        .headers()
+        .get("Content-Length")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+    if response.status().is_success() {
+        upload.record_bytes(bytes_served);
+    }
+    // The file was unshared without letting in-flight uploads finish
+    let cancelled = upload.is_cancelled();
+    upload.finish().await;
+    if cancelled {
+        return (
+            StatusCode::GONE,
+            Json(ErrorResponse {
+                error: format!("File is no longer shared: {}", file_hash),
+            }),
+        )
+            .into_response();
+    }
+
     // Record provider-side metrics if downloader peer ID is available
     if let Some(ref peer_id) = downloader_peer_id {
         let file_size = metadata.size;
@@ -419,7 +504,8 @@ pub async fn start_server(
 
     // Spawn server in background with graceful shutdown
     tokio::spawn(async move {
-        let server = axum::serve(listener, app)
+        // Uploads are queued per client address
+        let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
             .with_graceful_shutdown(async {
                 shutdown_rx.await.ok();
                 tracing::info!("HTTP server received shutdown signal");
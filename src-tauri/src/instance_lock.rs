@@ -0,0 +1,164 @@
+//! One running node per data directory.
+//!
+//! Two processes sharing a data directory corrupt the peer cache and fight
+//! over the identity, so the desktop app and headless nodes take an
+//! exclusive lock on `chiral.lock` in the directory before they start. The
+//! file also holds the owner's PID for people looking at it.
+//!
+//! The operating system drops the lock when its process exits, however it
+//! exits, so a lock file left behind by a crash is simply taken over; the
+//! PID it names is only used to say so in the log. A clean shutdown empties
+//! the file.
+
+use fs2::FileExt;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+pub const LOCK_FILE_NAME: &str = "chiral.lock";
+
+#[derive(Debug)]
+pub enum InstanceLockError {
+    /// Another process holds the lock
+    AlreadyRunning { dir: PathBuf, pid: Option<u32> },
+    Io { path: PathBuf, source: std::io::Error },
+}
+
+impl fmt::Display for InstanceLockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyRunning { dir, pid: Some(pid) } => write!(
+                f,
+                "another instance (PID {}) is using this data directory: {}",
+                pid,
+                dir.display()
+            ),
+            Self::AlreadyRunning { dir, pid: None } => {
+                write!(f, "another instance is using this data directory: {}", dir.display())
+            }
+            Self::Io { path, source } => write!(f, "failed to lock {}: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for InstanceLockError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::AlreadyRunning { .. } => None,
+        }
+    }
+}
+
+/// Held for the life of the node; dropping it releases the directory
+#[derive(Debug)]
+pub struct InstanceLock {
+    file: File,
+    path: PathBuf,
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut contents = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut contents).ok()?;
+    contents.trim().parse().ok()
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    // Signal 0 only checks that the process exists
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    unsafe { libc::kill(pid, 0) == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM) }
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    false
+}
+
+impl InstanceLock {
+    /// Lock `dir`, creating it if needed
+    pub fn acquire(dir: &Path) -> Result<Self, InstanceLockError> {
+        let path = dir.join(LOCK_FILE_NAME);
+        let io_error = |source| InstanceLockError::Io {
+            path: path.clone(),
+            source,
+        };
+        std::fs::create_dir_all(dir).map_err(io_error)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(io_error)?;
+
+        let previous = read_pid(&mut file);
+        if file.try_lock_exclusive().is_err() {
+            return Err(InstanceLockError::AlreadyRunning {
+                dir: dir.to_path_buf(),
+                pid: previous,
+            });
+        }
+        match previous {
+            Some(pid) if pid != std::process::id() && !process_alive(pid) => {
+                info!("Reclaiming lock on {} left by exited process {}", dir.display(), pid)
+            }
+            Some(pid) if pid != std::process::id() => {
+                // Alive but not holding the lock: PID reuse, or another PID namespace
+                warn!("Lock file in {} named running process {}; taking it over", dir.display(), pid)
+            }
+            _ => {}
+        }
+
+        file.set_len(0).map_err(io_error)?;
+        file.seek(SeekFrom::Start(0)).map_err(io_error)?;
+        writeln!(file, "{}", std::process::id()).map_err(io_error)?;
+        file.sync_all().map_err(io_error)?;
+        Ok(Self { file, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        // The file stays: removing it would let a process that opened it
+        // just before lock an unlinked file while another creates a new one
+        let _ = self.file.set_len(0);
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_lock_fails_and_stale_file_is_reclaimed() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock = InstanceLock::acquire(dir.path()).unwrap();
+        let pid = std::fs::read_to_string(lock.path()).unwrap();
+        assert_eq!(pid.trim(), std::process::id().to_string());
+
+        match InstanceLock::acquire(dir.path()) {
+            Err(InstanceLockError::AlreadyRunning { pid, .. }) => assert_eq!(pid, Some(std::process::id())),
+            other => panic!("expected AlreadyRunning, got {:?}", other),
+        }
+        drop(lock);
+        assert_eq!(std::fs::read_to_string(dir.path().join(LOCK_FILE_NAME)).unwrap(), "");
+        drop(InstanceLock::acquire(dir.path()).unwrap());
+
+        // Left behind by a crashed process
+        std::fs::write(dir.path().join(LOCK_FILE_NAME), "999999999\n").unwrap();
+        let lock = InstanceLock::acquire(dir.path()).unwrap();
+        let pid = std::fs::read_to_string(lock.path()).unwrap();
+        assert_eq!(pid.trim(), std::process::id().to_string());
+    }
+}
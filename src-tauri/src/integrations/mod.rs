@@ -0,0 +1,5 @@
+//! Hooks for plugging a node into an operator's own tooling.
+
+pub mod webhook;
+
+pub use webhook::{WebhookEvent, WebhookEventKind, WebhookNotifier, SIGNATURE_HEADER, TIMESTAMP_HEADER};
@@ -0,0 +1,309 @@
+//! Peer and NAT events POSTed to an operator's webhook.
+//!
+//! When `SwarmConfig::webhook_url` is set, the node sends a `WebhookEvent` as
+//! JSON for each enabled kind of event:
+//!
+//! ```json
+//! {"event":"peer_connected","timestamp":"2026-10-14T08:30:00.000Z","data":{"peer_id":"12D3KooW...","address":"/ip4/..."}}
+//! ```
+//!
+//! `bootstrap_status` is sent, after the peer event behind it, when the node
+//! gains its first or loses its last bootstrap connection, and `nat_status`
+//! when the reachability state changes, not on every probe.
+//!
+//! With `webhook_secret` set, each request carries `X-Chiral-Timestamp`, the
+//! Unix seconds it was signed at, and `X-Chiral-Signature: sha256=<hex>`, the
+//! HMAC-SHA256 under the secret of the timestamp, a `.` and the body.
+//! Receivers should refuse timestamps a few minutes old, so a captured
+//! request cannot be replayed. A request that fails or times out after 5
+//! seconds is retried once and then dropped; deliveries run on their own
+//! task and never hold up the events. Logs name only the webhook's origin,
+//! as the path or query may carry a token.
+
+use crate::config::SwarmConfig;
+use crate::dht::models::NatReachabilityState;
+use crate::dht::DhtEvent;
+use hmac::{Hmac, Mac};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+use url::Url;
+
+pub const SIGNATURE_HEADER: &str = "X-Chiral-Signature";
+
+pub const TIMESTAMP_HEADER: &str = "X-Chiral-Timestamp";
+
+/// Time allowed for one delivery attempt
+pub const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Events waiting for delivery; more are dropped
+const WEBHOOK_QUEUE: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    PeerConnected,
+    PeerDisconnected,
+    BootstrapStatus,
+    NatStatus,
+}
+
+impl WebhookEventKind {
+    pub const ALL: [WebhookEventKind; 4] = [
+        WebhookEventKind::PeerConnected,
+        WebhookEventKind::PeerDisconnected,
+        WebhookEventKind::BootstrapStatus,
+        WebhookEventKind::NatStatus,
+    ];
+}
+
+impl FromStr for WebhookEventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().replace('-', "_").as_str() {
+            "peer_connected" => Ok(Self::PeerConnected),
+            "peer_disconnected" => Ok(Self::PeerDisconnected),
+            "bootstrap_status" => Ok(Self::BootstrapStatus),
+            "nat_status" => Ok(Self::NatStatus),
+            other => Err(format!("unknown webhook event '{}'", other)),
+        }
+    }
+}
+
+/// Body of one webhook request
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WebhookEvent {
+    pub event: WebhookEventKind,
+    /// RFC 3339, UTC
+    pub timestamp: String,
+    pub data: serde_json::Value,
+}
+
+impl WebhookEvent {
+    fn new(event: WebhookEventKind, data: serde_json::Value) -> Self {
+        Self {
+            event,
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            data,
+        }
+    }
+}
+
+/// `sha256=<hex>` signature of `body` sent at `timestamp` (Unix seconds)
+pub fn sign(secret: &[u8], timestamp: u64, body: &[u8]) -> String {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: Url,
+    secret: Option<Vec<u8>>,
+    events: HashSet<WebhookEventKind>,
+    bootstrap_peers: HashSet<String>,
+    connected_bootstrap: HashSet<String>,
+    nat_state: Option<NatReachabilityState>,
+}
+
+impl WebhookNotifier {
+    pub fn new(
+        url: Url,
+        secret: Option<String>,
+        events: impl IntoIterator<Item = WebhookEventKind>,
+        bootstrap_peers: impl IntoIterator<Item = PeerId>,
+    ) -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .map_err(|e| format!("Failed to build webhook client: {}", e))?;
+        Ok(Self {
+            client,
+            url,
+            secret: secret.map(String::into_bytes),
+            events: events.into_iter().collect(),
+            bootstrap_peers: bootstrap_peers.into_iter().map(|p| p.to_string()).collect(),
+            connected_bootstrap: HashSet::new(),
+            nat_state: None,
+        })
+    }
+
+    /// `None` unless the configuration names a webhook
+    pub fn from_config(
+        swarm: &SwarmConfig,
+        bootstrap_peers: impl IntoIterator<Item = PeerId>,
+    ) -> Option<Result<Self, String>> {
+        let url = swarm.webhook_url.clone()?;
+        Some(Self::new(
+            url,
+            swarm.webhook_secret.clone(),
+            swarm.webhook_events.iter().copied(),
+            bootstrap_peers,
+        ))
+    }
+
+    /// The webhook events for `event` that the operator asked for
+    pub fn translate(&mut self, event: &DhtEvent) -> Vec<WebhookEvent> {
+        let bootstrap_was_connected = !self.connected_bootstrap.is_empty();
+        let webhook_event = match event {
+            DhtEvent::PeerConnected { peer_id, address } => {
+                if self.bootstrap_peers.contains(peer_id) {
+                    self.connected_bootstrap.insert(peer_id.clone());
+                }
+                WebhookEvent::new(
+                    WebhookEventKind::PeerConnected,
+                    json!({ "peer_id": peer_id, "address": address }),
+                )
+            }
+            DhtEvent::PeerDisconnected { peer_id } => {
+                self.connected_bootstrap.remove(peer_id);
+                WebhookEvent::new(WebhookEventKind::PeerDisconnected, json!({ "peer_id": peer_id }))
+            }
+            DhtEvent::NatStatus { state, confidence, summary, .. } => {
+                if self.nat_state.replace(*state) == Some(*state) {
+                    return Vec::new();
+                }
+                WebhookEvent::new(
+                    WebhookEventKind::NatStatus,
+                    json!({ "state": state, "confidence": confidence, "summary": summary }),
+                )
+            }
+            _ => return Vec::new(),
+        };
+
+        let mut webhook_events = vec![webhook_event];
+        let bootstrap_connected = !self.connected_bootstrap.is_empty();
+        if bootstrap_connected != bootstrap_was_connected {
+            webhook_events.push(WebhookEvent::new(
+                WebhookEventKind::BootstrapStatus,
+                json!({
+                    "status": if bootstrap_connected { "connected" } else { "disconnected" },
+                    "connected": self.connected_bootstrap.len(),
+                    "configured": self.bootstrap_peers.len(),
+                }),
+            ));
+        }
+        webhook_events.retain(|webhook_event| self.events.contains(&webhook_event.event));
+        webhook_events
+    }
+
+    /// POST `event`, retrying once
+    async fn deliver(client: &reqwest::Client, url: &Url, secret: Option<&[u8]>, event: &WebhookEvent) {
+        let body = match serde_json::to_vec(event) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to encode webhook event: {}", e);
+                return;
+            }
+        };
+        // The path or query may hold a token, so only the origin is logged
+        let origin = url.origin().ascii_serialization();
+        for attempt in 1..=2 {
+            let mut request = client
+                .post(url.clone())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            if let Some(secret) = secret {
+                let timestamp = chrono::Utc::now().timestamp().max(0) as u64;
+                request = request
+                    .header(TIMESTAMP_HEADER, timestamp.to_string())
+                    .header(SIGNATURE_HEADER, sign(secret, timestamp, &body));
+            }
+            match request.send().await.and_then(|r| r.error_for_status()) {
+                Ok(_) => {
+                    debug!("Delivered {:?} webhook to {}", event.event, origin);
+                    return;
+                }
+                Err(e) if attempt == 1 => {
+                    debug!("Webhook delivery to {} failed, retrying: {}", origin, e.without_url())
+                }
+                Err(e) => warn!("Dropping {:?} webhook after retry: {}", event.event, e.without_url()),
+            }
+        }
+    }
+
+    /// Send webhooks for `events` before passing them on
+    pub fn tee(mut self, mut events: mpsc::Receiver<DhtEvent>) -> mpsc::Receiver<DhtEvent> {
+        let (tx, rx) = mpsc::channel(events.max_capacity());
+        let (queue_tx, mut queue_rx) = mpsc::channel::<WebhookEvent>(WEBHOOK_QUEUE);
+        let (client, url, secret) = (self.client.clone(), self.url.clone(), self.secret.clone());
+        tokio::spawn(async move {
+            while let Some(event) = queue_rx.recv().await {
+                Self::deliver(&client, &url, secret.as_deref(), &event).await;
+            }
+        });
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                for webhook_event in self.translate(&event) {
+                    if queue_tx.try_send(webhook_event).is_err() {
+                        warn!("Webhook queue full; dropping event");
+                    }
+                }
+                if tx.send(event).await.is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht::models::NatConfidence;
+
+    #[test]
+    fn test_events_are_translated_and_signed() {
+        let bootstrap = PeerId::random();
+        let url: Url = "http://127.0.0.1:9/hook".parse().unwrap();
+        let mut notifier = WebhookNotifier::new(
+            url,
+            Some("s3cret".to_string()),
+            [WebhookEventKind::BootstrapStatus, WebhookEventKind::NatStatus, WebhookEventKind::PeerDisconnected],
+            [bootstrap],
+        )
+        .unwrap();
+
+        let connected = DhtEvent::PeerConnected { peer_id: bootstrap.to_string(), address: None };
+        let events = notifier.translate(&connected);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, WebhookEventKind::BootstrapStatus);
+        assert_eq!(events[0].data["status"], "connected");
+
+        // Not enabled
+        let other = DhtEvent::PeerConnected { peer_id: PeerId::random().to_string(), address: None };
+        assert!(notifier.translate(&other).is_empty());
+
+        // The peer event is sent as well as the bootstrap change
+        let disconnected = DhtEvent::PeerDisconnected { peer_id: bootstrap.to_string() };
+        let kinds: Vec<_> = notifier.translate(&disconnected).iter().map(|e| e.event).collect();
+        assert_eq!(kinds, [WebhookEventKind::PeerDisconnected, WebhookEventKind::BootstrapStatus]);
+
+        let nat = DhtEvent::NatStatus {
+            state: NatReachabilityState::Public,
+            confidence: NatConfidence::High,
+            last_error: None,
+            summary: None,
+        };
+        assert_eq!(notifier.translate(&nat)[0].event, WebhookEventKind::NatStatus);
+        assert!(notifier.translate(&nat).is_empty());
+
+        let body = br#"{"event":"nat_status"}"#;
+        let signature = sign(b"s3cret", 1_760_000_000, body);
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), "sha256=".len() + 64);
+        assert_ne!(signature, sign(b"other", 1_760_000_000, body));
+        // A replay with a fresh timestamp needs a fresh signature
+        assert_ne!(signature, sign(b"s3cret", 1_760_000_300, body));
+    }
+}
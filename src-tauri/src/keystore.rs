@@ -1,6 +1,7 @@
 use aes::cipher::{KeyIvInit, StreamCipher};
 use aes::Aes256;
 use ctr::Ctr128BE;
+use crate::data_dirs::{existing_or_legacy, DataDirs};
 use directories::ProjectDirs;
 use hmac::Hmac;
 use pbkdf2::pbkdf2;
@@ -48,17 +49,16 @@ impl Keystore {
         }
     }
 
+    /// `keystore.json` in the data directory; one still at the location
+    /// used before the data directory is kept there until it is moved
     pub fn get_keystore_path() -> Result<PathBuf, String> {
-        let proj_dirs = ProjectDirs::from("com", "chiral", "network")
-            .ok_or_else(|| "Could not determine project directories".to_string())?;
-
-        let data_dir = proj_dirs.data_dir();
-
-        // Create directory if it doesn't exist
-        fs::create_dir_all(data_dir)
+        let dirs = DataDirs::current();
+        fs::create_dir_all(dirs.root())
             .map_err(|e| format!("Failed to create data directory: {}", e))?;
 
-        Ok(data_dir.join("keystore.json"))
+        let legacy = ProjectDirs::from("com", "chiral", "network")
+            .map(|dirs| dirs.data_dir().join("keystore.json"));
+        Ok(existing_or_legacy(dirs.keystore(), legacy))
     }
 
     pub fn load() -> Result<Self, String> {
@@ -228,7 +228,9 @@ impl Keystore {
                 .as_secs(),
         };
 
-        account.file_encryption_keys.insert(file_hash, file_key);
+        if account.file_encryption_keys.insert(file_hash.clone(), file_key).is_some() {
+            audit_key_rotation(address, &file_hash);
+        }
         self.save()
     }
 
@@ -313,7 +315,9 @@ impl Keystore {
                 .as_secs(),
         };
 
-        account.file_encryption_keys.insert(file_hash, file_key);
+        if account.file_encryption_keys.insert(file_hash.clone(), file_key).is_some() {
+            audit_key_rotation(address, &file_hash);
+        }
         self.save()
     }
 
@@ -392,6 +396,15 @@ fn encrypt_private_key(
     Ok((hex::encode(data), hex::encode(salt), hex::encode(iv)))
 }
 
+/// Record that a stored file key was replaced by a new one.
+fn audit_key_rotation(address: &str, file_hash: &str) {
+    crate::crypto::audit::record(
+        crate::crypto::CryptoOperation::KeyRotated,
+        None,
+        format!("file key for {} replaced in account {}", file_hash, address),
+    );
+}
+
 /// Generic function to encrypt any string data using the password and a salt.
 fn encrypt_data(
     data_to_encrypt: &str,
@@ -41,4 +41,179 @@ pub mod stream_auth;
 pub mod reputation;
 
 // Logger module for file-based logging
-pub mod logger;
\ No newline at end of file
+pub mod logger;
+
+// Peer messaging and message history
+pub mod messaging;
+
+// Registry of files shared by this node
+pub mod shared_files;
+
+// Signed bootstrap node manifests
+pub mod bootstrap_manifest;
+
+// Storage locations, disk quota and cleanup
+pub mod storage;
+
+// Protocol version compatibility checks
+pub mod compatibility;
+
+// Wire protocol identifiers and versions
+pub mod protocol;
+
+// Persistent history of finished transfers
+pub mod transfer_history;
+
+// Per-peer event timelines for debugging
+pub mod monitoring;
+
+// Directory bundle manifests for multi-file shares
+pub mod bundle;
+
+// Cached peer addresses supplementing mDNS discovery
+pub mod discovery;
+
+// Byte-range validation and verification over chunked content
+pub mod byte_range;
+
+// Provider availability and speed probes before downloads
+pub mod provider_probe;
+
+// Adaptive AutoNAT probe scheduling
+pub mod nat;
+
+// Rarest-first chunk scheduling across download sources
+pub mod chunk_scheduler;
+
+// AES-256-GCM encrypted peer address storage
+pub mod encrypted_peer_store;
+
+// Upload slot limits with fair per-peer queuing
+pub mod upload_slots;
+
+// Per-content exclusion of providers that serve corrupt data
+pub mod source_exclusion;
+
+// Voice call signaling
+pub mod call;
+
+// Typing indicators and other ephemeral presence
+pub mod presence;
+
+// Resume records for interrupted downloads
+pub mod download_resume;
+
+// Provider-aware ranking of file search results
+pub mod search_ranking;
+
+// zstd compression of transfer chunks
+pub mod compression;
+
+// Time-of-day bandwidth limits
+pub mod bandwidth_schedule;
+
+// Auto-publishing of files dropped into a watch directory
+pub mod watch_dir;
+
+// Chunk request pipelining per download source
+pub mod chunk_pipeline;
+
+// JSON-lines log of swarm events
+pub mod swarm_event_log;
+
+// Stall detection and recovery for downloads
+pub mod stall_recovery;
+
+// Reachability reports to bootstrap nodes
+pub mod reachability_update;
+
+// Prometheus endpoint for headless nodes
+pub mod metrics_exporter;
+
+// Liveness and readiness endpoints for containers
+pub mod health_check;
+
+// Consent policy for circuits through the relay server
+pub mod relay_consent;
+
+// Text or JSON log output
+pub mod log_format;
+
+// History of NAT test runs
+pub mod nat_test;
+
+// Webhooks and other hooks into operator tooling
+pub mod integrations;
+
+// Peer address lookups on bootstrap nodes
+pub mod peer_lookup;
+
+// sd_notify readiness and watchdog for systemd units
+pub mod systemd;
+
+// UPnP and NAT-PMP port forwarding status
+pub mod port_forwarding;
+
+// One node per data directory
+pub mod instance_lock;
+
+// Audit trail of cryptographic operations
+pub mod crypto;
+
+// Node operations shared by the Tauri commands and the control API
+pub mod node_commands;
+
+// Local REST API for headless nodes
+pub mod control_api;
+
+// Kademlia records kept on a chosen number of peers
+pub mod replication;
+
+// Port selection when several nodes share a machine
+pub mod listen_ports;
+
+// Directories a headless seed node publishes at startup
+pub mod seed_dir;
+
+// Time-boxed network traces for bug reports
+pub mod diagnostics;
+
+// Memory budget shared by the caches
+pub mod memory_budget;
+
+// Summary of a headless run for soak tests
+pub mod run_report;
+
+// In-process libp2p protocol benchmarks
+pub mod protocol_bench;
+
+// Normalizing addresses before they are stored
+pub mod multiaddr;
+
+// Paths of everything a node persists
+pub mod data_dirs;
+
+// JSON-RPC control socket of headless nodes
+#[cfg(unix)]
+pub mod rpc;
+
+// Reproducible topologies for tests
+pub mod testing;
+
+// Startup failures of the desktop app, for the error dialog
+pub mod startup_error;
+
+// The unified `chiral-event` and its subscriptions
+pub mod chiral_events;
+
+// Zip of logs and state for bug reports
+pub mod diagnostics_bundle;
+
+// In-memory ring of recent log lines for the live log view
+pub mod log_buffer;
+
+// Allow, deny and throttle rules for incoming connections
+pub mod security;
+
+// Local GeoIP lookups of connected peers for the network map
+pub mod geolocation;
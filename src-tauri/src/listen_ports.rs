@@ -0,0 +1,231 @@
+//! Choosing the ports of a node, so several can run on one machine.
+//!
+//! Before a listener is started its port is tried with a throwaway bind.
+//! A busy port fails startup with an error naming the port and listener,
+//! unless port fallback is on (`network.port_fallback`, `--port-fallback`),
+//! in which case the OS picks a free port instead. Port 0 always asks the
+//! OS. The DHT port is resolved to a concrete number up front, so the
+//! startup log, `--show-multiaddr` and the listen addresses all agree.
+//!
+//! Once every listener is up the headless node writes the ports it got to
+//! `ports.json` in the data directory, e.g.
+//!
+//! ```json
+//! {"dht":40213,"metrics":9464,"health":null,"api":38117}
+//! ```
+//!
+//! The file is rewritten on every start; the data directory lock makes sure
+//! only one node writes it.
+//!
+//! `--print-multiaddrs` asks before a node starts: `advertised_addrs` listens
+//! briefly on free ports of the same interfaces and reports the addresses
+//! with the configured ports put back, so it works while a node holds them.
+
+use futures::StreamExt;
+use libp2p::multiaddr::Protocol;
+use libp2p::swarm::{dummy, ListenerId, SwarmEvent};
+use libp2p::{identity, tcp, yamux, Multiaddr, SwarmBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use std::path::Path;
+use std::time::Duration;
+
+/// File in the data directory listing the bound ports
+pub const PORTS_FILE_NAME: &str = "ports.json";
+
+/// Quiet time after which no more listen addresses are expected
+const ADDR_SETTLE: Duration = Duration::from_millis(300);
+
+/// Longest wait for every listener to report an address
+const ADDR_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether `addr` can be bound now; errors other than a busy port are left
+/// to the listener itself
+fn in_use(addr: SocketAddr) -> bool {
+    matches!(TcpListener::bind(addr), Err(e) if e.kind() == ErrorKind::AddrInUse)
+}
+
+fn free_port(ip: std::net::IpAddr) -> Result<u16, String> {
+    TcpListener::bind((ip, 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .map_err(|e| format!("Failed to find a free port on {}: {}", ip, e))
+}
+
+/// `addr`, or `addr` with port 0 when its port is busy and `fallback` is on
+///
+/// `listener` names the listener in the error and the log
+pub fn resolve_addr(listener: &str, addr: SocketAddr, fallback: bool) -> Result<SocketAddr, String> {
+    if addr.port() == 0 || !in_use(addr) {
+        return Ok(addr);
+    }
+    if !fallback {
+        return Err(format!(
+            "{} port {} is already in use on {}; choose another port, or pass --port-fallback to use a free one",
+            listener,
+            addr.port(),
+            addr.ip()
+        ));
+    }
+    tracing::warn!("{} port {} is in use; letting the OS pick one", listener, addr.port());
+    Ok(SocketAddr::new(addr.ip(), 0))
+}
+
+/// The TCP port the DHT listens on: `port`, or a free one when `port` is 0
+/// or busy with `fallback` on
+pub fn resolve_dht_port(port: u16, fallback: bool) -> Result<u16, String> {
+    let any = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port));
+    if port == 0 {
+        return free_port(any.ip());
+    }
+    if !in_use(any) {
+        return Ok(port);
+    }
+    if !fallback {
+        return Err(format!(
+            "DHT port {} is already in use, probably by another node; choose one with --port, \
+             or pass --port 0 or --port-fallback to use a free one",
+            port
+        ));
+    }
+    let chosen = free_port(any.ip())?;
+    tracing::warn!("DHT port {} is in use; listening on {} instead", port, chosen);
+    Ok(chosen)
+}
+
+fn tcp_port(addr: &Multiaddr) -> Option<u16> {
+    addr.iter().find_map(|component| match component {
+        Protocol::Tcp(port) => Some(port),
+        _ => None,
+    })
+}
+
+fn with_tcp_port(addr: &Multiaddr, port: u16) -> Multiaddr {
+    addr.iter()
+        .map(|component| match component {
+            Protocol::Tcp(_) => Protocol::Tcp(port),
+            other => other,
+        })
+        .collect()
+}
+
+/// The addresses a node with `keypair` would listen on: the DHT `port` on
+/// every IPv4 interface, then `listen_addrs`. With port 0 the printed port
+/// is only the one the OS handed out this time.
+pub async fn advertised_addrs(
+    keypair: identity::Keypair,
+    port: u16,
+    listen_addrs: &[String],
+    noise_prologue: &[u8],
+) -> Result<Vec<Multiaddr>, String> {
+    let mut wanted = vec![Multiaddr::from(Ipv4Addr::UNSPECIFIED).with(Protocol::Tcp(port))];
+    for addr in listen_addrs {
+        wanted.push(
+            addr.parse()
+                .map_err(|e| format!("invalid listen address {}: {}", addr, e))?,
+        );
+    }
+    let mut swarm = SwarmBuilder::with_existing_identity(keypair)
+        .with_tokio()
+        .with_tcp(
+            tcp::Config::default(),
+            |key: &identity::Keypair| crate::dht::noise_config(key, noise_prologue),
+            yamux::Config::default,
+        )
+        .map_err(|e| format!("transport: {}", e))?
+        .with_behaviour(|_| dummy::Behaviour)
+        .map_err(|e| format!("behaviour: {}", e))?
+        .build();
+
+    let mut configured: HashMap<ListenerId, Option<u16>> = HashMap::new();
+    for addr in &wanted {
+        let id = swarm
+            .listen_on(with_tcp_port(addr, 0))
+            .map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+        configured.insert(id, tcp_port(addr).filter(|port| *port != 0));
+    }
+    let mut pending: HashSet<ListenerId> = configured.keys().copied().collect();
+    let mut addrs = Vec::new();
+    let deadline = tokio::time::Instant::now() + ADDR_TIMEOUT;
+    loop {
+        match tokio::time::timeout(ADDR_SETTLE, swarm.select_next_some()).await {
+            Ok(SwarmEvent::NewListenAddr { listener_id, address }) => {
+                pending.remove(&listener_id);
+                let address = match configured.get(&listener_id).copied().flatten() {
+                    Some(port) => with_tcp_port(&address, port),
+                    None => address,
+                };
+                if !addrs.contains(&address) {
+                    addrs.push(address);
+                }
+            }
+            Ok(SwarmEvent::ListenerError { error, .. }) => {
+                return Err(format!("Listener failed: {}", error));
+            }
+            Ok(_) => {}
+            Err(_) if pending.is_empty() => break,
+            Err(_) if tokio::time::Instant::now() >= deadline => {
+                return Err("Timed out waiting for listen addresses".to_string());
+            }
+            Err(_) => {}
+        }
+    }
+    Ok(addrs)
+}
+
+/// The ports a running node bound
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListenPorts {
+    pub dht: u16,
+    pub metrics: Option<u16>,
+    pub health: Option<u16>,
+    pub api: Option<u16>,
+}
+
+impl ListenPorts {
+    /// Write `ports.json` in `dir`, replacing it in one step
+    pub fn write(&self, dir: &Path) -> Result<(), String> {
+        let path = dir.join(PORTS_FILE_NAME);
+        let tmp = dir.join(format!("{}.tmp", PORTS_FILE_NAME));
+        let json = serde_json::to_vec(self).map_err(|e| e.to_string())?;
+        std::fs::write(&tmp, json)
+            .and_then(|()| std::fs::rename(&tmp, &path))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn read(dir: &Path) -> Result<Self, String> {
+        let path = dir.join(PORTS_FILE_NAME);
+        let text =
+            std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_slice(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_busy_ports_fail_or_fall_back() {
+        let held = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let busy = held.local_addr().unwrap();
+
+        let error = resolve_addr("Metrics", busy, false).unwrap_err();
+        assert!(error.contains(&busy.port().to_string()), "{}", error);
+        assert_eq!(resolve_addr("Metrics", busy, true).unwrap().port(), 0);
+        drop(held);
+        assert_eq!(resolve_addr("Metrics", busy, false).unwrap(), busy);
+        assert_ne!(resolve_dht_port(0, false).unwrap(), 0);
+
+        let dir = tempfile::tempdir().unwrap();
+        let ports = ListenPorts {
+            dht: 4001,
+            api: Some(5001),
+            ..Default::default()
+        };
+        ports.write(dir.path()).unwrap();
+        assert_eq!(ListenPorts::read(dir.path()).unwrap(), ports);
+    }
+}
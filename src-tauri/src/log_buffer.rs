@@ -0,0 +1,260 @@
+//! The newest log lines, kept in memory for the live log view.
+//!
+//! `LogBufferLayer` sits in the tracing subscriber next to the console and
+//! file layers, so it sees the same lines whether or not file logging is
+//! on. The buffer holds at most `LOG_BUFFER_CAPACITY` lines and drops the
+//! oldest first. While streaming is on every new line is also sent to the
+//! subscribers of `subscribe`.
+//!
+//! Lines are stored as logged; callers that hand them out redact them with
+//! `SecretRedactor` first.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Lines kept in memory
+pub const LOG_BUFFER_CAPACITY: usize = 5_000;
+
+/// Tauri event carrying each new line while streaming is on
+pub const LOG_LINE_EVENT: &str = "log-line";
+
+/// Lines a slow stream subscriber may fall behind before it skips ahead
+const STREAM_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLine {
+    /// Unix milliseconds
+    pub timestamp: u64,
+    /// `ERROR`, `WARN`, `INFO`, `DEBUG` or `TRACE`
+    pub level: String,
+    pub target: String,
+    /// The message followed by the other fields as `key=value`
+    pub message: String,
+}
+
+struct BufferedLine {
+    level: Level,
+    line: LogLine,
+}
+
+pub struct LogBuffer {
+    capacity: usize,
+    lines: Mutex<VecDeque<BufferedLine>>,
+    streaming: AtomicBool,
+    /// Bumped each time streaming is turned on, so a forwarder started for
+    /// an earlier stream can tell it is stale
+    generation: AtomicU64,
+    stream: broadcast::Sender<LogLine>,
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::new(LOG_BUFFER_CAPACITY)
+    }
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        let (stream, _) = broadcast::channel(STREAM_CAPACITY);
+        Self {
+            capacity: capacity.max(1),
+            lines: Mutex::new(VecDeque::with_capacity(capacity.min(LOG_BUFFER_CAPACITY))),
+            streaming: AtomicBool::new(false),
+            generation: AtomicU64::new(0),
+            stream,
+        }
+    }
+
+    pub fn push(&self, level: Level, target: &str, message: String) {
+        let line = LogLine {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            level: level.as_str().to_string(),
+            target: target.to_string(),
+            message,
+        };
+        if self.is_streaming() {
+            let _ = self.stream.send(line.clone());
+        }
+        let mut lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        if lines.len() >= self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(BufferedLine { level, line });
+    }
+
+    /// The newest `count` lines at `min_level` or more severe, oldest first
+    pub fn tail(&self, count: usize, min_level: Option<Level>) -> Vec<LogLine> {
+        let lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        let mut tail: Vec<LogLine> = lines
+            .iter()
+            .rev()
+            .filter(|buffered| min_level.map_or(true, |min| buffered.level <= min))
+            .take(count)
+            .map(|buffered| buffered.line.clone())
+            .collect();
+        tail.reverse();
+        tail
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Turn streaming on or off; returns the generation of the stream when
+    /// it was just turned on
+    pub fn set_streaming(&self, enabled: bool) -> Option<u64> {
+        let was = self.streaming.swap(enabled, Ordering::SeqCst);
+        (enabled && !was).then(|| self.generation.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.streaming.load(Ordering::Relaxed)
+    }
+
+    /// Streaming is on and was last turned on as `generation`
+    pub fn is_current_stream(&self, generation: u64) -> bool {
+        self.is_streaming() && self.generation.load(Ordering::SeqCst) == generation
+    }
+
+    /// New lines, sent only while streaming is on
+    pub fn subscribe(&self) -> broadcast::Receiver<LogLine> {
+        self.stream.subscribe()
+    }
+}
+
+/// `error`, `warn`, `info`, `debug` or `trace`, in any case
+pub fn parse_level(level: &str) -> Result<Level, String> {
+    level
+        .trim()
+        .parse::<Level>()
+        .map_err(|_| format!("unknown log level '{}'", level))
+}
+
+/// Tracing layer recording every event that passes the filter into a
+/// `LogBuffer`
+pub struct LogBufferLayer {
+    buffer: Arc<LogBuffer>,
+}
+
+impl LogBufferLayer {
+    pub fn new(buffer: Arc<LogBuffer>) -> Self {
+        Self { buffer }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogBufferLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let metadata = event.metadata();
+        self.buffer
+            .push(*metadata.level(), metadata.target(), visitor.finish());
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl MessageVisitor {
+    fn finish(mut self) -> String {
+        if self.message.is_empty() {
+            return self.fields.trim_start().to_string();
+        }
+        self.message.push_str(&self.fields);
+        self.message
+    }
+}
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.fields, " {}={}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn test_buffer_is_bounded_and_filters_by_level() {
+        let buffer = Arc::new(LogBuffer::new(3));
+        let subscriber = tracing_subscriber::registry().with(LogBufferLayer::new(buffer.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!("first");
+            tracing::info!(peer = "12D3KooW", "connected");
+            tracing::warn!("relay lost");
+            tracing::debug!(attempt = 2, "redialing");
+        });
+
+        assert_eq!(buffer.len(), 3, "oldest line dropped");
+        let all: Vec<String> = buffer
+            .tail(10, None)
+            .into_iter()
+            .map(|line| line.message)
+            .collect();
+        assert_eq!(
+            all,
+            [
+                "connected peer=12D3KooW",
+                "relay lost",
+                "redialing attempt=2"
+            ]
+        );
+
+        let info = buffer.tail(10, Some(parse_level("INFO").unwrap()));
+        assert_eq!(info.len(), 2);
+        assert_eq!(info[1].level, "WARN");
+        assert_eq!(buffer.tail(1, None)[0].message, "redialing attempt=2");
+        assert!(parse_level("loud").is_err());
+
+        let mut stream = buffer.subscribe();
+        let generation = buffer.set_streaming(true).unwrap();
+        assert_eq!(buffer.set_streaming(true), None, "already streaming");
+        buffer.push(
+            Level::ERROR,
+            "chiral_network::dht",
+            "listen failed".to_string(),
+        );
+        assert_eq!(stream.try_recv().unwrap().message, "listen failed");
+        buffer.set_streaming(false);
+        buffer.push(
+            Level::ERROR,
+            "chiral_network::dht",
+            "not streamed".to_string(),
+        );
+        assert!(stream.try_recv().is_err());
+        assert!(!buffer.is_current_stream(generation));
+    }
+}
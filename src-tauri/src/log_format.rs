@@ -0,0 +1,98 @@
+//! Human-readable or JSON log output.
+//!
+//! The text format stays the default for the desktop app. The JSON format
+//! writes one object per line for log aggregation, with the event's fields
+//! at the top level next to the standard ones:
+//!
+//! ```json
+//! {"timestamp":"2025-01-01T00:00:00.000000Z","level":"INFO","target":"chiral_network::dht","message":"DCUtR: hole-punch succeeded","peer_id":"12D3KooW...","successes":1}
+//! ```
+//!
+//! Field names are part of the operator interface: `peer_id` for a remote
+//! peer, `local_peer_id` for this node, `transfer_id` for a transfer, and
+//! `outcome` for the result of a DCUtR or AutoNAT attempt.
+
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{fmt, Layer};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!("unknown log format '{}' (expected text or json)", other)),
+        }
+    }
+}
+
+impl LogFormat {
+    /// Formatting layer writing to `writer` in this format
+    pub fn layer<S, W>(self, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+    where
+        S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+        W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+    {
+        match self {
+            Self::Text => fmt::layer().with_writer(writer).boxed(),
+            Self::Json => fmt::layer()
+                .json()
+                .flatten_event(true)
+                .with_current_span(false)
+                .with_span_list(false)
+                .with_writer(writer)
+                .boxed(),
+        }
+    }
+}
+
+/// A field of a JSON log line, `None` for text lines and missing fields
+pub fn json_field(line: &str, field: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(line.trim()).ok()?;
+    match value.get(field)? {
+        serde_json::Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// This node's peer id as logged at startup, from either format
+pub fn local_peer_id(line: &str) -> Option<String> {
+    if let Some(peer_id) = json_field(line, "local_peer_id") {
+        return Some(peer_id);
+    }
+    let (_, rest) = line.split_once("local_peer_id=")?;
+    let peer_id: String = rest.chars().take_while(|c| c.is_ascii_alphanumeric()).collect();
+    (!peer_id.is_empty()).then_some(peer_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields_are_read_from_either_format() {
+        let json = r#"{"timestamp":"2025-01-01T00:00:00Z","level":"INFO","target":"chiral_network::headless","message":"Node started","local_peer_id":"12D3KooWabc","port":4001}"#;
+        assert_eq!(local_peer_id(json).as_deref(), Some("12D3KooWabc"));
+        assert_eq!(json_field(json, "port").as_deref(), Some("4001"));
+        assert_eq!(json_field(json, "transfer_id"), None);
+
+        let text = "2025-01-01T00:00:00Z  INFO chiral_network::headless: Node started local_peer_id=12D3KooWabc port=4001";
+        assert_eq!(local_peer_id(text).as_deref(), Some("12D3KooWabc"));
+        assert_eq!(json_field(text, "local_peer_id"), None);
+
+        assert_eq!("JSON".parse(), Ok(LogFormat::Json));
+        assert!("logfmt".parse::<LogFormat>().is_err());
+    }
+}
@@ -5,6 +5,9 @@ use std::sync::{Arc, Mutex};
 use chrono::Local;
 use tracing_subscriber::fmt::MakeWriter;
 
+/// Rotated files kept by default, the current one included
+pub const DEFAULT_MAX_LOG_FILES: usize = 5;
+
 /// Configuration for file logging
 #[derive(Clone, Debug)]
 pub struct LogConfig {
@@ -14,6 +17,8 @@ pub struct LogConfig {
     pub max_log_size_mb: u64,
     /// Whether file logging is enabled
     pub enabled: bool,
+    /// Number of log files kept; older ones are deleted on rotation
+    pub max_files: usize,
 }
 
 impl LogConfig {
@@ -22,8 +27,14 @@ impl LogConfig {
             logs_dir: logs_dir.as_ref().to_path_buf(),
             max_log_size_mb,
             enabled,
+            max_files: DEFAULT_MAX_LOG_FILES,
         }
     }
+
+    pub fn with_max_files(mut self, max_files: usize) -> Self {
+        self.max_files = max_files.max(1);
+        self
+    }
 }
 
 /// Custom file writer that handles log rotation
@@ -70,7 +81,15 @@ impl RotatingFileWriter {
 
     fn get_new_log_file_path(&self, config: &LogConfig) -> PathBuf {
         let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
-        config.logs_dir.join(format!("chiral_{}.log", timestamp))
+        let path = config.logs_dir.join(format!("chiral_{}.log", timestamp));
+        if !path.exists() {
+            return path;
+        }
+        // Rotated more than once within the second; the suffix keeps names in order
+        (1..)
+            .map(|n| config.logs_dir.join(format!("chiral_{}_{:03}.log", timestamp, n)))
+            .find(|path| !path.exists())
+            .expect("unbounded range")
     }
 
     fn should_rotate(&self, config: &LogConfig) -> io::Result<bool> {
@@ -78,8 +97,7 @@ impl RotatingFileWriter {
         
         if let Some(path) = current_path_lock.as_ref() {
             if let Ok(metadata) = fs::metadata(path) {
-                let size_mb = metadata.len() / (1024 * 1024);
-                return Ok(size_mb >= config.max_log_size_mb);
+                return Ok(metadata.len() >= config.max_log_size_mb * 1024 * 1024);
             }
         }
         
@@ -100,41 +118,9 @@ impl RotatingFileWriter {
     }
 
     fn cleanup_old_logs(&self, config: &LogConfig) -> io::Result<()> {
-        // Get all log files sorted by modification time
-        let mut log_files: Vec<_> = fs::read_dir(&config.logs_dir)?
-            .filter_map(|entry| entry.ok())
-            .filter(|entry| {
-                entry.path().extension()
-                    .and_then(|ext| ext.to_str())
-                    .map(|ext| ext == "log")
-                    .unwrap_or(false)
-            })
-            .collect();
-
-        // Sort by modification time (newest first)
-        log_files.sort_by_key(|entry| {
-            entry.metadata()
-                .and_then(|m| m.modified())
-                .ok()
-        });
-        log_files.reverse();
-
-        // Calculate total size
-        let mut total_size_mb = 0u64;
-        let max_total_size_mb = config.max_log_size_mb * 10; // Keep max 10x the individual file limit
-
-        for (idx, entry) in log_files.iter().enumerate() {
-            if let Ok(metadata) = entry.metadata() {
-                let file_size_mb = metadata.len() / (1024 * 1024);
-                total_size_mb += file_size_mb;
-
-                // Delete old files if we exceed the total limit (but keep at least the newest file)
-                if idx > 0 && total_size_mb > max_total_size_mb {
-                    let _ = fs::remove_file(entry.path());
-                }
-            }
+        for path in recent_log_files(&config.logs_dir)?.into_iter().skip(config.max_files) {
+            let _ = fs::remove_file(path);
         }
-
         Ok(())
     }
 
@@ -154,6 +140,7 @@ impl RotatingFileWriter {
             let file = File::create(&new_path)?;
             *current_file = Some(file);
             *current_path = Some(new_path);
+            let _ = self.cleanup_old_logs(&config);
         }
 
         Ok(())
@@ -165,7 +152,24 @@ impl RotatingFileWriter {
     }
 }
 
-
+/// Log files in `logs_dir`, newest first
+pub fn recent_log_files(logs_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut log_files: Vec<PathBuf> = fs::read_dir(logs_dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension().and_then(|ext| ext.to_str()) == Some("log")
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("chiral_"))
+        })
+        .collect();
+    // Names start with the creation time, so they sort oldest first
+    log_files.sort();
+    log_files.reverse();
+    Ok(log_files)
+}
 
 impl Write for RotatingFileWriter {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
@@ -180,11 +184,8 @@ impl Write for RotatingFileWriter {
 
         // Check if rotation is needed
         self.rotate_if_needed(&self.config.lock().unwrap())?;
-        
-        // Clean up old logs periodically (we could optimize this to not run every write)
-        let _ = self.cleanup_old_logs(&self.config.lock().unwrap());
 
-        // Ensure we have a file
+        // Ensure we have a file (old logs are cleaned up when one is created)
         self.get_or_create_file()?;
 
         // Write to the current file
@@ -269,3 +270,30 @@ impl Write for ThreadSafeWriterGuard {
         writer.flush()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_keeps_the_newest_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = LogConfig::new(dir.path(), 1, true).with_max_files(3);
+        let mut writer = RotatingFileWriter::new(config).unwrap();
+
+        let line = [b'x'; 1023];
+        for _ in 0..(5 * 1024 + 512) {
+            writer.write_all(&line).unwrap();
+            writer.write_all(b"\n").unwrap();
+        }
+        writer.flush().unwrap();
+
+        let files = recent_log_files(dir.path()).unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!(writer.current_log_file_path().as_ref(), files.first());
+        for old in &files[1..] {
+            let size = fs::metadata(old).unwrap().len();
+            assert!((1024 * 1024..1024 * 1024 + 2048).contains(&size), "{} bytes", size);
+        }
+    }
+}
@@ -20,10 +20,11 @@ pub mod reassembly;
 
 // Re-export modules from the lib crate
 use chiral_network::{
-    analytics, bandwidth, bittorrent_handler, download_restart,
-    dht, ed2k_client, encryption, file_transfer,
-    http_download, keystore, logger, manager, multi_source_download, peer_selection, protocols,
-    reputation, stream_auth, webrtc_service,
+    analytics, bandwidth, bandwidth_schedule, bittorrent_handler, bundle, call, chiral_events, compression, download_restart, download_resume,
+    dht, diagnostics, diagnostics_bundle, discovery, ed2k_client, encryption, file_transfer,
+    geolocation, http_download, keystore, log_buffer, logger, manager, messaging, monitoring, multi_source_download, peer_selection, protocol,
+    protocols, relay_consent, reputation, search_ranking, security, shared_files, storage, stream_auth, transfer_history,
+    upload_slots, watch_dir, webrtc_service,
 };
 
 use protocols::{BitTorrentProtocolHandler, ProtocolManager, SimpleProtocolHandler, ProtocolHandler};
@@ -34,9 +35,51 @@ use crate::commands::auth::{
 };
 
 use bandwidth::BandwidthController;
-use crate::commands::bootstrap::get_bootstrap_nodes_command;
+use bandwidth_schedule::{BandwidthScheduleStatus, BandwidthScheduler};
+use crate::commands::bootstrap::{
+    get_bootstrap_contribution_stats_command, get_bootstrap_node_status,
+    get_bootstrap_nodes_command, get_quarantined_bootstrap_nodes_command,
+    restore_quarantined_bootstrap_command, run_bootstrap_monitor,
+};
 use crate::commands::bootstrap::get_bootstrap_nodes;
+use crate::commands::messaging::{
+    get_message_reactions_command, get_thread_command, join_message_channel,
+    mark_messages_read, publish_message_command, react_to_message,
+    run_retransmission_loop, send_direct_message, set_topic_filter_command,
+};
 use crate::commands::network::get_full_network_stats;
+use crate::commands::bundle::{download_bundle, publish_directory};
+use crate::commands::protocol::get_protocol_versions_command;
+use crate::commands::call::{accept_call, end_call, get_active_calls, reject_call, start_call};
+use crate::commands::presence::{start_typing, stop_typing};
+use crate::commands::file_transfer::{
+    accept_file_transfer, get_transfer_compression_stats, reject_file_transfer, send_file_to_peer,
+};
+use crate::commands::watch_dir::{
+    get_watch_status, run_watch_dir_loop, set_watch_directory, WatchDirState,
+};
+use crate::commands::shared_files::{
+    get_seeding_limits, get_upload_slot_stats, list_shared_files, repair_shared_file,
+    reverify_shared_file, run_seeding_limit_loop, run_verification_loop,
+    set_content_seeding_limits, set_seeding_limits, set_upload_peer_trusted, unshare_file,
+    verify_shared_file,
+};
+use crate::commands::bandwidth::{
+    override_bandwidth_limits, resume_bandwidth_schedule, set_bandwidth_schedule,
+};
+use crate::commands::search::search_files;
+use crate::commands::storage::{
+    cleanup_storage, get_storage_settings, get_storage_usage, update_storage_settings,
+};
+use crate::commands::transfer_history::{clear_transfer_history, get_transfer_history};
+use crate::commands::events::{subscribe_events, unsubscribe_events};
+use crate::commands::geolocation::{
+    get_peer_locations, list_connected_peers, set_peer_geolocation_enabled,
+};
+use crate::commands::logs::{set_log_streaming, tail_logs};
+use crate::commands::relay::{add_trusted_peer, list_trusted_peers, remove_trusted_peer};
+use crate::commands::security::{add_filter_rule_command, get_filter_rules_command, remove_filter_rule_command};
+use crate::commands::{limited, RateLimiter};
 use crate::commands::proxy::{
     disable_privacy_routing, enable_privacy_routing, list_proxies, proxy_connect, proxy_disconnect,
     proxy_echo, proxy_remove, ProxyNode,
@@ -46,7 +89,6 @@ use stream_auth::{
     StreamAuthService,
 };
 use dht::{models::DhtMetricsSnapshot, models::FileMetadata, DhtEvent, DhtService};
-use directories::ProjectDirs;
 use ethereum::{
     create_new_account,
     get_account_from_private_key,
@@ -76,10 +118,20 @@ use fs2::available_space;
 use geth_downloader::GethDownloader;
 use keystore::Keystore;
 use lazy_static::lazy_static;
-use multi_source_download::{MultiSourceDownloadService, MultiSourceEvent, MultiSourceProgress};
+use multi_source_download::{
+    MultiSourceDownloadService, MultiSourceEvent, MultiSourceProgress, RangeRead,
+};
+use chiral_network::data_dirs::{self, DataDirs};
+use chiral_network::instance_lock::InstanceLock;
+use chiral_network::download_source::{ChunkHasher, WebSeeds};
+use chiral_network::log_format::LogFormat;
+use chiral_network::port_forwarding::PortForwardingStatus;
+use chiral_network::startup_error::{self, StartupError};
+use chiral_network::crypto::{AuditEntry, AuditLog};
+use chiral_network::node_commands::{self, PublishFileRequest};
 use chiral_network::transfer_events::{
     TransferEventBus, TransferStartedEvent, TransferCompletedEvent, TransferFailedEvent,
-    SourceInfo, SourceType, ErrorCategory, current_timestamp_ms,
+    TransferRates, SourceInfo, SourceType, ErrorCategory, current_timestamp_ms,
 };
 use serde::{Deserialize, Serialize};
 use sha2::Digest;
@@ -123,26 +175,30 @@ struct BackendSettings {
     enable_file_logging: bool,
     #[serde(rename = "maxLogSizeMB")]
     max_log_size_mb: u64,
+    #[serde(rename = "maxLogFiles")]
+    max_log_files: usize,
+    #[serde(rename = "logFormat")]
+    log_format: LogFormat,
 }
 
+/// Keeps the background file-log writer alive for the life of the app
+struct FileLogGuard(tracing_appender::non_blocking::WorkerGuard);
+
 impl Default for BackendSettings {
     fn default() -> Self {
         Self {
             storage_path: "~/ChiralNetwork/Storage".to_string(),
             enable_file_logging: false,
             max_log_size_mb: 10,
+            max_log_files: logger::DEFAULT_MAX_LOG_FILES,
+            log_format: LogFormat::Text,
         }
     }
 }
 
-/// Load settings from the Tauri app data directory
-fn load_settings_from_file(app_handle: &tauri::AppHandle) -> BackendSettings {
-    let app_data_dir = app_handle
-        .path()
-        .app_data_dir()
-        .expect("Failed to get app data directory");
-
-    let settings_file = app_data_dir.join("settings.json");
+/// Load settings from the data directory
+fn load_settings_from_file() -> BackendSettings {
+    let settings_file = DataDirs::current().settings_file();
     info!("Loading settings from: {}", settings_file.display());
 
     if settings_file.exists() {
@@ -164,11 +220,23 @@ fn load_settings_from_file(app_handle: &tauri::AppHandle) -> BackendSettings {
                             .get("maxLogSizeMB")
                             .and_then(|v| v.as_u64())
                             .unwrap_or(10);
+                        let max_log_files = json
+                            .get("maxLogFiles")
+                            .and_then(|v| v.as_u64())
+                            .map(|v| v as usize)
+                            .unwrap_or(logger::DEFAULT_MAX_LOG_FILES);
+                        let log_format = json
+                            .get("logFormat")
+                            .and_then(|v| v.as_str())
+                            .and_then(|v| v.parse().ok())
+                            .unwrap_or_default();
 
                         return BackendSettings {
                             storage_path,
                             enable_file_logging,
                             max_log_size_mb,
+                            max_log_files,
+                            log_format,
                         };
                     }
                     Err(e) => {
@@ -302,6 +370,10 @@ pub struct StreamingUploadSession {
     pub created_at: std::time::SystemTime,
     pub chunk_cids: Vec<String>,
     pub file_data: Vec<u8>,
+    /// HTTP(S) copies to advertise in the published metadata
+    pub web_seeds: Option<Vec<String>>,
+    /// Chunk hashes for `web_seeds`, while they are set
+    pub chunk_hasher: Option<ChunkHasher>,
 }
 
 /// Session for streaming WebRTC downloads - writes chunks directly to disk
@@ -332,6 +404,8 @@ struct AppState {
     file_transfer: Mutex<Option<Arc<FileTransferService>>>,
     webrtc: Mutex<Option<Arc<WebRTCService>>>,
     multi_source_download: Mutex<Option<Arc<MultiSourceDownloadService>>>,
+    /// Web seed preference, kept here so it survives DHT restarts
+    web_seeds_enabled: std::sync::atomic::AtomicBool,
     keystore: Arc<Mutex<Keystore>>,
     proxies: Arc<Mutex<Vec<ProxyNode>>>,
     privacy_proxies: Arc<Mutex<Vec<String>>>,
@@ -340,6 +414,8 @@ struct AppState {
     socks5_proxy_cli: Mutex<Option<String>>,
     analytics: Arc<analytics::AnalyticsService>,
     bandwidth: Arc<BandwidthController>,
+    /// Applies time-of-day limits to `bandwidth`
+    bandwidth_schedule: Arc<BandwidthScheduler>,
 
     // New fields for transaction queue
     transaction_queue: Arc<Mutex<VecDeque<QueuedTransaction>>>,
@@ -391,6 +467,12 @@ struct AppState {
 
     // Download restart service for pause/resume functionality
     download_restart: Mutex<Option<Arc<download_restart::DownloadRestartService>>>,
+
+    // Persistent registry of files this node is sharing
+    shared_files: Arc<shared_files::SharedFilesRegistry>,
+
+    // Download directory, disk quota and storage cleanup
+    storage: Arc<storage::StorageManager>,
 }
 
 /// Tauri command to create a new Chiral account
@@ -762,7 +844,27 @@ async fn set_bandwidth_limits(
     download_kbps: u64,
     state: State<'_, AppState>,
 ) -> Result<(), String> {
-    state.bandwidth.set_limits(upload_kbps, download_kbps).await;
+    // Defaults; the schedule or a manual override may still take precedence
+    state
+        .bandwidth_schedule
+        .set_default_limits(upload_kbps, download_kbps)
+        .await;
+    Ok(())
+}
+
+/// Limit one transfer's bandwidth on top of the global limits; 0 in both
+/// directions removes the limit
+#[tauri::command]
+async fn set_transfer_rate_limit(
+    state: State<'_, AppState>,
+    id: String,
+    down_kbps: u64,
+    up_kbps: u64,
+) -> Result<(), String> {
+    state
+        .bandwidth
+        .set_transfer_limits(&id, up_kbps, down_kbps)
+        .await;
     Ok(())
 }
 
@@ -854,34 +956,25 @@ async fn upload_file(
     let account = get_active_account(&state).await?;
     let dht_opt = { state.dht.lock().await.as_ref().cloned() };
     if let Some(dht) = dht_opt {
-        // --- FIX: Calculate file_hash using file_transfer helper
-        let file_data = tokio::fs::read(&file_path)
-            .await
-            .map_err(|e| e.to_string())?;
-        let file_hash = FileTransferService::calculate_file_hash(&file_data);
-
-        let created_at = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or(std::time::Duration::from_secs(0))
-            .as_secs();
-
-        // Use the DHT helper to create file metadata
-        let metadata = dht
-            .prepare_file_metadata(
-                file_hash.clone(),
-                file_name.clone(),
-                file_data.len() as u64, // Use file size directly from data
-                file_data.clone(),
-                created_at,
+        let node_commands::PreparedFile {
+            file_hash,
+            file_name,
+            data: file_data,
+            metadata,
+        } = node_commands::prepare_file(
+            &dht,
+            PublishFileRequest {
+                file_path,
+                file_name: Some(file_name),
                 mime_type,
-                None, // encrypted_key_bundle
                 is_encrypted,
                 encryption_method,
                 key_fingerprint,
-                price,
-                Some(account.clone()),
-            )
-            .await?;
+                price: Some(price),
+            },
+            Some(account.clone()),
+        )
+        .await?;
 
         // Store file data locally for seeding
         let ft = {
@@ -1399,7 +1492,7 @@ async fn start_dht_node(
     let auto_enabled = enable_autonat.unwrap_or(false);
     info!("AUTONAT {}", auto_enabled);
     let probe_interval = autonat_probe_interval_secs.map(Duration::from_secs);
-    let autonat_server_list = autonat_servers.unwrap_or(bootstrap_nodes.clone());
+    let mut autonat_server_list = autonat_servers.unwrap_or(bootstrap_nodes.clone());
 
     // Get the proxy from the command line, if it was provided at launch
     let cli_proxy = state.socks5_proxy_cli.lock().await.clone();
@@ -1413,11 +1506,8 @@ async fn start_dht_node(
     };
 
     // Create a ChunkManager instance
-    let app_data_dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Could not get app data directory: {}", e))?;
-    let chunk_storage_path = app_data_dir.join("chunk_storage");
+    let chunk_storage_path = DataDirs::current().chunk_storage();
+    state.storage.set_chunk_cache_dir(chunk_storage_path.clone());
     let chunk_manager = Arc::new(ChunkManager::new(chunk_storage_path));
 
     // --- AutoRelay is now disabled by default (can be enabled via config or env var)
@@ -1448,9 +1538,7 @@ async fn start_dht_node(
         }
     }
 
-    let proj_dirs = ProjectDirs::from("com", "chiral-network", "chiral-network")
-        .ok_or("Failed to get project directories")?;
-    let blockstore_db_path = proj_dirs.data_dir().join("blockstore_db");
+    let blockstore_db_path = DataDirs::current().blockstore();
     let async_blockstore_path = async_std::path::Path::new(blockstore_db_path.as_os_str());
 
     let previous_autorelay_enabled = {
@@ -1462,6 +1550,25 @@ async fn start_dht_node(
         guard.clone()
     };
 
+    // Quarantined bootstrap nodes are not dialed, as bootstrap nodes or as
+    // AutoNAT servers
+    let (active_bootstrap, quarantined_bootstrap) = {
+        let pruner = app.state::<Mutex<discovery::BootstrapNodePruner>>();
+        let mut pruner = pruner.lock().await;
+        pruner.set_nodes(bootstrap_nodes.iter().filter_map(|a| a.parse().ok()).collect());
+        (pruner.active(), pruner.quarantined_addrs())
+    };
+    let is_quarantined = |a: &String| {
+        a.parse::<libp2p::Multiaddr>()
+            .is_ok_and(|addr| quarantined_bootstrap.contains(&addr))
+    };
+    bootstrap_nodes.retain(|a| !is_quarantined(a));
+    autonat_server_list.retain(|a| !is_quarantined(a));
+    app.state::<Mutex<discovery::BootstrapNodeMonitor>>()
+        .lock()
+        .await
+        .set_nodes(active_bootstrap);
+
     let dht_service = DhtService::new(
         port,
         bootstrap_nodes,
@@ -1497,10 +1604,22 @@ async fn start_dht_node(
     }
 
     let peer_id = dht_service.get_peer_id().await;
+    dht_service
+        .set_quarantined_bootstrap_nodes(quarantined_bootstrap)
+        .await;
 
     // DHT node is already running in a spawned background task
     let dht_arc = Arc::new(dht_service);
 
+    // Files peers send us directly land in the user's download directory
+    match app.path().download_dir() {
+        Ok(dir) => dht_arc.set_direct_transfer_dir(dir).await,
+        Err(e) => warn!("No download directory for direct transfers: {}", e),
+    }
+
+    // Bandwidth and bootstrap kinds of `chiral-event`
+    chiral_events::spawn_stats_events(app.clone(), Arc::downgrade(&dht_arc));
+
     // Spawn the event pump
     let app_handle = app.clone();
     let proxies_arc = state.proxies.clone();
@@ -1526,6 +1645,9 @@ async fn start_dht_node(
             }
 
             for ev in events {
+                if let Some(event) = chiral_events::ChiralEvent::from_dht(&ev) {
+                    chiral_events::emit(&app_handle, event);
+                }
                 match ev {
                     DhtEvent::PeerDiscovered { peer_id, addresses } => {
                         let payload = serde_json::json!({
@@ -1545,6 +1667,20 @@ async fn start_dht_node(
                         let payload = serde_json::json!({ "peerId": peer_id });
                         let _ = app_handle.emit("dht_peer_disconnected", payload);
                     }
+                    DhtEvent::PeerVersionIncompatible {
+                        peer_id,
+                        local_version,
+                        remote_version,
+                        reason,
+                    } => {
+                        let payload = serde_json::json!({
+                            "peerId": peer_id,
+                            "localVersion": local_version,
+                            "remoteVersion": remote_version,
+                            "reason": reason,
+                        });
+                        let _ = app_handle.emit("peer-version-incompatible", payload);
+                    }
                     DhtEvent::ProxyStatus {
                         id,
                         address,
@@ -1652,6 +1788,7 @@ async fn start_dht_node(
                         });
 
                         // Update statistics based on event type
+                        let previous_score = entry.reputation_score;
                         entry.reputation_score += impact;
                         entry.total_events += 1;
                         entry.last_seen = data
@@ -1670,6 +1807,14 @@ async fn start_dht_node(
                             "RelayCircuitSuccessful" => entry.circuits_successful += 1,
                             _ => {}
                         }
+                        let score = entry.reputation_score;
+                        drop(stats);
+                        dht_clone_for_pump
+                            .record_peer_event(
+                                &peer_id,
+                                monitoring::PeerEvent::ReputationChanged(previous_score, score),
+                            )
+                            .await;
 
                         // Emit event to frontend
                         let payload = serde_json::json!({
@@ -1719,6 +1864,30 @@ async fn start_dht_node(
                             println!("✅ Payment notification forwarded to frontend with transaction_hash and downloader_peer_id");
                         }
                     }
+                    DhtEvent::FileOffer(offer) => {
+                        let _ = app_handle.emit("file-transfer-offer", offer);
+                    }
+                    DhtEvent::FileTransferProgress(progress) => {
+                        let _ = app_handle.emit("file-transfer-progress", progress);
+                    }
+                    DhtEvent::Call(event) => {
+                        let _ = app_handle.emit(event.kind.event_name(), event);
+                    }
+                    DhtEvent::PeerTyping(typing) => {
+                        let _ = app_handle.emit("peer-typing", typing);
+                    }
+                    DhtEvent::Channel { envelope, .. } => {
+                        handle_channel_envelope(&app_handle, envelope);
+                    }
+                    DhtEvent::MessagesRead(batch) => {
+                        handle_read_receipts(&app_handle, batch);
+                    }
+                    DhtEvent::QualityDegraded(degraded) => {
+                        let _ = app_handle.emit("quality-degraded", degraded);
+                    }
+                    DhtEvent::MeshHealthDegraded(health) => {
+                        let _ = app_handle.emit("mesh-health-degraded", health);
+                    }
                     _ => {}
                 }
             }
@@ -1777,26 +1946,89 @@ async fn stop_publishing_file(state: State<'_, AppState>, file_hash: String) ->
         dht_guard.as_ref().cloned()
     };
     if let Some(dht) = dht {
-        dht.stop_publishing_file(file_hash).await
+        node_commands::stop_publishing(&dht, file_hash).await
+    } else {
+        Err("DHT node is not running".to_string())
+    }
+}
+
+#[tauri::command]
+async fn connect_to_peer(
+    state: State<'_, AppState>,
+    rate_limiter: State<'_, Mutex<RateLimiter>>,
+    peer_address: String,
+) -> Result<(), String> {
+    limited(&rate_limiter, "connect_to_peer", async {
+        let dht = {
+            let dht_guard = state.dht.lock().await;
+            dht_guard.as_ref().cloned()
+        };
+
+        if let Some(dht) = dht {
+            node_commands::connect_peer(&dht, peer_address).await
+        } else {
+            Err("DHT node is not running".to_string())
+        }
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_peer_event_history_command(
+    state: State<'_, AppState>,
+    peer_id: String,
+    limit: u32,
+) -> Result<Vec<monitoring::PeerEvent>, String> {
+    let dht = {
+        let dht_guard = state.dht.lock().await;
+        dht_guard.as_ref().cloned()
+    };
+
+    if let Some(dht) = dht {
+        dht.peer_event_history(&peer_id, limit as usize).await
     } else {
         Err("DHT node is not running".to_string())
     }
 }
 
 #[tauri::command]
-async fn connect_to_peer(state: State<'_, AppState>, peer_address: String) -> Result<(), String> {
+async fn get_node_announcements_command(
+    state: State<'_, AppState>,
+) -> Result<Vec<chiral_network::discovery::NodeAnnouncement>, String> {
     let dht = {
         let dht_guard = state.dht.lock().await;
         dht_guard.as_ref().cloned()
     };
 
     if let Some(dht) = dht {
-        dht.connect_peer(peer_address).await
+        Ok(dht.node_announcements().await)
     } else {
         Err("DHT node is not running".to_string())
     }
 }
 
+/// Peers matching a full or partial peer id, with every address known for each
+#[tauri::command]
+async fn find_peer_addresses(
+    state: State<'_, AppState>,
+    rate_limiter: State<'_, Mutex<RateLimiter>>,
+    query: String,
+) -> Result<Vec<discovery::PeerAddrMatch>, String> {
+    limited(&rate_limiter, "find_peer", async {
+        let dht = {
+            let dht_guard = state.dht.lock().await;
+            dht_guard.as_ref().cloned()
+        };
+
+        if let Some(dht) = dht {
+            dht.find_peer_addrs(query).await
+        } else {
+            Err("DHT node is not running".to_string())
+        }
+    })
+    .await
+}
+
 #[tauri::command]
 async fn is_dht_running(state: State<'_, AppState>) -> Result<bool, String> {
     let dht_guard = state.dht.lock().await;
@@ -1858,9 +2090,7 @@ async fn get_dht_connected_peers(state: State<'_, AppState>) -> Result<Vec<Strin
     };
 
     if let Some(dht) = dht {
-        // Get connected peers from DHT
-        let connected_peers = dht.get_connected_peers().await;
-        Ok(connected_peers)
+        Ok(node_commands::connected_peers(&dht).await)
     } else {
         Ok(Vec::new()) // Return empty vector if DHT is not running
     }
@@ -1966,12 +2196,161 @@ async fn get_dht_health(state: State<'_, AppState>) -> Result<Option<DhtMetricsS
     };
 
     if let Some(dht) = dht {
-        Ok(Some(dht.metrics_snapshot().await))
+        Ok(Some(node_commands::health(&dht).await))
     } else {
         Ok(None)
     }
 }
 
+/// Peers, reachability, bandwidth, transfers and routing table size; the
+/// same numbers the headless status line logs. Lock-free, for polling.
+#[tauri::command]
+async fn get_network_stats_command(state: State<'_, AppState>) -> Result<Option<monitoring::NetworkStats>, String> {
+    let dht = state.dht.lock().await.as_ref().cloned();
+    match dht {
+        Some(dht) => Ok(Some(node_commands::network_stats(&dht).await)),
+        None => Ok(None),
+    }
+}
+
+/// Everything the dashboard shows, in one round trip to the swarm task;
+/// the defaults, with `running` false, while the DHT is not running
+#[tauri::command]
+async fn get_dashboard_stats_command(state: State<'_, AppState>) -> Result<monitoring::DashboardStats, String> {
+    let dht = state.dht.lock().await.as_ref().cloned();
+    match dht {
+        Some(dht) => Ok(node_commands::dashboard_stats(&dht).await),
+        None => Ok(monitoring::DashboardStats::default()),
+    }
+}
+
+/// GossipSub mesh size of each subscribed topic against its targets;
+/// empty while the DHT is not running
+#[tauri::command]
+async fn get_mesh_health_command(state: State<'_, AppState>) -> Result<Vec<monitoring::MeshTopicHealth>, String> {
+    let dht = state.dht.lock().await.as_ref().cloned();
+    match dht {
+        Some(dht) => Ok(dht.mesh_health().await),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// `get_network_stats_command` with the metrics snapshot, peer list and
+/// k-bucket sizes
+#[tauri::command]
+async fn get_detailed_network_stats_command(
+    state: State<'_, AppState>,
+) -> Result<Option<monitoring::DetailedNetworkStats>, String> {
+    let dht = state.dht.lock().await.as_ref().cloned();
+    match dht {
+        Some(dht) => node_commands::detailed_network_stats(&dht).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Bytes sent and received per transport; all zero while the DHT is not
+/// running
+#[tauri::command]
+async fn get_transport_stats_command(state: State<'_, AppState>) -> Result<monitoring::TransportStats, String> {
+    let dht = state.dht.lock().await.as_ref().cloned();
+    Ok(dht
+        .map(|dht| node_commands::transport_stats(&dht))
+        .unwrap_or_default())
+}
+
+/// Cached port forwarding status; all off while the DHT is not running
+#[tauri::command]
+async fn get_port_forwarding_status_command(
+    state: State<'_, AppState>,
+) -> Result<PortForwardingStatus, String> {
+    let dht = state.dht.lock().await.as_ref().cloned();
+    Ok(dht
+        .map(|dht| dht.port_forwarding_status())
+        .unwrap_or_default())
+}
+
+/// Latest `limit` entries of the crypto audit log, newest first
+#[tauri::command]
+async fn get_crypto_audit_log_command(limit: u32) -> Result<Vec<AuditEntry>, String> {
+    let log = AuditLog::global()
+        .ok_or_else(|| "Crypto audit log is disabled (set CHIRAL_ENABLE_CRYPTO_AUDIT=1)".to_string())?;
+    tokio::task::spawn_blocking(move || log.recent(limit))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("Failed to read crypto audit log: {}", e))
+}
+
+/// Trace the swarm for `duration_secs`, then write the trace as gzipped JSON
+/// under `diagnostics/` in the data directory and return its path
+#[tauri::command]
+async fn start_trace_command(duration_secs: u64) -> Result<String, String> {
+    let duration = Duration::from_secs(duration_secs);
+    if duration.is_zero() || duration > diagnostics::MAX_TRACE_DURATION {
+        return Err(format!(
+            "Trace duration must be between 1 and {} seconds",
+            diagnostics::MAX_TRACE_DURATION.as_secs()
+        ));
+    }
+    let dir = DataDirs::current().diagnostics();
+    let trace = diagnostics::start_trace(duration).await;
+    let path = dir.join(format!("trace-{}.json.gz", chrono::Utc::now().format("%Y%m%dT%H%M%SZ")));
+    let write_path = path.clone();
+    tokio::task::spawn_blocking(move || trace.write_compressed(&write_path))
+        .await
+        .map_err(|e| e.to_string())??;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Write a zip of logs, redacted config and network state to `path` for a
+/// bug report; with `dry_run` only report what it would contain and its size
+#[tauri::command]
+async fn export_diagnostics(
+    state: State<'_, AppState>,
+    path: String,
+    include_paths: Option<bool>,
+    dry_run: Option<bool>,
+) -> Result<diagnostics_bundle::BundleReport, String> {
+    let dht = state.dht.lock().await.as_ref().cloned();
+    let downloads = match state.download_restart.lock().await.as_ref().cloned() {
+        Some(service) => node_commands::list_downloads(&service).await,
+        None => Vec::new(),
+    };
+    let transfers = match state.multi_source_download.lock().await.as_ref().cloned() {
+        Some(service) => service.all_download_progress().await,
+        None => Vec::new(),
+    };
+    let options = diagnostics_bundle::BundleOptions {
+        include_paths: include_paths.unwrap_or(false),
+    };
+    let bundle = diagnostics_bundle::collect(
+        dht.as_deref(),
+        downloads,
+        transfers,
+        &chiral_network::config::ChiralConfig::from_env(),
+        &DataDirs::current(),
+        options,
+    )
+    .await;
+    let dry_run = dry_run.unwrap_or(false);
+    tokio::task::spawn_blocking(move || diagnostics_bundle::export(bundle, Path::new(&path), dry_run))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Peers last known to hold the record `key_hex` stored with `put_replicated`
+#[tauri::command]
+async fn get_dht_replication_status_command(
+    state: State<'_, AppState>,
+    key_hex: String,
+) -> Result<Vec<String>, String> {
+    let key = hex::decode(key_hex.trim()).map_err(|e| format!("Invalid record key: {}", e))?;
+    let dht = state.dht.lock().await.as_ref().cloned();
+    match dht {
+        Some(dht) => Ok(dht.replica_holders(&key).await),
+        None => Ok(Vec::new()),
+    }
+}
+
 #[tauri::command]
 async fn get_dht_events(state: State<'_, AppState>) -> Result<Vec<String>, String> {
     let dht = {
@@ -2002,6 +2381,12 @@ async fn get_dht_events(state: State<'_, AppState>) -> Result<Vec<String>, Strin
                 DhtEvent::PeerDisconnected { peer_id } => {
                     format!("peer_disconnected:{}", peer_id)
                 }
+                DhtEvent::PeerVersionIncompatible {
+                    peer_id,
+                    remote_version,
+                    reason,
+                    ..
+                } => format!("peer_version_incompatible:{}:{}:{}", peer_id, remote_version, reason),
                 DhtEvent::FileDiscovered(meta) => {
                     // Serialize the full metadata object to JSON for the frontend
                     let payload = serde_json::to_string(&meta).unwrap_or_else(|_| "{}".to_string());
@@ -2097,6 +2482,42 @@ async fn get_dht_events(state: State<'_, AppState>) -> Result<Vec<String>, Strin
                     .unwrap_or_else(|_| "{}".to_string());
                     format!("reputation_event:{}", json)
                 }
+                DhtEvent::FileOffer(offer) => format!(
+                    "file_transfer_offer:{}:{}:{}:{}",
+                    offer.transfer_id, offer.peer_id, offer.filename, offer.size
+                ),
+                DhtEvent::FileTransferProgress(progress) => format!(
+                    "file_transfer_progress:{}:{}:{}:{}",
+                    progress.peer_id, progress.filename, progress.bytes_received, progress.total_bytes
+                ),
+                DhtEvent::Call(event) => format!(
+                    "{}:{}:{}",
+                    event.kind.event_name(),
+                    event.session_id,
+                    event.peer_id
+                ),
+                DhtEvent::PeerTyping(typing) => format!(
+                    "peer_typing:{}:{}:{}",
+                    typing.peer_id, typing.channel, typing.typing
+                ),
+                DhtEvent::Channel { channel, envelope } => format!(
+                    "channel:{}:{}",
+                    channel,
+                    serde_json::to_string(&envelope).unwrap_or_default()
+                ),
+                DhtEvent::MessagesRead(batch) => format!(
+                    "messages_read:{}",
+                    serde_json::to_string(&batch).unwrap_or_default()
+                ),
+                DhtEvent::QualityDegraded(degraded) => format!(
+                    "quality_degraded:{}:{}",
+                    degraded.peer_id,
+                    degraded.quality.as_str()
+                ),
+                DhtEvent::MeshHealthDegraded(health) => format!(
+                    "mesh_health_degraded:{}:{}",
+                    health.topic, health.mesh_peers
+                ),
             })
             .collect();
         Ok(mapped)
@@ -3226,20 +3647,8 @@ fn detect_locale() -> String {
 }
 
 #[tauri::command]
-fn get_default_storage_path(app: tauri::AppHandle) -> Result<String, String> {
-    let app_data_dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Could not get app data directory: {}", e))?;
-
-    // Get the parent of app data dir to place storage at user level
-    let user_dir = app_data_dir
-        .parent()
-        .ok_or_else(|| "Failed to get parent directory".to_string())?;
-
-    let storage_path = user_dir.join("Chiral-Network-Storage");
-
-    storage_path
+fn get_default_storage_path() -> Result<String, String> {
+    DataDirs::default_storage()
         .to_str()
         .map(|s| s.to_string())
         .ok_or_else(|| "Failed to convert path to string".to_string())
@@ -3336,12 +3745,15 @@ async fn start_file_transfer_service(
         // Create transfer event bus for unified event emission
         let transfer_event_bus = Arc::new(TransferEventBus::new(app.app_handle().clone()));
         let multi_source_service = MultiSourceDownloadService::new(
-            dht_service,
+            dht_service.clone(),
             webrtc_arc.clone(),
             state.bittorrent_handler.clone(),
             transfer_event_bus,
             state.analytics.clone(),
         );
+        multi_source_service.set_web_seeds_enabled(
+            state.web_seeds_enabled.load(std::sync::atomic::Ordering::Relaxed),
+        );
         let multi_source_arc = Arc::new(multi_source_service);
 
         {
@@ -3367,6 +3779,17 @@ async fn start_file_transfer_service(
         tokio::spawn(async move {
             ms_clone.run().await;
         });
+
+        // Pick up downloads interrupted by the last shutdown, once per app run
+        static DOWNLOADS_RESUMED: std::sync::atomic::AtomicBool =
+            std::sync::atomic::AtomicBool::new(false);
+        if !DOWNLOADS_RESUMED.swap(true, std::sync::atomic::Ordering::SeqCst) {
+            let app_handle = app.clone();
+            let ms_clone = multi_source_arc.clone();
+            tokio::spawn(async move {
+                resume_interrupted_downloads(app_handle, ms_clone, dht_service).await;
+            });
+        }
     }
 
     {
@@ -3384,6 +3807,27 @@ async fn start_file_transfer_service(
     Ok(())
 }
 
+/// How long startup waits for a first peer before resuming downloads anyway
+const RESUME_NETWORK_WAIT: Duration = Duration::from_secs(60);
+
+/// Queue interrupted downloads again once the DHT has peers, and emit
+/// `downloads-resumed` summarizing what was picked up and how
+async fn resume_interrupted_downloads(
+    app: tauri::AppHandle,
+    ms: Arc<MultiSourceDownloadService>,
+    dht: Arc<DhtService>,
+) {
+    let deadline = Instant::now() + RESUME_NETWORK_WAIT;
+    while dht.get_peer_count().await == 0 && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    let resumed = ms.resume_incomplete_downloads().await;
+    if !resumed.is_empty() {
+        info!("Resumed {} interrupted download(s)", resumed.len());
+        let _ = app.emit("downloads-resumed", resumed);
+    }
+}
+
 #[tauri::command]
 async fn upload_file_to_network(
     app: tauri::AppHandle,
@@ -3392,7 +3836,23 @@ async fn upload_file_to_network(
     price: Option<f64>,
     protocol: Option<String>,
     original_file_name: Option<String>,
+    web_seeds: Option<Vec<String>>,
 ) -> Result<(), String> {
+    // Publisher-hosted HTTP(S) copies, advertised alongside the P2P sources
+    let web_seeds = match web_seeds {
+        Some(urls) => {
+            let urls: Vec<String> = urls
+                .into_iter()
+                .map(|url| url.trim().to_string())
+                .filter(|url| !url.is_empty())
+                .collect();
+            for url in &urls {
+                chiral_network::download_source::validate_web_seed(url)?;
+            }
+            Some(urls).filter(|urls| !urls.is_empty())
+        }
+        None => None,
+    };
 
     // Use provided original filename, or extract from path if not provided
     let original_file_name = original_file_name
@@ -3413,6 +3873,8 @@ async fn upload_file_to_network(
     let mut file = tokio::fs::File::open(&file_path).await
         .map_err(|e| format!("Failed to open file for hashing: {}", e))?;
     let mut buffer = vec![0u8; 64 * 1024]; // 64KB chunks for hashing
+    // Web seeds carry a hash of every download chunk
+    let mut chunk_hasher = web_seeds.as_ref().map(|_| ChunkHasher::default());
 
     loop {
         let bytes_read = file.read(&mut buffer).await
@@ -3421,14 +3883,32 @@ async fn upload_file_to_network(
             break;
         }
         hasher.update(&buffer[..bytes_read]);
+        if let Some(chunk_hasher) = chunk_hasher.as_mut() {
+            chunk_hasher.update(&buffer[..bytes_read]);
+        }
     }
 
     let file_hash = format!("{:x}", hasher.finalize());
+    // Signed by the DHT service when the file is published
+    let web_seeds = web_seeds
+        .zip(chunk_hasher)
+        .map(|(urls, chunk_hasher)| WebSeeds::new(urls, chunk_hasher.finish()));
     let permanent_path = state.http_server_state.storage_dir.join(&file_hash);
-
-    // Move/rename temp file to permanent storage instead of copying
-    tokio::fs::rename(&file_path, &permanent_path).await
-        .map_err(|e| format!("Failed to move file to permanent storage: {}", e))?;
+    let source_path = PathBuf::from(&file_path);
+
+    if state.shared_files.contains(&file_hash).await && permanent_path.exists() {
+        // Same content is already stored: keep the single copy and only add a reference
+        info!("Content {} already shared; adding {} as another reference", file_hash, file_path);
+        if source_path != permanent_path {
+            if let Err(e) = tokio::fs::remove_file(&source_path).await {
+                warn!("Failed to remove duplicate upload {}: {}", file_path, e);
+            }
+        }
+    } else {
+        // Move/rename temp file to permanent storage instead of copying
+        tokio::fs::rename(&file_path, &permanent_path).await
+            .map_err(|e| format!("Failed to move file to permanent storage: {}", e))?;
+    }
 
     // Update file_path to point to the permanent location
     let file_path = permanent_path.to_string_lossy().to_string();
@@ -3446,6 +3926,34 @@ async fn upload_file_to_network(
         encrypted: false,
     }).await;
 
+    // Sampled once here; senders only offer wire compression for content that shrinks
+    let compressible = {
+        let path = PathBuf::from(&file_path);
+        let name = original_file_name.clone();
+        tokio::task::spawn_blocking(move || {
+            compression::sample_is_compressible(&path, &name)
+        })
+        .await
+        .ok()
+        .and_then(Result::ok)
+        .unwrap_or(false)
+    };
+
+    // Track in the shared files registry (size/mtime are used to detect later edits)
+    if let Err(e) = state
+        .shared_files
+        .register(
+            file_hash.clone(),
+            permanent_path.clone(),
+            source_path,
+            original_file_name.clone(),
+            protocol.clone(),
+        )
+        .await
+    {
+        warn!("Failed to record shared file {}: {}", file_hash, e);
+    }
+
     // Handle protocol-specific uploads
     if let Some(protocol_name) = &protocol {
         match protocol_name.as_str() {
@@ -3517,10 +4025,12 @@ async fn upload_file_to_network(
                             uploader_address: Some(account),
                             ftp_sources: None,
                             http_sources: None,
+                            web_seeds: web_seeds.clone(),
                             info_hash,
                             trackers: Some(vec!["udp://tracker.openbittorrent.com:80".to_string()]),
                             ed2k_sources: None,
                             download_path: None,
+                            compressible,
                         };
 
                         // Publish metadata to DHT for discoverability
@@ -3533,6 +4043,8 @@ async fn upload_file_to_network(
                             if let Err(e) = dht.publish_file(metadata.clone(), None).await {
                                 warn!("Failed to publish BitTorrent file metadata to DHT: {}", e);
                                 // Don't fail the upload, just log the warning
+                            } else {
+                                state.shared_files.set_announced(&file_hash, true).await;
                             }
                         }
 
@@ -3608,6 +4120,7 @@ async fn upload_file_to_network(
                             uploader_address: Some(account),
                             ftp_sources: None,
                             http_sources: None,
+                            web_seeds: web_seeds.clone(),
                             info_hash: None,
                             trackers: None,
                             ed2k_sources: Some(vec![dht::models::Ed2kSourceInfo {
@@ -3619,6 +4132,7 @@ async fn upload_file_to_network(
                                 timeout: None,
                             }]),
                             download_path: None,
+                            compressible,
                         };
 
                         // Publish metadata to DHT for discoverability
@@ -3631,6 +4145,8 @@ async fn upload_file_to_network(
                             if let Err(e) = dht.publish_file(metadata.clone(), None).await {
                                 warn!("Failed to publish ED2K file metadata to DHT: {}", e);
                                 // Don't fail the upload, just log the warning
+                            } else {
+                                state.shared_files.set_announced(&file_hash, true).await;
                             }
                         }
 
@@ -3697,10 +4213,12 @@ async fn upload_file_to_network(
                                 is_available: true,
                             }]),
                             http_sources: None,
+                            web_seeds: web_seeds.clone(),
                             info_hash: None,
                             trackers: None,
                             ed2k_sources: None,
                             download_path: None,
+                            compressible,
                         };
 
 
@@ -3714,6 +4232,8 @@ async fn upload_file_to_network(
                             if let Err(e) = dht.publish_file(metadata.clone(), None).await {
                                 warn!("Failed to publish FTP file metadata to DHT: {}", e);
                                 // Don't fail the upload, just log the warning
+                            } else {
+                                state.shared_files.set_announced(&file_hash, true).await;
                             }
                         }
 
@@ -3751,7 +4271,13 @@ async fn upload_file_to_network(
                          total_chunks, chunk_size);
 
                 // Start streaming upload session
-                let upload_id = start_streaming_upload(original_file_name.clone(), file_size, state.clone()).await?;
+                let upload_id = start_streaming_upload(
+                    original_file_name.clone(),
+                    file_size,
+                    web_seeds.as_ref().map(|seeds| seeds.urls.clone()),
+                    state.clone(),
+                )
+                .await?;
 
                 // Stream file in chunks
                 let mut file = tokio::fs::File::open(&file_path)
@@ -3892,10 +4418,12 @@ async fn upload_file_to_network(
                 uploader_address: Some(account.clone()),
                 ftp_sources: None,
                 http_sources: None,
+                web_seeds: web_seeds.clone(),
                 info_hash: None,
                 trackers: None,
                 ed2k_sources: None,
                 download_path: None,
+                compressible,
             };
 
             dht.publish_file(metadata.clone(), None).await?;
@@ -3923,7 +4451,10 @@ async fn upload_file_to_network(
             );
 
             match dht.publish_file(metadata.clone(), None).await {
-                Ok(_) => info!("Published file metadata to DHT: {}", file_hash),
+                Ok(_) => {
+                    info!("Published file metadata to DHT: {}", file_hash);
+                    state.shared_files.set_announced(&file_hash, true).await;
+                }
                 Err(e) => warn!("Failed to publish file metadata to DHT: {}", e),
             }
 
@@ -4219,6 +4750,11 @@ async fn download_blocks_from_network(
         dht_guard.as_ref().cloned()
     };
 
+    state
+        .storage
+        .check_transfer_capacity(Path::new(&download_path), file_metadata.file_size)
+        .map_err(|e| e.to_string())?;
+
     if let Some(dht) = dht {
         info!("calling dht download_file");
         dht.download_file(file_metadata, download_path).await
@@ -4227,6 +4763,20 @@ async fn download_blocks_from_network(
     }
 }
 
+/// Complete a download from content we already hold locally (after verifying
+/// it), skipping the network. Returns `false` if the content is not held.
+async fn complete_download_locally(
+    state: &State<'_, AppState>,
+    file_hash: &str,
+    output_path: &str,
+) -> Result<bool, String> {
+    let copied = state
+        .shared_files
+        .copy_local(file_hash, Path::new(output_path))
+        .await?;
+    Ok(copied.is_some())
+}
+
 #[tauri::command]
 async fn download_file_from_network(
     state: State<'_, AppState>,
@@ -4256,6 +4806,10 @@ async fn download_file_from_network(
         return Err("Download failed: Invalid file path".to_string());
     }
 
+    if complete_download_locally(&state, &file_hash, &output_path).await? {
+        return Ok(format!("Download completed from local data: {}", file_hash));
+    }
+
     let ft = {
         let ft_guard = state.file_transfer.lock().await;
         ft_guard.as_ref().cloned()
@@ -4286,6 +4840,11 @@ async fn download_file_from_network(
                         metadata.file_name, metadata.file_size
                     );
 
+                    state
+                        .storage
+                        .check_transfer_capacity(path, metadata.file_size)
+                        .map_err(|e| format!("Download failed: {}", e))?;
+
                     // Implement peer discovery for file chunks
                     info!(
                         "Discovering peers for file: {} with {} known seeders",
@@ -4618,6 +5177,7 @@ async fn copy_file_to_temp(file_path: String) -> Result<String, String> {
 async fn start_streaming_upload(
     file_name: String,
     file_size: u64,
+    web_seeds: Option<Vec<String>>,
     state: State<'_, AppState>,
 ) -> Result<String, String> {
     // Check for active account - require login for all uploads
@@ -4650,6 +5210,8 @@ async fn start_streaming_upload(
             created_at: std::time::SystemTime::now(),
             chunk_cids: Vec::new(),
             file_data: Vec::new(),
+            chunk_hasher: web_seeds.as_ref().map(|_| ChunkHasher::default()),
+            web_seeds,
         },
     );
 
@@ -4671,6 +5233,9 @@ async fn upload_file_chunk(
 
     // Update hasher with chunk data
     session.hasher.update(&chunk_data);
+    if let Some(chunk_hasher) = session.chunk_hasher.as_mut() {
+        chunk_hasher.update(&chunk_data);
+    }
     session.received_chunks += 1;
 
     // Store chunk directly in Bitswap (if DHT is available)
@@ -4754,9 +5319,15 @@ async fn upload_file_chunk(
             uploader_address: None,
             ftp_sources: None,
             http_sources: None,
+            web_seeds: session
+                .web_seeds
+                .take()
+                .zip(session.chunk_hasher.take())
+                .map(|(urls, chunk_hasher)| WebSeeds::new(urls, chunk_hasher.finish())),
             info_hash: None,
             trackers: None,
             ed2k_sources: None,
+            compressible: false,
         };
 
         // Clean up session - rely entirely on Bitswap for distribution
@@ -4814,6 +5385,11 @@ async fn init_streaming_download(
     let session_id = format!("dl-{}-{}", file_hash.chars().take(8).collect::<String>(),
         SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis());
 
+    state
+        .storage
+        .check_transfer_capacity(Path::new(&output_path), file_size)
+        .map_err(|e| e.to_string())?;
+
     // Create temp file path
     let temp_path = std::path::PathBuf::from(&output_path)
         .with_extension("chiral_partial");
@@ -4926,8 +5502,11 @@ async fn finalize_streaming_download(
     }
 
     // Rename temp file to final destination
-    tokio::fs::rename(&session.temp_path, &session.output_path)
+    let temp_path = session.temp_path.clone();
+    let output_path = PathBuf::from(&session.output_path);
+    tokio::task::spawn_blocking(move || storage::promote_partial(&temp_path, &output_path))
         .await
+        .map_err(|e| format!("Failed to finalize download: {}", e))?
         .map_err(|e| format!("Failed to finalize download: {}", e))?;
 
     info!("Finalized streaming download: {} -> {}", session_id, session.output_path);
@@ -5186,15 +5765,32 @@ async fn start_multi_source_download(
     output_path: String,
     max_peers: Option<usize>,
     chunk_size: Option<usize>,
+    sequential: Option<bool>,
+    probe_providers: Option<bool>,
+    overwrite: Option<bool>,
+    encrypt_partials: Option<bool>,
 ) -> Result<String, String> {
     let ms = {
         let ms_guard = state.multi_source_download.lock().await;
         ms_guard.as_ref().cloned()
     };
 
+    if complete_download_locally(&state, &file_hash, &output_path).await? {
+        return Ok(format!("Download completed from local data: {}", file_hash));
+    }
+
     if let Some(multi_source_service) = ms {
         multi_source_service
-            .start_download(file_hash.clone(), output_path, max_peers, chunk_size)
+            .start_download(
+                file_hash.clone(),
+                output_path,
+                max_peers,
+                chunk_size,
+                sequential.unwrap_or(false),
+                probe_providers,
+                overwrite.unwrap_or(false),
+                encrypt_partials,
+            )
             .await?;
 
         Ok(format!("Multi-source download started for: {}", file_hash))
@@ -5231,12 +5827,178 @@ async fn get_multi_source_progress(
     };
 
     if let Some(multi_source_service) = ms {
-        Ok(multi_source_service.get_download_progress(&file_hash).await)
+        let mut progress = multi_source_service.get_download_progress(&file_hash).await;
+        if let Some(progress) = progress.as_mut() {
+            progress.rate_limit = state.bandwidth.get_transfer_limits(&file_hash).await;
+        }
+        Ok(progress)
     } else {
         Err("Multi-source download service not available".to_string())
     }
 }
 
+/// Readmit providers excluded from a transfer after serving corrupt chunks.
+/// Returns how many were cleared.
+#[tauri::command]
+async fn clear_source_exclusions(
+    state: State<'_, AppState>,
+    transfer_id: String,
+) -> Result<usize, String> {
+    let ms = {
+        let ms_guard = state.multi_source_download.lock().await;
+        ms_guard.as_ref().cloned()
+    };
+
+    if let Some(multi_source_service) = ms {
+        Ok(multi_source_service.clear_source_exclusions(&transfer_id).await)
+    } else {
+        Err("Multi-source download service not available".to_string())
+    }
+}
+
+/// Allow or forbid HTTP(S) web seeds as download sources
+#[tauri::command]
+async fn set_web_seeds_enabled(state: State<'_, AppState>, enabled: bool) -> Result<(), String> {
+    state
+        .web_seeds_enabled
+        .store(enabled, std::sync::atomic::Ordering::Relaxed);
+    if let Some(multi_source_service) = state.multi_source_download.lock().await.as_ref() {
+        multi_source_service.set_web_seeds_enabled(enabled);
+    }
+    Ok(())
+}
+
+/// Unfinished downloads that survive a restart
+#[tauri::command]
+async fn get_resumable_downloads(
+    state: State<'_, AppState>,
+) -> Result<Vec<download_resume::ResumeRecord>, String> {
+    let ms = {
+        let ms_guard = state.multi_source_download.lock().await;
+        ms_guard.as_ref().cloned()
+    };
+    match ms {
+        Some(multi_source_service) => Ok(multi_source_service.resumable_downloads().await),
+        None => Err("Multi-source download service not available".to_string()),
+    }
+}
+
+/// Choose whether an unfinished download is queued again on the next startup
+#[tauri::command]
+async fn set_download_auto_resume(
+    state: State<'_, AppState>,
+    file_hash: String,
+    auto_resume: bool,
+) -> Result<(), String> {
+    let ms = {
+        let ms_guard = state.multi_source_download.lock().await;
+        ms_guard.as_ref().cloned()
+    };
+    match ms {
+        Some(multi_source_service) => {
+            multi_source_service
+                .set_auto_resume(&file_hash, auto_resume)
+                .await
+        }
+        None => Err("Multi-source download service not available".to_string()),
+    }
+}
+
+/// Switch a multi-source download between in-order (streaming) and parallel fetching
+#[tauri::command]
+async fn set_transfer_sequential(
+    state: State<'_, AppState>,
+    file_hash: String,
+    sequential: bool,
+) -> Result<(), String> {
+    let ms = {
+        let ms_guard = state.multi_source_download.lock().await;
+        ms_guard.as_ref().cloned()
+    };
+
+    if let Some(multi_source_service) = ms {
+        multi_source_service.set_sequential(&file_hash, sequential).await
+    } else {
+        Err("Multi-source download service not available".to_string())
+    }
+}
+
+/// Read verified bytes of an in-progress download. Returns `None` when the
+/// covering chunks have not arrived within `wait_ms` (capped at 5s).
+#[tauri::command]
+async fn read_transfer_range(
+    state: State<'_, AppState>,
+    id: String,
+    offset: u64,
+    len: u64,
+    wait_ms: Option<u64>,
+) -> Result<Option<Vec<u8>>, String> {
+    let ms = {
+        let ms_guard = state.multi_source_download.lock().await;
+        ms_guard.as_ref().cloned()
+    };
+
+    let multi_source_service =
+        ms.ok_or_else(|| "Multi-source download service not available".to_string())?;
+    let wait = Duration::from_millis(wait_ms.unwrap_or(500).min(5000));
+    match multi_source_service.read_range(&id, offset, len, wait).await? {
+        RangeRead::Ready(data) => Ok(Some(data)),
+        RangeRead::NotYetAvailable { .. } => Ok(None),
+    }
+}
+
+/// Download `len` bytes of `content_hash` starting at `offset` into `dest`,
+/// transferring only the chunks that cover the range. Returns the bytes written.
+#[tauri::command]
+async fn download_range(
+    state: State<'_, AppState>,
+    content_hash: String,
+    offset: u64,
+    len: u64,
+    dest: String,
+) -> Result<u64, String> {
+    let data = match state.shared_files.get(&content_hash).await {
+        Some(entry) if !entry.status.is_stale() => {
+            read_local_range(&entry.path, offset, len).await?
+        }
+        _ => {
+            let ms = {
+                let ms_guard = state.multi_source_download.lock().await;
+                ms_guard.as_ref().cloned()
+            };
+            ms.ok_or_else(|| "Multi-source download service not available".to_string())?
+                .fetch_range(&content_hash, offset, len)
+                .await?
+        }
+    };
+
+    tokio::fs::write(&dest, &data)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", dest, e))?;
+    Ok(data.len() as u64)
+}
+
+async fn read_local_range(path: &Path, offset: u64, len: u64) -> Result<Vec<u8>, String> {
+    use tokio::io::AsyncSeekExt;
+
+    let size = tokio::fs::metadata(path)
+        .await
+        .map_err(|e| format!("Failed to stat {}: {}", path.display(), e))?
+        .len();
+    chiral_network::byte_range::validate_range(offset, len, size)?;
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    file.seek(std::io::SeekFrom::Start(offset))
+        .await
+        .map_err(|e| format!("Failed to seek {}: {}", path.display(), e))?;
+    let mut data = vec![0u8; len as usize];
+    file.read_exact(&mut data)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(data)
+}
+
 #[tauri::command]
 async fn update_proxy_latency(
     state: State<'_, AppState>,
@@ -5281,6 +6043,7 @@ async fn download_file_multi_source(
     output_path: String,
     prefer_multi_source: Option<bool>,
     max_peers: Option<usize>,
+    overwrite: Option<bool>,
 ) -> Result<String, String> {
     let prefer_multi_source = prefer_multi_source.unwrap_or(true);
 
@@ -5292,9 +6055,21 @@ async fn download_file_multi_source(
         };
 
         if let Some(multi_source_service) = ms {
+            if complete_download_locally(&state, &file_hash, &output_path).await? {
+                return Ok(format!("Download completed from local data: {}", file_hash));
+            }
             info!("Using multi-source download for file: {}", file_hash);
             return multi_source_service
-                .start_download(file_hash.clone(), output_path, max_peers, None)
+                .start_download(
+                    file_hash.clone(),
+                    output_path,
+                    max_peers,
+                    None,
+                    false,
+                    None,
+                    overwrite.unwrap_or(false),
+                    None,
+                )
                 .await
                 .map(|_| format!("Multi-source download initiated for: {}", file_hash));
         }
@@ -5929,6 +6704,18 @@ async fn get_peer_metrics(
     }
 }
 
+#[tauri::command]
+async fn get_full_peer_info(
+    state: State<'_, AppState>,
+) -> Result<Vec<monitoring::FullPeerInfo>, String> {
+    let dht_guard = state.dht.lock().await;
+    if let Some(ref dht) = *dht_guard {
+        Ok(dht.get_full_peer_info().await)
+    } else {
+        Err("DHT service not available".to_string())
+    }
+}
+
 #[tauri::command]
 async fn report_malicious_peer(
     peer_id: String,
@@ -6233,12 +7020,21 @@ async fn get_transaction_queue_status(
     }))
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BandwidthStatsResponse {
+    #[serde(flatten)]
+    stats: analytics::BandwidthStats,
+    schedule: BandwidthScheduleStatus,
+}
+
 // Analytics commands
 #[tauri::command]
-async fn get_bandwidth_stats(
-    state: State<'_, AppState>,
-) -> Result<analytics::BandwidthStats, String> {
-    Ok(state.analytics.get_bandwidth_stats().await)
+async fn get_bandwidth_stats(state: State<'_, AppState>) -> Result<BandwidthStatsResponse, String> {
+    Ok(BandwidthStatsResponse {
+        stats: state.analytics.get_bandwidth_stats().await,
+        schedule: state.bandwidth_schedule.status().await,
+    })
 }
 
 #[tauri::command]
@@ -6285,19 +7081,10 @@ async fn reset_analytics(state: State<'_, AppState>) -> Result<(), String> {
 }
 
 // Logger configuration commands
-/// Saves application settings to a JSON file in the app data directory
+/// Saves application settings to a JSON file in the data directory
 #[tauri::command]
-async fn save_app_settings(app: tauri::AppHandle, settings_json: String) -> Result<(), String> {
-    let app_data_dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Failed to get app data directory: {}", e))?;
-
-    // Ensure the directory exists
-    std::fs::create_dir_all(&app_data_dir)
-        .map_err(|e| format!("Failed to create app data directory: {}", e))?;
-
-    let settings_file = app_data_dir.join("settings.json");
+async fn save_app_settings(settings_json: String) -> Result<(), String> {
+    let settings_file = DataDirs::current().settings_file();
 
     std::fs::write(&settings_file, settings_json)
         .map_err(|e| format!("Failed to write settings file: {}", e))?;
@@ -6321,17 +7108,14 @@ async fn save_app_settings(app: tauri::AppHandle, settings_json: String) -> Resu
 async fn update_log_config(
     app: tauri::AppHandle,
     max_log_size_mb: u64,
+    max_log_files: Option<usize>,
     enabled: bool,
     state: State<'_, AppState>,
 ) -> Result<(), String> {
-    // Get the app data directory (not the user's storage directory)
-    let app_data_dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Failed to get app data directory: {}", e))?;
-
-    let logs_dir = app_data_dir.join("logs");
-    let config = logger::LogConfig::new(&logs_dir, max_log_size_mb, enabled);
+    // The data directory, not the user's storage directory
+    let logs_dir = DataDirs::current().logs();
+    let config = logger::LogConfig::new(&logs_dir, max_log_size_mb, enabled)
+        .with_max_files(max_log_files.unwrap_or(logger::DEFAULT_MAX_LOG_FILES));
 
     let logger_lock = state.file_logger.lock().await;
     if let Some(ref writer) = *logger_lock {
@@ -6357,15 +7141,33 @@ async fn update_log_config(
 
 /// Get the directory where logs are stored
 #[tauri::command]
-fn get_logs_directory(app: tauri::AppHandle) -> Result<String, String> {
-    let app_data_dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Failed to get app data directory: {}", e))?;
-
-    let logs_dir = app_data_dir.join("logs");
+fn get_logs_directory() -> Result<String, String> {
+    let logs_dir = DataDirs::current().logs();
     Ok(logs_dir.to_string_lossy().to_string())
 }
+
+/// The data directory this instance runs with
+#[tauri::command]
+fn get_data_dir_command() -> String {
+    DataDirs::current().root().to_string_lossy().into_owned()
+}
+
+/// Use `path` as the data directory from the next start, moving the state
+/// there first when `move_state` is set
+#[tauri::command]
+fn set_data_dir_command(path: String, move_state: bool) -> Result<(), String> {
+    DataDirs::current().set_data_dir(&DataDirs::default_root(), Path::new(&path), move_state)
+}
+
+/// Get the file currently written to, if file logging is enabled
+#[tauri::command]
+async fn get_log_file_path(state: State<'_, AppState>) -> Result<Option<String>, String> {
+    let logger_lock = state.file_logger.lock().await;
+    Ok(logger_lock
+        .as_ref()
+        .and_then(|writer| writer.current_log_file_path())
+        .map(|path| path.to_string_lossy().to_string()))
+}
 #[tauri::command]
 async fn reset_network_services(state: State<'_, AppState>) -> Result<(), String> {
     // Stop DHT if running
@@ -6662,10 +7464,7 @@ async fn start_download_restart(
 ) -> Result<String, String> {
     let dr_guard = state.download_restart.lock().await;
     if let Some(ref service) = *dr_guard {
-        service
-            .start_download(request)
-            .await
-            .map_err(|e| e.to_string())
+        node_commands::start_download(service, request).await
     } else {
         Err("Download restart service not initialized".to_string())
     }
@@ -6678,10 +7477,7 @@ async fn pause_download_restart(
 ) -> Result<(), String> {
     let dr_guard = state.download_restart.lock().await;
     if let Some(ref service) = *dr_guard {
-        service
-            .pause_download(&download_id)
-            .await
-            .map_err(|e| e.to_string())
+        node_commands::pause_download(service, &download_id).await
     } else {
         Err("Download restart service not initialized".to_string())
     }
@@ -6694,10 +7490,7 @@ async fn resume_download_restart(
 ) -> Result<(), String> {
     let dr_guard = state.download_restart.lock().await;
     if let Some(ref service) = *dr_guard {
-        service
-            .resume_download(&download_id)
-            .await
-            .map_err(|e| e.to_string())
+        node_commands::resume_download(service, &download_id).await
     } else {
         Err("Download restart service not initialized".to_string())
     }
@@ -6710,10 +7503,7 @@ async fn get_download_status_restart(
 ) -> Result<download_restart::DownloadStatus, String> {
     let dr_guard = state.download_restart.lock().await;
     if let Some(ref service) = *dr_guard {
-        service
-            .get_status(&download_id)
-            .await
-            .map_err(|e| e.to_string())
+        node_commands::download_status(service, &download_id).await
     } else {
         Err("Download restart service not initialized".to_string())
     }
@@ -6727,33 +7517,80 @@ fn main() {
     // Parse command line arguments
     use clap::Parser;
     let args = headless::CliArgs::parse();
+    // Before anything derives a path from the data directory
+    let data_dir_given = args.data_dir.is_some();
+    if let Some(dir) = &args.data_dir {
+        DataDirs::new(dir).install();
+    }
+
+    if args.status {
+        std::process::exit(headless::print_status(&args));
+    }
+
+    if args.print_peer_id || args.print_multiaddrs {
+        std::process::exit(headless::print_identity(&args));
+    }
+
+    if args.dump_config {
+        match headless::load_config(&args) {
+            Ok(config) => print!("{}", config.to_redacted_toml()),
+            Err(e) => {
+                eprintln!("Invalid configuration: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
     // For headless mode, initialize basic console logging
     if args.headless {
-        use tracing_subscriber::{fmt, prelude::*, EnvFilter};
-        let mut filter = EnvFilter::from_default_env();
+        let config = match headless::load_config(&args) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Invalid configuration: {}", e);
+                std::process::exit(1);
+            }
+        };
 
-        // Add directives with safe fallback
-        if let Ok(directive) = "chiral_network=info".parse() {
-            filter = filter.add_directive(directive);
-        }
-        if let Ok(directive) = "libp2p=warn".parse() {
-            filter = filter.add_directive(directive);
-        }
-        if let Ok(directive) = "libp2p_kad=warn".parse() {
-            filter = filter.add_directive(directive);
-        }
-        if let Ok(directive) = "libp2p_swarm=warn".parse() {
-            filter = filter.add_directive(directive);
-        }
-        if let Ok(directive) = "libp2p_mdns=warn".parse() {
-            filter = filter.add_directive(directive);
-        }
+        use tracing_subscriber::prelude::*;
+        // Reloadable so SIGHUP can change `logging.level`
+        let (filter, filter_handle) =
+            tracing_subscriber::reload::Layer::new(headless::log_filter(&config.logging.level));
+
+        let mut _file_log_guard = None;
+        // Reported once the subscriber is installed
+        let mut file_log_outcome = None;
+        let file_layer = if config.logging.file {
+            let logs_dir = headless::logs_dir(&args);
+            let log_config = logger::LogConfig::new(&logs_dir, config.logging.max_file_size_mb, true)
+                .with_max_files(config.logging.max_files);
+            match logger::RotatingFileWriter::new(log_config) {
+                Ok(writer) => {
+                    let (non_blocking, guard) =
+                        tracing_appender::non_blocking(logger::ThreadSafeWriter::new(writer));
+                    _file_log_guard = Some(guard);
+                    file_log_outcome = Some(Ok(logs_dir));
+                    Some(config.logging.format.layer(non_blocking))
+                }
+                Err(e) => {
+                    file_log_outcome = Some(Err(e));
+                    None
+                }
+            }
+        } else {
+            None
+        };
 
         tracing_subscriber::registry()
-            .with(fmt::layer())
             .with(filter)
+            .with(config.logging.format.layer(std::io::stdout))
+            .with(file_layer)
             .init();
+        match file_log_outcome {
+            Some(Ok(logs_dir)) => info!("Writing logs to {}", logs_dir.display()),
+            Some(Err(e)) => warn!("File logging disabled: {}", e),
+            None => {}
+        }
 
         println!("Running in headless mode...");
 
@@ -6761,29 +7598,115 @@ fn main() {
         let runtime = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
 
         // Run the headless mode
-        if let Err(e) = runtime.block_on(headless::run_headless(args)) {
-            eprintln!("Error in headless mode: {}", e);
-            std::process::exit(1);
+        let code = match runtime.block_on(headless::run_headless(args, config, filter_handle)) {
+            Ok(()) => 0,
+            Err(e) if e.is::<headless::GracePeriodExpired>() => {
+                eprintln!("{}", e);
+                headless::EXIT_GRACE_PERIOD_EXPIRED
+            }
+            Err(e) if e.is::<headless::RunFailed>() => {
+                eprintln!("{}", e);
+                headless::EXIT_RUN_FAILED
+            }
+            Err(e) if e.is::<StartupError>() => {
+                // With what to do about it, as the desktop app shows it
+                if let Some(e) = e.downcast_ref::<StartupError>() {
+                    eprintln!("{}", e.message());
+                }
+                1
+            }
+            Err(e) => {
+                eprintln!("Error in headless mode: {}", e);
+                1
+            }
+        };
+        // Exit without waiting on tasks that are still running in the runtime
+        drop(_file_log_guard);
+        std::process::exit(code);
+    }
+
+    // The data directory chosen in the settings, with any state it was
+    // moved from; --data-dir overrides the choice. Logged once the logger
+    // is up in `setup`.
+    let mut startup_notes: Vec<Result<String, String>> = Vec::new();
+    let note_migration =
+        |notes: &mut Vec<Result<String, String>>, from: &str, report: data_dirs::MigrationReport| {
+            if !report.moved.is_empty() {
+                notes.push(Ok(format!(
+                    "Moved {:?} from {} into {} ({:?} already there were kept)",
+                    report.moved,
+                    from,
+                    DataDirs::current().root().display(),
+                    report.skipped
+                )));
+            }
+        };
+    if !data_dir_given {
+        match DataDirs::resolve_desktop(&DataDirs::default_root()) {
+            Ok((dirs, migration)) => {
+                dirs.install();
+                if let Some(report) = migration {
+                    note_migration(&mut startup_notes, "the previous data directory", report);
+                }
+            }
+            Err(e) => startup_notes.push(Err(format!(
+                "Using {}: {}",
+                DataDirs::current().root().display(),
+                e
+            ))),
+        }
+    }
+    // Startup failures from here on are shown in a dialog
+    let context = tauri::generate_context!();
+    let data_dirs = DataDirs::current();
+    if let Err(detail) = data_dirs.create() {
+        exit_with_startup_error(
+            context,
+            StartupError::DataDirUnavailable {
+                path: data_dirs.root().to_path_buf(),
+                detail,
+            },
+        );
+    }
+    // Per directory: instances and headless nodes with other data
+    // directories run side by side
+    let _instance_lock = match InstanceLock::acquire(data_dirs.root()) {
+        Ok(lock) => lock,
+        Err(e) => exit_with_startup_error(context, e.into()),
+    };
+    // Releases before the data directory kept chunk storage, logs,
+    // settings and 2FA secrets in Tauri's app data directory
+    if let Some(legacy) = directories::BaseDirs::new()
+        .map(|base| base.data_dir().join(context.config().identifier.as_str()))
+        .filter(|legacy| !data_dir_given && legacy.is_dir())
+    {
+        match data_dirs::migrate(&legacy, data_dirs.root()) {
+            Ok(report) => {
+                note_migration(&mut startup_notes, &legacy.display().to_string(), report)
+            }
+            Err(e) => startup_notes.push(Err(e)),
         }
-        return;
     }
 
     let runtime = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
 
     // --- Initialize DHT Service at startup ---
+    let port = 4001; // Default port, can be configured
     let dht_service_arc = runtime.block_on(async {
         // These settings can be moved to a config file later
         let bootstrap_nodes = get_bootstrap_nodes();
-        let port = 4001; // Default port, can be configured
         let is_bootstrap = false;
         let enable_autonat = true;
         let enable_autorelay = true;
 
-        let proj_dirs = ProjectDirs::from("com", "chiral-network", "chiral-network")
-            .ok_or("Failed to get project directories").unwrap();
-        let blockstore_db_path = proj_dirs.data_dir().join("blockstore_db");
+        let blockstore_db_path = DataDirs::current().blockstore();
         let async_blockstore_path = async_std::path::Path::new(blockstore_db_path.as_os_str());
 
+        startup_error::check_desktop_config(&chiral_network::config::ChiralConfig::from_env())?;
+        // Probed first, so a busy port is reported as such whatever error
+        // the transport would give
+        chiral_network::listen_ports::resolve_dht_port(port, false)
+            .map_err(|_| StartupError::PortInUse { port })?;
         let dht_service = DhtService::new(
             port,
             bootstrap_nodes,
@@ -6806,10 +7729,15 @@ fn main() {
             None,
         )
         .await
-        .expect("Failed to create DHT service at startup");
+        // The startup node has a random identity, read from no file
+        .map_err(|e| StartupError::from_dht_error(e.as_ref(), port, None))?;
 
-        Arc::new(dht_service)
+        Ok::<_, StartupError>(Arc::new(dht_service))
     });
+    let dht_service_arc = match dht_service_arc {
+        Ok(dht_service) => dht_service,
+        Err(e) => exit_with_startup_error(context, e),
+    };
 
     // Store DHT service and related data for later use in setup()
     let dht_service_for_bt = dht_service_arc.clone();
@@ -6827,17 +7755,7 @@ fn main() {
             format!("-{}", instance_id)
         };
 
-        let download_dir =
-            directories::ProjectDirs::from("com", "chiral-network", "chiral-network")
-                .map(|dirs| {
-                    dirs.data_dir()
-                        .join(format!("downloads{}", instance_suffix))
-                })
-                .unwrap_or_else(|| {
-                    std::env::current_dir()
-                        .unwrap()
-                        .join(format!("downloads{}", instance_suffix))
-                });
+        let download_dir = DataDirs::current().downloads(&instance_suffix);
 
         if let Err(e) = std::fs::create_dir_all(&download_dir) {
             eprintln!("Failed to create download directory: {}", e);
@@ -6942,8 +7860,56 @@ fn main() {
         Ok(verdicts)
     }
 
+    let shared_files_registry = Arc::new(shared_files::SharedFilesRegistry::load(
+        shared_files::SharedFilesRegistry::default_path(),
+    ));
+    shared_files_registry
+        .set_verify_rate(chiral_network::config::ChiralConfig::from_env().uploads.verify_rate());
+    // One upload slot pool for the HTTP and WebRTC serving paths
+    let upload_slot_limiter = upload_slots::UploadSlotLimiter::new(
+        chiral_network::config::ChiralConfig::from_env().uploads.slot_config(),
+    )
+    .with_shared_files(shared_files_registry.clone());
+    let storage_manager = Arc::new(storage::StorageManager::new(
+        storage::StorageManager::default_settings_path(),
+        DataDirs::current().files(),
+        directories::UserDirs::new()
+            .and_then(|dirs| dirs.download_dir().map(|d| d.to_path_buf()))
+            .unwrap_or_else(|| std::env::current_dir().unwrap().join("downloads")),
+    ));
+    let message_store = Arc::new(
+        messaging::MessageStore::open(&messaging::MessageStore::default_path())
+            .or_else(|e| {
+                warn!("Message history unavailable, keeping it in memory: {}", e);
+                messaging::MessageStore::open_in_memory()
+            })
+            .expect("in-memory message store"),
+    );
+    let transfer_history_store = Arc::new(transfer_history::TransferHistory::load(
+        transfer_history::TransferHistory::default_path(),
+        transfer_history::RetentionPolicy::default(),
+    ));
+    let bandwidth_controller = Arc::new(BandwidthController::new());
+
     tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
+        .manage(transfer_history_store)
+        .manage(TransferRates::default())
+        .manage(chiral_events::EventSubscriptions::default())
+        .manage(upload_slot_limiter.clone())
+        .manage(Mutex::new(RateLimiter::default()))
+        .manage(Mutex::new(messaging::RetransmissionQueue::new()))
+        .manage(Mutex::new(search_ranking::ProviderCache::new()))
+        .manage(Mutex::new(discovery::BootstrapNodeMonitor::new()))
+        .manage(Mutex::new(discovery::BootstrapNodePruner::new(
+            chiral_network::config::ChiralConfig::from_env().swarm.bootstrap_prune_after_failures,
+        )))
+        .manage(Arc::new(geolocation::PeerGeolocation::new(
+            DataDirs::current().geoip_database(),
+        )))
+        .manage(relay_consent::TrustedPeers::shared())
+        .manage(WatchDirState::load(watch_dir::default_path()))
+        .manage(message_store)
         .manage(AppState {
             geth: Mutex::new(GethProcess::new()),
             downloader: Arc::new(GethDownloader::new()),
@@ -6955,6 +7921,9 @@ fn main() {
             file_transfer: Mutex::new(None),
             webrtc: Mutex::new(None),
             multi_source_download: Mutex::new(None),
+            web_seeds_enabled: std::sync::atomic::AtomicBool::new(
+                chiral_network::config::ChiralConfig::from_env().downloads.web_seeds,
+            ),
             keystore: Arc::new(Mutex::new(
                 Keystore::load().unwrap_or_else(|_| Keystore::new()),
             )),
@@ -6964,7 +7933,8 @@ fn main() {
             multi_source_pump: Mutex::new(None),
             socks5_proxy_cli: Mutex::new(args.socks5_proxy),
             analytics: Arc::new(analytics::AnalyticsService::new()),
-            bandwidth: Arc::new(BandwidthController::new()),
+            bandwidth: bandwidth_controller.clone(),
+            bandwidth_schedule: Arc::new(BandwidthScheduler::new(bandwidth_controller)),
 
             // Initialize transaction queue
             transaction_queue: Arc::new(Mutex::new(VecDeque::new())),
@@ -6983,11 +7953,10 @@ fn main() {
             // Initialize HTTP server state (uses same storage as FileTransferService)
             http_server_state: Arc::new(http_server::HttpServerState::new({
                 // Use same storage directory as FileTransferService (files/, not chunks/)
-                use directories::ProjectDirs;
-                ProjectDirs::from("com", "chiral-network", "chiral-network")
-                    .map(|dirs| dirs.data_dir().join("files"))
-                    .unwrap_or_else(|| std::env::current_dir().unwrap().join("files"))
-            })),
+                DataDirs::current().files()
+            })
+            .with_shared_files(shared_files_registry.clone())
+            .with_upload_slots(upload_slot_limiter)),
             http_server_addr: Arc::new(Mutex::new(None)),
             http_server_shutdown: Arc::new(Mutex::new(None)),
 
@@ -7023,6 +7992,10 @@ fn main() {
 
             // Download restart service (will be initialized in setup)
             download_restart: Mutex::new(None),
+
+            shared_files: shared_files_registry,
+
+            storage: storage_manager,
         })
         .invoke_handler(tauri::generate_handler![
             create_chiral_account,
@@ -7101,12 +8074,27 @@ fn main() {
             search_file_metadata,
             get_file_seeders,
             connect_to_peer,
+            get_peer_event_history_command,
+            get_node_announcements_command,
+            find_peer_addresses,
             get_dht_events,
             detect_locale,
             get_default_storage_path,
             check_directory_exists,
             ensure_directory_exists,
             get_dht_health,
+            get_port_forwarding_status_command,
+            get_network_stats_command,
+            get_dashboard_stats_command,
+            get_mesh_health_command,
+            get_detailed_network_stats_command,
+            get_transport_stats_command,
+            get_crypto_audit_log_command,
+            start_trace_command,
+            export_diagnostics,
+            tail_logs,
+            set_log_streaming,
+            get_dht_replication_status_command,
             get_dht_peer_count,
             get_dht_peer_id,
             get_peer_id,
@@ -7115,11 +8103,20 @@ fn main() {
             start_file_transfer_service,
             download_file_from_network,
             upload_file_to_network,
+            publish_directory,
+            download_bundle,
             start_ftp_download,
             download_blocks_from_network,
             start_multi_source_download,
             cancel_multi_source_download,
             get_multi_source_progress,
+            clear_source_exclusions,
+            set_web_seeds_enabled,
+            set_transfer_sequential,
+            get_resumable_downloads,
+            set_download_auto_resume,
+            read_transfer_range,
+            download_range,
             update_proxy_latency,
             get_proxy_optimization_status,
             download_file_multi_source,
@@ -7142,10 +8139,34 @@ fn main() {
             proxy_disconnect,
             proxy_remove,
             proxy_echo,
+            send_direct_message,
+            join_message_channel,
+            react_to_message,
+            get_message_reactions_command,
+            publish_message_command,
+            set_topic_filter_command,
+            get_thread_command,
+            mark_messages_read,
             list_proxies,
             enable_privacy_routing,
             disable_privacy_routing,
             get_bootstrap_nodes_command,
+            get_bootstrap_node_status,
+            get_bootstrap_contribution_stats_command,
+            get_quarantined_bootstrap_nodes_command,
+            restore_quarantined_bootstrap_command,
+            add_filter_rule_command,
+            remove_filter_rule_command,
+            get_filter_rules_command,
+            list_connected_peers,
+            get_peer_locations,
+            set_peer_geolocation_enabled,
+            list_trusted_peers,
+            add_trusted_peer,
+            remove_trusted_peer,
+            set_watch_directory,
+            get_watch_status,
+            get_protocol_versions_command,
             generate_totp_secret,
             is_2fa_enabled,
             verify_and_enable_totp,
@@ -7156,6 +8177,7 @@ fn main() {
             record_transfer_success,
             record_transfer_failure,
             get_peer_metrics,
+            get_full_peer_info,
             report_malicious_peer,
             select_peers_with_strategy,
             set_peer_encryption_support,
@@ -7163,6 +8185,10 @@ fn main() {
             upload_file,
             test_backend_connection,
             set_bandwidth_limits,
+            set_bandwidth_schedule,
+            override_bandwidth_limits,
+            resume_bandwidth_schedule,
+            set_transfer_rate_limit,
             establish_webrtc_connection,
             send_webrtc_file_request,
             get_webrtc_connection_status,
@@ -7235,6 +8261,9 @@ fn main() {
             save_app_settings,
             update_log_config,
             get_logs_directory,
+            get_data_dir_command,
+            set_data_dir_command,
+            get_log_file_path,
             check_directory_exists,
             get_multiaddresses,
             clear_seed_list,
@@ -7243,7 +8272,42 @@ fn main() {
             start_download_restart,
             pause_download_restart,
             resume_download_restart,
-            get_download_status_restart
+            get_download_status_restart,
+            // Shared files registry commands
+            list_shared_files,
+            unshare_file,
+            reverify_shared_file,
+            verify_shared_file,
+            repair_shared_file,
+            get_seeding_limits,
+            set_seeding_limits,
+            set_content_seeding_limits,
+            get_upload_slot_stats,
+            set_upload_peer_trusted,
+            // Direct file transfer
+            send_file_to_peer,
+            accept_file_transfer,
+            reject_file_transfer,
+            get_transfer_compression_stats,
+            start_call,
+            accept_call,
+            reject_call,
+            end_call,
+            get_active_calls,
+            start_typing,
+            stop_typing,
+            // Storage management commands
+            get_storage_settings,
+            update_storage_settings,
+            get_storage_usage,
+            cleanup_storage,
+            search_files,
+            // Transfer history commands
+            get_transfer_history,
+            clear_transfer_history,
+            // Unified event subscription
+            subscribe_events,
+            unsubscribe_events
         ])
         .plugin(tauri_plugin_process::init())
         .plugin(tauri_plugin_os::init())
@@ -7261,12 +8325,12 @@ fn main() {
                 }
             }
         })
-        .setup(|app| {
+        .setup(move |app| {
             // Load settings from disk
-            let settings = load_settings_from_file(&app.handle());
+            let settings = load_settings_from_file();
 
             // Initialize tracing subscriber with console output and optionally file output
-            use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+            use tracing_subscriber::{prelude::*, EnvFilter};
 
             let env_filter = {
                 #[cfg(debug_assertions)]
@@ -7287,17 +8351,14 @@ fn main() {
             };
 
             // Always create file logger (even if disabled) so it can be enabled/disabled later
-            let app_data_dir = app
-                .path()
-                .app_data_dir()
-                .expect("Failed to get app data directory");
-            let logs_dir = app_data_dir.join("logs");
+            let logs_dir = DataDirs::current().logs();
 
             let log_config = logger::LogConfig::new(
                 &logs_dir,
                 settings.max_log_size_mb,
                 settings.enable_file_logging,
-            );
+            )
+            .with_max_files(settings.max_log_files);
 
             let file_logger_writer = match logger::RotatingFileWriter::new(log_config) {
                 Ok(writer) => {
@@ -7310,21 +8371,39 @@ fn main() {
                 }
             };
 
+            // Recent lines stay in memory for `tail_logs`, even without file logging
+            let log_buffer = Arc::new(log_buffer::LogBuffer::default());
+            app.manage(log_buffer.clone());
+            let buffer_layer = log_buffer::LogBufferLayer::new(log_buffer);
+
             // Initialize tracing subscriber with both console and file output
             // File output will only write if enabled in config
+            let log_format = settings.log_format;
             if let Some(ref file_writer) = file_logger_writer {
+                // Writes go through a background thread so a slow disk never stalls the caller
+                let (non_blocking, guard) = tracing_appender::non_blocking(file_writer.clone());
+                app.manage(FileLogGuard(guard));
                 tracing_subscriber::registry()
-                    .with(fmt::layer()) // Console output
-                    .with(fmt::layer().with_writer(file_writer.clone())) // File output (respects enabled flag)
+                    .with(log_format.layer(std::io::stdout)) // Console output
+                    .with(log_format.layer(non_blocking)) // File output (respects enabled flag)
+                    .with(buffer_layer)
                     .with(env_filter)
                     .init();
             } else {
                 tracing_subscriber::registry()
-                    .with(fmt::layer()) // Console output only
+                    .with(log_format.layer(std::io::stdout)) // Console output only
+                    .with(buffer_layer)
                     .with(env_filter)
                     .init();
             }
 
+            for note in startup_notes {
+                match note {
+                    Ok(note) => info!("{}", note),
+                    Err(e) => warn!("{}", e),
+                }
+            }
+
             // Store the file logger in app state so it can be updated later
             if let Some(file_writer) = file_logger_writer {
                 if let Some(state) = app.try_state::<AppState>() {
@@ -7546,6 +8625,49 @@ fn main() {
                 });
             }
 
+            // Follow the bandwidth schedule as the clock crosses entry boundaries
+            if let Some(state) = app.try_state::<AppState>() {
+                tauri::async_runtime::spawn(state.bandwidth_schedule.clone().run());
+            }
+
+            // Retry unacknowledged direct messages
+            {
+                let app_handle = app.handle().clone();
+                tauri::async_runtime::spawn(run_retransmission_loop(app_handle));
+            }
+
+            // Watch bootstrap node reachability and redial nodes that recover
+            {
+                let app_handle = app.handle().clone();
+                tauri::async_runtime::spawn(run_bootstrap_monitor(app_handle));
+            }
+
+            // Publish files dropped into the watch directory
+            {
+                let app_handle = app.handle().clone();
+                tauri::async_runtime::spawn(run_watch_dir_loop(app_handle));
+            }
+
+            // Keep DHT announcements in sync with seeding limits
+            {
+                let app_handle = app.handle().clone();
+                tauri::async_runtime::spawn(run_seeding_limit_loop(app_handle));
+            }
+
+            // Periodically re-hash shared files to catch silent disk corruption
+            {
+                let hours = chiral_network::config::ChiralConfig::from_env()
+                    .uploads
+                    .verify_interval_hours;
+                if hours > 0 {
+                    let app_handle = app.handle().clone();
+                    tauri::async_runtime::spawn(run_verification_loop(
+                        app_handle,
+                        Duration::from_secs(hours as u64 * 3600),
+                    ));
+                }
+            }
+
             // Start DHT event pump with the real app handle
             {
                 let app_handle = app.handle().clone();
@@ -7562,6 +8684,7 @@ fn main() {
                 };
 
                 if let Some(dht_service) = dht_clone_for_pump {
+                    chiral_events::spawn_stats_events(app_handle.clone(), Arc::downgrade(&dht_service));
                     let proxies_arc_for_pump = Arc::new(Mutex::new(Vec::new()));
                     let relay_reputation_arc_for_pump = Arc::new(Mutex::new(std::collections::HashMap::new()));
 
@@ -7578,7 +8701,7 @@ fn main() {
 
             Ok(())
         })
-        .build(tauri::generate_context!())
+        .build(context)
         .expect("error while building tauri application")
         .run(|app_handle, event| match event {
             tauri::RunEvent::ExitRequested { .. } => {
@@ -7599,6 +8722,31 @@ fn main() {
         });
 }
 
+/// Show why the app cannot start in an error dialog, without opening the
+/// main window, and exit with status 1
+fn exit_with_startup_error(mut context: tauri::Context<tauri::Wry>, error: StartupError) -> ! {
+    use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
+
+    eprintln!("{}. {}", error, error.hint());
+    context.config_mut().app.windows.clear();
+    let result = tauri::Builder::default()
+        .plugin(tauri_plugin_dialog::init())
+        .setup(move |app| {
+            let handle = app.handle().clone();
+            app.dialog()
+                .message(error.message())
+                .title(error.title())
+                .kind(MessageDialogKind::Error)
+                .show(move |_| handle.exit(1));
+            Ok(())
+        })
+        .run(context);
+    if let Err(e) = result {
+        eprintln!("Failed to show the startup error: {}", e);
+    }
+    std::process::exit(1);
+}
+
 async fn create_bt_handler_with_fallback(
     download_dir: PathBuf,
     dht_service: Arc<DhtService>,
@@ -7644,7 +8792,6 @@ pub struct FileManifestForJs {
 
 #[tauri::command]
 async fn encrypt_file_for_self_upload(
-    app: tauri::AppHandle,
     state: State<'_, AppState>,
     file_path: String,
 ) -> Result<FileManifestForJs, String> {
@@ -7656,12 +8803,7 @@ async fn encrypt_file_for_self_upload(
         .clone()
         .ok_or("No account is currently active. Please log in.")?;
 
-    // Get the app data directory for chunk storage
-    let app_data_dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Could not get app data directory: {}", e))?;
-    let chunk_storage_path = app_data_dir.join("chunk_storage");
+    let chunk_storage_path = DataDirs::current().chunk_storage();
 
     // Run the encryption in a blocking task to avoid blocking the async runtime
     tokio::task::spawn_blocking(move || {
@@ -7672,7 +8814,7 @@ async fn encrypt_file_for_self_upload(
         );
         let public_key = PublicKey::from(&secret_key);
 
-        // 2. Initialize ChunkManager with proper app data directory
+        // 2. Initialize ChunkManager in the data directory
         let manager = ChunkManager::new(chunk_storage_path);
 
         // 3. Call the existing backend function to perform the encryption.
@@ -7695,17 +8837,11 @@ async fn encrypt_file_for_self_upload(
 /// Encrypt a file for upload with optional recipient public key
 #[tauri::command]
 async fn encrypt_file_for_recipient(
-    app: tauri::AppHandle,
     state: State<'_, AppState>,
     file_path: String,
     recipient_public_key: Option<String>,
 ) -> Result<FileManifestForJs, String> {
-    // Get the app data directory for chunk storage
-    let app_data_dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Could not get app data directory: {}", e))?;
-    let chunk_storage_path = app_data_dir.join("chunk_storage");
+    let chunk_storage_path = DataDirs::current().chunk_storage();
 
     // Determine the public key to use for encryption
     let recipient_pk = if let Some(pk_hex) = recipient_public_key {
@@ -7746,7 +8882,7 @@ async fn encrypt_file_for_recipient(
             <[u8; 32]>::try_from(pk_bytes).map_err(|_| "Private key is not 32 bytes")?,
         );
 
-        // Initialize ChunkManager with proper app data directory
+        // Initialize ChunkManager in the data directory
         let manager = ChunkManager::new(chunk_storage_path);
 
         // Call the existing backend function to perform the encryption with recipient's public key
@@ -7808,7 +8944,6 @@ async fn get_active_account_private_key(state: State<'_, AppState>) -> Result<St
 
 #[tauri::command]
 async fn decrypt_and_reassemble_file(
-    app: tauri::AppHandle,
     state: State<'_, AppState>,
     manifest_js: FileManifestForJs,
     output_path: String,
@@ -7831,12 +8966,7 @@ async fn decrypt_and_reassemble_file(
     let encrypted_key_bundle: encryption::EncryptedAesKeyBundle =
         serde_json::from_str(&manifest_js.encrypted_key_bundle).map_err(|e| e.to_string())?;
 
-    // Get the app data directory for chunk storage
-    let app_data_dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Could not get app data directory: {}", e))?;
-    let chunk_storage_path = app_data_dir.join("chunk_storage");
+    let chunk_storage_path = DataDirs::current().chunk_storage();
 
     // 3. Clone the data we need for the blocking task
     let chunks = manifest_js.chunks.clone();
@@ -7844,7 +8974,7 @@ async fn decrypt_and_reassemble_file(
 
     // Run the decryption in a blocking task to avoid blocking the async runtime
     tokio::task::spawn_blocking(move || {
-        // 4. Initialize ChunkManager with proper app data directory
+        // 4. Initialize ChunkManager in the data directory
         let manager = ChunkManager::new(chunk_storage_path);
 
         // 5. Call the existing backend function to decrypt and save the file.
@@ -8153,6 +9283,9 @@ async fn pump_dht_events(
         }
 
         for ev in events {
+            if let Some(event) = chiral_events::ChiralEvent::from_dht(&ev) {
+                chiral_events::emit(&app_handle, event);
+            }
             match ev {
                 DhtEvent::PeerDiscovered { peer_id, addresses } => {
                     let payload = serde_json::json!({ "peerId": peer_id, "addresses": addresses });
@@ -8166,6 +9299,10 @@ async fn pump_dht_events(
                     let payload = serde_json::json!({ "peerId": peer_id });
                     let _ = app_handle.emit("dht_peer_disconnected", payload);
                 }
+                DhtEvent::PeerVersionIncompatible { peer_id, local_version, remote_version, reason } => {
+                    let payload = serde_json::json!({ "peerId": peer_id, "localVersion": local_version, "remoteVersion": remote_version, "reason": reason });
+                    let _ = app_handle.emit("peer-version-incompatible", payload);
+                }
                 DhtEvent::ProxyStatus { id, address, status, latency_ms, error } => {
                     let to_emit: ProxyNode = {
                         let mut proxies = proxies_arc.lock().await;
@@ -8207,6 +9344,7 @@ async fn pump_dht_events(
                         last_seen: 0,
                     });
 
+                    let previous_score = entry.reputation_score;
                     entry.reputation_score += impact;
                     entry.total_events += 1;
                     entry.last_seen = data.get("timestamp").and_then(|v| v.as_u64()).unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs());
@@ -8217,6 +9355,11 @@ async fn pump_dht_events(
                         "RelayCircuitSuccessful" => entry.circuits_successful += 1,
                         _ => {}
                     }
+                    let score = entry.reputation_score;
+                    drop(stats);
+                    dht_service
+                        .record_peer_event(&peer_id, monitoring::PeerEvent::ReputationChanged(previous_score, score))
+                        .await;
 
                     let payload = serde_json::json!({ "peerId": peer_id, "eventType": event_type, "impact": impact, "data": data });
                     let _ = app_handle.emit("relay_reputation_event", payload);
@@ -8230,8 +9373,73 @@ async fn pump_dht_events(
                         let _ = app_handle.emit("seeder_payment_received", &notification);
                     }
                 }
+                DhtEvent::FileOffer(offer) => {
+                    let _ = app_handle.emit("file-transfer-offer", offer);
+                }
+                DhtEvent::FileTransferProgress(progress) => {
+                    let _ = app_handle.emit("file-transfer-progress", progress);
+                }
+                DhtEvent::Call(event) => {
+                    let _ = app_handle.emit(event.kind.event_name(), event);
+                }
+                DhtEvent::PeerTyping(typing) => {
+                    let _ = app_handle.emit("peer-typing", typing);
+                }
+                DhtEvent::Channel { envelope, .. } => {
+                    handle_channel_envelope(&app_handle, envelope);
+                }
+                DhtEvent::MessagesRead(batch) => {
+                    handle_read_receipts(&app_handle, batch);
+                }
+                DhtEvent::QualityDegraded(degraded) => {
+                    let _ = app_handle.emit("quality-degraded", degraded);
+                }
+                DhtEvent::MeshHealthDegraded(health) => {
+                    let _ = app_handle.emit("mesh-health-degraded", health);
+                }
                 _ => {}
             }
         }
     }
+}
+
+/// Record who read our messages; `message-read` fires once per new reader
+fn handle_read_receipts(app_handle: &tauri::AppHandle, batch: messaging::ReadReceiptBatch) {
+    let store = app_handle.state::<Arc<messaging::MessageStore>>();
+    for receipt in batch.receipts {
+        match store.apply_read_receipt(&receipt) {
+            Ok(true) => {
+                let _ = app_handle.emit("message-read", receipt);
+            }
+            Ok(false) => {}
+            Err(e) => warn!("Failed to record read receipt: {}", e),
+        }
+    }
+}
+
+/// Record channel traffic received over gossipsub and tell the UI about it
+fn handle_channel_envelope(app_handle: &tauri::AppHandle, envelope: messaging::ChannelEnvelope) {
+    match envelope {
+        messaging::ChannelEnvelope::Message(message) => {
+            let store = app_handle.state::<Arc<messaging::MessageStore>>();
+            match store.store_message(&message) {
+                Ok(id) => {
+                    if let Some(stored) = store.get_message(id) {
+                        let _ = app_handle.emit("channel-message", stored);
+                    }
+                }
+                Err(e) => warn!("Failed to store channel message: {}", e),
+            }
+        }
+        messaging::ChannelEnvelope::Reaction(reaction) => {
+            let store = app_handle.state::<Arc<messaging::MessageStore>>();
+            match store.apply_reaction(&reaction) {
+                Ok(true) => {
+                    let _ = app_handle.emit("message-reaction", reaction);
+                }
+                Ok(false) => {}
+                Err(e) => warn!("Failed to store reaction: {}", e),
+            }
+        }
+    }
 }      
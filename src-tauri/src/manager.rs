@@ -388,15 +388,7 @@ impl ChunkManager {
         all_chunk_hashes_hex: &[String],
         chunk_index_to_prove: usize,
     ) -> Result<(Vec<usize>, Vec<String>, usize), String> {
-        let all_chunk_hashes: Vec<[u8; 32]> = all_chunk_hashes_hex
-            .iter()
-            .map(|h| {
-                hex::decode(h)
-                    .map_err(|e| e.to_string())?
-                    .try_into()
-                    .map_err(|_| "Invalid chunk hash length".to_string())
-            })
-            .collect::<Result<Vec<_>, String>>()?;
+        let all_chunk_hashes = decode_chunk_hashes(all_chunk_hashes_hex)?;
 
         let merkle_tree = MerkleTree::<Sha256Hasher>::from_leaves(&all_chunk_hashes);
         let proof = merkle_tree.proof(&[chunk_index_to_prove]);
@@ -423,6 +415,28 @@ impl ChunkManager {
     }
 }
 
+fn decode_chunk_hashes(chunk_hashes_hex: &[String]) -> Result<Vec<[u8; 32]>, String> {
+    chunk_hashes_hex
+        .iter()
+        .map(|h| {
+            hex::decode(h)
+                .map_err(|e| e.to_string())?
+                .try_into()
+                .map_err(|_| "Invalid chunk hash length".to_string())
+        })
+        .collect()
+}
+
+/// Computes the Merkle root of a chunk hash list the same way `FileManifest.merkle_root` is built,
+/// so a list received from a peer can be checked against the file hash it claims to describe.
+pub fn merkle_root_of(chunk_hashes_hex: &[String]) -> Result<String, String> {
+    let chunk_hashes = decode_chunk_hashes(chunk_hashes_hex)?;
+    MerkleTree::<Sha256Hasher>::from_leaves(&chunk_hashes)
+        .root()
+        .map(hex::encode)
+        .ok_or_else(|| "Failed to compute Merkle root".to_string())
+}
+
 /// Verifies a downloaded chunk against its expected hash and a Merkle root using a proof.
 /// This is a standalone utility function for ensuring chunk integrity.
 pub fn verify_chunk_with_proof(
@@ -695,6 +709,12 @@ mod tests {
             "Test file should produce multiple chunks"
         );
 
+        // The root can be recomputed from the chunk hashes alone, and a changed list does not match it
+        let mut chunk_hashes: Vec<String> = manifest.chunks.iter().map(|c| c.hash.clone()).collect();
+        assert_eq!(merkle_root_of(&chunk_hashes).unwrap(), manifest.merkle_root);
+        chunk_hashes.swap(0, 1);
+        assert_ne!(merkle_root_of(&chunk_hashes).unwrap(), manifest.merkle_root);
+
         // 3. Choose a chunk to prove and verify (e.g., the second chunk).
         let chunk_to_verify_index = 1;
         let chunk_info = &manifest.chunks[chunk_to_verify_index];
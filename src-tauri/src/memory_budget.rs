@@ -0,0 +1,562 @@
+//! Keeping the caches of a node within one memory budget.
+//!
+//! The caches that grow with the network are sized independently, so on a
+//! small machine they can add up to more memory than it has. With
+//! `SwarmConfig::memory_budget_mb` (`CHIRAL_MEMORY_BUDGET_MB`,
+//! `--memory-budget-mb`) set, the budget is split among them by
+//! `CacheKind::share_percent`:
+//!
+//! - the Kademlia store (`BudgetedStore`) drops its oldest records and the
+//!   provider records it holds for others when a new one would not fit,
+//!   never those this node published itself;
+//! - the address book (`PeerStore`) drops its oldest peers when a new one
+//!   would not fit;
+//! - the file metadata and seeder heartbeat caches are trimmed every 15 s,
+//!   largest entries first, never dropping files this node provides, by a
+//!   task of their own rather than the swarm task;
+//! - chunk responses in flight are bounded by lowering the per-source cap of
+//!   the request pipeline so `BUFFERING_SOURCES` sources fit.
+//!
+//! Sizes are estimates of the heap bytes an entry holds, close enough to keep
+//! the total in check but not exact. Usage is measured every 15 s whether or
+//! not a budget is set, and reported by `network_stats` and as
+//! `chiral_cache_memory_bytes`. When the total stays above `PRESSURE_LEVEL`
+//! of the budget for `PRESSURE_SAMPLES` measurements in a row, the node logs
+//! a warning and emits `DhtEvent::Warning` once, until usage drops again.
+
+use crate::chunk_pipeline::PipelineConfig;
+use crate::dht::models::FileMetadata;
+use libp2p::kad::store::{self, MemoryStore, RecordStore};
+use libp2p::kad::{ProviderRecord, Record, RecordKey};
+use libp2p::PeerId;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Sources assumed to hold a full pipeline of responses at once
+pub const BUFFERING_SOURCES: u64 = 8;
+
+/// Estimated bytes of one provider record: key, peer id, addresses
+pub const PROVIDER_RECORD_BYTES: u64 = 256;
+
+/// Fraction of the budget counted as being at the budget
+pub const PRESSURE_LEVEL: f64 = 0.95;
+
+/// Consecutive measurements at the budget before warning, two minutes
+pub const PRESSURE_SAMPLES: u32 = 8;
+
+/// Chunk response bytes requested and not yet received, across downloads
+static IN_FLIGHT_BYTES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CacheKind {
+    /// Kademlia records and provider records
+    KadStore,
+    /// Identify addresses of peers (`PeerStore`)
+    AddressBook,
+    FileMetadata,
+    SeederHeartbeats,
+    /// Chunk responses requested and not yet received
+    TransferBuffers,
+}
+
+impl CacheKind {
+    pub const ALL: [CacheKind; 5] = [
+        CacheKind::KadStore,
+        CacheKind::AddressBook,
+        CacheKind::FileMetadata,
+        CacheKind::SeederHeartbeats,
+        CacheKind::TransferBuffers,
+    ];
+
+    /// Label value of `chiral_cache_memory_bytes`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CacheKind::KadStore => "kad_store",
+            CacheKind::AddressBook => "address_book",
+            CacheKind::FileMetadata => "file_metadata",
+            CacheKind::SeederHeartbeats => "seeder_heartbeats",
+            CacheKind::TransferBuffers => "transfer_buffers",
+        }
+    }
+
+    /// Part of the budget this cache may use; the shares add up to 100
+    pub fn share_percent(self) -> u64 {
+        match self {
+            CacheKind::KadStore => 35,
+            CacheKind::AddressBook => 10,
+            CacheKind::FileMetadata => 15,
+            CacheKind::SeederHeartbeats => 15,
+            CacheKind::TransferBuffers => 25,
+        }
+    }
+
+    /// Position in `ALL`
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryBudget {
+    total_bytes: u64,
+}
+
+impl MemoryBudget {
+    /// `None` for 0, which means no budget
+    pub fn from_mb(mb: u64) -> Option<Self> {
+        (mb > 0).then(|| Self {
+            total_bytes: mb.saturating_mul(1024 * 1024),
+        })
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    pub fn share(&self, cache: CacheKind) -> u64 {
+        self.total_bytes / 100 * cache.share_percent()
+    }
+
+    /// `pipeline` with its in-flight cap lowered so `BUFFERING_SOURCES`
+    /// sources fit in the transfer buffer share
+    pub fn cap_pipeline(&self, pipeline: PipelineConfig) -> PipelineConfig {
+        let per_source = self.share(CacheKind::TransferBuffers) / BUFFERING_SOURCES;
+        PipelineConfig {
+            max_in_flight_bytes: pipeline.max_in_flight_bytes.min(per_source.max(1)),
+            ..pipeline
+        }
+    }
+}
+
+/// Count chunk response bytes requested from a source
+pub fn add_in_flight(bytes: u64) {
+    IN_FLIGHT_BYTES.fetch_add(bytes, Ordering::Relaxed);
+}
+
+/// Stop counting bytes that arrived or will not
+pub fn release_in_flight(bytes: u64) {
+    let _ = IN_FLIGHT_BYTES.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+        Some(current.saturating_sub(bytes))
+    });
+}
+
+pub fn in_flight_bytes() -> u64 {
+    IN_FLIGHT_BYTES.load(Ordering::Relaxed)
+}
+
+/// Estimated heap bytes of a Kademlia record
+pub fn record_bytes(record: &Record) -> u64 {
+    (record.key.as_ref().len() + record.value.len() + std::mem::size_of::<Record>()) as u64
+}
+
+/// Estimated heap bytes of a value, from the length of its JSON encoding
+pub fn json_bytes<T: Serialize>(value: &T) -> u64 {
+    serde_json::to_vec(value)
+        .map(|json| json.len() as u64)
+        .unwrap_or(0)
+}
+
+/// Estimated heap bytes of file metadata, with the inline data that its JSON
+/// encoding leaves out
+pub fn file_metadata_bytes(metadata: &FileMetadata) -> u64 {
+    json_bytes(metadata) + metadata.file_data.len() as u64
+}
+
+/// Entry of the Kademlia store, in the order it was added
+#[derive(Debug, Clone)]
+enum StoreEntry {
+    Record(RecordKey),
+    /// Provider records of a key, whoever provides it
+    Providers(RecordKey),
+}
+
+/// The Kademlia `MemoryStore` kept within a byte limit.
+///
+/// Tracks the bytes of every record and provider record it holds, including
+/// those held for other nodes. Over the limit it drops the oldest records
+/// and provider records of others instead of refusing new ones, so the node
+/// keeps serving what the network stored most recently. Records and
+/// provider records this node published itself are never dropped.
+pub struct BudgetedStore {
+    local_id: PeerId,
+    inner: MemoryStore,
+    max_bytes: Option<u64>,
+    record_sizes: HashMap<RecordKey, u64>,
+    provider_counts: HashMap<RecordKey, usize>,
+    order: VecDeque<StoreEntry>,
+    record_bytes: u64,
+    provider_bytes: u64,
+}
+
+impl BudgetedStore {
+    /// Without a budget the store keeps the `MemoryStore` defaults
+    pub fn new(local_id: PeerId, budget: Option<MemoryBudget>) -> Self {
+        let inner = match budget {
+            Some(_) => MemoryStore::with_config(
+                local_id,
+                store::MemoryStoreConfig {
+                    max_records: usize::MAX,
+                    max_provided_keys: usize::MAX,
+                    ..Default::default()
+                },
+            ),
+            None => MemoryStore::new(local_id),
+        };
+        Self {
+            local_id,
+            inner,
+            max_bytes: budget.map(|budget| budget.share(CacheKind::KadStore)),
+            record_sizes: HashMap::new(),
+            provider_counts: HashMap::new(),
+            order: VecDeque::new(),
+            record_bytes: 0,
+            provider_bytes: 0,
+        }
+    }
+
+    /// Estimated bytes of all records and provider records held
+    pub fn used_bytes(&self) -> u64 {
+        self.record_bytes + self.provider_bytes
+    }
+
+    fn set_provider_count(&mut self, key: &RecordKey) {
+        let count = self.inner.providers(key).len();
+        let old = match count {
+            0 => self.provider_counts.remove(key),
+            _ => self.provider_counts.insert(key.clone(), count),
+        };
+        if old.is_none() && count > 0 {
+            self.order.push_back(StoreEntry::Providers(key.clone()));
+        }
+        self.provider_bytes = self.provider_bytes + count as u64 * PROVIDER_RECORD_BYTES
+            - old.unwrap_or(0) as u64 * PROVIDER_RECORD_BYTES;
+    }
+
+    /// Drop the oldest entries of others, except `key`, until the store fits
+    fn evict(&mut self, key: &RecordKey) {
+        let max_bytes = self.max_bytes.unwrap_or(u64::MAX);
+        let mut kept = Vec::new();
+        while self.used_bytes() > max_bytes {
+            let Some(entry) = self.order.pop_front() else {
+                break;
+            };
+            match &entry {
+                StoreEntry::Record(k) => {
+                    if !self.record_sizes.contains_key(k) {
+                        continue;
+                    }
+                    let own = self
+                        .inner
+                        .get(k)
+                        .is_some_and(|record| record.publisher == Some(self.local_id));
+                    if k == key || own {
+                        kept.push(entry);
+                        continue;
+                    }
+                    self.inner.remove(k);
+                    self.record_bytes -= self.record_sizes.remove(k).unwrap_or(0);
+                }
+                StoreEntry::Providers(k) => {
+                    if !self.provider_counts.contains_key(k) {
+                        continue;
+                    }
+                    if k == key {
+                        kept.push(entry);
+                        continue;
+                    }
+                    let others: Vec<PeerId> = self
+                        .inner
+                        .providers(k)
+                        .into_iter()
+                        .map(|record| record.provider)
+                        .filter(|provider| *provider != self.local_id)
+                        .collect();
+                    if others.is_empty() {
+                        kept.push(entry);
+                        continue;
+                    }
+                    for provider in &others {
+                        self.inner.remove_provider(k, provider);
+                    }
+                    // Our own provider record stays, as a newer entry
+                    let k = k.clone();
+                    let count = self.provider_counts.remove(&k).unwrap_or(0);
+                    self.provider_bytes -= count as u64 * PROVIDER_RECORD_BYTES;
+                    self.set_provider_count(&k);
+                }
+            }
+        }
+        for entry in kept.into_iter().rev() {
+            self.order.push_front(entry);
+        }
+        // Removed keys leave stale entries behind
+        if self.order.len() > 2 * (self.record_sizes.len() + self.provider_counts.len()) + 64 {
+            let (records, providers) = (&self.record_sizes, &self.provider_counts);
+            self.order.retain(|entry| match entry {
+                StoreEntry::Record(k) => records.contains_key(k),
+                StoreEntry::Providers(k) => providers.contains_key(k),
+            });
+        }
+    }
+}
+
+impl RecordStore for BudgetedStore {
+    type RecordsIter<'a> = <MemoryStore as RecordStore>::RecordsIter<'a>;
+    type ProvidedIter<'a> = <MemoryStore as RecordStore>::ProvidedIter<'a>;
+
+    fn get(&self, k: &RecordKey) -> Option<Cow<'_, Record>> {
+        self.inner.get(k)
+    }
+
+    fn put(&mut self, r: Record) -> store::Result<()> {
+        let key = r.key.clone();
+        let bytes = record_bytes(&r);
+        self.inner.put(r)?;
+        match self.record_sizes.insert(key.clone(), bytes) {
+            Some(old) => self.record_bytes -= old,
+            None => self.order.push_back(StoreEntry::Record(key.clone())),
+        }
+        self.record_bytes += bytes;
+        self.evict(&key);
+        Ok(())
+    }
+
+    fn remove(&mut self, k: &RecordKey) {
+        self.inner.remove(k);
+        if let Some(bytes) = self.record_sizes.remove(k) {
+            self.record_bytes -= bytes;
+        }
+    }
+
+    fn records(&self) -> Self::RecordsIter<'_> {
+        self.inner.records()
+    }
+
+    fn add_provider(&mut self, record: ProviderRecord) -> store::Result<()> {
+        let key = record.key.clone();
+        self.inner.add_provider(record)?;
+        self.set_provider_count(&key);
+        self.evict(&key);
+        Ok(())
+    }
+
+    fn providers(&self, key: &RecordKey) -> Vec<ProviderRecord> {
+        self.inner.providers(key)
+    }
+
+    fn provided(&self) -> Self::ProvidedIter<'_> {
+        self.inner.provided()
+    }
+
+    fn remove_provider(&mut self, k: &RecordKey, p: &PeerId) {
+        self.inner.remove_provider(k, p);
+        if self.provider_counts.contains_key(k) {
+            self.set_provider_count(k);
+        }
+    }
+}
+
+/// Remove entries of `map`, largest first, until their estimated size is at
+/// most `max_bytes`. Entries for which `keep` is true are never removed.
+///
+/// Returns the remaining size and the number of entries removed.
+pub fn evict_to_fit<K: Clone + Eq + Hash, V>(
+    map: &mut HashMap<K, V>,
+    max_bytes: u64,
+    size: impl Fn(&V) -> u64,
+    keep: impl Fn(&K) -> bool,
+) -> (u64, usize) {
+    let mut total: u64 = map.values().map(&size).sum();
+    if total <= max_bytes {
+        return (total, 0);
+    }
+    let mut candidates: Vec<(u64, K)> = map
+        .iter()
+        .filter(|(key, _)| !keep(key))
+        .map(|(key, value)| (size(value), key.clone()))
+        .collect();
+    candidates.sort_by(|a, b| b.0.cmp(&a.0));
+    let mut evicted = 0;
+    for (bytes, key) in candidates {
+        if total <= max_bytes {
+            break;
+        }
+        map.remove(&key);
+        total -= bytes;
+        evicted += 1;
+    }
+    (total, evicted)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheMemoryUsage {
+    pub cache: CacheKind,
+    pub used_bytes: u64,
+    /// Its share of the budget; `None` without a budget
+    pub budget_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryUsageReport {
+    /// `None` without a budget
+    pub budget_bytes: Option<u64>,
+    pub used_bytes: u64,
+    pub caches: Vec<CacheMemoryUsage>,
+}
+
+/// The last measurement of each cache, readable without a lock
+#[derive(Debug, Default)]
+pub struct MemoryUsage {
+    budget: Option<MemoryBudget>,
+    used: [AtomicU64; 5],
+}
+
+impl MemoryUsage {
+    pub fn new(budget: Option<MemoryBudget>) -> Self {
+        Self {
+            budget,
+            ..Default::default()
+        }
+    }
+
+    pub fn budget(&self) -> Option<MemoryBudget> {
+        self.budget
+    }
+
+    pub fn store(&self, cache: CacheKind, bytes: u64) {
+        self.used[cache.index()].store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> MemoryUsageReport {
+        let caches: Vec<CacheMemoryUsage> = CacheKind::ALL
+            .iter()
+            .map(|&cache| CacheMemoryUsage {
+                cache,
+                used_bytes: self.used[cache.index()].load(Ordering::Relaxed),
+                budget_bytes: self.budget.map(|budget| budget.share(cache)),
+            })
+            .collect();
+        MemoryUsageReport {
+            budget_bytes: self.budget.map(|budget| budget.total_bytes()),
+            used_bytes: caches.iter().map(|cache| cache.used_bytes).sum(),
+            caches,
+        }
+    }
+}
+
+/// Tells when usage has stayed at the budget
+#[derive(Debug, Default)]
+pub struct BudgetPressure {
+    samples_at_budget: u32,
+    warned: bool,
+}
+
+impl BudgetPressure {
+    /// Take one measurement; returns a warning the first time usage has been
+    /// at the budget for `PRESSURE_SAMPLES` measurements
+    pub fn observe(&mut self, report: &MemoryUsageReport) -> Option<String> {
+        let budget = report.budget_bytes?;
+        if (report.used_bytes as f64) < budget as f64 * PRESSURE_LEVEL {
+            self.samples_at_budget = 0;
+            self.warned = false;
+            return None;
+        }
+        self.samples_at_budget += 1;
+        if self.samples_at_budget < PRESSURE_SAMPLES || self.warned {
+            return None;
+        }
+        self.warned = true;
+        Some(format!(
+            "Memory budget of {} MiB is persistently full ({} MiB used); \
+             caches are evicting, raise memory_budget_mb if the node has room",
+            budget / (1024 * 1024),
+            report.used_bytes / (1024 * 1024)
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_is_split_and_enforced() {
+        assert_eq!(MemoryBudget::from_mb(0), None);
+        let budget = MemoryBudget::from_mb(512).unwrap();
+        let total: u64 = CacheKind::ALL
+            .iter()
+            .map(|cache| budget.share(*cache))
+            .sum();
+        assert!(total <= budget.total_bytes());
+        let pipeline = budget.cap_pipeline(PipelineConfig::default());
+        assert!(
+            pipeline.max_in_flight_bytes * BUFFERING_SOURCES
+                <= budget.share(CacheKind::TransferBuffers)
+        );
+
+        let mut map: HashMap<&str, Vec<u8>> = HashMap::new();
+        map.insert("small", vec![0; 10]);
+        map.insert("large", vec![0; 100]);
+        map.insert("pinned", vec![0; 1000]);
+        let (remaining, evicted) =
+            evict_to_fit(&mut map, 50, |v| v.len() as u64, |k| *k == "pinned");
+        assert_eq!((remaining, evicted), (1000, 2));
+        assert!(map.contains_key("pinned"));
+
+        let usage = MemoryUsage::new(Some(MemoryBudget::from_mb(1).unwrap()));
+        usage.store(CacheKind::KadStore, 1024 * 1024);
+        let mut pressure = BudgetPressure::default();
+        let warnings: Vec<String> = (0..2 * PRESSURE_SAMPLES)
+            .filter_map(|_| pressure.observe(&usage.report()))
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        usage.store(CacheKind::KadStore, 0);
+        assert_eq!(pressure.observe(&usage.report()), None);
+        assert_eq!(usage.report().caches.len(), CacheKind::ALL.len());
+    }
+
+    #[test]
+    fn test_store_evicts_the_oldest_records_of_others() {
+        let local = PeerId::random();
+        let budget = MemoryBudget::from_mb(1).unwrap();
+        let mut store = BudgetedStore::new(local, Some(budget));
+        let record = |key: &str, publisher: PeerId| Record {
+            publisher: Some(publisher),
+            ..Record::new(RecordKey::new(&key), vec![0; 60_000])
+        };
+
+        store.put(record("own", local)).unwrap();
+        for i in 0..10 {
+            store
+                .put(record(&format!("other-{}", i), PeerId::random()))
+                .unwrap();
+        }
+        assert!(store.used_bytes() <= budget.share(CacheKind::KadStore));
+        assert!(store.get(&RecordKey::new(&"own")).is_some());
+        assert!(store.get(&RecordKey::new(&"other-9")).is_some());
+        assert!(store.get(&RecordKey::new(&"other-0")).is_none());
+
+        // Provider records held for others count too
+        let before = store.used_bytes();
+        let key = RecordKey::new(&"file");
+        store
+            .add_provider(ProviderRecord::new(
+                key.clone(),
+                PeerId::random(),
+                Vec::new(),
+            ))
+            .unwrap();
+        assert_eq!(store.providers(&key).len(), 1);
+        assert!(store.used_bytes() <= budget.share(CacheKind::KadStore));
+        store.remove(&RecordKey::new(&"other-9"));
+        let provider = store.providers(&key)[0].provider;
+        store.remove_provider(&key, &provider);
+        assert!(store.used_bytes() < before);
+    }
+}
@@ -0,0 +1,144 @@
+// Gossip payload compression
+//
+// Payloads over `compression_threshold` bytes are compressed with LZ4 when
+// that makes them smaller, and go out behind `COMPRESSED_MAGIC`. Everything
+// else is published as is, exactly as nodes without compression send it, so
+// announcements, presence and short chat lines stay readable for them. Only
+// large compressed payloads are lost on such nodes; they drop them as
+// undecodable, as they would any payload they do not understand.
+//
+// The magic starts with a NUL byte, which no JSON payload starts with. A
+// payload that happens to start with the magic anyway is sent compressed
+// whatever its size, so a receiver never mistakes it for a compressed one.
+//
+// A compressed body starts with its decompressed length (u32, little
+// endian). The length is checked against `max_decompressed_size` before
+// anything is allocated, so a small message cannot expand into a
+// decompression bomb.
+
+use super::{MessagingError, Result};
+
+/// Payloads above this many bytes are compressed by default
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Largest payload a compressed message may expand to by default
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 10 * 1024 * 1024;
+
+/// Prefix of a compressed payload
+pub const COMPRESSED_MAGIC: &[u8; 4] = b"\0LZ4";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageCompressor {
+    pub compression_threshold: usize,
+    pub max_decompressed_size: usize,
+}
+
+impl Default for MessageCompressor {
+    fn default() -> Self {
+        Self {
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            max_decompressed_size: DEFAULT_MAX_DECOMPRESSED_SIZE,
+        }
+    }
+}
+
+/// Sizes of one compressed payload, for the compression ratio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionSample {
+    pub original_bytes: usize,
+    pub compressed_bytes: usize,
+}
+
+impl MessageCompressor {
+    /// Encode `payload` for publishing. The sample is set when the payload
+    /// went out compressed.
+    pub fn compress(&self, payload: &[u8]) -> (Vec<u8>, Option<CompressionSample>) {
+        let ambiguous = payload.starts_with(COMPRESSED_MAGIC);
+        if payload.len() > self.compression_threshold || ambiguous {
+            let compressed = lz4_flex::compress_prepend_size(payload);
+            if compressed.len() < payload.len() || ambiguous {
+                let mut framed = Vec::with_capacity(COMPRESSED_MAGIC.len() + compressed.len());
+                framed.extend_from_slice(COMPRESSED_MAGIC);
+                framed.extend_from_slice(&compressed);
+                let sample = CompressionSample {
+                    original_bytes: payload.len(),
+                    compressed_bytes: framed.len(),
+                };
+                return (framed, Some(sample));
+            }
+        }
+        (payload.to_vec(), None)
+    }
+
+    /// Payload carried by a received gossip message
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match data.strip_prefix(COMPRESSED_MAGIC.as_slice()) {
+            None => Ok(data.to_vec()),
+            Some(body) => {
+                let (size, block) = match body {
+                    [a, b, c, d, block @ ..] => (u32::from_le_bytes([*a, *b, *c, *d]) as usize, block),
+                    _ => {
+                        return Err(MessagingError::InvalidPayload(
+                            "compressed payload is missing its length".to_string(),
+                        ))
+                    }
+                };
+                if size > self.max_decompressed_size {
+                    return Err(MessagingError::InvalidPayload(format!(
+                        "payload expands to {} bytes, limit is {}",
+                        size, self.max_decompressed_size
+                    )));
+                }
+                lz4_flex::decompress(block, size)
+                    .map_err(|e| MessagingError::InvalidPayload(e.to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_large_payloads_round_trip_compressed() {
+        let compressor = MessageCompressor::default();
+
+        let small = br#"{"peerId":"12D3KooWtest"}"#;
+        // Small payloads go out as nodes without compression send them
+        let (framed, sample) = compressor.compress(small);
+        assert_eq!(framed, small);
+        assert!(sample.is_none());
+        assert_eq!(compressor.decompress(&framed).unwrap(), small);
+
+        let large = serde_json::to_vec(&vec!["the same chat line"; 500]).unwrap();
+        let (framed, sample) = compressor.compress(&large);
+        assert!(framed.starts_with(COMPRESSED_MAGIC));
+        let sample = sample.unwrap();
+        assert_eq!(sample.original_bytes, large.len());
+        assert!(sample.compressed_bytes * 4 < sample.original_bytes);
+        assert_eq!(compressor.decompress(&framed).unwrap(), large);
+
+        // A short payload that looks compressed is compressed to stay
+        // unambiguous
+        let lookalike = b"\0LZ4 not compressed";
+        let (framed, _) = compressor.compress(lookalike);
+        assert_ne!(framed, lookalike);
+        assert_eq!(compressor.decompress(&framed).unwrap(), lookalike);
+    }
+
+    #[test]
+    fn test_decompression_bomb_is_rejected() {
+        let compressor = MessageCompressor {
+            max_decompressed_size: 64 * 1024,
+            ..Default::default()
+        };
+        let (framed, _) = MessageCompressor::default().compress(&vec![0u8; 1024 * 1024]);
+        assert!(framed.len() < 8 * 1024);
+        assert!(matches!(
+            compressor.decompress(&framed),
+            Err(MessagingError::InvalidPayload(_))
+        ));
+        assert!(compressor.decompress(b"\0LZ4\x01\x00").is_err());
+    }
+}
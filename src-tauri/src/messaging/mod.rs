@@ -0,0 +1,130 @@
+//! Peer-to-peer messaging.
+//!
+//! Holds the types shared by everything that sends or receives chat-style
+//! messages between peers, plus the persistent message history.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+pub mod compression;
+pub mod reactions;
+pub mod receipts;
+pub mod retransmission;
+pub mod store;
+pub mod topic_filter;
+
+pub use compression::{CompressionSample, MessageCompressor};
+pub use reactions::{ChannelEnvelope, MessageReaction, ReactionAction};
+pub use receipts::{ReadReceipt, ReadReceiptBatch};
+pub use retransmission::{PendingMessage, RetransmissionQueue, RETRY_DELAYS};
+pub use store::{MessagePage, MessageStore, MessageStoreConfig, StoredMessage};
+pub use topic_filter::{FilterMode, TopicFilter, TopicFilterState};
+
+/// Errors produced by the messaging subsystem
+#[derive(Debug, thiserror::Error)]
+pub enum MessagingError {
+    #[error("storage error: {0}")]
+    Storage(#[from] rusqlite::Error),
+
+    #[error("message not found: {0}")]
+    NotFound(MessageId),
+
+    #[error("invalid message id: {0}")]
+    InvalidId(String),
+
+    #[error("invalid gossip payload: {0}")]
+    InvalidPayload(String),
+}
+
+pub type Result<T> = std::result::Result<T, MessagingError>;
+
+/// Content-derived identifier of a message.
+///
+/// The id is the SHA-256 of the topic, sender, timestamp and payload, so every
+/// peer that sees the same message computes the same id.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MessageId(String);
+
+impl MessageId {
+    pub fn for_message(topic: &str, from_peer: &str, timestamp: u64, payload: &[u8]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(topic.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(from_peer.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(timestamp.to_be_bytes());
+        hasher.update(payload);
+        MessageId(hex::encode(hasher.finalize()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for MessageId {
+    type Err = MessagingError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let trimmed = s.trim();
+        if trimmed.len() == 64 && trimmed.chars().all(|c| c.is_ascii_hexdigit()) {
+            Ok(MessageId(trimmed.to_ascii_lowercase()))
+        } else {
+            Err(MessagingError::InvalidId(s.to_string()))
+        }
+    }
+}
+
+/// A message received from (or sent to) the network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IncomingMessage {
+    /// Topic / channel the message was published on
+    pub topic: String,
+    /// Peer ID of the original sender
+    pub from_peer: String,
+    /// Raw message body
+    pub payload: Vec<u8>,
+    /// Sender-side timestamp (Unix seconds)
+    pub timestamp: u64,
+    /// Message this one replies to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<MessageId>,
+}
+
+impl IncomingMessage {
+    pub fn id(&self) -> MessageId {
+        MessageId::for_message(&self.topic, &self.from_peer, self.timestamp, &self.payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_id_is_deterministic() {
+        let a = MessageId::for_message("chat", "peerA", 10, b"hi");
+        let b = MessageId::for_message("chat", "peerA", 10, b"hi");
+        let c = MessageId::for_message("chat", "peerA", 11, b"hi");
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_message_id_round_trip() {
+        let id = MessageId::for_message("chat", "peerA", 10, b"hi");
+        let parsed: MessageId = id.to_string().parse().unwrap();
+        assert_eq!(id, parsed);
+        assert!("not-a-hash".parse::<MessageId>().is_err());
+    }
+}
@@ -0,0 +1,79 @@
+// Emoji reactions on messages
+//
+// Reactions travel on the channel's gossipsub topic like message bodies,
+// wrapped in a `ChannelEnvelope`. A peer reacts at most once with a given
+// emoji per message; a repeated `Add` is a no-op and `Remove` withdraws it
+// again.
+
+use super::{IncomingMessage, MessageId};
+use crate::compatibility::PeerCapabilitySet;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReactionAction {
+    Add,
+    Remove,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageReaction {
+    pub message_id: MessageId,
+    pub reactor_peer_id: String,
+    pub emoji: String,
+    pub action: ReactionAction,
+    /// Reactor-side timestamp (Unix seconds)
+    pub timestamp: u64,
+}
+
+impl MessageReaction {
+    /// Same reactor and emoji, i.e. the two only differ in action or time
+    pub fn same_reaction(&self, other: &MessageReaction) -> bool {
+        self.message_id == other.message_id
+            && self.reactor_peer_id == other.reactor_peer_id
+            && self.emoji == other.emoji
+    }
+}
+
+/// What is published on a channel topic
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ChannelEnvelope {
+    Message(IncomingMessage),
+    Reaction(MessageReaction),
+}
+
+impl ChannelEnvelope {
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        serde_json::from_slice(data).ok()
+    }
+
+    /// The envelope as a peer with `capabilities` can take it. Reactions are
+    /// withheld from peers without reactions, and replies reach peers without
+    /// threads as plain messages.
+    pub fn for_capabilities(&self, capabilities: &PeerCapabilitySet) -> Option<Self> {
+        match self {
+            ChannelEnvelope::Reaction(_) if !capabilities.supports_reactions => None,
+            ChannelEnvelope::Message(message) if !capabilities.supports_threads => {
+                Some(ChannelEnvelope::Message(IncomingMessage {
+                    reply_to: None,
+                    ..message.clone()
+                }))
+            }
+            envelope => Some(envelope.clone()),
+        }
+    }
+
+    /// Peer that produced the envelope, checked against the gossipsub source
+    pub fn author(&self) -> &str {
+        match self {
+            ChannelEnvelope::Message(message) => &message.from_peer,
+            ChannelEnvelope::Reaction(reaction) => &reaction.reactor_peer_id,
+        }
+    }
+}
@@ -0,0 +1,178 @@
+// Read receipts
+//
+// When the user views a message, the reader sends a receipt straight to the
+// message's author over request-response; nothing goes out on the channel
+// topic. Receipts for messages read together (opening a channel with a
+// backlog) are grouped per author and sent as one batch.
+
+use super::MessageId;
+use serde::{Deserialize, Serialize};
+
+/// Largest number of receipts carried by one request
+pub const MAX_RECEIPTS_PER_BATCH: usize = 256;
+
+/// Largest receipt frame accepted from the wire
+const MAX_RECEIPT_FRAME: usize = 256 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadReceipt {
+    pub message_id: MessageId,
+    pub reader_peer_id: String,
+    /// Reader-side time the message was viewed (Unix seconds)
+    pub read_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadReceiptProtocol;
+
+impl AsRef<str> for ReadReceiptProtocol {
+    fn as_ref(&self) -> &str {
+        crate::protocol::READ_RECEIPT_PROTOCOL
+    }
+}
+
+/// Receipts from one reader for messages of one author
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadReceiptBatch {
+    pub receipts: Vec<ReadReceipt>,
+}
+
+/// Reply to `ReadReceiptBatch`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadReceiptAck;
+
+#[derive(Clone, Debug, Default)]
+pub struct ReadReceiptCodec;
+
+async fn read_receipt_frame<T, M>(io: &mut T) -> std::io::Result<M>
+where
+    T: futures::AsyncRead + Unpin + Send,
+    M: serde::de::DeserializeOwned,
+{
+    use futures::AsyncReadExt;
+    let mut len_buf = [0u8; 4];
+    io.read_exact(&mut len_buf).await?;
+    let len = u32::from_le_bytes(len_buf) as usize;
+    if len > MAX_RECEIPT_FRAME {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("read receipt frame of {} bytes exceeds the limit", len),
+        ));
+    }
+    let mut data = vec![0u8; len];
+    io.read_exact(&mut data).await?;
+    serde_json::from_slice(&data)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+}
+
+async fn write_receipt_frame<T, M>(io: &mut T, message: &M) -> std::io::Result<()>
+where
+    T: futures::AsyncWrite + Unpin + Send,
+    M: Serialize,
+{
+    use futures::AsyncWriteExt;
+    let data = serde_json::to_vec(message)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+    io.write_all(&(data.len() as u32).to_le_bytes()).await?;
+    io.write_all(&data).await?;
+    io.flush().await
+}
+
+#[async_trait::async_trait]
+impl libp2p::request_response::Codec for ReadReceiptCodec {
+    type Protocol = ReadReceiptProtocol;
+    type Request = ReadReceiptBatch;
+    type Response = ReadReceiptAck;
+
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> std::io::Result<Self::Request>
+    where
+        T: futures::AsyncRead + Unpin + Send,
+    {
+        read_receipt_frame(io).await
+    }
+
+    async fn read_response<T>(&mut self, _: &Self::Protocol, io: &mut T) -> std::io::Result<Self::Response>
+    where
+        T: futures::AsyncRead + Unpin + Send,
+    {
+        read_receipt_frame(io).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        request: Self::Request,
+    ) -> std::io::Result<()>
+    where
+        T: futures::AsyncWrite + Unpin + Send,
+    {
+        write_receipt_frame(io, &request).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        response: Self::Response,
+    ) -> std::io::Result<()>
+    where
+        T: futures::AsyncWrite + Unpin + Send,
+    {
+        write_receipt_frame(io, &response).await
+    }
+}
+
+/// Group receipts by the author they go to, at most
+/// `MAX_RECEIPTS_PER_BATCH` per batch
+pub fn batch_by_author(receipts: Vec<(String, ReadReceipt)>) -> Vec<(String, ReadReceiptBatch)> {
+    let mut by_author: std::collections::BTreeMap<String, Vec<ReadReceipt>> = Default::default();
+    for (author, receipt) in receipts {
+        by_author.entry(author).or_default().push(receipt);
+    }
+    by_author
+        .into_iter()
+        .flat_map(|(author, receipts)| {
+            receipts
+                .chunks(MAX_RECEIPTS_PER_BATCH)
+                .map(|chunk| {
+                    (
+                        author.clone(),
+                        ReadReceiptBatch {
+                            receipts: chunk.to_vec(),
+                        },
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receipts_are_batched_per_author() {
+        let receipt = |n: usize| ReadReceipt {
+            message_id: MessageId::for_message("chat", "author", n as u64, b"hi"),
+            reader_peer_id: "reader".to_string(),
+            read_at: 1_700_000_000,
+        };
+        let mut receipts: Vec<_> = (0..MAX_RECEIPTS_PER_BATCH + 1)
+            .map(|n| ("author-a".to_string(), receipt(n)))
+            .collect();
+        receipts.push(("author-b".to_string(), receipt(0)));
+
+        let batches = batch_by_author(receipts);
+        let sizes: Vec<(&str, usize)> = batches
+            .iter()
+            .map(|(author, batch)| (author.as_str(), batch.receipts.len()))
+            .collect();
+        assert_eq!(
+            sizes,
+            vec![("author-a", MAX_RECEIPTS_PER_BATCH), ("author-a", 1), ("author-b", 1)]
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Retransmission of direct messages the target peer has not acknowledged.
+//!
+//! A direct message counts as delivered once the peer answers it. Messages
+//! that could not be delivered are queued here and retried on an exponential
+//! backoff schedule; after the last retry fails the message is given up on and
+//! handed back to the caller so it can report the failure.
+
+use super::MessageId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Delay before each retry, indexed by the number of failed attempts so far
+pub const RETRY_DELAYS: [Duration; 4] = [
+    Duration::from_secs(5),
+    Duration::from_secs(30),
+    Duration::from_secs(5 * 60),
+    Duration::from_secs(60 * 60),
+];
+
+/// A direct message waiting to be retransmitted
+#[derive(Debug, Clone)]
+pub struct PendingMessage {
+    pub message_id: MessageId,
+    pub target_peer: String,
+    pub payload: Vec<u8>,
+    /// Delivery attempts that have failed so far
+    pub attempts: u8,
+    pub next_attempt: Instant,
+}
+
+/// Unacknowledged direct messages, keyed by message id
+#[derive(Debug, Default)]
+pub struct RetransmissionQueue {
+    pending: HashMap<MessageId, PendingMessage>,
+}
+
+impl RetransmissionQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a message whose first delivery attempt failed at `now`.
+    ///
+    /// Returns false if the message is already queued.
+    pub fn enqueue(
+        &mut self,
+        message_id: MessageId,
+        target_peer: String,
+        payload: Vec<u8>,
+        now: Instant,
+    ) -> bool {
+        if self.pending.contains_key(&message_id) {
+            return false;
+        }
+        self.pending.insert(
+            message_id.clone(),
+            PendingMessage {
+                message_id,
+                target_peer,
+                payload,
+                attempts: 1,
+                next_attempt: now + RETRY_DELAYS[0],
+            },
+        );
+        true
+    }
+
+    /// Drop a message the peer has acknowledged; returns whether it was queued
+    pub fn acknowledge(&mut self, message_id: &MessageId) -> bool {
+        self.pending.remove(message_id).is_some()
+    }
+
+    /// Messages whose next attempt is due at `now`, earliest first
+    pub fn due(&self, now: Instant) -> Vec<PendingMessage> {
+        let mut due: Vec<PendingMessage> = self
+            .pending
+            .values()
+            .filter(|m| m.next_attempt <= now)
+            .cloned()
+            .collect();
+        due.sort_by_key(|m| m.next_attempt);
+        due
+    }
+
+    /// Record a failed retry at `now` and schedule the next one.
+    ///
+    /// Once every retry delay has been used up the message is removed and
+    /// returned so the caller can report it as undeliverable.
+    pub fn record_failure(&mut self, message_id: &MessageId, now: Instant) -> Option<PendingMessage> {
+        let message = self.pending.get_mut(message_id)?;
+        message.attempts = message.attempts.saturating_add(1);
+        match RETRY_DELAYS.get(message.attempts as usize - 1) {
+            Some(delay) => {
+                message.next_attempt = now + *delay;
+                None
+            }
+            None => self.pending.remove(message_id),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u64) -> MessageId {
+        MessageId::for_message("direct", "peerA", n, b"hi")
+    }
+
+    #[test]
+    fn test_backoff_schedule_then_give_up() {
+        let mut queue = RetransmissionQueue::new();
+        let start = Instant::now();
+        assert!(queue.enqueue(id(1), "peerB".into(), b"hi".to_vec(), start));
+        assert!(!queue.enqueue(id(1), "peerB".into(), b"hi".to_vec(), start));
+
+        assert!(queue.due(start).is_empty());
+        let mut now = start + RETRY_DELAYS[0];
+        assert_eq!(queue.due(now).len(), 1);
+
+        for delay in &RETRY_DELAYS[1..] {
+            assert!(queue.record_failure(&id(1), now).is_none());
+            assert_eq!(queue.due(now).len(), 0);
+            now += *delay;
+            assert_eq!(queue.due(now).len(), 1);
+        }
+
+        let failed = queue.record_failure(&id(1), now).unwrap();
+        assert_eq!(failed.attempts as usize, RETRY_DELAYS.len() + 1);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_acknowledge_removes_message() {
+        let mut queue = RetransmissionQueue::new();
+        let now = Instant::now();
+        queue.enqueue(id(1), "peerB".into(), Vec::new(), now);
+        queue.enqueue(id(2), "peerC".into(), Vec::new(), now);
+
+        assert!(queue.acknowledge(&id(1)));
+        assert!(!queue.acknowledge(&id(1)));
+        assert_eq!(queue.len(), 1);
+        assert!(queue.record_failure(&id(1), now).is_none());
+    }
+}
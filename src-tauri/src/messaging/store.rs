@@ -0,0 +1,809 @@
+// Message history persistence
+//
+// Received messages are written to a SQLite database (WAL mode) so that the
+// message history survives restarts. Each topic keeps at most
+// `max_messages_per_topic` messages; once the cap is reached the oldest
+// messages of that topic are evicted first.
+//
+// Reactions are persisted alongside and kept in an in-memory index keyed by
+// message id. They are removed together with the message they belong to.
+//
+// A reply records the message it answers and the root of its thread (the
+// first message of the reply chain). Reply counts per thread root live in the
+// `threads` table so they can be read without scanning the history.
+//
+// Read receipts are only kept for messages in the history; for our own
+// messages they list the peers that have seen them.
+
+use super::{
+    IncomingMessage, MessageId, MessageReaction, MessagingError, ReactionAction, ReadReceipt,
+    Result,
+};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+/// Default number of messages retained per topic
+pub const DEFAULT_MAX_MESSAGES_PER_TOPIC: u32 = 10_000;
+
+const MESSAGE_COLUMNS: &str =
+    "id, topic, from_peer, payload, sent_at, received_at, reply_to, thread_root_id";
+
+/// Configuration for the message store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageStoreConfig {
+    /// Maximum messages retained per topic (oldest evicted first)
+    pub max_messages_per_topic: u32,
+}
+
+impl Default for MessageStoreConfig {
+    fn default() -> Self {
+        Self {
+            max_messages_per_topic: DEFAULT_MAX_MESSAGES_PER_TOPIC,
+        }
+    }
+}
+
+/// A message as persisted in the history
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredMessage {
+    pub id: MessageId,
+    pub topic: String,
+    pub from_peer: String,
+    pub payload: Vec<u8>,
+    /// Sender-side timestamp (Unix seconds)
+    pub timestamp: u64,
+    /// Local receive time (Unix seconds)
+    pub received_at: u64,
+    /// Message this one replies to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<MessageId>,
+    /// First message of the reply chain this message belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_root_id: Option<MessageId>,
+    /// Peers that sent a read receipt for this message, first reader first
+    #[serde(default)]
+    pub read_by: Vec<String>,
+}
+
+/// One page of a message listing
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MessagePage {
+    pub messages: Vec<StoredMessage>,
+    /// Messages available across all pages
+    pub total: u64,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+fn parse_id(id: String) -> MessageId {
+    // Ids are validated on insert, so this never falls back in practice
+    id.parse().unwrap_or_else(|_| MessageId(id))
+}
+
+impl StoredMessage {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: parse_id(row.get("id")?),
+            topic: row.get("topic")?,
+            from_peer: row.get("from_peer")?,
+            payload: row.get("payload")?,
+            timestamp: row.get::<_, i64>("sent_at")? as u64,
+            received_at: row.get::<_, i64>("received_at")? as u64,
+            reply_to: row.get::<_, Option<String>>("reply_to")?.map(parse_id),
+            thread_root_id: row.get::<_, Option<String>>("thread_root_id")?.map(parse_id),
+            read_by: Vec::new(),
+        })
+    }
+}
+
+/// SQLite-backed message history
+pub struct MessageStore {
+    conn: Mutex<Connection>,
+    config: MessageStoreConfig,
+    /// Current reactions per message, mirrored from the `reactions` table
+    reactions: Mutex<HashMap<MessageId, Vec<MessageReaction>>>,
+}
+
+impl MessageStore {
+    /// Open (or create) the message database at `db_path` with default settings
+    pub fn open(db_path: &Path) -> Result<Self> {
+        Self::open_with_config(db_path, MessageStoreConfig::default())
+    }
+
+    /// Open (or create) the message database at `db_path`
+    pub fn open_with_config(db_path: &Path, config: MessageStoreConfig) -> Result<Self> {
+        if let Some(parent) = db_path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                warn!("Failed to create message store directory {:?}: {}", parent, e);
+            }
+        }
+
+        let conn = Connection::open(db_path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        debug!("Opened message store at {:?}", db_path);
+        Self::with_connection(conn, config)
+    }
+
+    /// A store that lives only as long as the process, for when the database
+    /// file cannot be opened
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?, MessageStoreConfig::default())
+    }
+
+    fn with_connection(conn: Connection, config: MessageStoreConfig) -> Result<Self> {
+        Self::init_schema(&conn)?;
+        let reactions = Self::load_reactions(&conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            config,
+            reactions: Mutex::new(reactions),
+        })
+    }
+
+    /// Default location inside the application data directory
+    pub fn default_path() -> PathBuf {
+        crate::data_dirs::DataDirs::current().messages()
+    }
+
+    fn init_schema(conn: &Connection) -> Result<()> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS messages (
+                seq         INTEGER PRIMARY KEY AUTOINCREMENT,
+                id          TEXT NOT NULL UNIQUE,
+                topic       TEXT NOT NULL,
+                from_peer   TEXT NOT NULL,
+                payload     BLOB NOT NULL,
+                sent_at     INTEGER NOT NULL,
+                received_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_messages_topic_seq ON messages(topic, seq);
+            CREATE TABLE IF NOT EXISTS reactions (
+                message_id TEXT NOT NULL,
+                reactor    TEXT NOT NULL,
+                emoji      TEXT NOT NULL,
+                reacted_at INTEGER NOT NULL,
+                PRIMARY KEY (message_id, reactor, emoji)
+            );
+            CREATE TABLE IF NOT EXISTS read_receipts (
+                message_id TEXT NOT NULL,
+                reader     TEXT NOT NULL,
+                read_at    INTEGER NOT NULL,
+                PRIMARY KEY (message_id, reader)
+            );
+            CREATE TABLE IF NOT EXISTS threads (
+                thread_root_id TEXT PRIMARY KEY,
+                message_count  INTEGER NOT NULL,
+                last_reply_at  INTEGER NOT NULL
+            );",
+        )?;
+
+        // Databases created before threads were added lack the reply columns
+        let has_reply_to = conn
+            .prepare("SELECT 1 FROM pragma_table_info('messages') WHERE name = 'reply_to'")?
+            .exists([])?;
+        if !has_reply_to {
+            conn.execute_batch(
+                "ALTER TABLE messages ADD COLUMN reply_to TEXT;
+                 ALTER TABLE messages ADD COLUMN thread_root_id TEXT;",
+            )?;
+        }
+        conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_messages_thread_root ON messages(thread_root_id, seq);",
+        )?;
+        Ok(())
+    }
+
+    /// Recompute reply counts and drop read receipts after messages were removed
+    fn recount_threads(conn: &Connection) -> Result<()> {
+        conn.execute_batch(
+            "UPDATE threads SET message_count =
+                 (SELECT COUNT(*) FROM messages WHERE thread_root_id = threads.thread_root_id);
+             DELETE FROM threads WHERE message_count = 0;
+             DELETE FROM read_receipts WHERE message_id NOT IN (SELECT id FROM messages);",
+        )?;
+        Ok(())
+    }
+
+    fn load_read_by(conn: &Connection, messages: &mut [StoredMessage]) -> Result<()> {
+        let mut stmt = conn.prepare(
+            "SELECT reader FROM read_receipts WHERE message_id = ?1 ORDER BY read_at, reader",
+        )?;
+        for message in messages {
+            message.read_by = stmt
+                .query_map(params![message.id.as_str()], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+        }
+        Ok(())
+    }
+
+    fn load_reactions(conn: &Connection) -> Result<HashMap<MessageId, Vec<MessageReaction>>> {
+        let mut stmt = conn.prepare(
+            "SELECT message_id, reactor, emoji, reacted_at FROM reactions ORDER BY reacted_at",
+        )?;
+        let rows = stmt.query_map([], |row| {
+                Ok(MessageReaction {
+                message_id: parse_id(row.get(0)?),
+                reactor_peer_id: row.get(1)?,
+                emoji: row.get(2)?,
+                action: ReactionAction::Add,
+                timestamp: row.get::<_, i64>(3)? as u64,
+            })
+        })?;
+
+        let mut index: HashMap<MessageId, Vec<MessageReaction>> = HashMap::new();
+        for reaction in rows {
+            let reaction = reaction?;
+            index
+                .entry(reaction.message_id.clone())
+                .or_default()
+                .push(reaction);
+        }
+        Ok(index)
+    }
+
+    fn reactions_index(&self) -> std::sync::MutexGuard<'_, HashMap<MessageId, Vec<MessageReaction>>> {
+        self.reactions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Delete the reactions of messages that are about to be removed from
+    /// the history. Returns the affected message ids for `forget_reactions`.
+    fn delete_reactions_where(
+        conn: &Connection,
+        message_filter: &str,
+        filter_params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Vec<MessageId>> {
+        let ids: Vec<String> = conn
+            .prepare(&format!(
+                "SELECT DISTINCT message_id FROM reactions
+                 WHERE message_id IN (SELECT id FROM messages WHERE {})",
+                message_filter
+            ))?
+            .query_map(filter_params, |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for id in &ids {
+            conn.execute("DELETE FROM reactions WHERE message_id = ?1", params![id])?;
+        }
+        Ok(ids.into_iter().map(MessageId).collect())
+    }
+
+    fn forget_reactions(&self, ids: &[MessageId]) {
+        if ids.is_empty() {
+            return;
+        }
+        let mut index = self.reactions_index();
+        for id in ids {
+            index.remove(id);
+        }
+    }
+
+    pub fn config(&self) -> &MessageStoreConfig {
+        &self.config
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+        // A poisoned lock only means another thread panicked mid-query;
+        // SQLite itself keeps the database consistent.
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Persist a message. Storing the same message twice is a no-op.
+    pub fn store_message(&self, msg: &IncomingMessage) -> Result<MessageId> {
+        let id = msg.id();
+        let received_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+        // A reply joins its parent's thread; replying to a message that is not
+        // in a thread (or not stored here) starts one rooted at that message
+        let thread_root_id = match &msg.reply_to {
+            Some(parent) => Some(
+                tx.query_row(
+                    "SELECT thread_root_id FROM messages WHERE id = ?1",
+                    params![parent.as_str()],
+                    |row| row.get::<_, Option<String>>(0),
+                )
+                .optional()?
+                .flatten()
+                .map(parse_id)
+                .unwrap_or_else(|| parent.clone()),
+            ),
+            None => None,
+        };
+        let inserted = tx.execute(
+            "INSERT OR IGNORE INTO messages
+                 (id, topic, from_peer, payload, sent_at, received_at, reply_to, thread_root_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                id.as_str(),
+                msg.topic,
+                msg.from_peer,
+                msg.payload,
+                msg.timestamp as i64,
+                received_at as i64,
+                msg.reply_to.as_ref().map(MessageId::as_str),
+                thread_root_id.as_ref().map(MessageId::as_str)
+            ],
+        )?;
+        if let (true, Some(root)) = (inserted > 0, &thread_root_id) {
+            tx.execute(
+                "INSERT INTO threads (thread_root_id, message_count, last_reply_at) VALUES (?1, 1, ?2)
+                 ON CONFLICT(thread_root_id) DO UPDATE SET
+                     message_count = message_count + 1,
+                     last_reply_at = MAX(last_reply_at, excluded.last_reply_at)",
+                params![root.as_str(), msg.timestamp as i64],
+            )?;
+        }
+
+        let mut evicted_reactions = Vec::new();
+        if inserted > 0 {
+            evicted_reactions = Self::delete_reactions_where(
+                &tx,
+                "topic = ?1 AND seq NOT IN (
+                     SELECT seq FROM messages WHERE topic = ?1 ORDER BY seq DESC LIMIT ?2
+                 )",
+                params![msg.topic, self.config.max_messages_per_topic as i64],
+            )?;
+            let evicted = tx.execute(
+                "DELETE FROM messages
+                 WHERE topic = ?1 AND seq NOT IN (
+                     SELECT seq FROM messages WHERE topic = ?1 ORDER BY seq DESC LIMIT ?2
+                 )",
+                params![msg.topic, self.config.max_messages_per_topic as i64],
+            )?;
+            if evicted > 0 {
+                debug!("Evicted {} old message(s) from topic {}", evicted, msg.topic);
+                Self::recount_threads(&tx)?;
+            }
+        }
+        tx.commit()?;
+        self.forget_reactions(&evicted_reactions);
+
+        Ok(id)
+    }
+
+    /// Look up a single message by id
+    pub fn get_message(&self, id: MessageId) -> Option<StoredMessage> {
+        let conn = self.lock();
+        let result = conn
+            .query_row(
+                &format!("SELECT {} FROM messages WHERE id = ?1", MESSAGE_COLUMNS),
+                params![id.as_str()],
+                StoredMessage::from_row,
+            )
+            .optional()
+            .map_err(MessagingError::from)
+            .and_then(|message| match message {
+                Some(mut message) => {
+                    Self::load_read_by(&conn, std::slice::from_mut(&mut message))?;
+                    Ok(Some(message))
+                }
+                None => Ok(None),
+            });
+
+        match result {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Failed to load message {}: {}", id, e);
+                None
+            }
+        }
+    }
+
+    /// List messages of a topic, newest first
+    pub fn list_messages(&self, topic: &str, limit: u32, offset: u32) -> Vec<StoredMessage> {
+        let conn = self.lock();
+        let result = conn
+            .prepare(&format!(
+                "SELECT {} FROM messages WHERE topic = ?1
+                 ORDER BY seq DESC LIMIT ?2 OFFSET ?3",
+                MESSAGE_COLUMNS
+            ))
+            .and_then(|mut stmt| {
+                stmt.query_map(
+                    params![topic, limit as i64, offset as i64],
+                    StoredMessage::from_row,
+                )?
+                .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .map_err(MessagingError::from)
+            .and_then(|mut messages| {
+                Self::load_read_by(&conn, &mut messages)?;
+                Ok(messages)
+            });
+
+        match result {
+            Ok(messages) => messages,
+            Err(e) => {
+                warn!("Failed to list messages for topic {}: {}", topic, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Delete a single message
+    pub fn delete_message(&self, id: MessageId) -> Result<()> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+        let reacted = Self::delete_reactions_where(&tx, "id = ?1", params![id.as_str()])?;
+        let deleted = tx.execute("DELETE FROM messages WHERE id = ?1", params![id.as_str()])?;
+        if deleted == 0 {
+            return Err(MessagingError::NotFound(id));
+        }
+        Self::recount_threads(&tx)?;
+        tx.commit()?;
+        self.forget_reactions(&reacted);
+        Ok(())
+    }
+
+    /// Delete every message of a topic, returning how many were removed
+    pub fn delete_topic_history(&self, topic: &str) -> Result<u64> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+        let reacted = Self::delete_reactions_where(&tx, "topic = ?1", params![topic])?;
+        let deleted = tx.execute("DELETE FROM messages WHERE topic = ?1", params![topic])?;
+        Self::recount_threads(&tx)?;
+        tx.commit()?;
+        self.forget_reactions(&reacted);
+        Ok(deleted as u64)
+    }
+
+    /// Apply a reaction received from (or sent to) the network.
+    ///
+    /// A peer's reaction with the same emoji is stored once; a `Remove` only
+    /// withdraws it if it is not older than the `Add`. Returns whether the
+    /// stored reactions changed.
+    pub fn apply_reaction(&self, reaction: &MessageReaction) -> Result<bool> {
+        let conn = self.lock();
+        let affected = match reaction.action {
+            ReactionAction::Add => conn.execute(
+                "INSERT OR IGNORE INTO reactions (message_id, reactor, emoji, reacted_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    reaction.message_id.as_str(),
+                    reaction.reactor_peer_id,
+                    reaction.emoji,
+                    reaction.timestamp as i64
+                ],
+            )?,
+            ReactionAction::Remove => conn.execute(
+                "DELETE FROM reactions
+                 WHERE message_id = ?1 AND reactor = ?2 AND emoji = ?3 AND reacted_at <= ?4",
+                params![
+                    reaction.message_id.as_str(),
+                    reaction.reactor_peer_id,
+                    reaction.emoji,
+                    reaction.timestamp as i64
+                ],
+            )?,
+        };
+        if affected == 0 {
+            return Ok(false);
+        }
+
+        let mut index = self.reactions_index();
+        let reactions = index.entry(reaction.message_id.clone()).or_default();
+        match reaction.action {
+            ReactionAction::Add => reactions.push(MessageReaction {
+                action: ReactionAction::Add,
+                ..reaction.clone()
+            }),
+            ReactionAction::Remove => {
+                reactions.retain(|r| !r.same_reaction(reaction));
+                if reactions.is_empty() {
+                    index.remove(&reaction.message_id);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Current reactions on a message, oldest first
+    pub fn get_reactions(&self, id: &MessageId) -> Vec<MessageReaction> {
+        self.reactions_index().get(id).cloned().unwrap_or_default()
+    }
+
+    /// Record that `receipt.reader_peer_id` has seen a stored message. Returns
+    /// false for a repeated receipt or a message not in the history.
+    pub fn apply_read_receipt(&self, receipt: &ReadReceipt) -> Result<bool> {
+        let conn = self.lock();
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO read_receipts (message_id, reader, read_at)
+             SELECT ?1, ?2, ?3 WHERE EXISTS (SELECT 1 FROM messages WHERE id = ?1)",
+            params![
+                receipt.message_id.as_str(),
+                receipt.reader_peer_id,
+                receipt.read_at as i64
+            ],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// A thread's root message followed by its replies, oldest first
+    pub fn get_thread(&self, root_id: &MessageId, limit: u32, offset: u32) -> Result<MessagePage> {
+        let conn = self.lock();
+        let mut messages = conn
+            .prepare(&format!(
+                "SELECT {} FROM messages WHERE id = ?1 OR thread_root_id = ?1
+                 ORDER BY seq ASC LIMIT ?2 OFFSET ?3",
+                MESSAGE_COLUMNS
+            ))?
+            .query_map(
+                params![root_id.as_str(), limit as i64, offset as i64],
+                StoredMessage::from_row,
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Self::load_read_by(&conn, &mut messages)?;
+
+        let replies: i64 = conn
+            .query_row(
+                "SELECT message_count FROM threads WHERE thread_root_id = ?1",
+                params![root_id.as_str()],
+                |row| row.get(0),
+            )
+            .optional()?
+            .unwrap_or(0);
+        let root_stored: bool = conn
+            .prepare("SELECT 1 FROM messages WHERE id = ?1")?
+            .exists(params![root_id.as_str()])?;
+        Ok(MessagePage {
+            messages,
+            total: replies as u64 + root_stored as u64,
+            limit,
+            offset,
+        })
+    }
+
+    /// Replies stored for the thread rooted at `root_id`
+    pub fn thread_reply_count(&self, root_id: &MessageId) -> Result<u64> {
+        let conn = self.lock();
+        let count: Option<i64> = conn
+            .query_row(
+                "SELECT message_count FROM threads WHERE thread_root_id = ?1",
+                params![root_id.as_str()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(count.unwrap_or(0) as u64)
+    }
+
+    /// Number of messages stored for a topic
+    pub fn count_messages(&self, topic: &str) -> Result<u64> {
+        let conn = self.lock();
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM messages WHERE topic = ?1",
+            params![topic],
+            |row| row.get(0),
+        )?;
+        Ok(count as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn message(topic: &str, n: u64) -> IncomingMessage {
+        IncomingMessage {
+            topic: topic.to_string(),
+            from_peer: "12D3KooWTestPeer".to_string(),
+            payload: format!("message {}", n).into_bytes(),
+            timestamp: 1_700_000_000 + n,
+            reply_to: None,
+        }
+    }
+
+    fn open_store(dir: &TempDir, max: u32) -> MessageStore {
+        MessageStore::open_with_config(
+            &dir.path().join("messages.db"),
+            MessageStoreConfig {
+                max_messages_per_topic: max,
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_store_and_get_message() {
+        let dir = TempDir::new().unwrap();
+        let store = open_store(&dir, 100);
+
+        let msg = message("chat", 1);
+        let id = store.store_message(&msg).unwrap();
+
+        let stored = store.get_message(id.clone()).unwrap();
+        assert_eq!(stored.id, id);
+        assert_eq!(stored.payload, msg.payload);
+        assert_eq!(stored.timestamp, msg.timestamp);
+    }
+
+    #[test]
+    fn test_duplicate_message_is_ignored() {
+        let dir = TempDir::new().unwrap();
+        let store = open_store(&dir, 100);
+
+        let msg = message("chat", 1);
+        let first = store.store_message(&msg).unwrap();
+        let second = store.store_message(&msg).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(store.count_messages("chat").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_list_messages_newest_first_with_paging() {
+        let dir = TempDir::new().unwrap();
+        let store = open_store(&dir, 100);
+        for n in 0..5 {
+            store.store_message(&message("chat", n)).unwrap();
+        }
+        store.store_message(&message("other", 0)).unwrap();
+
+        let page = store.list_messages("chat", 2, 1);
+        assert_eq!(page.len(), 2);
+        assert_eq!(page[0].payload, b"message 3".to_vec());
+        assert_eq!(page[1].payload, b"message 2".to_vec());
+    }
+
+    #[test]
+    fn test_oldest_messages_evicted_per_topic() {
+        let dir = TempDir::new().unwrap();
+        let store = open_store(&dir, 3);
+        for n in 0..5 {
+            store.store_message(&message("chat", n)).unwrap();
+        }
+        store.store_message(&message("other", 0)).unwrap();
+
+        assert_eq!(store.count_messages("chat").unwrap(), 3);
+        assert_eq!(store.count_messages("other").unwrap(), 1);
+        assert!(store.get_message(message("chat", 0).id()).is_none());
+        assert!(store.get_message(message("chat", 4).id()).is_some());
+    }
+
+    #[test]
+    fn test_delete_message_and_topic_history() {
+        let dir = TempDir::new().unwrap();
+        let store = open_store(&dir, 100);
+        let id = store.store_message(&message("chat", 0)).unwrap();
+        store.store_message(&message("chat", 1)).unwrap();
+        store.store_message(&message("chat", 2)).unwrap();
+
+        store.delete_message(id.clone()).unwrap();
+        assert!(store.get_message(id.clone()).is_none());
+        assert!(matches!(
+            store.delete_message(id),
+            Err(MessagingError::NotFound(_))
+        ));
+
+        assert_eq!(store.delete_topic_history("chat").unwrap(), 2);
+        assert!(store.list_messages("chat", 10, 0).is_empty());
+    }
+
+    fn reaction(
+        message_id: &MessageId,
+        peer: &str,
+        emoji: &str,
+        action: ReactionAction,
+        timestamp: u64,
+    ) -> MessageReaction {
+        MessageReaction {
+            message_id: message_id.clone(),
+            reactor_peer_id: peer.to_string(),
+            emoji: emoji.to_string(),
+            action,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_reactions_are_deduplicated_and_removed() {
+        let dir = TempDir::new().unwrap();
+        let store = open_store(&dir, 100);
+        let id = store.store_message(&message("chat", 0)).unwrap();
+
+        assert!(store.apply_reaction(&reaction(&id, "a", "👍", ReactionAction::Add, 10)).unwrap());
+        assert!(!store.apply_reaction(&reaction(&id, "a", "👍", ReactionAction::Add, 11)).unwrap());
+        assert!(store.apply_reaction(&reaction(&id, "b", "👍", ReactionAction::Add, 12)).unwrap());
+        assert_eq!(store.get_reactions(&id).len(), 2);
+
+        // A stale remove does not undo a newer add
+        assert!(!store.apply_reaction(&reaction(&id, "b", "👍", ReactionAction::Remove, 5)).unwrap());
+        assert!(store.apply_reaction(&reaction(&id, "a", "👍", ReactionAction::Remove, 20)).unwrap());
+        let remaining = store.get_reactions(&id);
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].reactor_peer_id, "b");
+
+        // Reactions survive a reopen and go away with their message
+        drop(store);
+        let store = open_store(&dir, 100);
+        assert_eq!(store.get_reactions(&id).len(), 1);
+        store.delete_message(id.clone()).unwrap();
+        assert!(store.get_reactions(&id).is_empty());
+    }
+
+    #[test]
+    fn test_replies_join_the_root_thread() {
+        let dir = TempDir::new().unwrap();
+        let store = open_store(&dir, 100);
+        let root = store.store_message(&message("chat", 0)).unwrap();
+        store.store_message(&message("chat", 1)).unwrap();
+        let reply = store
+            .store_message(&IncomingMessage {
+                reply_to: Some(root.clone()),
+                ..message("chat", 2)
+            })
+            .unwrap();
+        // A reply to a reply still belongs to the root's thread
+        let nested = store
+            .store_message(&IncomingMessage {
+                reply_to: Some(reply.clone()),
+                ..message("chat", 3)
+            })
+            .unwrap();
+
+        let stored = store.get_message(nested.clone()).unwrap();
+        assert_eq!(stored.reply_to, Some(reply.clone()));
+        assert_eq!(stored.thread_root_id, Some(root.clone()));
+        assert_eq!(store.thread_reply_count(&root).unwrap(), 2);
+
+        let page = store.get_thread(&root, 2, 0).unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.messages[0].id, root);
+        assert_eq!(page.messages[1].id, reply);
+
+        store.delete_message(reply).unwrap();
+        assert_eq!(store.thread_reply_count(&root).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_read_receipts_are_recorded_once() {
+        let dir = TempDir::new().unwrap();
+        let store = open_store(&dir, 100);
+        let id = store.store_message(&message("chat", 0)).unwrap();
+        let receipt = |reader: &str, read_at: u64| ReadReceipt {
+            message_id: id.clone(),
+            reader_peer_id: reader.to_string(),
+            read_at,
+        };
+
+        assert!(store.apply_read_receipt(&receipt("peer-b", 20)).unwrap());
+        assert!(store.apply_read_receipt(&receipt("peer-a", 10)).unwrap());
+        assert!(!store.apply_read_receipt(&receipt("peer-a", 30)).unwrap());
+        assert_eq!(store.get_message(id.clone()).unwrap().read_by, vec!["peer-a", "peer-b"]);
+
+        // Receipts for messages not in the history are dropped
+        let unknown = ReadReceipt {
+            message_id: MessageId::for_message("chat", "peer", 1, b"gone"),
+            ..receipt("peer-a", 10)
+        };
+        assert!(!store.apply_read_receipt(&unknown).unwrap());
+
+        store.delete_message(id.clone()).unwrap();
+        let again = store.store_message(&message("chat", 0)).unwrap();
+        assert!(store.get_message(again).unwrap().read_by.is_empty());
+    }
+
+    #[test]
+    fn test_history_survives_reopen() {
+        let dir = TempDir::new().unwrap();
+        let id = {
+            let store = open_store(&dir, 100);
+            store.store_message(&message("chat", 7)).unwrap()
+        };
+
+        let reopened = open_store(&dir, 100);
+        assert!(reopened.get_message(id).is_some());
+    }
+}
@@ -0,0 +1,158 @@
+// Topic filter
+//
+// Decides which gossip messages are kept once they arrive. Peers can be
+// blocked outright, channels muted one by one, or the node can switch to an
+// allow-list and keep only the channels named in it. Filtered messages are
+// dropped before they are decoded, so they never reach the message store.
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+
+/// Change requested through `set_topic_filter_command`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterMode {
+    /// Drop every message from the peer
+    BlockPeer,
+    UnblockPeer,
+    /// Drop messages on the channel
+    MuteTopic,
+    UnmuteTopic,
+    /// Keep only allow-listed channels, adding this one to the list
+    AllowTopicOnly,
+    /// Leave allow-list mode; the value is ignored
+    AllowAllTopics,
+}
+
+/// Current filter settings, sorted for display
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopicFilterState {
+    pub blocked_peers: Vec<String>,
+    pub muted_topics: Vec<String>,
+    /// `None` unless the filter is in allow-list mode
+    pub allowed_topics: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TopicFilter {
+    blocked_peers: HashSet<PeerId>,
+    muted_topics: HashSet<String>,
+    allowed_topics: Option<BTreeSet<String>>,
+}
+
+impl TopicFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_blocked_peer(&mut self, peer_id: PeerId) {
+        self.blocked_peers.insert(peer_id);
+    }
+
+    pub fn remove_blocked_peer(&mut self, peer_id: &PeerId) {
+        self.blocked_peers.remove(peer_id);
+    }
+
+    pub fn mute_topic(&mut self, topic: impl Into<String>) {
+        self.muted_topics.insert(topic.into());
+    }
+
+    pub fn unmute_topic(&mut self, topic: &str) {
+        self.muted_topics.remove(topic);
+    }
+
+    /// Switch to allow-list mode, adding `topics` to the allowed channels
+    pub fn add_allowed_topics_only(&mut self, topics: Vec<String>) {
+        self.allowed_topics.get_or_insert_with(BTreeSet::new).extend(topics);
+    }
+
+    /// Leave allow-list mode
+    pub fn allow_all_topics(&mut self) {
+        self.allowed_topics = None;
+    }
+
+    /// Apply a change requested by the user
+    pub fn apply(&mut self, mode: FilterMode, topic_or_peer: &str) -> Result<(), String> {
+        let value = topic_or_peer.trim();
+        let peer = || {
+            value
+                .parse::<PeerId>()
+                .map_err(|e| format!("invalid peer id {}: {}", value, e))
+        };
+        if value.is_empty() && mode != FilterMode::AllowAllTopics {
+            return Err("a topic or peer id is required".to_string());
+        }
+        match mode {
+            FilterMode::BlockPeer => self.add_blocked_peer(peer()?),
+            FilterMode::UnblockPeer => self.remove_blocked_peer(&peer()?),
+            FilterMode::MuteTopic => self.mute_topic(value),
+            FilterMode::UnmuteTopic => self.unmute_topic(value),
+            FilterMode::AllowTopicOnly => self.add_allowed_topics_only(vec![value.to_string()]),
+            FilterMode::AllowAllTopics => self.allow_all_topics(),
+        }
+        Ok(())
+    }
+
+    /// Whether messages from `source` are kept; unsigned messages always are
+    pub fn allows_peer(&self, source: Option<&PeerId>) -> bool {
+        !source.is_some_and(|peer| self.blocked_peers.contains(peer))
+    }
+
+    /// Whether a message on the channel `topic` from `source` is kept
+    pub fn allows(&self, topic: &str, source: Option<&PeerId>) -> bool {
+        self.allows_peer(source)
+            && !self.muted_topics.contains(topic)
+            && !self
+                .allowed_topics
+                .as_ref()
+                .is_some_and(|allowed| !allowed.contains(topic))
+    }
+
+    pub fn state(&self) -> TopicFilterState {
+        let mut blocked_peers: Vec<String> =
+            self.blocked_peers.iter().map(|p| p.to_string()).collect();
+        blocked_peers.sort();
+        let mut muted_topics: Vec<String> = self.muted_topics.iter().cloned().collect();
+        muted_topics.sort();
+        TopicFilterState {
+            blocked_peers,
+            muted_topics,
+            allowed_topics: self
+                .allowed_topics
+                .as_ref()
+                .map(|allowed| allowed.iter().cloned().collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_blocks_peers_and_topics() {
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+        let mut filter = TopicFilter::new();
+        assert!(filter.allows("general", Some(&alice)));
+
+        filter.add_blocked_peer(bob);
+        filter.apply(FilterMode::MuteTopic, "random").unwrap();
+        assert!(!filter.allows("general", Some(&bob)));
+        assert!(!filter.allows_peer(Some(&bob)));
+        assert!(!filter.allows("random", Some(&alice)));
+        assert!(filter.allows("general", Some(&alice)));
+
+        filter.add_allowed_topics_only(vec!["dev".to_string()]);
+        assert!(!filter.allows("general", Some(&alice)));
+        assert!(filter.allows("dev", Some(&alice)));
+        assert!(filter.allows("dev", None));
+
+        filter.apply(FilterMode::AllowAllTopics, "").unwrap();
+        filter.apply(FilterMode::UnblockPeer, &bob.to_string()).unwrap();
+        assert!(filter.allows("general", Some(&bob)));
+        assert_eq!(filter.state().muted_topics, vec!["random".to_string()]);
+        assert!(filter.apply(FilterMode::BlockPeer, "not-a-peer").is_err());
+    }
+}
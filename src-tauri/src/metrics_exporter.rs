@@ -0,0 +1,258 @@
+//! Prometheus endpoint for headless nodes.
+//!
+//! `GET /metrics` answers in the OpenMetrics text format. Every metric is
+//! named `chiral_*`; the names are part of the operator interface and must
+//! not change once released. A subsystem exposes metrics by implementing
+//! `MetricsSource` and registering itself in the `MetricsRegistry` the node
+//! serves. Sources are read at scrape time, so nothing is kept here.
+//!
+//! The endpoint is off unless an address is configured, and binding it to
+//! anything but a loopback address has to be allowed explicitly.
+
+use crate::dht::DhtService;
+use axum::{extract::State, http::header, response::IntoResponse, routing::get, Router};
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Address suggested for `--metrics-addr`
+pub const DEFAULT_METRICS_ADDR: &str = "127.0.0.1:9464";
+
+/// Prefix added to every metric name
+pub const METRIC_PREFIX: &str = "chiral_";
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Something with metrics to report on each scrape
+#[async_trait::async_trait]
+pub trait MetricsSource: Send + Sync {
+    async fn collect(&self, out: &mut MetricsWriter);
+}
+
+/// OpenMetrics text of one scrape
+#[derive(Debug, Default)]
+pub struct MetricsWriter {
+    text: String,
+}
+
+impl MetricsWriter {
+    /// Monotonic count; `name` is given without the prefix and `_total`
+    pub fn counter(&mut self, name: &str, help: &str, value: u64) {
+        self.counter_family(name, help, "", &[("", value)]);
+    }
+
+    /// Counter split by one label, e.g. `outcome="success"`
+    pub fn counter_family(&mut self, name: &str, help: &str, label: &str, samples: &[(&str, u64)]) {
+        let name = self.family(name, help, "counter");
+        for (value_of_label, value) in samples {
+            let _ = writeln!(self.text, "{}_total{} {}", name, labels(label, value_of_label), value);
+        }
+    }
+
+    pub fn gauge(&mut self, name: &str, help: &str, value: f64) {
+        self.gauge_family(name, help, "", &[("", value)]);
+    }
+
+    /// Gauge split by one label, e.g. `cache="kad_store"`
+    pub fn gauge_family(&mut self, name: &str, help: &str, label: &str, samples: &[(&str, f64)]) {
+        let name = self.family(name, help, "gauge");
+        for (value_of_label, value) in samples {
+            let _ = writeln!(self.text, "{}{} {}", name, labels(label, value_of_label), value);
+        }
+    }
+
+    /// Constant `1` whose label describes the node, e.g. `role="infra"`;
+    /// `name` is given without the prefix and `_info`
+    pub fn info(&mut self, name: &str, help: &str, label: &str, value_of_label: &str) {
+        let name = self.family(name, help, "info");
+        let _ = writeln!(self.text, "{}_info{} 1", name, labels(label, value_of_label));
+    }
+
+    /// Families encoded by `prometheus-client`, such as libp2p's; their names
+    /// must carry the prefix already
+    pub fn append_registry(&mut self, registry: &prometheus_client::registry::Registry) {
+        let mut encoded = String::new();
+        if prometheus_client::encoding::text::encode(&mut encoded, registry).is_ok() {
+            self.text.push_str(encoded.trim_end().trim_end_matches("# EOF").trim_end());
+            if !self.text.ends_with('\n') {
+                self.text.push('\n');
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> String {
+        self.text.push_str("# EOF\n");
+        self.text
+    }
+
+    fn family(&mut self, name: &str, help: &str, kind: &str) -> String {
+        let name = format!("{}{}", METRIC_PREFIX, name);
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+        name
+    }
+}
+
+fn labels(label: &str, value: &str) -> String {
+    if label.is_empty() {
+        return String::new();
+    }
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    format!("{{{}=\"{}\"}}", label, escaped)
+}
+
+/// Sources read on every scrape
+#[derive(Clone, Default)]
+pub struct MetricsRegistry {
+    sources: Vec<Arc<dyn MetricsSource>>,
+}
+
+impl MetricsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, source: Arc<dyn MetricsSource>) {
+        self.sources.push(source);
+    }
+
+    pub async fn render(&self) -> String {
+        let mut out = MetricsWriter::default();
+        for source in &self.sources {
+            source.collect(&mut out).await;
+        }
+        out.finish()
+    }
+}
+
+#[async_trait::async_trait]
+impl MetricsSource for DhtService {
+    async fn collect(&self, out: &mut MetricsWriter) {
+        let m = self.metrics_snapshot().await;
+        out.info("node", "What the node is run for.", "role", self.role().as_str());
+        out.gauge("connected_peers", "Peers with an open connection.", m.peer_count as f64);
+        out.counter("connections_opened", "Connections established.", m.connections_opened);
+        out.counter("connections_closed", "Connections closed.", m.connections_closed);
+        out.gauge("kad_records_stored", "Records in the local Kademlia store.", m.kad_records_stored as f64);
+        out.gauge("kad_routing_table_size", "Peers in the Kademlia routing table.", m.kad_routing_table_size as f64);
+        out.counter("kad_requests_served", "Kademlia requests answered for other peers.", m.kad_requests_served);
+        out.counter("relay_circuits_accepted", "Circuits relayed for other peers.", m.relay_circuits_accepted);
+        out.gauge("relay_circuits_active", "Circuits currently relayed.", m.relay_circuits_active as f64);
+        out.counter_family(
+            "hole_punches",
+            "DCUtR hole punches by outcome.",
+            "outcome",
+            &[("success", m.dcutr_hole_punch_successes), ("failure", m.dcutr_hole_punch_failures)],
+        );
+        out.counter_family(
+            "transfers",
+            "Direct file transfers received, by outcome.",
+            "outcome",
+            &[("completed", m.transfers_received), ("failed", m.transfers_failed)],
+        );
+        out.counter("gossip_messages_blocked", "Gossip messages dropped by the topic filter.", m.gossip_messages_blocked);
+        out.counter_family(
+            "gossip_compressed_bytes",
+            "Gossip payloads published compressed, in bytes before and after compression.",
+            "stage",
+            &[("original", m.gossip_bytes_uncompressed), ("compressed", m.gossip_bytes_compressed)],
+        );
+        if m.gossip_bytes_compressed > 0 {
+            out.gauge(
+                "gossip_compression_ratio",
+                "Original over compressed size of the gossip payloads published compressed.",
+                m.gossip_bytes_uncompressed as f64 / m.gossip_bytes_compressed as f64,
+            );
+        }
+        let filter_rules = self.connection_filter().list();
+        if !filter_rules.is_empty() {
+            let hits: Vec<(&str, u64)> = filter_rules.iter().map(|rule| (rule.id.as_str(), rule.hits)).collect();
+            let denied: Vec<(&str, u64)> = filter_rules.iter().map(|rule| (rule.id.as_str(), rule.denied)).collect();
+            out.counter_family("connection_filter_hits", "Incoming connections matched by each filter rule.", "rule", &hits);
+            out.counter_family("connection_filter_denied", "Incoming connections refused by each filter rule.", "rule", &denied);
+        }
+        let memory = self.memory_usage();
+        let caches: Vec<(&str, f64)> = memory
+            .caches
+            .iter()
+            .map(|cache| (cache.cache.as_str(), cache.used_bytes as f64))
+            .collect();
+        out.gauge_family("cache_memory_bytes", "Estimated memory held by each cache, sampled every 15 s.", "cache", &caches);
+        if let Some(budget) = memory.budget_bytes {
+            out.gauge("memory_budget_bytes", "Memory budget shared by the caches.", budget as f64);
+        }
+        out.append_registry(self.bandwidth_metrics());
+    }
+}
+
+/// Refuse addresses other than loopback unless `allow_public`
+pub fn check_bind_addr(addr: SocketAddr, allow_public: bool) -> Result<(), String> {
+    if addr.ip().is_loopback() || allow_public {
+        Ok(())
+    } else {
+        Err(format!(
+            "refusing to serve metrics on non-loopback address {}; allow it with --metrics-allow-public",
+            addr
+        ))
+    }
+}
+
+async fn serve_metrics(State(registry): State<Arc<MetricsRegistry>>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], registry.render().await)
+}
+
+/// Serve `registry` on `addr` for the life of the process
+///
+/// Returns the bound address (useful if port 0 was used)
+pub async fn start_server(
+    registry: Arc<MetricsRegistry>,
+    addr: SocketAddr,
+    allow_public: bool,
+) -> Result<SocketAddr, String> {
+    check_bind_addr(addr, allow_public)?;
+    let app = Router::new()
+        .route("/metrics", get(serve_metrics))
+        .with_state(registry);
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| format!("Failed to bind metrics endpoint {}: {}", addr, e))?;
+    let bound_addr = listener.local_addr().map_err(|e| e.to_string())?;
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!("Metrics endpoint error: {}", e);
+        }
+    });
+    Ok(bound_addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed;
+
+    #[async_trait::async_trait]
+    impl MetricsSource for Fixed {
+        async fn collect(&self, out: &mut MetricsWriter) {
+            out.gauge("connected_peers", "Peers with an open connection.", 3.0);
+            out.info("node", "What the node is run for.", "role", "infra");
+            out.counter_family("hole_punches", "Hole punches.", "outcome", &[("success", 2), ("failure", 1)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_render_is_prefixed_openmetrics() {
+        let mut registry = MetricsRegistry::new();
+        registry.register(Arc::new(Fixed));
+        let text = registry.render().await;
+        assert!(text.contains("# TYPE chiral_connected_peers gauge\nchiral_connected_peers 3\n"));
+        assert!(text.contains("chiral_hole_punches_total{outcome=\"success\"} 2\n"));
+        assert!(text.contains("# TYPE chiral_node info\nchiral_node_info{role=\"infra\"} 1\n"));
+        assert!(text.ends_with("# EOF\n"));
+
+        assert!(check_bind_addr("127.0.0.1:9464".parse().unwrap(), false).is_ok());
+        assert!(check_bind_addr("[::1]:9464".parse().unwrap(), false).is_ok());
+        assert!(check_bind_addr("0.0.0.0:9464".parse().unwrap(), false).is_err());
+        assert!(check_bind_addr("0.0.0.0:9464".parse().unwrap(), true).is_ok());
+    }
+}
@@ -0,0 +1,333 @@
+//! Recent per-peer event timelines for debugging.
+//!
+//! Every tracked peer keeps a bounded ring buffer of the last events seen
+//! for it (connections, pings, messages, protocol failures). The number of
+//! tracked peers is bounded as well; the peer updated least recently is
+//! evicted first.
+//!
+//! Connections are also labelled with a coarse quality derived from the
+//! peer's latency and transfer error rate.
+//!
+//! `stats` sums the node up in one `NetworkStats` structure.
+//! `mesh` tracks the GossipSub mesh size per topic.
+
+use crate::peer_selection::PeerMetrics;
+use libp2p::{Multiaddr, PeerId};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+pub mod mesh;
+pub mod stats;
+pub use mesh::{MeshHealthMonitor, MeshTopicHealth};
+pub use stats::{ConnectionKinds, DashboardStats, DetailedNetworkStats, NetworkStats, StatsCollector, StatsCounters, TransportStats};
+
+/// Events kept per peer by default
+pub const DEFAULT_EVENTS_PER_PEER: usize = 100;
+
+/// Peers tracked by default
+pub const DEFAULT_MAX_TRACKED_PEERS: usize = 512;
+
+/// Why a connection to a peer was closed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "error", rename_all = "snake_case")]
+pub enum CloseReason {
+    /// Closed without an error (keep-alive expired, closed by either side)
+    Graceful,
+    /// Connection failed with the given error
+    Error(String),
+}
+
+/// Something that happened with a peer. Topics are protocol or topic names.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum PeerEvent {
+    Connected(Multiaddr),
+    Disconnected(CloseReason),
+    /// Topic and payload size in bytes
+    MessageReceived(String, usize),
+    /// Topic and payload size in bytes
+    MessageSent(String, usize),
+    PingSuccess(Duration),
+    PingFailure,
+    /// Old and new reputation score
+    ReputationChanged(f64, f64),
+    ProtocolNegotiationFailed(String),
+}
+
+/// Ring buffer of the most recent events for one peer
+#[derive(Debug, Clone)]
+pub struct PeerEventHistory {
+    pub peer_id: PeerId,
+    pub events: VecDeque<PeerEvent>,
+    capacity: usize,
+    last_updated: Instant,
+}
+
+impl PeerEventHistory {
+    pub fn new(peer_id: PeerId, capacity: usize) -> Self {
+        Self {
+            peer_id,
+            events: VecDeque::with_capacity(capacity.min(DEFAULT_EVENTS_PER_PEER)),
+            capacity: capacity.max(1),
+            last_updated: Instant::now(),
+        }
+    }
+
+    pub fn push(&mut self, event: PeerEvent) {
+        if self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+        self.last_updated = Instant::now();
+    }
+
+    /// The last `limit` events, oldest first
+    pub fn recent(&self, limit: usize) -> Vec<PeerEvent> {
+        let skip = self.events.len().saturating_sub(limit);
+        self.events.iter().skip(skip).cloned().collect()
+    }
+}
+
+/// Event histories for all recently seen peers
+#[derive(Debug)]
+pub struct PeerEventLog {
+    histories: HashMap<PeerId, PeerEventHistory>,
+    events_per_peer: usize,
+    max_peers: usize,
+}
+
+impl Default for PeerEventLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENTS_PER_PEER, DEFAULT_MAX_TRACKED_PEERS)
+    }
+}
+
+impl PeerEventLog {
+    pub fn new(events_per_peer: usize, max_peers: usize) -> Self {
+        Self {
+            histories: HashMap::new(),
+            events_per_peer,
+            max_peers: max_peers.max(1),
+        }
+    }
+
+    pub fn record(&mut self, peer_id: PeerId, event: PeerEvent) {
+        if !self.histories.contains_key(&peer_id) && self.histories.len() >= self.max_peers {
+            if let Some(stalest) = self
+                .histories
+                .values()
+                .min_by_key(|h| h.last_updated)
+                .map(|h| h.peer_id)
+            {
+                self.histories.remove(&stalest);
+            }
+        }
+
+        let events_per_peer = self.events_per_peer;
+        self.histories
+            .entry(peer_id)
+            .or_insert_with(|| PeerEventHistory::new(peer_id, events_per_peer))
+            .push(event);
+    }
+
+    pub fn history(&self, peer_id: &PeerId) -> Option<&PeerEventHistory> {
+        self.histories.get(peer_id)
+    }
+
+    /// The last `limit` events for `peer_id`, oldest first
+    pub fn recent(&self, peer_id: &PeerId, limit: usize) -> Vec<PeerEvent> {
+        self.history(peer_id)
+            .map(|h| h.recent(limit))
+            .unwrap_or_default()
+    }
+}
+
+/// Coarse quality of the connection to a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionQuality {
+    Good,
+    Fair,
+    Poor,
+    Unusable,
+}
+
+impl ConnectionQuality {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionQuality::Good => "good",
+            ConnectionQuality::Fair => "fair",
+            ConnectionQuality::Poor => "poor",
+            ConnectionQuality::Unusable => "unusable",
+        }
+    }
+
+    /// Worse than `Fair`
+    pub fn is_degraded(&self) -> bool {
+        matches!(self, ConnectionQuality::Poor | ConnectionQuality::Unusable)
+    }
+}
+
+/// Payload of the `quality-degraded` event
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QualityDegraded {
+    pub peer_id: String,
+    pub previous: ConnectionQuality,
+    pub quality: ConnectionQuality,
+    pub latency_ms: Option<u64>,
+    pub error_rate: f64,
+}
+
+/// Labels connections from peer metrics and remembers the last label per
+/// peer so a drop below `Fair` is reported once
+#[derive(Debug, Default)]
+pub struct ConnectionQualityClassifier {
+    last: HashMap<String, ConnectionQuality>,
+}
+
+impl ConnectionQualityClassifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Good: RTT under 100ms and no failed transfers. Fair: RTT under 500ms
+    /// or under 1% errors. Poor: RTT under 2s or under 5% errors.
+    /// A peer that has not been pinged yet is judged on errors alone.
+    pub fn classify(peer: &PeerMetrics) -> ConnectionQuality {
+        let rtt_below = |limit: u64| peer.latency_ms.is_some_and(|rtt| rtt < limit);
+        let error_rate = error_rate(peer);
+        if rtt_below(100) && peer.failed_transfers == 0 {
+            ConnectionQuality::Good
+        } else if rtt_below(500) || error_rate < 0.01 {
+            ConnectionQuality::Fair
+        } else if rtt_below(2000) || error_rate < 0.05 {
+            ConnectionQuality::Poor
+        } else {
+            ConnectionQuality::Unusable
+        }
+    }
+
+    /// Classify `peer` and return the transition if it just dropped below
+    /// `Fair`. The first classification of a peer is never a drop.
+    pub fn observe(&mut self, peer: &PeerMetrics) -> Option<QualityDegraded> {
+        let quality = Self::classify(peer);
+        let previous = self.last.insert(peer.peer_id.clone(), quality)?;
+        (quality.is_degraded() && !previous.is_degraded()).then(|| QualityDegraded {
+            peer_id: peer.peer_id.clone(),
+            previous,
+            quality,
+            latency_ms: peer.latency_ms,
+            error_rate: error_rate(peer),
+        })
+    }
+
+    pub fn forget(&mut self, peer_id: &str) {
+        self.last.remove(peer_id);
+    }
+}
+
+/// Share of attempted transfers that failed
+fn error_rate(peer: &PeerMetrics) -> f64 {
+    if peer.transfer_count == 0 {
+        0.0
+    } else {
+        peer.failed_transfers as f64 / peer.transfer_count as f64
+    }
+}
+
+/// Peer metrics together with the connection quality derived from them
+#[derive(Debug, Clone, Serialize)]
+pub struct FullPeerInfo {
+    #[serde(flatten)]
+    pub metrics: PeerMetrics,
+    pub connection_quality: String,
+}
+
+impl From<PeerMetrics> for FullPeerInfo {
+    fn from(metrics: PeerMetrics) -> Self {
+        let connection_quality = ConnectionQualityClassifier::classify(&metrics)
+            .as_str()
+            .to_string();
+        Self {
+            metrics,
+            connection_quality,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_is_bounded() {
+        let peer = PeerId::random();
+        let mut history = PeerEventHistory::new(peer, 3);
+        for i in 0..5 {
+            history.push(PeerEvent::MessageReceived("chat".into(), i));
+        }
+        assert_eq!(history.events.len(), 3);
+        assert_eq!(
+            history.recent(2),
+            vec![
+                PeerEvent::MessageReceived("chat".into(), 3),
+                PeerEvent::MessageReceived("chat".into(), 4),
+            ]
+        );
+        assert_eq!(history.recent(10).len(), 3);
+    }
+
+    #[test]
+    fn test_log_evicts_stalest_peer() {
+        let mut log = PeerEventLog::new(10, 2);
+        let (a, b, c) = (PeerId::random(), PeerId::random(), PeerId::random());
+
+        log.record(a, PeerEvent::PingFailure);
+        log.record(b, PeerEvent::PingFailure);
+        std::thread::sleep(Duration::from_millis(2));
+        log.record(a, PeerEvent::PingSuccess(Duration::from_millis(20)));
+        log.record(c, PeerEvent::PingFailure);
+
+        assert!(log.history(&a).is_some());
+        assert!(log.history(&b).is_none());
+        assert_eq!(log.recent(&c, 5), vec![PeerEvent::PingFailure]);
+        assert!(log.recent(&b, 5).is_empty());
+    }
+
+    fn metrics(latency_ms: Option<u64>, transfers: u64, failed: u64) -> PeerMetrics {
+        let mut peer = PeerMetrics::new("peer".into(), String::new());
+        peer.latency_ms = latency_ms;
+        peer.transfer_count = transfers;
+        peer.failed_transfers = failed;
+        peer.successful_transfers = transfers - failed;
+        peer
+    }
+
+    #[test]
+    fn test_classify_connection_quality() {
+        use ConnectionQuality::*;
+        let classify = ConnectionQualityClassifier::classify;
+        assert_eq!(classify(&metrics(Some(50), 10, 0)), Good);
+        assert_eq!(classify(&metrics(Some(50), 200, 1)), Fair);
+        assert_eq!(classify(&metrics(Some(300), 10, 5)), Fair);
+        assert_eq!(classify(&metrics(None, 0, 0)), Fair);
+        assert_eq!(classify(&metrics(Some(1500), 10, 5)), Poor);
+        assert_eq!(classify(&metrics(Some(3000), 100, 3)), Poor);
+        assert_eq!(classify(&metrics(Some(3000), 10, 5)), Unusable);
+        assert_eq!(classify(&metrics(None, 10, 1)), Unusable);
+    }
+
+    #[test]
+    fn test_degradation_reported_once() {
+        let mut classifier = ConnectionQualityClassifier::new();
+        assert_eq!(classifier.observe(&metrics(Some(50), 10, 0)), None);
+        let degraded = classifier.observe(&metrics(Some(1500), 10, 5)).unwrap();
+        assert_eq!(degraded.previous, ConnectionQuality::Good);
+        assert_eq!(degraded.quality, ConnectionQuality::Poor);
+        assert_eq!(classifier.observe(&metrics(Some(3000), 10, 5)), None);
+        assert_eq!(classifier.observe(&metrics(Some(300), 10, 5)), None);
+        assert!(classifier.observe(&metrics(Some(3000), 10, 5)).is_some());
+    }
+}
@@ -0,0 +1,145 @@
+//! GossipSub mesh size per topic.
+//!
+//! A topic whose mesh has fewer than `mesh_n_low` peers still works, but
+//! messages reach fewer peers directly and depend on gossip to spread. The
+//! swarm task samples the mesh of every subscribed topic after GossipSub
+//! events and every `SAMPLE_INTERVAL`; a topic that had a full mesh and
+//! drops below `mesh_n_low` is reported once with `mesh-health-degraded`,
+//! and again only after it recovered.
+
+use crate::config::SwarmConfig;
+use libp2p::gossipsub::TopicHash;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Payload of the `mesh-health-degraded` event and of `get_mesh_health_command`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeshTopicHealth {
+    pub topic: String,
+    pub mesh_peers: usize,
+    pub mesh_n_low: usize,
+    pub mesh_n: usize,
+    pub mesh_n_high: usize,
+    /// At least `mesh_n_low` mesh peers
+    pub healthy: bool,
+}
+
+#[derive(Debug)]
+pub struct MeshHealthMonitor {
+    mesh_n_low: usize,
+    mesh_n: usize,
+    mesh_n_high: usize,
+    mesh_peer_count_per_topic: HashMap<TopicHash, usize>,
+    /// Topics that reached `mesh_n_low` since they were last reported
+    healthy: HashSet<TopicHash>,
+}
+
+impl MeshHealthMonitor {
+    pub fn new(config: &SwarmConfig) -> Self {
+        Self {
+            mesh_n_low: config.mesh_n_low,
+            mesh_n: config.mesh_n,
+            mesh_n_high: config.mesh_n_high,
+            mesh_peer_count_per_topic: HashMap::new(),
+            healthy: HashSet::new(),
+        }
+    }
+
+    /// Record the mesh size of every subscribed topic; topics missing from
+    /// `sizes` were unsubscribed. Returns the topics that just dropped
+    /// below `mesh_n_low`.
+    pub fn update(
+        &mut self,
+        sizes: impl IntoIterator<Item = (TopicHash, usize)>,
+    ) -> Vec<MeshTopicHealth> {
+        let sizes: HashMap<TopicHash, usize> = sizes.into_iter().collect();
+        self.healthy.retain(|topic| sizes.contains_key(topic));
+        let mut degraded = Vec::new();
+        for (topic, &count) in &sizes {
+            if count >= self.mesh_n_low {
+                self.healthy.insert(topic.clone());
+            } else if self.healthy.remove(topic) {
+                degraded.push(self.health(topic, count));
+            }
+        }
+        self.mesh_peer_count_per_topic = sizes;
+        degraded.sort_by(|a, b| a.topic.cmp(&b.topic));
+        degraded
+    }
+
+    /// Every subscribed topic, by name
+    pub fn topics(&self) -> Vec<MeshTopicHealth> {
+        let mut topics: Vec<MeshTopicHealth> = self
+            .mesh_peer_count_per_topic
+            .iter()
+            .map(|(topic, &count)| self.health(topic, count))
+            .collect();
+        topics.sort_by(|a, b| a.topic.cmp(&b.topic));
+        topics
+    }
+
+    fn health(&self, topic: &TopicHash, mesh_peers: usize) -> MeshTopicHealth {
+        MeshTopicHealth {
+            topic: topic.to_string(),
+            mesh_peers,
+            mesh_n_low: self.mesh_n_low,
+            mesh_n: self.mesh_n,
+            mesh_n_high: self.mesh_n_high,
+            healthy: mesh_peers >= self.mesh_n_low,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_degraded_once_after_full_mesh() {
+        let config = SwarmConfig {
+            mesh_n_low: 4,
+            mesh_n: 6,
+            mesh_n_high: 12,
+            ..SwarmConfig::default()
+        };
+        let mut monitor = MeshHealthMonitor::new(&config);
+        let chat = TopicHash::from_raw("chat");
+        let files = TopicHash::from_raw("files");
+
+        // A mesh still forming is not reported
+        assert!(monitor
+            .update([(chat.clone(), 1), (files.clone(), 0)])
+            .is_empty());
+        assert!(monitor
+            .update([(chat.clone(), 5), (files.clone(), 2)])
+            .is_empty());
+
+        let degraded = monitor.update([(chat.clone(), 3), (files.clone(), 2)]);
+        assert_eq!(degraded.len(), 1);
+        assert_eq!(degraded[0].topic, "chat");
+        assert_eq!(degraded[0].mesh_peers, 3);
+        assert!(!degraded[0].healthy);
+        assert!(monitor
+            .update([(chat.clone(), 2), (files.clone(), 2)])
+            .is_empty());
+
+        // Reported again after recovering
+        monitor.update([(chat.clone(), 4), (files.clone(), 2)]);
+        assert_eq!(monitor.update([(chat.clone(), 0)]).len(), 1);
+
+        let topics = monitor.topics();
+        assert_eq!(topics.len(), 1, "files was unsubscribed");
+        assert_eq!(
+            topics[0],
+            MeshTopicHealth {
+                topic: "chat".to_string(),
+                mesh_peers: 0,
+                mesh_n_low: 4,
+                mesh_n: 6,
+                mesh_n_high: 12,
+                healthy: false,
+            }
+        );
+    }
+}
@@ -0,0 +1,576 @@
+//! Aggregate node statistics, for the UI and for operators.
+//!
+//! `DhtService::network_stats` returns a `NetworkStats`. The Tauri command
+//! `get_network_stats_command`, the headless `--status-interval` log line
+//! and the status socket all read it, so they show the same numbers.
+//!
+//! It is polled often, so it takes no lock. Every `SAMPLE_INTERVAL` the
+//! swarm task reads the libp2p byte counters into its `StatsCollector`,
+//! which keeps the last two readings for rates, and copies the totals,
+//! rates, reachability and transfer count into `StatsCounters` atomics.
+//! A `GetStats` command copies those out, adding the peer count, relay and
+//! routing table size the task reads from the swarm it owns, and
+//! `DhtService::network_stats` adds the last `memory_budget` measurement.
+//! `DetailedNetworkStats` adds the full metrics snapshot and the peer list;
+//! it locks the metrics and is meant for occasional use.
+//!
+//! `TransportStats` splits the byte totals by transport, from the
+//! `protocols` label libp2p puts on its counters. Bytes of a relayed
+//! connection count under relay and again under the transport of the
+//! connection to the relay; the totals add up the transports as labelled.
+//!
+//! `DashboardStats` is what the desktop dashboard polls, once a second: the
+//! `NetworkStats` fields with the identity, addresses, direct and relayed
+//! peer counts, bootstrap state and uptime, all read by the swarm task in
+//! one `GetDashboardStats` round trip without touching the metrics lock.
+//! Connections are counted by `ConnectionKinds` as the swarm reports them.
+//! Every field has a default, which is what a node that is not running
+//! shows.
+//!
+//! On Unix a headless node answers on a status socket: each connection
+//! receives `NetworkStats::summary_line` and is closed. `chiral-network
+//! --status` prints what the socket returns.
+
+use crate::dht::models::{DhtMetricsSnapshot, NatReachabilityState};
+use crate::dht::DhtService;
+use crate::memory_budget::MemoryUsageReport;
+use libp2p::PeerId;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Interval between readings of the byte counters
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// File in the data directory the status socket is bound to
+pub const STATUS_SOCKET_NAME: &str = "status.sock";
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkStats {
+    pub peers_connected: usize,
+    pub reachability: NatReachabilityState,
+    /// Relay we hold a reservation on
+    pub relay_reservation: Option<String>,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    /// Bytes per second over the last sample interval
+    pub download_rate: f64,
+    pub upload_rate: f64,
+    /// Downloads in progress plus transfers being received
+    pub active_transfers: usize,
+    /// Peers in the Kademlia routing table
+    pub dht_table_size: u64,
+    /// Cache sizes against the memory budget, measured every 15 s
+    pub memory: MemoryUsageReport,
+}
+
+/// Everything the dashboard shows
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DashboardStats {
+    /// False before the DHT is started and after it stopped
+    pub running: bool,
+    pub peer_id: Option<String>,
+    pub reachability: NatReachabilityState,
+    pub listen_addrs: Vec<String>,
+    /// Addresses confirmed reachable from outside
+    pub external_addrs: Vec<String>,
+    pub peers_connected: usize,
+    /// Peers with at least one direct connection
+    pub direct_peers: usize,
+    /// Peers reached only through a relay
+    pub relayed_peers: usize,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub download_rate: f64,
+    pub upload_rate: f64,
+    pub dht_table_size: u64,
+    pub bootstrap_connected: usize,
+    pub bootstrap_configured: usize,
+    /// The readiness check of `/readyz` passes
+    pub dht_ready: bool,
+    pub relay_reservation: Option<String>,
+    pub active_transfers: usize,
+    pub uptime_secs: u64,
+}
+
+impl DashboardStats {
+    /// `stats` with the rest filled in by the swarm task
+    pub fn from_stats(stats: NetworkStats) -> Self {
+        Self {
+            running: true,
+            peers_connected: stats.peers_connected,
+            reachability: stats.reachability,
+            relay_reservation: stats.relay_reservation,
+            bytes_received: stats.bytes_received,
+            bytes_sent: stats.bytes_sent,
+            download_rate: stats.download_rate,
+            upload_rate: stats.upload_rate,
+            active_transfers: stats.active_transfers,
+            dht_table_size: stats.dht_table_size,
+            ..Default::default()
+        }
+    }
+}
+
+/// Open connections of each peer, by whether they go through a relay
+#[derive(Debug, Default)]
+pub struct ConnectionKinds {
+    /// (direct, relayed)
+    peers: HashMap<PeerId, (usize, usize)>,
+}
+
+impl ConnectionKinds {
+    pub fn opened(&mut self, peer: PeerId, relayed: bool) {
+        let counts = self.peers.entry(peer).or_default();
+        if relayed {
+            counts.1 += 1;
+        } else {
+            counts.0 += 1;
+        }
+    }
+
+    pub fn closed(&mut self, peer: &PeerId, relayed: bool) {
+        if let Some(counts) = self.peers.get_mut(peer) {
+            let count = if relayed { &mut counts.1 } else { &mut counts.0 };
+            *count = count.saturating_sub(1);
+            if *counts == (0, 0) {
+                self.peers.remove(peer);
+            }
+        }
+    }
+
+    /// Peers with a direct connection, and peers with relayed ones only
+    pub fn peer_counts(&self) -> (usize, usize) {
+        let direct = self.peers.values().filter(|(direct, _)| *direct > 0).count();
+        (direct, self.peers.len() - direct)
+    }
+}
+
+impl NetworkStats {
+    /// One line for logs and the status socket
+    pub fn summary_line(&self) -> String {
+        format!(
+            "peers={} reachability={:?} relay={} down={}/s up={}/s transfers={} dht_peers={}",
+            self.peers_connected,
+            self.reachability,
+            self.relay_reservation.as_deref().unwrap_or("none"),
+            format_bytes(self.download_rate),
+            format_bytes(self.upload_rate),
+            self.active_transfers,
+            self.dht_table_size,
+        )
+    }
+}
+
+/// `NetworkStats` with the full metrics snapshot and peer list
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DetailedNetworkStats {
+    #[serde(flatten)]
+    pub stats: NetworkStats,
+    pub metrics: DhtMetricsSnapshot,
+    pub connected_peers: Vec<String>,
+    /// Entries in each non-empty k-bucket, nearest bucket first
+    pub kbucket_sizes: Vec<usize>,
+}
+
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0}{}", value, UNITS[unit])
+    } else {
+        format!("{:.1}{}", value, UNITS[unit])
+    }
+}
+
+/// Bytes moved per transport since the node started
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransportStats {
+    pub tcp_bytes_sent: u64,
+    pub tcp_bytes_received: u64,
+    pub quic_bytes_sent: u64,
+    pub quic_bytes_received: u64,
+    pub ws_bytes_sent: u64,
+    pub ws_bytes_received: u64,
+    /// Over `/p2p-circuit` connections
+    pub relay_bytes_sent: u64,
+    pub relay_bytes_received: u64,
+}
+
+impl TransportStats {
+    /// Count `bytes` under the transport of a `protocols` label such as
+    /// `/ip4/tcp`; stacks of no known transport are left out
+    pub fn add(&mut self, protocols: &str, inbound: bool, bytes: u64) {
+        let (received, sent) = if protocols.contains("/p2p-circuit") {
+            (&mut self.relay_bytes_received, &mut self.relay_bytes_sent)
+        } else if protocols.contains("/quic") {
+            (&mut self.quic_bytes_received, &mut self.quic_bytes_sent)
+        } else if protocols.contains("/ws") {
+            (&mut self.ws_bytes_received, &mut self.ws_bytes_sent)
+        } else if protocols.contains("/tcp") {
+            (&mut self.tcp_bytes_received, &mut self.tcp_bytes_sent)
+        } else {
+            return;
+        };
+        if inbound {
+            *received += bytes;
+        } else {
+            *sent += bytes;
+        }
+    }
+
+    pub fn bytes_received(&self) -> u64 {
+        self.tcp_bytes_received
+            + self.quic_bytes_received
+            + self.ws_bytes_received
+            + self.relay_bytes_received
+    }
+
+    pub fn bytes_sent(&self) -> u64 {
+        self.tcp_bytes_sent + self.quic_bytes_sent + self.ws_bytes_sent + self.relay_bytes_sent
+    }
+
+    fn to_array(self) -> [u64; 8] {
+        [
+            self.tcp_bytes_sent,
+            self.tcp_bytes_received,
+            self.quic_bytes_sent,
+            self.quic_bytes_received,
+            self.ws_bytes_sent,
+            self.ws_bytes_received,
+            self.relay_bytes_sent,
+            self.relay_bytes_received,
+        ]
+    }
+
+    fn from_array(values: [u64; 8]) -> Self {
+        Self {
+            tcp_bytes_sent: values[0],
+            tcp_bytes_received: values[1],
+            quic_bytes_sent: values[2],
+            quic_bytes_received: values[3],
+            ws_bytes_sent: values[4],
+            ws_bytes_received: values[5],
+            relay_bytes_sent: values[6],
+            relay_bytes_received: values[7],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at: Instant,
+    transports: TransportStats,
+}
+
+/// Last two readings of the transport byte counters
+#[derive(Debug, Default)]
+pub struct StatsCollector {
+    previous: Option<Sample>,
+    latest: Option<Sample>,
+}
+
+impl StatsCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, at: Instant, transports: TransportStats) {
+        self.previous = self.latest.replace(Sample { at, transports });
+    }
+
+    /// Per-transport totals from the latest reading
+    pub fn transports(&self) -> TransportStats {
+        self.latest
+            .map(|sample| sample.transports)
+            .unwrap_or_default()
+    }
+
+    /// Totals from the latest reading
+    pub fn totals(&self) -> (u64, u64) {
+        let transports = self.transports();
+        (transports.bytes_received(), transports.bytes_sent())
+    }
+
+    /// Download and upload bytes per second between the last two readings
+    pub fn rates(&self) -> (f64, f64) {
+        let (Some(previous), Some(latest)) = (self.previous, self.latest) else {
+            return (0.0, 0.0);
+        };
+        let secs = latest
+            .at
+            .saturating_duration_since(previous.at)
+            .as_secs_f64();
+        if secs <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let (latest, previous) = (latest.transports, previous.transports);
+        (
+            latest
+                .bytes_received()
+                .saturating_sub(previous.bytes_received()) as f64
+                / secs,
+            latest.bytes_sent().saturating_sub(previous.bytes_sent()) as f64 / secs,
+        )
+    }
+}
+
+/// The values of `NetworkStats` sampled by the swarm task, readable
+/// without a lock
+#[derive(Debug, Default)]
+pub struct StatsCounters {
+    reachability: AtomicU8,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    /// `f64` bits
+    download_rate: AtomicU64,
+    upload_rate: AtomicU64,
+    active_transfers: AtomicUsize,
+    /// `TransportStats` fields, in declaration order
+    transport_bytes: [AtomicU64; 8],
+}
+
+fn reachability_code(state: NatReachabilityState) -> u8 {
+    match state {
+        NatReachabilityState::Unknown => 0,
+        NatReachabilityState::Public => 1,
+        NatReachabilityState::Private => 2,
+    }
+}
+
+fn reachability_from_code(code: u8) -> NatReachabilityState {
+    match code {
+        1 => NatReachabilityState::Public,
+        2 => NatReachabilityState::Private,
+        _ => NatReachabilityState::Unknown,
+    }
+}
+
+impl StatsCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Publish the latest sample
+    pub fn store(
+        &self,
+        collector: &StatsCollector,
+        reachability: NatReachabilityState,
+        active_transfers: usize,
+    ) {
+        let (received, sent) = collector.totals();
+        let (download_rate, upload_rate) = collector.rates();
+        self.reachability
+            .store(reachability_code(reachability), Ordering::Relaxed);
+        self.bytes_received.store(received, Ordering::Relaxed);
+        self.bytes_sent.store(sent, Ordering::Relaxed);
+        self.download_rate
+            .store(download_rate.to_bits(), Ordering::Relaxed);
+        self.upload_rate
+            .store(upload_rate.to_bits(), Ordering::Relaxed);
+        self.active_transfers
+            .store(active_transfers, Ordering::Relaxed);
+        for (counter, value) in self
+            .transport_bytes
+            .iter()
+            .zip(collector.transports().to_array())
+        {
+            counter.store(value, Ordering::Relaxed);
+        }
+    }
+
+    /// The published per-transport totals
+    pub fn transports(&self) -> TransportStats {
+        TransportStats::from_array(std::array::from_fn(|i| {
+            self.transport_bytes[i].load(Ordering::Relaxed)
+        }))
+    }
+
+    /// The published values, with what the swarm task reads itself
+    pub fn snapshot(
+        &self,
+        peers_connected: usize,
+        relay_reservation: Option<String>,
+        dht_table_size: u64,
+    ) -> NetworkStats {
+        NetworkStats {
+            peers_connected,
+            reachability: reachability_from_code(self.reachability.load(Ordering::Relaxed)),
+            relay_reservation,
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            download_rate: f64::from_bits(self.download_rate.load(Ordering::Relaxed)),
+            upload_rate: f64::from_bits(self.upload_rate.load(Ordering::Relaxed)),
+            active_transfers: self.active_transfers.load(Ordering::Relaxed),
+            dht_table_size,
+            memory: MemoryUsageReport::default(),
+        }
+    }
+}
+
+/// libp2p's bandwidth counters, by transport
+pub fn transport_bytes(registry: &prometheus_client::registry::Registry) -> TransportStats {
+    let mut transports = TransportStats::default();
+    let mut encoded = String::new();
+    if prometheus_client::encoding::text::encode(&mut encoded, registry).is_err() {
+        return transports;
+    }
+    for line in encoded.lines().filter(|line| !line.starts_with('#')) {
+        let Some((series, value)) = line.rsplit_once(' ') else {
+            continue;
+        };
+        if !series.contains("_bandwidth_") {
+            continue;
+        }
+        let Ok(value) = value.parse::<u64>() else {
+            continue;
+        };
+        let series = series.to_ascii_lowercase();
+        let Some(protocols) = label(&series, "protocols") else {
+            continue;
+        };
+        if series.contains("direction=\"inbound\"") {
+            transports.add(protocols, true, value);
+        } else if series.contains("direction=\"outbound\"") {
+            transports.add(protocols, false, value);
+        }
+    }
+    transports
+}
+
+/// The value of `name` in a series such as `total{name="value"}`
+fn label<'a>(series: &'a str, name: &str) -> Option<&'a str> {
+    let start = series.find(&format!("{}=\"", name))? + name.len() + 2;
+    let len = series[start..].find('"')?;
+    Some(&series[start..start + len])
+}
+
+/// Log the summary line every `interval` for as long as the node runs
+pub async fn log_status(dht: Arc<DhtService>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick fires at once, before anything has happened
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if Arc::strong_count(&dht) <= 1 {
+            break;
+        }
+        tracing::info!("Status: {}", dht.network_stats().await.summary_line());
+    }
+}
+
+/// Answer every connection on `path` with the summary line
+///
+/// A socket file left behind by an earlier run is replaced, so the caller
+/// must hold the data directory lock.
+#[cfg(unix)]
+pub async fn serve_status_socket(
+    dht: Arc<DhtService>,
+    path: &std::path::Path,
+) -> std::io::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    match std::fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    tokio::spawn(async move {
+        loop {
+            let mut stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::warn!("Status socket accept failed: {}", e);
+                    continue;
+                }
+            };
+            let mut line = dht.network_stats().await.summary_line();
+            line.push('\n');
+            let _ = stream.write_all(line.as_bytes()).await;
+        }
+    });
+    Ok(())
+}
+
+/// The summary line of the node serving `path`
+#[cfg(unix)]
+pub async fn query_status_socket(path: &std::path::Path) -> std::io::Result<String> {
+    use tokio::io::AsyncReadExt;
+
+    let mut stream = tokio::net::UnixStream::connect(path).await?;
+    let mut line = String::new();
+    stream.read_to_string(&mut line).await?;
+    Ok(line.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates_come_from_the_last_two_samples() {
+        let mut collector = StatsCollector::new();
+        let start = Instant::now();
+        assert_eq!(collector.rates(), (0.0, 0.0));
+        let sample = |tcp_received, relay_received, sent| {
+            let mut transports = TransportStats::default();
+            transports.add("/ip4/tcp", true, tcp_received);
+            transports.add("/ip4/tcp/p2p/p2p-circuit", true, relay_received);
+            transports.add("/ip6/tcp/ws", false, sent);
+            transports
+        };
+        collector.record(start, sample(1_000, 0, 500));
+        assert_eq!(collector.rates(), (0.0, 0.0));
+        collector.record(start + Duration::from_secs(2), sample(4_000, 1_000, 1_500));
+        collector.record(start + Duration::from_secs(4), sample(8_000, 1_096, 1_500));
+        assert_eq!(collector.totals(), (9_096, 1_500));
+        assert_eq!(collector.rates(), (2_048.0, 0.0));
+
+        let counters = StatsCounters::new();
+        counters.store(&collector, NatReachabilityState::Private, 1);
+        let stats = counters.snapshot(3, None, 12);
+        assert_eq!(stats.reachability, NatReachabilityState::Private);
+        assert_eq!(stats.bytes_received, 9_096);
+        assert_eq!(stats.active_transfers, 1);
+        let transports = counters.transports();
+        assert_eq!(transports.relay_bytes_received, 1_096);
+        assert_eq!(transports.ws_bytes_sent, 1_500);
+        assert_eq!(transports.quic_bytes_received, 0);
+        assert_eq!(
+            label("b_total{protocols=\"/ip4/tcp\"}", "protocols"),
+            Some("/ip4/tcp")
+        );
+        assert_eq!(
+            stats.summary_line(),
+            "peers=3 reachability=Private relay=none down=2.0KiB/s up=0B/s transfers=1 dht_peers=12"
+        );
+
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let mut kinds = ConnectionKinds::default();
+        kinds.opened(a, true);
+        kinds.opened(b, true);
+        assert_eq!(kinds.peer_counts(), (0, 2));
+        // A hole punch adds a direct connection next to the relayed one
+        kinds.opened(a, false);
+        kinds.closed(&a, true);
+        assert_eq!(kinds.peer_counts(), (1, 1));
+        kinds.closed(&b, true);
+        kinds.closed(&b, true);
+        assert_eq!(kinds.peer_counts(), (1, 0));
+        let dashboard = DashboardStats::from_stats(stats);
+        assert!(dashboard.running && !DashboardStats::default().running);
+        assert_eq!(dashboard.dht_table_size, 12);
+    }
+}
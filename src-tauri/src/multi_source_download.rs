@@ -1,39 +1,55 @@
 use crate::analytics::AnalyticsService;
+use crate::bandwidth::TransferRateLimit;
 use crate::bittorrent_handler::BitTorrentHandler;
+use crate::chunk_pipeline::{PipelineConfig, RequestWindow, RequestWindowInfo};
+use crate::chunk_scheduler::{ChunkScheduler, ENDGAME_CHUNKS};
 use crate::dht::{DhtService, models::FileMetadata, WebRTCOfferRequest};
+use crate::download_resume::{
+    self, PartialCipher, ResumeOutcome, ResumePlan, ResumeRecord, ResumeStore, ResumedDownload,
+};
 use crate::download_source::{
     BitTorrentSourceInfo, DownloadSource, Ed2kSourceInfo as DownloadEd2kSourceInfo,
-    FtpSourceInfo as DownloadFtpSourceInfo,
+    FtpSourceInfo as DownloadFtpSourceInfo, HttpSourceInfo, WebSeeds,
 };
 use crate::ed2k_client::{Ed2kClient, Ed2kConfig, ED2K_CHUNK_SIZE};
 use crate::transfer_events::{
     TransferEventBus, TransferStartedEvent, SourceConnectedEvent, SourceDisconnectedEvent,
     ChunkCompletedEvent, ChunkFailedEvent, TransferProgressEvent, TransferCompletedEvent,
-    TransferFailedEvent, SourceInfo, SourceType, SourceSummary, DisconnectReason, ErrorCategory,
-    current_timestamp_ms, calculate_progress,
+    TransferFailedEvent, TransferRecoveringEvent, SourceInfo, SourceType, SourceSummary,
+    DisconnectReason, ErrorCategory, current_timestamp_ms, calculate_progress,
 };
 use crate::ftp_downloader::{FtpCredentials, FtpDownloader};
+use crate::memory_budget::MemoryBudget;
+use crate::provider_probe::{self, ProviderProbe, MAX_PROBED_PROVIDERS, PROBE_SAMPLE_BYTES, PROBE_TIMEOUT};
+use crate::source_exclusion::{ExcludedSource, SourceExclusions};
+use crate::stall_recovery::{StallDetector, StallPolicy, StallVerdict};
 use crate::webrtc_service::{WebRTCFileRequest, WebRTCService};
+use futures::stream::{FuturesUnordered, StreamExt};
 use md4::Md4;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use suppaftp::FtpStream;
 use tokio::sync::{mpsc, Mutex, RwLock};
 use tokio::time::timeout;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 use url::Url;
 
 const DEFAULT_CHUNK_SIZE: usize = 256 * 1024; // 256KB chunks
 const MAX_CHUNKS_PER_PEER: usize = 10; // Maximum chunks to assign to a single peer
 const MIN_CHUNKS_FOR_PARALLEL: usize = 4; // Minimum chunks to enable parallel download
+/// Source id of chunks read back from an interrupted download's partial file
+const RESUMED_SOURCE_ID: &str = "local-partial";
 const CONNECTION_TIMEOUT_SECS: u64 = 30;
 #[allow(dead_code)]
 const CHUNK_REQUEST_TIMEOUT_SECS: u64 = 60;
 #[allow(dead_code)]
 const MAX_RETRY_ATTEMPTS: u32 = 3;
+const MAX_RANGE_SEEDERS: usize = 3; // Seeders tried for a single range request
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "camelCase")]
@@ -62,6 +78,10 @@ pub struct SourceAssignment {
 
     /// Timestamp of last activity from this source
     pub last_activity: Option<u64>,
+
+    /// Request pipelining state, for sources that pipeline chunk requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline: Option<RequestWindowInfo>,
 }
 
 /// Status of a download source
@@ -84,6 +104,7 @@ impl SourceAssignment {
             status: SourceStatus::Connecting,
             connected_at: None,
             last_activity: None,
+            pipeline: None,
         }
     }
 
@@ -113,6 +134,85 @@ fn normalized_sha256_hex(hash: &str) -> Option<String> {
     }
 }
 
+/// Source assignments of `download` with their request windows filled in
+fn source_assignments(download: &ActiveDownload) -> Vec<SourceAssignment> {
+    download
+        .source_assignments
+        .iter()
+        .map(|(source_id, assignment)| SourceAssignment {
+            pipeline: download.request_windows.get(source_id).cloned(),
+            ..assignment.clone()
+        })
+        .collect()
+}
+
+/// Verified bytes of `download` that were fetched from its web seeds
+fn web_seed_bytes(download: &ActiveDownload) -> u64 {
+    let Some(web_seeds) = &download.file_metadata.web_seeds else {
+        return 0;
+    };
+    download
+        .completed_chunks
+        .values()
+        .filter(|chunk| web_seeds.urls.contains(&chunk.source_id))
+        .map(|chunk| chunk.data.len() as u64)
+        .sum()
+}
+
+/// The web seeds of `metadata` if its publisher signed them; anything else
+/// is ignored, since any node can write a file's record
+fn verified_web_seeds(metadata: &FileMetadata) -> Option<&WebSeeds> {
+    let seeds = metadata.web_seeds.as_ref()?;
+    match crate::download_source::verify_web_seeds(
+        seeds,
+        &metadata.merkle_root,
+        metadata.file_size,
+        &metadata.seeders,
+    ) {
+        Ok(()) => Some(seeds),
+        Err(e) => {
+            warn!("Ignoring web seeds of {}: {}", metadata.merkle_root, e);
+            None
+        }
+    }
+}
+
+/// Failed range request for one chunk
+struct HttpChunkError {
+    timed_out: bool,
+    message: String,
+}
+
+/// Send a range request and read the partial content it returns
+async fn fetch_http_range(request: reqwest::RequestBuilder, chunk_id: u32) -> Result<Vec<u8>, HttpChunkError> {
+    let failed = |e: reqwest::Error, what: &str| HttpChunkError {
+        timed_out: e.is_timeout(),
+        message: format!("{} for chunk {}: {}", what, chunk_id, e),
+    };
+    let response = request
+        .send()
+        .await
+        .map_err(|e| failed(e, "HTTP request failed"))?;
+
+    // Check for partial content response
+    if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        return Err(HttpChunkError {
+            timed_out: false,
+            message: format!(
+                "HTTP server doesn't support range requests for chunk {} (status: {})",
+                chunk_id,
+                response.status()
+            ),
+        });
+    }
+
+    response
+        .bytes()
+        .await
+        .map(|data| data.to_vec())
+        .map_err(|e| failed(e, "Failed to read HTTP response"))
+}
+
 fn verify_chunk_integrity(chunk: &ChunkInfo, data: &[u8]) -> Result<(), (String, String)> {
     let expected = match normalized_sha256_hex(&chunk.hash) {
         Some(value) => value,
@@ -130,6 +230,138 @@ fn verify_chunk_integrity(chunk: &ChunkInfo, data: &[u8]) -> Result<(), (String,
     Ok(())
 }
 
+/// Assemble the whole file from its completed chunks. Fails if a chunk is
+/// missing, has the wrong length or no longer matches its hash.
+fn assemble_verified_file(
+    chunks: &[ChunkInfo],
+    completed: &HashMap<u32, CompletedChunk>,
+    file_size: u64,
+) -> Result<Vec<u8>, String> {
+    let mut file_data = vec![0u8; file_size as usize];
+    for chunk in chunks {
+        let completed_chunk = completed
+            .get(&chunk.chunk_id)
+            .ok_or_else(|| format!("Chunk {} is missing", chunk.chunk_id))?;
+        let start = chunk.offset as usize;
+        let end = start + chunk.size;
+        if completed_chunk.data.len() != chunk.size || end > file_data.len() {
+            return Err(format!(
+                "Chunk {} has {} bytes, expected {}",
+                chunk.chunk_id,
+                completed_chunk.data.len(),
+                chunk.size
+            ));
+        }
+        verify_chunk_integrity(chunk, &completed_chunk.data).map_err(|(expected, actual)| {
+            format!(
+                "Chunk {} failed verification: expected {}, got {}",
+                chunk.chunk_id, expected, actual
+            )
+        })?;
+        file_data[start..end].copy_from_slice(&completed_chunk.data);
+    }
+    Ok(file_data)
+}
+
+/// Source and failure of every completed chunk that no longer matches its
+/// hash
+fn corrupt_chunk_sources(
+    chunks: &[ChunkInfo],
+    completed: &HashMap<u32, CompletedChunk>,
+) -> Vec<(String, String)> {
+    chunks
+        .iter()
+        .filter_map(|chunk| {
+            let completed_chunk = completed.get(&chunk.chunk_id)?;
+            let (expected, actual) = verify_chunk_integrity(chunk, &completed_chunk.data).err()?;
+            let reason = format!(
+                "Chunk {} failed verification: expected {}, got {}",
+                chunk.chunk_id, expected, actual
+            );
+            Some((completed_chunk.source_id.clone(), reason))
+        })
+        .collect()
+}
+
+/// Check the assembled file against its SHA-256 file hash. Encrypted files
+/// and keys that are no SHA-256 hash (info hashes, ED2K hashes) are left to
+/// the per-chunk checks.
+fn verify_file_hash(metadata: &FileMetadata, data: &[u8]) -> Result<(), String> {
+    if metadata.is_encrypted {
+        return Ok(());
+    }
+    let Some(expected) = normalized_sha256_hex(&metadata.merkle_root) else {
+        return Ok(());
+    };
+    let actual = hex::encode(Sha256::digest(data));
+    if actual != expected {
+        return Err(format!(
+            "File failed verification: expected {}, got {}",
+            expected, actual
+        ));
+    }
+    Ok(())
+}
+
+/// Result of reading a byte range from an in-progress download
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeRead {
+    /// Every chunk covering the range has been verified
+    Ready(Vec<u8>),
+    /// The range is not servable yet; `chunk_id` is the first missing chunk
+    NotYetAvailable { chunk_id: u32 },
+}
+
+/// Serve `len` bytes at `offset` from verified chunks only.
+///
+/// Completed chunks are checked with `verify_chunk_integrity` before they are
+/// stored, so anything in `completed` is safe to hand to a media player.
+/// Ranges running past the end of the file are truncated.
+fn read_verified_range(
+    chunks: &[ChunkInfo],
+    completed: &HashMap<u32, CompletedChunk>,
+    file_size: u64,
+    offset: u64,
+    len: u64,
+) -> Result<RangeRead, String> {
+    if offset >= file_size {
+        return Err(format!(
+            "Offset {} is beyond end of file ({} bytes)",
+            offset, file_size
+        ));
+    }
+    let end = offset.saturating_add(len).min(file_size);
+    let mut data = Vec::with_capacity((end - offset) as usize);
+
+    for chunk in chunks {
+        let chunk_end = chunk.offset + chunk.size as u64;
+        if chunk_end <= offset || chunk.offset >= end {
+            continue;
+        }
+        let completed_chunk = match completed.get(&chunk.chunk_id) {
+            Some(c) => c,
+            None => return Ok(RangeRead::NotYetAvailable { chunk_id: chunk.chunk_id }),
+        };
+        let start = (offset.max(chunk.offset) - chunk.offset) as usize;
+        let stop = ((end.min(chunk_end) - chunk.offset) as usize).min(completed_chunk.data.len());
+        if start < stop {
+            data.extend_from_slice(&completed_chunk.data[start..stop]);
+        }
+    }
+
+    Ok(RangeRead::Ready(data))
+}
+
+/// Take the next batch of failed chunks to retry. Sequential downloads retry
+/// the lowest chunk ids first so playback is unblocked as early as possible.
+fn next_retry_batch(failed: &mut VecDeque<u32>, sequential: bool, limit: usize) -> Vec<u32> {
+    if sequential {
+        failed.make_contiguous().sort_unstable();
+    }
+    let take = failed.len().min(limit);
+    failed.drain(..take).collect()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MultiSourceProgress {
@@ -143,6 +375,18 @@ pub struct MultiSourceProgress {
     pub download_speed_bps: f64,
     pub eta_seconds: Option<u32>,
     pub source_assignments: Vec<SourceAssignment>,
+    /// Per-transfer bandwidth limit, filled in by the caller that owns the limiter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<TransferRateLimit>,
+    /// Results of the provider probe that chose the sources
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provider_probes: Vec<ProviderProbe>,
+    /// Providers dropped for this file after serving corrupt chunks
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub excluded_sources: Vec<ExcludedSource>,
+    /// Verified bytes that came from HTTP(S) web seeds rather than peers
+    #[serde(default)]
+    pub web_seed_bytes: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -179,6 +423,20 @@ pub struct ActiveDownload {
     pub start_time: Instant,
     pub last_progress_update: Instant,
     pub output_path: String,
+    /// Fetch chunks strictly in order (streaming/media playback)
+    pub sequential: bool,
+    /// What the provider probe found before sources were chosen
+    pub provider_probes: Vec<ProviderProbe>,
+    /// Which sources hold which chunks, for rarest-first scheduling
+    pub chunk_scheduler: ChunkScheduler,
+    /// Sources excluded after repeated verification failures
+    pub excluded_sources: Vec<ExcludedSource>,
+    /// Replace an existing file at `output_path` instead of picking a free name
+    pub overwrite: bool,
+    /// Latest request window of each pipelining source
+    pub request_windows: HashMap<String, RequestWindowInfo>,
+    /// Session key of the partial file, when it is kept encrypted
+    pub partial_cipher: Option<PartialCipher>,
 }
 
 pub struct MultiSourceDownloadService {
@@ -200,6 +458,20 @@ pub struct MultiSourceDownloadService {
     transfer_event_bus: Arc<TransferEventBus>,
     // Analytics service for backend metrics tracking
     analytics_service: Arc<AnalyticsService>,
+    // Providers that failed verification too often, per file hash
+    source_exclusions: Arc<Mutex<SourceExclusions>>,
+    // Whether web seeds from file metadata may be used as sources
+    web_seeds_enabled: Arc<AtomicBool>,
+    // Partial data and resume records of unfinished downloads
+    resume_store: Arc<ResumeStore>,
+    // Whether new downloads are queued again after a restart
+    auto_resume: bool,
+    // Whether downloads keep their partial data encrypted unless told otherwise
+    encrypt_partials: bool,
+    // Chunk requests kept outstanding per source
+    pipeline: PipelineConfig,
+    // When a download counts as stalled and how often it is recovered
+    stall_policy: StallPolicy,
 }
 
 #[derive(Debug, Serialize)]
@@ -209,6 +481,10 @@ pub enum MultiSourceCommand {
         output_path: String,
         max_peers: Option<usize>,
         chunk_size: Option<usize>,
+        sequential: bool,
+        probe_providers: Option<bool>,
+        overwrite: bool,
+        encrypt_partials: Option<bool>,
     },
     CancelDownload {
         file_hash: String,
@@ -216,6 +492,12 @@ pub enum MultiSourceCommand {
     RetryFailedChunks {
         file_hash: String,
     },
+    /// No verified data for a while; replace the silent sources
+    RecoverStalled {
+        file_hash: String,
+        attempt: u32,
+        stalled_for: Duration,
+    },
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -270,6 +552,9 @@ impl MultiSourceDownloadService {
     ) -> Self {
         let (event_tx, event_rx) = mpsc::unbounded_channel();
         let (command_tx, command_rx) = mpsc::unbounded_channel();
+        let chiral_config = crate::config::ChiralConfig::from_env();
+        let downloads_config = chiral_config.downloads;
+        let memory_budget = MemoryBudget::from_mb(chiral_config.swarm.memory_budget_mb);
 
         Self {
             dht_service,
@@ -288,6 +573,235 @@ impl MultiSourceDownloadService {
             ed2k_connections: Arc::new(Mutex::new(HashMap::new())),
             transfer_event_bus,
             analytics_service,
+            source_exclusions: Arc::new(Mutex::new(SourceExclusions::new())),
+            web_seeds_enabled: Arc::new(AtomicBool::new(downloads_config.web_seeds)),
+            resume_store: Arc::new(ResumeStore::load(ResumeStore::default_path())),
+            auto_resume: downloads_config.auto_resume,
+            encrypt_partials: downloads_config.encrypt_partials,
+            pipeline: memory_budget.map_or(PipelineConfig::from(&downloads_config), |budget| {
+                budget.cap_pipeline(PipelineConfig::from(&downloads_config))
+            }),
+            stall_policy: StallPolicy::from(&downloads_config),
+        }
+    }
+
+    /// Allow or forbid fetching from HTTP(S) web seeds for downloads started from now on
+    pub fn set_web_seeds_enabled(&self, enabled: bool) {
+        self.web_seeds_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn web_seeds_enabled(&self) -> bool {
+        self.web_seeds_enabled.load(Ordering::Relaxed)
+    }
+
+    /// Unfinished downloads that can be picked up again, oldest first
+    pub async fn resumable_downloads(&self) -> Vec<ResumeRecord> {
+        self.resume_store.records().await
+    }
+
+    /// Partial files of active and resumable downloads, which storage
+    /// cleanup must keep
+    pub async fn partial_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self
+            .resume_store
+            .records()
+            .await
+            .iter()
+            .map(ResumeRecord::partial_path)
+            .collect();
+        paths.extend(
+            self.active_downloads
+                .read()
+                .await
+                .values()
+                .map(|download| download_resume::partial_path(&download.output_path)),
+        );
+        paths
+    }
+
+    /// Choose whether an unfinished download is queued again on startup
+    pub async fn set_auto_resume(&self, file_hash: &str, auto_resume: bool) -> Result<(), String> {
+        if !self.resume_store.set_auto_resume(file_hash, auto_resume).await {
+            return Err(format!("No unfinished download for file {}", file_hash));
+        }
+        self.resume_store.flush().await
+    }
+
+    /// Queue every unfinished download marked auto-resume again.
+    ///
+    /// Provider discovery runs afresh, as the sources of the interrupted run
+    /// are probably gone; each download keeps whatever partial data survives
+    /// the spot check.
+    pub async fn resume_incomplete_downloads(&self) -> Vec<ResumedDownload> {
+        let mut resumed = Vec::new();
+        for record in self.resume_store.records().await {
+            if !record.auto_resume
+                || self.active_downloads.read().await.contains_key(&record.file_hash)
+            {
+                continue;
+            }
+            info!("Resuming interrupted download of {}", record.file_name);
+            let outcome = self
+                .handle_start_download(
+                    record.file_hash.clone(),
+                    record.output_path.clone(),
+                    None,
+                    Some(record.chunk_size),
+                    record.sequential,
+                    None,
+                    record.overwrite,
+                    Some(record.encrypt_partials),
+                )
+                .await
+                .unwrap_or_else(|error| ResumeOutcome::Failed { error });
+            resumed.push(ResumedDownload {
+                file_hash: record.file_hash,
+                file_name: record.file_name,
+                output_path: record.output_path,
+                outcome,
+            });
+        }
+        resumed
+    }
+
+    /// Take over the partial data of an interrupted run of this download, or
+    /// start a new resume record for it
+    async fn restore_partial_download(
+        &self,
+        file_hash: &str,
+        output_path: &str,
+        metadata: &FileMetadata,
+        chunk_size: usize,
+        sequential: bool,
+        overwrite: bool,
+        encrypt_partials: bool,
+    ) -> (HashMap<u32, CompletedChunk>, ResumeOutcome) {
+        let mut restored = HashMap::new();
+        let mut completed_chunks = std::collections::BTreeMap::new();
+        let previous = self.resume_store.get(file_hash).await;
+
+        let outcome = match &previous {
+            Some(record) if record.output_path == output_path => {
+                match download_resume::plan_resume(
+                    record,
+                    &metadata.merkle_root,
+                    metadata.file_size,
+                    chunk_size,
+                )
+                .await
+                {
+                    ResumePlan::Continue(chunks) => {
+                        let chunks_kept = chunks.len() as u32;
+                        let bytes_kept = chunks.iter().map(|(_, data)| data.len() as u64).sum();
+                        for (chunk_id, data) in chunks {
+                            if let Some(digest) = record.completed_chunks.get(&chunk_id) {
+                                completed_chunks.insert(chunk_id, digest.clone());
+                            }
+                            restored.insert(
+                                chunk_id,
+                                CompletedChunk {
+                                    chunk_id,
+                                    data,
+                                    source_id: RESUMED_SOURCE_ID.to_string(),
+                                    completed_at: Instant::now(),
+                                },
+                            );
+                        }
+                        info!(
+                            "Continuing download of {} with {} chunk(s) from its partial file",
+                            metadata.file_name, chunks_kept
+                        );
+                        ResumeOutcome::Continued {
+                            chunks_kept,
+                            bytes_kept,
+                        }
+                    }
+                    ResumePlan::Restart(reason) => {
+                        warn!(
+                            "Restarting download of {} from scratch: {:?}",
+                            metadata.file_name, reason
+                        );
+                        ResumeOutcome::Restarted { reason }
+                    }
+                }
+            }
+            Some(record) => {
+                // Downloading to a new destination; the old partial file is of no use
+                let _ = tokio::fs::remove_file(record.partial_path()).await;
+                ResumeOutcome::Fresh
+            }
+            None => ResumeOutcome::Fresh,
+        };
+        if restored.is_empty() {
+            let _ = tokio::fs::remove_file(download_resume::partial_path(output_path)).await;
+        }
+
+        self.resume_store
+            .insert(ResumeRecord {
+                file_hash: file_hash.to_string(),
+                file_name: metadata.file_name.clone(),
+                output_path: output_path.to_string(),
+                manifest_root: metadata.merkle_root.clone(),
+                file_size: metadata.file_size,
+                chunk_size,
+                sequential,
+                overwrite,
+                auto_resume: previous.map_or(self.auto_resume, |r| r.auto_resume),
+                encrypt_partials,
+                completed_chunks,
+                updated_at: 0,
+            })
+            .await;
+        if let Err(e) = self.resume_store.flush().await {
+            warn!("Failed to save resume record for {}: {}", file_hash, e);
+        }
+        (restored, outcome)
+    }
+
+    /// Write chunks completed since the last checkpoint to the partial file
+    /// and record them, so an interrupted download can continue from there
+    async fn checkpoint_partial_download(
+        downloads: &Arc<RwLock<HashMap<String, ActiveDownload>>>,
+        resume_store: &ResumeStore,
+        file_hash: &str,
+    ) {
+        let Some(record) = resume_store.get(file_hash).await else {
+            return;
+        };
+        let (cipher, new_chunks): (Option<PartialCipher>, Vec<(u32, u64, Vec<u8>)>) = {
+            let downloads = downloads.read().await;
+            let Some(download) = downloads.get(file_hash) else {
+                return;
+            };
+            let new_chunks = download
+                .chunks
+                .iter()
+                .filter(|chunk| !record.completed_chunks.contains_key(&chunk.chunk_id))
+                .filter_map(|chunk| {
+                    download
+                        .completed_chunks
+                        .get(&chunk.chunk_id)
+                        .map(|done| (chunk.chunk_id, chunk.offset, done.data.clone()))
+                })
+                .collect();
+            (download.partial_cipher.clone(), new_chunks)
+        };
+
+        let partial_path = record.partial_path();
+        for (chunk_id, offset, data) in new_chunks {
+            let digest = download_resume::sha256_hex(&data);
+            let mut stored = data;
+            if let Some(cipher) = &cipher {
+                cipher.apply(offset, &mut stored);
+            }
+            if let Err(e) = download_resume::write_partial_chunk(&partial_path, offset, &stored).await {
+                warn!("Failed to write partial data for {}: {}", file_hash, e);
+                break;
+            }
+            resume_store.record_chunk(file_hash, chunk_id, digest).await;
+        }
+        if let Err(e) = resume_store.flush().await {
+            warn!("Failed to save resume record for {}: {}", file_hash, e);
         }
     }
 
@@ -297,6 +811,10 @@ impl MultiSourceDownloadService {
         output_path: String,
         max_peers: Option<usize>,
         chunk_size: Option<usize>,
+        sequential: bool,
+        probe_providers: Option<bool>,
+        overwrite: bool,
+        encrypt_partials: Option<bool>,
     ) -> Result<(), String> {
         self.command_tx
             .send(MultiSourceCommand::StartDownload {
@@ -304,6 +822,10 @@ impl MultiSourceDownloadService {
                 output_path,
                 max_peers,
                 chunk_size,
+                sequential,
+                probe_providers,
+                overwrite,
+                encrypt_partials,
             })
             .map_err(|e| format!("Failed to send download command: {}", e))
     }
@@ -314,6 +836,150 @@ impl MultiSourceDownloadService {
             .map_err(|e| format!("Failed to send cancel command: {}", e))
     }
 
+    /// Switch an active download between sequential and availability-based
+    /// scheduling. Takes effect for queued and retried chunks immediately.
+    pub async fn set_sequential(&self, file_hash: &str, sequential: bool) -> Result<(), String> {
+        let mut downloads = self.active_downloads.write().await;
+        let download = downloads
+            .get_mut(file_hash)
+            .ok_or_else(|| format!("Active download not found for file {}", file_hash))?;
+
+        download.sequential = sequential;
+        download.chunk_scheduler.set_sequential(sequential);
+        if sequential {
+            download.failed_chunks.make_contiguous().sort_unstable();
+            for assignment in download.source_assignments.values_mut() {
+                assignment.chunks.sort_unstable();
+            }
+        }
+        info!(
+            "Download {} switched to {} mode",
+            file_hash,
+            if sequential { "sequential" } else { "parallel" }
+        );
+        Ok(())
+    }
+
+    /// Read a byte range of an active download as soon as the chunks covering
+    /// it are verified, waiting up to `wait` for missing chunks to arrive.
+    pub async fn read_range(
+        &self,
+        file_hash: &str,
+        offset: u64,
+        len: u64,
+        wait: Duration,
+    ) -> Result<RangeRead, String> {
+        let deadline = Instant::now() + wait;
+        let mut tried_range_request = false;
+        loop {
+            let result = {
+                let downloads = self.active_downloads.read().await;
+                let download = downloads
+                    .get(file_hash)
+                    .ok_or_else(|| format!("Active download not found for file {}", file_hash))?;
+                read_verified_range(
+                    &download.chunks,
+                    &download.completed_chunks,
+                    download.file_metadata.file_size,
+                    offset,
+                    len,
+                )?
+            };
+
+            if matches!(result, RangeRead::Ready(_)) || Instant::now() >= deadline {
+                return Ok(result);
+            }
+
+            // A seek ahead of the download: fetch just this range from a
+            // connected peer instead of waiting for its chunks to come up
+            if !tried_range_request {
+                tried_range_request = true;
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                for peer_id in self.connected_p2p_sources(file_hash).await {
+                    match timeout(
+                        remaining,
+                        self.webrtc_service.request_range(&peer_id, file_hash, offset, len),
+                    )
+                    .await
+                    {
+                        Ok(Ok(data)) => return Ok(RangeRead::Ready(data)),
+                        Ok(Err(e)) => debug!("Range request to {} failed: {}", peer_id, e),
+                        Err(_) => break,
+                    }
+                }
+                continue;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// P2P sources of an active download with an open WebRTC connection
+    async fn connected_p2p_sources(&self, file_hash: &str) -> Vec<String> {
+        let peers: Vec<String> = {
+            let downloads = self.active_downloads.read().await;
+            downloads
+                .get(file_hash)
+                .map(|download| {
+                    download
+                        .source_assignments
+                        .values()
+                        .filter(|a| {
+                            matches!(a.status, SourceStatus::Connected | SourceStatus::Downloading)
+                        })
+                        .filter_map(|a| match &a.source {
+                            DownloadSource::P2p(info) => Some(info.peer_id.clone()),
+                            _ => None,
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let mut connected = Vec::with_capacity(peers.len());
+        for peer_id in peers {
+            if self.webrtc_service.get_connection_status(&peer_id).await {
+                connected.push(peer_id);
+            }
+        }
+        connected
+    }
+
+    /// Download a byte range of `file_hash` without downloading the whole file.
+    ///
+    /// Peers already serving an active download are tried first; otherwise the
+    /// file's seeders are looked up in the DHT and connected to as needed.
+    pub async fn fetch_range(&self, file_hash: &str, offset: u64, len: u64) -> Result<Vec<u8>, String> {
+        let mut last_error = "No seeders available".to_string();
+        for peer_id in self.connected_p2p_sources(file_hash).await {
+            match self.webrtc_service.request_range(&peer_id, file_hash, offset, len).await {
+                Ok(data) => return Ok(data),
+                Err(e) => last_error = e,
+            }
+        }
+
+        let metadata = self
+            .dht_service
+            .synchronous_search_metadata(file_hash.to_string(), 35000)
+            .await?
+            .ok_or_else(|| "File metadata not found".to_string())?;
+        for peer_id in metadata.seeders.iter().take(MAX_RANGE_SEEDERS) {
+            if !self.webrtc_service.get_connection_status(peer_id).await {
+                if let Err(e) = self.negotiate_webrtc(file_hash, peer_id).await {
+                    last_error = e;
+                    continue;
+                }
+            }
+            match self.webrtc_service.request_range(peer_id, file_hash, offset, len).await {
+                Ok(data) => return Ok(data),
+                Err(e) => {
+                    warn!("Range request to {} failed: {}", peer_id, e);
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
     pub async fn get_download_progress(&self, file_hash: &str) -> Option<MultiSourceProgress> {
         let downloads = self.active_downloads.read().await;
         if let Some(download) = downloads.get(file_hash) {
@@ -323,6 +989,12 @@ impl MultiSourceDownloadService {
         }
     }
 
+    /// Progress of every active download
+    pub async fn all_download_progress(&self) -> Vec<MultiSourceProgress> {
+        let downloads = self.active_downloads.read().await;
+        downloads.values().map(|download| self.calculate_progress(download)).collect()
+    }
+
     pub async fn run(&self) {
         info!("Starting MultiSourceDownloadService");
 
@@ -335,9 +1007,22 @@ impl MultiSourceDownloadService {
                     output_path,
                     max_peers,
                     chunk_size,
+                    sequential,
+                    probe_providers,
+                    overwrite,
+                    encrypt_partials,
                 } => {
                     if let Err(e) = self
-                        .handle_start_download(file_hash, output_path, max_peers, chunk_size)
+                        .handle_start_download(
+                            file_hash,
+                            output_path,
+                            max_peers,
+                            chunk_size,
+                            sequential,
+                            probe_providers,
+                            overwrite,
+                            encrypt_partials,
+                        )
                         .await
                     {
                         error!("Failed to start download: {}", e);
@@ -351,6 +1036,14 @@ impl MultiSourceDownloadService {
                         error!("Failed to retry chunks for {}: {}", file_hash, e);
                     }
                 }
+                MultiSourceCommand::RecoverStalled {
+                    file_hash,
+                    attempt,
+                    stalled_for,
+                } => {
+                    self.handle_recover_stalled(&file_hash, attempt, stalled_for)
+                        .await;
+                }
             }
         }
     }
@@ -361,7 +1054,11 @@ impl MultiSourceDownloadService {
         output_path: String,
         max_peers: Option<usize>,
         chunk_size: Option<usize>,
-    ) -> Result<(), String> {
+        sequential: bool,
+        probe_providers: Option<bool>,
+        overwrite: bool,
+        encrypt_partials: Option<bool>,
+    ) -> Result<ResumeOutcome, String> {
         info!("Starting multi-source download for file: {}", file_hash);
 
         // Check if download is already active
@@ -462,14 +1159,58 @@ impl MultiSourceDownloadService {
             }));
         }
 
+        // 5. Web seeds: the publisher's own HTTP(S) copy, checked against
+        // the chunk hashes it signed
+        let web_seeds = verified_web_seeds(&metadata).cloned();
+        if let Some(web_seeds) = &web_seeds {
+            if self.web_seeds_enabled() {
+                info!("Found {} web seeds for file", web_seeds.urls.len());
+                for url in &web_seeds.urls {
+                    match crate::download_source::check_web_seed_resolves_publicly(url).await {
+                        Ok(()) => available_sources
+                            .push(DownloadSource::Http(HttpSourceInfo::web_seed(url))),
+                        Err(e) => warn!("Skipping web seed: {}", e),
+                    }
+                }
+            } else {
+                info!("Ignoring {} web seeds: web seeds are disabled", web_seeds.urls.len());
+            }
+        }
+
+        // Leave out providers already caught serving corrupt data for this file
+        let excluded_sources = self.source_exclusions.lock().await.excluded_for(&file_hash);
+        if !excluded_sources.is_empty() {
+            available_sources.retain(|source| {
+                let id = source.identifier();
+                !excluded_sources.iter().any(|e| e.source_id == id)
+            });
+            info!(
+                "Skipping {} excluded source(s) for file {}",
+                excluded_sources.len(),
+                file_hash
+            );
+        }
+
         if available_sources.is_empty() {
             return Err("No sources available for download".to_string());
         }
 
-        // Calculate chunk information
-        let chunk_size = chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+        // Provider records can be stale; check the top candidates before committing
+        let mut provider_probes = Vec::new();
+        if provider_probe::should_probe(probe_providers, metadata.file_size) {
+            provider_probes = self
+                .probe_providers(&file_hash, metadata.file_size, &available_sources)
+                .await;
+            available_sources = provider_probe::rank_sources(available_sources, &provider_probes);
+        }
+
+        // Calculate chunk information; signed chunk hashes fix the layout
+        let chunk_size = match &web_seeds {
+            Some(seeds) => seeds.chunk_size as usize,
+            None => chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE),
+        };
         let total_chunks = ((metadata.file_size as usize + chunk_size - 1) / chunk_size) as u32;
-        let chunks = self.calculate_chunks(&metadata, chunk_size);
+        let chunks = self.calculate_chunks(&metadata, chunk_size, web_seeds.as_ref());
 
         // Determine if we should use multi-source download
         let use_multi_source =
@@ -477,31 +1218,56 @@ impl MultiSourceDownloadService {
 
         if !use_multi_source {
             info!("Using single-source download (not enough chunks or sources)");
-            return self
-                .start_single_source_download(metadata, output_path)
-                .await;
+            self.start_single_source_download(metadata, output_path)
+                .await?;
+            return Ok(ResumeOutcome::Fresh);
         }
 
         // Select optimal sources for multi-source download
         let max_sources = max_peers.unwrap_or(available_sources.len().min(4));
         let selected_sources = self.select_optimal_sources(&available_sources, max_sources);
+        for probe in provider_probes.iter_mut() {
+            probe.selected = selected_sources
+                .iter()
+                .any(|s| s.identifier() == probe.peer_id);
+        }
 
         info!(
             "Selected {} sources for multi-source download",
             selected_sources.len()
         );
 
+        let encrypt_partials = encrypt_partials.unwrap_or(self.encrypt_partials);
+        let (completed_chunks, resume_outcome) = self
+            .restore_partial_download(
+                &file_hash,
+                &output_path,
+                &metadata,
+                chunk_size,
+                sequential,
+                overwrite,
+                encrypt_partials,
+            )
+            .await;
+
         // Create download state
         let download = ActiveDownload {
             file_metadata: metadata.clone(),
             chunks,
             source_assignments: HashMap::new(),
-            completed_chunks: HashMap::new(),
+            completed_chunks,
             pending_requests: HashMap::new(),
             failed_chunks: VecDeque::new(),
             start_time: Instant::now(),
             last_progress_update: Instant::now(),
             output_path,
+            sequential,
+            provider_probes: provider_probes.clone(),
+            chunk_scheduler: ChunkScheduler::new(total_chunks, sequential),
+            excluded_sources,
+            overwrite,
+            request_windows: HashMap::new(),
+            partial_cipher: encrypt_partials.then(PartialCipher::generate),
         };
 
         // Store download state
@@ -523,13 +1289,14 @@ impl MultiSourceDownloadService {
                 DownloadSource::BitTorrent(info) => (SourceType::BitTorrent, info.magnet_uri.clone()),
                 DownloadSource::Ed2k(info) => (SourceType::P2p, info.server_url.clone()),
             };
+            let probe = provider_probes.iter().find(|p| p.peer_id == s.identifier());
             SourceInfo {
                 id: s.identifier(),
                 source_type,
                 address,
                 reputation: None,
-                estimated_speed_bps: None,
-                latency_ms: None,
+                estimated_speed_bps: probe.and_then(|p| p.throughput_bps),
+                latency_ms: probe.and_then(|p| p.latency_ms).map(|ms| ms as u32),
                 location: None,
             }
         }).collect();
@@ -557,7 +1324,7 @@ impl MultiSourceDownloadService {
         // Start monitoring download progress
         self.spawn_download_monitor(file_hash).await;
 
-        Ok(())
+        Ok(resume_outcome)
     }
 
     async fn start_single_source_download(
@@ -570,7 +1337,14 @@ impl MultiSourceDownloadService {
         Err("Single-source download not implemented in this service".to_string())
     }
 
-    fn calculate_chunks(&self, metadata: &FileMetadata, chunk_size: usize) -> Vec<ChunkInfo> {
+    /// Split the file into chunks of `chunk_size`, with the hashes from
+    /// `web_seeds` when the publisher signed them
+    fn calculate_chunks(
+        &self,
+        metadata: &FileMetadata,
+        chunk_size: usize,
+        web_seeds: Option<&WebSeeds>,
+    ) -> Vec<ChunkInfo> {
         let mut chunks = Vec::new();
         let total_size = metadata.file_size as usize;
         let mut offset = 0u64;
@@ -580,8 +1354,10 @@ impl MultiSourceDownloadService {
             let remaining = (metadata.file_size - offset) as usize;
             let size = remaining.min(chunk_size);
 
-            // Calculate chunk hash (simplified - in real implementation this would be pre-calculated)
-            let hash = format!("{}_{}", metadata.merkle_root, chunk_id);
+            // Without signed hashes this placeholder skips per-chunk checks
+            let hash = web_seeds
+                .and_then(|seeds| seeds.chunk_hashes.get(chunk_id as usize).cloned())
+                .unwrap_or_else(|| format!("{}_{}", metadata.merkle_root, chunk_id));
 
             chunks.push(ChunkInfo {
                 chunk_id,
@@ -597,6 +1373,93 @@ impl MultiSourceDownloadService {
         chunks
     }
 
+    /// Probe the top P2P candidates concurrently, within `PROBE_TIMEOUT` overall
+    async fn probe_providers(
+        &self,
+        file_hash: &str,
+        file_size: u64,
+        sources: &[DownloadSource],
+    ) -> Vec<ProviderProbe> {
+        let peers: Vec<String> = sources
+            .iter()
+            .filter_map(|s| match s {
+                DownloadSource::P2p(info) => Some(info.peer_id.clone()),
+                _ => None,
+            })
+            .take(MAX_PROBED_PROVIDERS)
+            .collect();
+        if peers.is_empty() {
+            return Vec::new();
+        }
+
+        let deadline = tokio::time::Instant::now() + PROBE_TIMEOUT;
+        let probes = futures::future::join_all(
+            peers
+                .iter()
+                .map(|peer_id| self.probe_provider(file_hash, file_size, peer_id, deadline)),
+        )
+        .await;
+
+        info!(
+            "Probed {} providers for {}: {} still have the file",
+            probes.len(),
+            file_hash,
+            probes.iter().filter(|p| p.has_content).count()
+        );
+        probes
+    }
+
+    /// Dial `peer_id`, confirm it still has the file and time a small sample fetch
+    async fn probe_provider(
+        &self,
+        file_hash: &str,
+        file_size: u64,
+        peer_id: &str,
+        deadline: tokio::time::Instant,
+    ) -> ProviderProbe {
+        let started = Instant::now();
+        let have = tokio::time::timeout_at(deadline, async {
+            if !self.webrtc_service.get_connection_status(peer_id).await {
+                self.negotiate_webrtc(file_hash, peer_id).await?;
+            }
+            self.webrtc_service.has_file(peer_id, file_hash).await
+        })
+        .await;
+        match have {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => return ProviderProbe::failed(peer_id, e),
+            Err(_) => return ProviderProbe::failed(peer_id, "probe timed out"),
+        }
+
+        let mut probe = ProviderProbe {
+            peer_id: peer_id.to_string(),
+            has_content: true,
+            latency_ms: Some(started.elapsed().as_millis() as u64),
+            ..Default::default()
+        };
+
+        let sample = file_size.min(PROBE_SAMPLE_BYTES);
+        if sample > 0 {
+            let fetch_started = Instant::now();
+            match tokio::time::timeout_at(
+                deadline,
+                self.webrtc_service.request_range(peer_id, file_hash, 0, sample),
+            )
+            .await
+            {
+                Ok(Ok(data)) => {
+                    let secs = fetch_started.elapsed().as_secs_f64();
+                    if secs > 0.0 {
+                        probe.throughput_bps = Some(data.len() as f64 / secs);
+                    }
+                }
+                Ok(Err(e)) => probe.error = Some(format!("Sample fetch failed: {}", e)),
+                Err(_) => debug!("Sample fetch from {} did not finish in time", peer_id),
+            }
+        }
+        probe
+    }
+
     /// Select optimal sources based on priority scoring
     fn select_optimal_sources(
         &self,
@@ -635,13 +1498,31 @@ impl MultiSourceDownloadService {
             return Err("No sources provided for download".to_string());
         }
 
-        let downloads = self.active_downloads.read().await;
-        let download = downloads.get(file_hash).ok_or("Download not found")?;
+        let mut downloads = self.active_downloads.write().await;
+        let download = downloads.get_mut(file_hash).ok_or("Download not found")?;
 
-        // Assign chunks to sources using round-robin strategy
-        let chunk_assignments = self.assign_chunks_to_sources(&download.chunks, &sources);
+        // Every discovered source serves the complete file
+        for source in &sources {
+            download.chunk_scheduler.add_source(source.identifier(), None);
+        }
+        // Chunks restored from an interrupted run are not fetched again
+        let outstanding: Vec<ChunkInfo> = download
+            .chunks
+            .iter()
+            .filter(|chunk| !download.completed_chunks.contains_key(&chunk.chunk_id))
+            .cloned()
+            .collect();
+        if outstanding.is_empty() {
+            return Ok(());
+        }
+        let chunk_assignments =
+            self.assign_chunks_to_sources(&download.chunk_scheduler, &outstanding, &sources);
         drop(downloads);
 
+        // HTTP sources are fetched inline, so start everything else first
+        let mut chunk_assignments = chunk_assignments;
+        chunk_assignments.sort_by_key(|(source, _)| matches!(source, DownloadSource::Http(_)));
+
         // Start connecting to sources
         for (source, chunk_ids) in chunk_assignments {
             match &source {
@@ -671,9 +1552,13 @@ impl MultiSourceDownloadService {
         Ok(())
     }
 
-    /// Assign chunks to sources using round-robin strategy
+    /// Assign chunks to sources, rarest first and balanced across sources.
+    ///
+    /// In sequential mode each source's queue is kept in ascending chunk order,
+    /// so the sources together always work on the lowest outstanding chunks.
     fn assign_chunks_to_sources(
         &self,
+        scheduler: &ChunkScheduler,
         chunks: &[ChunkInfo],
         sources: &[DownloadSource],
     ) -> Vec<(DownloadSource, Vec<u32>)> {
@@ -682,52 +1567,23 @@ impl MultiSourceDownloadService {
             return Vec::new();
         }
 
-        let mut assignments: Vec<(DownloadSource, Vec<u32>)> =
-            sources.iter().map(|s| (s.clone(), Vec::new())).collect();
-
-        // Round-robin assignment
-        for (index, chunk) in chunks.iter().enumerate() {
-            let source_index = index % sources.len();
-            if let Some((_, chunks)) = assignments.get_mut(source_index) {
-                if chunks.len() < MAX_CHUNKS_PER_PEER {
-                    chunks.push(chunk.chunk_id);
-                }
-            }
-        }
-
-        // Redistribute chunks if some sources have too few
-        self.balance_source_assignments(assignments, chunks.len())
-    }
-
-    /// Balance chunk assignments across sources
-    fn balance_source_assignments(
-        &self,
-        mut assignments: Vec<(DownloadSource, Vec<u32>)>,
-        total_chunks: usize,
-    ) -> Vec<(DownloadSource, Vec<u32>)> {
-        let source_count = assignments.len();
-        let target_chunks_per_source = (total_chunks + source_count - 1) / source_count;
-
-        // Find sources with too many chunks and redistribute
-        let mut excess_chunks = Vec::new();
-        for (_, chunks) in assignments.iter_mut() {
-            while chunks.len() > target_chunks_per_source {
-                if let Some(chunk_id) = chunks.pop() {
-                    excess_chunks.push(chunk_id);
-                }
-            }
-        }
-
-        // Redistribute excess chunks to sources with capacity
-        for chunk_id in excess_chunks {
-            for (_, chunks) in assignments.iter_mut() {
-                if chunks.len() < target_chunks_per_source {
-                    chunks.push(chunk_id);
-                    break;
-                }
+        let source_ids: Vec<String> = sources.iter().map(DownloadSource::identifier).collect();
+        let pending: Vec<u32> = chunks.iter().map(|chunk| chunk.chunk_id).collect();
+        let mut assignments: Vec<(DownloadSource, Vec<u32>)> = sources
+            .iter()
+            .cloned()
+            .zip(
+                scheduler
+                    .assign(&pending, &source_ids, MAX_CHUNKS_PER_PEER)
+                    .into_iter()
+                    .map(|(_, chunk_ids)| chunk_ids),
+            )
+            .collect();
+        if scheduler.is_sequential() {
+            for (_, chunk_ids) in assignments.iter_mut() {
+                chunk_ids.sort_unstable();
             }
         }
-
         assignments
     }
 
@@ -763,79 +1619,64 @@ impl MultiSourceDownloadService {
             }
         }
 
-        // Create WebRTC offer (existing WebRTC logic)
-        match self.webrtc_service.create_offer(peer_id.clone()).await {
-            Ok(offer) => {
-                let offer_request = WebRTCOfferRequest {
-                    offer_sdp: offer,
-                    file_hash: file_hash.to_string(),
-                    requester_peer_id: self.dht_service.get_peer_id().await,
-                };
-
-                match timeout(
-                    Duration::from_secs(CONNECTION_TIMEOUT_SECS),
-                    self.dht_service
-                        .send_webrtc_offer(peer_id.clone(), offer_request),
-                )
-                .await
-                {
-                    Ok(Ok(answer_receiver)) => {
-                        match timeout(
-                            Duration::from_secs(CONNECTION_TIMEOUT_SECS),
-                            answer_receiver,
-                        )
-                        .await
-                        {
-                            Ok(Ok(Ok(answer_response))) => {
-                                match self
-                                    .webrtc_service
-                                    .establish_connection_with_answer(
-                                        peer_id.clone(),
-                                        answer_response.answer_sdp,
-                                    )
-                                    .await
-                                {
-                                    Ok(_) => {
-                                        self.on_source_connected(file_hash, &peer_id, chunk_ids)
-                                            .await;
-                                        Ok(())
-                                    }
-                                    Err(e) => {
-                                        self.on_source_failed(
-                                            file_hash,
-                                            &peer_id,
-                                            format!("Connection failed: {}", e),
-                                        )
-                                        .await;
-                                        Err(e)
-                                    }
-                                }
-                            }
-                            _ => {
-                                let error = "Answer timeout".to_string();
-                                self.on_source_failed(file_hash, &peer_id, error.clone())
-                                    .await;
-                                Err(error)
-                            }
-                        }
-                    }
-                    _ => {
-                        let error = "Offer timeout".to_string();
-                        self.on_source_failed(file_hash, &peer_id, error.clone())
-                            .await;
-                        Err(error)
-                    }
-                }
+        // A provider probe may already have opened the connection
+        let connected = if self.webrtc_service.get_connection_status(&peer_id).await {
+            Ok(())
+        } else {
+            self.negotiate_webrtc(file_hash, &peer_id).await
+        };
+        match connected {
+            Ok(()) => {
+                self.on_source_connected(file_hash, &peer_id, chunk_ids).await;
+                Ok(())
             }
-            Err(e) => {
-                let error = format!("Failed to create offer: {}", e);
-                self.on_source_failed(file_hash, &peer_id, error.clone())
-                    .await;
+            Err(error) => {
+                self.on_source_failed(file_hash, &peer_id, error.clone()).await;
                 Err(error)
             }
         }
     }
 
+    /// Open a WebRTC connection to `peer_id` through DHT offer/answer signaling
+    async fn negotiate_webrtc(&self, file_hash: &str, peer_id: &str) -> Result<(), String> {
+        let offer = self
+            .webrtc_service
+            .create_offer(peer_id.to_string())
+            .await
+            .map_err(|e| format!("Failed to create offer: {}", e))?;
+        let offer_request = WebRTCOfferRequest {
+            offer_sdp: offer,
+            file_hash: file_hash.to_string(),
+            requester_peer_id: self.dht_service.get_peer_id().await,
+        };
+
+        let answer_receiver = match timeout(
+            Duration::from_secs(CONNECTION_TIMEOUT_SECS),
+            self.dht_service
+                .send_webrtc_offer(peer_id.to_string(), offer_request),
+        )
+        .await
+        {
+            Ok(Ok(answer_receiver)) => answer_receiver,
+            _ => return Err("Offer timeout".to_string()),
+        };
+
+        let answer_response = match timeout(
+            Duration::from_secs(CONNECTION_TIMEOUT_SECS),
+            answer_receiver,
+        )
+        .await
+        {
+            Ok(Ok(Ok(answer_response))) => answer_response,
+            _ => return Err("Answer timeout".to_string()),
+        };
+
+        self.webrtc_service
+            .establish_connection_with_answer(peer_id.to_string(), answer_response.answer_sdp)
+            .await
+            .map_err(|e| format!("Connection failed: {}", e))
+    }
+
     /// Start FTP connection and chunk downloading
     async fn start_ftp_connection(
         &self,
@@ -993,6 +1834,8 @@ impl MultiSourceDownloadService {
         let event_tx = self.event_tx.clone();
         let downloads = self.active_downloads.clone();
         let transfer_event_bus = self.transfer_event_bus.clone();
+        let source_exclusions = self.source_exclusions.clone();
+        let dht_service = self.dht_service.clone();
 
         tokio::spawn(async move {
             let semaphore = Arc::new(tokio::sync::Semaphore::new(2)); // Max 2 concurrent FTP downloads per server
@@ -1014,6 +1857,8 @@ impl MultiSourceDownloadService {
                 let downloads = downloads.clone();
                 let chunk = chunk_info.clone();
                 let transfer_event_bus = transfer_event_bus.clone();
+                let source_exclusions = source_exclusions.clone();
+                let dht_service = dht_service.clone();
 
                 let task = tokio::spawn(async move {
                     let _permit = permit.unwrap();
@@ -1113,6 +1958,15 @@ impl MultiSourceDownloadService {
                                         download.failed_chunks.push_back(chunk.chunk_id);
                                     }
                                 }
+                                Self::record_corruption(
+                                    &source_exclusions,
+                                    &downloads,
+                                    &dht_service,
+                                    &file_hash,
+                                    &ftp_url,
+                                    &error_msg,
+                                )
+                                .await;
                                 // Emit chunk failed event via TransferEventBus
                                 transfer_event_bus.emit_chunk_failed(ChunkFailedEvent {
                                     transfer_id: file_hash.clone(),
@@ -1281,10 +2135,16 @@ impl MultiSourceDownloadService {
         // In a full implementation, this would use the http_download.rs module
         // to download chunks with Range requests and verify hashes
 
-        // Get file metadata to access chunk information
-        let downloads = self.active_downloads.read().await;
-        let download = match downloads.get(file_hash) {
-            Some(download) => download,
+        // Copy the chunk layout so the download lock isn't held while fetching
+        let (chunks, web_seed) = match self.active_downloads.read().await.get(file_hash) {
+            Some(download) => (
+                download.chunks.clone(),
+                download
+                    .file_metadata
+                    .web_seeds
+                    .as_ref()
+                    .is_some_and(|seeds| seeds.urls.contains(&http_info.url)),
+            ),
             None => {
                 let error = format!("No active download found for file {}", file_hash);
                 error!("{}", error);
@@ -1293,62 +2153,68 @@ impl MultiSourceDownloadService {
             }
         };
 
-        // For each requested chunk, attempt HTTP download with hash verification
-        for chunk_id in chunk_ids {
-            // Capture start time for duration tracking
-            let download_start_ms = current_timestamp_ms();
-
-            // Find chunk info
-            let chunk_info = match download.chunks.iter().find(|c| c.chunk_id == chunk_id) {
-                Some(chunk) => chunk,
-                None => {
+        // A web seed must not redirect into the downloader's own network
+        let redirect = match web_seed {
+            true => reqwest::redirect::Policy::none(),
+            false => reqwest::redirect::Policy::default(),
+        };
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(30))
+            .redirect(redirect)
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+        // Keep several range requests outstanding so a distant server is not
+        // left idle for a round trip per chunk
+        let mut window = RequestWindow::new(self.pipeline);
+        let mut queue: VecDeque<u32> = chunk_ids.into();
+        let mut requests = FuturesUnordered::new();
+        loop {
+            while let Some(&chunk_id) = queue.front() {
+                let Some(chunk_info) = chunks.iter().find(|c| c.chunk_id == chunk_id) else {
                     warn!("Chunk {} not found in metadata for file {}", chunk_id, file_hash);
+                    queue.pop_front();
                     continue;
+                };
+                if !window.can_send(chunk_info.size as u64) {
+                    break;
                 }
-            };
-
-            // Calculate byte range for this chunk
-            let start_byte = chunk_info.offset;
-            let end_byte = start_byte + chunk_info.size as u64 - 1;
-
-            // Create HTTP client for range request
-            let client = reqwest::Client::builder()
-                .timeout(std::time::Duration::from_secs(30))
-                .build()
-                .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
-
-            // Make range request
-            let response = match client
-                .get(&http_info.url)
-                .header("Range", format!("bytes={}-{}", start_byte, end_byte))
-                .send()
-                .await
-            {
-                Ok(resp) => resp,
-                Err(e) => {
-                    let error = format!("HTTP request failed for chunk {}: {}", chunk_id, e);
-                    warn!("{}", error);
-                    self.on_source_failed(file_hash, &http_info.url, error).await;
-                    continue;
-                }
-            };
-
-            // Check for partial content response
-            if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
-                let error = format!("HTTP server doesn't support range requests for chunk {} (status: {})",
-                    chunk_id, response.status());
-                warn!("{}", error);
-                self.on_source_failed(file_hash, &http_info.url, error).await;
-                continue;
+                queue.pop_front();
+                window.on_send(chunk_info.size as u64, Instant::now());
+
+                // Calculate byte range for this chunk
+                let start_byte = chunk_info.offset;
+                let end_byte = start_byte + chunk_info.size as u64 - 1;
+                let request = client
+                    .get(&http_info.url)
+                    .header("Range", format!("bytes={}-{}", start_byte, end_byte));
+                let chunk_info = chunk_info.clone();
+                requests.push(async move {
+                    // Capture start time for duration tracking
+                    let download_start_ms = current_timestamp_ms();
+                    let result = fetch_http_range(request, chunk_info.chunk_id).await;
+                    (chunk_info, download_start_ms, result)
+                });
             }
+            self.publish_request_window(file_hash, &http_info.url, &window).await;
 
-            // Read response data
-            let chunk_data = match response.bytes().await {
-                Ok(data) => data.to_vec(),
+            let Some((chunk_info, download_start_ms, result)) = requests.next().await else {
+                break;
+            };
+            let chunk_id = chunk_info.chunk_id;
+            let chunk_data = match result {
+                Ok(data) => {
+                    window.on_complete(chunk_info.size as u64, Instant::now());
+                    data
+                }
                 Err(e) => {
-                    let error = format!("Failed to read HTTP response for chunk {}: {}", chunk_id, e);
-                    warn!("{}", error);
-                    self.on_source_failed(file_hash, &http_info.url, error).await;
+                    if e.timed_out {
+                        window.on_timeout(chunk_info.size as u64);
+                    } else {
+                        window.on_failure(chunk_info.size as u64);
+                    }
+                    warn!("{}", e.message);
+                    self.on_source_failed(file_hash, &http_info.url, e.message).await;
                     continue;
                 }
             };
@@ -1365,19 +2231,31 @@ impl MultiSourceDownloadService {
             }
 
             // Verify chunk hash
-            if let Err((expected, actual)) = verify_chunk_integrity(chunk_info, &chunk_data) {
+            if let Err((expected, actual)) = verify_chunk_integrity(&chunk_info, &chunk_data) {
                 let error = format!(
                     "HTTP chunk {} hash verification failed: expected {}, got {}",
                     chunk_id, expected, actual
                 );
                 warn!("{}", error);
+                Self::record_corruption(
+                    &self.source_exclusions,
+                    &self.active_downloads,
+                    &self.dht_service,
+                    file_hash,
+                    &http_info.url,
+                    &error,
+                )
+                .await;
                 self.on_source_failed(file_hash, &http_info.url, error).await;
                 continue;
             }
 
             // Chunk passed verification - store it
             info!("HTTP chunk {} downloaded and verified successfully", chunk_id);
-            if let Err(e) = self.store_verified_chunk(file_hash, chunk_info, chunk_data, download_start_ms).await {
+            if let Err(e) = self
+                .store_verified_chunk(file_hash, &http_info.url, &chunk_info, chunk_data, download_start_ms)
+                .await
+            {
                 let error = format!("Failed to store HTTP chunk {}: {}", chunk_id, e);
                 error!("{}", error);
                 self.on_source_failed(file_hash, &http_info.url, error).await;
@@ -1387,10 +2265,20 @@ impl MultiSourceDownloadService {
         Ok(())
     }
 
+    /// Record the request window of `source_id` for the transfer details
+    async fn publish_request_window(&self, file_hash: &str, source_id: &str, window: &RequestWindow) {
+        if let Some(download) = self.active_downloads.write().await.get_mut(file_hash) {
+            download
+                .request_windows
+                .insert(source_id.to_string(), window.info());
+        }
+    }
+
     /// Store a verified chunk in the active download
     async fn store_verified_chunk(
         &self,
         file_hash: &str,
+        source_id: &str,
         chunk_info: &ChunkInfo,
         data: Vec<u8>,
         download_start_ms: u64,
@@ -1403,7 +2291,7 @@ impl MultiSourceDownloadService {
         let completed_chunk = CompletedChunk {
             chunk_id: chunk_info.chunk_id,
             data,
-            source_id: "http".to_string(),
+            source_id: source_id.to_string(),
             completed_at: std::time::Instant::now(),
         };
         download.completed_chunks.insert(chunk_info.chunk_id, completed_chunk);
@@ -1417,7 +2305,7 @@ impl MultiSourceDownloadService {
             transfer_id: file_hash.to_string(),
             chunk_id: chunk_info.chunk_id,
             chunk_size: chunk_info.size,
-            source_id: "http".to_string(),
+            source_id: source_id.to_string(),
             source_type: SourceType::Http,
             completed_at,
             download_duration_ms,
@@ -1428,7 +2316,7 @@ impl MultiSourceDownloadService {
         if let Err(e) = self.event_tx.send(MultiSourceEvent::ChunkCompleted {
             file_hash: file_hash.to_string(),
             chunk_id: chunk_info.chunk_id,
-            peer_id: "http".to_string(),
+            peer_id: source_id.to_string(),
         }) {
             warn!("Failed to emit chunk completed event: {}", e);
         }
@@ -1436,7 +2324,14 @@ impl MultiSourceDownloadService {
         // Check if download is complete
         if download.completed_chunks.len() == download.chunks.len() {
             drop(downloads); // Release lock before calling finalize
-            Self::finalize_download_static(&self.active_downloads, file_hash).await?;
+            Self::finalize_download_static(
+                &self.active_downloads,
+                &self.resume_store,
+                &self.source_exclusions,
+                &self.dht_service,
+                file_hash,
+            )
+            .await?;
         }
 
         Ok(())
@@ -1600,6 +2495,8 @@ impl MultiSourceDownloadService {
         let file_hash_clone = file_hash.to_string();
         let ed2k_connections = Arc::clone(&self.ed2k_connections);
         let active_downloads = Arc::clone(&self.active_downloads);
+        let source_exclusions = Arc::clone(&self.source_exclusions);
+        let dht_service = Arc::clone(&self.dht_service);
         let chunks_map_clone = Arc::new(chunks_map);
 
         // Spawn task to download chunks
@@ -1619,6 +2516,8 @@ impl MultiSourceDownloadService {
                 let permit = semaphore.clone().acquire_owned().await;
                 let ed2k_connections_clone = Arc::clone(&ed2k_connections);
                 let active_downloads_clone = Arc::clone(&active_downloads);
+                let source_exclusions = Arc::clone(&source_exclusions);
+                let dht_service = Arc::clone(&dht_service);
                 let file_hash_inner = file_hash_clone.clone();
                 let server_url_clone = server_url_id.clone();
                 let ed2k_file_hash = ed2k_info.file_hash.clone();
@@ -1670,6 +2569,7 @@ impl MultiSourceDownloadService {
                                 }
 
                                 // Extract all needed chunks from the downloaded ed2k chunk
+                                let mut corrupt = Vec::new();
                                 let mut downloads = active_downloads_clone.write().await;
                                 if let Some(download) = downloads.get_mut(&file_hash_inner) {
                                     for chunk_info in &our_chunk_infos {
@@ -1686,6 +2586,18 @@ impl MultiSourceDownloadService {
 
                                         if end <= ed2k_chunk_data.len() {
                                             let chunk_data = ed2k_chunk_data[start..end].to_vec();
+                                            if let Err((expected, actual)) =
+                                                verify_chunk_integrity(chunk_info, &chunk_data)
+                                            {
+                                                let error = format!(
+                                                    "Ed2k chunk {} hash verification failed: expected {}, got {}",
+                                                    chunk_info.chunk_id, expected, actual
+                                                );
+                                                warn!("{}", error);
+                                                download.failed_chunks.push_back(chunk_info.chunk_id);
+                                                corrupt.push(error);
+                                                continue;
+                                            }
 
                                             let completed_chunk = CompletedChunk {
                                                 chunk_id: chunk_info.chunk_id,
@@ -1711,6 +2623,18 @@ impl MultiSourceDownloadService {
                                         }
                                     }
                                 }
+                                drop(downloads);
+                                for error in corrupt {
+                                    Self::record_corruption(
+                                        &source_exclusions,
+                                        &active_downloads_clone,
+                                        &dht_service,
+                                        &file_hash_inner,
+                                        &server_url_clone,
+                                        &error,
+                                    )
+                                    .await;
+                                }
                             }
                             Err(e) => {
                                 error!("Failed to download Ed2k chunk {}: {:?}", ed2k_chunk_id, e);
@@ -2259,6 +3183,71 @@ impl MultiSourceDownloadService {
         });
     }
 
+    /// Count a failed chunk verification against `source_id`. Once the source
+    /// crosses the threshold it is dropped from this file's source set for the
+    /// rest of the session, penalized if it is a peer, and its outstanding
+    /// chunks are re-queued. Returns true when the source was just excluded.
+    async fn record_corruption(
+        source_exclusions: &Arc<Mutex<SourceExclusions>>,
+        downloads: &Arc<RwLock<HashMap<String, ActiveDownload>>>,
+        dht_service: &Arc<DhtService>,
+        file_hash: &str,
+        source_id: &str,
+        reason: &str,
+    ) -> bool {
+        let excluded = {
+            let mut exclusions = source_exclusions.lock().await;
+            match exclusions.record_failure(source_id, file_hash, reason) {
+                Some(_) => exclusions.excluded_for(file_hash),
+                None => return false,
+            }
+        };
+
+        warn!(
+            "Excluding source {} for file {} after repeated corrupt chunks: {}",
+            source_id, file_hash, reason
+        );
+        if source_id.parse::<libp2p::PeerId>().is_ok() {
+            dht_service.report_malicious_peer(source_id, "moderate").await;
+        }
+
+        let mut downloads = downloads.write().await;
+        if let Some(download) = downloads.get_mut(file_hash) {
+            download.excluded_sources = excluded;
+            download.chunk_scheduler.remove_source(source_id);
+            let mut requeue = Vec::new();
+            for assignment in download.source_assignments.values_mut() {
+                if assignment.source.identifier() != source_id
+                    || matches!(assignment.status, SourceStatus::Failed)
+                {
+                    continue;
+                }
+                assignment.status = SourceStatus::Failed;
+                requeue.extend(assignment.chunks.iter().copied());
+            }
+            for chunk_id in requeue {
+                if !download.completed_chunks.contains_key(&chunk_id)
+                    && !download.failed_chunks.contains(&chunk_id)
+                {
+                    download.failed_chunks.push_back(chunk_id);
+                }
+            }
+        }
+        true
+    }
+
+    /// Readmit every source excluded for `file_hash`; returns how many were cleared
+    pub async fn clear_source_exclusions(&self, file_hash: &str) -> usize {
+        let cleared = self.source_exclusions.lock().await.clear(file_hash);
+        if let Some(download) = self.active_downloads.write().await.get_mut(file_hash) {
+            download.excluded_sources.clear();
+        }
+        if cleared > 0 {
+            info!("Cleared {} source exclusion(s) for file {}", cleared, file_hash);
+        }
+        cleared
+    }
+
     /// Handle source connection failure
     async fn on_source_failed(&self, file_hash: &str, source_id: &str, error: String) {
         warn!(
@@ -2286,6 +3275,8 @@ impl MultiSourceDownloadService {
                 if let Some(assignment) = download.source_assignments.get_mut(source_id) {
                     assignment.status = SourceStatus::Failed;
                     let chunks = assignment.chunks.clone();
+                    let scheduler_id = assignment.source.identifier();
+                    download.chunk_scheduler.remove_source(&scheduler_id);
                     let completed = download.completed_chunks.len() as u32;
 
                     // Add failed chunks back to retry queue
@@ -2420,6 +3411,12 @@ impl MultiSourceDownloadService {
             downloads.remove(file_hash)
         };
 
+        // A cancelled download is not resumed later
+        self.resume_store.remove(file_hash).await;
+        if let Err(e) = self.resume_store.flush().await {
+            warn!("Failed to save resume records: {}", e);
+        }
+
         if let Some(download) = download {
             // Close connections based on source type
             for (source_id, assignment) in download.source_assignments.iter() {
@@ -2461,73 +3458,247 @@ impl MultiSourceDownloadService {
         }
     }
 
-    async fn handle_retry_failed_chunks(&self, file_hash: &str) -> Result<(), String> {
-        info!("Retrying failed chunks for file: {}", file_hash);
-
-        let failed_chunks = {
-            let mut downloads = self.active_downloads.write().await;
-            if let Some(download) = downloads.get_mut(file_hash) {
-                let mut chunks = Vec::new();
-                while let Some(chunk_id) = download.failed_chunks.pop_front() {
-                    chunks.push(chunk_id);
-                    if chunks.len() >= 10 {
-                        break; // Limit retry batch size
-                    }
-                }
-                chunks
-            } else {
-                return Err("Download not found".to_string());
-            }
+    /// Drop the sources of a stalled download that still owe chunks, look
+    /// for providers again and hand the unverified chunks to the ones found
+    async fn handle_recover_stalled(&self, file_hash: &str, attempt: u32, stalled_for: Duration) {
+        let (silent, metadata, known) = {
+            let downloads = self.active_downloads.read().await;
+            let Some(download) = downloads.get(file_hash) else {
+                return;
+            };
+            let silent: Vec<(String, DownloadSource)> = download
+                .source_assignments
+                .iter()
+                .filter(|(_, assignment)| {
+                    !matches!(assignment.status, SourceStatus::Failed | SourceStatus::Completed)
+                        && assignment
+                            .chunks
+                            .iter()
+                            .any(|id| !download.completed_chunks.contains_key(id))
+                })
+                .map(|(key, assignment)| (key.clone(), assignment.source.clone()))
+                .collect();
+            // Sources still in use are not connected again
+            let known: Vec<String> = download
+                .source_assignments
+                .iter()
+                .filter(|(key, assignment)| {
+                    assignment.status != SourceStatus::Failed
+                        && !silent.iter().any(|(silent_key, _)| silent_key == *key)
+                })
+                .map(|(_, assignment)| assignment.source.identifier())
+                .collect();
+            (silent, download.file_metadata.clone(), known)
         };
 
-        if failed_chunks.is_empty() {
-            return Ok(());
+        warn!(
+            "Download of {} stalled for {}s, recovery attempt {}/{}: dropping {} silent source(s)",
+            metadata.file_name,
+            stalled_for.as_secs(),
+            attempt,
+            self.stall_policy.max_recoveries,
+            silent.len()
+        );
+        for (key, source) in &silent {
+            self.on_source_failed(
+                file_hash,
+                key,
+                format!("timeout: no verified data for {}s", stalled_for.as_secs()),
+            )
+            .await;
+            if let DownloadSource::P2p(_) = source {
+                let _ = self.webrtc_service.close_connection(key.clone()).await;
+            }
         }
 
-        // Try to find available peers for retry
-        let available_peers = {
+        let (downloaded_bytes, total_bytes) = {
             let downloads = self.active_downloads.read().await;
-            if let Some(download) = downloads.get(file_hash) {
-                download
-                    .source_assignments
-                    .iter()
-                    .filter(|(_, assignment)| {
-                        matches!(
-                            assignment.status,
-                            SourceStatus::Connected | SourceStatus::Downloading
-                        )
+            match downloads.get(file_hash) {
+                Some(download) => {
+                    let progress = Self::calculate_progress_static(download);
+                    (progress.downloaded_size, progress.total_size)
+                }
+                None => return,
+            }
+        };
+        self.transfer_event_bus.emit_recovering(TransferRecoveringEvent {
+            transfer_id: file_hash.to_string(),
+            attempt,
+            max_attempts: self.stall_policy.max_recoveries,
+            stalled_seconds: stalled_for.as_secs(),
+            dropped_sources: silent.iter().map(|(key, _)| key.clone()).collect(),
+            downloaded_bytes,
+            total_bytes,
+            timestamp: current_timestamp_ms(),
+        });
+
+        // Providers are discovered afresh; a dropped source that is found
+        // again gets a new connection
+        let excluded = self.source_exclusions.lock().await.excluded_for(file_hash);
+        let fresh: Vec<DownloadSource> = match self.dht_service.discover_peers_for_file(&metadata).await {
+            Ok(peers) => peers
+                .into_iter()
+                .filter(|peer_id| {
+                    !known.contains(peer_id) && !excluded.iter().any(|e| e.source_id == *peer_id)
+                })
+                .map(|peer_id| {
+                    DownloadSource::P2p(crate::download_source::P2pSourceInfo {
+                        peer_id,
+                        multiaddr: None,
+                        reputation: None,
+                        supports_encryption: false,
+                        protocol: Some("webrtc".to_string()),
                     })
-                    .map(|(peer_id, _)| peer_id.clone())
-                    .collect::<Vec<_>>()
-            } else {
+                })
+                .collect(),
+            Err(e) => {
+                warn!("Provider discovery for stalled download {} failed: {}", file_hash, e);
                 Vec::new()
             }
         };
+        if fresh.is_empty() {
+            info!("No new providers for {}; retrying with the remaining sources", file_hash);
+            return;
+        }
+
+        info!("Retrying {} with {} new source(s)", file_hash, fresh.len());
+        if let Err(e) = self.start_source_connections(file_hash, fresh).await {
+            warn!("Failed to connect new sources for {}: {}", file_hash, e);
+        }
+        // Chunks the new sources took over are no longer waiting for a retry
+        let mut downloads = self.active_downloads.write().await;
+        if let Some(download) = downloads.get_mut(file_hash) {
+            let assigned: std::collections::HashSet<u32> = download
+                .source_assignments
+                .values()
+                .filter(|assignment| assignment.status != SourceStatus::Failed)
+                .flat_map(|assignment| assignment.chunks.iter().copied())
+                .collect();
+            download.failed_chunks.retain(|id| !assigned.contains(id));
+        }
+    }
+
+    async fn handle_retry_failed_chunks(&self, file_hash: &str) -> Result<(), String> {
+        info!("Retrying failed chunks for file: {}", file_hash);
+
+        let mut downloads = self.active_downloads.write().await;
+        let download = downloads
+            .get_mut(file_hash)
+            .ok_or_else(|| "Download not found".to_string())?;
+
+        // Sources able to take more chunks, keyed by assignment id with the
+        // identifier the chunk scheduler knows them by
+        let available: Vec<(String, String)> = download
+            .source_assignments
+            .iter()
+            .filter(|(_, assignment)| {
+                matches!(
+                    assignment.status,
+                    SourceStatus::Connected | SourceStatus::Downloading
+                )
+            })
+            .map(|(key, assignment)| (key.clone(), assignment.source.identifier()))
+            .collect();
+        let source_ids: Vec<String> = available.iter().map(|(_, id)| id.clone()).collect();
+        let assignment_key = |source_id: &str| {
+            available
+                .iter()
+                .find(|(_, id)| id == source_id)
+                .map(|(key, _)| key.clone())
+        };
 
-        if available_peers.is_empty() {
+        // Rarest chunks are retried first unless streaming needs them in order
+        let sequential = download.sequential;
+        if !sequential {
+            download
+                .chunk_scheduler
+                .prioritize(download.failed_chunks.make_contiguous());
+        }
+        let failed_chunks = next_retry_batch(&mut download.failed_chunks, sequential, 10);
+
+        if failed_chunks.is_empty() {
+            self.schedule_endgame(download, &source_ids, assignment_key);
+            return Ok(());
+        }
+
+        if available.is_empty() {
+            download.failed_chunks.extend(failed_chunks);
             warn!("No available peers for retry");
             return Err("No available peers for retry".to_string());
         }
 
-        // Reassign failed chunks to available peers
-        for (index, chunk_id) in failed_chunks.iter().enumerate() {
-            let peer_index = index % available_peers.len();
-            let peer_id = &available_peers[peer_index];
-
-            // Add chunk to peer's assignment
-            {
-                let mut downloads = self.active_downloads.write().await;
-                if let Some(download) = downloads.get_mut(file_hash) {
-                    if let Some(assignment) = download.source_assignments.get_mut(peer_id) {
-                        assignment.chunks.push(*chunk_id);
-                    }
+        // Reassign failed chunks to available sources that hold them
+        let mut placed = Vec::new();
+        for (source_id, chunk_ids) in
+            download
+                .chunk_scheduler
+                .assign(&failed_chunks, &source_ids, usize::MAX)
+        {
+            let Some(key) = assignment_key(&source_id) else {
+                continue;
+            };
+            if let Some(assignment) = download.source_assignments.get_mut(&key) {
+                placed.extend_from_slice(&chunk_ids);
+                assignment.chunks.extend(chunk_ids);
+                if sequential {
+                    assignment.chunks.sort_unstable();
                 }
             }
         }
 
+        // Chunks no current source holds wait for the source set to change
+        download
+            .failed_chunks
+            .extend(failed_chunks.into_iter().filter(|c| !placed.contains(c)));
+
         Ok(())
     }
 
+    /// In endgame, request the last outstanding chunks from every other source
+    /// that holds them so one slow source cannot stall the download
+    fn schedule_endgame(
+        &self,
+        download: &mut ActiveDownload,
+        source_ids: &[String],
+        assignment_key: impl Fn(&str) -> Option<String>,
+    ) {
+        let outstanding: Vec<u32> = download
+            .chunks
+            .iter()
+            .map(|chunk| chunk.chunk_id)
+            .filter(|id| !download.completed_chunks.contains_key(id))
+            .collect();
+        if !download.chunk_scheduler.is_endgame(outstanding.len()) {
+            return;
+        }
+
+        let in_flight: HashMap<u32, Vec<String>> = outstanding
+            .iter()
+            .map(|&chunk_id| {
+                let holders = download
+                    .source_assignments
+                    .values()
+                    .filter(|assignment| assignment.chunks.contains(&chunk_id))
+                    .map(|assignment| assignment.source.identifier())
+                    .collect();
+                (chunk_id, holders)
+            })
+            .collect();
+
+        for (source_id, chunk_id) in download.chunk_scheduler.endgame_requests(&in_flight, source_ids) {
+            let Some(key) = assignment_key(&source_id) else {
+                continue;
+            };
+            if let Some(assignment) = download.source_assignments.get_mut(&key) {
+                debug!("Endgame: also requesting chunk {} from {}", chunk_id, source_id);
+                assignment.chunks.push(chunk_id);
+                if download.sequential {
+                    assignment.chunks.sort_unstable();
+                }
+            }
+        }
+    }
+
     fn calculate_progress(&self, download: &ActiveDownload) -> MultiSourceProgress {
         let total_chunks = download.chunks.len() as u32;
         let completed_chunks = download.completed_chunks.len() as u32;
@@ -2573,22 +3744,33 @@ impl MultiSourceDownloadService {
             active_sources,
             download_speed_bps,
             eta_seconds,
-            source_assignments: download.source_assignments.values().cloned().collect(),
+            source_assignments: source_assignments(download),
+            rate_limit: None,
+            provider_probes: download.provider_probes.clone(),
+            excluded_sources: download.excluded_sources.clone(),
+            web_seed_bytes: web_seed_bytes(download),
         }
     }
 
     async fn spawn_download_monitor(&self, file_hash: String) {
         let downloads = self.active_downloads.clone();
+        let command_tx = self.command_tx.clone();
         let event_tx = self.event_tx.clone();
         let transfer_event_bus = self.transfer_event_bus.clone();
         let analytics_service = self.analytics_service.clone();
+        let resume_store = self.resume_store.clone();
+        let source_exclusions = self.source_exclusions.clone();
+        let dht_service = self.dht_service.clone();
+        let stall_policy = self.stall_policy;
 
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(2));
             let start_time = std::time::Instant::now();
+            let mut stall = None;
 
             loop {
                 interval.tick().await;
+                Self::checkpoint_partial_download(&downloads, &resume_store, &file_hash).await;
 
                 let (progress, download_info, sources_used) = {
                     let downloads = downloads.read().await;
@@ -2658,7 +3840,7 @@ impl MultiSourceDownloadService {
                 if let Some(progress) = progress {
                     // Check if download is complete
                     if progress.completed_chunks >= progress.total_chunks {
-                        let (file_name, file_size, output_path) = download_info.unwrap_or_default();
+                        let (file_name, file_size, _) = download_info.unwrap_or_default();
                         let duration = start_time.elapsed();
                         let avg_speed = if duration.as_secs_f64() > 0.0 {
                             file_size as f64 / duration.as_secs_f64()
@@ -2667,47 +3849,108 @@ impl MultiSourceDownloadService {
                         };
 
                         // Finalize download
-                        if let Err(e) = Self::finalize_download_static(&downloads, &file_hash).await
+                        match Self::finalize_download_static(
+                            &downloads,
+                            &resume_store,
+                            &source_exclusions,
+                            &dht_service,
+                            &file_hash,
+                        )
+                        .await
                         {
-                            // Emit failed event via TransferEventBus with analytics
+                            Err(e) => {
+                                // Emit failed event via TransferEventBus with analytics
+                                transfer_event_bus.emit_failed_with_analytics(TransferFailedEvent {
+                                    transfer_id: file_hash.clone(),
+                                    file_hash: file_hash.clone(),
+                                    failed_at: current_timestamp_ms(),
+                                    error: format!("Failed to finalize download: {}", e),
+                                    error_category: ErrorCategory::Filesystem,
+                                    downloaded_bytes: progress.downloaded_size,
+                                    total_bytes: progress.total_size,
+                                    retry_possible: false,
+                                }, &analytics_service).await;
+                                // Also emit legacy internal event
+                                let _ = event_tx.send(MultiSourceEvent::DownloadFailed {
+                                    file_hash: file_hash.clone(),
+                                    error: format!("Failed to finalize download: {}", e),
+                                });
+                            }
+                            Ok(output_path) => {
+                                // Emit completed event via TransferEventBus with analytics
+                                transfer_event_bus.emit_completed_with_analytics(TransferCompletedEvent {
+                                    transfer_id: file_hash.clone(),
+                                    file_hash: file_hash.clone(),
+                                    file_name,
+                                    file_size,
+                                    output_path: output_path.clone(),
+                                    completed_at: current_timestamp_ms(),
+                                    duration_seconds: duration.as_secs(),
+                                    average_speed_bps: avg_speed,
+                                    total_chunks: progress.total_chunks,
+                                    sources_used,
+                                }, &analytics_service).await;
+                                // Also emit legacy internal event
+                                let _ = event_tx.send(MultiSourceEvent::DownloadCompleted {
+                                    file_hash: file_hash.clone(),
+                                    output_path,
+                                    duration_secs: duration.as_secs(),
+                                    average_speed_bps: avg_speed,
+                                });
+                            }
+                        }
+                        break;
+                    }
+
+                    let now = Instant::now();
+                    let verdict = stall
+                        .get_or_insert_with(|| {
+                            StallDetector::new(stall_policy, progress.downloaded_size, now)
+                        })
+                        .observe(progress.downloaded_size, now);
+                    match verdict {
+                        StallVerdict::Recover { attempt, stalled_for } => {
+                            let _ = command_tx.send(MultiSourceCommand::RecoverStalled {
+                                file_hash: file_hash.clone(),
+                                attempt,
+                                stalled_for,
+                            });
+                        }
+                        StallVerdict::Fail { attempts, stalled_for } => {
+                            // The resume record stays, so the download can be
+                            // picked up again later
+                            downloads.write().await.remove(&file_hash);
+                            let error = format!(
+                                "stalled: no data for {}s after {} recovery attempt(s)",
+                                stalled_for.as_secs(),
+                                attempts
+                            );
+                            warn!("Download {} {}", file_hash, error);
                             transfer_event_bus.emit_failed_with_analytics(TransferFailedEvent {
                                 transfer_id: file_hash.clone(),
                                 file_hash: file_hash.clone(),
                                 failed_at: current_timestamp_ms(),
-                                error: format!("Failed to finalize download: {}", e),
-                                error_category: ErrorCategory::Filesystem,
+                                error: error.clone(),
+                                error_category: ErrorCategory::Stalled,
                                 downloaded_bytes: progress.downloaded_size,
                                 total_bytes: progress.total_size,
-                                retry_possible: false,
+                                retry_possible: true,
                             }, &analytics_service).await;
-                            // Also emit legacy internal event
                             let _ = event_tx.send(MultiSourceEvent::DownloadFailed {
                                 file_hash: file_hash.clone(),
-                                error: format!("Failed to finalize download: {}", e),
-                            });
-                        } else {
-                            // Emit completed event via TransferEventBus with analytics
-                            transfer_event_bus.emit_completed_with_analytics(TransferCompletedEvent {
-                                transfer_id: file_hash.clone(),
-                                file_hash: file_hash.clone(),
-                                file_name,
-                                file_size,
-                                output_path,
-                                completed_at: current_timestamp_ms(),
-                                duration_seconds: duration.as_secs(),
-                                average_speed_bps: avg_speed,
-                                total_chunks: progress.total_chunks,
-                                sources_used,
-                            }, &analytics_service).await;
-                            // Also emit legacy internal event
-                            let _ = event_tx.send(MultiSourceEvent::DownloadCompleted {
-                                file_hash: file_hash.clone(),
-                                output_path: String::new(),
-                                duration_secs: duration.as_secs(),
-                                average_speed_bps: avg_speed,
+                                error,
                             });
+                            break;
                         }
-                        break;
+                        StallVerdict::Progressing | StallVerdict::Waiting => {}
+                    }
+
+                    // Near the end, duplicate the outstanding requests (endgame)
+                    let outstanding = progress.total_chunks.saturating_sub(progress.completed_chunks);
+                    if outstanding as usize <= ENDGAME_CHUNKS {
+                        let _ = command_tx.send(MultiSourceCommand::RetryFailedChunks {
+                            file_hash: file_hash.clone(),
+                        });
                     }
 
                     // Emit progress update via TransferEventBus with analytics
@@ -2720,7 +3963,9 @@ impl MultiSourceDownloadService {
                         progress_percentage: calculate_progress(progress.downloaded_size, progress.total_size),
                         download_speed_bps: progress.download_speed_bps,
                         upload_speed_bps: 0.0,
+                        instantaneous_speed_bps: progress.download_speed_bps,
                         eta_seconds: progress.eta_seconds,
+                        stalled: false,
                         active_sources: progress.active_sources,
                         timestamp: current_timestamp_ms(),
                     }, &analytics_service).await;
@@ -2783,35 +4028,100 @@ impl MultiSourceDownloadService {
             active_sources,
             download_speed_bps,
             eta_seconds,
-            source_assignments: download.source_assignments.values().cloned().collect(),
+            source_assignments: source_assignments(download),
+            rate_limit: None,
+            provider_probes: download.provider_probes.clone(),
+            excluded_sources: download.excluded_sources.clone(),
+            web_seed_bytes: web_seed_bytes(download),
         }
     }
 
     async fn finalize_download_static(
         downloads: &Arc<RwLock<HashMap<String, ActiveDownload>>>,
+        resume_store: &ResumeStore,
+        source_exclusions: &Arc<Mutex<SourceExclusions>>,
+        dht_service: &Arc<DhtService>,
         file_hash: &str,
-    ) -> Result<(), String> {
+    ) -> Result<String, String> {
         let download = {
             let mut downloads = downloads.write().await;
             downloads.remove(file_hash)
         };
 
         if let Some(download) = download {
-            // Assemble file from chunks
-            let mut file_data = vec![0u8; download.file_metadata.file_size as usize];
-
-            for chunk_info in &download.chunks {
-                if let Some(completed_chunk) = download.completed_chunks.get(&chunk_info.chunk_id) {
-                    let start = chunk_info.offset as usize;
-                    let end = start + completed_chunk.data.len();
-                    file_data[start..end].copy_from_slice(&completed_chunk.data);
+            let assembled = assemble_verified_file(
+                &download.chunks,
+                &download.completed_chunks,
+                download.file_metadata.file_size,
+            );
+            let file_data = match assembled {
+                Ok(file_data) => file_data,
+                Err(error) => {
+                    // Chunks read back from the partial file are our own
+                    let corrupt = corrupt_chunk_sources(&download.chunks, &download.completed_chunks);
+                    for (source_id, reason) in corrupt {
+                        if source_id != RESUMED_SOURCE_ID {
+                            Self::record_corruption(
+                                source_exclusions,
+                                downloads,
+                                dht_service,
+                                file_hash,
+                                &source_id,
+                                &reason,
+                            )
+                            .await;
+                        }
+                    }
+                    return Err(error);
                 }
-            }
+            };
+            let metadata = download.file_metadata.clone();
+            let verified = tokio::task::spawn_blocking(move || {
+                verify_file_hash(&metadata, &file_data).map(|()| file_data)
+            })
+            .await
+            .map_err(|e| format!("Failed to verify file: {}", e))?;
+            let file_data = match verified {
+                Ok(file_data) => file_data,
+                Err(error) => {
+                    // Some chunk is bad but not which one, so start over next time
+                    if let Some(record) = resume_store.remove(file_hash).await {
+                        let _ = tokio::fs::remove_file(record.partial_path()).await;
+                    }
+                    if let Err(e) = resume_store.flush().await {
+                        warn!("Failed to save resume records: {}", e);
+                    }
+                    return Err(error);
+                }
+            };
 
-            // Write file to disk
-            tokio::fs::write(&download.output_path, file_data)
+            // The destination only ever sees the complete, verified file
+            let partial = download_resume::partial_path(&download.output_path);
+            tokio::fs::write(&partial, file_data)
                 .await
                 .map_err(|e| format!("Failed to write file: {}", e))?;
+            let requested = PathBuf::from(&download.output_path);
+            let overwrite = download.overwrite;
+            let final_path = tokio::task::spawn_blocking(move || {
+                let dest = crate::storage::resolve_destination(&requested, overwrite);
+                crate::storage::promote_partial(&partial, &dest).map(|()| dest)
+            })
+            .await
+            .map_err(|e| format!("Failed to move file into place: {}", e))?
+            .map_err(|e| format!("Failed to move file into place: {}", e))?;
+            let final_path = final_path.to_string_lossy().into_owned();
+            if final_path != download.output_path {
+                info!(
+                    "{} already exists, saved download as {}",
+                    download.output_path, final_path
+                );
+            }
+
+            // Nothing left to resume
+            resume_store.remove(file_hash).await;
+            if let Err(e) = resume_store.flush().await {
+                warn!("Failed to save resume records: {}", e);
+            }
 
             let duration = download.start_time.elapsed();
             let average_speed = download.file_metadata.file_size as f64 / duration.as_secs_f64();
@@ -2824,7 +4134,7 @@ impl MultiSourceDownloadService {
                 average_speed / 1024.0
             );
 
-            Ok(())
+            Ok(final_path)
         } else {
             Err("Download not found".to_string())
         }
@@ -3010,6 +4320,64 @@ mod tests {
         assert!(verify_chunk_integrity(&chunk, data).is_ok());
     }
 
+    #[test]
+    fn assemble_verified_file_rejects_missing_and_corrupt_chunks() {
+        let parts: [&[u8]; 2] = [b"hello ", b"world"];
+        let chunks = vec![
+            ChunkInfo {
+                chunk_id: 0,
+                offset: 0,
+                size: 6,
+                hash: hex::encode(Sha256::digest(parts[0])),
+            },
+            ChunkInfo {
+                chunk_id: 1,
+                offset: 6,
+                size: 5,
+                hash: hex::encode(Sha256::digest(parts[1])),
+            },
+        ];
+        let completed_chunk = |chunk_id: u32, data: &[u8]| CompletedChunk {
+            chunk_id,
+            data: data.to_vec(),
+            source_id: "peer".to_string(),
+            completed_at: Instant::now(),
+        };
+
+        let mut completed = HashMap::new();
+        completed.insert(0, completed_chunk(0, parts[0]));
+        assert!(assemble_verified_file(&chunks, &completed, 11).is_err());
+
+        assert!(corrupt_chunk_sources(&chunks, &completed).is_empty());
+
+        completed.insert(1, completed_chunk(1, b"wormd"));
+        assert!(assemble_verified_file(&chunks, &completed, 11).is_err());
+        let corrupt = corrupt_chunk_sources(&chunks, &completed);
+        assert_eq!(corrupt.len(), 1);
+        assert_eq!(corrupt[0].0, "peer");
+
+        completed.insert(1, completed_chunk(1, parts[1]));
+        assert_eq!(
+            assemble_verified_file(&chunks, &completed, 11).unwrap(),
+            b"hello world"
+        );
+    }
+
+    #[test]
+    fn verify_file_hash_compares_sha256_keys_only() {
+        let metadata = |merkle_root: String| FileMetadata {
+            merkle_root,
+            file_size: 11,
+            ..Default::default()
+        };
+        let hash = hex::encode(Sha256::digest(b"hello world"));
+        assert!(verify_file_hash(&metadata(hash.clone()), b"hello world").is_ok());
+        assert!(verify_file_hash(&metadata(hash), b"hello wormd").is_err());
+        // An info hash says nothing about the SHA-256 of the data
+        let info_hash = "08ada5a7a6183aae1e09d831df6748d566095a10".to_string();
+        assert!(verify_file_hash(&metadata(info_hash), b"hello wormd").is_ok());
+    }
+
     // Helper function to create mock services
     fn create_mock_services() -> (Arc<DhtService>, Arc<WebRTCService>) {
         // For testing, we'll skip actual service initialization
@@ -3151,4 +4519,64 @@ mod tests {
         assert_eq!(http_source.priority_score(), 50);
         assert_eq!(p2p_source.priority_score(), 180); // 100 + 80 reputation
     }
+
+    fn completed(chunk_id: u32, data: &[u8]) -> CompletedChunk {
+        CompletedChunk {
+            chunk_id,
+            data: data.to_vec(),
+            source_id: "peer".to_string(),
+            completed_at: Instant::now(),
+        }
+    }
+
+    fn four_byte_chunks(count: u32) -> Vec<ChunkInfo> {
+        (0..count)
+            .map(|chunk_id| ChunkInfo {
+                chunk_id,
+                offset: chunk_id as u64 * 4,
+                size: 4,
+                hash: String::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_read_verified_range_spans_chunks() {
+        let chunks = four_byte_chunks(3);
+        let mut done = HashMap::new();
+        done.insert(0, completed(0, b"abcd"));
+        done.insert(1, completed(1, b"efgh"));
+
+        assert_eq!(
+            read_verified_range(&chunks, &done, 12, 2, 4).unwrap(),
+            RangeRead::Ready(b"cdef".to_vec())
+        );
+        assert_eq!(
+            read_verified_range(&chunks, &done, 12, 6, 4).unwrap(),
+            RangeRead::NotYetAvailable { chunk_id: 2 }
+        );
+        assert!(read_verified_range(&chunks, &done, 12, 12, 1).is_err());
+    }
+
+    #[test]
+    fn test_read_verified_range_truncates_at_end_of_file() {
+        let mut chunks = four_byte_chunks(2);
+        chunks[1].size = 2;
+        let mut done = HashMap::new();
+        done.insert(0, completed(0, b"abcd"));
+        done.insert(1, completed(1, b"ef"));
+
+        assert_eq!(
+            read_verified_range(&chunks, &done, 6, 3, 100).unwrap(),
+            RangeRead::Ready(b"def".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_next_retry_batch_orders_sequential_downloads() {
+        let mut failed: VecDeque<u32> = vec![7, 2, 9, 4].into();
+        assert_eq!(next_retry_batch(&mut failed.clone(), false, 2), vec![7, 2]);
+        assert_eq!(next_retry_batch(&mut failed, true, 3), vec![2, 4, 7]);
+        assert_eq!(failed, VecDeque::from(vec![9]));
+    }
 }
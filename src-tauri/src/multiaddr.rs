@@ -0,0 +1,93 @@
+//! One spelling per address.
+//!
+//! A dual-stack host can report the same IPv4 address as `/ip4/1.2.3.4` and
+//! as the IPv4-mapped IPv6 address `/ip6/::ffff:1.2.3.4` (RFC 4291 section
+//! 2.5.5.2), and the peer stores would keep both as separate entries.
+//! Addresses are normalized before they are stored or dialed as bootstrap
+//! nodes, so the two collapse into the IPv4 form. Only the mapped form
+//! `::ffff:0:0/96` is rewritten; the deprecated IPv4-compatible form
+//! (`::1.2.3.4`), NAT64 (`64:ff9b::/96`) and SIIT (`::ffff:0:0:0/96`)
+//! addresses name different hosts and are left alone.
+
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+
+/// `addr` with every IPv4-mapped IPv6 component replaced by its IPv4
+/// address, including those inside a relay circuit
+pub fn normalize(addr: &Multiaddr) -> Multiaddr {
+    addr.iter()
+        .map(|component| match component {
+            Protocol::Ip6(ip) => match ip.to_ipv4_mapped() {
+                Some(v4) => Protocol::Ip4(v4),
+                None => Protocol::Ip6(ip),
+            },
+            other => other,
+        })
+        .collect()
+}
+
+/// `addrs` normalized, keeping the first of any that normalize alike
+pub fn normalize_all(addrs: &[Multiaddr]) -> Vec<Multiaddr> {
+    let mut normalized: Vec<Multiaddr> = Vec::with_capacity(addrs.len());
+    for addr in addrs.iter().map(normalize) {
+        if !normalized.contains(&addr) {
+            normalized.push(addr);
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ma(s: &str) -> Multiaddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_mapped_addresses_become_ipv4() {
+        let peer = "12D3KooWFYTuQ2FY8tXRtFKfpXkTSipTF55mZkLntwtN1nHu83qE";
+        let cases = [
+            ("/ip6/::ffff:1.2.3.4/tcp/4001", "/ip4/1.2.3.4/tcp/4001"),
+            (
+                "/ip6/::ffff:102:304/udp/4001/quic-v1",
+                "/ip4/1.2.3.4/udp/4001/quic-v1",
+            ),
+            ("/ip6/0:0:0:0:0:ffff:7f00:1/tcp/1", "/ip4/127.0.0.1/tcp/1"),
+            ("/ip6/::ffff:0.0.0.0/tcp/0", "/ip4/0.0.0.0/tcp/0"),
+            // Other IPv6 forms that embed an IPv4 address are other hosts
+            ("/ip6/::1.2.3.4/tcp/4001", "/ip6/::1.2.3.4/tcp/4001"),
+            (
+                "/ip6/64:ff9b::1.2.3.4/tcp/4001",
+                "/ip6/64:ff9b::1.2.3.4/tcp/4001",
+            ),
+            (
+                "/ip6/::ffff:0:1.2.3.4/tcp/4001",
+                "/ip6/::ffff:0:1.2.3.4/tcp/4001",
+            ),
+            ("/ip6/::ffff/tcp/4001", "/ip6/::ffff/tcp/4001"),
+            ("/ip6/::1/tcp/4001", "/ip6/::1/tcp/4001"),
+            ("/ip4/1.2.3.4/tcp/4001", "/ip4/1.2.3.4/tcp/4001"),
+            ("/dns4/ffff.example/tcp/443", "/dns4/ffff.example/tcp/443"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalize(&ma(input)), ma(expected), "{}", input);
+        }
+        let circuit = format!("/tcp/4001/p2p/{peer}/p2p-circuit/p2p/{peer}");
+        assert_eq!(
+            normalize(&ma(&format!("/ip6/::ffff:10.0.0.1{circuit}"))),
+            ma(&format!("/ip4/10.0.0.1{circuit}"))
+        );
+
+        let addrs = normalize_all(&[
+            ma("/ip4/1.2.3.4/tcp/4001"),
+            ma("/ip6/::ffff:1.2.3.4/tcp/4001"),
+            ma("/ip4/1.2.3.4/tcp/4002"),
+        ]);
+        assert_eq!(
+            addrs,
+            vec![ma("/ip4/1.2.3.4/tcp/4001"), ma("/ip4/1.2.3.4/tcp/4002")]
+        );
+    }
+}
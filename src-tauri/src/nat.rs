@@ -0,0 +1,375 @@
+//! Adaptive scheduling of AutoNAT reachability probes.
+//!
+//! Probing at a fixed interval wastes bandwidth once the NAT status has
+//! settled. The scheduler probes often while the status is unknown, backs off
+//! once repeated results agree, and probes straight away when the set of
+//! local network interfaces changes.
+//!
+//! The AutoNAT v2 client would otherwise probe on its own fixed timer, so it is
+//! wrapped in [`ScheduledAutoNATClient`], which only lets it run while the
+//! scheduler has opened a probe window.
+
+use crate::dht::models::NatReachabilityState;
+use libp2p::autonat::v2;
+use libp2p::core::{transport::PortUse, Endpoint};
+use libp2p::swarm::{
+    ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler, THandlerInEvent,
+    THandlerOutEvent, ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId};
+use serde::Serialize;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+/// Probe interval while reachability is unknown or not yet confirmed
+pub const UNKNOWN_PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Probe interval once the status is a stable `Public` or `Private`
+pub const STABLE_PROBE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How long a probe window stays open when no result comes back
+pub const PROBE_WINDOW: Duration = Duration::from_secs(15);
+
+/// Confidence at which a `Public` or `Private` status counts as stable
+pub const STABLE_CONFIDENCE: f64 = 0.75;
+
+/// How the last probe results agree with each other
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoNATConfidence {
+    pub status: NatReachabilityState,
+    /// 0.0 (no evidence) to 1.0 (many consecutive agreeing probes)
+    pub confidence: f64,
+    /// Consecutive probes that reported `status`
+    pub agreeing_probes: u32,
+}
+
+impl Default for AutoNATConfidence {
+    fn default() -> Self {
+        Self {
+            status: NatReachabilityState::Unknown,
+            confidence: 0.0,
+            agreeing_probes: 0,
+        }
+    }
+}
+
+impl AutoNATConfidence {
+    /// Fold a probe result in: agreeing results raise confidence, a change resets it
+    pub fn record(&mut self, result: NatReachabilityState) {
+        if result == self.status {
+            self.agreeing_probes = self.agreeing_probes.saturating_add(1);
+        } else {
+            self.status = result;
+            self.agreeing_probes = 1;
+        }
+        self.confidence = match self.status {
+            NatReachabilityState::Unknown => 0.0,
+            _ => 1.0 - 0.5f64.powi(self.agreeing_probes as i32),
+        };
+    }
+
+    /// Whether the status is a known state backed by enough agreeing probes
+    pub fn is_stable(&self) -> bool {
+        self.status != NatReachabilityState::Unknown && self.confidence >= STABLE_CONFIDENCE
+    }
+}
+
+/// Decides when the next AutoNAT probe runs.
+///
+/// Cloning yields another handle to the same scheduler, so the swarm loop can
+/// feed results and interface changes into the task that runs it.
+#[derive(Clone, Default)]
+pub struct AutoNATProbeScheduler {
+    confidence: Arc<Mutex<AutoNATConfidence>>,
+    interface_changed: Arc<Notify>,
+}
+
+impl AutoNATProbeScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, AutoNATConfidence> {
+        self.confidence.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn confidence(&self) -> AutoNATConfidence {
+        *self.lock()
+    }
+
+    /// Record the outcome of a probe
+    pub fn record_result(&self, result: NatReachabilityState) {
+        let mut confidence = self.lock();
+        confidence.record(result);
+        debug!(
+            "AutoNAT status {:?} (confidence {:.2})",
+            confidence.status, confidence.confidence
+        );
+    }
+
+    /// Ask for a probe right away because a local network interface changed
+    pub fn notify_interface_change(&self) {
+        self.interface_changed.notify_one();
+    }
+
+    /// Delay until the next probe given what is currently known
+    pub fn next_interval(&self) -> Duration {
+        if self.lock().is_stable() {
+            STABLE_PROBE_INTERVAL
+        } else {
+            UNKNOWN_PROBE_INTERVAL
+        }
+    }
+
+    /// Run `probe` on schedule until `shutdown_token` is cancelled
+    pub async fn run<F, Fut>(self, mut probe: F, shutdown_token: CancellationToken)
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        loop {
+            let delay = self.next_interval();
+            tokio::select! {
+                _ = shutdown_token.cancelled() => break,
+                _ = tokio::time::sleep(delay) => {}
+                _ = self.interface_changed.notified() => {
+                    info!("Network interfaces changed, probing NAT status now");
+                }
+            }
+            probe().await;
+        }
+        debug!("AutoNAT probe scheduler stopped");
+    }
+}
+
+/// Tracks whether the AutoNAT client may currently run a probe round
+#[derive(Debug, Default)]
+pub struct ProbeWindow {
+    open_until: Option<Instant>,
+}
+
+impl ProbeWindow {
+    /// Let one probe round run, for at most [`PROBE_WINDOW`]
+    pub fn open(&mut self, now: Instant) {
+        self.open_until = Some(now + PROBE_WINDOW);
+    }
+
+    /// End the window once the round has produced a result
+    pub fn close(&mut self) {
+        self.open_until = None;
+    }
+
+    pub fn is_open(&self, now: Instant) -> bool {
+        self.open_until.is_some_and(|until| now < until)
+    }
+}
+
+/// AutoNAT v2 client that probes only when [`AutoNATProbeScheduler`] asks.
+///
+/// Connection handling is always forwarded so the client keeps track of
+/// servers and candidate addresses, but the inner behaviour is only polled
+/// while a window is open. Its own probe timer has long expired by then, so
+/// opening a window runs a round straight away and the first result closes it.
+pub struct ScheduledAutoNATClient {
+    inner: v2::client::Behaviour,
+    window: ProbeWindow,
+}
+
+impl ScheduledAutoNATClient {
+    pub fn new(inner: v2::client::Behaviour) -> Self {
+        Self {
+            inner,
+            window: ProbeWindow::default(),
+        }
+    }
+
+    /// Run a probe round the next time the swarm polls this behaviour
+    pub fn probe(&mut self) {
+        self.window.open(Instant::now());
+    }
+}
+
+impl NetworkBehaviour for ScheduledAutoNATClient {
+    type ConnectionHandler = THandler<v2::client::Behaviour>;
+    type ToSwarm = v2::client::Event;
+
+    fn handle_pending_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<(), ConnectionDenied> {
+        self.inner
+            .handle_pending_inbound_connection(connection_id, local_addr, remote_addr)
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.inner
+            .handle_established_inbound_connection(connection_id, peer, local_addr, remote_addr)
+    }
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        maybe_peer: Option<PeerId>,
+        addresses: &[Multiaddr],
+        effective_role: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        self.inner.handle_pending_outbound_connection(
+            connection_id,
+            maybe_peer,
+            addresses,
+            effective_role,
+        )
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        addr: &Multiaddr,
+        role_override: Endpoint,
+        port_use: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.inner.handle_established_outbound_connection(
+            connection_id,
+            peer,
+            addr,
+            role_override,
+            port_use,
+        )
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        self.inner.on_swarm_event(event);
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        self.inner
+            .on_connection_handler_event(peer_id, connection_id, event);
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        // The swarm polls again after every command, so a window opened by
+        // `probe` is picked up without a waker of our own
+        if !self.window.is_open(Instant::now()) {
+            return Poll::Pending;
+        }
+        let event = self.inner.poll(cx);
+        if let Poll::Ready(ToSwarm::GenerateEvent(_)) = &event {
+            self.window.close();
+        }
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_interval_backs_off_once_status_is_stable() {
+        let scheduler = AutoNATProbeScheduler::new();
+        assert_eq!(scheduler.next_interval(), UNKNOWN_PROBE_INTERVAL);
+
+        scheduler.record_result(NatReachabilityState::Public);
+        assert_eq!(scheduler.next_interval(), UNKNOWN_PROBE_INTERVAL);
+        scheduler.record_result(NatReachabilityState::Public);
+        assert_eq!(scheduler.confidence().agreeing_probes, 2);
+        assert_eq!(scheduler.next_interval(), STABLE_PROBE_INTERVAL);
+
+        // A flip resets confidence and goes back to probing often
+        scheduler.record_result(NatReachabilityState::Private);
+        assert_eq!(scheduler.confidence().confidence, 0.5);
+        assert_eq!(scheduler.next_interval(), UNKNOWN_PROBE_INTERVAL);
+
+        scheduler.record_result(NatReachabilityState::Unknown);
+        assert_eq!(scheduler.confidence().confidence, 0.0);
+    }
+
+    #[test]
+    fn test_probe_rate_drops_once_status_is_stable() {
+        let hour = Duration::from_secs(60 * 60);
+        let probes_per_hour = |scheduler: &AutoNATProbeScheduler, result| {
+            let mut window = ProbeWindow::default();
+            let start = Instant::now();
+            let mut elapsed = Duration::ZERO;
+            let mut probes = 0;
+            loop {
+                elapsed += scheduler.next_interval();
+                if elapsed > hour {
+                    break probes;
+                }
+                // Each scheduled probe opens one window, and its result closes it
+                window.open(start + elapsed);
+                assert!(window.is_open(start + elapsed));
+                probes += 1;
+                scheduler.record_result(result);
+                window.close();
+                assert!(!window.is_open(start + elapsed));
+            }
+        };
+
+        let unknown = probes_per_hour(&AutoNATProbeScheduler::new(), NatReachabilityState::Unknown);
+        let stable = probes_per_hour(&AutoNATProbeScheduler::new(), NatReachabilityState::Public);
+        assert_eq!(unknown, 120);
+        // Two probes at the unknown interval, then the stable interval
+        assert!(stable < unknown / 8, "{stable} probes/hour once stable");
+
+        // A window nobody answers closes on its own
+        let mut window = ProbeWindow::default();
+        let now = Instant::now();
+        assert!(!window.is_open(now));
+        window.open(now);
+        assert!(window.is_open(now + PROBE_WINDOW / 2));
+        assert!(!window.is_open(now + PROBE_WINDOW));
+    }
+
+    #[tokio::test]
+    async fn test_interface_change_probes_immediately_and_shutdown_stops() {
+        let scheduler = AutoNATProbeScheduler::new();
+        let token = CancellationToken::new();
+        let probes = Arc::new(AtomicU32::new(0));
+
+        let task = {
+            let scheduler = scheduler.clone();
+            let token = token.clone();
+            let probes = probes.clone();
+            tokio::spawn(scheduler.run(
+                move || {
+                    probes.fetch_add(1, Ordering::SeqCst);
+                    async {}
+                },
+                token,
+            ))
+        };
+
+        scheduler.notify_interface_change();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(probes.load(Ordering::SeqCst), 1);
+
+        token.cancel();
+        task.await.unwrap();
+    }
+}
@@ -0,0 +1,243 @@
+//! History of NAT test runs, kept to see whether traversal is getting
+//! better or worse over time.
+//!
+//! Each run of a NAT test suite is one `NatTestResult` appended to a SQLite
+//! database at `~/.chiral/nat_test_history.db`. The `nat_test` binary prints
+//! the history and compares the two latest runs:
+//!
+//! ```text
+//! nat_test --show-history --last 10
+//! nat_test --compare-last-two   # exits 1 if the success rate dropped
+//! nat_test --scenario lossy     # run `lossy` in Docker and record it
+//! ```
+
+pub mod lossy;
+
+use rusqlite::{params, Connection};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One run of a NAT test suite
+#[derive(Debug, Clone, PartialEq)]
+pub struct NatTestResult {
+    /// Unix seconds when the run finished
+    pub timestamp: u64,
+    /// Suite or scenario name, e.g. `e2e`
+    pub scenario: String,
+    pub passed: u32,
+    pub failed: u32,
+    /// Peers the test nodes were connected to at the end of the run
+    pub peer_count: u32,
+    pub dcutr_attempts: u64,
+    pub dcutr_successes: u64,
+}
+
+impl NatTestResult {
+    /// A result stamped with the current time
+    pub fn now(scenario: impl Into<String>, passed: u32, failed: u32) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            scenario: scenario.into(),
+            passed,
+            failed,
+            peer_count: 0,
+            dcutr_attempts: 0,
+            dcutr_successes: 0,
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.failed == 0 && self.passed > 0
+    }
+
+    /// Share of passed tests, 0.0 for a run without tests
+    pub fn success_rate(&self) -> f64 {
+        let total = self.passed + self.failed;
+        if total == 0 {
+            0.0
+        } else {
+            self.passed as f64 / total as f64
+        }
+    }
+
+    /// Share of successful hole punches, `None` without attempts
+    pub fn dcutr_rate(&self) -> Option<f64> {
+        (self.dcutr_attempts > 0).then(|| self.dcutr_successes as f64 / self.dcutr_attempts as f64)
+    }
+}
+
+/// Change between two runs, `previous` being the older
+#[derive(Debug, Clone, PartialEq)]
+pub struct NatTestComparison {
+    pub previous: NatTestResult,
+    pub latest: NatTestResult,
+}
+
+impl NatTestComparison {
+    pub fn success_rate_delta(&self) -> f64 {
+        self.latest.success_rate() - self.previous.success_rate()
+    }
+
+    pub fn dcutr_rate_delta(&self) -> Option<f64> {
+        Some(self.latest.dcutr_rate()? - self.previous.dcutr_rate()?)
+    }
+
+    pub fn regressed(&self) -> bool {
+        self.success_rate_delta() < 0.0
+    }
+}
+
+pub struct NatTestResultStore {
+    conn: Mutex<Connection>,
+}
+
+impl NatTestResultStore {
+    /// `~/.chiral/nat_test_history.db`
+    pub fn default_path() -> PathBuf {
+        directories::BaseDirs::new()
+            .map(|dirs| dirs.home_dir().join(".chiral").join("nat_test_history.db"))
+            .unwrap_or_else(|| PathBuf::from("nat_test_history.db"))
+    }
+
+    /// Open (or create) the history at `db_path`
+    pub fn open(db_path: &Path) -> rusqlite::Result<Self> {
+        if let Some(parent) = db_path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                tracing::warn!("Failed to create NAT test history directory {:?}: {}", parent, e);
+            }
+        }
+        let conn = Connection::open(db_path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS nat_test_results (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp       INTEGER NOT NULL,
+                scenario        TEXT NOT NULL,
+                passed          INTEGER NOT NULL,
+                failed          INTEGER NOT NULL,
+                peer_count      INTEGER NOT NULL,
+                dcutr_attempts  INTEGER NOT NULL,
+                dcutr_successes INTEGER NOT NULL
+            );",
+        )?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn append(&self, result: NatTestResult) -> rusqlite::Result<()> {
+        self.lock().execute(
+            "INSERT INTO nat_test_results
+                (timestamp, scenario, passed, failed, peer_count, dcutr_attempts, dcutr_successes)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                result.timestamp as i64,
+                result.scenario,
+                result.passed,
+                result.failed,
+                result.peer_count,
+                result.dcutr_attempts as i64,
+                result.dcutr_successes as i64,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The latest `n` runs, newest first
+    pub fn last(&self, n: usize) -> rusqlite::Result<Vec<NatTestResult>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, scenario, passed, failed, peer_count, dcutr_attempts, dcutr_successes
+             FROM nat_test_results ORDER BY timestamp DESC, id DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(params![n as i64], |row| {
+            Ok(NatTestResult {
+                timestamp: row.get::<_, i64>(0)? as u64,
+                scenario: row.get(1)?,
+                passed: row.get(2)?,
+                failed: row.get(3)?,
+                peer_count: row.get(4)?,
+                dcutr_attempts: row.get::<_, i64>(5)? as u64,
+                dcutr_successes: row.get::<_, i64>(6)? as u64,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// The two latest runs, `None` with fewer than two recorded
+    pub fn compare_last_two(&self) -> rusqlite::Result<Option<NatTestComparison>> {
+        let mut runs = self.last(2)?;
+        if runs.len() < 2 {
+            return Ok(None);
+        }
+        let previous = runs.pop().expect("two runs");
+        let latest = runs.pop().expect("two runs");
+        Ok(Some(NatTestComparison { previous, latest }))
+    }
+}
+
+fn percent(rate: Option<f64>) -> String {
+    rate.map(|r| format!("{:.1}%", r * 100.0)).unwrap_or_else(|| "-".to_string())
+}
+
+/// Table of `runs`, one per line
+pub fn format_history(runs: &[NatTestResult]) -> String {
+    let mut out = format!(
+        "{:<20} {:<12} {:<7} {:>7} {:>7} {:>6} {:>7}\n",
+        "TIMESTAMP", "SCENARIO", "RESULT", "PASSED", "RATE", "PEERS", "DCUTR"
+    );
+    for run in runs {
+        let timestamp = chrono::DateTime::from_timestamp(run.timestamp as i64, 0)
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| run.timestamp.to_string());
+        let _ = writeln!(
+            out,
+            "{:<20} {:<12} {:<7} {:>7} {:>7} {:>6} {:>7}",
+            timestamp,
+            run.scenario,
+            if run.succeeded() { "pass" } else { "fail" },
+            format!("{}/{}", run.passed, run.passed + run.failed),
+            percent(Some(run.success_rate())),
+            run.peer_count,
+            percent(run.dcutr_rate()),
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_is_newest_first_and_regressions_are_flagged() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = NatTestResultStore::open(&dir.path().join("history.db")).unwrap();
+        assert!(store.compare_last_two().unwrap().is_none());
+
+        let mut older = NatTestResult::now("e2e", 9, 1);
+        older.timestamp = 1_700_000_000;
+        older.dcutr_attempts = 10;
+        older.dcutr_successes = 6;
+        let mut newer = NatTestResult::now("e2e", 7, 3);
+        newer.timestamp = 1_700_000_600;
+        store.append(older.clone()).unwrap();
+        store.append(newer.clone()).unwrap();
+
+        assert_eq!(store.last(5).unwrap(), vec![newer.clone(), older.clone()]);
+        let comparison = store.compare_last_two().unwrap().unwrap();
+        assert!(comparison.regressed());
+        assert!((comparison.success_rate_delta() + 0.2).abs() < 1e-9);
+        assert_eq!(comparison.dcutr_rate_delta(), None);
+
+        let table = format_history(&[older]);
+        assert!(table.contains("9/10"));
+        assert!(table.contains("60.0%"));
+    }
+}
@@ -0,0 +1,557 @@
+//! Lossy network scenario: headless nodes in Docker containers, with
+//! `tc netem` dropping a share of the packets each container sends.
+//!
+//! The containers share a bridge network created with
+//! `com.docker.network.bridge.enable_ip_masquerade=false`, so they reach each
+//! other but not the internet. One node is the bootstrap node and the others
+//! join through it. Every node joins one channel and posts each of its
+//! messages once. Getting them through the loss is left to the nodes
+//! themselves: TCP retransmits, and gossipsub re-offers what a peer missed
+//! through its IHAVE/IWANT gossip.
+//!
+//! The run passes when every message reached every node before the timeout,
+//! every node reports an AutoNAT status, and at least one node recorded a
+//! DCUtR attempt. A run where no node ever tried to hole-punch fails, since
+//! it says nothing about NAT traversal under loss.
+//!
+//! The scenario needs `docker` on the PATH and an image built from the
+//! repository `Dockerfile`. The host kernel needs the `sch_netem` module.
+
+use super::NatTestResult;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+/// Name the scenario is recorded under in the history
+pub const SCENARIO: &str = "lossy";
+
+/// Port of the control API inside each container
+const API_PORT: u16 = 9480;
+
+const DHT_PORT: u16 = 4001;
+
+const CHANNEL: &str = "nat-test-lossy";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Time a container gets to write its API token and answer
+const START_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct LossyScenario {
+    /// Image built from the repository `Dockerfile`
+    pub image: String,
+    /// Nodes started besides the bootstrap node
+    pub nodes: usize,
+    /// Share of packets dropped, in percent
+    pub loss_percent: u8,
+    /// Messages each node posts
+    pub messages: usize,
+    /// Time the messages have to reach every node
+    pub timeout: Duration,
+}
+
+impl Default for LossyScenario {
+    fn default() -> Self {
+        Self {
+            image: "chiral-network".to_string(),
+            nodes: 2,
+            loss_percent: 20,
+            messages: 3,
+            timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+/// What one node reported at the end of the run
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeReport {
+    pub name: String,
+    pub peer_count: u32,
+    /// As `GET /nat` reports it: `unknown`, `public` or `private`
+    pub reachability: String,
+    pub autonat_enabled: bool,
+    pub dcutr_attempts: u64,
+    pub dcutr_successes: u64,
+}
+
+impl NodeReport {
+    pub fn has_autonat_status(&self) -> bool {
+        self.autonat_enabled && self.reachability != "unknown"
+    }
+}
+
+/// A posted message and the nodes it has not reached yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    pub payload: String,
+    pub sender: usize,
+    pub missing: BTreeSet<usize>,
+}
+
+impl Delivery {
+    fn new(payload: String, sender: usize, nodes: usize) -> Self {
+        Self {
+            payload,
+            sender,
+            missing: (0..nodes).filter(|node| *node != sender).collect(),
+        }
+    }
+
+    pub fn delivered(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LossyReport {
+    pub loss_percent: u8,
+    pub nodes: Vec<NodeReport>,
+    pub deliveries: Vec<Delivery>,
+    /// From the first post until every message arrived or the timeout
+    pub elapsed: Duration,
+}
+
+impl LossyReport {
+    /// Whether any node recorded a DCUtR attempt or success
+    pub fn dcutr_recorded(&self) -> bool {
+        self.nodes
+            .iter()
+            .any(|n| n.dcutr_attempts > 0 || n.dcutr_successes > 0)
+    }
+
+    /// One check per message and receiving node, one per node for its
+    /// AutoNAT status, and one for DCUtR having been tried at all
+    pub fn to_result(&self) -> NatTestResult {
+        let nodes = self.nodes.len() as u32;
+        let receipts: u32 = self
+            .deliveries
+            .iter()
+            .map(|_| nodes.saturating_sub(1))
+            .sum();
+        let missed: u32 = self.deliveries.iter().map(|d| d.missing.len() as u32).sum();
+        let without_status = self
+            .nodes
+            .iter()
+            .filter(|n| !n.has_autonat_status())
+            .count() as u32;
+        let dcutr_missing = u32::from(!self.dcutr_recorded());
+
+        let mut result = NatTestResult::now(
+            SCENARIO,
+            receipts - missed + nodes - without_status + 1 - dcutr_missing,
+            missed + without_status + dcutr_missing,
+        );
+        result.peer_count = self.nodes.iter().map(|n| n.peer_count).min().unwrap_or(0);
+        result.dcutr_attempts = self.nodes.iter().map(|n| n.dcutr_attempts).sum();
+        result.dcutr_successes = self.nodes.iter().map(|n| n.dcutr_successes).sum();
+        result
+    }
+
+    pub fn summary(&self) -> String {
+        let delivered = self.deliveries.iter().filter(|d| d.delivered()).count();
+        let mut out = format!(
+            "{}% loss: {}/{} messages reached every node in {}s\n",
+            self.loss_percent,
+            delivered,
+            self.deliveries.len(),
+            self.elapsed.as_secs()
+        );
+        if !self.dcutr_recorded() {
+            out.push_str("  no node recorded a DCUtR attempt\n");
+        }
+        for delivery in self.deliveries.iter().filter(|d| !d.delivered()) {
+            let missing: Vec<&str> = delivery
+                .missing
+                .iter()
+                .map(|node| self.nodes[*node].name.as_str())
+                .collect();
+            let _ = writeln!(
+                out,
+                "  {:?} never reached {}",
+                delivery.payload,
+                missing.join(", ")
+            );
+        }
+        for node in &self.nodes {
+            let _ = writeln!(
+                out,
+                "  {:<6} peers {:>2}  autonat {:<7}  dcutr {}/{}",
+                node.name,
+                node.peer_count,
+                if node.autonat_enabled {
+                    node.reachability.as_str()
+                } else {
+                    "off"
+                },
+                node.dcutr_successes,
+                node.dcutr_attempts
+            );
+        }
+        out
+    }
+}
+
+/// Run `docker` and return its trimmed standard output
+fn docker(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("docker")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run docker: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "docker {} failed: {}",
+            args.first().copied().unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// A running container and the token of its control API
+struct Node {
+    name: String,
+    container: String,
+    token: String,
+}
+
+impl Node {
+    /// Call the control API from inside the container, where the packet
+    /// loss does not apply
+    fn api(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value, String> {
+        let auth = format!("Authorization: Bearer {}", self.token);
+        let url = format!("http://127.0.0.1:{}/api/v1{}", API_PORT, path);
+        let body = body.map(Value::to_string);
+        let mut args = vec![
+            "exec",
+            self.container.as_str(),
+            "curl",
+            "-sS",
+            "--fail-with-body",
+            "-X",
+            method,
+        ];
+        args.extend(["-H", auth.as_str()]);
+        if let Some(body) = &body {
+            args.extend([
+                "-H",
+                "Content-Type: application/json",
+                "--data",
+                body.as_str(),
+            ]);
+        }
+        args.push(url.as_str());
+        let output =
+            docker(&args).map_err(|e| format!("{} {} on {}: {}", method, path, self.name, e))?;
+        serde_json::from_str(&output)
+            .map_err(|e| format!("{} {} on {}: {}", method, path, self.name, e))
+    }
+
+    /// Payloads of the messages received on the channel
+    fn received(&self) -> Result<BTreeSet<String>, String> {
+        let messages = self.api(
+            "GET",
+            &format!("/channels/{}/messages?limit=1000", CHANNEL),
+            None,
+        )?;
+        Ok(messages
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|message| {
+                serde_json::from_value::<Vec<u8>>(message["payload"].clone()).ok()
+            })
+            .map(|payload| String::from_utf8_lossy(&payload).into_owned())
+            .collect())
+    }
+
+    fn report(&self) -> Result<NodeReport, String> {
+        let nat = self.api("GET", "/nat", None)?;
+        let health = self.api("GET", "/health", None)?;
+        Ok(NodeReport {
+            name: self.name.clone(),
+            peer_count: health["peerCount"].as_u64().unwrap_or(0) as u32,
+            reachability: nat["reachability"]
+                .as_str()
+                .unwrap_or("unknown")
+                .to_string(),
+            autonat_enabled: nat["autonatEnabled"].as_bool().unwrap_or(false),
+            dcutr_attempts: health["dcutrHolePunchAttempts"].as_u64().unwrap_or(0),
+            dcutr_successes: health["dcutrHolePunchSuccesses"].as_u64().unwrap_or(0),
+        })
+    }
+}
+
+/// The network and containers of one run, removed when dropped
+struct Environment {
+    network: String,
+    containers: Vec<String>,
+}
+
+impl Environment {
+    fn create() -> Result<Self, String> {
+        let network = format!("chiral-lossy-{}", std::process::id());
+        docker(&[
+            "network",
+            "create",
+            "--driver",
+            "bridge",
+            "--opt",
+            "com.docker.network.bridge.enable_ip_masquerade=false",
+            &network,
+        ])?;
+        Ok(Self {
+            network,
+            containers: Vec::new(),
+        })
+    }
+
+    fn start_node(
+        &mut self,
+        name: &str,
+        image: &str,
+        loss_percent: u8,
+        bootstrap: Option<&str>,
+    ) -> Result<Node, String> {
+        let container = format!("{}-{}", self.network, name);
+        let port = DHT_PORT.to_string();
+        let api_addr = format!("127.0.0.1:{}", API_PORT);
+        let mut args = vec![
+            "run",
+            "-d",
+            "--name",
+            container.as_str(),
+            "--network",
+            self.network.as_str(),
+            // tc needs it to change the queueing discipline
+            "--cap-add",
+            "NET_ADMIN",
+            image,
+            "--port",
+            port.as_str(),
+            "--data-dir",
+            "/data",
+            "--no-default-bootstrap",
+            "--api-addr",
+            api_addr.as_str(),
+        ];
+        if let Some(bootstrap) = bootstrap {
+            args.extend(["--bootstrap", bootstrap]);
+        }
+        docker(&args)?;
+        self.containers.push(container.clone());
+
+        let loss = format!("{}%", loss_percent);
+        docker(&[
+            "exec", &container, "tc", "qdisc", "add", "dev", "eth0", "root", "netem", "loss", &loss,
+        ])
+        .map_err(|e| {
+            format!(
+                "Failed to add packet loss to {} (is sch_netem loaded?): {}",
+                name, e
+            )
+        })?;
+
+        let started = Instant::now();
+        loop {
+            let token = docker(&["exec", &container, "cat", "/data/api.token"]).unwrap_or_default();
+            if !token.is_empty() {
+                let node = Node {
+                    name: name.to_string(),
+                    container: container.clone(),
+                    token,
+                };
+                if node.api("GET", "/node", None).is_ok() {
+                    return Ok(node);
+                }
+            }
+            if started.elapsed() >= START_TIMEOUT {
+                return Err(format!(
+                    "{} did not serve its control API within {}s",
+                    name,
+                    START_TIMEOUT.as_secs()
+                ));
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn address(&self, node: &Node) -> Result<String, String> {
+        docker(&[
+            "inspect",
+            "-f",
+            "{{range .NetworkSettings.Networks}}{{.IPAddress}}{{end}}",
+            &node.container,
+        ])
+    }
+}
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        if !self.containers.is_empty() {
+            let mut args = vec!["rm", "-f"];
+            args.extend(self.containers.iter().map(String::as_str));
+            if let Err(e) = docker(&args) {
+                tracing::warn!("Failed to remove the lossy scenario containers: {}", e);
+            }
+        }
+        if let Err(e) = docker(&["network", "rm", &self.network]) {
+            tracing::warn!("Failed to remove network {}: {}", self.network, e);
+        }
+    }
+}
+
+impl LossyScenario {
+    pub fn run(&self) -> Result<LossyReport, String> {
+        docker(&["version", "--format", "{{.Server.Version}}"])?;
+        let mut env = Environment::create()?;
+
+        let bootstrap = env.start_node("boot", &self.image, self.loss_percent, None)?;
+        let peer_id = bootstrap.api("GET", "/node", None)?["peerId"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or("the bootstrap node reported no peer id")?;
+        let bootstrap_addr = format!(
+            "/ip4/{}/tcp/{}/p2p/{}",
+            env.address(&bootstrap)?,
+            DHT_PORT,
+            peer_id
+        );
+        let mut nodes = vec![bootstrap];
+        for i in 1..=self.nodes {
+            let name = format!("node{}", i);
+            nodes.push(env.start_node(
+                &name,
+                &self.image,
+                self.loss_percent,
+                Some(&bootstrap_addr),
+            )?);
+        }
+
+        // Joining happens under loss too, so it gets its own timeout
+        let started = Instant::now();
+        for node in &nodes {
+            while !node
+                .api("GET", "/peers", None)?
+                .as_array()
+                .is_some_and(|peers| !peers.is_empty())
+            {
+                if started.elapsed() >= self.timeout {
+                    return Err(format!(
+                        "{} connected to no peer within {}s",
+                        node.name,
+                        self.timeout.as_secs()
+                    ));
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            node.api("POST", &format!("/channels/{}", CHANNEL), Some(&json!({})))?;
+        }
+
+        let count = nodes.len();
+        let mut deliveries: Vec<Delivery> = (0..count)
+            .flat_map(|sender| {
+                let name = &nodes[sender].name;
+                (1..=self.messages)
+                    .map(move |n| Delivery::new(format!("{} #{}", name, n), sender, count))
+            })
+            .collect();
+        let started = Instant::now();
+        for delivery in &deliveries {
+            let body = json!({ "payload": delivery.payload.as_bytes() });
+            nodes[delivery.sender].api(
+                "POST",
+                &format!("/channels/{}/messages", CHANNEL),
+                Some(&body),
+            )?;
+        }
+        while started.elapsed() < self.timeout && !deliveries.iter().all(Delivery::delivered) {
+            std::thread::sleep(POLL_INTERVAL);
+            for (i, node) in nodes.iter().enumerate() {
+                let received = node.received()?;
+                for delivery in &mut deliveries {
+                    if received.contains(&delivery.payload) {
+                        delivery.missing.remove(&i);
+                    }
+                }
+            }
+        }
+        let elapsed = started.elapsed();
+
+        Ok(LossyReport {
+            loss_percent: self.loss_percent,
+            nodes: nodes.iter().map(Node::report).collect::<Result<_, _>>()?,
+            deliveries,
+            elapsed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, reachability: &str) -> NodeReport {
+        NodeReport {
+            name: name.to_string(),
+            peer_count: 2,
+            reachability: reachability.to_string(),
+            autonat_enabled: true,
+            dcutr_attempts: 1,
+            dcutr_successes: 1,
+        }
+    }
+
+    #[test]
+    fn test_missed_messages_and_autonat_status_fail_the_run() {
+        let mut delivered = Delivery::new("boot #1".to_string(), 0, 3);
+        assert_eq!(delivered.missing, BTreeSet::from([1, 2]));
+        delivered.missing.clear();
+        assert!(delivered.delivered());
+
+        let mut missed = Delivery::new("node1 #1".to_string(), 1, 3);
+        missed.missing.remove(&0);
+
+        let report = LossyReport {
+            loss_percent: 20,
+            nodes: vec![
+                node("boot", "public"),
+                node("node1", "public"),
+                node("node2", "unknown"),
+            ],
+            deliveries: vec![delivered, missed],
+            elapsed: Duration::from_secs(120),
+        };
+        let result = report.to_result();
+        assert_eq!(result.scenario, SCENARIO);
+        // 3 of 4 receipts, 2 of 3 AutoNAT statuses and the DCUtR check
+        assert_eq!((result.passed, result.failed), (6, 2));
+        assert_eq!((result.dcutr_attempts, result.dcutr_successes), (3, 3));
+        assert!(report
+            .summary()
+            .contains("\"node1 #1\" never reached node2"));
+    }
+
+    #[test]
+    fn test_run_without_dcutr_fails() {
+        let mut delivered = Delivery::new("boot #1".to_string(), 0, 2);
+        delivered.missing.clear();
+        let mut nodes = vec![node("boot", "public"), node("node1", "private")];
+        for node in &mut nodes {
+            node.dcutr_attempts = 0;
+            node.dcutr_successes = 0;
+        }
+        let report = LossyReport {
+            loss_percent: 20,
+            nodes,
+            deliveries: vec![delivered],
+            elapsed: Duration::from_secs(10),
+        };
+        assert!(!report.dcutr_recorded());
+        let result = report.to_result();
+        assert_eq!((result.passed, result.failed), (3, 1));
+        assert!(report.summary().contains("no node recorded a DCUtR attempt"));
+    }
+}
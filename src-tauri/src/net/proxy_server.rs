@@ -2,7 +2,7 @@ use libp2p::futures::StreamExt;
 use libp2p::relay::client::Behaviour as RelayClientBehaviour;
 use libp2p::swarm::{Swarm, SwarmEvent};
 use libp2p::SwarmBuilder;
-use libp2p::{identity, noise, tcp, yamux, Multiaddr, PeerId};
+use libp2p::{identity, tcp, yamux, Multiaddr, PeerId};
 use std::error::Error;
 use tracing::info;
 
@@ -14,15 +14,21 @@ pub async fn run_proxy_server(
     let local_peer_id = PeerId::from(local_key.public());
     info!("Local peer id: {:?}", local_peer_id);
 
+    let noise_prologue = crate::config::ChiralConfig::from_env().swarm.noise_prologue;
+    let tcp_prologue = noise_prologue.clone();
+
     // SwarmBuilder: TCP + RelayClient + with_behaviour + build()
     let mut swarm: Swarm<RelayClientBehaviour> = SwarmBuilder::with_existing_identity(local_key)
         .with_tokio()
         .with_tcp(
             tcp::Config::default(),
-            noise::Config::new,
+            move |key: &identity::Keypair| crate::dht::noise_config(key, &tcp_prologue),
+            yamux::Config::default,
+        )?
+        .with_relay_client(
+            move |key: &identity::Keypair| crate::dht::noise_config(key, &noise_prologue),
             yamux::Config::default,
         )?
-        .with_relay_client(noise::Config::new, yamux::Config::default)?
         .with_behaviour(|_keypair, relay_client| Ok(relay_client))?
         .build();
 
@@ -0,0 +1,265 @@
+//! Node operations shared by the Tauri commands and the headless control API.
+//!
+//! Each front end only finds the services it holds and turns the result into
+//! its own response, so the desktop app and `control_api` cannot drift apart.
+//! Errors are plain strings, as the Tauri commands return them.
+
+use crate::dht::models::{DhtMetricsSnapshot, FileMetadata, NatConfidence, NatReachabilityState};
+use crate::dht::DhtService;
+use crate::discovery::BootstrapContributionStats;
+use crate::download_restart::{DownloadRestartService, DownloadStatus, StartDownloadRequest};
+use crate::file_transfer::FileTransferService;
+use crate::messaging::{IncomingMessage, MessageId};
+use crate::monitoring::{DashboardStats, DetailedNetworkStats, NetworkStats, TransportStats};
+use crate::port_forwarding::PortForwardingStatus;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInfo {
+    pub peer_id: String,
+    pub multiaddrs: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapStatus {
+    /// Bootstrap nodes with an open connection
+    pub connected: usize,
+    /// Bootstrap nodes the DHT was started with
+    pub configured: usize,
+    pub contributions: Vec<BootstrapContributionStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NatStatus {
+    pub reachability: NatReachabilityState,
+    pub reachability_confidence: NatConfidence,
+    pub last_reachability_error: Option<String>,
+    pub observed_addrs: Vec<String>,
+    pub autonat_enabled: bool,
+    pub autorelay_enabled: bool,
+    pub active_relay_peer_id: Option<String>,
+    pub dcutr_enabled: bool,
+    pub port_forwarding: PortForwardingStatus,
+}
+
+/// A local file to announce on the DHT
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishFileRequest {
+    pub file_path: String,
+    /// Defaults to the last component of `file_path`
+    #[serde(default)]
+    pub file_name: Option<String>,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    /// The file is already encrypted; these only describe how
+    #[serde(default)]
+    pub is_encrypted: bool,
+    #[serde(default)]
+    pub encryption_method: Option<String>,
+    #[serde(default)]
+    pub key_fingerprint: Option<String>,
+    #[serde(default)]
+    pub price: Option<f64>,
+}
+
+/// A file read from disk with the metadata that announces it
+pub struct PreparedFile {
+    pub file_hash: String,
+    pub file_name: String,
+    pub data: Vec<u8>,
+    pub metadata: FileMetadata,
+}
+
+pub async fn node_info(dht: &DhtService) -> NodeInfo {
+    NodeInfo {
+        peer_id: dht.get_peer_id().await,
+        multiaddrs: dht.get_multiaddresses().await,
+    }
+}
+
+pub async fn connected_peers(dht: &DhtService) -> Vec<String> {
+    dht.get_connected_peers().await
+}
+
+pub async fn connect_peer(dht: &DhtService, address: String) -> Result<(), String> {
+    dht.connect_peer(address).await
+}
+
+pub async fn bootstrap_status(dht: &DhtService) -> BootstrapStatus {
+    let (connected, configured) = dht.bootstrap_connections().await;
+    BootstrapStatus {
+        connected,
+        configured,
+        contributions: dht.get_bootstrap_contribution_stats().await,
+    }
+}
+
+pub async fn health(dht: &DhtService) -> DhtMetricsSnapshot {
+    dht.metrics_snapshot().await
+}
+
+pub async fn network_stats(dht: &DhtService) -> NetworkStats {
+    dht.network_stats().await
+}
+
+pub async fn dashboard_stats(dht: &DhtService) -> DashboardStats {
+    dht.dashboard_stats().await
+}
+
+pub fn transport_stats(dht: &DhtService) -> TransportStats {
+    dht.transport_stats()
+}
+
+pub async fn detailed_network_stats(dht: &DhtService) -> Result<DetailedNetworkStats, String> {
+    dht.detailed_network_stats().await
+}
+
+pub async fn nat_status(dht: &DhtService) -> NatStatus {
+    let snapshot = dht.metrics_snapshot().await;
+    NatStatus {
+        reachability: snapshot.reachability,
+        reachability_confidence: snapshot.reachability_confidence,
+        last_reachability_error: snapshot.last_reachability_error,
+        observed_addrs: snapshot.observed_addrs,
+        autonat_enabled: snapshot.autonat_enabled,
+        autorelay_enabled: snapshot.autorelay_enabled,
+        active_relay_peer_id: snapshot.active_relay_peer_id,
+        dcutr_enabled: snapshot.dcutr_enabled,
+        port_forwarding: dht.port_forwarding_status(),
+    }
+}
+
+/// Read the file and build its metadata, without announcing it
+pub async fn prepare_file(
+    dht: &DhtService,
+    request: PublishFileRequest,
+    uploader_address: Option<String>,
+) -> Result<PreparedFile, String> {
+    let file_name = match request.file_name {
+        Some(name) => name,
+        None => Path::new(&request.file_path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| format!("No file name in {}", request.file_path))?,
+    };
+    let data = tokio::fs::read(&request.file_path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", request.file_path, e))?;
+    let file_hash = FileTransferService::calculate_file_hash(&data);
+    let created_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let metadata = dht
+        .prepare_file_metadata(
+            file_hash.clone(),
+            file_name.clone(),
+            data.len() as u64,
+            data.clone(),
+            created_at,
+            request.mime_type,
+            None, // encrypted_key_bundle
+            request.is_encrypted,
+            request.encryption_method,
+            request.key_fingerprint,
+            request.price.unwrap_or(0.0),
+            uploader_address,
+        )
+        .await?;
+    Ok(PreparedFile {
+        file_hash,
+        file_name,
+        data,
+        metadata,
+    })
+}
+
+/// Store the file for seeding when a transfer service runs, then announce it
+pub async fn publish_file(
+    dht: &DhtService,
+    file_transfer: Option<&FileTransferService>,
+    request: PublishFileRequest,
+    uploader_address: Option<String>,
+) -> Result<FileMetadata, String> {
+    let prepared = prepare_file(dht, request, uploader_address).await?;
+    if let Some(ft) = file_transfer {
+        ft.store_file_data(prepared.file_hash, prepared.file_name, prepared.data)
+            .await;
+    }
+    dht.publish_file(prepared.metadata.clone(), None).await?;
+    Ok(prepared.metadata)
+}
+
+pub async fn stop_publishing(dht: &DhtService, file_hash: String) -> Result<(), String> {
+    dht.stop_publishing_file(file_hash).await
+}
+
+/// The message this node would post on `channel`
+pub async fn channel_message(
+    dht: &DhtService,
+    channel: String,
+    payload: Vec<u8>,
+    reply_to: Option<MessageId>,
+) -> IncomingMessage {
+    IncomingMessage {
+        topic: channel,
+        from_peer: dht.get_peer_id().await,
+        payload,
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        reply_to,
+    }
+}
+
+/// Post `message` on its channel and return its id
+pub async fn publish_message(dht: &DhtService, message: IncomingMessage) -> Result<MessageId, String> {
+    let id = message.id();
+    dht.publish_message(message).await?;
+    Ok(id)
+}
+
+pub async fn start_download(
+    downloads: &DownloadRestartService,
+    request: StartDownloadRequest,
+) -> Result<String, String> {
+    downloads
+        .start_download(request)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+pub async fn pause_download(downloads: &DownloadRestartService, download_id: &str) -> Result<(), String> {
+    downloads
+        .pause_download(download_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+pub async fn resume_download(downloads: &DownloadRestartService, download_id: &str) -> Result<(), String> {
+    downloads
+        .resume_download(download_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+pub async fn list_downloads(downloads: &DownloadRestartService) -> Vec<DownloadStatus> {
+    downloads.list_statuses().await
+}
+
+pub async fn download_status(
+    downloads: &DownloadRestartService,
+    download_id: &str,
+) -> Result<DownloadStatus, String> {
+    downloads
+        .get_status(download_id)
+        .await
+        .map_err(|e| e.to_string())
+}
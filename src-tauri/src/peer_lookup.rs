@@ -0,0 +1,89 @@
+//! Lookups of a peer's addresses on bootstrap nodes.
+//!
+//! Kademlia merges the responses to a query without saying which peer
+//! named which result, so a single bootstrap node could fill the routing
+//! table with made-up peers. When `MultiBootstrapConsensus` is enabled, a
+//! node asks every connected bootstrap node what it knows about a newly
+//! found peer with a `PeerLookupRequest`, and the peer's addresses are only
+//! used once enough of them agree.
+//!
+//! The bootstrap node answers from its own routing table; an empty list
+//! means it does not know the peer.
+
+use serde::{Deserialize, Serialize};
+
+/// Largest lookup frame accepted from the wire
+const MAX_PEER_LOOKUP_FRAME: usize = 16 * 1024;
+
+/// Addresses returned for one peer
+pub const MAX_LOOKUP_ADDRESSES: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerLookupRequest {
+    pub peer_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerLookupResponse {
+    pub peer_id: String,
+    pub addrs: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerLookupProtocol;
+
+impl AsRef<str> for PeerLookupProtocol {
+    fn as_ref(&self) -> &str {
+        crate::protocol::PEER_LOOKUP_PROTOCOL
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PeerLookupCodec;
+
+#[async_trait::async_trait]
+impl libp2p::request_response::Codec for PeerLookupCodec {
+    type Protocol = PeerLookupProtocol;
+    type Request = PeerLookupRequest;
+    type Response = PeerLookupResponse;
+
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> std::io::Result<Self::Request>
+    where
+        T: futures::AsyncRead + Unpin + Send,
+    {
+        crate::protocol::read_json_frame(io, MAX_PEER_LOOKUP_FRAME).await
+    }
+
+    async fn read_response<T>(&mut self, _: &Self::Protocol, io: &mut T) -> std::io::Result<Self::Response>
+    where
+        T: futures::AsyncRead + Unpin + Send,
+    {
+        crate::protocol::read_json_frame(io, MAX_PEER_LOOKUP_FRAME).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        request: Self::Request,
+    ) -> std::io::Result<()>
+    where
+        T: futures::AsyncWrite + Unpin + Send,
+    {
+        crate::protocol::write_json_frame(io, &request).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        response: Self::Response,
+    ) -> std::io::Result<()>
+    where
+        T: futures::AsyncWrite + Unpin + Send,
+    {
+        crate::protocol::write_json_frame(io, &response).await
+    }
+}
@@ -0,0 +1,320 @@
+//! Whether automatic port forwarding works on this node's router.
+//!
+//! UPnP mappings are made by libp2p's UPnP behaviour, whose events update
+//! the status as they happen. Every five minutes the monitor also looks up
+//! the UPnP gateway for its address and external IP and, while UPnP has not
+//! mapped the listen port, asks the gateway for a NAT-PMP mapping instead
+//! (RFC 6886). Readers get the cached status and never wait on the router.
+//!
+//! NAT-PMP is tried at the system's default gateway (read from
+//! `/proc/net/route` on Linux), or at the UPnP gateway elsewhere.
+
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use serde::Serialize;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+/// How often the gateway is asked again
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Port NAT-PMP gateways listen on
+pub const NAT_PMP_PORT: u16 = 5351;
+
+/// Lifetime requested for a NAT-PMP mapping; renewed on every refresh
+const NAT_PMP_LIFETIME_SECS: u32 = 60 * 60;
+
+const NAT_PMP_TIMEOUT: Duration = Duration::from_secs(1);
+const GATEWAY_SEARCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortForwardingStatus {
+    pub upnp_enabled: bool,
+    /// The UPnP behaviour holds a mapping
+    pub upnp_success: bool,
+    pub upnp_external_port: Option<u16>,
+    pub nat_pmp_enabled: bool,
+    /// The gateway granted a NAT-PMP mapping on the last refresh
+    pub nat_pmp_success: bool,
+    pub nat_pmp_external_port: Option<u16>,
+    pub external_ip: Option<String>,
+    pub gateway_addr: Option<String>,
+    /// Unix seconds of the last refresh, `None` before the first
+    pub last_checked: Option<u64>,
+}
+
+/// Mapping granted by a NAT-PMP gateway
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatPmpMapping {
+    pub internal_port: u16,
+    pub external_port: u16,
+    pub lifetime_secs: u32,
+}
+
+/// Opcode 2: map a TCP port
+pub fn nat_pmp_tcp_mapping_request(internal_port: u16, external_port: u16, lifetime_secs: u32) -> [u8; 12] {
+    let mut request = [0u8; 12];
+    request[1] = 2;
+    request[4..6].copy_from_slice(&internal_port.to_be_bytes());
+    request[6..8].copy_from_slice(&external_port.to_be_bytes());
+    request[8..12].copy_from_slice(&lifetime_secs.to_be_bytes());
+    request
+}
+
+fn check_header(response: &[u8], opcode: u8, len: usize) -> Result<(), String> {
+    if response.len() < len {
+        return Err(format!("NAT-PMP response of {} bytes is too short", response.len()));
+    }
+    if response[0] != 0 || response[1] != 128 + opcode {
+        return Err(format!("unexpected NAT-PMP response {}/{}", response[0], response[1]));
+    }
+    match u16::from_be_bytes([response[2], response[3]]) {
+        0 => Ok(()),
+        2 => Err("NAT-PMP refused: not authorized".to_string()),
+        3 => Err("NAT-PMP refused: gateway has no external address".to_string()),
+        4 => Err("NAT-PMP refused: out of resources".to_string()),
+        code => Err(format!("NAT-PMP refused with result code {}", code)),
+    }
+}
+
+/// Response to opcode 0, the gateway's external address
+pub fn parse_nat_pmp_external_address(response: &[u8]) -> Result<Ipv4Addr, String> {
+    check_header(response, 0, 12)?;
+    Ok(Ipv4Addr::new(response[8], response[9], response[10], response[11]))
+}
+
+/// Response to opcode 2
+pub fn parse_nat_pmp_tcp_mapping(response: &[u8]) -> Result<NatPmpMapping, String> {
+    check_header(response, 2, 16)?;
+    Ok(NatPmpMapping {
+        internal_port: u16::from_be_bytes([response[8], response[9]]),
+        external_port: u16::from_be_bytes([response[10], response[11]]),
+        lifetime_secs: u32::from_be_bytes([response[12], response[13], response[14], response[15]]),
+    })
+}
+
+async fn nat_pmp_exchange(gateway: Ipv4Addr, request: &[u8]) -> Result<Vec<u8>, String> {
+    let socket = tokio::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .await
+        .map_err(|e| e.to_string())?;
+    socket
+        .connect((gateway, NAT_PMP_PORT))
+        .await
+        .map_err(|e| e.to_string())?;
+    // UDP may drop one datagram, so ask twice before giving up
+    let mut buf = [0u8; 16];
+    for _ in 0..2 {
+        socket.send(request).await.map_err(|e| e.to_string())?;
+        if let Ok(received) = tokio::time::timeout(NAT_PMP_TIMEOUT, socket.recv(&mut buf)).await {
+            let len = received.map_err(|e| e.to_string())?;
+            return Ok(buf[..len].to_vec());
+        }
+    }
+    Err(format!("no NAT-PMP answer from {}", gateway))
+}
+
+/// The system's default IPv4 gateway
+#[cfg(target_os = "linux")]
+pub fn default_gateway() -> Option<Ipv4Addr> {
+    let routes = std::fs::read_to_string("/proc/net/route").ok()?;
+    routes.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 || fields[1] != "00000000" {
+            return None;
+        }
+        u32::from_str_radix(fields[2], 16)
+            .ok()
+            .map(|gateway| Ipv4Addr::from(gateway.to_le_bytes()))
+            .filter(|gateway| !gateway.is_unspecified())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn default_gateway() -> Option<Ipv4Addr> {
+    None
+}
+
+/// Cached port forwarding status; clones share it
+#[derive(Clone)]
+pub struct PortForwardingMonitor {
+    status: Arc<Mutex<PortForwardingStatus>>,
+    listen_port: u16,
+}
+
+impl PortForwardingMonitor {
+    pub fn new(enabled: bool, listen_port: u16) -> Self {
+        Self {
+            status: Arc::new(Mutex::new(PortForwardingStatus {
+                upnp_enabled: enabled,
+                nat_pmp_enabled: enabled,
+                ..Default::default()
+            })),
+            listen_port,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, PortForwardingStatus> {
+        self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn status(&self) -> PortForwardingStatus {
+        self.lock().clone()
+    }
+
+    /// The UPnP behaviour mapped `addr`
+    pub fn on_upnp_mapped(&self, addr: &Multiaddr) {
+        let mut status = self.lock();
+        status.upnp_success = true;
+        for protocol in addr.iter() {
+            match protocol {
+                Protocol::Ip4(ip) => status.external_ip = Some(ip.to_string()),
+                Protocol::Ip6(ip) => status.external_ip = Some(ip.to_string()),
+                Protocol::Tcp(port) | Protocol::Udp(port) => status.upnp_external_port = Some(port),
+                _ => {}
+            }
+        }
+    }
+
+    /// The UPnP mapping expired, or no usable gateway was found
+    pub fn on_upnp_lost(&self) {
+        let mut status = self.lock();
+        status.upnp_success = false;
+        status.upnp_external_port = None;
+    }
+
+    /// Ask the gateway again. Returns the external address of a new or
+    /// changed NAT-PMP mapping, for the swarm to advertise.
+    pub async fn refresh(&self) -> Option<Multiaddr> {
+        let (enabled, upnp_success, previous) = {
+            let status = self.lock();
+            (
+                status.upnp_enabled,
+                status.upnp_success,
+                status.nat_pmp_external_port.zip(status.external_ip.clone()),
+            )
+        };
+        if !enabled {
+            return None;
+        }
+
+        let options = igd_next::SearchOptions {
+            timeout: Some(GATEWAY_SEARCH_TIMEOUT),
+            ..Default::default()
+        };
+        let (gateway_addr, upnp_ip) = match igd_next::aio::tokio::search_gateway(options).await {
+            Ok(gateway) => (Some(gateway.addr), gateway.get_external_ip().await.ok()),
+            Err(e) => {
+                debug!("No UPnP gateway found: {}", e);
+                (None, None)
+            }
+        };
+
+        let nat_pmp_gateway = default_gateway().or(match gateway_addr {
+            Some(SocketAddr::V4(addr)) => Some(*addr.ip()),
+            _ => None,
+        });
+        let nat_pmp = match nat_pmp_gateway {
+            Some(gateway) if !upnp_success => self.map_nat_pmp(gateway).await,
+            _ => None,
+        };
+
+        let mut status = self.lock();
+        status.last_checked = SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
+        status.gateway_addr = gateway_addr
+            .map(|addr| addr.ip().to_string())
+            .or_else(|| nat_pmp_gateway.map(|ip| ip.to_string()));
+        if let Some(ip) = upnp_ip {
+            status.external_ip = Some(ip.to_string());
+        }
+        status.nat_pmp_success = nat_pmp.is_some();
+        status.nat_pmp_external_port = nat_pmp.map(|(_, mapping)| mapping.external_port);
+        let (external_ip, mapping) = nat_pmp?;
+        status.external_ip = Some(external_ip.to_string());
+        if previous == Some((mapping.external_port, external_ip.to_string())) {
+            return None;
+        }
+        info!(
+            "🌐 NAT-PMP: mapped TCP port {} to {}:{}",
+            mapping.internal_port, external_ip, mapping.external_port
+        );
+        Some(
+            Multiaddr::empty()
+                .with(Protocol::Ip4(external_ip))
+                .with(Protocol::Tcp(mapping.external_port)),
+        )
+    }
+
+    async fn map_nat_pmp(&self, gateway: Ipv4Addr) -> Option<(Ipv4Addr, NatPmpMapping)> {
+        let result = async {
+            let external = parse_nat_pmp_external_address(&nat_pmp_exchange(gateway, &[0, 0]).await?)?;
+            let request = nat_pmp_tcp_mapping_request(self.listen_port, self.listen_port, NAT_PMP_LIFETIME_SECS);
+            let mapping = parse_nat_pmp_tcp_mapping(&nat_pmp_exchange(gateway, &request).await?)?;
+            Ok::<_, String>((external, mapping))
+        }
+        .await;
+        match result {
+            Ok((external, mapping)) if !external.is_unspecified() && mapping.external_port != 0 => {
+                Some((external, mapping))
+            }
+            Ok(_) => None,
+            Err(e) => {
+                debug!("NAT-PMP at {} failed: {}", gateway, e);
+                None
+            }
+        }
+    }
+
+    /// Refresh now and every `REFRESH_INTERVAL` until `shutdown`, handing
+    /// new NAT-PMP addresses to `on_mapping`
+    pub async fn run<F, Fut>(self, on_mapping: F, shutdown: CancellationToken)
+    where
+        F: Fn(Multiaddr) -> Fut,
+        Fut: std::future::Future<Output = ()>,
+    {
+        loop {
+            if let Some(addr) = self.refresh().await {
+                on_mapping(addr).await;
+            }
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = tokio::time::sleep(REFRESH_INTERVAL) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nat_pmp_messages_and_upnp_events() {
+        let request = nat_pmp_tcp_mapping_request(4001, 4001, 3600);
+        assert_eq!(request, [0, 2, 0, 0, 0x0f, 0xa1, 0x0f, 0xa1, 0, 0, 0x0e, 0x10]);
+
+        let external = [0, 128, 0, 0, 0, 0, 0, 1, 203, 0, 113, 7];
+        assert_eq!(parse_nat_pmp_external_address(&external), Ok(Ipv4Addr::new(203, 0, 113, 7)));
+        let mapping = [0, 130, 0, 0, 0, 0, 0, 1, 0x0f, 0xa1, 0x9c, 0x41, 0, 0, 0x0e, 0x10];
+        assert_eq!(
+            parse_nat_pmp_tcp_mapping(&mapping),
+            Ok(NatPmpMapping { internal_port: 4001, external_port: 40001, lifetime_secs: 3600 })
+        );
+        let refused = [0, 130, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert!(parse_nat_pmp_tcp_mapping(&refused).unwrap_err().contains("not authorized"));
+        assert!(parse_nat_pmp_tcp_mapping(&external).is_err());
+
+        let monitor = PortForwardingMonitor::new(true, 4001);
+        monitor.on_upnp_mapped(&"/ip4/203.0.113.7/tcp/4001".parse().unwrap());
+        let status = monitor.status();
+        assert!(status.upnp_success);
+        assert_eq!(status.upnp_external_port, Some(4001));
+        assert_eq!(status.external_ip.as_deref(), Some("203.0.113.7"));
+        monitor.on_upnp_lost();
+        assert_eq!(monitor.status().upnp_external_port, None);
+        assert!(!PortForwardingMonitor::new(false, 4001).status().nat_pmp_enabled);
+    }
+}
@@ -0,0 +1,235 @@
+//! Ephemeral presence signals published over gossipsub.
+//!
+//! Typing indicators are fire-and-forget: they go out on the presence topic,
+//! are never written to the message history, and do not need to be delivered
+//! reliably. The sender sends `StopTyping` on its own after a few seconds
+//! without a keypress. The receiver drops a `StartTyping` after its expiry if
+//! the matching `StopTyping` was lost.
+
+use libp2p::gossipsub::{IdentTopic, TopicHash};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Gossipsub topic carrying presence events
+pub const PRESENCE_TOPIC: &str = "chiral/presence/1.0.0";
+
+/// Without a keypress for this long, the sender stops typing
+pub const TYPING_IDLE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A received `StartTyping` is dropped after this long without a refresh
+pub const TYPING_EXPIRY: Duration = Duration::from_secs(10);
+
+/// While the user keeps typing, `StartTyping` is re-sent at this interval so
+/// receivers do not expire it
+const TYPING_REFRESH: Duration = Duration::from_secs(5);
+
+pub fn presence_topic() -> IdentTopic {
+    IdentTopic::new(PRESENCE_TOPIC)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TypingAction {
+    StartTyping,
+    StopTyping,
+}
+
+/// Wire format of a typing indicator on the presence topic
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypingEvent {
+    pub from_peer: String,
+    pub channel: String,
+    pub action: TypingAction,
+}
+
+impl TypingEvent {
+    fn new(from_peer: &PeerId, channel: &TopicHash, action: TypingAction) -> Self {
+        Self {
+            from_peer: from_peer.to_string(),
+            channel: channel.as_str().to_string(),
+            action,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        serde_json::from_slice(data).ok()
+    }
+}
+
+/// Payload of the `peer-typing` event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerTyping {
+    pub peer_id: String,
+    pub channel: String,
+    pub typing: bool,
+}
+
+struct LocalTyping {
+    peer_id: PeerId,
+    last_keypress: Instant,
+    last_sent: Instant,
+}
+
+/// Typing state for both directions: channels we are typing in, and which
+/// remote peers are currently typing where
+#[derive(Default)]
+pub struct TypingIndicator {
+    local: HashMap<TopicHash, LocalTyping>,
+    /// (peer, channel) -> when their `StartTyping` expires
+    remote: HashMap<(String, String), Instant>,
+}
+
+impl TypingIndicator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a keypress in `channel`; returns the event to publish, if any.
+    /// Repeated keypresses are debounced into one `StartTyping`.
+    pub fn start_typing(&mut self, peer_id: PeerId, channel: TopicHash) -> Option<TypingEvent> {
+        self.start_typing_at(peer_id, channel, Instant::now())
+    }
+
+    pub fn start_typing_at(
+        &mut self,
+        peer_id: PeerId,
+        channel: TopicHash,
+        now: Instant,
+    ) -> Option<TypingEvent> {
+        match self.local.get_mut(&channel) {
+            Some(typing) => {
+                typing.last_keypress = now;
+                if now.duration_since(typing.last_sent) < TYPING_REFRESH {
+                    return None;
+                }
+                typing.last_sent = now;
+            }
+            None => {
+                self.local.insert(
+                    channel.clone(),
+                    LocalTyping {
+                        peer_id,
+                        last_keypress: now,
+                        last_sent: now,
+                    },
+                );
+            }
+        }
+        Some(TypingEvent::new(&peer_id, &channel, TypingAction::StartTyping))
+    }
+
+    /// Stop typing right away, e.g. because the message was sent
+    pub fn stop_typing(&mut self, channel: &TopicHash) -> Option<TypingEvent> {
+        self.local
+            .remove(channel)
+            .map(|typing| TypingEvent::new(&typing.peer_id, channel, TypingAction::StopTyping))
+    }
+
+    /// `StopTyping` events for channels without a keypress in the idle timeout
+    pub fn poll_idle(&mut self, now: Instant) -> Vec<TypingEvent> {
+        let idle: Vec<TopicHash> = self
+            .local
+            .iter()
+            .filter(|(_, typing)| now.duration_since(typing.last_keypress) >= TYPING_IDLE_TIMEOUT)
+            .map(|(channel, _)| channel.clone())
+            .collect();
+        idle.iter()
+            .filter_map(|channel| self.stop_typing(channel))
+            .collect()
+    }
+
+    /// Apply an event from a remote peer; returns the change to show, if any
+    pub fn on_event(&mut self, event: &TypingEvent, now: Instant) -> Option<PeerTyping> {
+        let key = (event.from_peer.clone(), event.channel.clone());
+        let changed = match event.action {
+            TypingAction::StartTyping => self.remote.insert(key, now + TYPING_EXPIRY).is_none(),
+            TypingAction::StopTyping => self.remote.remove(&key).is_some(),
+        };
+        changed.then(|| PeerTyping {
+            peer_id: event.from_peer.clone(),
+            channel: event.channel.clone(),
+            typing: event.action == TypingAction::StartTyping,
+        })
+    }
+
+    /// Drop remote `StartTyping`s that were never stopped or refreshed
+    pub fn expire(&mut self, now: Instant) -> Vec<PeerTyping> {
+        let expired: Vec<(String, String)> = self
+            .remote
+            .iter()
+            .filter(|(_, expires_at)| **expires_at <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        expired
+            .into_iter()
+            .map(|key| {
+                self.remote.remove(&key);
+                let (peer_id, channel) = key;
+                PeerTyping {
+                    peer_id,
+                    channel,
+                    typing: false,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keypresses_are_debounced_and_stop_when_idle() {
+        let mut typing = TypingIndicator::new();
+        let peer = PeerId::random();
+        let channel = TopicHash::from_raw("chat/room");
+        let t0 = Instant::now();
+
+        let start = typing.start_typing_at(peer, channel.clone(), t0).unwrap();
+        assert_eq!(start.action, TypingAction::StartTyping);
+        assert_eq!(start.channel, "chat/room");
+        assert!(typing
+            .start_typing_at(peer, channel.clone(), t0 + Duration::from_secs(2))
+            .is_none());
+
+        // The second keypress pushed the idle deadline back
+        assert!(typing.poll_idle(t0 + Duration::from_secs(4)).is_empty());
+        let stops = typing.poll_idle(t0 + Duration::from_secs(5));
+        assert_eq!(stops.len(), 1);
+        assert_eq!(stops[0].action, TypingAction::StopTyping);
+        assert!(typing.stop_typing(&channel).is_none());
+    }
+
+    #[test]
+    fn test_remote_start_typing_expires_without_stop() {
+        let mut typing = TypingIndicator::new();
+        let t0 = Instant::now();
+        let start = TypingEvent {
+            from_peer: "peer-a".to_string(),
+            channel: "chat/room".to_string(),
+            action: TypingAction::StartTyping,
+        };
+
+        assert!(typing.on_event(&start, t0).unwrap().typing);
+        // A refresh is not a new change, but extends the expiry
+        assert!(typing.on_event(&start, t0 + Duration::from_secs(5)).is_none());
+        assert!(typing.expire(t0 + TYPING_EXPIRY).is_empty());
+
+        let expired = typing.expire(t0 + Duration::from_secs(5) + TYPING_EXPIRY);
+        assert_eq!(expired.len(), 1);
+        assert!(!expired[0].typing);
+
+        let stop = TypingEvent {
+            action: TypingAction::StopTyping,
+            ..start
+        };
+        assert!(typing.on_event(&stop, t0).is_none());
+    }
+}
@@ -0,0 +1,153 @@
+//! Wire protocol identifiers and versions spoken by this node.
+//!
+//! Everything that negotiates a libp2p stream should take its protocol name
+//! from here so the running node can report exactly what it supports.
+
+use std::collections::HashMap;
+
+/// Version of the Chiral protocol suite
+pub const CHIRAL_PROTOCOL_VERSION: &str = "1.0.0";
+
+/// Identify protocol version; peers advertising anything else are dropped
+/// from the routing table
+pub const IDENTIFY_PROTOCOL: &str = "/chiral/1.0.0";
+
+/// Kademlia DHT
+pub const KADEMLIA_PROTOCOL: &str = "/chiral/kad/1.0.0";
+
+/// Proxy request-response
+pub const PROXY_PROTOCOL: &str = "/chiral/proxy/1.0.0";
+
+/// WebRTC offer/answer signaling request-response
+pub const WEBRTC_SIGNALING_PROTOCOL: &str = "/chiral/webrtc-signaling/1.0.0";
+
+/// Encryption key exchange request-response
+pub const KEY_REQUEST_PROTOCOL: &str = "/chiral/key-request/1.0.0";
+
+/// Direct peer-to-peer file transfer request-response
+pub const FILE_TRANSFER_PROTOCOL: &str = "/chiral/file-transfer/1.0.0";
+
+/// Voice call invite/answer/hangup request-response
+pub const CALL_SIGNALING_PROTOCOL: &str = "/chiral/call/1.0.0";
+
+/// Read receipts sent to a message's author
+pub const READ_RECEIPT_PROTOCOL: &str = "/chiral/read-receipt/1.0.0";
+
+/// AutoNAT reachability reports sent to bootstrap nodes
+pub const REACHABILITY_PROTOCOL: &str = "/chiral/reachability/1.0.0";
+
+/// Asks a bootstrap node which addresses it knows for a peer
+pub const PEER_LOOKUP_PROTOCOL: &str = "/chiral/peer-lookup/1.0.0";
+
+/// Circuit Relay v2 version
+pub const RELAY_PROTOCOL_VERSION: &str = "0.2.0";
+
+/// Circuit Relay v2 hop protocol, advertised by peers that act as relays
+pub const RELAY_HOP_PROTOCOL: &str = "/libp2p/circuit/relay/0.2.0/hop";
+
+/// Stream protocols every node speaks
+const STREAM_PROTOCOLS: &[&str] = &[
+    KADEMLIA_PROTOCOL,
+    PROXY_PROTOCOL,
+    WEBRTC_SIGNALING_PROTOCOL,
+    KEY_REQUEST_PROTOCOL,
+    FILE_TRANSFER_PROTOCOL,
+    CALL_SIGNALING_PROTOCOL,
+    READ_RECEIPT_PROTOCOL,
+    REACHABILITY_PROTOCOL,
+    PEER_LOOKUP_PROTOCOL,
+    crate::control_plane::handshake::HANDSHAKE_PROTOCOL_ID,
+];
+
+/// Full protocol ids this node accepts streams on, for node announcements
+pub fn advertised_protocols(relay_server: bool) -> Vec<String> {
+    let mut protocols: Vec<String> = STREAM_PROTOCOLS.iter().map(|p| p.to_string()).collect();
+    if relay_server {
+        protocols.push(RELAY_HOP_PROTOCOL.to_string());
+    }
+    protocols
+}
+
+/// Split a `/name/.../<version>` protocol id into its name and version
+fn split_versioned(protocol: &str) -> (String, String) {
+    match protocol.rsplit_once('/') {
+        Some((name, version)) => (name.trim_start_matches('/').to_string(), version.to_string()),
+        None => (protocol.to_string(), String::new()),
+    }
+}
+
+/// Protocol name to version for everything this node speaks, e.g.
+/// `"chiral/kad" => "1.0.0"` and `"/libp2p/circuit/relay" => "0.2.0"`.
+pub fn protocol_versions() -> HashMap<String, String> {
+    let mut versions: HashMap<String, String> = STREAM_PROTOCOLS
+        .iter()
+        .map(|protocol| split_versioned(protocol))
+        .collect();
+
+    versions.insert("chiral".to_string(), CHIRAL_PROTOCOL_VERSION.to_string());
+    versions.insert(
+        "/libp2p/circuit/relay".to_string(),
+        RELAY_PROTOCOL_VERSION.to_string(),
+    );
+    versions.insert(
+        "chiral-network".to_string(),
+        env!("CARGO_PKG_VERSION").to_string(),
+    );
+    versions
+}
+
+/// Read one length-prefixed JSON message of at most `max_len` bytes, the
+/// framing of the small request-response protocols (reachability reports,
+/// peer lookups): a little-endian `u32` length, then the JSON
+pub async fn read_json_frame<T, M>(io: &mut T, max_len: usize) -> std::io::Result<M>
+where
+    T: futures::AsyncRead + Unpin + Send,
+    M: serde::de::DeserializeOwned,
+{
+    use futures::AsyncReadExt;
+    let mut len_buf = [0u8; 4];
+    io.read_exact(&mut len_buf).await?;
+    let len = u32::from_le_bytes(len_buf) as usize;
+    if len > max_len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("frame of {} bytes exceeds the limit of {}", len, max_len),
+        ));
+    }
+    let mut data = vec![0u8; len];
+    io.read_exact(&mut data).await?;
+    serde_json::from_slice(&data)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// Write `message` framed as `read_json_frame` expects
+pub async fn write_json_frame<T, M>(io: &mut T, message: &M) -> std::io::Result<()>
+where
+    T: futures::AsyncWrite + Unpin + Send,
+    M: serde::Serialize,
+{
+    use futures::AsyncWriteExt;
+    let data = serde_json::to_vec(message)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+    io.write_all(&(data.len() as u32).to_le_bytes()).await?;
+    io.write_all(&data).await?;
+    io.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protocol_versions() {
+        let versions = protocol_versions();
+        assert_eq!(versions.get("chiral").map(String::as_str), Some("1.0.0"));
+        assert_eq!(versions.get("chiral/kad").map(String::as_str), Some("1.0.0"));
+        assert_eq!(
+            versions.get("/libp2p/circuit/relay").map(String::as_str),
+            Some(RELAY_PROTOCOL_VERSION)
+        );
+        assert!(RELAY_HOP_PROTOCOL.contains(RELAY_PROTOCOL_VERSION));
+        assert!(IDENTIFY_PROTOCOL.ends_with(CHIRAL_PROTOCOL_VERSION));
+    }
+}
@@ -0,0 +1,718 @@
+//! Timing the libp2p protocols of a node in isolation.
+//!
+//! `LocalTestNetwork` starts nodes in this process that listen on loopback
+//! TCP, or on the in-memory transport for tests, and speak only noise, yamux,
+//! identify, GossipSub, Kademlia and a small echo request-response protocol,
+//! so each measurement covers one protocol and nothing else of the DHT
+//! service. Timings are taken around real sockets
+//! on the tokio runtime rather than in a criterion loop:
+//!
+//! - `noiseHandshake`: dial to `ConnectionEstablished`, which is the TCP
+//!   connect, the noise handshake and yamux negotiation on loopback;
+//! - `identifyExchange`: `ConnectionEstablished` to the identify info of the
+//!   remote arriving;
+//! - `gossipsubDelivery`: publish on one node to the message arriving on the
+//!   other, one message at a time;
+//! - `requestResponseRtt`: an echo request of `payload_bytes` to its response;
+//! - `kademliaLookup`: a closest-peers lookup across a chain of nodes in
+//!   which each node only knows its neighbours, so the lookup takes
+//!   `kad_hops` hops to reach the far end.
+//!
+//! The `protocol_bench` binary runs `ProtocolBenchmarkSuite` and prints the
+//! `BenchReport` as JSON.
+
+use futures::future::select_all;
+use futures::{FutureExt, StreamExt};
+use libp2p::core::transport::MemoryTransport;
+use libp2p::core::upgrade::Version;
+use libp2p::kad::store::MemoryStore;
+use libp2p::multiaddr::Protocol;
+use libp2p::request_response::{self as rr, ProtocolSupport};
+use libp2p::swarm::{NetworkBehaviour, SwarmEvent};
+use libp2p::{
+    gossipsub, identify, identity, kad, noise, tcp, yamux, Multiaddr, PeerId, StreamProtocol,
+    Swarm, SwarmBuilder, Transport,
+};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Identify protocol version of the benchmark nodes
+const BENCH_PROTOCOL_VERSION: &str = "/chiral-bench/1.0.0";
+
+const ECHO_PROTOCOL: StreamProtocol = StreamProtocol::new("/chiral/bench-echo/1.0.0");
+
+/// Largest echo frame accepted from the wire
+const MAX_ECHO_FRAME: usize = 1024 * 1024;
+
+/// Time allowed for any one step before the run fails
+const STEP_TIMEOUT: Duration = Duration::from_secs(30);
+
+const BENCH_TOPIC: &str = "chiral-bench";
+
+#[derive(Clone, Debug, Default)]
+struct EchoCodec;
+
+async fn read_frame<T>(io: &mut T) -> std::io::Result<Vec<u8>>
+where
+    T: futures::AsyncRead + Unpin + Send,
+{
+    use futures::AsyncReadExt;
+    let mut len_buf = [0u8; 4];
+    io.read_exact(&mut len_buf).await?;
+    let len = u32::from_le_bytes(len_buf) as usize;
+    if len > MAX_ECHO_FRAME {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("echo frame of {} bytes exceeds the limit", len),
+        ));
+    }
+    let mut data = vec![0u8; len];
+    io.read_exact(&mut data).await?;
+    Ok(data)
+}
+
+async fn write_frame<T>(io: &mut T, data: &[u8]) -> std::io::Result<()>
+where
+    T: futures::AsyncWrite + Unpin + Send,
+{
+    use futures::AsyncWriteExt;
+    io.write_all(&(data.len() as u32).to_le_bytes()).await?;
+    io.write_all(data).await?;
+    io.flush().await
+}
+
+#[async_trait::async_trait]
+impl rr::Codec for EchoCodec {
+    type Protocol = StreamProtocol;
+    type Request = Vec<u8>;
+    type Response = Vec<u8>;
+
+    async fn read_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+    ) -> std::io::Result<Self::Request>
+    where
+        T: futures::AsyncRead + Unpin + Send,
+    {
+        read_frame(io).await
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+    ) -> std::io::Result<Self::Response>
+    where
+        T: futures::AsyncRead + Unpin + Send,
+    {
+        read_frame(io).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        request: Self::Request,
+    ) -> std::io::Result<()>
+    where
+        T: futures::AsyncWrite + Unpin + Send,
+    {
+        write_frame(io, &request).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        response: Self::Response,
+    ) -> std::io::Result<()>
+    where
+        T: futures::AsyncWrite + Unpin + Send,
+    {
+        write_frame(io, &response).await
+    }
+}
+
+#[derive(NetworkBehaviour)]
+struct BenchBehaviour {
+    identify: identify::Behaviour,
+    gossipsub: gossipsub::Behaviour,
+    kademlia: kad::Behaviour<MemoryStore>,
+    echo: rr::Behaviour<EchoCodec>,
+}
+
+/// How the nodes of a `LocalTestNetwork` reach each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestTransport {
+    /// Loopback TCP, for timings that include real sockets
+    Tcp,
+    /// libp2p's in-memory transport: no sockets and no ports to run out of
+    Memory,
+}
+
+impl TestTransport {
+    fn listen_addr(self) -> Multiaddr {
+        match self {
+            TestTransport::Tcp => "/ip4/127.0.0.1/tcp/0",
+            TestTransport::Memory => "/memory/0",
+        }
+        .parse()
+        .expect("valid multiaddr")
+    }
+}
+
+fn build_node(transport: TestTransport) -> Result<Swarm<BenchBehaviour>, String> {
+    let key = identity::Keypair::generate_ed25519();
+    let peer_id = key.public().to_peer_id();
+    let gossipsub_config = gossipsub::ConfigBuilder::default()
+        .validation_mode(gossipsub::ValidationMode::Strict)
+        .build()
+        .map_err(|e| format!("gossipsub config: {e:?}"))?;
+    let gossipsub = gossipsub::Behaviour::new(
+        gossipsub::MessageAuthenticity::Signed(key.clone()),
+        gossipsub_config,
+    )
+    .map_err(|e| format!("gossipsub: {e}"))?;
+    let mut kademlia = kad::Behaviour::new(peer_id, MemoryStore::new(peer_id));
+    // Loopback addresses are never confirmed external; answer anyway
+    kademlia.set_mode(Some(kad::Mode::Server));
+    let behaviour = BenchBehaviour {
+        identify: identify::Behaviour::new(identify::Config::new(
+            BENCH_PROTOCOL_VERSION.to_string(),
+            key.public(),
+        )),
+        gossipsub,
+        kademlia,
+        echo: rr::Behaviour::new(
+            [(ECHO_PROTOCOL, ProtocolSupport::Full)],
+            rr::Config::default(),
+        ),
+    };
+
+    let swarm_config =
+        |c: libp2p::swarm::Config| c.with_idle_connection_timeout(Duration::from_secs(60));
+    let builder = SwarmBuilder::with_existing_identity(key).with_tokio();
+    let swarm = match transport {
+        TestTransport::Tcp => builder
+            .with_tcp(
+                tcp::Config::default().nodelay(true),
+                noise::Config::new,
+                yamux::Config::default,
+            )
+            .map_err(|e| format!("transport: {}", e))?
+            .with_behaviour(|_| behaviour)
+            .map_err(|e| format!("behaviour: {}", e))?
+            .with_swarm_config(swarm_config)
+            .build(),
+        TestTransport::Memory => builder
+            .with_other_transport(|key| -> Result<_, noise::Error> {
+                Ok(MemoryTransport::default()
+                    .upgrade(Version::V1)
+                    .authenticate(noise::Config::new(key)?)
+                    .multiplex(yamux::Config::default()))
+            })
+            .map_err(|e| format!("transport: {}", e))?
+            .with_behaviour(|_| behaviour)
+            .map_err(|e| format!("behaviour: {}", e))?
+            .with_swarm_config(swarm_config)
+            .build(),
+    };
+    Ok(swarm)
+}
+
+/// Nodes listening on loopback or in memory in this process
+pub struct LocalTestNetwork {
+    transport: TestTransport,
+    /// `None` once dropped, so the other nodes keep their indices
+    nodes: Vec<Option<Swarm<BenchBehaviour>>>,
+    peer_ids: Vec<PeerId>,
+    addrs: Vec<Multiaddr>,
+}
+
+impl LocalTestNetwork {
+    /// Start `size` nodes on loopback TCP and wait until each listens; they
+    /// are not connected
+    pub async fn start(size: usize) -> Result<Self, String> {
+        Self::start_with(size, TestTransport::Tcp).await
+    }
+
+    pub async fn start_with(size: usize, transport: TestTransport) -> Result<Self, String> {
+        let mut network = Self {
+            transport,
+            nodes: Vec::with_capacity(size),
+            peer_ids: Vec::with_capacity(size),
+            addrs: Vec::with_capacity(size),
+        };
+        for _ in 0..size {
+            network.add_node().await?;
+        }
+        Ok(network)
+    }
+
+    /// Start one more node, unconnected, and return its index
+    pub async fn add_node(&mut self) -> Result<usize, String> {
+        let mut node = build_node(self.transport)?;
+        node.listen_on(self.transport.listen_addr())
+            .map_err(|e| format!("listen: {}", e))?;
+        let index = self.nodes.len();
+        self.peer_ids.push(*node.local_peer_id());
+        self.nodes.push(Some(node));
+        let address = self
+            .wait_for("a listen address", |from, event| match event {
+                SwarmEvent::NewListenAddr { address, .. } if from == index => Some(address),
+                _ => None,
+            })
+            .await?;
+        self.addrs.push(address);
+        Ok(index)
+    }
+
+    /// Close the connections of `node`, wait until its peers saw them close
+    /// and stop it. Its index is not reused.
+    pub async fn drop_node(&mut self, node: usize) -> Result<(), String> {
+        let dropped = self.peer_id(node);
+        let mut neighbours: Vec<usize> = self
+            .live_nodes()
+            .into_iter()
+            .filter(|&other| other != node && self.is_connected(node, other))
+            .collect();
+        for &other in &neighbours {
+            let peer = self.peer_ids[other];
+            let _ = self.node_mut(node).disconnect_peer_id(peer);
+        }
+        if !neighbours.is_empty() {
+            self.wait_for("peers to see the node go", |index, event| {
+                if let SwarmEvent::ConnectionClosed {
+                    peer_id,
+                    num_established: 0,
+                    ..
+                } = event
+                {
+                    if peer_id == dropped {
+                        neighbours.retain(|&other| other != index);
+                    }
+                }
+                neighbours.is_empty().then_some(())
+            })
+            .await?;
+        }
+        self.nodes[node] = None;
+        Ok(())
+    }
+
+    /// Nodes started, including dropped ones
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Indices of the nodes not dropped
+    pub fn live_nodes(&self) -> Vec<usize> {
+        (0..self.nodes.len())
+            .filter(|&index| self.nodes[index].is_some())
+            .collect()
+    }
+
+    pub fn peer_id(&self, node: usize) -> PeerId {
+        self.peer_ids[node]
+    }
+
+    /// Whether `from` has a connection to `to`; false if either was dropped
+    pub fn is_connected(&self, from: usize, to: usize) -> bool {
+        match (&self.nodes[from], &self.nodes[to]) {
+            (Some(node), Some(_)) => node.is_connected(&self.peer_ids[to]),
+            _ => false,
+        }
+    }
+
+    fn node_mut(&mut self, node: usize) -> &mut Swarm<BenchBehaviour> {
+        self.nodes[node]
+            .as_mut()
+            .unwrap_or_else(|| panic!("node {} was dropped", node))
+    }
+
+    /// Drive every node until `matches` returns a value for an event. Echo
+    /// requests are answered along the way.
+    async fn wait_for<T>(
+        &mut self,
+        what: &str,
+        mut matches: impl FnMut(usize, SwarmEvent<BenchBehaviourEvent>) -> Option<T>,
+    ) -> Result<T, String> {
+        if self.nodes.iter().all(Option::is_none) {
+            return Err(format!("No node left to wait for {}", what));
+        }
+        let nodes = &mut self.nodes;
+        let wait = async {
+            loop {
+                let (event, index) = {
+                    let live = nodes.iter_mut().enumerate().filter_map(|(index, node)| {
+                        let node = node.as_mut()?;
+                        Some(node.select_next_some().map(move |event| (event, index)))
+                    });
+                    let (found, _, _rest) = select_all(live).await;
+                    found
+                };
+                if let SwarmEvent::Behaviour(BenchBehaviourEvent::Echo(rr::Event::Message {
+                    message:
+                        rr::Message::Request {
+                            request, channel, ..
+                        },
+                    ..
+                })) = event
+                {
+                    let _ = nodes[index]
+                        .as_mut()
+                        .expect("events only come from live nodes")
+                        .behaviour_mut()
+                        .echo
+                        .send_response(channel, request);
+                    continue;
+                }
+                if let Some(found) = matches(index, event) {
+                    return found;
+                }
+            }
+        };
+        tokio::time::timeout(STEP_TIMEOUT, wait).await.map_err(|_| {
+            format!(
+                "Timed out after {}s waiting for {}",
+                STEP_TIMEOUT.as_secs(),
+                what
+            )
+        })
+    }
+
+    /// Connect `from` to `to`; returns the handshake time and the time from
+    /// the connection being established to identify info arriving
+    pub async fn connect(
+        &mut self,
+        from: usize,
+        to: usize,
+    ) -> Result<(Duration, Duration), String> {
+        let target = self.peer_id(to);
+        let addr = self.addrs[to].clone().with(Protocol::P2p(target));
+        let started = Instant::now();
+        self.node_mut(from)
+            .dial(addr)
+            .map_err(|e| format!("dial: {}", e))?;
+        let mut established = None;
+        self.wait_for("identify", |index, event| {
+            if index != from {
+                return None;
+            }
+            match event {
+                SwarmEvent::ConnectionEstablished { peer_id, .. } if peer_id == target => {
+                    established = Some(started.elapsed());
+                    None
+                }
+                SwarmEvent::OutgoingConnectionError { error, .. } => {
+                    Some(Err(format!("dial failed: {}", error)))
+                }
+                SwarmEvent::Behaviour(BenchBehaviourEvent::Identify(
+                    identify::Event::Received { peer_id, .. },
+                )) if peer_id == target => {
+                    let handshake = established.unwrap_or_default();
+                    Some(Ok((handshake, started.elapsed().saturating_sub(handshake))))
+                }
+                _ => None,
+            }
+        })
+        .await?
+    }
+
+    /// Close every connection between `from` and `to` and wait until both
+    /// sides saw them close
+    pub async fn disconnect(&mut self, from: usize, to: usize) -> Result<(), String> {
+        let (from_peer, to_peer) = (self.peer_id(from), self.peer_id(to));
+        let _ = self.node_mut(from).disconnect_peer_id(to_peer);
+        let mut closed = [false, false];
+        self.wait_for("connections to close", |index, event| match event {
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                num_established: 0,
+                ..
+            } => {
+                if index == from && peer_id == to_peer {
+                    closed[0] = true;
+                } else if index == to && peer_id == from_peer {
+                    closed[1] = true;
+                }
+                (closed[0] && closed[1]).then_some(())
+            }
+            _ => None,
+        })
+        .await
+    }
+}
+
+/// Latencies of one measurement, in milliseconds
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencySummary {
+    pub samples: usize,
+    pub min_ms: f64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencySummary {
+    /// Nearest-rank percentiles of `samples`
+    pub fn from_samples(mut samples: Vec<Duration>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let percentile = |p: usize| {
+            let rank = (p * samples.len()).div_ceil(100);
+            ms(samples[rank.clamp(1, samples.len()) - 1])
+        };
+        Self {
+            samples: samples.len(),
+            min_ms: ms(samples[0]),
+            mean_ms: samples.iter().copied().map(ms).sum::<f64>() / samples.len() as f64,
+            p50_ms: percentile(50),
+            p95_ms: percentile(95),
+            p99_ms: percentile(99),
+            max_ms: ms(samples[samples.len() - 1]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BenchReport {
+    pub noise_handshake: LatencySummary,
+    pub identify_exchange: LatencySummary,
+    pub gossipsub_delivery: LatencySummary,
+    pub request_response_rtt: LatencySummary,
+    pub kademlia_lookup: LatencySummary,
+    pub kad_hops: usize,
+    pub payload_bytes: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProtocolBenchmarkSuite {
+    /// Handshakes, GossipSub messages and echo requests measured
+    pub samples: usize,
+    /// Lookups measured, each on a fresh chain of nodes
+    pub kad_samples: usize,
+    pub kad_hops: usize,
+    /// Size of GossipSub messages and echo requests
+    pub payload_bytes: usize,
+}
+
+impl Default for ProtocolBenchmarkSuite {
+    fn default() -> Self {
+        Self {
+            samples: 100,
+            kad_samples: 5,
+            kad_hops: 10,
+            payload_bytes: 1024,
+        }
+    }
+}
+
+impl ProtocolBenchmarkSuite {
+    pub async fn run(&self) -> Result<BenchReport, String> {
+        let mut network = LocalTestNetwork::start(2).await?;
+
+        let mut handshakes = Vec::with_capacity(self.samples);
+        let mut identifies = Vec::with_capacity(self.samples);
+        for _ in 0..self.samples {
+            let (handshake, identify) = network.connect(0, 1).await?;
+            handshakes.push(handshake);
+            identifies.push(identify);
+            network.disconnect(0, 1).await?;
+        }
+
+        network.connect(0, 1).await?;
+        let gossip = self.gossipsub_delivery(&mut network).await?;
+        let rtts = self.request_response_rtt(&mut network).await?;
+
+        let mut lookups = Vec::with_capacity(self.kad_samples);
+        for _ in 0..self.kad_samples {
+            lookups.push(self.kademlia_lookup().await?);
+        }
+
+        Ok(BenchReport {
+            noise_handshake: LatencySummary::from_samples(handshakes),
+            identify_exchange: LatencySummary::from_samples(identifies),
+            gossipsub_delivery: LatencySummary::from_samples(gossip),
+            request_response_rtt: LatencySummary::from_samples(rtts),
+            kademlia_lookup: LatencySummary::from_samples(lookups),
+            kad_hops: self.kad_hops,
+            payload_bytes: self.payload_bytes,
+        })
+    }
+
+    /// Node 0 publishes to node 1, which it is connected to
+    async fn gossipsub_delivery(
+        &self,
+        network: &mut LocalTestNetwork,
+    ) -> Result<Vec<Duration>, String> {
+        let topic = gossipsub::IdentTopic::new(BENCH_TOPIC);
+        for node in network.nodes.iter_mut().flatten() {
+            node.behaviour_mut()
+                .gossipsub
+                .subscribe(&topic)
+                .map_err(|e| format!("subscribe: {}", e))?;
+        }
+        let receiver = network.peer_id(1);
+        network
+            .wait_for("the GossipSub subscription", |index, event| match event {
+                SwarmEvent::Behaviour(BenchBehaviourEvent::Gossipsub(
+                    gossipsub::Event::Subscribed { peer_id, .. },
+                )) if index == 0 && peer_id == receiver => Some(()),
+                _ => None,
+            })
+            .await?;
+
+        let mut latencies = Vec::with_capacity(self.samples);
+        for seq in 0..self.samples as u64 {
+            let mut payload = vec![0u8; self.payload_bytes.max(8)];
+            payload[..8].copy_from_slice(&seq.to_be_bytes());
+            let started = Instant::now();
+            network
+                .node_mut(0)
+                .behaviour_mut()
+                .gossipsub
+                .publish(topic.clone(), payload.clone())
+                .map_err(|e| format!("publish: {}", e))?;
+            network
+                .wait_for("a GossipSub message", |index, event| match event {
+                    SwarmEvent::Behaviour(BenchBehaviourEvent::Gossipsub(
+                        gossipsub::Event::Message { message, .. },
+                    )) if index == 1 && message.data == payload => Some(()),
+                    _ => None,
+                })
+                .await?;
+            latencies.push(started.elapsed());
+        }
+        Ok(latencies)
+    }
+
+    async fn request_response_rtt(
+        &self,
+        network: &mut LocalTestNetwork,
+    ) -> Result<Vec<Duration>, String> {
+        let responder = network.peer_id(1);
+        let payload = vec![0xa5u8; self.payload_bytes];
+        let mut rtts = Vec::with_capacity(self.samples);
+        for _ in 0..self.samples {
+            let started = Instant::now();
+            let sent = network
+                .node_mut(0)
+                .behaviour_mut()
+                .echo
+                .send_request(&responder, payload.clone());
+            network
+                .wait_for("an echo response", |index, event| match event {
+                    SwarmEvent::Behaviour(BenchBehaviourEvent::Echo(rr::Event::Message {
+                        message:
+                            rr::Message::Response {
+                                request_id,
+                                response,
+                            },
+                        ..
+                    })) if index == 0 && request_id == sent => Some(if response == payload {
+                        Ok(())
+                    } else {
+                        Err("echo response differs from the request".to_string())
+                    }),
+                    SwarmEvent::Behaviour(BenchBehaviourEvent::Echo(
+                        rr::Event::OutboundFailure {
+                            request_id, error, ..
+                        },
+                    )) if index == 0 && request_id == sent => {
+                        Some(Err(format!("echo request failed: {}", error)))
+                    }
+                    _ => None,
+                })
+                .await??;
+            rtts.push(started.elapsed());
+        }
+        Ok(rtts)
+    }
+
+    /// Look up the far end of a chain of `kad_hops + 1` nodes from its start
+    async fn kademlia_lookup(&self) -> Result<Duration, String> {
+        let mut network = LocalTestNetwork::start(self.kad_hops + 1).await?;
+        for i in 0..self.kad_hops {
+            let (left, right) = (network.peer_id(i), network.peer_id(i + 1));
+            let (left_addr, right_addr) = (network.addrs[i].clone(), network.addrs[i + 1].clone());
+            network
+                .node_mut(i)
+                .behaviour_mut()
+                .kademlia
+                .add_address(&right, right_addr);
+            network
+                .node_mut(i + 1)
+                .behaviour_mut()
+                .kademlia
+                .add_address(&left, left_addr);
+        }
+        let target = network.peer_id(self.kad_hops);
+        let started = Instant::now();
+        let query = network
+            .node_mut(0)
+            .behaviour_mut()
+            .kademlia
+            .get_closest_peers(target);
+        network
+            .wait_for("a Kademlia lookup", |index, event| match event {
+                SwarmEvent::Behaviour(BenchBehaviourEvent::Kademlia(
+                    kad::Event::OutboundQueryProgressed {
+                        id, result, step, ..
+                    },
+                )) if index == 0 && id == query && step.last => Some(match result {
+                    kad::QueryResult::GetClosestPeers(Ok(ok))
+                        if ok.peers.iter().any(|peer| peer.peer_id == target) =>
+                    {
+                        Ok(started.elapsed())
+                    }
+                    kad::QueryResult::GetClosestPeers(Ok(_)) => {
+                        Err("the lookup did not reach the end of the chain".to_string())
+                    }
+                    other => Err(format!("lookup failed: {:?}", other)),
+                }),
+                _ => None,
+            })
+            .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_percentiles_and_small_run() {
+        let samples = (1..=100).map(Duration::from_millis).collect();
+        let summary = LatencySummary::from_samples(samples);
+        assert_eq!(
+            (summary.p50_ms, summary.p95_ms, summary.p99_ms),
+            (50.0, 95.0, 99.0)
+        );
+        assert_eq!((summary.min_ms, summary.max_ms), (1.0, 100.0));
+        assert_eq!(LatencySummary::from_samples(Vec::new()).samples, 0);
+
+        let suite = ProtocolBenchmarkSuite {
+            samples: 3,
+            kad_samples: 1,
+            kad_hops: 3,
+            payload_bytes: 64,
+        };
+        let report = suite.run().await.unwrap();
+        assert_eq!(report.noise_handshake.samples, 3);
+        assert_eq!(report.gossipsub_delivery.samples, 3);
+        assert_eq!(report.kademlia_lookup.samples, 1);
+    }
+}
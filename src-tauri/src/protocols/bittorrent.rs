@@ -547,7 +547,9 @@ impl ProtocolHandler for BitTorrentProtocolHandler {
                             progress_percentage: progress_pct,
                             download_speed_bps: progress.download_speed,
                             upload_speed_bps: 0.0,
+                            instantaneous_speed_bps: progress.download_speed,
                             eta_seconds: progress.eta_seconds.map(|e| e as u32),
+                            stalled: false,
                             active_sources: 1, // Swarm
                             timestamp: now_ms,
                         });
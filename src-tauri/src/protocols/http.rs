@@ -390,7 +390,9 @@ impl HttpProtocolHandler {
                                         progress_percentage: progress_pct,
                                         download_speed_bps: speed,
                                         upload_speed_bps: 0.0,
+                                        instantaneous_speed_bps: speed,
                                         eta_seconds: eta.map(|e| e as u32),
+                                        stalled: false,
                                         active_sources: 1,
                                         timestamp: now_ms,
                                     });
@@ -0,0 +1,179 @@
+//! Probing of provider peers before a download commits to them.
+//!
+//! Provider records in the DHT can be hours old, so before a large transfer the
+//! top candidates are dialed, asked whether they still have the content, and
+//! optionally asked for one chunk to estimate throughput. The results are used
+//! to rank the sources, and are kept with the download so the transfer detail
+//! can show why a source was chosen.
+
+use crate::download_source::DownloadSource;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Upper bound on the whole probe phase
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Provider peers probed before a download
+pub const MAX_PROBED_PROVIDERS: usize = 6;
+
+/// Bytes fetched from each provider to estimate its throughput
+pub const PROBE_SAMPLE_BYTES: u64 = 64 * 1024;
+
+/// Files smaller than this are downloaded without probing unless asked to
+pub const PROBE_MIN_FILE_SIZE: u64 = 8 * 1024 * 1024;
+
+/// What a probe found out about one provider
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderProbe {
+    pub peer_id: String,
+    /// The provider confirmed it still has the content
+    pub has_content: bool,
+    /// Time from dialing until the "have" answer arrived
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// Throughput measured while fetching the probe chunk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub throughput_bps: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The source was kept for the download
+    #[serde(default)]
+    pub selected: bool,
+}
+
+impl ProviderProbe {
+    pub fn failed(peer_id: impl Into<String>, error: impl Into<String>) -> Self {
+        Self {
+            peer_id: peer_id.into(),
+            error: Some(error.into()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Whether a download of `file_size` bytes should probe its providers.
+///
+/// `requested` overrides the size-based default.
+pub fn should_probe(requested: Option<bool>, file_size: u64) -> bool {
+    requested.unwrap_or(file_size >= PROBE_MIN_FILE_SIZE)
+}
+
+/// Better probe results sort first: confirmed content, then throughput, then latency
+fn compare_probes(a: &ProviderProbe, b: &ProviderProbe) -> Ordering {
+    b.has_content
+        .cmp(&a.has_content)
+        .then_with(|| {
+            let a_bps = a.throughput_bps.unwrap_or(0.0);
+            let b_bps = b.throughput_bps.unwrap_or(0.0);
+            b_bps.partial_cmp(&a_bps).unwrap_or(Ordering::Equal)
+        })
+        .then_with(|| {
+            a.latency_ms
+                .unwrap_or(u64::MAX)
+                .cmp(&b.latency_ms.unwrap_or(u64::MAX))
+        })
+}
+
+/// Reorder `sources` by the probe results.
+///
+/// Probed P2P providers that confirmed the content come first, best first,
+/// followed by unprobed sources in their original order. Providers whose probe
+/// failed are dropped, unless that would leave nothing to download from.
+pub fn rank_sources(sources: Vec<DownloadSource>, probes: &[ProviderProbe]) -> Vec<DownloadSource> {
+    let by_peer: HashMap<&str, &ProviderProbe> =
+        probes.iter().map(|p| (p.peer_id.as_str(), p)).collect();
+
+    let mut confirmed = Vec::new();
+    let mut unprobed = Vec::new();
+    let mut failed = Vec::new();
+    for source in sources {
+        let probe = match &source {
+            DownloadSource::P2p(info) => by_peer.get(info.peer_id.as_str()).copied(),
+            _ => None,
+        };
+        match probe {
+            Some(probe) if probe.has_content => confirmed.push((source, probe)),
+            Some(_) => failed.push(source),
+            None => unprobed.push(source),
+        }
+    }
+    confirmed.sort_by(|(_, a), (_, b)| compare_probes(a, b));
+
+    let mut ranked: Vec<DownloadSource> = confirmed.into_iter().map(|(s, _)| s).collect();
+    ranked.extend(unprobed);
+    if ranked.is_empty() {
+        return failed;
+    }
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::download_source::{HttpSourceInfo, P2pSourceInfo};
+
+    fn peer(id: &str) -> DownloadSource {
+        DownloadSource::P2p(P2pSourceInfo {
+            peer_id: id.to_string(),
+            multiaddr: None,
+            reputation: None,
+            supports_encryption: false,
+            protocol: Some("webrtc".to_string()),
+        })
+    }
+
+    fn ok(id: &str, latency_ms: u64, throughput_bps: Option<f64>) -> ProviderProbe {
+        ProviderProbe {
+            peer_id: id.to_string(),
+            has_content: true,
+            latency_ms: Some(latency_ms),
+            throughput_bps,
+            ..Default::default()
+        }
+    }
+
+    fn ids(sources: &[DownloadSource]) -> Vec<String> {
+        sources.iter().map(DownloadSource::identifier).collect()
+    }
+
+    #[test]
+    fn test_rank_sources_by_probe_results() {
+        let http = DownloadSource::Http(HttpSourceInfo {
+            url: "http://mirror/file".to_string(),
+            auth_header: None,
+            verify_ssl: true,
+            headers: None,
+            timeout_secs: None,
+        });
+        let sources = vec![peer("slow"), peer("gone"), http, peer("fast"), peer("unprobed")];
+        let probes = vec![
+            ok("slow", 20, Some(10_000.0)),
+            ProviderProbe::failed("gone", "dial failed"),
+            ok("fast", 200, Some(5_000_000.0)),
+        ];
+
+        assert_eq!(
+            ids(&rank_sources(sources, &probes)),
+            vec!["fast", "slow", "http://mirror/file", "unprobed"]
+        );
+    }
+
+    #[test]
+    fn test_rank_sources_falls_back_and_breaks_ties_on_latency() {
+        let probes = vec![
+            ProviderProbe::failed("a", "timed out"),
+            ProviderProbe::failed("b", "timed out"),
+        ];
+        assert_eq!(ids(&rank_sources(vec![peer("a"), peer("b")], &probes)), vec!["a", "b"]);
+
+        let probes = vec![ok("a", 300, None), ok("b", 40, None)];
+        assert_eq!(ids(&rank_sources(vec![peer("a"), peer("b")], &probes)), vec!["b", "a"]);
+
+        assert!(should_probe(None, PROBE_MIN_FILE_SIZE));
+        assert!(!should_probe(None, 1024));
+        assert!(should_probe(Some(true), 1024));
+    }
+}
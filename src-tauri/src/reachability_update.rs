@@ -0,0 +1,158 @@
+//! Reachability reports sent to bootstrap nodes.
+//!
+//! A node behind NAT holds a relay reservation on a bootstrap node so others
+//! can reach it through a circuit. Once AutoNAT finds the node publicly
+//! reachable that reservation only takes up relay capacity, so the node tells
+//! every connected bootstrap node with a `NodeReachabilityUpdate`.
+//!
+//! Circuit Relay v2 has no call to drop a single reservation; the relay
+//! server forgets a reservation when the connection that made it closes. A
+//! bootstrap node releases the reservation of a peer that reports itself
+//! public by closing its connections to that peer.
+//!
+//! The relay daemon only runs an AutoNAT server and does not take these
+//! updates, so the node also closes its own circuit listeners when it turns
+//! public, which frees the reservation on any relay. It does not reserve
+//! again while AutoNAT reports it public.
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Largest reachability frame accepted from the wire
+const MAX_REACHABILITY_FRAME: usize = 4 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeReachabilityUpdate {
+    pub is_public: bool,
+    /// Address AutoNAT confirmed, when public
+    pub external_addr: Option<String>,
+}
+
+/// Reply to `NodeReachabilityUpdate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReachabilityAck;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReachabilityProtocol;
+
+impl AsRef<str> for ReachabilityProtocol {
+    fn as_ref(&self) -> &str {
+        crate::protocol::REACHABILITY_PROTOCOL
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ReachabilityCodec;
+
+#[async_trait::async_trait]
+impl libp2p::request_response::Codec for ReachabilityCodec {
+    type Protocol = ReachabilityProtocol;
+    type Request = NodeReachabilityUpdate;
+    type Response = ReachabilityAck;
+
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> std::io::Result<Self::Request>
+    where
+        T: futures::AsyncRead + Unpin + Send,
+    {
+        crate::protocol::read_json_frame(io, MAX_REACHABILITY_FRAME).await
+    }
+
+    async fn read_response<T>(&mut self, _: &Self::Protocol, io: &mut T) -> std::io::Result<Self::Response>
+    where
+        T: futures::AsyncRead + Unpin + Send,
+    {
+        crate::protocol::read_json_frame(io, MAX_REACHABILITY_FRAME).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        request: Self::Request,
+    ) -> std::io::Result<()>
+    where
+        T: futures::AsyncWrite + Unpin + Send,
+    {
+        crate::protocol::write_json_frame(io, &request).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        response: Self::Response,
+    ) -> std::io::Result<()>
+    where
+        T: futures::AsyncWrite + Unpin + Send,
+    {
+        crate::protocol::write_json_frame(io, &response).await
+    }
+}
+
+/// Peers holding a relay reservation on this bootstrap node
+#[derive(Debug, Default)]
+pub struct RelayReservations {
+    holders: HashSet<PeerId>,
+}
+
+impl RelayReservations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_accepted(&mut self, peer: PeerId) {
+        self.holders.insert(peer);
+    }
+
+    /// The reservation timed out or the peer's last connection closed
+    pub fn on_ended(&mut self, peer: &PeerId) {
+        self.holders.remove(peer);
+    }
+
+    /// Handle an update from `peer`; true when its reservation should be
+    /// released
+    pub fn on_update(&mut self, peer: &PeerId, update: &NodeReachabilityUpdate) -> bool {
+        update.is_public && self.holders.remove(peer)
+    }
+
+    pub fn len(&self) -> usize {
+        self.holders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.holders.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_public_update_releases_reservation() {
+        let (relayed, other) = (PeerId::random(), PeerId::random());
+        let mut reservations = RelayReservations::new();
+        reservations.on_accepted(relayed);
+
+        let private = NodeReachabilityUpdate {
+            is_public: false,
+            external_addr: None,
+        };
+        let public = NodeReachabilityUpdate {
+            is_public: true,
+            external_addr: Some("/ip4/203.0.113.7/tcp/4001".to_string()),
+        };
+        assert!(!reservations.on_update(&relayed, &private));
+        assert!(!reservations.on_update(&other, &public));
+        assert!(reservations.on_update(&relayed, &public));
+        // Already released
+        assert!(!reservations.on_update(&relayed, &public));
+        assert!(reservations.is_empty());
+
+        let wire = serde_json::to_value(&public).unwrap();
+        assert_eq!(wire["isPublic"], true);
+        assert_eq!(wire["externalAddr"], "/ip4/203.0.113.7/tcp/4001");
+    }
+}
@@ -0,0 +1,353 @@
+//! Consent checks for circuits through this node's relay server.
+//!
+//! By default the relay server builds a circuit for anyone who asks, so a
+//! public relay carries strangers' traffic. `RelayConsentPolicy` narrows
+//! who may open circuits: `KnownPeersOnly` admits trusted peers (and the
+//! allow list), `ExplicitAllowList` only the allow list.
+//!
+//! The check runs as one of the relay's circuit source limiters. Circuit
+//! Relay v2 reports every refusal as `RESOURCE_LIMIT_EXCEEDED` on the wire,
+//! so the `NO_CONSENT` reason shows up in this node's events and logs only.
+//!
+//! The policy and allow list can be replaced while the node runs (on a
+//! configuration reload); circuits already open are not affected.
+//!
+//! Trusted peers are the ones the user added and the ones that served a
+//! completed download, kept in `trusted_peers.json` in the data directory.
+//! Merely having connected or identified does not make a peer trusted; the
+//! address cache records every such peer and is not consulted.
+
+use crate::transfer_events::TransferEvent;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Reason recorded for circuits refused by the consent policy
+pub const NO_CONSENT: &str = "NO_CONSENT";
+
+/// Peer ids of the `relay_allow_list` entries, skipping invalid ones
+pub fn parse_allow_list(entries: &[String]) -> Vec<PeerId> {
+    entries
+        .iter()
+        .filter_map(|peer| match peer.parse() {
+            Ok(peer) => Some(peer),
+            Err(e) => {
+                tracing::warn!("Ignoring invalid relay allow-list entry {}: {}", peer, e);
+                None
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelayConsentPolicy {
+    /// Relay for anyone
+    #[default]
+    Open,
+    /// Relay for trusted peers or the allow list
+    KnownPeersOnly,
+    /// Relay only for peers in the allow list
+    ExplicitAllowList,
+}
+
+impl FromStr for RelayConsentPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().replace('-', "_").as_str() {
+            "open" => Ok(Self::Open),
+            "known_peers_only" => Ok(Self::KnownPeersOnly),
+            "explicit_allow_list" => Ok(Self::ExplicitAllowList),
+            other => Err(format!("unknown relay consent policy '{}'", other)),
+        }
+    }
+}
+
+/// Why a peer is trusted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrustReason {
+    /// Added by the user
+    Added,
+    /// Served a download that completed
+    Transfer,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustedPeer {
+    pub peer_id: String,
+    pub reason: TrustReason,
+    /// Unix seconds
+    pub since: u64,
+}
+
+/// Peers `KnownPeersOnly` relays for, persisted as JSON
+pub struct TrustedPeers {
+    path: PathBuf,
+    peers: Mutex<BTreeMap<PeerId, TrustedPeer>>,
+}
+
+static SHARED: OnceLock<Arc<TrustedPeers>> = OnceLock::new();
+
+impl TrustedPeers {
+    /// Starts empty when the file is missing or unreadable
+    pub fn load(path: PathBuf) -> Self {
+        let entries: Vec<TrustedPeer> = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| match serde_json::from_slice(&bytes) {
+                Ok(entries) => Some(entries),
+                Err(e) => {
+                    tracing::warn!("Ignoring unreadable {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        let peers = entries
+            .into_iter()
+            .filter_map(|entry| Some((entry.peer_id.parse().ok()?, entry)))
+            .collect();
+        Self {
+            path,
+            peers: Mutex::new(peers),
+        }
+    }
+
+    /// The store of this process, in the data directory
+    pub fn shared() -> Arc<Self> {
+        SHARED
+            .get_or_init(|| {
+                Arc::new(Self::load(
+                    crate::data_dirs::DataDirs::current().trusted_peers(),
+                ))
+            })
+            .clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<PeerId, TrustedPeer>> {
+        self.peers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn contains(&self, peer: &PeerId) -> bool {
+        self.lock().contains_key(peer)
+    }
+
+    pub fn list(&self) -> Vec<TrustedPeer> {
+        self.lock().values().cloned().collect()
+    }
+
+    /// Trust `peer`; a user-added entry is not downgraded by a transfer
+    pub fn trust(&self, peer: PeerId, reason: TrustReason) -> Result<(), String> {
+        let mut peers = self.lock();
+        if peers
+            .get(&peer)
+            .is_some_and(|entry| entry.reason == TrustReason::Added || entry.reason == reason)
+        {
+            return Ok(());
+        }
+        let since = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        peers.insert(
+            peer,
+            TrustedPeer {
+                peer_id: peer.to_string(),
+                reason,
+                since,
+            },
+        );
+        self.save(&peers)
+    }
+
+    /// True if `peer` was trusted
+    pub fn remove(&self, peer: &PeerId) -> Result<bool, String> {
+        let mut peers = self.lock();
+        if peers.remove(peer).is_none() {
+            return Ok(false);
+        }
+        self.save(&peers).map(|()| true)
+    }
+
+    /// Trust the peers that served chunks of a completed download
+    pub fn observe(&self, event: &TransferEvent) {
+        let TransferEvent::Completed(e) = event else {
+            return;
+        };
+        for source in e.sources_used.iter().filter(|s| s.chunks_provided > 0) {
+            // Other sources are HTTP, FTP or torrent ids
+            let Ok(peer) = source.source_id.parse::<PeerId>() else {
+                continue;
+            };
+            if let Err(e) = self.trust(peer, TrustReason::Transfer) {
+                tracing::warn!("Failed to record trusted peer {}: {}", peer, e);
+            }
+        }
+    }
+
+    fn save(&self, peers: &BTreeMap<PeerId, TrustedPeer>) -> Result<(), String> {
+        let entries: Vec<&TrustedPeer> = peers.values().collect();
+        let json = serde_json::to_vec_pretty(&entries).map_err(|e| e.to_string())?;
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)
+            .and_then(|()| std::fs::rename(&tmp, &self.path))
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
+
+struct ConsentRules {
+    policy: RelayConsentPolicy,
+    allow_list: HashSet<PeerId>,
+}
+
+/// Decides which peers may open circuits; clones share their state
+#[derive(Clone)]
+pub struct RelayConsent {
+    rules: Arc<Mutex<ConsentRules>>,
+    trusted: Option<Arc<TrustedPeers>>,
+    /// Peers refused since the relay last reported a denial for them
+    refused: Arc<Mutex<HashSet<PeerId>>>,
+}
+
+impl RelayConsent {
+    /// Without trusted peers, `KnownPeersOnly` admits the allow list only
+    pub fn new(
+        policy: RelayConsentPolicy,
+        allow_list: impl IntoIterator<Item = PeerId>,
+        trusted: Option<Arc<TrustedPeers>>,
+    ) -> Self {
+        Self {
+            rules: Arc::new(Mutex::new(ConsentRules {
+                policy,
+                allow_list: allow_list.into_iter().collect(),
+            })),
+            trusted,
+            refused: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    fn rules(&self) -> std::sync::MutexGuard<'_, ConsentRules> {
+        self.rules.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn policy(&self) -> RelayConsentPolicy {
+        self.rules().policy
+    }
+
+    /// Replace the policy and allow list for circuits requested from now on
+    pub fn update(&self, policy: RelayConsentPolicy, allow_list: impl IntoIterator<Item = PeerId>) {
+        let mut rules = self.rules();
+        rules.policy = policy;
+        rules.allow_list = allow_list.into_iter().collect();
+    }
+
+    pub fn allows(&self, peer: &PeerId) -> bool {
+        let rules = self.rules();
+        match rules.policy {
+            RelayConsentPolicy::Open => true,
+            RelayConsentPolicy::ExplicitAllowList => rules.allow_list.contains(peer),
+            RelayConsentPolicy::KnownPeersOnly => {
+                rules.allow_list.contains(peer)
+                    || self
+                        .trusted
+                        .as_deref()
+                        .is_some_and(|trusted| trusted.contains(peer))
+            }
+        }
+    }
+
+    /// Whether the last circuit refused for `peer` was refused for lack of
+    /// consent rather than by another limit
+    pub fn take_refusal(&self, peer: &PeerId) -> bool {
+        self.refused
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(peer)
+    }
+}
+
+impl libp2p::relay::RateLimiter for RelayConsent {
+    fn try_next(&mut self, peer: PeerId, _addr: &Multiaddr, _now: Instant) -> bool {
+        let allowed = self.allows(&peer);
+        if !allowed {
+            self.refused
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(peer);
+        }
+        allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transfer_events::{SourceSummary, SourceType, TransferCompletedEvent};
+    use libp2p::relay::RateLimiter;
+
+    #[test]
+    fn test_policies_admit_the_right_peers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trusted_peers.json");
+        let store = Arc::new(TrustedPeers::load(path.clone()));
+        let (known, listed, stranger) = (PeerId::random(), PeerId::random(), PeerId::random());
+        store.trust(known, TrustReason::Added).unwrap();
+
+        let open = RelayConsent::new(RelayConsentPolicy::Open, [], None);
+        assert!(open.allows(&stranger));
+
+        let mut known_only = RelayConsent::new(RelayConsentPolicy::KnownPeersOnly, [listed], Some(store.clone()));
+        let addr: Multiaddr = "/ip4/10.0.0.9/tcp/4001".parse().unwrap();
+        assert!(known_only.try_next(known, &addr, Instant::now()));
+        assert!(known_only.try_next(listed, &addr, Instant::now()));
+        assert!(!known_only.try_next(stranger, &addr, Instant::now()));
+        assert!(known_only.take_refusal(&stranger));
+        assert!(!known_only.take_refusal(&stranger));
+
+        let listed_only = RelayConsent::new(RelayConsentPolicy::ExplicitAllowList, [listed], Some(store));
+        assert!(listed_only.allows(&listed));
+        assert!(!listed_only.allows(&known));
+
+        // Clones see a replaced policy
+        let shared = listed_only.clone();
+        listed_only.update(RelayConsentPolicy::ExplicitAllowList, [known]);
+        assert!(shared.allows(&known));
+        assert!(!shared.allows(&listed));
+
+        // Sources of completed downloads become trusted and stay so
+        let (server, idle) = (PeerId::random(), PeerId::random());
+        let source = |peer: PeerId, chunks_provided| SourceSummary {
+            source_id: peer.to_string(),
+            source_type: SourceType::P2p,
+            chunks_provided,
+            bytes_provided: 0,
+            average_speed_bps: 0.0,
+            connection_duration_seconds: 0,
+        };
+        store.observe(&TransferEvent::Completed(TransferCompletedEvent {
+            transfer_id: "t".into(),
+            file_hash: "h".into(),
+            file_name: "f".into(),
+            file_size: 1,
+            output_path: "out".into(),
+            completed_at: 0,
+            duration_seconds: 1,
+            average_speed_bps: 1.0,
+            total_chunks: 1,
+            sources_used: vec![source(server, 1), source(idle, 0)],
+        }));
+        let reloaded = TrustedPeers::load(path);
+        assert!(reloaded.contains(&server) && reloaded.contains(&known));
+        assert!(!reloaded.contains(&idle) && !reloaded.contains(&stranger));
+        assert!(reloaded.remove(&known).unwrap());
+        assert!(!reloaded.remove(&known).unwrap());
+
+        assert_eq!("known-peers-only".parse(), Ok(RelayConsentPolicy::KnownPeersOnly));
+        assert!("everyone".parse::<RelayConsentPolicy>().is_err());
+    }
+}
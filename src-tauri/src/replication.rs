@@ -0,0 +1,254 @@
+//! Records stored on a chosen number of peers, and kept there.
+//!
+//! A plain `put_record` leaves a record on whichever of the closest peers
+//! answer, and nobody knows which they are. `DhtService::put_replicated`
+//! looks up the peers closest to the key, stores the record on up to
+//! `replication_factor` of them with `put_record_to`, and remembers the
+//! peers that took a copy. Records expire after `RECORD_TTL`; shortly
+//! before that the node looks the key up again and re-stores the record, so
+//! a copy lost with an offline peer is replaced on one that is online.
+//!
+//! The node also keeps a copy in its own Kademlia store, so `get_record`
+//! finds it locally. Kademlia's own replication job re-puts every record in
+//! that store to the closest peers it finds each hour, so peers beyond the
+//! tracked holders may hold copies too; `holders` lists only the peers this
+//! module stored on. The local copy expires with the others and is not
+//! refreshed by the re-publish here.
+
+use libp2p::kad::{QueryId, Record, RecordKey};
+use libp2p::PeerId;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// Most copies of one record. A closest-peers lookup returns as many peers
+/// as the Kademlia replication factor, so more holders are never found;
+/// the swarm is built with this factor.
+pub const MAX_REPLICATION_FACTOR: u8 = 3;
+
+/// Lifetime of a replicated record on the peers holding it
+pub const RECORD_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Records this close to expiring are stored again
+pub const REPUBLISH_MARGIN: Duration = Duration::from_secs(60 * 60);
+
+/// How often records are checked for re-publication
+pub const REPUBLISH_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+pub type ReplicationResult = Result<Vec<PeerId>, String>;
+
+/// `replication_factor` limited to 1..=`MAX_REPLICATION_FACTOR`
+pub fn clamp_factor(replication_factor: u8) -> u8 {
+    replication_factor.clamp(1, MAX_REPLICATION_FACTOR)
+}
+
+struct ReplicatedRecord {
+    value: Vec<u8>,
+    replication_factor: u8,
+    holders: Vec<PeerId>,
+    stored_at: Instant,
+}
+
+/// A lookup or store in flight, with the caller waiting for it if any
+struct Pending {
+    key: RecordKey,
+    targets: Vec<PeerId>,
+    response: Option<oneshot::Sender<ReplicationResult>>,
+}
+
+/// Keyed by the id of the Kademlia query each lookup and store runs as
+pub struct RecordReplicator<Q = QueryId> {
+    records: HashMap<RecordKey, ReplicatedRecord>,
+    lookups: HashMap<Q, Pending>,
+    stores: HashMap<Q, Pending>,
+}
+
+impl<Q> Default for RecordReplicator<Q> {
+    fn default() -> Self {
+        Self {
+            records: HashMap::new(),
+            lookups: HashMap::new(),
+            stores: HashMap::new(),
+        }
+    }
+}
+
+impl<Q: Copy + Eq + Hash> RecordReplicator<Q> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember `value` under `key`; replaces an earlier value
+    pub fn track(&mut self, key: RecordKey, value: Vec<u8>, replication_factor: u8, now: Instant) {
+        let holders = self
+            .records
+            .remove(&key)
+            .map(|record| record.holders)
+            .unwrap_or_default();
+        self.records.insert(
+            key,
+            ReplicatedRecord {
+                value,
+                replication_factor: clamp_factor(replication_factor),
+                holders,
+                stored_at: now,
+            },
+        );
+    }
+
+    /// The record to store, expiring `RECORD_TTL` after `now`
+    pub fn record(&self, key: &RecordKey, now: Instant) -> Option<Record> {
+        self.records.get(key).map(|tracked| Record {
+            key: key.clone(),
+            value: tracked.value.clone(),
+            publisher: None,
+            expires: Some(now + RECORD_TTL),
+        })
+    }
+
+    pub fn is_query(&self, id: &Q) -> bool {
+        self.lookups.contains_key(id) || self.stores.contains_key(id)
+    }
+
+    /// A closest-peers lookup for `key` was started as query `id`
+    pub fn lookup_started(
+        &mut self,
+        id: Q,
+        key: RecordKey,
+        response: Option<oneshot::Sender<ReplicationResult>>,
+    ) {
+        self.lookups.insert(
+            id,
+            Pending {
+                key,
+                targets: Vec::new(),
+                response,
+            },
+        );
+    }
+
+    /// The lookup `id` found `closest`, nearest first. Returns the record
+    /// and the peers to store it on; with none, the caller is told.
+    pub fn lookup_finished(
+        &mut self,
+        id: &Q,
+        closest: Vec<PeerId>,
+        now: Instant,
+    ) -> Option<(Record, Vec<PeerId>)> {
+        let mut pending = self.lookups.remove(id)?;
+        let Some(factor) = self.records.get(&pending.key).map(|r| r.replication_factor) else {
+            respond(pending.response.take(), Err("record is no longer tracked".to_string()));
+            return None;
+        };
+        let targets: Vec<PeerId> = closest.into_iter().take(factor as usize).collect();
+        if targets.is_empty() {
+            respond(pending.response.take(), Err("no peers to replicate to".to_string()));
+            return None;
+        }
+        let record = self.record(&pending.key, now)?;
+        pending.targets = targets.clone();
+        // Moved to `stores` once the caller has the query id
+        self.lookups.insert(*id, pending);
+        Some((record, targets))
+    }
+
+    /// The record found by lookup `lookup` is being stored as query `store`
+    pub fn store_started(&mut self, lookup: &Q, store: Q) {
+        if let Some(pending) = self.lookups.remove(lookup) {
+            self.stores.insert(store, pending);
+        }
+    }
+
+    /// The store `id` finished; `stored` are the peers that took a copy,
+    /// `None` meaning all of them
+    pub fn store_finished(&mut self, id: &Q, stored: Option<Vec<PeerId>>, now: Instant) {
+        let Some(mut pending) = self.stores.remove(id) else {
+            return;
+        };
+        let holders = stored.unwrap_or_else(|| pending.targets.clone());
+        let result = if holders.is_empty() {
+            Err("no peer stored the record".to_string())
+        } else {
+            Ok(holders.clone())
+        };
+        if let Some(record) = self.records.get_mut(&pending.key) {
+            record.holders = holders;
+            record.stored_at = now;
+        }
+        respond(pending.response.take(), result);
+    }
+
+    /// Records close enough to expiry to store again, not counting those
+    /// already being stored
+    pub fn due_for_republish(&self, now: Instant) -> Vec<RecordKey> {
+        let in_flight: Vec<&RecordKey> = self
+            .lookups
+            .values()
+            .chain(self.stores.values())
+            .map(|pending| &pending.key)
+            .collect();
+        self.records
+            .iter()
+            .filter(|(key, record)| {
+                now.saturating_duration_since(record.stored_at) + REPUBLISH_MARGIN >= RECORD_TTL
+                    && !in_flight.contains(key)
+            })
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Peers last known to hold a copy of `key`
+    pub fn holders(&self, key: &RecordKey) -> Vec<PeerId> {
+        self.records
+            .get(key)
+            .map(|record| record.holders.clone())
+            .unwrap_or_default()
+    }
+}
+
+fn respond(response: Option<oneshot::Sender<ReplicationResult>>, result: ReplicationResult) {
+    if let Some(response) = response {
+        let _ = response.send(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replicas_are_tracked_and_republished_before_expiry() {
+        let mut replicator = RecordReplicator::<u64>::new();
+        let key = RecordKey::new(&b"key");
+        let start = Instant::now();
+        replicator.track(key.clone(), b"value".to_vec(), 2, start);
+        assert_eq!(clamp_factor(0), 1);
+        assert_eq!(clamp_factor(200), MAX_REPLICATION_FACTOR);
+
+        let (lookup, store) = (1, 2);
+        let (response, mut result) = oneshot::channel();
+        replicator.lookup_started(lookup, key.clone(), Some(response));
+        let peers: Vec<PeerId> = (0..3).map(|_| PeerId::random()).collect();
+        let (record, targets) = replicator.lookup_finished(&lookup, peers.clone(), start).unwrap();
+        assert_eq!(record.value, b"value");
+        assert_eq!(record.expires, Some(start + RECORD_TTL));
+        assert_eq!(targets, peers[..2]);
+
+        replicator.store_started(&lookup, store);
+        assert!(replicator.is_query(&store));
+        replicator.store_finished(&store, Some(vec![peers[1]]), start);
+        assert_eq!(result.try_recv().unwrap().unwrap(), vec![peers[1]]);
+        assert_eq!(replicator.holders(&key), vec![peers[1]]);
+
+        assert!(replicator.due_for_republish(start).is_empty());
+        let late = start + RECORD_TTL - REPUBLISH_MARGIN;
+        assert_eq!(replicator.due_for_republish(late), vec![key.clone()]);
+
+        // Nobody left to hold it
+        let (response, mut result) = oneshot::channel();
+        replicator.lookup_started(3, key, Some(response));
+        assert!(replicator.lookup_finished(&3, Vec::new(), late).is_none());
+        assert!(result.try_recv().unwrap().is_err());
+    }
+}
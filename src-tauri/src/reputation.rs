@@ -657,6 +657,7 @@ impl ReputationDhtService {
             uploader_address: None,
             ftp_sources: None,
             http_sources: None,
+            web_seeds: None,
             info_hash: None,
             trackers: None,
             ..Default::default()
@@ -791,6 +792,7 @@ impl ReputationDhtService {
             ftp_sources: None,
             ed2k_sources: None,
             http_sources: None,
+            web_seeds: None,
             info_hash: None,
             trackers: None,
             ..Default::default()
@@ -832,6 +834,7 @@ impl ReputationDhtService {
             ftp_sources: None,
             ed2k_sources: None,
             http_sources: None,
+            web_seeds: None,
             info_hash: None,
             trackers: None,
             ..Default::default()
@@ -947,6 +950,7 @@ impl ReputationDhtService {
             uploader_address: None,
             ftp_sources: None,
             http_sources: None,
+            web_seeds: None,
             info_hash: None,
             trackers: None,
             ..Default::default()
@@ -0,0 +1,532 @@
+//! JSON-RPC 2.0 over a Unix socket, for scripting a headless node.
+//!
+//! `--rpc-socket PATH` serves the node on a Unix domain socket. Each line a
+//! client writes is one request, or a batch of them, and each answer is one
+//! line. Requests without an `id` member are notifications and get no
+//! answer; `"id": null` is an ordinary id and is answered. A connection stays
+//! open for as many requests as the client sends, and every connection is
+//! served by its own task. The socket is removed when the server stops.
+//!
+//! The socket is created readable and writable by the owner only, which is
+//! the whole of the access control: unlike `control_api` there is no token.
+//! It is bound in a directory only the owner can enter and moved into place
+//! once its mode is set, so nobody can connect before that.
+//!
+//! Only the methods below are served, not every Tauri command. Those named
+//! after a Tauri command do what it does and take the same named parameters;
+//! `get_node_info`, `get_bootstrap_status`, `get_nat_status`, `publish_file`,
+//! `list_channel_messages`, `list_downloads`, `get_settings`, `reload_config`
+//! and `list_methods` have no command of that name and follow the control API
+//! instead.
+//!
+//! | Method                               | Parameters                      |
+//! | ------------------------------------ | ------------------------------- |
+//! | `get_dht_peer_id`                    |                                 |
+//! | `get_multiaddresses`                 |                                 |
+//! | `get_node_info`                      |                                 |
+//! | `get_dht_connected_peers`            |                                 |
+//! | `connect_to_peer`                    | `peerAddress`                   |
+//! | `get_bootstrap_status`               |                                 |
+//! | `get_dht_health`                     |                                 |
+//! | `get_network_stats_command`          |                                 |
+//! | `get_dashboard_stats_command`        |                                 |
+//! | `get_detailed_network_stats_command` |                                 |
+//! | `get_transport_stats_command`        |                                 |
+//! | `get_nat_status`                     |                                 |
+//! | `publish_file`                       | as `POST /api/v1/files`         |
+//! | `stop_publishing_file`               | `fileHash`                      |
+//! | `join_message_channel`               | `channel`                       |
+//! | `publish_message_command`            | `channel`, `payload`, `replyTo` |
+//! | `list_channel_messages`              | `channel`, `limit`, `offset`    |
+//! | `list_downloads`                     |                                 |
+//! | `start_download_restart`             | `request`                       |
+//! | `get_download_status_restart`        | `downloadId`                    |
+//! | `pause_download_restart`             | `downloadId`                    |
+//! | `resume_download_restart`            | `downloadId`                    |
+//! | `get_settings`                       |                                 |
+//! | `reload_config`                      |                                 |
+//! | `list_methods`                       |                                 |
+//!
+//! A failed operation answers error code `-32000` with the message the Tauri
+//! command would return; the codes of the specification are used for
+//! malformed requests, unknown methods and bad parameters.
+
+use crate::control_api::ControlApi;
+use crate::download_restart::{DownloadRestartService, StartDownloadRequest};
+use crate::messaging::MessageId;
+use crate::node_commands::{self, PublishFileRequest};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+/// Default socket in the data directory
+pub const SOCKET_NAME: &str = "rpc.sock";
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// The operation ran and failed
+pub const OPERATION_FAILED: i64 = -32000;
+
+/// Every method `list_methods` reports
+pub const METHODS: &[&str] = &[
+    "get_dht_peer_id",
+    "get_multiaddresses",
+    "get_node_info",
+    "get_dht_connected_peers",
+    "connect_to_peer",
+    "get_bootstrap_status",
+    "get_dht_health",
+    "get_network_stats_command",
+    "get_dashboard_stats_command",
+    "get_detailed_network_stats_command",
+    "get_transport_stats_command",
+    "get_nat_status",
+    "publish_file",
+    "stop_publishing_file",
+    "join_message_channel",
+    "publish_message_command",
+    "list_channel_messages",
+    "list_downloads",
+    "start_download_restart",
+    "get_download_status_restart",
+    "pause_download_restart",
+    "resume_download_restart",
+    "get_settings",
+    "reload_config",
+    "list_methods",
+];
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    /// `None` only when absent: that is a notification, `null` is an id
+    #[serde(default, deserialize_with = "present")]
+    id: Option<Value>,
+}
+
+fn present<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+    /// `null` when the request could not be read far enough to find its id
+    pub id: Value,
+}
+
+impl Response {
+    fn new(id: Value, outcome: Result<Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(value) => (Some(value), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result,
+            error,
+            id,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PeerAddressParams {
+    peer_address: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileHashParams {
+    file_hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DownloadIdParams {
+    download_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct StartDownloadParams {
+    request: StartDownloadRequest,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChannelParams {
+    channel: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListMessagesParams {
+    channel: String,
+    limit: u32,
+    #[serde(default)]
+    offset: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PublishMessageParams {
+    channel: String,
+    payload: Vec<u8>,
+    #[serde(default)]
+    reply_to: Option<String>,
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn ok<T: Serialize>(value: T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))
+}
+
+fn reply<T: Serialize>(result: Result<T, String>) -> Result<Value, RpcError> {
+    ok(result.map_err(|e| RpcError::new(OPERATION_FAILED, e))?)
+}
+
+fn downloads(node: &ControlApi) -> Result<&DownloadRestartService, RpcError> {
+    node.downloads()
+        .map_err(|e| RpcError::new(OPERATION_FAILED, e))
+}
+
+async fn dispatch(node: &ControlApi, method: &str, p: Value) -> Result<Value, RpcError> {
+    let dht = &node.dht;
+    match method {
+        "get_dht_peer_id" => ok(dht.get_peer_id().await),
+        "get_multiaddresses" => ok(dht.get_multiaddresses().await),
+        "get_node_info" => ok(node_commands::node_info(dht).await),
+        "get_dht_connected_peers" => ok(node_commands::connected_peers(dht).await),
+        "connect_to_peer" => {
+            let p: PeerAddressParams = params(p)?;
+            reply(node_commands::connect_peer(dht, p.peer_address).await)
+        }
+        "get_bootstrap_status" => ok(node_commands::bootstrap_status(dht).await),
+        "get_dht_health" => ok(node_commands::health(dht).await),
+        "get_network_stats_command" => ok(node_commands::network_stats(dht).await),
+        "get_dashboard_stats_command" => ok(node_commands::dashboard_stats(dht).await),
+        "get_detailed_network_stats_command" => {
+            reply(node_commands::detailed_network_stats(dht).await)
+        }
+        "get_transport_stats_command" => ok(node_commands::transport_stats(dht)),
+        "get_nat_status" => ok(node_commands::nat_status(dht).await),
+        "publish_file" => {
+            let request: PublishFileRequest = params(p)?;
+            let ft = node.file_transfer.as_deref();
+            reply(node_commands::publish_file(dht, ft, request, None).await)
+        }
+        "stop_publishing_file" => {
+            let p: FileHashParams = params(p)?;
+            reply(node_commands::stop_publishing(dht, p.file_hash).await)
+        }
+        "join_message_channel" => {
+            let p: ChannelParams = params(p)?;
+            reply(dht.join_channel(&p.channel).await)
+        }
+        "publish_message_command" => {
+            let p: PublishMessageParams = params(p)?;
+            let reply_to = p
+                .reply_to
+                .map(|id| id.parse::<MessageId>())
+                .transpose()
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+            reply(node.post_message(p.channel, p.payload, reply_to).await)
+        }
+        "list_channel_messages" => {
+            let p: ListMessagesParams = params(p)?;
+            let store = node
+                .messages()
+                .map_err(|e| RpcError::new(OPERATION_FAILED, e))?;
+            ok(store.list_messages(&p.channel, p.limit, p.offset))
+        }
+        "list_downloads" => match &node.downloads {
+            Some(downloads) => ok(node_commands::list_downloads(downloads).await),
+            None => ok(Vec::<Value>::new()),
+        },
+        "start_download_restart" => {
+            let p: StartDownloadParams = params(p)?;
+            reply(node_commands::start_download(downloads(node)?, p.request).await)
+        }
+        "get_download_status_restart" => {
+            let p: DownloadIdParams = params(p)?;
+            reply(node_commands::download_status(downloads(node)?, &p.download_id).await)
+        }
+        "pause_download_restart" => {
+            let p: DownloadIdParams = params(p)?;
+            reply(node_commands::pause_download(downloads(node)?, &p.download_id).await)
+        }
+        "resume_download_restart" => {
+            let p: DownloadIdParams = params(p)?;
+            reply(node_commands::resume_download(downloads(node)?, &p.download_id).await)
+        }
+        "get_settings" => ok(&node.settings),
+        "reload_config" => match &node.reload {
+            Some(handle) => reply(handle.reload().await),
+            None => Err(RpcError::new(
+                OPERATION_FAILED,
+                "this node cannot reload its configuration",
+            )),
+        },
+        "list_methods" => ok(METHODS),
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("unknown method {}", method),
+        )),
+    }
+}
+
+/// The request in `value`, or the answer to a malformed one
+fn parse_request(value: Value) -> Result<Request, Response> {
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    match serde_json::from_value::<Request>(value) {
+        Ok(request) if request.jsonrpc == "2.0" => Ok(request),
+        Ok(_) => Err(Response::new(
+            id,
+            Err(RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"")),
+        )),
+        Err(e) => Err(Response::new(
+            id,
+            Err(RpcError::new(INVALID_REQUEST, e.to_string())),
+        )),
+    }
+}
+
+async fn handle_request(node: &ControlApi, value: Value) -> Option<Response> {
+    let request = match parse_request(value) {
+        Ok(request) => request,
+        Err(response) => return Some(response),
+    };
+    let outcome = dispatch(node, &request.method, request.params).await;
+    request.id.map(|id| Response::new(id, outcome))
+}
+
+/// The line to answer `line` with, if any
+async fn handle_line(node: &ControlApi, line: &str) -> Option<String> {
+    let value = match serde_json::from_str::<Value>(line) {
+        Ok(value) => value,
+        Err(e) => {
+            let response =
+                Response::new(Value::Null, Err(RpcError::new(PARSE_ERROR, e.to_string())));
+            return serde_json::to_string(&response).ok();
+        }
+    };
+    match value {
+        Value::Array(batch) if batch.is_empty() => {
+            let response = Response::new(
+                Value::Null,
+                Err(RpcError::new(INVALID_REQUEST, "empty batch")),
+            );
+            serde_json::to_string(&response).ok()
+        }
+        Value::Array(batch) => {
+            let mut responses = Vec::new();
+            for value in batch {
+                responses.extend(handle_request(node, value).await);
+            }
+            // A batch of notifications gets no answer at all
+            (!responses.is_empty())
+                .then(|| serde_json::to_string(&responses).ok())
+                .flatten()
+        }
+        value => handle_request(node, value)
+            .await
+            .and_then(|response| serde_json::to_string(&response).ok()),
+    }
+}
+
+async fn serve_connection(node: Arc<ControlApi>, stream: tokio::net::UnixStream) {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(mut answer) = handle_line(&node, &line).await {
+            answer.push('\n');
+            if writer.write_all(answer.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// A socket at `socket_path` with mode `0600` from the moment it can be
+/// reached
+///
+/// Binding and then restricting the final path would leave a window in which
+/// anyone could connect, so the socket is bound in a fresh `0700` directory
+/// beside it and renamed over `socket_path`, replacing a stale one.
+fn bind_private(socket_path: &Path) -> Result<tokio::net::UnixListener, String> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let parent = match socket_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let staging = parent.join(format!(".rpc-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&staging)
+        .map_err(|e| format!("Failed to create {}: {}", staging.display(), e))?;
+    let staged = staging.join(SOCKET_NAME);
+    let bound = tokio::net::UnixListener::bind(&staged)
+        .map_err(|e| format!("Failed to bind {}: {}", socket_path.display(), e))
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))
+                .map_err(|e| format!("Failed to restrict {}: {}", socket_path.display(), e))?;
+            std::fs::rename(&staged, socket_path)
+                .map_err(|e| format!("Failed to move {}: {}", socket_path.display(), e))?;
+            Ok(listener)
+        });
+    let _ = std::fs::remove_dir_all(&staging);
+    bound
+}
+
+/// Serves the socket until dropped, and then removes it
+pub struct HeadlessRPCServer {
+    socket_path: PathBuf,
+    task: JoinHandle<()>,
+}
+
+impl HeadlessRPCServer {
+    /// Serve `node` on `socket_path`
+    ///
+    /// A socket file left behind by an earlier run is replaced, so the caller
+    /// must hold the data directory lock.
+    pub fn start(socket_path: &Path, node: Arc<ControlApi>) -> Result<Self, String> {
+        let listener = bind_private(socket_path)?;
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(serve_connection(node.clone(), stream));
+                    }
+                    Err(e) => tracing::warn!("RPC socket accept failed: {}", e),
+                }
+            }
+        });
+        Ok(Self {
+            socket_path: socket_path.to_path_buf(),
+            task,
+        })
+    }
+}
+
+impl Drop for HeadlessRPCServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = std::fs::remove_file(&self.socket_path);
+    }
+}
+
+/// Call `method` on the node serving `socket_path`
+pub async fn call(socket_path: &Path, method: &str, params: Value) -> Result<Value, String> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let stream = tokio::net::UnixStream::connect(socket_path)
+        .await
+        .map_err(|e| format!("Failed to connect to {}: {}", socket_path.display(), e))?;
+    let (reader, mut writer) = stream.into_split();
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": method,
+        "params": params,
+    });
+    let mut line = request.to_string();
+    line.push('\n');
+    writer
+        .write_all(line.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    let answer = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "The node closed the connection without answering".to_string())?;
+    let response: Response =
+        serde_json::from_str(&answer).map_err(|e| format!("Invalid answer: {}", e))?;
+    match (response.result, response.error) {
+        (_, Some(error)) => Err(format!("{} (code {})", error.message, error.code)),
+        (result, None) => Ok(result.unwrap_or(Value::Null)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_malformed_requests_are_answered() {
+        let request = parse_request(json!({
+            "jsonrpc": "2.0",
+            "method": "connect_to_peer",
+            "params": {"peerAddress": "/ip4/127.0.0.1/tcp/4001"},
+            "id": 7,
+        }))
+        .unwrap();
+        assert_eq!(request.id, Some(json!(7)));
+        let p: PeerAddressParams = params(request.params).unwrap();
+        assert_eq!(p.peer_address, "/ip4/127.0.0.1/tcp/4001");
+
+        let notification =
+            parse_request(json!({"jsonrpc": "2.0", "method": "list_methods"})).unwrap();
+        assert_eq!(notification.id, None);
+        assert_eq!(notification.params, Value::Null);
+        let null_id =
+            parse_request(json!({"jsonrpc": "2.0", "method": "list_methods", "id": null})).unwrap();
+        assert_eq!(null_id.id, Some(Value::Null));
+
+        let wrong_version =
+            parse_request(json!({"jsonrpc": "1.0", "method": "x", "id": "a"})).unwrap_err();
+        assert_eq!(wrong_version.id, json!("a"));
+        assert_eq!(wrong_version.error.unwrap().code, INVALID_REQUEST);
+        let no_method = parse_request(json!({"jsonrpc": "2.0", "id": 2})).unwrap_err();
+        assert_eq!(no_method.error.unwrap().code, INVALID_REQUEST);
+
+        let missing: Result<DownloadIdParams, _> = params(json!({"id": "x"}));
+        assert_eq!(missing.unwrap_err().code, INVALID_PARAMS);
+
+        let answer = serde_json::to_value(Response::new(json!(1), ok(METHODS))).unwrap();
+        assert_eq!(answer["jsonrpc"], "2.0");
+        assert!(answer.get("error").is_none());
+        assert_eq!(answer["result"][0], "get_dht_peer_id");
+    }
+}
@@ -0,0 +1,262 @@
+//! Summary of a headless run, for soak tests and the NAT test harness.
+//!
+//! `--run-for 10m` stops the node through the normal graceful shutdown once
+//! the time is up; SIGTERM and SIGINT still stop it earlier. With
+//! `--run-report PATH` the node writes a `RunReport` to `PATH` as JSON when
+//! it stops, however it was stopped, so a harness can assert on what each
+//! node saw instead of scraping logs.
+//!
+//! Errors are counted by category: `bootstrap`, `holePunch` and `transfer`
+//! come from the DHT metrics, `connection` counts peers whose last connection
+//! closed with an error, and `dht`, `bitswap` and `incompatiblePeer` count
+//! the matching `DhtEvent`s. Fatal errors are the ones that make the run
+//! fail: the network task stopping before the run ended, or the shutdown
+//! outlasting its grace period. The headless binary exits with code 4 after
+//! a run with fatal errors, or 3 when the grace period ran out.
+
+use crate::dht::{DhtEvent, DhtService};
+use crate::discovery::PeerDiscoveryEvent;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Parse `90`, `90s`, `10m`, `2h`, `1d` or combinations such as `1h30m`;
+/// a bare number is seconds. Zero, in any unit, is refused.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let zero = || format!("invalid duration '{}': must be longer than zero", text);
+    if let Ok(secs) = text.parse::<u64>() {
+        return match secs {
+            0 => Err(zero()),
+            secs => Ok(Duration::from_secs(secs)),
+        };
+    }
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(format!("invalid duration '{}': unknown unit '{}'", text, c)),
+        };
+        let value: u64 = digits.parse().map_err(|_| {
+            format!(
+                "invalid duration '{}': expected a number before '{}'",
+                text, c
+            )
+        })?;
+        total = value
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(|| format!("invalid duration '{}': too long", text))?;
+        digits.clear();
+    }
+    if !digits.is_empty() || text.is_empty() {
+        return Err(format!(
+            "invalid duration '{}': use e.g. 90s, 10m or 1h30m",
+            text
+        ));
+    }
+    if total == 0 {
+        return Err(zero());
+    }
+    Ok(Duration::from_secs(total))
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HolePunchReport {
+    pub attempts: u64,
+    pub successes: u64,
+    pub failures: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunReport {
+    pub peer_id: String,
+    /// Unix seconds
+    pub started_at: u64,
+    pub duration_secs: u64,
+    /// `run-for elapsed`, or the signal that stopped the node
+    pub stop_reason: String,
+    /// Distinct peers connected to during the run
+    pub peers_seen: usize,
+    pub peers_connected_at_end: usize,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub transfers_completed: u64,
+    pub hole_punches: HolePunchReport,
+    pub errors: BTreeMap<String, u64>,
+    pub fatal_errors: Vec<String>,
+    /// Whether the graceful shutdown finished within its grace period
+    pub clean_shutdown: bool,
+}
+
+impl RunReport {
+    pub fn succeeded(&self) -> bool {
+        self.fatal_errors.is_empty()
+    }
+
+    /// Write the report to `path`, replacing it in one step
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, json)
+            .and_then(|()| std::fs::rename(&tmp, path))
+            .map_err(|e| format!("Failed to write run report {}: {}", path.display(), e))
+    }
+}
+
+#[derive(Debug, Default)]
+struct Observed {
+    peers: HashSet<String>,
+    errors: BTreeMap<String, u64>,
+    fatal: Vec<String>,
+}
+
+/// Collects what the report needs while the node runs
+pub struct RunRecorder {
+    started: Instant,
+    started_at: u64,
+    observed: Mutex<Observed>,
+}
+
+impl RunRecorder {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            started: Instant::now(),
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            observed: Mutex::new(Observed::default()),
+        })
+    }
+
+    fn observed(&self) -> std::sync::MutexGuard<'_, Observed> {
+        self.observed.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn count_error(&self, category: &str) {
+        *self
+            .observed()
+            .errors
+            .entry(category.to_string())
+            .or_default() += 1;
+    }
+
+    /// Follow the peer discovery stream of `dht` for the life of the process
+    pub fn watch_peers(self: &Arc<Self>, dht: &DhtService) {
+        let recorder = self.clone();
+        let mut events = Box::pin(dht.peer_discovery_stream());
+        tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                match event {
+                    PeerDiscoveryEvent::PeerConnected { peer_id, .. } => {
+                        recorder.observed().peers.insert(peer_id);
+                    }
+                    PeerDiscoveryEvent::PeerDisconnected { reason, .. } if reason != "graceful" => {
+                        recorder.count_error("connection");
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    /// Count the errors among the events the node drained
+    pub fn on_event(&self, event: &DhtEvent) {
+        match event {
+            DhtEvent::Error(_) => self.count_error("dht"),
+            DhtEvent::BitswapError { .. } => self.count_error("bitswap"),
+            DhtEvent::PeerVersionIncompatible { .. } => self.count_error("incompatiblePeer"),
+            _ => {}
+        }
+    }
+
+    pub fn fatal(&self, error: impl Into<String>) {
+        self.observed().fatal.push(error.into());
+    }
+
+    /// Everything but `clean_shutdown`; taken before the DHT shuts down,
+    /// while its statistics can still be read
+    pub async fn report(&self, dht: &DhtService, stop_reason: &str) -> RunReport {
+        let stats = dht.network_stats().await;
+        let metrics = dht.metrics_snapshot().await;
+        let observed = self.observed();
+        let mut errors = observed.errors.clone();
+        for (category, count) in [
+            ("bootstrap", metrics.bootstrap_failures),
+            ("holePunch", metrics.dcutr_hole_punch_failures),
+            ("transfer", metrics.transfers_failed),
+        ] {
+            if count > 0 {
+                errors.insert(category.to_string(), count);
+            }
+        }
+        RunReport {
+            peer_id: dht.get_peer_id().await,
+            started_at: self.started_at,
+            duration_secs: self.started.elapsed().as_secs(),
+            stop_reason: stop_reason.to_string(),
+            peers_seen: observed.peers.len(),
+            peers_connected_at_end: stats.peers_connected,
+            bytes_sent: stats.bytes_sent,
+            bytes_received: stats.bytes_received,
+            transfers_completed: metrics.transfers_received,
+            hole_punches: HolePunchReport {
+                attempts: metrics.dcutr_hole_punch_attempts,
+                successes: metrics.dcutr_hole_punch_successes,
+                failures: metrics.dcutr_hole_punch_failures,
+            },
+            errors,
+            fatal_errors: observed.fatal.clone(),
+            clean_shutdown: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_durations_and_report_file() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("10m").unwrap(), Duration::from_secs(600));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert!(parse_duration("10x").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("5m3").is_err());
+        // Zero is refused however it is written
+        for zero in ["0", "0s", "0m"] {
+            assert!(parse_duration(zero).unwrap_err().contains("longer than zero"));
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.json");
+        let mut report = RunReport {
+            stop_reason: "run-for elapsed".to_string(),
+            clean_shutdown: true,
+            ..Default::default()
+        };
+        report.errors.insert("connection".to_string(), 2);
+        assert!(report.succeeded());
+        report.write(&path).unwrap();
+        let read: RunReport = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(read, report);
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(json["holePunches"]["attempts"], 0);
+    }
+}
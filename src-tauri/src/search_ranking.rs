@@ -0,0 +1,203 @@
+// Ranking of file search results
+//
+// Matches on file name alone put dead entries (no live provider) next to well
+// seeded ones. The first page of results is enriched with a provider count
+// and, for the top few, a reachability probe of one provider; results are then
+// sorted by a score combining the two with how well the name matches. Only the
+// first page is enriched so a broad query does not fan out hundreds of
+// provider lookups.
+
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Results that get a provider lookup; the rest keep `provider_count: None`
+pub const RANKED_PAGE_SIZE: usize = 20;
+
+/// Top results whose first provider is probed for reachability
+pub const MAX_PROBED_RESULTS: usize = 5;
+
+/// Upper bound on one reachability probe
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Provider lookups are reused for this long
+pub const PROVIDER_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Files kept in `ProviderCache`
+const MAX_CACHED_FILES: usize = 512;
+
+// Score weights; they add up to 1
+const PROVIDER_WEIGHT: f64 = 0.5;
+const NAME_WEIGHT: f64 = 0.3;
+const LATENCY_WEIGHT: f64 = 0.2;
+
+/// Latency at which the latency component drops to one half
+const REFERENCE_LATENCY_MS: f64 = 200.0;
+
+/// Outcome of the reachability probe of a result's first provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum ReachabilityProbe {
+    /// Not among the probed results
+    NotProbed,
+    #[serde(rename_all = "camelCase")]
+    Reachable { latency_ms: u64 },
+    Unreachable,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RankedSearchResult {
+    pub file_hash: String,
+    pub file_name: String,
+    pub file_size: u64,
+    /// Live providers, if the result was on the enriched first page
+    pub provider_count: Option<usize>,
+    pub probe: ReachabilityProbe,
+    pub score: f64,
+}
+
+/// How well `name` matches `query`, from 0 (no match) to 1 (exact, ignoring case)
+pub fn name_match_quality(query: &str, name: &str) -> f64 {
+    let query = query.trim().to_lowercase();
+    let name = name.to_lowercase();
+    if query.is_empty() {
+        return 0.0;
+    }
+    if name == query {
+        return 1.0;
+    }
+    if name.starts_with(&query) {
+        return 0.8;
+    }
+    if name.contains(&query) {
+        return 0.6;
+    }
+    let terms: Vec<&str> = query.split_whitespace().collect();
+    let matched = terms.iter().filter(|term| name.contains(*term)).count();
+    0.5 * matched as f64 / terms.len() as f64
+}
+
+/// Combined score in [0, 1]; higher ranks first.
+///
+/// A result with no known providers gets no provider component, and an
+/// unreachable probe no latency component. Results that were not probed get
+/// half of the best latency component, between fast and dead entries.
+pub fn score(provider_count: Option<usize>, probe: ReachabilityProbe, name_quality: f64) -> f64 {
+    // 1 provider -> 0.5, 3 -> 0.75, 9 -> 0.9
+    let providers = provider_count.map_or(0.0, |n| 1.0 - 1.0 / (1.0 + n as f64));
+    let latency = match probe {
+        ReachabilityProbe::Reachable { latency_ms } => {
+            1.0 / (1.0 + latency_ms as f64 / REFERENCE_LATENCY_MS)
+        }
+        ReachabilityProbe::NotProbed => 0.5,
+        ReachabilityProbe::Unreachable => 0.0,
+    };
+    PROVIDER_WEIGHT * providers
+        + NAME_WEIGHT * name_quality.clamp(0.0, 1.0)
+        + LATENCY_WEIGHT * latency
+}
+
+/// Sort by score, best first; ties go to the shorter, then alphabetical name
+pub fn rank(results: &mut [RankedSearchResult]) {
+    results.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.file_name.len().cmp(&b.file_name.len()))
+            .then_with(|| a.file_name.cmp(&b.file_name))
+    });
+}
+
+/// Recent provider lookups by file hash
+#[derive(Default)]
+pub struct ProviderCache {
+    entries: HashMap<String, (Vec<String>, Instant)>,
+}
+
+impl ProviderCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, file_hash: &str, now: Instant) -> Option<Vec<String>> {
+        self.entries
+            .get(file_hash)
+            .filter(|(_, at)| now.duration_since(*at) < PROVIDER_CACHE_TTL)
+            .map(|(providers, _)| providers.clone())
+    }
+
+    pub fn insert(&mut self, file_hash: String, providers: Vec<String>, now: Instant) {
+        if self.entries.len() >= MAX_CACHED_FILES && !self.entries.contains_key(&file_hash) {
+            self.entries
+                .retain(|_, (_, at)| now.duration_since(*at) < PROVIDER_CACHE_TTL);
+            if self.entries.len() >= MAX_CACHED_FILES {
+                if let Some(oldest) = self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, (_, at))| *at)
+                    .map(|(hash, _)| hash.clone())
+                {
+                    self.entries.remove(&oldest);
+                }
+            }
+        }
+        self.entries.insert(file_hash, (providers, now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(name: &str, providers: Option<usize>, probe: ReachabilityProbe) -> RankedSearchResult {
+        RankedSearchResult {
+            file_hash: name.to_string(),
+            file_name: name.to_string(),
+            file_size: 0,
+            provider_count: providers,
+            probe,
+            score: score(providers, probe, name_match_quality("ubuntu iso", name)),
+        }
+    }
+
+    #[test]
+    fn test_name_match_quality() {
+        assert_eq!(name_match_quality("Ubuntu", "ubuntu"), 1.0);
+        assert_eq!(name_match_quality("ubuntu", "ubuntu-24.04.iso"), 0.8);
+        assert_eq!(name_match_quality("24.04", "ubuntu-24.04.iso"), 0.6);
+        assert_eq!(name_match_quality("ubuntu iso", "ubuntu-24.04.iso"), 0.5);
+        assert_eq!(name_match_quality("ubuntu debian", "ubuntu-24.04.iso"), 0.25);
+        assert_eq!(name_match_quality("", "anything"), 0.0);
+    }
+
+    #[test]
+    fn test_live_providers_outrank_better_name_matches() {
+        let mut results = vec![
+            result("ubuntu iso", Some(0), ReachabilityProbe::Unreachable),
+            result("ubuntu-24.04.iso", Some(4), ReachabilityProbe::Reachable { latency_ms: 50 }),
+            result("ubuntu-22.04.iso", Some(4), ReachabilityProbe::Reachable { latency_ms: 900 }),
+            result("ubuntu-server.iso", None, ReachabilityProbe::NotProbed),
+        ];
+        rank(&mut results);
+        let order: Vec<&str> = results.iter().map(|r| r.file_name.as_str()).collect();
+        assert_eq!(
+            order,
+            vec!["ubuntu-24.04.iso", "ubuntu-22.04.iso", "ubuntu iso", "ubuntu-server.iso"]
+        );
+        assert!(results.iter().all(|r| (0.0..=1.0).contains(&r.score)));
+    }
+
+    #[test]
+    fn test_provider_lookups_expire() {
+        let mut cache = ProviderCache::new();
+        let t0 = Instant::now();
+        cache.insert("hash".to_string(), vec!["peer".to_string()], t0);
+        assert_eq!(
+            cache.get("hash", t0 + Duration::from_secs(59)),
+            Some(vec!["peer".to_string()])
+        );
+        assert_eq!(cache.get("hash", t0 + PROVIDER_CACHE_TTL), None);
+    }
+}
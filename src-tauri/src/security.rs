@@ -0,0 +1,674 @@
+//! Allow, deny and throttle rules for incoming connections.
+//!
+//! Bootstrap nodes accept connections from anyone, so a single host can
+//! open connections faster than the node can serve them. An
+//! `IncomingConnectionFilter` is the first behaviour of the swarm and checks
+//! every incoming connection twice:
+//!
+//! - before the Noise handshake, against the `ip_range` and `protocol`
+//!   rules, with the remote address as the only thing known about the peer;
+//! - once the handshake names the peer, against the `peer_id_prefix` rules.
+//!
+//! At each check the first matching rule decides; a connection no rule
+//! matches is admitted. `throttle` admits at most `max_per_minute`
+//! connections a minute from each source (IP address, or peer for
+//! `peer_id_prefix` rules) and refuses the rest. Outgoing connections are
+//! never filtered.
+//!
+//! Rules come from `[[security.filter_rules]]` in `chiral.toml` and can be
+//! added and removed while the node runs; rules added at run time are
+//! checked before the configured ones and are not written back to the file.
+//! Each rule counts the connections it matched and refused.
+//!
+//! ```toml
+//! [[security.filter_rules]]
+//! id = "lan"
+//! match = { ip_range = "192.168.0.0/16" }
+//! action = "allow"
+//!
+//! [[security.filter_rules]]
+//! match = { ip_range = "0.0.0.0/0" }
+//! action = { throttle = { max_per_minute = 30 } }
+//! ```
+
+use libp2p::core::{transport::PortUse, Endpoint};
+use libp2p::multiaddr::Protocol;
+use libp2p::swarm::{
+    dummy, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler, THandlerInEvent,
+    THandlerOutEvent, ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Window `throttle` counts connections in
+const THROTTLE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Sources each throttle rule remembers; the quietest are forgotten first
+const MAX_THROTTLED_SOURCES: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterMatch {
+    /// Peer ids starting with this text
+    PeerIdPrefix(String),
+    /// An address such as `203.0.113.7` or a CIDR range such as
+    /// `203.0.113.0/24`
+    IpRange(String),
+    /// A protocol of the connection's addresses, e.g. `tcp`, `ws` or
+    /// `p2p-circuit` for relayed connections
+    Protocol(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    Allow,
+    Deny,
+    /// Admit at most `max_per_minute` connections a minute from each source
+    Throttle {
+        max_per_minute: u32,
+    },
+}
+
+/// A rule as written in `chiral.toml` and passed to `add_filter_rule_command`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilterRuleSpec {
+    /// Chosen by the node when not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "match")]
+    pub matches: FilterMatch,
+    pub action: FilterAction,
+}
+
+/// A rule with its hit counts, as reported to the frontend
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterRuleReport {
+    pub id: String,
+    #[serde(rename = "match")]
+    pub matches: FilterMatch,
+    pub action: FilterAction,
+    /// From `chiral.toml` rather than added at run time
+    pub configured: bool,
+    /// Connections the rule matched
+    pub hits: u64,
+    /// Connections the rule refused, by `deny` or over the `throttle` limit
+    pub denied: u64,
+}
+
+/// Why a connection was refused; the cause of the swarm's `ConnectionDenied`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterDenied {
+    pub rule_id: String,
+    pub throttled: bool,
+}
+
+impl fmt::Display for FilterDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.throttled {
+            write!(
+                f,
+                "over the limit of connection filter rule {}",
+                self.rule_id
+            )
+        } else {
+            write!(f, "refused by connection filter rule {}", self.rule_id)
+        }
+    }
+}
+
+impl std::error::Error for FilterDenied {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IpRange {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpRange {
+    fn parse(range: &str) -> Result<Self, String> {
+        let (addr, prefix_len) = match range.trim().split_once('/') {
+            Some((addr, len)) => (addr, Some(len)),
+            None => (range.trim(), None),
+        };
+        let network: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("'{}' is not an IP address or range", range))?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => len
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("'{}' has an invalid prefix length", range))?,
+            None => max_len,
+        };
+        Ok(Self {
+            network,
+            prefix_len,
+        })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(network: &[u8], ip: &[u8], prefix_len: u8) -> bool {
+    let (bytes, bits) = ((prefix_len / 8) as usize, prefix_len % 8);
+    if network[..bytes] != ip[..bytes] {
+        return false;
+    }
+    bits == 0 || (network[bytes] ^ ip[bytes]) >> (8 - bits) == 0
+}
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    PeerIdPrefix(String),
+    IpRange(IpRange),
+    Protocol(String),
+}
+
+impl Matcher {
+    fn compile(matches: &FilterMatch) -> Result<Self, String> {
+        match matches {
+            FilterMatch::PeerIdPrefix(prefix) if prefix.trim().is_empty() => {
+                Err("peer_id_prefix must not be empty".to_string())
+            }
+            FilterMatch::PeerIdPrefix(prefix) => Ok(Self::PeerIdPrefix(prefix.trim().to_string())),
+            FilterMatch::IpRange(range) => IpRange::parse(range).map(Self::IpRange),
+            FilterMatch::Protocol(protocol) if protocol.trim().is_empty() => {
+                Err("protocol must not be empty".to_string())
+            }
+            FilterMatch::Protocol(protocol) => Ok(Self::Protocol(
+                protocol.trim().trim_matches('/').to_ascii_lowercase(),
+            )),
+        }
+    }
+
+    fn after_handshake(&self) -> bool {
+        matches!(self, Self::PeerIdPrefix(_))
+    }
+}
+
+/// The connection being checked
+struct Incoming<'a> {
+    peer: Option<&'a PeerId>,
+    local_addr: &'a Multiaddr,
+    remote_addr: &'a Multiaddr,
+}
+
+impl Incoming<'_> {
+    fn remote_ip(&self) -> Option<IpAddr> {
+        self.remote_addr.iter().find_map(|protocol| match protocol {
+            Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+            Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+            _ => None,
+        })
+    }
+
+    fn matches(&self, matcher: &Matcher) -> bool {
+        match matcher {
+            Matcher::PeerIdPrefix(prefix) => self
+                .peer
+                .is_some_and(|peer| peer.to_string().starts_with(prefix.as_str())),
+            Matcher::IpRange(range) => self.remote_ip().is_some_and(|ip| range.contains(ip)),
+            Matcher::Protocol(name) => self
+                .local_addr
+                .iter()
+                .chain(self.remote_addr.iter())
+                .any(|protocol| protocol_name(&protocol) == name.as_str()),
+        }
+    }
+
+    /// Who a throttle counts the connection against
+    fn source(&self, matcher: &Matcher) -> String {
+        match (matcher, self.peer) {
+            (Matcher::PeerIdPrefix(_), Some(peer)) => peer.to_string(),
+            _ => self
+                .remote_ip()
+                .map(|ip| ip.to_canonical().to_string())
+                .unwrap_or_else(|| self.remote_addr.to_string()),
+        }
+    }
+}
+
+/// `tcp` for `/tcp/4001`
+fn protocol_name(protocol: &Protocol<'_>) -> String {
+    let text = protocol.to_string();
+    text.trim_start_matches('/')
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+struct FilterRule {
+    id: String,
+    spec: FilterRuleSpec,
+    matcher: Matcher,
+    configured: bool,
+    hits: u64,
+    denied: u64,
+    /// Admission times per source, for `throttle`
+    recent: HashMap<String, VecDeque<Instant>>,
+}
+
+impl FilterRule {
+    fn new(id: String, spec: FilterRuleSpec, configured: bool) -> Result<Self, String> {
+        let matcher = Matcher::compile(&spec.matches)?;
+        if spec.action == (FilterAction::Throttle { max_per_minute: 0 }) {
+            return Err("throttle needs a max_per_minute above 0".to_string());
+        }
+        Ok(Self {
+            id,
+            spec: FilterRuleSpec { id: None, ..spec },
+            matcher,
+            configured,
+            hits: 0,
+            denied: 0,
+            recent: HashMap::new(),
+        })
+    }
+
+    /// Admit one more connection from `source` unless it is over `limit`
+    fn throttle(&mut self, source: String, limit: u32, now: Instant) -> bool {
+        if !self.recent.contains_key(&source) && self.recent.len() >= MAX_THROTTLED_SOURCES {
+            self.recent.retain(|_, times| {
+                times
+                    .back()
+                    .is_some_and(|at| now.saturating_duration_since(*at) < THROTTLE_WINDOW)
+            });
+            if self.recent.len() >= MAX_THROTTLED_SOURCES {
+                if let Some(quietest) = self
+                    .recent
+                    .iter()
+                    .min_by_key(|(_, times)| times.len())
+                    .map(|(source, _)| source.clone())
+                {
+                    self.recent.remove(&quietest);
+                }
+            }
+        }
+        let times = self.recent.entry(source).or_default();
+        while times
+            .front()
+            .is_some_and(|at| now.saturating_duration_since(*at) >= THROTTLE_WINDOW)
+        {
+            times.pop_front();
+        }
+        if times.len() >= limit as usize {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+
+    fn report(&self) -> FilterRuleReport {
+        FilterRuleReport {
+            id: self.id.clone(),
+            matches: self.spec.matches.clone(),
+            action: self.spec.action,
+            configured: self.configured,
+            hits: self.hits,
+            denied: self.denied,
+        }
+    }
+}
+
+#[derive(Default)]
+struct FilterRules {
+    /// Rules added at run time, then the configured ones
+    rules: Vec<FilterRule>,
+    next_id: u64,
+}
+
+impl FilterRules {
+    fn check(&mut self, incoming: &Incoming<'_>, now: Instant) -> Result<(), FilterDenied> {
+        let after_handshake = incoming.peer.is_some();
+        for rule in &mut self.rules {
+            if rule.matcher.after_handshake() != after_handshake || !incoming.matches(&rule.matcher)
+            {
+                continue;
+            }
+            rule.hits += 1;
+            let admitted = match rule.spec.action {
+                FilterAction::Allow => true,
+                FilterAction::Deny => false,
+                FilterAction::Throttle { max_per_minute } => {
+                    let source = incoming.source(&rule.matcher);
+                    rule.throttle(source, max_per_minute, now)
+                }
+            };
+            if admitted {
+                return Ok(());
+            }
+            rule.denied += 1;
+            return Err(FilterDenied {
+                rule_id: rule.id.clone(),
+                throttled: matches!(rule.spec.action, FilterAction::Throttle { .. }),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Checks incoming connections against the filter rules; clones share
+/// their rules and counts
+#[derive(Clone, Default)]
+pub struct IncomingConnectionFilter {
+    rules: Arc<Mutex<FilterRules>>,
+}
+
+impl IncomingConnectionFilter {
+    /// Filter with the configured `specs`; invalid rules are skipped with a
+    /// warning
+    pub fn new(specs: &[FilterRuleSpec]) -> Self {
+        let filter = Self::default();
+        filter.set_configured(specs);
+        filter
+    }
+
+    fn rules(&self) -> std::sync::MutexGuard<'_, FilterRules> {
+        self.rules.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Replace the configured rules, as on a configuration reload; rules
+    /// added at run time are kept, and configured rules whose id stays
+    /// keep their counts
+    pub fn set_configured(&self, specs: &[FilterRuleSpec]) {
+        let mut rules = self.rules();
+        let mut counts: HashMap<String, (u64, u64)> = HashMap::new();
+        rules.rules.retain(|rule| {
+            if rule.configured {
+                counts.insert(rule.id.clone(), (rule.hits, rule.denied));
+            }
+            !rule.configured
+        });
+        for (i, spec) in specs.iter().enumerate() {
+            let id = spec
+                .id
+                .clone()
+                .unwrap_or_else(|| format!("config-{}", i + 1));
+            if rules.rules.iter().any(|rule| rule.id == id) {
+                tracing::warn!("Ignoring connection filter rule with duplicate id {}", id);
+                continue;
+            }
+            match FilterRule::new(id.clone(), spec.clone(), true) {
+                Ok(mut rule) => {
+                    if let Some(&(hits, denied)) = counts.get(&id) {
+                        rule.hits = hits;
+                        rule.denied = denied;
+                    }
+                    rules.rules.push(rule);
+                }
+                Err(e) => tracing::warn!("Ignoring connection filter rule {}: {}", id, e),
+            }
+        }
+    }
+
+    /// Add a rule checked before the configured ones and after rules added
+    /// earlier
+    pub fn add(&self, spec: FilterRuleSpec) -> Result<FilterRuleReport, String> {
+        let mut rules = self.rules();
+        let id = match spec.id.as_deref().map(str::trim) {
+            Some("") => return Err("rule id must not be empty".to_string()),
+            Some(id) => id.to_string(),
+            None => loop {
+                rules.next_id += 1;
+                let id = format!("rule-{}", rules.next_id);
+                if !rules.rules.iter().any(|rule| rule.id == id) {
+                    break id;
+                }
+            },
+        };
+        if rules.rules.iter().any(|rule| rule.id == id) {
+            return Err(format!("a filter rule with id {} already exists", id));
+        }
+        let rule = FilterRule::new(id, spec, false)?;
+        let report = rule.report();
+        let at = rules
+            .rules
+            .iter()
+            .take_while(|rule| !rule.configured)
+            .count();
+        rules.rules.insert(at, rule);
+        Ok(report)
+    }
+
+    /// Remove the rule `id`, configured or not; a configured rule returns
+    /// with the next start or reload
+    pub fn remove(&self, id: &str) -> Result<(), String> {
+        let mut rules = self.rules();
+        let before = rules.rules.len();
+        rules.rules.retain(|rule| rule.id != id);
+        if rules.rules.len() == before {
+            return Err(format!("no filter rule with id {}", id));
+        }
+        Ok(())
+    }
+
+    /// Every rule in the order they are checked
+    pub fn list(&self) -> Vec<FilterRuleReport> {
+        self.rules().rules.iter().map(FilterRule::report).collect()
+    }
+
+    fn check(&self, incoming: &Incoming<'_>) -> Result<(), ConnectionDenied> {
+        self.rules()
+            .check(incoming, Instant::now())
+            .map_err(ConnectionDenied::new)
+    }
+}
+
+/// Check that every rule compiles; for validating a configuration file
+pub fn validate_rules(specs: &[FilterRuleSpec]) -> Vec<String> {
+    specs
+        .iter()
+        .enumerate()
+        .filter_map(|(i, spec)| {
+            FilterRule::new(String::new(), spec.clone(), true)
+                .err()
+                .map(|e| format!("security.filter_rules[{}]: {}", i, e))
+        })
+        .collect()
+}
+
+impl NetworkBehaviour for IncomingConnectionFilter {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = Infallible;
+
+    fn handle_pending_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<(), ConnectionDenied> {
+        self.check(&Incoming {
+            peer: None,
+            local_addr,
+            remote_addr,
+        })
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check(&Incoming {
+            peer: Some(&peer),
+            local_addr,
+            remote_addr,
+        })?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+        _port_use: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, _event: FromSwarm) {}
+
+    fn on_connection_handler_event(
+        &mut self,
+        _peer_id: PeerId,
+        _connection_id: ConnectionId,
+        _event: THandlerOutEvent<Self>,
+    ) {
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(matches: FilterMatch, action: FilterAction) -> FilterRuleSpec {
+        FilterRuleSpec {
+            id: None,
+            matches,
+            action,
+        }
+    }
+
+    fn before<'a>(local_addr: &'a Multiaddr, remote_addr: &'a Multiaddr) -> Incoming<'a> {
+        Incoming {
+            peer: None,
+            local_addr,
+            remote_addr,
+        }
+    }
+
+    #[test]
+    fn test_first_matching_rule_decides() {
+        let config: toml::Value = toml::from_str(
+            r#"
+            [[filter_rules]]
+            id = "lan"
+            match = { ip_range = "192.168.0.0/16" }
+            action = "allow"
+
+            [[filter_rules]]
+            match = { ip_range = "0.0.0.0/0" }
+            action = { throttle = { max_per_minute = 2 } }
+
+            [[filter_rules]]
+            match = { peer_id_prefix = "12D3KooW" }
+            action = "deny"
+            "#,
+        )
+        .unwrap();
+        let specs: Vec<FilterRuleSpec> = config["filter_rules"].clone().try_into().unwrap();
+        let filter = IncomingConnectionFilter::new(&specs);
+        let local: Multiaddr = "/ip4/0.0.0.0/tcp/4001".parse().unwrap();
+        let lan: Multiaddr = "/ip4/192.168.1.5/tcp/50000".parse().unwrap();
+        let wan: Multiaddr = "/ip4/203.0.113.9/tcp/50000".parse().unwrap();
+        let now = Instant::now();
+        let mut rules = filter.rules();
+
+        for _ in 0..5 {
+            assert!(rules.check(&before(&local, &lan), now).is_ok());
+        }
+        assert!(rules.check(&before(&local, &wan), now).is_ok());
+        assert!(rules.check(&before(&local, &wan), now).is_ok());
+        let denied = rules.check(&before(&local, &wan), now).unwrap_err();
+        assert_eq!(denied.rule_id, "config-2");
+        assert!(denied.throttled);
+        assert!(rules
+            .check(&before(&local, &wan), now + THROTTLE_WINDOW)
+            .is_ok());
+
+        // Peer id rules wait for the handshake
+        let peer = libp2p::identity::Keypair::generate_ed25519()
+            .public()
+            .to_peer_id();
+        let after = Incoming {
+            peer: Some(&peer),
+            local_addr: &local,
+            remote_addr: &lan,
+        };
+        assert_eq!(rules.check(&after, now).unwrap_err().rule_id, "config-3");
+        drop(rules);
+
+        let block = filter
+            .add(spec(
+                FilterMatch::IpRange("192.168.1.5".into()),
+                FilterAction::Deny,
+            ))
+            .unwrap();
+        assert_eq!(block.id, "rule-1");
+        assert!(filter
+            .add(spec(
+                FilterMatch::IpRange("192.168.1.0/33".into()),
+                FilterAction::Deny
+            ))
+            .is_err());
+        assert!(
+            filter.rules().check(&before(&local, &lan), now).is_err(),
+            "added rules come first"
+        );
+
+        let report = filter.list();
+        let ids: Vec<&str> = report.iter().map(|rule| rule.id.as_str()).collect();
+        assert_eq!(ids, ["rule-1", "lan", "config-2", "config-3"]);
+        assert_eq!((report[1].hits, report[1].denied), (5, 0));
+        assert_eq!((report[2].hits, report[2].denied), (4, 1));
+        assert_eq!((report[3].hits, report[3].denied), (1, 1));
+
+        filter.remove("rule-1").unwrap();
+        assert!(filter.remove("rule-1").is_err());
+        // A reload keeps the counts of rules whose id stays
+        filter.set_configured(&specs[..2]);
+        let report = filter.list();
+        assert_eq!((report[0].hits, report[0].denied), (5, 0));
+        assert_eq!((report[1].hits, report[1].denied), (4, 1));
+        filter.set_configured(&[]);
+        assert!(filter.list().is_empty());
+
+        let mapped: IpAddr = "::ffff:192.168.1.5".parse().unwrap();
+        assert!(IpRange::parse("192.168.0.0/16").unwrap().contains(mapped));
+        assert!(!IpRange::parse("10.0.0.0/8").unwrap().contains(mapped));
+        assert!(IpRange::parse("2001:db8::/32")
+            .unwrap()
+            .contains("2001:db8:1::1".parse().unwrap()));
+        let relayed: Multiaddr = "/ip4/203.0.113.1/tcp/4001/p2p-circuit".parse().unwrap();
+        assert!(Incoming {
+            peer: None,
+            local_addr: &relayed,
+            remote_addr: &wan,
+        }
+        .matches(&Matcher::Protocol("p2p-circuit".into())));
+    }
+}
@@ -0,0 +1,478 @@
+//! Seed directories
+//!
+//! A headless seed node started with `--seed-dir PATH` publishes every regular
+//! file under PATH once it has reached a bootstrap node, logging its progress,
+//! and prints a table of the content hashes for test harnesses. Hidden files
+//! are left out, as in the watch directory.
+//!
+//! Each file is recorded in the shared files registry. Content already
+//! registered from the same path with the same hash is not stored again, only
+//! announced: provider records do not outlive the node. Content seen twice in
+//! one run is announced once. Files are streamed for hashing, storing and
+//! announcing, so a seed directory of large files is never held in memory.
+//!
+//! Afterwards the directories are watched by the same loop as the watch
+//! directory: files added or rewritten are published once they settle, and
+//! files deleted are un-announced.
+
+use crate::dht::models::FileMetadata;
+use crate::dht::{split_into_blocks, Cid, Code, DhtService, MultihashDigest, RAW_CODEC};
+use crate::file_transfer::FileTransferService;
+use crate::shared_files::{hash_file, SharedFilesRegistry};
+use crate::watch_dir::{
+    self, modified_secs, scan, PublishedWatchFile, WatchActions, WatchFilter, WatchSet,
+    WatchTracker, DEFAULT_SETTLE_SECS,
+};
+use async_trait::async_trait;
+use blockstore::block::Block;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncReadExt;
+use tokio::sync::{mpsc, Mutex};
+use tracing::{info, warn};
+
+/// How long seeding waits for a bootstrap connection before publishing anyway
+pub const READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeedStatus {
+    /// Stored, registered and announced
+    Published,
+    /// Registered before with the same hash; announced again
+    Known,
+    /// Same content as a file published earlier in this run
+    Duplicate,
+    Failed,
+}
+
+impl SeedStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SeedStatus::Published => "published",
+            SeedStatus::Known => "known",
+            SeedStatus::Duplicate => "duplicate",
+            SeedStatus::Failed => "failed",
+        }
+    }
+}
+
+/// What happened to one file of a seed directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeededFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified_at: u64,
+    pub content_hash: Option<String>,
+    pub status: SeedStatus,
+    pub error: Option<String>,
+}
+
+impl SeededFile {
+    /// One row of `summary_table`
+    pub fn row(&self) -> String {
+        format!(
+            "{:<64}  {:<9}  {:>12}  {}",
+            self.content_hash.as_deref().unwrap_or("-"),
+            self.status.as_str(),
+            self.size,
+            self.path.display()
+        )
+    }
+}
+
+/// Header, one row per file with its content hash first, and the counts
+pub fn summary_table(files: &[SeededFile]) -> String {
+    let mut table = format!("{:<64}  {:<9}  {:>12}  PATH\n", "CONTENT HASH", "STATUS", "SIZE");
+    for file in files {
+        table.push_str(&file.row());
+        table.push('\n');
+    }
+    let count = |status| files.iter().filter(|f| f.status == status).count();
+    table.push_str(&format!(
+        "{} files: {} published, {} known, {} duplicate, {} failed",
+        files.len(),
+        count(SeedStatus::Published),
+        count(SeedStatus::Known),
+        count(SeedStatus::Duplicate),
+        count(SeedStatus::Failed)
+    ));
+    table
+}
+
+/// Wait until a bootstrap node is connected, or `timeout` passes; a node
+/// without bootstrap nodes is ready at once
+pub async fn wait_until_ready(dht: &DhtService, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let (connected, configured) = dht.bootstrap_connections().await;
+        if configured == 0 || connected > 0 {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+}
+
+/// Regular, non-hidden files under `dirs`, sorted
+pub fn seed_files(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let filter = WatchFilter::new(&[], &[]).expect("empty filter");
+    let mut files: Vec<PathBuf> = dirs
+        .iter()
+        .flat_map(|dir| {
+            scan(dir)
+                .into_iter()
+                .filter(|path| path.strip_prefix(dir).is_ok_and(|relative| filter.matches(relative)))
+        })
+        .collect();
+    files.sort();
+    files.dedup();
+    files
+}
+
+pub struct Seeder {
+    dht: Arc<DhtService>,
+    file_transfer: Arc<FileTransferService>,
+    registry: Arc<SharedFilesRegistry>,
+    /// Content announced in this run
+    announced: HashSet<String>,
+}
+
+impl Seeder {
+    pub fn new(
+        dht: Arc<DhtService>,
+        file_transfer: Arc<FileTransferService>,
+        registry: Arc<SharedFilesRegistry>,
+    ) -> Self {
+        Self {
+            dht,
+            file_transfer,
+            registry,
+            announced: HashSet::new(),
+        }
+    }
+
+    /// Publish every file under `dirs`
+    pub async fn seed_all(&mut self, dirs: &[PathBuf]) -> Vec<SeededFile> {
+        let dirs = dirs.to_vec();
+        let files = tokio::task::spawn_blocking(move || seed_files(&dirs))
+            .await
+            .unwrap_or_default();
+        info!("Seeding {} files", files.len());
+        let mut seeded = Vec::with_capacity(files.len());
+        for (i, path) in files.iter().enumerate() {
+            let file = self.seed(path).await;
+            match &file.error {
+                Some(error) => warn!(
+                    "[{}/{}] Failed to seed {}: {}",
+                    i + 1,
+                    files.len(),
+                    path.display(),
+                    error
+                ),
+                None => info!(
+                    "[{}/{}] {} {} ({} bytes)",
+                    i + 1,
+                    files.len(),
+                    file.status.as_str(),
+                    path.display(),
+                    file.size
+                ),
+            }
+            seeded.push(file);
+        }
+        seeded
+    }
+
+    /// Hash, store, register and announce the file at `path`
+    pub async fn seed(&mut self, path: &Path) -> SeededFile {
+        let mut file = SeededFile {
+            path: path.to_path_buf(),
+            size: 0,
+            modified_at: 0,
+            content_hash: None,
+            status: SeedStatus::Failed,
+            error: None,
+        };
+        if let Ok(meta) = tokio::fs::metadata(path).await {
+            file.size = meta.len();
+            file.modified_at = modified_secs(&meta);
+        }
+        match self.publish(path).await {
+            Ok((content_hash, status)) => {
+                file.content_hash = Some(content_hash);
+                file.status = status;
+            }
+            Err(error) => file.error = Some(error),
+        }
+        file
+    }
+
+    async fn publish(&mut self, path: &Path) -> Result<(String, SeedStatus), String> {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| format!("No file name in {}", path.display()))?;
+        let content_hash = hash_file(path).await?;
+        if self.announced.contains(&content_hash) {
+            self.add_reference(&content_hash, &file_name, path).await?;
+            return Ok((content_hash, SeedStatus::Duplicate));
+        }
+
+        let known = self
+            .registry
+            .get(&content_hash)
+            .await
+            .is_some_and(|entry| entry.references.iter().any(|p| p == path) && entry.path.exists());
+        if !known {
+            self.file_transfer
+                .store_file_copy(&content_hash, &file_name, path)
+                .await?;
+            self.add_reference(&content_hash, &file_name, path).await?;
+        }
+        let metadata = self.store_blocks(path, &content_hash, file_name).await?;
+        self.dht.publish_file(metadata, None).await?;
+        self.registry.set_announced(&content_hash, true).await;
+        self.announced.insert(content_hash.clone());
+        let status = if known { SeedStatus::Known } else { SeedStatus::Published };
+        Ok((content_hash, status))
+    }
+
+    /// Store the content in Bitswap one block at a time, as streaming uploads
+    /// from the app do, and describe it by its root block
+    async fn store_blocks(
+        &self,
+        path: &Path,
+        content_hash: &str,
+        file_name: String,
+    ) -> Result<FileMetadata, String> {
+        let chunk_size = self.dht.chunk_size();
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let mut buffer = vec![0u8; chunk_size];
+        let mut hasher = Sha256::new();
+        let mut chunk_cids = Vec::new();
+        let mut size = 0u64;
+        loop {
+            let read = read_chunk(&mut file, &mut buffer)
+                .await
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            size += read as u64;
+            for block in split_into_blocks(&buffer[..read], chunk_size) {
+                let cid = block
+                    .cid()
+                    .map_err(|e| format!("failed to get cid for block: {}", e))?;
+                self.dht.store_block(cid.clone(), block.data().to_vec()).await?;
+                chunk_cids.push(cid.to_string());
+            }
+        }
+        if format!("{:x}", hasher.finalize()) != content_hash {
+            return Err(format!("{} changed while it was being seeded", path.display()));
+        }
+
+        let root_block = serde_json::to_vec(&chunk_cids)
+            .map_err(|e| format!("Failed to serialize chunk CIDs: {}", e))?;
+        let root_cid = Cid::new_v1(RAW_CODEC, Code::Sha2_256.digest(&root_block));
+        self.dht.store_block(root_cid.clone(), root_block).await?;
+
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut metadata = self
+            .dht
+            .prepare_file_metadata(
+                content_hash.to_string(),
+                file_name,
+                size,
+                Vec::new(), // The blocks hold the data
+                created_at,
+                None,
+                None,
+                false,
+                None,
+                None,
+                0.0,
+                None,
+            )
+            .await?;
+        metadata.cids = Some(vec![root_cid]);
+        Ok(metadata)
+    }
+
+    async fn add_reference(&self, content_hash: &str, file_name: &str, path: &Path) -> Result<(), String> {
+        let stored = self.file_transfer.storage_dir().join(content_hash);
+        self.registry
+            .register(
+                content_hash.to_string(),
+                stored,
+                path.to_path_buf(),
+                file_name.to_string(),
+                None,
+            )
+            .await
+            .map(|_| ())
+    }
+
+    /// Stop announcing content published from `path`, unless another path
+    /// still references it
+    async fn withdraw(&mut self, path: &Path, content_hash: String) {
+        info!("{} left the seed directory; un-announcing {}", path.display(), content_hash);
+        match self.registry.remove_reference(&content_hash, path).await {
+            Ok(0) | Err(_) => {}
+            Ok(_) => return,
+        }
+        self.announced.remove(&content_hash);
+        self.registry.set_announced(&content_hash, false).await;
+        if let Err(e) = self.dht.stop_publishing_file(content_hash.clone()).await {
+            warn!("Failed to un-announce {}: {}", content_hash, e);
+        }
+    }
+
+    /// Publish files added to `dirs` later, once they stop changing, and
+    /// un-announce deleted ones; `seeded` is what `seed_all` returned
+    pub async fn watch(mut self, dirs: Vec<PathBuf>, seeded: &[SeededFile]) {
+        let (events_tx, events) = mpsc::unbounded_channel();
+        let watchers: Vec<_> = dirs
+            .iter()
+            .filter_map(|dir| match watch_dir::watch_recursive(dir, events_tx.clone()) {
+                Ok(watcher) => {
+                    info!("Watching {} for files to seed", dir.display());
+                    Some(watcher)
+                }
+                Err(e) => {
+                    warn!("Failed to watch {}: {}", dir.display(), e);
+                    None
+                }
+            })
+            .collect();
+        drop(events_tx);
+        if watchers.is_empty() {
+            warn!("Not watching the seed directories");
+            return;
+        }
+
+        let mut tracker = WatchTracker::new(Duration::from_secs(DEFAULT_SETTLE_SECS));
+        tracker.restore(
+            seeded
+                .iter()
+                .filter_map(|file| {
+                    Some(PublishedWatchFile {
+                        path: file.path.clone(),
+                        size: file.size,
+                        modified_at: file.modified_at,
+                        content_hash: file.content_hash.clone()?,
+                    })
+                })
+                .collect(),
+            Instant::now(),
+        );
+        let files = Mutex::new(WatchSet {
+            roots: dirs,
+            filter: WatchFilter::new(&[], &[]).expect("empty filter"),
+            tracker,
+        });
+        watch_dir::run_watch_loop(&files, events, &mut self).await;
+        drop(watchers);
+    }
+}
+
+#[async_trait]
+impl WatchActions for Seeder {
+    async fn publish(&mut self, path: &Path) -> Result<String, String> {
+        let file = self.seed(path).await;
+        match file.content_hash {
+            Some(ref content_hash) => {
+                // Same row as the startup table
+                println!("{}", file.row());
+                Ok(content_hash.clone())
+            }
+            None => {
+                let error = file.error.unwrap_or_default();
+                warn!("Failed to seed {}: {}", path.display(), error);
+                Err(error)
+            }
+        }
+    }
+
+    async fn unannounce(&mut self, path: &Path, content_hash: String) {
+        self.withdraw(path, content_hash).await;
+    }
+}
+
+/// Fill `buffer` unless the file ends first; returns the bytes read
+async fn read_chunk(file: &mut tokio::fs::File, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        let read = file.read(&mut buffer[filled..]).await?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed_files_and_summary_table() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("nested/.cache")).unwrap();
+        std::fs::write(dir.path().join("b.bin"), b"b").unwrap();
+        std::fs::write(dir.path().join("nested/a.txt"), b"a").unwrap();
+        std::fs::write(dir.path().join("nested/.cache/tmp"), b"t").unwrap();
+        std::fs::write(dir.path().join(".partial"), b"p").unwrap();
+        let files = seed_files(&[dir.path().to_path_buf(), dir.path().join("nested")]);
+        assert_eq!(files, vec![dir.path().join("b.bin"), dir.path().join("nested/a.txt")]);
+
+        let seeded = |path: &str, hash: Option<&str>, status| SeededFile {
+            path: PathBuf::from(path),
+            size: 1,
+            modified_at: 0,
+            content_hash: hash.map(str::to_string),
+            status,
+            error: None,
+        };
+        let hash = "ab".repeat(32);
+        let table = summary_table(&[
+            seeded("/seed/a.txt", Some(&hash), SeedStatus::Published),
+            seeded("/seed/copy.txt", Some(&hash), SeedStatus::Duplicate),
+            seeded("/seed/locked", None, SeedStatus::Failed),
+        ]);
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[0].starts_with("CONTENT HASH"));
+        assert!(lines[1].starts_with(&format!("{}  published", hash)));
+        assert!(lines[3].starts_with("-  "));
+        assert_eq!(lines[4], "3 files: 1 published, 0 known, 1 duplicate, 1 failed");
+    }
+
+    #[tokio::test]
+    async fn test_read_chunk_fills_the_buffer_until_the_end() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("five");
+        std::fs::write(&path, b"12345").unwrap();
+        let mut file = tokio::fs::File::open(&path).await.unwrap();
+        let mut buffer = [0u8; 2];
+        let mut chunks = Vec::new();
+        loop {
+            let read = read_chunk(&mut file, &mut buffer).await.unwrap();
+            if read == 0 {
+                break;
+            }
+            chunks.push(buffer[..read].to_vec());
+        }
+        assert_eq!(chunks, vec![b"12".to_vec(), b"34".to_vec(), b"5".to_vec()]);
+    }
+}
@@ -0,0 +1,1355 @@
+// Shared files registry
+//
+// Persistent record of everything this node is sharing. Each entry is keyed by
+// the file's SHA-256 content hash and remembers where the file lives on disk,
+// its size/mtime at publish time, whether it is announced in the DHT and how
+// much has been uploaded from it.
+//
+// Before a file is served the registry re-checks the file on disk; if it was
+// moved or modified since publishing the entry is flagged stale and serving is
+// suspended until `reverify` confirms the content still matches its hash.
+//
+// Entries are content-addressed: publishing the same content from a second
+// path only adds a reference to the existing entry, and the content stays
+// shared until its last reference is removed.
+//
+// Silent disk corruption leaves size and mtime untouched, so `verify`
+// periodically re-hashes each file chunk by chunk (IO-throttled) against the
+// chunk hashes recorded when the file was registered and at each good
+// verification since. A mismatch suspends serving and remembers the damaged
+// chunks so `repair` can re-fetch only those.
+//
+// Seeding limits (upload ratio, byte cap, time window) are checked as uploads
+// are accounted and periodically. Content over its limit declines new
+// requests; every transition is broadcast so the DHT announcement can be
+// withdrawn or restored to match.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::{broadcast, RwLock};
+use tracing::{info, warn};
+
+const REGISTRY_VERSION: u32 = 1;
+
+/// Granularity of the chunk hashes used to locate corruption
+pub const VERIFY_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Default read rate for background verification
+pub const DEFAULT_VERIFY_RATE: u64 = 32 * 1024 * 1024;
+
+/// Why an entry can currently not be served
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StaleReason {
+    /// The file no longer exists at the registered path
+    Missing,
+    /// Size on disk differs from the size at publish time
+    SizeChanged,
+    /// Modification time differs from the one at publish time
+    Modified,
+    /// Content no longer matches its hash although size and mtime are unchanged
+    Corrupted,
+}
+
+/// Which seeding limit a shared file has reached
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SeedingLimitReason {
+    Ratio,
+    UploadCap,
+    TimeWindow,
+}
+
+/// When to stop seeding a file. Every limit is optional; with none set the
+/// file is seeded forever.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SeedingLimits {
+    /// Stop after uploading this many times the file size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_ratio: Option<f64>,
+    /// Stop after uploading this many bytes of the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_upload_bytes: Option<u64>,
+    /// Stop this many seconds after the file was first shared
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_seed_secs: Option<u64>,
+}
+
+impl SeedingLimits {
+    /// The first limit `entry` has reached at `now`, if any
+    pub fn exceeded(&self, entry: &SharedFileEntry, now: u64) -> Option<SeedingLimitReason> {
+        if self.max_ratio.is_some_and(|max| entry.upload_ratio() >= max) {
+            return Some(SeedingLimitReason::Ratio);
+        }
+        if self
+            .max_upload_bytes
+            .is_some_and(|max| entry.total_bytes_uploaded >= max)
+        {
+            return Some(SeedingLimitReason::UploadCap);
+        }
+        if self
+            .max_seed_secs
+            .is_some_and(|max| now.saturating_sub(entry.shared_at) >= max)
+        {
+            return Some(SeedingLimitReason::TimeWindow);
+        }
+        None
+    }
+}
+
+/// Broadcast when a shared file reaches a seeding limit or gets headroom back
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeedingLimitChange {
+    pub content_hash: String,
+    /// The limit now reached; `None` when serving resumed
+    pub reason: Option<SeedingLimitReason>,
+    /// `Some(false)` to withdraw the DHT announcement, `Some(true)` to
+    /// restore one withdrawn for the limit
+    pub announce: Option<bool>,
+}
+
+/// Serving state of a shared file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "state", content = "reason", rename_all = "snake_case")]
+pub enum SharedFileStatus {
+    Active,
+    Stale(StaleReason),
+    /// Intact, but over its seeding limit; new requests are declined
+    LimitReached(SeedingLimitReason),
+}
+
+impl SharedFileStatus {
+    /// The stored copy no longer matches what was shared
+    pub fn is_stale(&self) -> bool {
+        matches!(self, SharedFileStatus::Stale(_))
+    }
+}
+
+/// A single shared file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedFileEntry {
+    pub content_hash: String,
+    /// Location of the stored copy that is actually served
+    pub path: PathBuf,
+    /// Paths this content was published from; all share the stored copy
+    #[serde(default)]
+    pub references: Vec<PathBuf>,
+    pub file_name: String,
+    pub size: u64,
+    /// Modification time (Unix seconds) when the file was published or last verified
+    pub modified_at: u64,
+    /// Upload protocol used when publishing (e.g. "WebRTC", "BitTorrent")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    /// Whether the provider record is currently announced in the DHT
+    pub announced: bool,
+    pub status: SharedFileStatus,
+    pub total_bytes_uploaded: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_request_at: Option<u64>,
+    pub shared_at: u64,
+    /// Unix seconds of the last completed verification
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_verified_at: Option<u64>,
+    /// Chunks (of `VERIFY_CHUNK_SIZE`) that failed the last verification
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub damaged_chunks: Vec<u32>,
+    /// Limits for this file; the global limits apply when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seeding_limits: Option<SeedingLimits>,
+    /// The DHT announcement was withdrawn because a limit was reached
+    #[serde(default)]
+    pub withdrawn_for_limit: bool,
+    /// Bytes uploaded divided by the file size, refreshed when listing
+    #[serde(default)]
+    pub ratio: f64,
+    /// Uploads currently being served from this entry (not persisted)
+    #[serde(skip)]
+    pub active_uploads: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RegistryFile {
+    version: u32,
+    entries: Vec<SharedFileEntry>,
+    #[serde(default)]
+    seeding_limits: SeedingLimits,
+    /// Content hash -> SHA-256 of each `VERIFY_CHUNK_SIZE` chunk
+    #[serde(default)]
+    chunk_hashes: HashMap<String, Vec<String>>,
+}
+
+/// Outcome of re-hashing a shared file
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationReport {
+    pub content_hash: String,
+    pub intact: bool,
+    /// Chunks to re-fetch; every chunk if no good chunk hashes were recorded yet
+    pub damaged_chunks: Vec<u32>,
+    pub chunk_size: usize,
+    pub verified_at: u64,
+}
+
+impl SharedFileEntry {
+    pub fn upload_ratio(&self) -> f64 {
+        if self.size == 0 {
+            return 0.0;
+        }
+        self.total_bytes_uploaded as f64 / self.size as f64
+    }
+}
+
+/// Move an entry between `Active` and `LimitReached` to match its limits.
+/// Stale entries are left alone.
+fn apply_seeding_limits(
+    entry: &mut SharedFileEntry,
+    global: &SeedingLimits,
+    now: u64,
+) -> Option<SeedingLimitChange> {
+    let exceeded = entry
+        .seeding_limits
+        .as_ref()
+        .unwrap_or(global)
+        .exceeded(entry, now);
+    match (&entry.status, exceeded) {
+        (SharedFileStatus::Active, Some(reason)) => {
+            info!(
+                "Shared file {} reached its seeding limit ({:?})",
+                entry.content_hash, reason
+            );
+            entry.status = SharedFileStatus::LimitReached(reason);
+            let announce = if entry.announced {
+                entry.announced = false;
+                entry.withdrawn_for_limit = true;
+                Some(false)
+            } else {
+                None
+            };
+            Some(SeedingLimitChange {
+                content_hash: entry.content_hash.clone(),
+                reason: Some(reason),
+                announce,
+            })
+        }
+        (SharedFileStatus::LimitReached(current), Some(reason)) => {
+            if *current != reason {
+                entry.status = SharedFileStatus::LimitReached(reason);
+            }
+            None
+        }
+        (SharedFileStatus::LimitReached(_), None) => {
+            info!("Shared file {} has seeding headroom again", entry.content_hash);
+            entry.status = SharedFileStatus::Active;
+            let announce = if entry.withdrawn_for_limit {
+                entry.withdrawn_for_limit = false;
+                entry.announced = true;
+                Some(true)
+            } else {
+                None
+            };
+            Some(SeedingLimitChange {
+                content_hash: entry.content_hash.clone(),
+                reason: None,
+                announce,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Reason a serve request was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServeRefusal {
+    NotShared,
+    Stale(StaleReason),
+    LimitReached(SeedingLimitReason),
+}
+
+impl std::fmt::Display for ServeRefusal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServeRefusal::NotShared => write!(f, "content is not shared"),
+            ServeRefusal::Stale(reason) => {
+                write!(f, "shared file changed on disk ({:?}); serving suspended", reason)
+            }
+            ServeRefusal::LimitReached(reason) => {
+                write!(f, "seeding limit reached ({:?}); no longer serving", reason)
+            }
+        }
+    }
+}
+
+/// Live per-entry state that outlives the entry itself while uploads finish
+#[derive(Debug, Default)]
+struct UploadTracker {
+    active: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+/// Held by the serving side for the duration of one upload
+pub struct UploadGuard {
+    tracker: Arc<UploadTracker>,
+}
+
+impl UploadGuard {
+    /// True when the file was unshared without letting in-flight uploads finish
+    pub fn is_cancelled(&self) -> bool {
+        self.tracker.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for UploadGuard {
+    fn drop(&mut self) {
+        self.tracker.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn mtime_secs(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Compare an entry against the file currently on disk
+fn check_on_disk(entry: &SharedFileEntry) -> Option<StaleReason> {
+    match std::fs::metadata(&entry.path) {
+        Err(_) => Some(StaleReason::Missing),
+        Ok(meta) if meta.len() != entry.size => Some(StaleReason::SizeChanged),
+        Ok(meta) if mtime_secs(&meta) != entry.modified_at => Some(StaleReason::Modified),
+        Ok(_) => None,
+    }
+}
+
+/// SHA-256 of a file, streamed so large files are not loaded into memory
+pub async fn hash_file(path: &Path) -> Result<String, String> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Paces sequential reads so verification does not starve interactive IO
+struct IoThrottle {
+    bytes_per_sec: u64,
+    started: Instant,
+    consumed: u64,
+}
+
+impl IoThrottle {
+    fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            started: Instant::now(),
+            consumed: 0,
+        }
+    }
+
+    async fn consume(&mut self, bytes: usize) {
+        self.consumed += bytes as u64;
+        if self.bytes_per_sec == 0 {
+            return;
+        }
+        let due = Duration::from_secs_f64(self.consumed as f64 / self.bytes_per_sec as f64);
+        let elapsed = self.started.elapsed();
+        if due > elapsed {
+            tokio::time::sleep(due - elapsed).await;
+        }
+    }
+}
+
+/// SHA-256 of the whole file and of each `chunk_size` chunk, read at most
+/// `bytes_per_sec` (0 = unthrottled)
+async fn hash_file_chunks(
+    path: &Path,
+    chunk_size: usize,
+    bytes_per_sec: u64,
+) -> Result<(String, Vec<String>), String> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut throttle = IoThrottle::new(bytes_per_sec);
+    let mut hasher = Sha256::new();
+    let mut chunk_hashes = Vec::new();
+    let mut buffer = vec![0u8; chunk_size];
+    loop {
+        let mut filled = 0;
+        while filled < chunk_size {
+            let read = file
+                .read(&mut buffer[filled..])
+                .await
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+        }
+        if filled == 0 {
+            break;
+        }
+        hasher.update(&buffer[..filled]);
+        chunk_hashes.push(format!("{:x}", Sha256::digest(&buffer[..filled])));
+        throttle.consume(filled).await;
+        if filled < chunk_size {
+            break;
+        }
+    }
+    Ok((format!("{:x}", hasher.finalize()), chunk_hashes))
+}
+
+/// Persistent registry of shared files
+pub struct SharedFilesRegistry {
+    registry_path: PathBuf,
+    entries: RwLock<HashMap<String, SharedFileEntry>>,
+    chunk_hashes: RwLock<HashMap<String, Vec<String>>>,
+    trackers: RwLock<HashMap<String, Arc<UploadTracker>>>,
+    /// Read rate for `verify`, bytes per second
+    verify_rate: AtomicU64,
+    /// Limits for files without their own
+    global_limits: RwLock<SeedingLimits>,
+    seeding_tx: broadcast::Sender<SeedingLimitChange>,
+}
+
+impl SharedFilesRegistry {
+    /// Load the registry from `registry_path`, starting empty if it does not exist
+    pub fn load(registry_path: PathBuf) -> Self {
+        let (entries, chunk_hashes, global_limits) = match std::fs::read(&registry_path) {
+            Ok(bytes) => match serde_json::from_slice::<RegistryFile>(&bytes) {
+                Ok(file) if file.version == REGISTRY_VERSION => (
+                    file.entries
+                        .into_iter()
+                        .map(|e| (e.content_hash.clone(), e))
+                        .collect(),
+                    file.chunk_hashes,
+                    file.seeding_limits,
+                ),
+                Ok(file) => {
+                    warn!(
+                        "Ignoring shared files registry with unsupported version {}",
+                        file.version
+                    );
+                    Default::default()
+                }
+                Err(e) => {
+                    warn!("Shared files registry is corrupted, starting empty: {}", e);
+                    Default::default()
+                }
+            },
+            Err(_) => Default::default(),
+        };
+
+        Self {
+            registry_path,
+            entries: RwLock::new(entries),
+            chunk_hashes: RwLock::new(chunk_hashes),
+            trackers: RwLock::new(HashMap::new()),
+            verify_rate: AtomicU64::new(DEFAULT_VERIFY_RATE),
+            global_limits: RwLock::new(global_limits),
+            seeding_tx: broadcast::channel(64).0,
+        }
+    }
+
+    /// Seeding limit transitions, for keeping the DHT announcement in sync
+    pub fn subscribe_seeding_changes(&self) -> broadcast::Receiver<SeedingLimitChange> {
+        self.seeding_tx.subscribe()
+    }
+
+    fn broadcast_seeding_changes(&self, changes: Vec<SeedingLimitChange>) {
+        for change in changes {
+            // No receivers just means nobody manages announcements (e.g. in tests)
+            let _ = self.seeding_tx.send(change);
+        }
+    }
+
+    /// Re-check entries (all, or only `content_hash`) against their limits
+    async fn enforce_limits(&self, content_hash: Option<&str>) -> Vec<SeedingLimitChange> {
+        let global = self.global_limits.read().await.clone();
+        let now = now_secs();
+        let mut entries = self.entries.write().await;
+        entries
+            .values_mut()
+            .filter(|e| match content_hash {
+                Some(hash) => e.content_hash == hash,
+                None => true,
+            })
+            .filter_map(|e| apply_seeding_limits(e, &global, now))
+            .collect()
+    }
+
+    /// Apply limit transitions that are due, e.g. time windows that ran out
+    pub async fn enforce_seeding_limits(&self) {
+        let changes = self.enforce_limits(None).await;
+        if !changes.is_empty() {
+            if let Err(e) = self.persist().await {
+                warn!("{}", e);
+            }
+            self.broadcast_seeding_changes(changes);
+        }
+    }
+
+    pub async fn seeding_limits(&self) -> SeedingLimits {
+        self.global_limits.read().await.clone()
+    }
+
+    /// Replace the limits for files without their own; serving resumes right
+    /// away for files that have headroom again
+    pub async fn set_seeding_limits(&self, limits: SeedingLimits) -> Result<(), String> {
+        *self.global_limits.write().await = limits;
+        let changes = self.enforce_limits(None).await;
+        self.persist().await?;
+        self.broadcast_seeding_changes(changes);
+        Ok(())
+    }
+
+    /// Give one file its own limits, or `None` to fall back to the global ones
+    pub async fn set_content_seeding_limits(
+        &self,
+        content_hash: &str,
+        limits: Option<SeedingLimits>,
+    ) -> Result<SharedFileEntry, String> {
+        {
+            let mut entries = self.entries.write().await;
+            let entry = entries
+                .get_mut(content_hash)
+                .ok_or_else(|| format!("File {} is not shared", content_hash))?;
+            entry.seeding_limits = limits;
+        }
+        let changes = self.enforce_limits(Some(content_hash)).await;
+        self.persist().await?;
+        self.broadcast_seeding_changes(changes);
+        self.get(content_hash)
+            .await
+            .ok_or_else(|| format!("File {} is not shared", content_hash))
+    }
+
+    /// Limit how fast `verify` reads from disk; 0 removes the limit
+    pub fn set_verify_rate(&self, bytes_per_sec: u64) {
+        self.verify_rate.store(bytes_per_sec, Ordering::Relaxed);
+    }
+
+    /// Default location inside the application data directory
+    pub fn default_path() -> PathBuf {
+        crate::data_dirs::DataDirs::current().shared_files()
+    }
+
+    async fn persist(&self) -> Result<(), String> {
+        let snapshot = {
+            let entries = self.entries.read().await;
+            RegistryFile {
+                version: REGISTRY_VERSION,
+                entries: entries.values().cloned().collect(),
+                chunk_hashes: self.chunk_hashes.read().await.clone(),
+                seeding_limits: self.global_limits.read().await.clone(),
+            }
+        };
+        let json = serde_json::to_vec_pretty(&snapshot)
+            .map_err(|e| format!("Failed to serialize shared files registry: {}", e))?;
+
+        if let Some(parent) = self.registry_path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("Failed to create registry directory: {}", e))?;
+        }
+        // Atomic write: temp file then rename
+        let tmp_path = self.registry_path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, json)
+            .await
+            .map_err(|e| format!("Failed to write shared files registry: {}", e))?;
+        tokio::fs::rename(&tmp_path, &self.registry_path)
+            .await
+            .map_err(|e| format!("Failed to replace shared files registry: {}", e))
+    }
+
+    /// Whether content with this hash is already shared
+    pub async fn contains(&self, content_hash: &str) -> bool {
+        self.entries.read().await.contains_key(content_hash)
+    }
+
+    /// Record a newly shared file stored at `path`, published from `source`.
+    ///
+    /// If the content is already shared, `source` is added as another reference
+    /// to the existing entry.
+    pub async fn register(
+        &self,
+        content_hash: String,
+        path: PathBuf,
+        source: PathBuf,
+        file_name: String,
+        protocol: Option<String>,
+    ) -> Result<SharedFileEntry, String> {
+        let meta = tokio::fs::metadata(&path)
+            .await
+            .map_err(|e| format!("Failed to stat shared file: {}", e))?;
+        // Chunk hashes from publish time, so corruption found by the first
+        // `verify` can already be repaired chunk by chunk
+        if !self.chunk_hashes.read().await.contains_key(&content_hash) {
+            let (actual, chunks) = hash_file_chunks(&path, VERIFY_CHUNK_SIZE, 0).await?;
+            if actual == content_hash {
+                self.chunk_hashes
+                    .write()
+                    .await
+                    .insert(content_hash.clone(), chunks);
+            } else {
+                warn!(
+                    "{} does not match content hash {}; no chunk hashes recorded",
+                    path.display(),
+                    content_hash
+                );
+            }
+        }
+
+        let entry = {
+            let mut entries = self.entries.write().await;
+            let previous = entries.remove(&content_hash);
+            let mut references = previous
+                .as_ref()
+                .map(|p| p.references.clone())
+                .unwrap_or_default();
+            if !references.contains(&source) {
+                references.push(source);
+            }
+            let entry = SharedFileEntry {
+                content_hash: content_hash.clone(),
+                path,
+                references,
+                file_name,
+                size: meta.len(),
+                modified_at: mtime_secs(&meta),
+                protocol,
+                announced: previous.as_ref().map(|p| p.announced).unwrap_or(false),
+                status: SharedFileStatus::Active,
+                total_bytes_uploaded: previous
+                    .as_ref()
+                    .map(|p| p.total_bytes_uploaded)
+                    .unwrap_or(0),
+                last_request_at: previous.as_ref().and_then(|p| p.last_request_at),
+                shared_at: previous
+                    .as_ref()
+                    .map(|p| p.shared_at)
+                    .unwrap_or_else(now_secs),
+                last_verified_at: previous.as_ref().and_then(|p| p.last_verified_at),
+                damaged_chunks: Vec::new(),
+                seeding_limits: previous.as_ref().and_then(|p| p.seeding_limits.clone()),
+                withdrawn_for_limit: previous.map(|p| p.withdrawn_for_limit).unwrap_or(false),
+                ratio: 0.0,
+                active_uploads: 0,
+            };
+            entries.insert(content_hash.clone(), entry.clone());
+            entry
+        };
+
+        let changes = self.enforce_limits(Some(&content_hash)).await;
+        self.persist().await?;
+        self.broadcast_seeding_changes(changes);
+        info!("Registered shared file {} ({})", entry.file_name, content_hash);
+        Ok(self.get(&content_hash).await.unwrap_or(entry))
+    }
+
+    /// Update whether the file is announced in the DHT
+    pub async fn set_announced(&self, content_hash: &str, announced: bool) {
+        let changed = {
+            let mut entries = self.entries.write().await;
+            match entries.get_mut(content_hash) {
+                Some(entry) if entry.announced != announced => {
+                    entry.announced = announced;
+                    true
+                }
+                _ => false,
+            }
+        };
+        if changed {
+            if let Err(e) = self.persist().await {
+                warn!("{}", e);
+            }
+        }
+    }
+
+    /// List all shared files, re-checking each against the file on disk and
+    /// its seeding limits
+    pub async fn list(&self) -> Vec<SharedFileEntry> {
+        let limit_changes = self.enforce_limits(None).await;
+        let mut changed = !limit_changes.is_empty();
+        let mut listing = {
+            let mut entries = self.entries.write().await;
+            for entry in entries.values_mut() {
+                entry.ratio = entry.upload_ratio();
+                if let Some(reason) = check_on_disk(entry) {
+                    let status = SharedFileStatus::Stale(reason);
+                    if entry.status != status {
+                        warn!(
+                            "Shared file {} is stale ({:?}); serving suspended",
+                            entry.content_hash, status
+                        );
+                        entry.status = status;
+                        changed = true;
+                    }
+                }
+            }
+            entries.values().cloned().collect::<Vec<_>>()
+        };
+
+        if changed {
+            if let Err(e) = self.persist().await {
+                warn!("{}", e);
+            }
+        }
+        self.broadcast_seeding_changes(limit_changes);
+
+        let trackers = self.trackers.read().await;
+        for entry in listing.iter_mut() {
+            entry.active_uploads = trackers
+                .get(&entry.content_hash)
+                .map(|t| t.active.load(Ordering::SeqCst))
+                .unwrap_or(0);
+        }
+        listing.sort_by(|a, b| b.shared_at.cmp(&a.shared_at));
+        listing
+    }
+
+    pub async fn get(&self, content_hash: &str) -> Option<SharedFileEntry> {
+        self.entries.read().await.get(content_hash).cloned()
+    }
+
+    /// Start serving an upload. Fails if the content is not shared, is stale
+    /// or has reached its seeding limit.
+    pub async fn begin_upload(&self, content_hash: &str) -> Result<UploadGuard, ServeRefusal> {
+        let limit_changes = self.enforce_limits(Some(content_hash)).await;
+        if !limit_changes.is_empty() {
+            if let Err(e) = self.persist().await {
+                warn!("{}", e);
+            }
+            self.broadcast_seeding_changes(limit_changes);
+        }
+        {
+            let mut entries = self.entries.write().await;
+            let entry = entries
+                .get_mut(content_hash)
+                .ok_or(ServeRefusal::NotShared)?;
+            match &entry.status {
+                SharedFileStatus::Stale(reason) => return Err(ServeRefusal::Stale(reason.clone())),
+                SharedFileStatus::LimitReached(reason) => {
+                    return Err(ServeRefusal::LimitReached(*reason))
+                }
+                SharedFileStatus::Active => {}
+            }
+            if let Some(reason) = check_on_disk(entry) {
+                warn!(
+                    "Shared file {} changed on disk ({:?}); serving suspended",
+                    content_hash, reason
+                );
+                entry.status = SharedFileStatus::Stale(reason.clone());
+                drop(entries);
+                if let Err(e) = self.persist().await {
+                    warn!("{}", e);
+                }
+                return Err(ServeRefusal::Stale(reason));
+            }
+        }
+
+        let tracker = {
+            let mut trackers = self.trackers.write().await;
+            trackers
+                .entry(content_hash.to_string())
+                .or_insert_with(|| Arc::new(UploadTracker::default()))
+                .clone()
+        };
+        tracker.active.fetch_add(1, Ordering::SeqCst);
+        Ok(UploadGuard { tracker })
+    }
+
+    /// Account bytes served for a shared file
+    pub async fn record_upload(&self, content_hash: &str, bytes: u64) {
+        let updated = {
+            let mut entries = self.entries.write().await;
+            match entries.get_mut(content_hash) {
+                Some(entry) => {
+                    entry.total_bytes_uploaded = entry.total_bytes_uploaded.saturating_add(bytes);
+                    entry.last_request_at = Some(now_secs());
+                    true
+                }
+                None => false,
+            }
+        };
+        if updated {
+            let changes = self.enforce_limits(Some(content_hash)).await;
+            if let Err(e) = self.persist().await {
+                warn!("{}", e);
+            }
+            self.broadcast_seeding_changes(changes);
+        }
+    }
+
+    /// Drop one path reference to shared content, returning how many remain.
+    ///
+    /// The entry itself is kept; callers unshare the content once the count
+    /// reaches zero.
+    pub async fn remove_reference(&self, content_hash: &str, source: &Path) -> Result<usize, String> {
+        let remaining = {
+            let mut entries = self.entries.write().await;
+            let entry = entries
+                .get_mut(content_hash)
+                .ok_or_else(|| format!("File {} is not shared", content_hash))?;
+            let before = entry.references.len();
+            entry.references.retain(|p| p != source);
+            if entry.references.len() == before {
+                return Err(format!(
+                    "{} is not a reference of {}",
+                    source.display(),
+                    content_hash
+                ));
+            }
+            entry.references.len()
+        };
+        self.persist().await?;
+        Ok(remaining)
+    }
+
+    /// Point a path reference of shared content somewhere else, e.g. from a
+    /// staging copy to the file it was copied from
+    pub async fn replace_reference(&self, content_hash: &str, from: &Path, to: PathBuf) -> Result<(), String> {
+        {
+            let mut entries = self.entries.write().await;
+            let entry = entries
+                .get_mut(content_hash)
+                .ok_or_else(|| format!("File {} is not shared", content_hash))?;
+            entry.references.retain(|p| p != from && *p != to);
+            entry.references.push(to);
+        }
+        self.persist().await
+    }
+
+    /// Bytes saved by storing content referenced from several paths only once
+    pub async fn dedup_savings(&self) -> u64 {
+        self.entries
+            .read()
+            .await
+            .values()
+            .map(|e| e.size * e.references.len().saturating_sub(1) as u64)
+            .sum()
+    }
+
+    /// Complete a download from local data if we already hold `content_hash`.
+    ///
+    /// The stored copy is re-hashed before it is copied to `dest`. Returns the
+    /// number of bytes copied, or `None` if the content is not available locally.
+    pub async fn copy_local(&self, content_hash: &str, dest: &Path) -> Result<Option<u64>, String> {
+        let Some(entry) = self.get(content_hash).await else {
+            return Ok(None);
+        };
+        if entry.status.is_stale() {
+            return Ok(None);
+        }
+        match hash_file(&entry.path).await {
+            Ok(actual) if actual == entry.content_hash => {}
+            _ => {
+                warn!(
+                    "Local copy of {} failed verification; falling back to network",
+                    content_hash
+                );
+                return Ok(None);
+            }
+        }
+
+        if entry.path == dest {
+            return Ok(Some(entry.size));
+        }
+        let copied = tokio::fs::copy(&entry.path, dest)
+            .await
+            .map_err(|e| format!("Failed to copy local content to {}: {}", dest.display(), e))?;
+        info!("Completed {} from local data ({} bytes)", content_hash, copied);
+        Ok(Some(copied))
+    }
+
+    /// Remove a file from the registry.
+    ///
+    /// With `finish_in_flight` the uploads already being served run to
+    /// completion; otherwise they are told to abort.
+    pub async fn unshare(
+        &self,
+        content_hash: &str,
+        finish_in_flight: bool,
+    ) -> Result<SharedFileEntry, String> {
+        let removed = self
+            .entries
+            .write()
+            .await
+            .remove(content_hash)
+            .ok_or_else(|| format!("File {} is not shared", content_hash))?;
+        self.chunk_hashes.write().await.remove(content_hash);
+
+        if let Some(tracker) = self.trackers.write().await.remove(content_hash) {
+            if !finish_in_flight {
+                tracker.cancelled.store(true, Ordering::SeqCst);
+            }
+        }
+
+        self.persist().await?;
+        info!("Unshared file {} ({})", removed.file_name, content_hash);
+        Ok(removed)
+    }
+
+    /// Re-hash a stale file and resume serving if it still matches its content hash
+    pub async fn reverify(&self, content_hash: &str) -> Result<SharedFileEntry, String> {
+        let entry = self
+            .get(content_hash)
+            .await
+            .ok_or_else(|| format!("File {} is not shared", content_hash))?;
+
+        let actual = hash_file(&entry.path).await?;
+        if actual != entry.content_hash {
+            return Err(format!(
+                "File at {} no longer matches its content hash (now {})",
+                entry.path.display(),
+                actual
+            ));
+        }
+
+        let meta = tokio::fs::metadata(&entry.path)
+            .await
+            .map_err(|e| format!("Failed to stat shared file: {}", e))?;
+        let updated = {
+            let mut entries = self.entries.write().await;
+            let entry = entries
+                .get_mut(content_hash)
+                .ok_or_else(|| format!("File {} is not shared", content_hash))?;
+            entry.size = meta.len();
+            entry.modified_at = mtime_secs(&meta);
+            entry.status = SharedFileStatus::Active;
+            entry.last_verified_at = Some(now_secs());
+            entry.damaged_chunks.clear();
+            entry.clone()
+        };
+        self.persist().await?;
+        info!("Re-verified shared file {}", content_hash);
+        Ok(updated)
+    }
+
+    /// Re-hash a shared file chunk by chunk, throttled to the verify rate.
+    ///
+    /// On a mismatch serving is suspended and the damaged chunks are recorded
+    /// for `repair`. Files that were moved or visibly modified are flagged
+    /// stale as usual and reported as an error instead.
+    pub async fn verify(&self, content_hash: &str) -> Result<VerificationReport, String> {
+        let entry = self
+            .get(content_hash)
+            .await
+            .ok_or_else(|| format!("File {} is not shared", content_hash))?;
+        if let Some(reason) = check_on_disk(&entry) {
+            if entry.status != SharedFileStatus::Stale(reason.clone()) {
+                if let Some(e) = self.entries.write().await.get_mut(content_hash) {
+                    e.status = SharedFileStatus::Stale(reason.clone());
+                }
+                self.persist().await?;
+            }
+            return Err(format!(
+                "Shared file {} changed on disk ({:?}); reverify it instead",
+                content_hash, reason
+            ));
+        }
+
+        let (actual, chunks) = hash_file_chunks(
+            &entry.path,
+            VERIFY_CHUNK_SIZE,
+            self.verify_rate.load(Ordering::Relaxed),
+        )
+        .await?;
+        let intact = actual == entry.content_hash;
+        let known = self.chunk_hashes.read().await.get(content_hash).cloned();
+        let damaged_chunks: Vec<u32> = match (intact, known) {
+            (true, _) => Vec::new(),
+            (false, Some(known)) => chunks
+                .iter()
+                .enumerate()
+                .filter(|(i, hash)| known.get(*i) != Some(*hash))
+                .map(|(i, _)| i as u32)
+                .collect(),
+            (false, None) => (0..chunks.len() as u32).collect(),
+        };
+        if intact {
+            self.chunk_hashes
+                .write()
+                .await
+                .insert(content_hash.to_string(), chunks);
+        }
+
+        let verified_at = now_secs();
+        {
+            let mut entries = self.entries.write().await;
+            let entry = entries
+                .get_mut(content_hash)
+                .ok_or_else(|| format!("File {} is not shared", content_hash))?;
+            entry.last_verified_at = Some(verified_at);
+            if intact {
+                if entry.status == SharedFileStatus::Stale(StaleReason::Corrupted) {
+                    entry.status = SharedFileStatus::Active;
+                }
+                entry.damaged_chunks.clear();
+            } else {
+                warn!(
+                    "Shared file {} is corrupted ({} damaged chunk(s)); serving suspended",
+                    content_hash,
+                    damaged_chunks.len()
+                );
+                entry.status = SharedFileStatus::Stale(StaleReason::Corrupted);
+                entry.damaged_chunks = damaged_chunks.clone();
+            }
+        }
+        self.persist().await?;
+
+        Ok(VerificationReport {
+            content_hash: content_hash.to_string(),
+            intact,
+            damaged_chunks,
+            chunk_size: VERIFY_CHUNK_SIZE,
+            verified_at,
+        })
+    }
+
+    /// Patch the damaged chunks of a corrupted file with data from `fetch`
+    /// (called with offset and length) and resume serving once the whole
+    /// file matches its content hash again.
+    pub async fn repair<F, Fut>(&self, content_hash: &str, fetch: F) -> Result<SharedFileEntry, String>
+    where
+        F: Fn(u64, u64) -> Fut,
+        Fut: Future<Output = Result<Vec<u8>, String>>,
+    {
+        let entry = self
+            .get(content_hash)
+            .await
+            .ok_or_else(|| format!("File {} is not shared", content_hash))?;
+        if entry.status != SharedFileStatus::Stale(StaleReason::Corrupted) {
+            return Err(format!(
+                "File {} is not marked corrupted; verify it first",
+                content_hash
+            ));
+        }
+        let known = self.chunk_hashes.read().await.get(content_hash).cloned();
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(&entry.path)
+            .await
+            .map_err(|e| format!("Failed to open {}: {}", entry.path.display(), e))?;
+        for &index in &entry.damaged_chunks {
+            let offset = index as u64 * VERIFY_CHUNK_SIZE as u64;
+            let len = (VERIFY_CHUNK_SIZE as u64).min(entry.size.saturating_sub(offset));
+            if len == 0 {
+                continue;
+            }
+            let data = fetch(offset, len).await?;
+            if data.len() as u64 != len {
+                return Err(format!(
+                    "Chunk {} came back with {} bytes, expected {}",
+                    index,
+                    data.len(),
+                    len
+                ));
+            }
+            if let Some(expected) = known.as_ref().and_then(|k| k.get(index as usize)) {
+                if format!("{:x}", Sha256::digest(&data)) != *expected {
+                    return Err(format!("Chunk {} from the network failed verification", index));
+                }
+            }
+            file.seek(std::io::SeekFrom::Start(offset))
+                .await
+                .map_err(|e| format!("Failed to seek {}: {}", entry.path.display(), e))?;
+            file.write_all(&data)
+                .await
+                .map_err(|e| format!("Failed to patch {}: {}", entry.path.display(), e))?;
+        }
+        file.sync_all()
+            .await
+            .map_err(|e| format!("Failed to sync {}: {}", entry.path.display(), e))?;
+        drop(file);
+
+        info!(
+            "Patched {} chunk(s) of {}",
+            entry.damaged_chunks.len(),
+            content_hash
+        );
+        self.reverify(content_hash).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn share(dir: &TempDir, registry: &SharedFilesRegistry, contents: &[u8]) -> String {
+        let path = dir.path().join("shared.bin");
+        tokio::fs::write(&path, contents).await.unwrap();
+        let hash = hash_file(&path).await.unwrap();
+        registry
+            .register(hash.clone(), path.clone(), path, "shared.bin".to_string(), None)
+            .await
+            .unwrap();
+        hash
+    }
+
+    #[tokio::test]
+    async fn test_register_and_persist() {
+        let dir = TempDir::new().unwrap();
+        let registry_path = dir.path().join("registry.json");
+        let registry = SharedFilesRegistry::load(registry_path.clone());
+        let hash = share(&dir, &registry, b"hello world").await;
+        registry.set_announced(&hash, true).await;
+        registry.record_upload(&hash, 11).await;
+
+        let reloaded = SharedFilesRegistry::load(registry_path);
+        let entry = reloaded.get(&hash).await.unwrap();
+        assert!(entry.announced);
+        assert_eq!(entry.size, 11);
+        assert_eq!(entry.total_bytes_uploaded, 11);
+        assert!(entry.last_request_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_modified_file_is_flagged_stale_until_reverified() {
+        let dir = TempDir::new().unwrap();
+        let registry = SharedFilesRegistry::load(dir.path().join("registry.json"));
+        let hash = share(&dir, &registry, b"original").await;
+        assert!(registry.begin_upload(&hash).await.is_ok());
+
+        tokio::fs::write(dir.path().join("shared.bin"), b"changed!!")
+            .await
+            .unwrap();
+        let listing = registry.list().await;
+        assert_eq!(
+            listing[0].status,
+            SharedFileStatus::Stale(StaleReason::SizeChanged)
+        );
+        assert!(matches!(
+            registry.begin_upload(&hash).await,
+            Err(ServeRefusal::Stale(_))
+        ));
+        assert!(registry.reverify(&hash).await.is_err());
+
+        tokio::fs::write(dir.path().join("shared.bin"), b"original")
+            .await
+            .unwrap();
+        let entry = registry.reverify(&hash).await.unwrap();
+        assert_eq!(entry.status, SharedFileStatus::Active);
+        assert!(registry.begin_upload(&hash).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_unshare_refuses_new_uploads() {
+        let dir = TempDir::new().unwrap();
+        let registry = SharedFilesRegistry::load(dir.path().join("registry.json"));
+        let hash = share(&dir, &registry, b"data").await;
+
+        let finishing = registry.begin_upload(&hash).await.unwrap();
+        registry.unshare(&hash, true).await.unwrap();
+        assert!(!finishing.is_cancelled());
+        assert_eq!(
+            registry.begin_upload(&hash).await.err(),
+            Some(ServeRefusal::NotShared)
+        );
+        assert!(registry.list().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unshare_without_finishing_cancels_uploads() {
+        let dir = TempDir::new().unwrap();
+        let registry = SharedFilesRegistry::load(dir.path().join("registry.json"));
+        let hash = share(&dir, &registry, b"data").await;
+
+        let in_flight = registry.begin_upload(&hash).await.unwrap();
+        registry.unshare(&hash, false).await.unwrap();
+        assert!(in_flight.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_same_content_from_two_paths_is_deduplicated() {
+        let dir = TempDir::new().unwrap();
+        let registry = SharedFilesRegistry::load(dir.path().join("registry.json"));
+        let hash = share(&dir, &registry, b"same bytes").await;
+        let stored = dir.path().join("shared.bin");
+        let second = dir.path().join("elsewhere/copy.bin");
+        registry
+            .register(hash.clone(), stored.clone(), second.clone(), "copy.bin".to_string(), None)
+            .await
+            .unwrap();
+
+        let listing = registry.list().await;
+        assert_eq!(listing.len(), 1);
+        assert_eq!(listing[0].references.len(), 2);
+        assert_eq!(registry.dedup_savings().await, 10);
+
+        // Removing one reference keeps the content shared
+        assert_eq!(registry.remove_reference(&hash, &second).await.unwrap(), 1);
+        assert!(registry.begin_upload(&hash).await.is_ok());
+        assert_eq!(registry.remove_reference(&hash, &stored).await.unwrap(), 0);
+        assert!(registry.remove_reference(&hash, &stored).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_corruption_is_located_and_repaired() {
+        let dir = TempDir::new().unwrap();
+        let registry = SharedFilesRegistry::load(dir.path().join("registry.json"));
+        registry.set_verify_rate(0);
+        let original: Vec<u8> = (0..VERIFY_CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+        let hash = share(&dir, &registry, &original).await;
+        assert!(registry.verify(&hash).await.unwrap().intact);
+
+        // Flip a byte in the second chunk without changing size or mtime
+        let path = dir.path().join("shared.bin");
+        let mtime = std::fs::metadata(&path).unwrap().modified().unwrap();
+        let mut damaged = original.clone();
+        damaged[VERIFY_CHUNK_SIZE + 1] ^= 0xff;
+        std::fs::write(&path, &damaged).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+
+        let report = registry.verify(&hash).await.unwrap();
+        assert!(!report.intact);
+        assert_eq!(report.damaged_chunks, vec![1]);
+        assert_eq!(
+            registry.begin_upload(&hash).await.err(),
+            Some(ServeRefusal::Stale(StaleReason::Corrupted))
+        );
+
+        let source = original.clone();
+        let entry = registry
+            .repair(&hash, |offset, len| {
+                let data = source[offset as usize..(offset + len) as usize].to_vec();
+                async move { Ok(data) }
+            })
+            .await
+            .unwrap();
+        assert_eq!(entry.status, SharedFileStatus::Active);
+        assert!(entry.damaged_chunks.is_empty());
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+    }
+
+    #[tokio::test]
+    async fn test_corruption_before_the_first_check_is_repaired_per_chunk() {
+        let dir = TempDir::new().unwrap();
+        let registry = SharedFilesRegistry::load(dir.path().join("registry.json"));
+        registry.set_verify_rate(0);
+        let original: Vec<u8> = (0..VERIFY_CHUNK_SIZE * 3).map(|i| (i / 7) as u8).collect();
+        let hash = share(&dir, &registry, &original).await;
+
+        let path = dir.path().join("shared.bin");
+        let mtime = std::fs::metadata(&path).unwrap().modified().unwrap();
+        let mut damaged = original.clone();
+        damaged[2 * VERIFY_CHUNK_SIZE + 5] ^= 0xff;
+        std::fs::write(&path, &damaged).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+
+        // Never verified while intact, yet only the damaged chunk is refetched
+        let report = registry.verify(&hash).await.unwrap();
+        assert_eq!(report.damaged_chunks, vec![2]);
+        let fetched = std::sync::Mutex::new(Vec::new());
+        let entry = registry
+            .repair(&hash, |offset, len| {
+                fetched.lock().unwrap().push(offset);
+                let data = original[offset as usize..(offset + len) as usize].to_vec();
+                async move { Ok(data) }
+            })
+            .await
+            .unwrap();
+        assert_eq!(entry.status, SharedFileStatus::Active);
+        assert_eq!(*fetched.lock().unwrap(), vec![2 * VERIFY_CHUNK_SIZE as u64]);
+    }
+
+    #[tokio::test]
+    async fn test_seeding_limits_stop_and_resume_serving() {
+        let dir = TempDir::new().unwrap();
+        let registry = SharedFilesRegistry::load(dir.path().join("registry.json"));
+        let mut changes = registry.subscribe_seeding_changes();
+        let hash = share(&dir, &registry, b"0123456789").await;
+        registry.set_announced(&hash, true).await;
+        registry
+            .set_seeding_limits(SeedingLimits {
+                max_ratio: Some(2.0),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        registry.record_upload(&hash, 15).await;
+        assert!(registry.begin_upload(&hash).await.is_ok());
+        registry.record_upload(&hash, 5).await;
+        assert_eq!(
+            registry.begin_upload(&hash).await.err(),
+            Some(ServeRefusal::LimitReached(SeedingLimitReason::Ratio))
+        );
+        let reached = changes.try_recv().unwrap();
+        assert_eq!(reached.reason, Some(SeedingLimitReason::Ratio));
+        assert_eq!(reached.announce, Some(false));
+        let listing = registry.list().await;
+        assert_eq!(listing[0].ratio, 2.0);
+        assert!(!listing[0].announced);
+
+        // A per-file byte cap with headroom overrides the global ratio
+        let entry = registry
+            .set_content_seeding_limits(
+                &hash,
+                Some(SeedingLimits {
+                    max_upload_bytes: Some(100),
+                    ..Default::default()
+                }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(entry.status, SharedFileStatus::Active);
+        assert!(entry.announced);
+        assert_eq!(changes.try_recv().unwrap().announce, Some(true));
+        assert!(registry.begin_upload(&hash).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_copy_local_verifies_content() {
+        let dir = TempDir::new().unwrap();
+        let registry = SharedFilesRegistry::load(dir.path().join("registry.json"));
+        let hash = share(&dir, &registry, b"local data").await;
+
+        let dest = dir.path().join("download.bin");
+        assert_eq!(registry.copy_local(&hash, &dest).await.unwrap(), Some(10));
+        assert_eq!(tokio::fs::read(&dest).await.unwrap(), b"local data");
+        assert_eq!(registry.copy_local("unknown", &dest).await.unwrap(), None);
+    }
+}
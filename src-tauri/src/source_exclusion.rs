@@ -0,0 +1,149 @@
+//! Session-wide exclusion of providers that serve corrupt data.
+//!
+//! A chunk that fails hash verification is normally just retried, which can
+//! mean asking the same bad provider again and again. Failures are counted per
+//! (provider, content hash); once a provider reaches the threshold it is left
+//! out of the source set for that content until the app restarts or the
+//! exclusions are cleared (e.g. because the corruption was on our own disk).
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Failed verifications after which a provider is excluded for the content
+pub const CORRUPTION_THRESHOLD: u32 = 3;
+
+/// A provider left out of the source set for one piece of content
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExcludedSource {
+    pub source_id: String,
+    pub content_hash: String,
+    /// The last verification error seen from the provider
+    pub reason: String,
+    pub failures: u32,
+    /// Unix seconds
+    pub excluded_at: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct SourceExclusions {
+    /// (source id, content hash) -> failed verifications so far
+    failures: HashMap<(String, String), u32>,
+    excluded: HashMap<(String, String), ExcludedSource>,
+}
+
+impl SourceExclusions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a failed verification; returns the exclusion when this failure
+    /// is the one that crosses the threshold
+    pub fn record_failure(
+        &mut self,
+        source_id: &str,
+        content_hash: &str,
+        reason: &str,
+    ) -> Option<ExcludedSource> {
+        let key = (source_id.to_string(), content_hash.to_string());
+        if let Some(excluded) = self.excluded.get_mut(&key) {
+            excluded.failures += 1;
+            excluded.reason = reason.to_string();
+            return None;
+        }
+
+        let failures = self.failures.entry(key.clone()).or_insert(0);
+        *failures += 1;
+        if *failures < CORRUPTION_THRESHOLD {
+            return None;
+        }
+
+        let excluded = ExcludedSource {
+            source_id: source_id.to_string(),
+            content_hash: content_hash.to_string(),
+            reason: reason.to_string(),
+            failures: *failures,
+            excluded_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+        self.failures.remove(&key);
+        self.excluded.insert(key, excluded.clone());
+        Some(excluded)
+    }
+
+    pub fn is_excluded(&self, source_id: &str, content_hash: &str) -> bool {
+        self.excluded
+            .contains_key(&(source_id.to_string(), content_hash.to_string()))
+    }
+
+    /// Providers excluded for `content_hash`, oldest exclusion first
+    pub fn excluded_for(&self, content_hash: &str) -> Vec<ExcludedSource> {
+        let mut excluded: Vec<ExcludedSource> = self
+            .excluded
+            .values()
+            .filter(|e| e.content_hash == content_hash)
+            .cloned()
+            .collect();
+        excluded.sort_by(|a, b| {
+            a.excluded_at
+                .cmp(&b.excluded_at)
+                .then_with(|| a.source_id.cmp(&b.source_id))
+        });
+        excluded
+    }
+
+    /// Forget exclusions and failure counts for `content_hash`; returns how
+    /// many providers were readmitted
+    pub fn clear(&mut self, content_hash: &str) -> usize {
+        self.failures.retain(|(_, hash), _| hash != content_hash);
+        let before = self.excluded.len();
+        self.excluded.retain(|(_, hash), _| hash != content_hash);
+        before - self.excluded.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exclusion_after_threshold_is_per_content() {
+        let mut exclusions = SourceExclusions::new();
+        for _ in 1..CORRUPTION_THRESHOLD {
+            assert!(exclusions.record_failure("peer", "file-a", "hash mismatch").is_none());
+        }
+        assert!(!exclusions.is_excluded("peer", "file-a"));
+
+        let excluded = exclusions
+            .record_failure("peer", "file-a", "hash mismatch on chunk 7")
+            .unwrap();
+        assert_eq!(excluded.failures, CORRUPTION_THRESHOLD);
+        assert_eq!(excluded.reason, "hash mismatch on chunk 7");
+        assert!(exclusions.is_excluded("peer", "file-a"));
+        // Only reported once, and only for that content
+        assert!(exclusions.record_failure("peer", "file-a", "again").is_none());
+        assert!(!exclusions.is_excluded("peer", "file-b"));
+        assert_eq!(exclusions.excluded_for("file-a")[0].failures, CORRUPTION_THRESHOLD + 1);
+    }
+
+    #[test]
+    fn test_clear_readmits_sources() {
+        let mut exclusions = SourceExclusions::new();
+        for _ in 0..CORRUPTION_THRESHOLD {
+            exclusions.record_failure("a", "file", "bad");
+            exclusions.record_failure("b", "other", "bad");
+        }
+        exclusions.record_failure("c", "file", "bad");
+
+        assert_eq!(exclusions.clear("file"), 1);
+        assert!(exclusions.excluded_for("file").is_empty());
+        assert!(exclusions.is_excluded("b", "other"));
+        // The partial count for "c" was reset too
+        for _ in 1..CORRUPTION_THRESHOLD {
+            assert!(exclusions.record_failure("c", "file", "bad").is_none());
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Stall detection for downloads.
+//!
+//! A source can go silent without closing its connection, leaving a download
+//! at a fixed percentage forever. `StallDetector` watches the verified bytes
+//! of one download; once nothing new arrives for `StallPolicy::timeout` it
+//! asks for a recovery (drop the silent sources, look for providers again and
+//! carry on from the chunks already verified). Progress resets the count, and
+//! after `max_recoveries` attempts without progress the download fails.
+
+use crate::config::DownloadsConfig;
+use std::time::{Duration, Instant};
+
+/// Time without verified bytes before a download counts as stalled
+pub const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Recoveries tried without progress before a stalled download fails
+pub const DEFAULT_MAX_STALL_RECOVERIES: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StallPolicy {
+    pub timeout: Duration,
+    pub max_recoveries: u32,
+}
+
+impl Default for StallPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_STALL_TIMEOUT,
+            max_recoveries: DEFAULT_MAX_STALL_RECOVERIES,
+        }
+    }
+}
+
+impl From<&DownloadsConfig> for StallPolicy {
+    fn from(config: &DownloadsConfig) -> Self {
+        Self {
+            timeout: Duration::from_secs(config.stall_timeout_secs.max(1)),
+            max_recoveries: config.max_stall_recoveries,
+        }
+    }
+}
+
+/// What the download monitor should do after an observation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallVerdict {
+    /// New bytes were verified since the last observation
+    Progressing,
+    /// No new bytes, but not for long enough to act
+    Waiting,
+    /// Stalled; try recovery number `attempt`
+    Recover { attempt: u32, stalled_for: Duration },
+    /// Still stalled after every recovery attempt
+    Fail { attempts: u32, stalled_for: Duration },
+}
+
+#[derive(Debug, Clone)]
+pub struct StallDetector {
+    policy: StallPolicy,
+    verified_bytes: u64,
+    last_progress: Instant,
+    /// Last progress or recovery; the timeout runs from here
+    last_action: Instant,
+    attempts: u32,
+}
+
+impl StallDetector {
+    /// `verified_bytes` counts data restored from an earlier run
+    pub fn new(policy: StallPolicy, verified_bytes: u64, now: Instant) -> Self {
+        Self {
+            policy,
+            verified_bytes,
+            last_progress: now,
+            last_action: now,
+            attempts: 0,
+        }
+    }
+
+    pub fn observe(&mut self, verified_bytes: u64, now: Instant) -> StallVerdict {
+        if verified_bytes > self.verified_bytes {
+            self.verified_bytes = verified_bytes;
+            self.last_progress = now;
+            self.last_action = now;
+            self.attempts = 0;
+            return StallVerdict::Progressing;
+        }
+        if now.saturating_duration_since(self.last_action) < self.policy.timeout {
+            return StallVerdict::Waiting;
+        }
+
+        let stalled_for = now.saturating_duration_since(self.last_progress);
+        if self.attempts >= self.policy.max_recoveries {
+            return StallVerdict::Fail {
+                attempts: self.attempts,
+                stalled_for,
+            };
+        }
+        self.attempts += 1;
+        self.last_action = now;
+        StallVerdict::Recover {
+            attempt: self.attempts,
+            stalled_for,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: Duration = Duration::from_secs(2);
+    const CHUNK: u64 = 256 * 1024;
+
+    /// Serves one chunk per tick until `stops_after` chunks, then holds the
+    /// connection open without sending anything
+    struct MockSource {
+        served: u64,
+        stops_after: Option<u64>,
+    }
+
+    impl MockSource {
+        fn new(stops_after: Option<u64>) -> Self {
+            Self {
+                served: 0,
+                stops_after,
+            }
+        }
+
+        fn tick(&mut self) -> u64 {
+            if self.stops_after.is_some_and(|limit| self.served >= limit) {
+                return 0;
+            }
+            self.served += 1;
+            CHUNK
+        }
+    }
+
+    struct Run {
+        verdicts: Vec<StallVerdict>,
+        verified: u64,
+    }
+
+    /// Download `chunks` from `source`, replacing it with `replacement()` on
+    /// each recovery, until the download completes or fails
+    fn run(
+        policy: StallPolicy,
+        chunks: u64,
+        mut source: MockSource,
+        mut replacement: impl FnMut() -> MockSource,
+    ) -> Run {
+        let start = Instant::now();
+        let mut detector = StallDetector::new(policy, 0, start);
+        let (mut verified, mut verdicts) = (0, Vec::new());
+        for tick in 1..=10_000 {
+            verified += source.tick();
+            if verified >= chunks * CHUNK {
+                break;
+            }
+            let verdict = detector.observe(verified, start + TICK * tick);
+            match verdict {
+                StallVerdict::Recover { .. } => source = replacement(),
+                StallVerdict::Fail { .. } => {
+                    verdicts.push(verdict);
+                    break;
+                }
+                _ => continue,
+            }
+            verdicts.push(verdict);
+        }
+        Run { verdicts, verified }
+    }
+
+    fn policy() -> StallPolicy {
+        StallPolicy {
+            timeout: Duration::from_secs(10),
+            max_recoveries: 2,
+        }
+    }
+
+    #[test]
+    fn test_silent_source_is_replaced_and_download_finishes() {
+        let outcome = run(policy(), 20, MockSource::new(Some(8)), || MockSource::new(None));
+        assert_eq!(
+            outcome.verdicts,
+            vec![StallVerdict::Recover {
+                attempt: 1,
+                stalled_for: Duration::from_secs(10),
+            }]
+        );
+        assert_eq!(outcome.verified, 20 * CHUNK);
+    }
+
+    #[test]
+    fn test_download_fails_after_bounded_recoveries() {
+        let outcome = run(policy(), 20, MockSource::new(Some(8)), || MockSource::new(Some(0)));
+        assert_eq!(
+            outcome.verdicts,
+            vec![
+                StallVerdict::Recover {
+                    attempt: 1,
+                    stalled_for: Duration::from_secs(10),
+                },
+                StallVerdict::Recover {
+                    attempt: 2,
+                    stalled_for: Duration::from_secs(20),
+                },
+                StallVerdict::Fail {
+                    attempts: 2,
+                    stalled_for: Duration::from_secs(30),
+                },
+            ]
+        );
+        assert_eq!(outcome.verified, 8 * CHUNK);
+    }
+
+    #[test]
+    fn test_progress_resets_recovery_attempts() {
+        // Every replacement serves a few chunks and then goes silent too
+        let outcome = run(policy(), 20, MockSource::new(Some(4)), || MockSource::new(Some(4)));
+        assert!(outcome.verdicts.len() >= 3);
+        assert!(outcome
+            .verdicts
+            .iter()
+            .all(|v| matches!(v, StallVerdict::Recover { attempt: 1, .. })));
+        assert_eq!(outcome.verified, 20 * CHUNK);
+    }
+}
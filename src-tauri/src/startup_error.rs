@@ -0,0 +1,273 @@
+//! Why the desktop app could not start, in words a user can act on.
+//!
+//! Everything the app needs before its window opens (the data directory,
+//! its lock, the swarm settings from the environment and the startup DHT
+//! node) reports its failure as a `StartupError`. There is no node builder:
+//! errors of `DhtService::new` are classified by `from_dht_error`. The setup
+//! hook shows the error in a dialog instead of the main window and exits
+//! with status 1; the same text goes to stderr. A headless node prints it
+//! for a damaged or unreadable identity file. Failures after startup, such
+//! as unreachable bootstrap nodes, are not startup errors: the node keeps
+//! running and retries.
+
+use crate::config::ChiralConfig;
+use crate::instance_lock::InstanceLockError;
+use libp2p::identity::DecodingError;
+use libp2p::Multiaddr;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartupError {
+    PortInUse {
+        port: u16,
+    },
+    KeypairCorrupt {
+        path: PathBuf,
+        detail: String,
+    },
+    /// `field` as spelled in the configuration file
+    ConfigInvalid {
+        field: String,
+        reason: String,
+    },
+    DataDirUnavailable {
+        path: PathBuf,
+        detail: String,
+    },
+    AlreadyRunning {
+        dir: PathBuf,
+        pid: Option<u32>,
+    },
+    /// Any other failure to start the node
+    Network {
+        detail: String,
+    },
+}
+
+impl StartupError {
+    /// Title of the error dialog
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::PortInUse { .. } => "Port already in use",
+            Self::KeypairCorrupt { .. } => "Identity key is damaged",
+            Self::ConfigInvalid { .. } => "Invalid configuration",
+            Self::DataDirUnavailable { .. } => "Data directory unavailable",
+            Self::AlreadyRunning { .. } => "Chiral Network is already running",
+            Self::Network { .. } => "Could not start the network node",
+        }
+    }
+
+    /// What the user can do about it
+    pub fn hint(&self) -> String {
+        match self {
+            Self::PortInUse { port } => format!(
+                "Quit the other program using port {} (often another Chiral node), \
+                 or set a different port, then start the app again.",
+                port
+            ),
+            Self::KeypairCorrupt { path, .. } => format!(
+                "Restore {} from a backup, or move it away to start with a new \
+                 identity (peers will see a new peer ID).",
+                path.display()
+            ),
+            Self::ConfigInvalid { field, .. } => format!(
+                "Correct `{}` in chiral.toml or the environment, then start the app again.",
+                field
+            ),
+            Self::DataDirUnavailable { path, .. } => format!(
+                "Check that {} exists and is writable, or choose another data directory \
+                 with --data-dir.",
+                path.display()
+            ),
+            Self::AlreadyRunning { .. } => {
+                "Switch to the window that is already open, or quit that instance first."
+                    .to_string()
+            }
+            Self::Network { .. } => {
+                "Check the network settings and the log, then start the app again.".to_string()
+            }
+        }
+    }
+
+    /// Text of the error dialog
+    pub fn message(&self) -> String {
+        let text = self.to_string();
+        let mut chars = text.chars();
+        let sentence: String = match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => text,
+        };
+        format!("{}.\n\n{}", sentence, self.hint())
+    }
+
+    /// Classify an error from `DhtService::new` for a node listening on
+    /// `port`; `identity_file` is where its keypair came from, if a file
+    pub fn from_dht_error(
+        error: &(dyn Error + 'static),
+        port: u16,
+        identity_file: Option<&Path>,
+    ) -> Self {
+        for cause in std::iter::successors(Some(error), |e| e.source()) {
+            if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+                if io.kind() == std::io::ErrorKind::AddrInUse {
+                    return Self::PortInUse { port };
+                }
+            }
+            if let (Some(decoding), Some(path)) =
+                (cause.downcast_ref::<DecodingError>(), identity_file)
+            {
+                return Self::KeypairCorrupt {
+                    path: path.to_path_buf(),
+                    detail: decoding.to_string(),
+                };
+            }
+        }
+        Self::Network {
+            detail: error.to_string(),
+        }
+    }
+}
+
+/// The swarm settings the desktop node takes from the environment, which
+/// `DhtService::new` would otherwise reject as a plain network failure: the
+/// listen addresses it adds to port 4001 and the relay consent settings.
+/// Fields are named by their environment variable.
+pub fn check_desktop_config(config: &ChiralConfig) -> Result<(), StartupError> {
+    for addr in &config.swarm.listen_addrs {
+        if let Err(e) = addr.parse::<Multiaddr>() {
+            return Err(StartupError::ConfigInvalid {
+                field: "CHIRAL_LISTEN_ADDRS".to_string(),
+                reason: format!("{} is not a multiaddr: {}", addr, e),
+            });
+        }
+    }
+    match crate::config::reload::validate_swarm(config)
+        .into_iter()
+        .next()
+    {
+        Some(error) => {
+            let (key, reason) = error.split_once(": ").unwrap_or(("configuration", &error));
+            let field = match key {
+                "swarm.relay_allow_list" => "CHIRAL_RELAY_ALLOW_LIST",
+                "swarm.relay_consent" => "CHIRAL_RELAY_CONSENT",
+                other => other,
+            };
+            Err(StartupError::ConfigInvalid {
+                field: field.to_string(),
+                reason: reason.to_string(),
+            })
+        }
+        None => Ok(()),
+    }
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PortInUse { port } => write!(f, "TCP port {} is already in use", port),
+            Self::KeypairCorrupt { path, detail } => {
+                write!(
+                    f,
+                    "the identity key in {} cannot be read: {}",
+                    path.display(),
+                    detail
+                )
+            }
+            Self::ConfigInvalid { field, reason } => write!(f, "{} is invalid: {}", field, reason),
+            Self::DataDirUnavailable { path, detail } => {
+                write!(
+                    f,
+                    "cannot use the data directory {}: {}",
+                    path.display(),
+                    detail
+                )
+            }
+            Self::AlreadyRunning {
+                dir,
+                pid: Some(pid),
+            } => write!(
+                f,
+                "another instance (PID {}) is using the data directory {}",
+                pid,
+                dir.display()
+            ),
+            Self::AlreadyRunning { dir, pid: None } => {
+                write!(
+                    f,
+                    "another instance is using the data directory {}",
+                    dir.display()
+                )
+            }
+            Self::Network { detail } => write!(f, "the network node failed to start: {}", detail),
+        }
+    }
+}
+
+impl Error for StartupError {}
+
+impl From<InstanceLockError> for StartupError {
+    fn from(error: InstanceLockError) -> Self {
+        match error {
+            InstanceLockError::AlreadyRunning { dir, pid } => Self::AlreadyRunning { dir, pid },
+            InstanceLockError::Io { path, source } => Self::DataDirUnavailable {
+                path,
+                detail: source.to_string(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_startup_failures() {
+        let busy = std::io::Error::from(std::io::ErrorKind::AddrInUse);
+        let wrapped: Box<dyn Error> = Box::new(libp2p::TransportError::Other(busy));
+        assert_eq!(
+            StartupError::from_dht_error(wrapped.as_ref(), 4001, None),
+            StartupError::PortInUse { port: 4001 }
+        );
+
+        let key_error = libp2p::identity::Keypair::ed25519_from_bytes([0u8; 3]).unwrap_err();
+        let path = Path::new("/data/identity.key");
+        assert!(matches!(
+            StartupError::from_dht_error(&key_error, 4001, Some(path)),
+            StartupError::KeypairCorrupt { path: p, .. } if p == path
+        ));
+        assert!(matches!(
+            StartupError::from_dht_error(&key_error, 4001, None),
+            StartupError::Network { .. }
+        ));
+
+        let mut config = ChiralConfig::default();
+        config.swarm.listen_addrs = vec![
+            "/ip4/0.0.0.0/udp/4001/quic-v1".into(),
+            "0.0.0.0:4001".into(),
+        ];
+        let error = check_desktop_config(&config).unwrap_err();
+        assert!(
+            matches!(&error, StartupError::ConfigInvalid { field, .. } if field == "CHIRAL_LISTEN_ADDRS")
+        );
+        assert!(error.message().contains("environment"));
+        config.swarm.listen_addrs.pop();
+        assert_eq!(check_desktop_config(&config), Ok(()));
+        config.swarm.relay_allow_list = vec!["not-a-peer".into()];
+        assert!(matches!(
+            check_desktop_config(&config),
+            Err(StartupError::ConfigInvalid { field, .. }) if field == "CHIRAL_RELAY_ALLOW_LIST"
+        ));
+
+        let locked = StartupError::from(InstanceLockError::AlreadyRunning {
+            dir: PathBuf::from("/data"),
+            pid: Some(42),
+        });
+        assert_eq!(
+            locked.to_string(),
+            "another instance (PID 42) is using the data directory /data"
+        );
+    }
+}
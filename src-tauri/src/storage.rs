@@ -0,0 +1,561 @@
+// Chiral-managed storage: download directory, disk quota and cleanup
+//
+// Everything the node writes on behalf of transfers (partial downloads, the
+// chunk cache, transfer manifests and the shared-file store) counts against a
+// single optional quota. Transfers check both free disk space and quota
+// headroom before they start so they fail fast instead of filling the disk.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+/// Extension used by streaming downloads while in progress
+pub const STREAMING_PARTIAL_EXTENSION: &str = "chiral_partial";
+/// Extension used by restartable downloads while in progress
+pub const RESTART_PARTIAL_EXTENSION: &str = "part";
+/// Extension of multi-source downloads until they are verified and renamed
+pub const DOWNLOAD_PARTIAL_EXTENSION: &str = "chiral-partial";
+/// Suffix of restartable download metadata files
+pub const MANIFEST_SUFFIX: &str = ".meta.json";
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("insufficient disk space: need {needed} bytes, {available} bytes available")]
+    InsufficientDiskSpace { needed: u64, available: u64 },
+
+    #[error("storage quota exceeded: need {needed} bytes, {remaining} of {quota} bytes remaining")]
+    QuotaExceeded { needed: u64, remaining: u64, quota: u64 },
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("failed to save storage settings: {0}")]
+    Settings(String),
+}
+
+/// User-configurable storage settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageSettings {
+    /// Default directory for completed downloads
+    #[serde(default)]
+    pub download_dir: Option<PathBuf>,
+    /// Upper bound for Chiral-managed storage in bytes (None = unlimited)
+    #[serde(default)]
+    pub quota_bytes: Option<u64>,
+}
+
+/// Usage of Chiral-managed storage, broken down by category
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageUsage {
+    pub partial_downloads: u64,
+    pub chunk_cache: u64,
+    pub manifests: u64,
+    pub shared_files: u64,
+    pub total: u64,
+    /// Bytes not stored because identical content is shared from several paths
+    #[serde(default)]
+    pub deduplicated_savings: u64,
+    pub quota_bytes: Option<u64>,
+    /// Free space on the volume holding the download directory
+    pub available_disk: Option<u64>,
+}
+
+/// Result of a cleanup pass
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CleanupReport {
+    pub removed_partials: usize,
+    pub removed_manifests: usize,
+    pub removed_cache_entries: usize,
+    pub freed_bytes: u64,
+}
+
+/// What a cleanup pass must keep
+#[derive(Debug, Clone, Default)]
+pub struct CleanupInput {
+    /// Content hashes still referenced (shared files, active downloads)
+    pub referenced_hashes: HashSet<String>,
+    /// Partial files belonging to transfers that are still running or paused
+    pub active_partials: HashSet<PathBuf>,
+}
+
+fn is_partial(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some(STREAMING_PARTIAL_EXTENSION)
+            | Some(RESTART_PARTIAL_EXTENSION)
+            | Some(DOWNLOAD_PARTIAL_EXTENSION)
+    )
+}
+
+/// `<dest>.chiral-partial`, where a download is written until it is verified
+pub fn download_partial_path(dest: &Path) -> PathBuf {
+    let mut name = dest.as_os_str().to_os_string();
+    name.push(".");
+    name.push(DOWNLOAD_PARTIAL_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Final path for a completed download. Unless `overwrite` is set an existing
+/// file is left alone and the download gets a " (1)", " (2)", ... suffix.
+pub fn resolve_destination(dest: &Path, overwrite: bool) -> PathBuf {
+    if overwrite || !dest.exists() {
+        return dest.to_path_buf();
+    }
+    let stem = dest
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = dest
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1u32..)
+        .map(|n| dest.with_file_name(format!("{} ({}){}", stem, n, extension)))
+        .find(|candidate| !candidate.exists())
+        .expect("unbounded suffix search")
+}
+
+fn is_cross_device(error: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        error.raw_os_error() == Some(libc::EXDEV)
+    }
+    #[cfg(windows)]
+    {
+        // ERROR_NOT_SAME_DEVICE
+        error.raw_os_error() == Some(17)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = error;
+        false
+    }
+}
+
+/// Make a rename in `dir` durable
+fn sync_dir(dir: &Path) {
+    #[cfg(unix)]
+    if let Err(e) = std::fs::File::open(dir).and_then(|d| d.sync_all()) {
+        debug!("Failed to sync directory {}: {}", dir.display(), e);
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+}
+
+/// Move a verified partial file to `dest`, replacing anything there.
+///
+/// The data is synced before the rename so a crash leaves either the old
+/// state or the complete file. When `dest` is on another filesystem the
+/// partial is copied next to `dest`, synced and renamed from there.
+pub fn promote_partial(partial: &Path, dest: &Path) -> Result<(), StorageError> {
+    std::fs::File::open(partial)?.sync_all()?;
+    match std::fs::rename(partial, dest) {
+        Ok(()) => {}
+        Err(e) if is_cross_device(&e) => {
+            let staging = download_partial_path(dest);
+            std::fs::copy(partial, &staging)?;
+            std::fs::File::open(&staging)?.sync_all()?;
+            std::fs::rename(&staging, dest)?;
+            std::fs::remove_file(partial)?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+    if let Some(dir) = dest.parent() {
+        sync_dir(dir);
+    }
+    Ok(())
+}
+
+fn is_manifest(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .map(|n| n.ends_with(MANIFEST_SUFFIX))
+        .unwrap_or(false)
+}
+
+/// Files whose presence means a manifest is still in use.
+///
+/// `name.meta.json` belongs to `name.part` (download persistence) and
+/// `.name.chiral.meta.json` to `name` itself (restartable downloads write in place).
+fn manifest_owners(manifest: &Path) -> Vec<PathBuf> {
+    let name = manifest
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    let stem = name.trim_end_matches(MANIFEST_SUFFIX);
+    let mut owners = vec![manifest.with_file_name(format!("{}.{}", stem, RESTART_PARTIAL_EXTENSION))];
+    if let Some(target) = stem
+        .strip_prefix('.')
+        .and_then(|s| s.strip_suffix(".chiral"))
+    {
+        owners.push(manifest.with_file_name(target));
+    }
+    owners
+}
+
+/// Total size of all files below `dir` (0 if it does not exist)
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Files directly inside `dir` matching `filter`, with their sizes
+fn matching_files(dir: &Path, filter: impl Fn(&Path) -> bool) -> Vec<(PathBuf, u64)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let meta = entry.metadata().ok()?;
+            (meta.is_file() && filter(&path)).then(|| (path, meta.len()))
+        })
+        .collect()
+}
+
+fn remove_path(path: &Path) -> Option<u64> {
+    let size = if path.is_dir() {
+        dir_size(path)
+    } else {
+        std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+    };
+    let result = if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    };
+    match result {
+        Ok(()) => {
+            debug!("Removed {}", path.display());
+            Some(size)
+        }
+        Err(e) => {
+            warn!("Failed to remove {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Manages storage locations, the quota and cleanup
+pub struct StorageManager {
+    settings_path: PathBuf,
+    settings: RwLock<StorageSettings>,
+    /// Directory with whole shared files (keyed by SHA-256)
+    shared_files_dir: PathBuf,
+    /// Chunk cache directory; only known once the DHT node has started
+    chunk_cache_dir: RwLock<Option<PathBuf>>,
+    /// Used when no download directory is configured
+    fallback_download_dir: PathBuf,
+}
+
+impl StorageManager {
+    pub fn new(settings_path: PathBuf, shared_files_dir: PathBuf, fallback_download_dir: PathBuf) -> Self {
+        let settings = std::fs::read(&settings_path)
+            .ok()
+            .and_then(|bytes| match serde_json::from_slice(&bytes) {
+                Ok(settings) => Some(settings),
+                Err(e) => {
+                    warn!("Ignoring invalid storage settings: {}", e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            settings_path,
+            settings: RwLock::new(settings),
+            shared_files_dir,
+            chunk_cache_dir: RwLock::new(None),
+            fallback_download_dir,
+        }
+    }
+
+    /// Default settings location inside the application data directory
+    pub fn default_settings_path() -> PathBuf {
+        crate::data_dirs::DataDirs::current().storage_settings()
+    }
+
+    pub fn settings(&self) -> StorageSettings {
+        self.settings.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Replace the settings and persist them (atomic write)
+    pub fn update_settings(&self, settings: StorageSettings) -> Result<(), StorageError> {
+        if let Some(dir) = &settings.download_dir {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let json = serde_json::to_vec_pretty(&settings)
+            .map_err(|e| StorageError::Settings(e.to_string()))?;
+        if let Some(parent) = self.settings_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp_path = self.settings_path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, json)?;
+        std::fs::rename(&tmp_path, &self.settings_path)?;
+
+        *self.settings.write().unwrap_or_else(|e| e.into_inner()) = settings;
+        info!("Updated storage settings");
+        Ok(())
+    }
+
+    pub fn set_chunk_cache_dir(&self, dir: PathBuf) {
+        *self.chunk_cache_dir.write().unwrap_or_else(|e| e.into_inner()) = Some(dir);
+    }
+
+    fn chunk_cache_dir(&self) -> Option<PathBuf> {
+        self.chunk_cache_dir
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Directory downloads go to when the caller does not pick one
+    pub fn download_dir(&self) -> PathBuf {
+        self.settings()
+            .download_dir
+            .unwrap_or_else(|| self.fallback_download_dir.clone())
+    }
+
+    /// Compute current usage by category
+    pub fn usage(&self) -> StorageUsage {
+        let download_dir = self.download_dir();
+        let partial_downloads = matching_files(&download_dir, is_partial)
+            .iter()
+            .map(|(_, size)| size)
+            .sum();
+        let manifests = matching_files(&download_dir, is_manifest)
+            .iter()
+            .map(|(_, size)| size)
+            .sum();
+        let chunk_cache = self.chunk_cache_dir().map(|d| dir_size(&d)).unwrap_or(0);
+        let shared_files = dir_size(&self.shared_files_dir);
+
+        StorageUsage {
+            partial_downloads,
+            chunk_cache,
+            manifests,
+            shared_files,
+            total: partial_downloads + chunk_cache + manifests + shared_files,
+            deduplicated_savings: 0,
+            quota_bytes: self.settings().quota_bytes,
+            available_disk: fs2::available_space(&download_dir).ok(),
+        }
+    }
+
+    /// Check that a transfer of `size` bytes into `destination` fits on disk
+    /// and within the quota.
+    pub fn check_transfer_capacity(&self, destination: &Path, size: u64) -> Result<(), StorageError> {
+        let probe_dir = destination
+            .ancestors()
+            .find(|p| p.is_dir())
+            .unwrap_or_else(|| Path::new("."));
+        let available = fs2::available_space(probe_dir)?;
+        if available < size {
+            return Err(StorageError::InsufficientDiskSpace {
+                needed: size,
+                available,
+            });
+        }
+
+        if let Some(quota) = self.settings().quota_bytes {
+            let used = self.usage().total;
+            let remaining = quota.saturating_sub(used);
+            if remaining < size {
+                return Err(StorageError::QuotaExceeded {
+                    needed: size,
+                    remaining,
+                    quota,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove orphaned partial downloads, manifests without a partial file and
+    /// cache entries for content that is no longer referenced.
+    pub fn cleanup(&self, input: &CleanupInput) -> CleanupReport {
+        let mut report = CleanupReport::default();
+        let download_dir = self.download_dir();
+
+        for (path, _) in matching_files(&download_dir, is_partial) {
+            if input.active_partials.contains(&path) {
+                continue;
+            }
+            if let Some(freed) = remove_path(&path) {
+                report.removed_partials += 1;
+                report.freed_bytes += freed;
+            }
+        }
+
+        for (path, _) in matching_files(&download_dir, is_manifest) {
+            if input.active_partials.contains(&path)
+                || manifest_owners(&path).iter().any(|owner| owner.exists())
+            {
+                continue;
+            }
+            if let Some(freed) = remove_path(&path) {
+                report.removed_manifests += 1;
+                report.freed_bytes += freed;
+            }
+        }
+
+        // The shared-file store is keyed by content hash. Chunk cache entries are
+        // keyed by encrypted chunk hash and owned by their manifests, so they are
+        // only reported in `usage`, never removed here.
+        if let Ok(entries) = std::fs::read_dir(&self.shared_files_dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let Some(hash) = path.file_name().and_then(|n| n.to_str()) else {
+                    continue;
+                };
+                if input.referenced_hashes.contains(hash) {
+                    continue;
+                }
+                if let Some(freed) = remove_path(&path) {
+                    report.removed_cache_entries += 1;
+                    report.freed_bytes += freed;
+                }
+            }
+        }
+
+        info!(
+            "Storage cleanup removed {} partial(s), {} manifest(s), {} cache entr(ies), freed {} bytes",
+            report.removed_partials,
+            report.removed_manifests,
+            report.removed_cache_entries,
+            report.freed_bytes
+        );
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn manager(dir: &TempDir) -> StorageManager {
+        let shared = dir.path().join("files");
+        let downloads = dir.path().join("downloads");
+        std::fs::create_dir_all(&shared).unwrap();
+        std::fs::create_dir_all(&downloads).unwrap();
+        StorageManager::new(dir.path().join("settings.json"), shared, downloads)
+    }
+
+    #[test]
+    fn test_usage_by_category() {
+        let dir = TempDir::new().unwrap();
+        let storage = manager(&dir);
+        let downloads = storage.download_dir();
+        std::fs::write(downloads.join("movie.part"), vec![0u8; 100]).unwrap();
+        std::fs::write(downloads.join("movie.meta.json"), vec![0u8; 10]).unwrap();
+        std::fs::write(downloads.join("done.txt"), vec![0u8; 1000]).unwrap();
+        std::fs::write(dir.path().join("files").join("abc"), vec![0u8; 50]).unwrap();
+
+        let usage = storage.usage();
+        assert_eq!(usage.partial_downloads, 100);
+        assert_eq!(usage.manifests, 10);
+        assert_eq!(usage.shared_files, 50);
+        assert_eq!(usage.total, 160);
+    }
+
+    #[test]
+    fn test_quota_refuses_transfer() {
+        let dir = TempDir::new().unwrap();
+        let storage = manager(&dir);
+        std::fs::write(dir.path().join("files").join("abc"), vec![0u8; 80]).unwrap();
+        storage
+            .update_settings(StorageSettings {
+                download_dir: None,
+                quota_bytes: Some(100),
+            })
+            .unwrap();
+
+        let dest = storage.download_dir().join("new.bin");
+        assert!(storage.check_transfer_capacity(&dest, 20).is_ok());
+        assert!(matches!(
+            storage.check_transfer_capacity(&dest, 21),
+            Err(StorageError::QuotaExceeded { remaining: 20, .. })
+        ));
+    }
+
+    #[test]
+    fn test_settings_persist() {
+        let dir = TempDir::new().unwrap();
+        let settings = StorageSettings {
+            download_dir: Some(dir.path().join("custom")),
+            quota_bytes: Some(1 << 30),
+        };
+        manager(&dir).update_settings(settings.clone()).unwrap();
+        assert_eq!(manager(&dir).settings(), settings);
+        assert!(dir.path().join("custom").is_dir());
+    }
+
+    #[test]
+    fn test_cleanup_keeps_referenced_and_active() {
+        let dir = TempDir::new().unwrap();
+        let storage = manager(&dir);
+        let downloads = storage.download_dir();
+        let active = downloads.join("active.part");
+        std::fs::write(&active, b"a").unwrap();
+        std::fs::write(downloads.join("active.meta.json"), b"{}").unwrap();
+        std::fs::write(downloads.join("orphan.chiral_partial"), b"bb").unwrap();
+        std::fs::write(downloads.join("stale.meta.json"), b"{}").unwrap();
+        std::fs::write(downloads.join("paused.iso"), b"p").unwrap();
+        std::fs::write(downloads.join(".paused.iso.chiral.meta.json"), b"{}").unwrap();
+        std::fs::write(dir.path().join("files").join("keep"), b"k").unwrap();
+        std::fs::write(dir.path().join("files").join("drop"), b"ddd").unwrap();
+
+        let input = CleanupInput {
+            referenced_hashes: ["keep".to_string()].into_iter().collect(),
+            active_partials: [active.clone()].into_iter().collect(),
+        };
+        let report = storage.cleanup(&input);
+
+        assert_eq!(report.removed_partials, 1);
+        assert_eq!(report.removed_manifests, 1);
+        assert_eq!(report.removed_cache_entries, 1);
+        assert_eq!(report.freed_bytes, 2 + 2 + 3);
+        assert!(active.exists());
+        assert!(downloads.join("active.meta.json").exists());
+        assert!(downloads.join(".paused.iso.chiral.meta.json").exists());
+        assert!(dir.path().join("files").join("keep").exists());
+    }
+
+    #[test]
+    fn test_partial_is_promoted_beside_existing_file() {
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("report.pdf");
+        std::fs::write(&dest, b"old").unwrap();
+        std::fs::write(dir.path().join("report (1).pdf"), b"older").unwrap();
+
+        let partial = download_partial_path(&dest);
+        assert!(is_partial(&partial));
+        std::fs::write(&partial, b"new").unwrap();
+
+        let target = resolve_destination(&dest, false);
+        assert_eq!(target, dir.path().join("report (2).pdf"));
+        promote_partial(&partial, &target).unwrap();
+        assert!(!partial.exists());
+        assert_eq!(std::fs::read(&target).unwrap(), b"new");
+        assert_eq!(std::fs::read(&dest).unwrap(), b"old");
+        assert_eq!(resolve_destination(&dest, true), dest);
+    }
+}
@@ -0,0 +1,166 @@
+//! JSON-lines log of swarm events.
+//!
+//! When `SwarmConfig::event_log_path` is set, every `DhtEvent` is appended to
+//! a daily rotating file, one JSON object per line, so a production node can
+//! be debugged after the fact with `jq` or `grep`. Each line carries a
+//! `timestamp` (RFC 3339, UTC), the `event_type` (the variant name) and the
+//! fields of the event. Large byte payloads are replaced by their length.
+//!
+//! Lines are handed to `tracing-appender`'s non-blocking writer, which does
+//! the file I/O on its own thread, so logging never stalls the event loop.
+
+use crate::dht::DhtEvent;
+use serde_json::{Map, Value};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tracing::warn;
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+
+/// Days of event logs kept on disk
+pub const EVENT_LOG_RETENTION_DAYS: usize = 7;
+
+/// Arrays longer than this are logged as their length
+const MAX_LOGGED_ARRAY: usize = 64;
+
+pub struct SwarmEventLogger {
+    writer: NonBlocking,
+    /// Flushes the pending lines when the logger is dropped
+    _guard: WorkerGuard,
+}
+
+impl SwarmEventLogger {
+    /// Log to `path`; the date is appended to the file name on rotation,
+    /// e.g. `swarm-events.jsonl.2026-10-14`
+    pub fn new(path: &Path) -> Result<Self, String> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let prefix = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| format!("Invalid event log path {}", path.display()))?;
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let appender = RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix(prefix)
+            .max_log_files(EVENT_LOG_RETENTION_DAYS)
+            .build(&dir)
+            .map_err(|e| format!("Failed to open event log in {}: {}", dir.display(), e))?;
+        let (writer, guard) = tracing_appender::non_blocking(appender);
+        Ok(Self {
+            writer,
+            _guard: guard,
+        })
+    }
+
+    pub fn log(&mut self, event: &DhtEvent) {
+        let line = event_line(event, chrono::Utc::now());
+        // One write per line so lines from a full buffer are dropped whole
+        if let Err(e) = self.writer.write_all(line.as_bytes()) {
+            warn!("Failed to write swarm event log: {}", e);
+        }
+    }
+
+    /// Log every event from `events` before passing it on
+    pub fn tee(mut self, mut events: mpsc::Receiver<DhtEvent>) -> mpsc::Receiver<DhtEvent> {
+        let (tx, rx) = mpsc::channel(events.max_capacity());
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                self.log(&event);
+                if tx.send(event).await.is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    }
+}
+
+/// JSON line for `event`, newline included
+pub fn event_line(event: &DhtEvent, at: chrono::DateTime<chrono::Utc>) -> String {
+    let mut line = Map::new();
+    line.insert(
+        "timestamp".to_string(),
+        Value::String(at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)),
+    );
+    // Externally tagged: a unit variant is its name, any other variant a
+    // single-entry object keyed by its name
+    match serde_json::to_value(event).unwrap_or(Value::Null) {
+        Value::String(name) => {
+            line.insert("event_type".to_string(), Value::String(name));
+        }
+        Value::Object(tagged) => {
+            if let Some((name, fields)) = tagged.into_iter().next() {
+                line.insert("event_type".to_string(), Value::String(name));
+                match compact(fields) {
+                    Value::Object(fields) => {
+                        for (key, value) in fields {
+                            line.entry(key).or_insert(value);
+                        }
+                    }
+                    value => {
+                        line.insert("data".to_string(), value);
+                    }
+                }
+            }
+        }
+        _ => {
+            line.insert("event_type".to_string(), Value::String("Unknown".to_string()));
+        }
+    }
+    let mut text = Value::Object(line).to_string();
+    text.push('\n');
+    text
+}
+
+fn compact(value: Value) -> Value {
+    match value {
+        Value::Array(items) if items.len() > MAX_LOGGED_ARRAY => {
+            serde_json::json!({ "len": items.len() })
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(compact).collect()),
+        Value::Object(fields) => {
+            Value::Object(fields.into_iter().map(|(k, v)| (k, compact(v))).collect())
+        }
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_lines_are_flat_json() {
+        let at = chrono::DateTime::parse_from_rfc3339("2026-10-14T08:30:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let line = event_line(
+            &DhtEvent::PeerDiscovered {
+                peer_id: "12D3KooWtest".to_string(),
+                addresses: vec!["/ip4/10.0.0.1/tcp/4001".to_string()],
+            },
+            at,
+        );
+        assert!(line.ends_with('\n'));
+        let parsed: Value = serde_json::from_str(line.trim_end()).unwrap();
+        assert_eq!(parsed["timestamp"], "2026-10-14T08:30:00.000Z");
+        assert_eq!(parsed["event_type"], "PeerDiscovered");
+        assert_eq!(parsed["peer_id"], "12D3KooWtest");
+
+        let line = event_line(
+            &DhtEvent::BitswapDataReceived {
+                query_id: "q".to_string(),
+                data: vec![0; 4096],
+            },
+            at,
+        );
+        let parsed: Value = serde_json::from_str(line.trim_end()).unwrap();
+        assert_eq!(parsed["event_type"], "BitswapDataReceived");
+        assert_eq!(parsed["data"]["len"], 4096);
+    }
+}
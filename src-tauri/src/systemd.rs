@@ -0,0 +1,224 @@
+//! Readiness and watchdog notifications for nodes run by systemd.
+//!
+//! With `Type=notify` systemd sets `NOTIFY_SOCKET` and waits for `READY=1`
+//! before it counts the unit as started. A headless node sends it once it
+//! has bound a listen address and its bootstrap connections have either
+//! succeeded or failed, then keeps `STATUS=` up to date with peer counts.
+//! Under `WatchdogSec=` the node sends `WATCHDOG=1` only while the DHT task
+//! answers, so a stuck node is restarted by systemd.
+//!
+//! Without `NOTIFY_SOCKET` (or off Unix) nothing here does anything.
+
+use crate::dht::DhtService;
+use crate::health_check::{self, LIVENESS_TIMEOUT};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Longest wait for the node to become ready before `READY=1` is sent anyway
+pub const READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often `STATUS=` is refreshed
+pub const STATUS_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Connection to the service manager's notification socket
+pub struct SdNotifier {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+    #[cfg(unix)]
+    addr: std::os::unix::net::SocketAddr,
+}
+
+impl SdNotifier {
+    /// `None` unless the process was started by a service manager that
+    /// expects notifications
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var_os("NOTIFY_SOCKET")?;
+        match Self::connect(&path) {
+            Ok(notifier) => Some(notifier),
+            Err(e) => {
+                warn!("Ignoring NOTIFY_SOCKET {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn connect(path: &std::ffi::OsStr) -> std::io::Result<Self> {
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+        let bytes = path.as_bytes();
+        let addr = match bytes.strip_prefix(b"@") {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                SocketAddr::from_abstract_name(name)?
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "abstract sockets are not supported on this platform",
+                ))
+            }
+            None => SocketAddr::from_pathname(std::path::Path::new(path))?,
+        };
+        Ok(Self {
+            socket: UnixDatagram::unbound()?,
+            addr,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn connect(_path: &std::ffi::OsStr) -> std::io::Result<Self> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "sd_notify needs Unix sockets",
+        ))
+    }
+
+    /// Send newline-separated `KEY=value` assignments
+    pub fn notify(&self, state: &str) -> std::io::Result<()> {
+        #[cfg(unix)]
+        {
+            self.socket.send_to_addr(state.as_bytes(), &self.addr).map(|_| ())
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = state;
+            Ok(())
+        }
+    }
+
+    fn send(&self, state: &str) {
+        if let Err(e) = self.notify(state) {
+            debug!("sd_notify {:?} failed: {}", state, e);
+        }
+    }
+
+    pub fn ready(&self, status: &str) {
+        self.send(&format!("READY=1\nSTATUS={}", status));
+    }
+
+    pub fn status(&self, status: &str) {
+        self.send(&format!("STATUS={}", status));
+    }
+
+    pub fn watchdog(&self) {
+        self.send("WATCHDOG=1");
+    }
+
+    pub fn stopping(&self) {
+        self.send("STOPPING=1\nSTATUS=Shutting down");
+    }
+}
+
+/// How often to ping the watchdog: half of `WatchdogSec`, per sd_watchdog_enabled(3).
+/// `None` without a watchdog or when it is meant for another process.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.trim().parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.trim().parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+/// `STATUS=` text for the unit
+pub fn status_line(peers: usize, bootstrap_connected: usize, bootstrap_configured: usize) -> String {
+    if bootstrap_configured == 0 {
+        format!("{} peers connected", peers)
+    } else {
+        format!(
+            "{} peers connected, {} of {} bootstrap nodes",
+            peers, bootstrap_connected, bootstrap_configured
+        )
+    }
+}
+
+async fn current_status(dht: &DhtService) -> String {
+    let (connected, configured) = dht.bootstrap_connections().await;
+    status_line(dht.get_peer_count().await, connected, configured)
+}
+
+/// Send `READY=1`, then keep the status and watchdog going for as long as
+/// the DHT service is alive.
+///
+/// Start this once the bootstrap connection attempts are over. `READY=1`
+/// waits for a bound listen address, or `READY_TIMEOUT`; a node that
+/// failed its checks reports itself degraded in `STATUS=`.
+pub async fn run(notifier: Arc<SdNotifier>, dht: Arc<DhtService>) {
+    let deadline = Instant::now() + READY_TIMEOUT;
+    loop {
+        let listen_addrs = dht.metrics_snapshot().await.listen_addrs.len();
+        if listen_addrs > 0 || Instant::now() >= deadline {
+            let (connected, configured) = dht.bootstrap_connections().await;
+            let report = health_check::readiness(connected, configured, listen_addrs);
+            let status = current_status(&dht).await;
+            if report.is_ok() {
+                notifier.ready(&status);
+                info!("Notified systemd that the node is ready");
+            } else {
+                let failed: Vec<&str> = report.checks.iter().filter(|c| !c.ok).map(|c| c.name).collect();
+                warn!("Notifying systemd of a degraded start: {} failed", failed.join(", "));
+                notifier.ready(&format!("Degraded ({}): {}", failed.join(", "), status));
+            }
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+
+    let watchdog = watchdog_interval();
+    if let Some(interval) = watchdog {
+        info!("systemd watchdog enabled, pinging every {}ms", interval.as_millis());
+    }
+    let tick = watchdog.map_or(STATUS_INTERVAL, |interval| interval.min(STATUS_INTERVAL));
+    let mut last_status = Instant::now();
+    loop {
+        tokio::time::sleep(tick).await;
+        // The caller holds the other reference until shutdown
+        if Arc::strong_count(&dht) <= 1 {
+            break;
+        }
+        if watchdog.is_some() {
+            if dht.is_responsive(LIVENESS_TIMEOUT).await {
+                notifier.watchdog();
+            } else {
+                warn!("DHT task did not answer; withholding the systemd watchdog ping");
+            }
+        }
+        if last_status.elapsed() >= STATUS_INTERVAL {
+            notifier.status(&current_status(&dht).await);
+            last_status = Instant::now();
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notifications_reach_the_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify.sock");
+        let listener = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+
+        let notifier = SdNotifier::connect(path.as_os_str()).unwrap();
+        notifier.ready(&status_line(3, 1, 2));
+        let mut buf = [0u8; 256];
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&buf[..len]).unwrap(),
+            "READY=1\nSTATUS=3 peers connected, 1 of 2 bootstrap nodes"
+        );
+
+        notifier.watchdog();
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"WATCHDOG=1");
+
+        assert_eq!(status_line(0, 0, 0), "0 peers connected");
+    }
+}
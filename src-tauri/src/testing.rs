@@ -0,0 +1,187 @@
+//! Reproducible network topologies for tests.
+//!
+//! `NetworkSimulator` starts a `LocalTestNetwork` on the in-memory transport
+//! and connects its nodes along the edges of a `Topology`. Random edges are
+//! drawn from the simulator's seeded `StdRng`, so a failing property test is
+//! reproduced from its seed alone. Nodes can be dropped and added while a
+//! test runs; a dropped node keeps its index, and `add_node` connects the
+//! new node the way the topology would have:
+//!
+//! - `Ring`: between the highest and the lowest live node, replacing the
+//!   edge that closed the ring;
+//! - `Star`: to the hub, node 0, which must still be live;
+//! - `FullMesh`: to every live node;
+//! - `Random`: to each live node with the edge probability.
+
+pub use crate::protocol_bench::LocalTestNetwork;
+use crate::protocol_bench::TestTransport;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Topology {
+    Ring(usize),
+    /// Node 0 is the hub
+    Star(usize),
+    FullMesh(usize),
+    Random {
+        nodes: usize,
+        edge_probability: f64,
+    },
+}
+
+impl Topology {
+    pub fn nodes(&self) -> usize {
+        match *self {
+            Topology::Ring(n) | Topology::Star(n) | Topology::FullMesh(n) => n,
+            Topology::Random { nodes, .. } => nodes,
+        }
+    }
+
+    /// Edges `(a, b)` with `a < b`; only `Random` draws from `rng`
+    pub fn edges(&self, rng: &mut StdRng) -> BTreeSet<(usize, usize)> {
+        let n = self.nodes();
+        let pairs = (0..n).flat_map(|a| (a + 1..n).map(move |b| (a, b)));
+        match *self {
+            Topology::Ring(_) if n < 2 => BTreeSet::new(),
+            Topology::Ring(_) => (0..n).map(|i| edge(i, (i + 1) % n)).collect(),
+            Topology::Star(_) => (1..n).map(|i| (0, i)).collect(),
+            Topology::FullMesh(_) => pairs.collect(),
+            Topology::Random {
+                edge_probability, ..
+            } => pairs
+                .filter(|_| rng.gen::<f64>() < edge_probability)
+                .collect(),
+        }
+    }
+}
+
+fn edge(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+pub struct NetworkSimulator {
+    pub rng: StdRng,
+    pub topology: Topology,
+    network: Option<LocalTestNetwork>,
+    edges: BTreeSet<(usize, usize)>,
+}
+
+impl NetworkSimulator {
+    pub fn new(topology: Topology, seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            topology,
+            network: None,
+            edges: BTreeSet::new(),
+        }
+    }
+
+    /// Start the nodes of the topology and connect every edge, replacing
+    /// any network built before
+    pub async fn build(&mut self) -> Result<&mut LocalTestNetwork, String> {
+        self.network = None;
+        let mut network =
+            LocalTestNetwork::start_with(self.topology.nodes(), TestTransport::Memory).await?;
+        let edges = self.topology.edges(&mut self.rng);
+        for &(a, b) in &edges {
+            network.connect(a, b).await?;
+        }
+        self.edges = edges;
+        Ok(self.network.insert(network))
+    }
+
+    /// The built network; panics before `build`
+    pub fn network(&mut self) -> &mut LocalTestNetwork {
+        self.network.as_mut().expect("build() the network first")
+    }
+
+    /// Edges between live nodes
+    pub fn edges(&self) -> &BTreeSet<(usize, usize)> {
+        &self.edges
+    }
+
+    pub async fn drop_node(&mut self, idx: usize) -> Result<(), String> {
+        self.network().drop_node(idx).await?;
+        self.edges.retain(|&(a, b)| a != idx && b != idx);
+        Ok(())
+    }
+
+    /// Start a node, connect it as the topology would and return its index
+    pub async fn add_node(&mut self) -> Result<usize, String> {
+        let live = self.network().live_nodes();
+        let mut removed = None;
+        let peers: Vec<usize> = match self.topology {
+            Topology::Ring(_) => match (live.first().copied(), live.last().copied()) {
+                (Some(first), Some(last)) if first != last => {
+                    if live.len() > 2 && self.edges.contains(&(first, last)) {
+                        removed = Some((first, last));
+                    }
+                    vec![last, first]
+                }
+                _ => live,
+            },
+            Topology::Star(_) if live.is_empty() => Vec::new(),
+            Topology::Star(_) if live.first() == Some(&0) => vec![0],
+            Topology::Star(_) => return Err("the hub of the star was dropped".to_string()),
+            Topology::FullMesh(_) => live,
+            Topology::Random {
+                edge_probability, ..
+            } => live
+                .into_iter()
+                .filter(|_| self.rng.gen::<f64>() < edge_probability)
+                .collect(),
+        };
+
+        if let Some((a, b)) = removed {
+            self.network().disconnect(a, b).await?;
+            self.edges.remove(&(a, b));
+        }
+        let index = self.network().add_node().await?;
+        for peer in peers {
+            self.network().connect(index, peer).await?;
+            self.edges.insert(edge(index, peer));
+        }
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_topologies_and_changes() {
+        let mut rng = StdRng::seed_from_u64(7);
+        assert_eq!(Topology::Ring(1).edges(&mut rng).len(), 0);
+        assert_eq!(Topology::Ring(2).edges(&mut rng).len(), 1);
+        assert_eq!(Topology::Ring(5).edges(&mut rng).len(), 5);
+        assert_eq!(Topology::Star(5).edges(&mut rng).len(), 4);
+        assert_eq!(Topology::FullMesh(5).edges(&mut rng).len(), 10);
+        let random = Topology::Random {
+            nodes: 8,
+            edge_probability: 0.5,
+        };
+        let drawn = random.edges(&mut StdRng::seed_from_u64(42));
+        assert_eq!(drawn, random.edges(&mut StdRng::seed_from_u64(42)));
+        assert!(drawn.iter().all(|&(a, b)| a < b && b < 8));
+
+        let mut sim = NetworkSimulator::new(Topology::Ring(4), 1);
+        let network = sim.build().await.unwrap();
+        assert!(network.is_connected(0, 1) && network.is_connected(3, 0));
+        assert!(!network.is_connected(0, 2));
+
+        sim.drop_node(1).await.unwrap();
+        assert!(!sim.network().is_connected(0, 1));
+        assert_eq!(sim.edges(), &BTreeSet::from([(0, 3), (2, 3)]));
+
+        let added = sim.add_node().await.unwrap();
+        assert_eq!(added, 4);
+        assert_eq!(sim.edges(), &BTreeSet::from([(0, 4), (2, 3), (3, 4)]));
+        let network = sim.network();
+        assert!(network.is_connected(4, 3) && network.is_connected(0, 4));
+        assert!(!network.is_connected(0, 3));
+        assert_eq!(network.live_nodes(), vec![0, 2, 3, 4]);
+    }
+}
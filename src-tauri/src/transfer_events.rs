@@ -12,15 +12,28 @@
 // - Debuggable: All events carry contextual information for troubleshooting
 
 use crate::analytics::AnalyticsService;
+use crate::chiral_events::{self, ChiralEvent};
+use crate::relay_consent::TrustedPeers;
+use crate::transfer_history::TransferHistory;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use std::time::SystemTime;
-use tauri::{AppHandle, Emitter};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tauri::{AppHandle, Emitter, Manager};
 use tracing::{debug, error};
 
 /// Current version of the event schema for backwards compatibility
 pub const EVENT_SCHEMA_VERSION: &str = "1.0.0";
 
+/// Progress samples the transfer rate is computed over
+pub const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// Time constant of the moving average over the windowed rate
+pub const RATE_SMOOTHING: Duration = Duration::from_secs(5);
+
+/// A transfer with no new bytes for this long is reported as stalled
+pub const STALL_TIMEOUT: Duration = Duration::from_secs(15);
+
 /// Primary transfer lifecycle events - covers all stages of a file transfer
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -55,6 +68,9 @@ pub enum TransferEvent {
     /// Transfer completed successfully
     Completed(TransferCompletedEvent),
     
+    /// Transfer stalled and is retrying with new sources
+    Recovering(TransferRecoveringEvent),
+
     /// Transfer failed permanently (no more retries)
     Failed(TransferFailedEvent),
     
@@ -65,6 +81,27 @@ pub enum TransferEvent {
     SpeedUpdate(SpeedUpdateEvent),
 }
 
+impl TransferEvent {
+    pub fn transfer_id(&self) -> &str {
+        match self {
+            TransferEvent::Queued(e) => &e.transfer_id,
+            TransferEvent::Started(e) => &e.transfer_id,
+            TransferEvent::SourceConnected(e) => &e.transfer_id,
+            TransferEvent::SourceDisconnected(e) => &e.transfer_id,
+            TransferEvent::ChunkCompleted(e) => &e.transfer_id,
+            TransferEvent::ChunkFailed(e) => &e.transfer_id,
+            TransferEvent::Progress(e) => &e.transfer_id,
+            TransferEvent::Paused(e) => &e.transfer_id,
+            TransferEvent::Resumed(e) => &e.transfer_id,
+            TransferEvent::Completed(e) => &e.transfer_id,
+            TransferEvent::Recovering(e) => &e.transfer_id,
+            TransferEvent::Failed(e) => &e.transfer_id,
+            TransferEvent::Canceled(e) => &e.transfer_id,
+            TransferEvent::SpeedUpdate(e) => &e.transfer_id,
+        }
+    }
+}
+
 /// Event when a transfer is added to the download queue
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -159,9 +196,16 @@ pub struct TransferProgressEvent {
     pub completed_chunks: u32,
     pub total_chunks: u32,
     pub progress_percentage: f64,
-    pub download_speed_bps: f64, // Bytes per second
+    pub download_speed_bps: f64, // Bytes per second, smoothed
     pub upload_speed_bps: f64, // For seeding
+    /// Rate since the previous progress event, before smoothing
+    #[serde(default)]
+    pub instantaneous_speed_bps: f64,
+    /// From the smoothed rate; `None` while stalled
     pub eta_seconds: Option<u32>,
+    /// No new bytes for `STALL_TIMEOUT`
+    #[serde(default)]
+    pub stalled: bool,
     pub active_sources: usize,
     pub timestamp: u64,
 }
@@ -219,6 +263,21 @@ pub struct TransferFailedEvent {
     pub retry_possible: bool,
 }
 
+/// Event when a stalled transfer drops its silent sources and looks for new ones
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferRecoveringEvent {
+    pub transfer_id: String,
+    /// 1 for the first recovery since the transfer last made progress
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub stalled_seconds: u64,
+    pub dropped_sources: Vec<String>,
+    pub downloaded_bytes: u64,
+    pub total_bytes: u64,
+    pub timestamp: u64,
+}
+
 /// Event when transfer is canceled by user
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -334,9 +393,137 @@ pub enum ErrorCategory {
     Authentication,
     NoSources,
     RateLimit,
+    /// No data arrived, even after recovering with new sources
+    Stalled,
     Unknown,
 }
 
+// ============================================================================
+// Transfer Rate Smoothing
+// ============================================================================
+
+/// Rates and ETA derived from the progress of one transfer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateEstimate {
+    pub instantaneous_bps: f64,
+    pub smoothed_bps: f64,
+    pub eta_seconds: Option<u32>,
+    pub stalled: bool,
+}
+
+/// Exponentially weighted moving average of a transfer's rate, where each
+/// sample is the rate over the last `RATE_WINDOW` of progress
+#[derive(Debug, Clone, Default)]
+pub struct TransferRateEstimator {
+    samples: VecDeque<(Instant, u64)>,
+    smoothed_bps: Option<f64>,
+    last_progress: Option<Instant>,
+}
+
+impl TransferRateEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `transferred` of `total` bytes are done at `now`
+    pub fn record(&mut self, transferred: u64, total: u64, now: Instant) -> RateEstimate {
+        // Restarted from scratch; earlier samples no longer apply
+        if self.samples.back().is_some_and(|&(_, bytes)| transferred < bytes) {
+            *self = Self::default();
+        }
+
+        let previous = self.samples.back().copied();
+        let progressed = match previous {
+            Some((_, bytes)) => transferred > bytes,
+            None => true,
+        };
+        if progressed {
+            self.last_progress = Some(now);
+        }
+        self.samples.push_back((now, transferred));
+        // Keep the newest sample older than the window as its start
+        while self.samples.len() > 2
+            && self.samples.get(1).is_some_and(|&(at, _)| now.duration_since(at) >= RATE_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+
+        let instantaneous_bps = previous
+            .map(|(at, bytes)| rate(transferred - bytes, now.duration_since(at)))
+            .unwrap_or(0.0);
+        let (start, start_bytes) = self.samples[0];
+        let elapsed = now.duration_since(start);
+        if !elapsed.is_zero() {
+            let windowed = rate(transferred - start_bytes, elapsed);
+            let dt = previous.map_or(elapsed, |(at, _)| now.duration_since(at));
+            let alpha = 1.0 - (-dt.as_secs_f64() / RATE_SMOOTHING.as_secs_f64()).exp();
+            self.smoothed_bps = Some(match self.smoothed_bps {
+                Some(smoothed) => smoothed + alpha * (windowed - smoothed),
+                None => windowed,
+            });
+        }
+
+        let stalled = transferred < total
+            && self
+                .last_progress
+                .is_some_and(|at| now.duration_since(at) >= STALL_TIMEOUT);
+        let smoothed_bps = if stalled { 0.0 } else { self.smoothed_bps.unwrap_or(0.0) };
+        let eta_seconds = if stalled {
+            None
+        } else {
+            calculate_eta(total.saturating_sub(transferred), smoothed_bps)
+        };
+        RateEstimate {
+            instantaneous_bps,
+            smoothed_bps,
+            eta_seconds,
+            stalled,
+        }
+    }
+}
+
+fn rate(bytes: u64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        0.0
+    } else {
+        bytes as f64 / elapsed.as_secs_f64()
+    }
+}
+
+/// Rate estimators of in-flight transfers, managed by the app so that every
+/// `TransferEventBus` smooths progress the same way
+#[derive(Debug, Default)]
+pub struct TransferRates {
+    estimators: Mutex<HashMap<String, TransferRateEstimator>>,
+}
+
+impl TransferRates {
+    /// Replace the producer's speed and ETA with smoothed values
+    fn apply(&self, event: &mut TransferEvent) {
+        let Ok(mut estimators) = self.estimators.lock() else {
+            return;
+        };
+        match event {
+            TransferEvent::Progress(progress) => {
+                let estimate = estimators
+                    .entry(progress.transfer_id.clone())
+                    .or_default()
+                    .record(progress.downloaded_bytes, progress.total_bytes, Instant::now());
+                progress.instantaneous_speed_bps = estimate.instantaneous_bps;
+                progress.download_speed_bps = estimate.smoothed_bps;
+                progress.eta_seconds = estimate.eta_seconds;
+                progress.stalled = estimate.stalled;
+            }
+            TransferEvent::Completed(TransferCompletedEvent { transfer_id, .. })
+            | TransferEvent::Failed(TransferFailedEvent { transfer_id, .. })
+            | TransferEvent::Canceled(TransferCanceledEvent { transfer_id, .. }) => {
+                estimators.remove(transfer_id);
+            }
+            _ => {}
+        }
+    }
+}
+
 // ============================================================================
 // Event Bus Implementation
 // ============================================================================
@@ -355,7 +542,19 @@ impl TransferEventBus {
     }
 
     /// Emit a transfer event to all listeners
-    pub fn emit(&self, event: TransferEvent) {
+    pub fn emit(&self, mut event: TransferEvent) {
+        self.smooth(&mut event);
+        self.publish(event);
+    }
+
+    /// Smooth progress rates if the app manages `TransferRates`
+    fn smooth(&self, event: &mut TransferEvent) {
+        if let Some(rates) = self.app_handle.try_state::<TransferRates>() {
+            rates.apply(event);
+        }
+    }
+
+    fn publish(&self, event: TransferEvent) {
         let event_type = match &event {
             TransferEvent::Queued(_) => "queued",
             TransferEvent::Started(_) => "started",
@@ -367,12 +566,13 @@ impl TransferEventBus {
             TransferEvent::Paused(_) => "paused",
             TransferEvent::Resumed(_) => "resumed",
             TransferEvent::Completed(_) => "completed",
+            TransferEvent::Recovering(_) => "recovering",
             TransferEvent::Failed(_) => "failed",
             TransferEvent::Canceled(_) => "canceled",
             TransferEvent::SpeedUpdate(_) => "speed_update",
         };
 
-        debug!("Emitting transfer event: {}", event_type);
+        debug!(transfer_id = event.transfer_id(), event = event_type, "Emitting transfer event");
 
         // Emit to specific typed channel
         let typed_channel = format!("transfer:{}", event_type);
@@ -384,6 +584,18 @@ impl TransferEventBus {
         if let Err(e) = self.app_handle.emit("transfer:event", &event) {
             error!("Failed to emit event to transfer:event: {}", e);
         }
+
+        chiral_events::emit(&self.app_handle, ChiralEvent::from_transfer(&event));
+
+        // Persist terminal states to the transfer history, if one is managed
+        if let Some(history) = self.app_handle.try_state::<Arc<TransferHistory>>() {
+            history.observe(&event);
+        }
+        // Peers that served a completed download may use the relay under
+        // `known_peers_only`
+        if let Some(trusted) = self.app_handle.try_state::<Arc<TrustedPeers>>() {
+            trusted.observe(&event);
+        }
     }
 
     /// Helper to emit queued event
@@ -436,6 +648,11 @@ impl TransferEventBus {
         self.emit(TransferEvent::Completed(event));
     }
 
+    /// Helper to emit recovering event
+    pub fn emit_recovering(&self, event: TransferRecoveringEvent) {
+        self.emit(TransferEvent::Recovering(event));
+    }
+
     /// Helper to emit failed event
     pub fn emit_failed(&self, event: TransferFailedEvent) {
         self.emit(TransferEvent::Failed(event));
@@ -459,9 +676,10 @@ impl TransferEventBus {
     ///
     /// This method should be used when you want to emit an event and also
     /// update the backend analytics service in a single call.
-    pub async fn emit_with_analytics(&self, event: TransferEvent, analytics: &Arc<AnalyticsService>) {
+    pub async fn emit_with_analytics(&self, mut event: TransferEvent, analytics: &Arc<AnalyticsService>) {
         // Emit to frontend
-        self.emit(event.clone());
+        self.smooth(&mut event);
+        self.publish(event.clone());
 
         // Update backend analytics
         analytics.handle_transfer_event(&event).await;
@@ -574,6 +792,47 @@ mod tests {
         assert_eq!(calculate_eta(1000, 0.0), None);
     }
 
+    #[test]
+    fn test_rate_estimator_smooths_bursts() {
+        let start = Instant::now();
+        let mut estimator = TransferRateEstimator::new();
+        estimator.record(0, 10_000_000, start);
+        let mut estimate = estimator.record(100_000, 10_000_000, start + Duration::from_secs(1));
+        assert_eq!(estimate.instantaneous_bps, 100_000.0);
+
+        // 100 KB/s on average, arriving in bursts every other second
+        for second in 2..=20u64 {
+            let transferred = 100_000 * (second - second % 2);
+            estimate = estimator.record(transferred, 10_000_000, start + Duration::from_secs(second));
+        }
+        assert_eq!(estimate.instantaneous_bps, 200_000.0);
+        assert!((estimate.smoothed_bps - 100_000.0).abs() < 10_000.0);
+        let eta = estimate.eta_seconds.unwrap();
+        assert!((72..=88).contains(&eta), "eta {}", eta);
+        assert!(!estimate.stalled);
+    }
+
+    #[test]
+    fn test_rate_estimator_reports_stall() {
+        let start = Instant::now();
+        let mut estimator = TransferRateEstimator::new();
+        estimator.record(0, 1_000, start);
+        estimator.record(500, 1_000, start + Duration::from_secs(1));
+
+        let estimate = estimator.record(500, 1_000, start + Duration::from_secs(5));
+        assert!(!estimate.stalled);
+        assert!(estimate.eta_seconds.is_some());
+
+        let estimate = estimator.record(500, 1_000, start + Duration::from_secs(20));
+        assert!(estimate.stalled);
+        assert_eq!(estimate.eta_seconds, None);
+        assert_eq!(estimate.smoothed_bps, 0.0);
+
+        let estimate = estimator.record(600, 1_000, start + Duration::from_secs(21));
+        assert!(!estimate.stalled);
+        assert_eq!(estimate.instantaneous_bps, 100.0);
+    }
+
     #[test]
     fn test_event_serialization() {
         let event = TransferEvent::Queued(TransferQueuedEvent {
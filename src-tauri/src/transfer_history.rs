@@ -0,0 +1,506 @@
+//! Persistent history of finished transfers.
+//!
+//! A record is appended whenever a transfer reaches a terminal state
+//! (completed, failed or canceled); live transfers stay in their own listings.
+//! History is stored as JSON lines in the data directory and trimmed to the
+//! configured retention policy.
+//!
+//! Download history is collected from the `TransferEventBus`: every bus built
+//! from an `AppHandle` that manages an `Arc<TransferHistory>` feeds it.
+
+use crate::transfer_events::{current_timestamp_ms, TransferEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::warn;
+
+/// Keep at most this many records by default
+pub const DEFAULT_MAX_ENTRIES: usize = 5000;
+
+/// Drop records older than this by default (90 days)
+pub const DEFAULT_MAX_AGE_MS: u64 = 90 * 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferDirection {
+    Download,
+    Upload,
+}
+
+/// Terminal state of a transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferState {
+    Completed,
+    Failed,
+    Canceled,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferRecord {
+    pub transfer_id: String,
+    pub content_hash: String,
+    pub file_name: String,
+    pub file_size: u64,
+    pub direction: TransferDirection,
+    /// Peers or sources that took part in the transfer
+    pub peers: Vec<String>,
+    pub duration_secs: u64,
+    pub average_rate_bps: f64,
+    /// Unix timestamp in milliseconds
+    pub finished_at: u64,
+    pub state: TransferState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Query filter for `TransferHistory::query`. Time bounds are inclusive Unix
+/// timestamps in milliseconds.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryFilter {
+    pub direction: Option<TransferDirection>,
+    pub state: Option<TransferState>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+impl HistoryFilter {
+    fn matches(&self, record: &TransferRecord) -> bool {
+        self.direction.map_or(true, |d| d == record.direction)
+            && self.state.map_or(true, |s| s == record.state)
+            && self.since.map_or(true, |t| record.finished_at >= t)
+            && self.until.map_or(true, |t| record.finished_at <= t)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionPolicy {
+    pub max_entries: usize,
+    pub max_age_ms: Option<u64>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_age_ms: Some(DEFAULT_MAX_AGE_MS),
+        }
+    }
+}
+
+/// What is known about a transfer between its start and terminal events
+#[derive(Debug, Clone)]
+struct InFlight {
+    file_hash: String,
+    file_name: String,
+    file_size: u64,
+    started_at: u64,
+    peers: Vec<String>,
+}
+
+pub struct TransferHistory {
+    path: PathBuf,
+    retention: RetentionPolicy,
+    records: Mutex<VecDeque<TransferRecord>>,
+    in_flight: Mutex<HashMap<String, InFlight>>,
+}
+
+impl TransferHistory {
+    /// Load history from `path`, skipping lines that fail to parse
+    pub fn load(path: PathBuf, retention: RetentionPolicy) -> Self {
+        let records = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .filter_map(|line| match serde_json::from_str(line) {
+                    Ok(record) => Some(record),
+                    Err(e) => {
+                        warn!("Skipping corrupted transfer history entry: {}", e);
+                        None
+                    }
+                })
+                .collect(),
+            Err(_) => VecDeque::new(),
+        };
+
+        let history = Self {
+            path,
+            retention,
+            records: Mutex::new(records),
+            in_flight: Mutex::new(HashMap::new()),
+        };
+        let pruned = {
+            let mut records = history.records.lock().unwrap();
+            history.prune(&mut records)
+        };
+        if pruned {
+            if let Err(e) = history.rewrite() {
+                warn!("Failed to compact transfer history: {}", e);
+            }
+        }
+        history
+    }
+
+    /// Default location inside the application data directory
+    pub fn default_path() -> PathBuf {
+        crate::data_dirs::DataDirs::current().transfer_history()
+    }
+
+    /// Append a finished transfer and persist it
+    pub fn record(&self, record: TransferRecord) -> Result<(), String> {
+        let line = serde_json::to_string(&record)
+            .map_err(|e| format!("Failed to serialize transfer record: {}", e))?;
+        let pruned = {
+            let mut records = self.records.lock().unwrap();
+            records.push_back(record);
+            self.prune(&mut records)
+        };
+
+        if pruned {
+            return self.rewrite();
+        }
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create history directory: {}", e))?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("Failed to open transfer history: {}", e))?;
+        writeln!(file, "{}", line).map_err(|e| format!("Failed to write transfer history: {}", e))
+    }
+
+    /// Records matching `filter`, newest first
+    pub fn query(&self, filter: &HistoryFilter, limit: usize, offset: usize) -> Vec<TransferRecord> {
+        let records = self.records.lock().unwrap();
+        records
+            .iter()
+            .rev()
+            .filter(|r| filter.matches(r))
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Delete all history, in memory and on disk
+    pub fn clear(&self) -> Result<(), String> {
+        self.records.lock().unwrap().clear();
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!("Failed to remove transfer history: {}", e)),
+        }
+    }
+
+    /// Track a transfer lifecycle event and record terminal states
+    pub fn observe(&self, event: &TransferEvent) {
+        let record = match event {
+            TransferEvent::Started(e) => {
+                self.in_flight.lock().unwrap().insert(
+                    e.transfer_id.clone(),
+                    InFlight {
+                        file_hash: e.file_hash.clone(),
+                        file_name: e.file_name.clone(),
+                        file_size: e.file_size,
+                        started_at: e.started_at,
+                        peers: e.selected_sources.clone(),
+                    },
+                );
+                return;
+            }
+            TransferEvent::SourceConnected(e) => {
+                if let Some(transfer) = self.in_flight.lock().unwrap().get_mut(&e.transfer_id) {
+                    if !transfer.peers.contains(&e.source_id) {
+                        transfer.peers.push(e.source_id.clone());
+                    }
+                }
+                return;
+            }
+            TransferEvent::Completed(e) => {
+                let in_flight = self.in_flight.lock().unwrap().remove(&e.transfer_id);
+                let mut peers: Vec<String> =
+                    e.sources_used.iter().map(|s| s.source_id.clone()).collect();
+                if peers.is_empty() {
+                    peers = in_flight.map(|t| t.peers).unwrap_or_default();
+                }
+                TransferRecord {
+                    transfer_id: e.transfer_id.clone(),
+                    content_hash: e.file_hash.clone(),
+                    file_name: e.file_name.clone(),
+                    file_size: e.file_size,
+                    direction: TransferDirection::Download,
+                    peers,
+                    duration_secs: e.duration_seconds,
+                    average_rate_bps: e.average_speed_bps,
+                    finished_at: e.completed_at,
+                    state: TransferState::Completed,
+                    error: None,
+                }
+            }
+            TransferEvent::Failed(e) => {
+                if e.retry_possible {
+                    return;
+                }
+                let in_flight = self.in_flight.lock().unwrap().remove(&e.transfer_id);
+                self.unfinished_record(
+                    &e.transfer_id,
+                    Some(&e.file_hash),
+                    in_flight,
+                    e.total_bytes,
+                    e.downloaded_bytes,
+                    e.failed_at,
+                    TransferState::Failed,
+                    Some(e.error.clone()),
+                )
+            }
+            TransferEvent::Canceled(e) => {
+                let in_flight = self.in_flight.lock().unwrap().remove(&e.transfer_id);
+                self.unfinished_record(
+                    &e.transfer_id,
+                    None,
+                    in_flight,
+                    e.total_bytes,
+                    e.downloaded_bytes,
+                    e.canceled_at,
+                    TransferState::Canceled,
+                    None,
+                )
+            }
+            _ => return,
+        };
+
+        if let Err(e) = self.record(record) {
+            warn!("Failed to record transfer history: {}", e);
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn unfinished_record(
+        &self,
+        transfer_id: &str,
+        file_hash: Option<&str>,
+        in_flight: Option<InFlight>,
+        total_bytes: u64,
+        transferred_bytes: u64,
+        finished_at: u64,
+        state: TransferState,
+        error: Option<String>,
+    ) -> TransferRecord {
+        let in_flight = in_flight.unwrap_or_else(|| InFlight {
+            file_hash: file_hash.unwrap_or(transfer_id).to_string(),
+            file_name: String::new(),
+            file_size: total_bytes,
+            started_at: finished_at,
+            peers: Vec::new(),
+        });
+        let duration_ms = finished_at.saturating_sub(in_flight.started_at);
+        let average_rate_bps = if duration_ms > 0 {
+            transferred_bytes as f64 * 1000.0 / duration_ms as f64
+        } else {
+            0.0
+        };
+
+        TransferRecord {
+            transfer_id: transfer_id.to_string(),
+            content_hash: file_hash.map(str::to_string).unwrap_or(in_flight.file_hash),
+            file_name: in_flight.file_name,
+            file_size: in_flight.file_size,
+            direction: TransferDirection::Download,
+            peers: in_flight.peers,
+            duration_secs: duration_ms / 1000,
+            average_rate_bps,
+            finished_at,
+            state,
+            error,
+        }
+    }
+
+    /// Apply the retention policy; returns whether anything was dropped
+    fn prune(&self, records: &mut VecDeque<TransferRecord>) -> bool {
+        let before = records.len();
+        if let Some(max_age) = self.retention.max_age_ms {
+            let cutoff = current_timestamp_ms().saturating_sub(max_age);
+            records.retain(|r| r.finished_at >= cutoff);
+        }
+        while records.len() > self.retention.max_entries {
+            records.pop_front();
+        }
+        records.len() != before
+    }
+
+    /// Rewrite the whole history file atomically
+    fn rewrite(&self) -> Result<(), String> {
+        let mut contents = String::new();
+        for record in self.records.lock().unwrap().iter() {
+            let line = serde_json::to_string(record)
+                .map_err(|e| format!("Failed to serialize transfer record: {}", e))?;
+            contents.push_str(&line);
+            contents.push('\n');
+        }
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create history directory: {}", e))?;
+        }
+        let tmp_path = self.path.with_extension("jsonl.tmp");
+        std::fs::write(&tmp_path, contents)
+            .map_err(|e| format!("Failed to write transfer history: {}", e))?;
+        std::fs::rename(&tmp_path, &self.path)
+            .map_err(|e| format!("Failed to replace transfer history: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transfer_events::{TransferCanceledEvent, TransferStartedEvent};
+
+    fn record(
+        id: &str,
+        direction: TransferDirection,
+        state: TransferState,
+        at: u64,
+    ) -> TransferRecord {
+        TransferRecord {
+            transfer_id: id.to_string(),
+            content_hash: format!("hash-{}", id),
+            file_name: format!("{}.bin", id),
+            file_size: 1024,
+            direction,
+            peers: vec!["peer-a".to_string()],
+            duration_secs: 2,
+            average_rate_bps: 512.0,
+            finished_at: at,
+            state,
+            error: None,
+        }
+    }
+
+    fn no_age_limit(max_entries: usize) -> RetentionPolicy {
+        RetentionPolicy {
+            max_entries,
+            max_age_ms: None,
+        }
+    }
+
+    #[test]
+    fn test_history_survives_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+
+        let history = TransferHistory::load(path.clone(), no_age_limit(10));
+        history
+            .record(record("a", TransferDirection::Download, TransferState::Completed, 100))
+            .unwrap();
+        history
+            .record(record("b", TransferDirection::Upload, TransferState::Failed, 200))
+            .unwrap();
+
+        let reloaded = TransferHistory::load(path, no_age_limit(10));
+        let all = reloaded.query(&HistoryFilter::default(), 10, 0);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].transfer_id, "b");
+    }
+
+    #[test]
+    fn test_query_filters_and_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = TransferHistory::load(dir.path().join("h.jsonl"), no_age_limit(10));
+        for (i, direction) in [TransferDirection::Download, TransferDirection::Upload]
+            .iter()
+            .cycle()
+            .take(6)
+            .enumerate()
+        {
+            let entry = record(&i.to_string(), *direction, TransferState::Completed, i as u64 * 10);
+            history.record(entry).unwrap();
+        }
+
+        let downloads = HistoryFilter {
+            direction: Some(TransferDirection::Download),
+            ..Default::default()
+        };
+        let ids: Vec<_> = history
+            .query(&downloads, 10, 0)
+            .into_iter()
+            .map(|r| r.transfer_id)
+            .collect();
+        assert_eq!(ids, vec!["4", "2", "0"]);
+
+        let window = HistoryFilter {
+            since: Some(10),
+            until: Some(30),
+            ..Default::default()
+        };
+        assert_eq!(history.query(&window, 10, 0).len(), 3);
+        assert_eq!(history.query(&window, 1, 1)[0].transfer_id, "2");
+    }
+
+    #[test]
+    fn test_retention_cap_and_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("h.jsonl");
+        let history = TransferHistory::load(path.clone(), no_age_limit(2));
+        for i in 0..3 {
+            let entry = record(
+                &i.to_string(),
+                TransferDirection::Download,
+                TransferState::Completed,
+                i,
+            );
+            history.record(entry).unwrap();
+        }
+        assert_eq!(history.query(&HistoryFilter::default(), 10, 0).len(), 2);
+        assert_eq!(
+            TransferHistory::load(path.clone(), no_age_limit(2))
+                .query(&HistoryFilter::default(), 10, 0)
+                .len(),
+            2
+        );
+
+        history.clear().unwrap();
+        assert!(history.query(&HistoryFilter::default(), 10, 0).is_empty());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_observe_records_terminal_events_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = TransferHistory::load(dir.path().join("h.jsonl"), no_age_limit(10));
+
+        history.observe(&TransferEvent::Started(TransferStartedEvent {
+            transfer_id: "t1".to_string(),
+            file_hash: "abc".to_string(),
+            file_name: "movie.mkv".to_string(),
+            file_size: 4096,
+            total_chunks: 1,
+            chunk_size: 4096,
+            started_at: 1_000,
+            available_sources: Vec::new(),
+            selected_sources: vec!["peer-a".to_string()],
+        }));
+        assert!(history.query(&HistoryFilter::default(), 10, 0).is_empty());
+
+        history.observe(&TransferEvent::Canceled(TransferCanceledEvent {
+            transfer_id: "t1".to_string(),
+            canceled_at: 3_000,
+            downloaded_bytes: 2048,
+            total_bytes: 4096,
+            keep_partial: false,
+        }));
+        let records = history.query(&HistoryFilter::default(), 10, 0);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].file_name, "movie.mkv");
+        assert_eq!(records[0].content_hash, "abc");
+        assert_eq!(records[0].state, TransferState::Canceled);
+        assert_eq!(records[0].duration_secs, 2);
+        assert_eq!(records[0].average_rate_bps, 1024.0);
+    }
+}
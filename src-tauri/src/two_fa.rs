@@ -1,7 +1,7 @@
 use std::fs;
 use std::path::PathBuf;
 use std::sync::Mutex;
-use tauri::{Manager, State};
+use tauri::State;
 use totp_rs::{Algorithm, Secret, TOTP};
 use aes_gcm::{
     aead::{Aead, KeyInit, OsRng},
@@ -24,13 +24,8 @@ pub struct TotpSetupInfo {
 
 // Helper to get the file path for a 2FA secret.
 // It uses a hash of the address for the filename to avoid issues with special characters.
-fn get_2fa_file_path(app_handle: &tauri::AppHandle, address: &str) -> Result<PathBuf, String> {
-    let app_data_dir = app_handle
-        .path()
-        .app_data_dir()
-        .ok_or_else(|| "Could not get app data directory".to_string())?;
-
-    let two_fa_dir = app_data_dir.join("2fa_secrets");
+fn get_2fa_file_path(address: &str) -> Result<PathBuf, String> {
+    let two_fa_dir = crate::DataDirs::current().two_fa_secrets();
     fs::create_dir_all(&two_fa_dir).map_err(|e| format!("Failed to create 2FA directory: {}", e))?;
 
     let filename = format!("{:x}", sha256::digest(address.as_bytes()));
@@ -102,11 +97,10 @@ fn decrypt_2fa_secret(encrypted_hex: &str, password: &str, salt: &[u8]) -> Resul
 /// Checks if 2FA is enabled for the currently active account.
 #[tauri::command]
 pub fn is_2fa_enabled(
-    app_handle: tauri::AppHandle,
     active_account: State<'_, ActiveAccount>,
 ) -> Result<bool, String> {
     let address = get_active_address(&active_account)?;
-    let path = get_2fa_file_path(&app_handle, &address)?;
+    let path = get_2fa_file_path(&address)?;
     Ok(path.exists())
 }
 
@@ -143,7 +137,6 @@ pub fn verify_and_enable_totp(
     secret: String,
     code: String,
     password: String,
-    app_handle: tauri::AppHandle,
     active_account: State<'_, ActiveAccount>,
 ) -> Result<bool, String> {
     let address = get_active_address(&active_account)?;
@@ -156,7 +149,7 @@ pub fn verify_and_enable_totp(
     let totp = TOTP::new(Algorithm::SHA256, 6, 1, 30, secret_bytes).map_err(|e| e.to_string())?;
 
     if totp.check_current(&code).unwrap_or(false) {
-        let path = get_2fa_file_path(&app_handle, &address)?;
+        let path = get_2fa_file_path(&address)?;
         // SECURITY: Encrypt the 2FA secret using AES-256-GCM with password-derived key
         let salt = generate_random_salt();
         let encrypted_secret = encrypt_2fa_secret(&secret, password, &salt)?;
@@ -176,12 +169,11 @@ pub fn verify_and_enable_totp(
 pub fn verify_totp_code(
     code: String,
     password: String,
-    app_handle: tauri::AppHandle,
     active_account: State<'_, ActiveAccount>,
 ) -> Result<bool, String> {
     let address = get_active_address(&active_account)?;
 
-    let path = get_2fa_file_path(&app_handle, &address)?;
+    let path = get_2fa_file_path(&address)?;
     if !path.exists() {
         return Err("2FA is not enabled for this account.".to_string());
     }
@@ -212,10 +204,10 @@ pub fn verify_totp_code(
 
 /// Disables 2FA by deleting the stored secret.
 #[tauri::command]
-pub fn disable_2fa(app_handle: tauri::AppHandle, active_account: State<'_, ActiveAccount>) -> Result<(), String> {
+pub fn disable_2fa(active_account: State<'_, ActiveAccount>) -> Result<(), String> {
     let address = get_active_address(&active_account)?;
 
-    let path = get_2fa_file_path(&app_handle, &address)?;
+    let path = get_2fa_file_path(&address)?;
     if path.exists() {
         fs::remove_file(&path).map_err(|e| format!("Failed to remove 2FA secret: {}", e))?;
     }
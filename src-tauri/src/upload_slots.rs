@@ -0,0 +1,645 @@
+// Upload slot limiting with fair queuing across requesting peers
+//
+// Without a limit a single peer can open many transfer streams and take the
+// whole uplink. A fixed number of upload slots bounds how many requests are
+// served at once. Requests beyond that wait in a bounded queue and are
+// admitted round-robin across distinct peers, so a peer with many queued
+// requests cannot starve one with a single request. When even the queue is
+// full the request is refused with a retry-after hint. Trusted peers can be
+// given extra slots that other peers never use. Only authenticated peer ids
+// (WebRTC) count as trusted; HTTP requests are keyed on the client address
+// and never use the reserved slots.
+//
+// Every serving path (HTTP and WebRTC) goes through `acquire_upload`, which
+// also refuses content that is stale or past its seeding limit and counts
+// the bytes sent toward that limit.
+
+use crate::shared_files::{ServeRefusal, SharedFilesRegistry, UploadGuard};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tracing::debug;
+
+/// Requests served at once for ordinary peers
+pub const DEFAULT_UPLOAD_SLOTS: usize = 8;
+
+/// Requests allowed to wait for a slot
+pub const DEFAULT_UPLOAD_QUEUE: usize = 64;
+
+/// Suggested wait before a refused requester tries again
+pub const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(10);
+
+/// Prefix of unauthenticated requesters in the stats and queues, so they
+/// never share an entry with a peer id
+pub const ANONYMOUS_PREFIX: &str = "anonymous:";
+
+/// Requesters kept in the per-peer stats; idle ones that were served least
+/// make room for new ones
+pub const MAX_TRACKED_PEERS: usize = 256;
+
+#[derive(Debug, Clone)]
+pub struct UploadSlotConfig {
+    pub slots: usize,
+    /// Additional slots only trusted peers may use
+    pub reserved_slots: usize,
+    pub queue_capacity: usize,
+    pub retry_after: Duration,
+    pub trusted_peers: HashSet<String>,
+}
+
+impl Default for UploadSlotConfig {
+    fn default() -> Self {
+        Self {
+            slots: DEFAULT_UPLOAD_SLOTS,
+            reserved_slots: 0,
+            queue_capacity: DEFAULT_UPLOAD_QUEUE,
+            retry_after: DEFAULT_RETRY_AFTER,
+            trusted_peers: HashSet::new(),
+        }
+    }
+}
+
+/// The slot pool and the request queue are both full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("upload slots busy, retry after {}s", retry_after.as_secs())]
+pub struct SlotsBusy {
+    pub retry_after: Duration,
+}
+
+/// Upload counters for one requesting peer
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerUploadStats {
+    pub peer_id: String,
+    pub active: usize,
+    pub queued: usize,
+    pub requests_served: u64,
+    pub bytes_served: u64,
+    /// Requests refused because the queue was full
+    pub requests_refused: u64,
+    pub trusted: bool,
+}
+
+/// Snapshot of the limiter for the serving stats
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadSlotStats {
+    pub slots: usize,
+    pub reserved_slots: usize,
+    pub active: usize,
+    pub queued: usize,
+    pub queue_capacity: usize,
+    pub peers: Vec<PeerUploadStats>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SlotKind {
+    General,
+    Reserved,
+}
+
+#[derive(Default)]
+struct LimiterState {
+    active_general: usize,
+    active_reserved: usize,
+    /// Waiters per peer, oldest first
+    waiting: HashMap<String, VecDeque<oneshot::Sender<UploadSlot>>>,
+    /// Peers with waiters, in the order they are next admitted
+    rotation: VecDeque<String>,
+    peers: HashMap<String, PeerUploadStats>,
+}
+
+impl LimiterState {
+    fn queued(&self) -> usize {
+        self.waiting.values().map(VecDeque::len).sum()
+    }
+
+    /// Make room for a new requester by dropping the idle one served least
+    fn make_room(&mut self) {
+        if self.peers.len() < MAX_TRACKED_PEERS {
+            return;
+        }
+        let idle = self
+            .peers
+            .values()
+            .filter(|stats| stats.active == 0 && stats.queued == 0)
+            .min_by_key(|stats| (stats.bytes_served, stats.requests_served))
+            .map(|stats| stats.peer_id.clone());
+        if let Some(peer) = idle {
+            self.peers.remove(&peer);
+        }
+    }
+
+    /// Drop waiters whose request was abandoned
+    fn purge_abandoned(&mut self) {
+        for (peer, queue) in self.waiting.iter_mut() {
+            queue.retain(|tx| !tx.is_closed());
+            if let Some(stats) = self.peers.get_mut(peer) {
+                stats.queued = queue.len();
+            }
+        }
+        self.waiting.retain(|_, queue| !queue.is_empty());
+        let waiting = &self.waiting;
+        self.rotation.retain(|peer| waiting.contains_key(peer));
+    }
+}
+
+/// Why `acquire_upload` did not admit a request
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum UploadRefusal {
+    #[error("{0}")]
+    Busy(SlotsBusy),
+    #[error("{0}")]
+    Refused(ServeRefusal),
+}
+
+/// Shared upload slot pool; clones refer to the same pool
+#[derive(Clone)]
+pub struct UploadSlotLimiter {
+    config: Arc<Mutex<UploadSlotConfig>>,
+    state: Arc<Mutex<LimiterState>>,
+    /// Seeding limits and stale checks for `acquire_upload`
+    shared_files: Option<Arc<SharedFilesRegistry>>,
+}
+
+impl Default for UploadSlotLimiter {
+    fn default() -> Self {
+        Self::new(UploadSlotConfig::default())
+    }
+}
+
+impl UploadSlotLimiter {
+    pub fn new(config: UploadSlotConfig) -> Self {
+        Self {
+            config: Arc::new(Mutex::new(config)),
+            state: Arc::new(Mutex::new(LimiterState::default())),
+            shared_files: None,
+        }
+    }
+
+    /// Check uploads from `acquire_upload` against `registry`
+    pub fn with_shared_files(mut self, registry: Arc<SharedFilesRegistry>) -> Self {
+        self.shared_files = Some(registry);
+        self
+    }
+
+    /// Take a slot for the authenticated `peer_id` to upload `content_hash`.
+    /// Content that is stale or past its seeding limit is refused before a
+    /// slot is taken; content the registry does not know (e.g. seeded before
+    /// it existed) is served as before.
+    pub async fn acquire_upload(
+        &self,
+        peer_id: &str,
+        content_hash: &str,
+    ) -> Result<ContentUpload, UploadRefusal> {
+        self.acquire_content(peer_id, true, content_hash).await
+    }
+
+    /// `acquire_upload` for a requester that proved nothing about who it
+    /// is, such as an HTTP client keyed on its address. It is queued as
+    /// `ANONYMOUS_PREFIX` + `requester` and never trusted.
+    pub async fn acquire_anonymous_upload(
+        &self,
+        requester: &str,
+        content_hash: &str,
+    ) -> Result<ContentUpload, UploadRefusal> {
+        let requester = format!("{}{}", ANONYMOUS_PREFIX, requester);
+        self.acquire_content(&requester, false, content_hash).await
+    }
+
+    async fn acquire_content(
+        &self,
+        peer_id: &str,
+        authenticated: bool,
+        content_hash: &str,
+    ) -> Result<ContentUpload, UploadRefusal> {
+        let guard = match &self.shared_files {
+            Some(registry) => match registry.begin_upload(content_hash).await {
+                Ok(guard) => Some((registry.clone(), guard)),
+                Err(ServeRefusal::NotShared) => None,
+                Err(refusal) => return Err(UploadRefusal::Refused(refusal)),
+            },
+            None => None,
+        };
+        let slot = self
+            .acquire_as(peer_id, authenticated)
+            .await
+            .map_err(UploadRefusal::Busy)?;
+        Ok(ContentUpload {
+            slot,
+            guard,
+            content_hash: content_hash.to_string(),
+        })
+    }
+
+    fn config(&self) -> std::sync::MutexGuard<'_, UploadSlotConfig> {
+        self.config.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LimiterState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Grant or revoke access to the reserved slots for `peer_id`
+    pub fn set_trusted(&self, peer_id: &str, trusted: bool) {
+        let mut config = self.config();
+        if trusted {
+            config.trusted_peers.insert(peer_id.to_string());
+        } else {
+            config.trusted_peers.remove(peer_id);
+        }
+    }
+
+    /// Take a slot for a request from the authenticated `peer_id`, waiting
+    /// in the queue if needed
+    pub async fn acquire(&self, peer_id: &str) -> Result<UploadSlot, SlotsBusy> {
+        self.acquire_as(peer_id, true).await
+    }
+
+    async fn acquire_as(&self, peer_id: &str, authenticated: bool) -> Result<UploadSlot, SlotsBusy> {
+        let receiver = {
+            let config = self.config().clone();
+            let trusted = authenticated && config.trusted_peers.contains(peer_id);
+            let mut guard = self.lock();
+            let state = &mut *guard;
+            state.purge_abandoned();
+            let queued = state.queued();
+            if !state.peers.contains_key(peer_id) {
+                state.make_room();
+            }
+
+            let stats = state
+                .peers
+                .entry(peer_id.to_string())
+                .or_insert_with(|| PeerUploadStats {
+                    peer_id: peer_id.to_string(),
+                    ..Default::default()
+                });
+            stats.trusted = trusted;
+
+            if trusted && state.active_reserved < config.reserved_slots {
+                stats.active += 1;
+                state.active_reserved += 1;
+                return Ok(self.slot(peer_id, SlotKind::Reserved, true));
+            }
+            // Nobody may jump the queue while other requests are waiting
+            if state.active_general < config.slots && queued == 0 {
+                stats.active += 1;
+                state.active_general += 1;
+                return Ok(self.slot(peer_id, SlotKind::General, true));
+            }
+
+            if queued >= config.queue_capacity {
+                stats.requests_refused += 1;
+                debug!("Upload queue full, refusing request from {}", peer_id);
+                return Err(SlotsBusy {
+                    retry_after: config.retry_after,
+                });
+            }
+
+            stats.queued += 1;
+            let (tx, rx) = oneshot::channel();
+            let queue = state.waiting.entry(peer_id.to_string()).or_default();
+            queue.push_back(tx);
+            if queue.len() == 1 {
+                state.rotation.push_back(peer_id.to_string());
+            }
+            rx
+        };
+
+        match receiver.await {
+            Ok(mut slot) => {
+                slot.admitted = true;
+                Ok(slot)
+            }
+            // The limiter only drops waiters it no longer tracks
+            Err(_) => Err(SlotsBusy {
+                retry_after: self.config().retry_after,
+            }),
+        }
+    }
+
+    fn slot(&self, peer_id: &str, kind: SlotKind, admitted: bool) -> UploadSlot {
+        UploadSlot {
+            limiter: self.clone(),
+            peer_id: peer_id.to_string(),
+            kind,
+            bytes: 0,
+            admitted,
+        }
+    }
+
+    /// Take the next waiter a freed slot of `kind` goes to, in rotation order.
+    ///
+    /// A reserved slot only goes to a trusted peer; a general one to anyone.
+    fn next_waiter(
+        state: &mut LimiterState,
+        kind: SlotKind,
+        config: &UploadSlotConfig,
+    ) -> Option<(String, oneshot::Sender<UploadSlot>)> {
+        let mut skipped = 0;
+        while skipped < state.rotation.len() {
+            let next = state.rotation.pop_front()?;
+            if kind == SlotKind::Reserved && !config.trusted_peers.contains(&next) {
+                state.rotation.push_back(next);
+                skipped += 1;
+                continue;
+            }
+
+            let Some(queue) = state.waiting.get_mut(&next) else {
+                continue;
+            };
+            let mut waiter = None;
+            while let Some(tx) = queue.pop_front() {
+                if !tx.is_closed() {
+                    waiter = Some(tx);
+                    break;
+                }
+            }
+            let remaining = queue.len();
+            if remaining == 0 {
+                state.waiting.remove(&next);
+            } else {
+                state.rotation.push_back(next.clone());
+            }
+            if let Some(stats) = state.peers.get_mut(&next) {
+                stats.queued = remaining;
+            }
+
+            if let Some(tx) = waiter {
+                if let Some(stats) = state.peers.get_mut(&next) {
+                    stats.active += 1;
+                }
+                match kind {
+                    SlotKind::General => state.active_general += 1,
+                    SlotKind::Reserved => state.active_reserved += 1,
+                }
+                return Some((next, tx));
+            }
+        }
+        None
+    }
+
+    /// Free a slot and hand it to the next waiting peer
+    fn release(&self, peer_id: &str, kind: SlotKind, bytes: u64, admitted: bool) {
+        let config = self.config().clone();
+        let handoff = {
+            let mut guard = self.lock();
+            let state = &mut *guard;
+            if let Some(stats) = state.peers.get_mut(peer_id) {
+                stats.active = stats.active.saturating_sub(1);
+                if admitted {
+                    stats.requests_served += 1;
+                    stats.bytes_served += bytes;
+                }
+            }
+            match kind {
+                SlotKind::General => state.active_general = state.active_general.saturating_sub(1),
+                SlotKind::Reserved => state.active_reserved = state.active_reserved.saturating_sub(1),
+            }
+            Self::next_waiter(state, kind, &config)
+        };
+
+        // Sent outside the lock: if the waiter went away meanwhile, the slot
+        // comes back, is dropped, and frees itself for the next waiter
+        if let Some((peer, tx)) = handoff {
+            let _ = tx.send(self.slot(&peer, kind, false));
+        }
+    }
+
+    pub fn stats(&self) -> UploadSlotStats {
+        let config = self.config().clone();
+        let state = self.lock();
+        let mut peers: Vec<PeerUploadStats> = state.peers.values().cloned().collect();
+        peers.sort_by(|a, b| b.bytes_served.cmp(&a.bytes_served).then(a.peer_id.cmp(&b.peer_id)));
+        UploadSlotStats {
+            slots: config.slots,
+            reserved_slots: config.reserved_slots,
+            active: state.active_general + state.active_reserved,
+            queued: state.queued(),
+            queue_capacity: config.queue_capacity,
+            peers,
+        }
+    }
+}
+
+/// An upload slot held for the duration of one request; dropping it frees the slot
+pub struct UploadSlot {
+    limiter: UploadSlotLimiter,
+    peer_id: String,
+    kind: SlotKind,
+    bytes: u64,
+    /// False while the slot is in transit to a queued requester
+    admitted: bool,
+}
+
+impl UploadSlot {
+    pub fn peer_id(&self) -> &str {
+        &self.peer_id
+    }
+
+    /// Count bytes sent under this slot toward the peer's stats
+    pub fn record_bytes(&mut self, bytes: u64) {
+        self.bytes += bytes;
+    }
+}
+
+impl Drop for UploadSlot {
+    fn drop(&mut self) {
+        self.limiter
+            .release(&self.peer_id, self.kind, self.bytes, self.admitted);
+    }
+}
+
+/// An upload of one shared file under an upload slot
+pub struct ContentUpload {
+    slot: UploadSlot,
+    guard: Option<(Arc<SharedFilesRegistry>, UploadGuard)>,
+    content_hash: String,
+}
+
+impl ContentUpload {
+    pub fn record_bytes(&mut self, bytes: u64) {
+        self.slot.record_bytes(bytes);
+    }
+
+    /// True when the file was unshared without letting in-flight uploads finish
+    pub fn is_cancelled(&self) -> bool {
+        self.guard
+            .as_ref()
+            .is_some_and(|(_, guard)| guard.is_cancelled())
+    }
+
+    /// Free the slot and count the recorded bytes toward the file's seeding
+    /// limits
+    pub async fn finish(self) {
+        let bytes = self.slot.bytes;
+        drop(self.slot);
+        if let Some((registry, guard)) = self.guard {
+            if bytes > 0 && !guard.is_cancelled() {
+                registry.record_upload(&self.content_hash, bytes).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(slots: usize, reserved_slots: usize, queue_capacity: usize) -> UploadSlotLimiter {
+        UploadSlotLimiter::new(UploadSlotConfig {
+            slots,
+            reserved_slots,
+            queue_capacity,
+            trusted_peers: ["friend".to_string()].into_iter().collect(),
+            ..Default::default()
+        })
+    }
+
+    /// Queue a request from `peer` that records the order it was admitted in
+    fn request(
+        limiter: &UploadSlotLimiter,
+        peer: &'static str,
+        order: Arc<Mutex<Vec<&'static str>>>,
+    ) -> tokio::task::JoinHandle<()> {
+        let limiter = limiter.clone();
+        tokio::spawn(async move {
+            let _slot = limiter.acquire(peer).await.unwrap();
+            order.lock().unwrap().push(peer);
+        })
+    }
+
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    #[tokio::test]
+    async fn test_queued_requests_are_admitted_round_robin() {
+        let limiter = limiter(1, 0, 16);
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let blocker = limiter.acquire("greedy").await.unwrap();
+        let mut tasks = Vec::new();
+        // The greedy peer queues several requests before the others show up
+        for _ in 0..3 {
+            tasks.push(request(&limiter, "greedy", order.clone()));
+            settle().await;
+        }
+        tasks.push(request(&limiter, "a", order.clone()));
+        settle().await;
+        tasks.push(request(&limiter, "b", order.clone()));
+        settle().await;
+        assert_eq!(limiter.stats().queued, 5);
+
+        drop(blocker);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec!["greedy", "a", "b", "greedy", "greedy"]
+        );
+
+        let stats = limiter.stats();
+        assert_eq!((stats.active, stats.queued), (0, 0));
+        let greedy = stats.peers.iter().find(|p| p.peer_id == "greedy").unwrap();
+        assert_eq!(greedy.requests_served, 4);
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_refuses_and_reserved_slots_serve_trusted_peers() {
+        let limiter = limiter(1, 1, 1);
+
+        let mut first = limiter.acquire("a").await.unwrap();
+        first.record_bytes(1024);
+        let queued = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire("b").await.map(|_| ()) })
+        };
+        settle().await;
+
+        let refused = limiter.acquire("c").await.err().unwrap();
+        assert_eq!(refused.retry_after, DEFAULT_RETRY_AFTER);
+
+        // The trusted peer skips the queue into its reserved slot
+        let trusted = limiter.acquire("friend").await.unwrap();
+        assert_eq!(limiter.stats().active, 2);
+        drop(trusted);
+        assert_eq!(limiter.stats().queued, 1, "a reserved slot is not given to b");
+
+        drop(first);
+        queued.await.unwrap().unwrap();
+
+        let stats = limiter.stats();
+        let peer = |id: &str| stats.peers.iter().find(|p| p.peer_id == id).unwrap().clone();
+        assert_eq!(peer("a").bytes_served, 1024);
+        assert_eq!(peer("c").requests_refused, 1);
+        assert!(peer("friend").trusted);
+        assert_eq!(stats.peers[0].peer_id, "a");
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_requesters_are_never_trusted_and_stats_stay_bounded() {
+        let limiter = limiter(1, 1, 4);
+
+        let first = limiter.acquire_upload("a", "content").await.unwrap();
+        // Claiming the trusted id without authenticating only queues
+        let claimed = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire_anonymous_upload("friend", "content").await.map(|_| ()) })
+        };
+        settle().await;
+        assert_eq!(limiter.stats().queued, 1);
+        let trusted = limiter.acquire("friend").await.unwrap();
+        drop(trusted);
+        assert_eq!(limiter.stats().queued, 1, "a reserved slot is not given to the claimed id");
+        drop(first);
+        claimed.await.unwrap().unwrap();
+
+        // Rotating requester keys cannot grow the stats without bound
+        for n in 0..2 * MAX_TRACKED_PEERS {
+            drop(limiter.acquire_anonymous_upload(&format!("10.0.0.{}", n), "content").await.unwrap());
+        }
+        assert_eq!(limiter.stats().peers.len(), MAX_TRACKED_PEERS);
+    }
+
+    #[tokio::test]
+    async fn test_uploads_count_toward_seeding_limits() {
+        use crate::shared_files::{SeedingLimitReason, SeedingLimits};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let registry = Arc::new(SharedFilesRegistry::load(dir.path().join("registry.json")));
+        let path = dir.path().join("shared.bin");
+        tokio::fs::write(&path, b"0123456789").await.unwrap();
+        let hash = crate::shared_files::hash_file(&path).await.unwrap();
+        registry
+            .register(hash.clone(), path.clone(), path, "shared.bin".to_string(), None)
+            .await
+            .unwrap();
+        registry
+            .set_seeding_limits(SeedingLimits {
+                max_upload_bytes: Some(10),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let limiter = limiter(1, 0, 1).with_shared_files(registry.clone());
+
+        let mut upload = limiter.acquire_upload("a", &hash).await.unwrap();
+        upload.record_bytes(10);
+        upload.finish().await;
+        assert_eq!(registry.get(&hash).await.unwrap().total_bytes_uploaded, 10);
+        assert_eq!(limiter.stats().active, 0);
+        assert_eq!(
+            limiter.acquire_upload("a", &hash).await.err(),
+            Some(UploadRefusal::Refused(ServeRefusal::LimitReached(
+                SeedingLimitReason::UploadCap
+            )))
+        );
+        // Content the registry does not know is only slot limited
+        assert!(limiter.acquire_upload("a", "unknown").await.is_ok());
+    }
+}
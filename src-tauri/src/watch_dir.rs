@@ -0,0 +1,615 @@
+// Watch directory
+//
+// Files dropped into a configured directory are published automatically. A
+// file is only published once it has stopped changing for a settle period, so
+// a file that is still being written is not hashed half-way. Include/exclude
+// globs are matched against the path relative to the directory. Entries whose
+// files are deleted are un-announced.
+//
+// What was published from which path (with the size and mtime at the time) is
+// persisted alongside the configuration, so a restart does not re-publish
+// unchanged files and files deleted while the node was down are noticed.
+//
+// `run_watch_loop` is the watcher shared by the app's watch directory and the
+// headless `--seed-dir` mode; each supplies its own `WatchActions` to publish
+// and un-announce files.
+
+use async_trait::async_trait;
+use glob::Pattern;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tokio::sync::{mpsc, Mutex};
+use tracing::warn;
+
+pub type WatchEvent = notify::Result<notify::Event>;
+
+/// How long a file must stay unchanged before it is published
+pub const DEFAULT_SETTLE_SECS: u64 = 5;
+
+/// How often pending files are re-checked for changes
+pub const WATCH_TICK: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchDirConfig {
+    /// Directory to watch; `None` disables watching
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Globs a file must match to be published; empty matches everything
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Globs excluding files even if they match `include`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default = "default_settle_secs")]
+    pub settle_secs: u64,
+}
+
+fn default_settle_secs() -> u64 {
+    DEFAULT_SETTLE_SECS
+}
+
+impl Default for WatchDirConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            settle_secs: DEFAULT_SETTLE_SECS,
+        }
+    }
+}
+
+/// A file published from the watch directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishedWatchFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified_at: u64,
+    pub content_hash: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WatchDirFile {
+    #[serde(default)]
+    config: WatchDirConfig,
+    #[serde(default)]
+    published: Vec<PublishedWatchFile>,
+}
+
+/// Configuration and published files as persisted on disk
+pub fn load(path: &Path) -> (WatchDirConfig, Vec<PublishedWatchFile>) {
+    let file: WatchDirFile = match std::fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+            warn!("Ignoring unreadable watch directory state {}: {}", path.display(), e);
+            WatchDirFile::default()
+        }),
+        Err(_) => WatchDirFile::default(),
+    };
+    (file.config, file.published)
+}
+
+pub fn save(path: &Path, config: &WatchDirConfig, published: Vec<PublishedWatchFile>) -> Result<(), String> {
+    let file = WatchDirFile {
+        config: config.clone(),
+        published,
+    };
+    let json = serde_json::to_string_pretty(&file).map_err(|e| e.to_string())?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    std::fs::write(path, json).map_err(|e| format!("Failed to save watch directory state: {}", e))
+}
+
+pub fn default_path() -> PathBuf {
+    crate::data_dirs::DataDirs::current().watch_dir()
+}
+
+/// Include/exclude globs, matched against paths relative to the watch directory
+#[derive(Debug, Clone)]
+pub struct WatchFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl WatchFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, String> {
+        let compile = |globs: &[String]| {
+            globs
+                .iter()
+                .map(|g| Pattern::new(g).map_err(|e| format!("Invalid glob '{}': {}", g, e)))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    /// Whether the file at `relative` should be published. Hidden files, such
+    /// as the temporary files many tools write before renaming, never are.
+    pub fn matches(&self, relative: &Path) -> bool {
+        let hidden = relative
+            .components()
+            .any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+        if hidden {
+            return false;
+        }
+        let included =
+            self.include.is_empty() || self.include.iter().any(|p| p.matches_path(relative));
+        included && !self.exclude.iter().any(|p| p.matches_path(relative))
+    }
+}
+
+/// Publish state of a file in the watch directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "camelCase")]
+pub enum WatchFileState {
+    /// Waiting for the file to stop changing
+    Settling,
+    Publishing,
+    #[serde(rename_all = "camelCase")]
+    Published { content_hash: String },
+    /// Publishing failed; retried once the file changes
+    Failed { error: String },
+}
+
+#[derive(Debug, Clone)]
+struct TrackedFile {
+    size: u64,
+    modified_at: u64,
+    changed_at: Instant,
+    state: WatchFileState,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchedFileReport {
+    pub path: String,
+    pub size: u64,
+    #[serde(flatten)]
+    pub state: WatchFileState,
+}
+
+/// Reported by `get_watch_status`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchStatus {
+    pub config: WatchDirConfig,
+    pub watching: bool,
+    /// Why the directory is not being watched, if it is configured
+    pub error: Option<String>,
+    pub files: Vec<WatchedFileReport>,
+}
+
+pub fn modified_secs(meta: &std::fs::Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Files seen in the watch directory and how far each got towards publishing
+#[derive(Debug)]
+pub struct WatchTracker {
+    files: HashMap<PathBuf, TrackedFile>,
+    settle: Duration,
+}
+
+impl WatchTracker {
+    pub fn new(settle: Duration) -> Self {
+        Self {
+            files: HashMap::new(),
+            settle,
+        }
+    }
+
+    /// Start from files published before a restart
+    pub fn restore(&mut self, published: Vec<PublishedWatchFile>, now: Instant) {
+        for file in published {
+            self.files.insert(
+                file.path,
+                TrackedFile {
+                    size: file.size,
+                    modified_at: file.modified_at,
+                    changed_at: now,
+                    state: WatchFileState::Published {
+                        content_hash: file.content_hash,
+                    },
+                },
+            );
+        }
+    }
+
+    /// The file at `path` was seen with this size and mtime. A file that
+    /// differs from what was last seen starts settling again; published
+    /// content that changed is returned so it can be un-announced.
+    pub fn observe(&mut self, path: &Path, size: u64, modified_at: u64, now: Instant) -> Option<String> {
+        if let Some(file) = self.files.get_mut(path) {
+            if file.size == size && file.modified_at == modified_at {
+                return None;
+            }
+            let previous = std::mem::replace(&mut file.state, WatchFileState::Settling);
+            file.size = size;
+            file.modified_at = modified_at;
+            file.changed_at = now;
+            return match previous {
+                WatchFileState::Published { content_hash } => Some(content_hash),
+                _ => None,
+            };
+        }
+        self.files.insert(
+            path.to_path_buf(),
+            TrackedFile {
+                size,
+                modified_at,
+                changed_at: now,
+                state: WatchFileState::Settling,
+            },
+        );
+        None
+    }
+
+    /// Files that have been unchanged for the settle period; they are marked
+    /// as publishing
+    pub fn take_settled(&mut self, now: Instant) -> Vec<PathBuf> {
+        let mut settled = Vec::new();
+        for (path, file) in self.files.iter_mut() {
+            if file.state == WatchFileState::Settling
+                && now.duration_since(file.changed_at) >= self.settle
+            {
+                file.state = WatchFileState::Publishing;
+                settled.push(path.clone());
+            }
+        }
+        settled.sort();
+        settled
+    }
+
+    /// Files still settling, to be re-checked on disk
+    pub fn settling(&self) -> Vec<PathBuf> {
+        self.files
+            .iter()
+            .filter(|(_, f)| f.state == WatchFileState::Settling)
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.files.keys().cloned().collect()
+    }
+
+    pub fn mark_published(&mut self, path: &Path, content_hash: String) {
+        if let Some(file) = self.files.get_mut(path) {
+            if file.state == WatchFileState::Publishing {
+                file.state = WatchFileState::Published { content_hash };
+            }
+        }
+    }
+
+    pub fn mark_failed(&mut self, path: &Path, error: String) {
+        if let Some(file) = self.files.get_mut(path) {
+            if file.state == WatchFileState::Publishing {
+                file.state = WatchFileState::Failed { error };
+            }
+        }
+    }
+
+    /// Forget a deleted file; returns its content hash if it was published
+    pub fn remove(&mut self, path: &Path) -> Option<String> {
+        match self.files.remove(path)?.state {
+            WatchFileState::Published { content_hash } => Some(content_hash),
+            _ => None,
+        }
+    }
+
+    pub fn published(&self) -> Vec<PublishedWatchFile> {
+        let mut published: Vec<PublishedWatchFile> = self
+            .files
+            .iter()
+            .filter_map(|(path, file)| match &file.state {
+                WatchFileState::Published { content_hash } => Some(PublishedWatchFile {
+                    path: path.clone(),
+                    size: file.size,
+                    modified_at: file.modified_at,
+                    content_hash: content_hash.clone(),
+                }),
+                _ => None,
+            })
+            .collect();
+        published.sort_by(|a, b| a.path.cmp(&b.path));
+        published
+    }
+
+    pub fn report(&self) -> Vec<WatchedFileReport> {
+        let mut report: Vec<WatchedFileReport> = self
+            .files
+            .iter()
+            .map(|(path, file)| WatchedFileReport {
+                path: path.to_string_lossy().to_string(),
+                size: file.size,
+                state: file.state.clone(),
+            })
+            .collect();
+        report.sort_by(|a, b| a.path.cmp(&b.path));
+        report
+    }
+}
+
+/// Regular files under `dir`, recursively
+pub fn scan(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Failed to read watch directory {}: {}", dir.display(), e);
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => pending.push(entry.path()),
+                Ok(t) if t.is_file() => files.push(entry.path()),
+                _ => {}
+            }
+        }
+    }
+    files
+}
+
+/// Watch `dir` recursively, sending its events to `events_tx`
+pub fn watch_recursive(
+    dir: &Path,
+    events_tx: mpsc::UnboundedSender<WatchEvent>,
+) -> notify::Result<RecommendedWatcher> {
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = events_tx.send(event);
+    })?;
+    watcher.watch(dir, RecursiveMode::Recursive)?;
+    Ok(watcher)
+}
+
+/// The directories a watcher covers, which files in them count, and how far
+/// each of those got
+#[derive(Debug)]
+pub struct WatchSet {
+    pub roots: Vec<PathBuf>,
+    pub filter: WatchFilter,
+    pub tracker: WatchTracker,
+}
+
+impl WatchSet {
+    /// Whether `path` is under one of the roots and passes the filter
+    pub fn relevant(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| {
+            path.strip_prefix(root)
+                .is_ok_and(|relative| self.filter.matches(relative))
+        })
+    }
+
+    /// Bring the tracked state of `path` in line with `file`, its size and
+    /// mtime on disk (`None` if it is not a regular file). Returns published
+    /// content that has to be un-announced.
+    pub fn reconcile(
+        &mut self,
+        path: &Path,
+        file: Option<(u64, u64)>,
+        now: Instant,
+    ) -> Vec<(PathBuf, String)> {
+        match file {
+            Some((size, modified_at)) if self.relevant(path) => self
+                .tracker
+                .observe(path, size, modified_at, now)
+                // The published file was rewritten; it is published again
+                // once it settles
+                .map(|content_hash| vec![(path.to_path_buf(), content_hash)])
+                .unwrap_or_default(),
+            _ => {
+                // Deleted, renamed away or no longer matching; a deleted
+                // directory takes every tracked file below it along
+                let gone: Vec<PathBuf> = self
+                    .tracker
+                    .paths()
+                    .into_iter()
+                    .filter(|p| p.starts_with(path))
+                    .collect();
+                gone.into_iter()
+                    .filter_map(|p| {
+                        let content_hash = self.tracker.remove(&p)?;
+                        Some((p, content_hash))
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// How a front end of the watcher shares and withdraws files
+#[async_trait]
+pub trait WatchActions: Send {
+    /// Publish a settled file, returning its content hash
+    async fn publish(&mut self, path: &Path) -> Result<String, String>;
+
+    /// Stop announcing content published from `path`
+    async fn unannounce(&mut self, path: &Path, content_hash: String);
+
+    /// Called once the tracker has recorded the outcome of `publish`
+    async fn published(&mut self, _path: &Path, _result: &Result<String, String>) {}
+}
+
+/// Bring the tracked state of `path` in line with the disk; a directory is
+/// reconciled file by file
+pub async fn refresh<A: WatchActions>(set: &Mutex<WatchSet>, path: &Path, actions: &mut A) {
+    let meta = tokio::fs::metadata(path).await.ok();
+    let paths = if meta.as_ref().is_some_and(|m| m.is_dir()) {
+        let dir = path.to_path_buf();
+        tokio::task::spawn_blocking(move || scan(&dir))
+            .await
+            .unwrap_or_default()
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    for file in paths {
+        let meta = if file == path {
+            meta.clone()
+        } else {
+            tokio::fs::metadata(&file).await.ok()
+        };
+        let on_disk = meta
+            .filter(|m| m.is_file())
+            .map(|m| (m.len(), modified_secs(&m)));
+        let withdrawn = set.lock().await.reconcile(&file, on_disk, Instant::now());
+        for (path, content_hash) in withdrawn {
+            actions.unannounce(&path, content_hash).await;
+        }
+    }
+}
+
+/// Re-check settling files and publish those that stopped changing
+pub async fn publish_settled<A: WatchActions>(set: &Mutex<WatchSet>, actions: &mut A) {
+    let settling = set.lock().await.tracker.settling();
+    for path in settling {
+        refresh(set, &path, actions).await;
+    }
+    let settled = set.lock().await.tracker.take_settled(Instant::now());
+    for path in settled {
+        let result = actions.publish(&path).await;
+        {
+            let mut set = set.lock().await;
+            match &result {
+                Ok(content_hash) => set.tracker.mark_published(&path, content_hash.clone()),
+                Err(e) => set.tracker.mark_failed(&path, e.clone()),
+            }
+        }
+        actions.published(&path, &result).await;
+    }
+}
+
+/// Follow watcher `events` and publish settled files until `events` closes
+pub async fn run_watch_loop<A: WatchActions>(
+    set: &Mutex<WatchSet>,
+    mut events: mpsc::UnboundedReceiver<WatchEvent>,
+    actions: &mut A,
+) {
+    let mut interval = tokio::time::interval(WATCH_TICK);
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(Ok(event)) => {
+                    for path in event.paths {
+                        refresh(set, &path, actions).await;
+                    }
+                }
+                Some(Err(e)) => warn!("Watch directory error: {}", e),
+                None => break,
+            },
+            _ = interval.tick() => publish_settled(set, actions).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_applies_excludes_after_includes() {
+        let filter = WatchFilter::new(
+            &["*.csv".to_string(), "results/**/*.json".to_string()],
+            &["*draft*".to_string()],
+        )
+        .unwrap();
+        assert!(filter.matches(Path::new("run-1.csv")));
+        assert!(filter.matches(Path::new("results/2026/summary.json")));
+        assert!(!filter.matches(Path::new("run-1-draft.csv")));
+        assert!(!filter.matches(Path::new("notes.txt")));
+        assert!(!filter.matches(Path::new(".run-2.csv")));
+        assert!(WatchFilter::new(&["[".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn test_files_publish_once_they_stop_growing() {
+        let mut tracker = WatchTracker::new(Duration::from_secs(5));
+        let path = Path::new("/watch/run-1.csv");
+        let t0 = Instant::now();
+
+        tracker.observe(path, 100, 1, t0);
+        tracker.observe(path, 200, 2, t0 + Duration::from_secs(3));
+        assert!(tracker.take_settled(t0 + Duration::from_secs(6)).is_empty());
+        assert_eq!(
+            tracker.take_settled(t0 + Duration::from_secs(8)),
+            vec![path.to_path_buf()]
+        );
+        tracker.mark_published(path, "hash-1".to_string());
+        assert_eq!(tracker.observe(path, 200, 2, t0 + Duration::from_secs(9)), None);
+
+        // Rewriting published content withdraws it until it settles again
+        assert_eq!(
+            tracker.observe(path, 300, 3, t0 + Duration::from_secs(10)),
+            Some("hash-1".to_string())
+        );
+        assert_eq!(tracker.take_settled(t0 + Duration::from_secs(15)).len(), 1);
+        tracker.mark_published(path, "hash-2".to_string());
+        assert_eq!(tracker.published()[0].content_hash, "hash-2");
+        assert_eq!(tracker.remove(path), Some("hash-2".to_string()));
+        assert!(tracker.report().is_empty());
+    }
+
+    #[derive(Default)]
+    struct Recorded {
+        published: Vec<PathBuf>,
+        unannounced: Vec<(PathBuf, String)>,
+    }
+
+    #[async_trait]
+    impl WatchActions for Recorded {
+        async fn publish(&mut self, path: &Path) -> Result<String, String> {
+            self.published.push(path.to_path_buf());
+            Ok(format!("hash-{}", self.published.len()))
+        }
+
+        async fn unannounce(&mut self, path: &Path, content_hash: String) {
+            self.unannounced.push((path.to_path_buf(), content_hash));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watch_core_publishes_and_unannounces_through_the_actions() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("nested")).unwrap();
+        let kept = dir.path().join("nested/run-1.csv");
+        std::fs::write(&kept, b"1,2").unwrap();
+        std::fs::write(dir.path().join(".partial.csv"), b"1").unwrap();
+        let set = Mutex::new(WatchSet {
+            roots: vec![dir.path().to_path_buf()],
+            filter: WatchFilter::new(&[], &[]).unwrap(),
+            tracker: WatchTracker::new(Duration::ZERO),
+        });
+        let mut actions = Recorded::default();
+
+        refresh(&set, dir.path(), &mut actions).await;
+        publish_settled(&set, &mut actions).await;
+        assert_eq!(actions.published, vec![kept.clone()]);
+        assert_eq!(set.lock().await.tracker.published()[0].content_hash, "hash-1");
+
+        // Deleting the directory withdraws what was published from it
+        std::fs::remove_dir_all(dir.path().join("nested")).unwrap();
+        refresh(&set, &dir.path().join("nested"), &mut actions).await;
+        assert_eq!(actions.unannounced, vec![(kept, "hash-1".to_string())]);
+        assert!(set.lock().await.tracker.paths().is_empty());
+    }
+}
@@ -3,18 +3,24 @@ use crate::encryption::{decrypt_aes_key, encrypt_aes_key, EncryptedAesKeyBundle,
 use crate::file_transfer::FileTransferService;
 use crate::keystore::Keystore;
 use crate::bandwidth::BandwidthController;
-use crate::manager::{ChunkInfo, FileManifest};
+use crate::byte_range::{self, MAX_CONCURRENT_RANGE_REQUESTS_PER_PEER, MAX_RANGE_LENGTH};
+use crate::manager::{merkle_root_of, ChunkInfo, FileManifest};
 use crate::stream_auth::{AuthMessage, StreamAuthService};
+use crate::transfer_events::current_timestamp_ms;
+use crate::upload_slots::UploadSlotLimiter;
+use crate::transfer_history::{
+    TransferDirection, TransferHistory, TransferRecord, TransferState,
+};
 use aes_gcm::aead::Aead;
 use aes_gcm::{AeadCore, KeyInit};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use tokio_util::bytes::Bytes;
-use tauri::Emitter;
-use std::collections::HashMap;
+use tauri::{Emitter, Manager};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use std::time::Instant;
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{mpsc, oneshot, Mutex};
 use tokio::time::{sleep, Duration};
 use tracing::{debug, error, info, warn};
 use webrtc::api::APIBuilder;
@@ -29,6 +35,12 @@ use webrtc::peer_connection::RTCPeerConnection;
 
 const CHUNK_SIZE: usize = 4096; // 4KB chunks - safe size for WebRTC data channel max message size (~16KB after JSON serialization)
 
+/// How long a requester waits for a provider's chunk hash list
+const MANIFEST_RESPONSE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How long a requester waits for all chunks of a range request
+const RANGE_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
 /// Maximum connection retry attempts before giving up
 const MAX_CONNECTION_RETRIES: u32 = 3;
 
@@ -86,6 +98,64 @@ pub struct WebRTCManifestResponse {
     pub manifest_json: String, // The full FileManifest, serialized to JSON
 }
 
+/// Sent by a downloader to request `length` bytes starting at `offset`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebRTCRangeRequest {
+    pub request_id: String,
+    pub file_hash: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// Sent by a seeder in response to a range request. On success the covering
+/// chunks follow as `RangeChunk` messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebRTCRangeResponse {
+    pub request_id: String,
+    pub file_size: u64,
+    pub first_chunk: u32,
+    pub chunk_count: u32,
+    pub error: Option<String>,
+}
+
+/// One chunk covering a requested range
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebRTCRangeChunk {
+    pub request_id: String,
+    pub chunk_index: u32,
+    pub data: Vec<u8>,
+}
+
+/// A range request waiting for the seeder's response and chunks
+pub struct PendingRange {
+    /// Transfer the chunks are throttled under, as for whole-file downloads
+    file_hash: String,
+    response: Option<WebRTCRangeResponse>,
+    chunks: BTreeMap<u32, Vec<u8>>,
+    reply: oneshot::Sender<Result<(WebRTCRangeResponse, Vec<Vec<u8>>), String>>,
+}
+
+impl PendingRange {
+    /// Whether the response and every chunk it announced have arrived
+    fn is_complete(&self) -> bool {
+        match &self.response {
+            Some(response) => {
+                response.error.is_some() || self.chunks.len() == response.chunk_count as usize
+            }
+            None => false,
+        }
+    }
+
+    fn finish(self) {
+        let result = match self.response {
+            Some(WebRTCRangeResponse { error: Some(error), .. }) => Err(error),
+            Some(response) => Ok((response, self.chunks.into_values().collect())),
+            None => Err("Range request ended without a response".to_string()),
+        };
+        let _ = self.reply.send(result);
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileChunk {
     pub file_hash: String,
@@ -121,6 +191,12 @@ pub struct PeerConnection {
     pub pending_acks: HashMap<String, u32>, // file_hash -> number of unacked chunks
     /// Retry context for connection resilience
     pub retry_context: Option<WebRtcRetryContext>,
+    /// Manifest requests waiting for a response, by file hash
+    pub pending_manifests: HashMap<String, Vec<oneshot::Sender<String>>>,
+    /// Outgoing range requests waiting for their chunks, by request id
+    pub pending_ranges: HashMap<String, PendingRange>,
+    /// Range requests from this peer currently being served
+    pub active_range_requests: u32,
 }
 
 #[derive(Debug)]
@@ -250,6 +326,9 @@ pub enum WebRTCMessage {
     ManifestResponse(WebRTCManifestResponse),
     FileChunk(FileChunk),
     ChunkAck(ChunkAck),
+    RangeRequest(WebRTCRangeRequest),
+    RangeResponse(WebRTCRangeResponse),
+    RangeChunk(WebRTCRangeChunk),
 }
 
 pub struct WebRTCService {
@@ -821,6 +900,9 @@ impl WebRTCService {
             acked_chunks: HashMap::new(),
             pending_acks: HashMap::new(),
             retry_context: Some(retry_ctx),
+            pending_manifests: HashMap::new(),
+            pending_ranges: HashMap::new(),
+            active_range_requests: 0,
         };
         conns.insert(peer_id.to_string(), connection);
     }
@@ -962,6 +1044,7 @@ impl WebRTCService {
         keystore: &Arc<Mutex<Keystore>>,
         stream_auth: &Arc<Mutex<StreamAuthService>>,
         bandwidth: &Arc<BandwidthController>,
+        app_handle: &tauri::AppHandle,
     ) {
         info!(
             "📥 Handling file request from peer {}: {}",
@@ -978,8 +1061,28 @@ impl WebRTCService {
             .any(|(hash, _)| hash == &request.file_hash);
 
         if has_file {
+            // Seeding limits and upload slots apply as on the HTTP path
+            let upload = match app_handle.try_state::<UploadSlotLimiter>() {
+                Some(limiter) => match limiter.acquire_upload(peer_id, &request.file_hash).await {
+                    Ok(upload) => Some(upload),
+                    Err(refusal) => {
+                        warn!("Refusing to serve {} to {}: {}", request.file_hash, peer_id, refusal);
+                        let _ = event_tx
+                            .send(WebRTCEvent::TransferFailed {
+                                peer_id: peer_id.to_string(),
+                                file_hash: request.file_hash.clone(),
+                                error: refusal.to_string(),
+                            })
+                            .await;
+                        return;
+                    }
+                },
+                None => None,
+            };
+
             // Start sending file chunks
-            if let Err(e) = Self::start_file_transfer(
+            let started = Instant::now();
+            let result = Self::start_file_transfer(
                 peer_id,
                 request,
                 event_tx,
@@ -989,8 +1092,16 @@ impl WebRTCService {
                 stream_auth,
                 bandwidth,
             )
-            .await
-            {
+            .await;
+            record_upload_history(app_handle, peer_id, request, started, result.as_ref().err());
+            if let Some(mut upload) = upload {
+                if let Ok(bytes) = &result {
+                    upload.record_bytes(*bytes);
+                }
+                upload.finish().await;
+            }
+
+            if let Err(e) = result {
                 let _ = event_tx
                     .send(WebRTCEvent::TransferFailed {
                         peer_id: peer_id.to_string(),
@@ -1016,7 +1127,9 @@ impl WebRTCService {
         connections: &Arc<Mutex<HashMap<String, PeerConnection>>>,
         bandwidth: &Arc<BandwidthController>,
     ) {
-        bandwidth.acquire_upload(chunk.data.len()).await;
+        bandwidth
+            .acquire_upload_for_transfer(chunk.data.len(), &chunk.file_hash)
+            .await;
 
         // Wait for data channel to open (with timeout)
         use webrtc::data_channel::data_channel_state::RTCDataChannelState;
@@ -1150,6 +1263,7 @@ impl WebRTCService {
                     keystore,
                     stream_auth,
                     &bandwidth,
+                    &app_handle,
                 )
                 .await;
             }
@@ -1172,6 +1286,7 @@ impl WebRTCService {
                             keystore,
                             stream_auth,
                             &bandwidth,
+                            &app_handle,
                         )
                         .await;
                     }
@@ -1283,8 +1398,96 @@ impl WebRTCService {
                     }
                     WebRTCMessage::ManifestResponse(response) => {
                         info!("Received manifest response for a file download.");
-                        // Downloader receives this. We can emit a specific event or handle it directly.
-                        // For simplicity, we can have the main download logic listen for this.
+                        // Hand the chunk hash list to range requests waiting for it
+                        let mut conns = connections.lock().await;
+                        if let Some(connection) = conns.get_mut(peer_id) {
+                            if let Some(waiters) =
+                                connection.pending_manifests.remove(&response.file_hash)
+                            {
+                                for waiter in waiters {
+                                    let _ = waiter.send(response.manifest_json.clone());
+                                }
+                            }
+                        }
+                    }
+                    WebRTCMessage::RangeRequest(request) => {
+                        // Served in the background so other messages from this peer keep flowing
+                        let peer_id = peer_id.to_string();
+                        let file_transfer_service = file_transfer_service.clone();
+                        let connections = connections.clone();
+                        let bandwidth = bandwidth.clone();
+                        let upload_slots = app_handle.try_state::<UploadSlotLimiter>().map(|l| l.inner().clone());
+                        tokio::spawn(async move {
+                            Self::handle_range_request(
+                                &peer_id,
+                                &request,
+                                &file_transfer_service,
+                                &connections,
+                                &bandwidth,
+                                upload_slots.as_ref(),
+                            )
+                            .await;
+                        });
+                    }
+                    WebRTCMessage::RangeResponse(response) => {
+                        let mut conns = connections.lock().await;
+                        if let Some(connection) = conns.get_mut(peer_id) {
+                            let request_id = response.request_id.clone();
+                            if let Some(pending) = connection.pending_ranges.get_mut(&request_id) {
+                                pending.response = Some(response);
+                                // Drop chunks outside the announced range
+                                if let Some(r) = &pending.response {
+                                    let end = r.first_chunk.saturating_add(r.chunk_count);
+                                    pending.chunks.retain(|i, _| *i >= r.first_chunk && *i < end);
+                                }
+                                if pending.is_complete() {
+                                    if let Some(pending) = connection.pending_ranges.remove(&request_id) {
+                                        pending.finish();
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    WebRTCMessage::RangeChunk(chunk) => {
+                        let file_hash = connections
+                            .lock()
+                            .await
+                            .get(peer_id)
+                            .and_then(|c| c.pending_ranges.get(&chunk.request_id))
+                            .map(|pending| pending.file_hash.clone());
+                        match file_hash {
+                            Some(file_hash) => {
+                                bandwidth
+                                    .acquire_download_for_transfer(chunk.data.len(), &file_hash)
+                                    .await
+                            }
+                            None => bandwidth.acquire_download(chunk.data.len()).await,
+                        }
+                        let mut conns = connections.lock().await;
+                        if let Some(connection) = conns.get_mut(peer_id) {
+                            let request_id = chunk.request_id.clone();
+                            if let Some(pending) = connection.pending_ranges.get_mut(&request_id) {
+                                let in_range = match &pending.response {
+                                    Some(r) => {
+                                        chunk.chunk_index >= r.first_chunk
+                                            && chunk.chunk_index < r.first_chunk.saturating_add(r.chunk_count)
+                                    }
+                                    // Bound what a peer can make us buffer before its response
+                                    None => {
+                                        (pending.chunks.len() as u64) * (CHUNK_SIZE as u64)
+                                            < MAX_RANGE_LENGTH + 2 * CHUNK_SIZE as u64
+                                    }
+                                };
+                                if in_range {
+                                    pending.chunks.insert(chunk.chunk_index, chunk.data);
+                                }
+                                if pending.is_complete() {
+                                    if let Some(pending) = connection.pending_ranges.remove(&request_id) {
+                                        pending.finish();
+                                    }
+                                }
+                            }
+                        }
                     }
                     WebRTCMessage::FileChunk(chunk) => {
                         Self::process_incoming_chunk(
@@ -1327,6 +1530,196 @@ impl WebRTCService {
         }
     }
 
+    /// Wait up to 10s for the data channel to `peer_id` to be open
+    async fn open_data_channel(
+        peer_id: &str,
+        connections: &Arc<Mutex<HashMap<String, PeerConnection>>>,
+    ) -> Result<Arc<RTCDataChannel>, String> {
+        use webrtc::data_channel::data_channel_state::RTCDataChannelState;
+
+        let start = Instant::now();
+        let timeout = Duration::from_secs(10);
+        loop {
+            {
+                let conns = connections.lock().await;
+                let connection = conns
+                    .get(peer_id)
+                    .ok_or_else(|| format!("Peer {} not found in connections", peer_id))?;
+                let dc = connection
+                    .data_channel
+                    .as_ref()
+                    .ok_or_else(|| format!("No data channel found for peer {}", peer_id))?;
+                match dc.ready_state() {
+                    RTCDataChannelState::Open => return Ok(dc.clone()),
+                    RTCDataChannelState::Closed | RTCDataChannelState::Closing => {
+                        return Err(format!("Data channel is closed or closing for peer {}", peer_id));
+                    }
+                    _ if start.elapsed() > timeout => {
+                        return Err(format!("Timeout waiting for data channel to open for peer {}", peer_id));
+                    }
+                    _ => {}
+                }
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    async fn send_message(dc: &RTCDataChannel, message: &WebRTCMessage) -> Result<(), String> {
+        let json = serde_json::to_string(message)
+            .map_err(|e| format!("Failed to serialize message: {}", e))?;
+        dc.send_text(json)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Failed to send over data channel: {}", e))
+    }
+
+    /// Serve a range request, bounding how many run concurrently per peer and
+    /// holding an upload slot of `upload_slots` while it is served
+    async fn handle_range_request(
+        peer_id: &str,
+        request: &WebRTCRangeRequest,
+        file_transfer_service: &Arc<FileTransferService>,
+        connections: &Arc<Mutex<HashMap<String, PeerConnection>>>,
+        bandwidth: &Arc<BandwidthController>,
+        upload_slots: Option<&UploadSlotLimiter>,
+    ) {
+        let admitted = {
+            let mut conns = connections.lock().await;
+            match conns.get_mut(peer_id) {
+                Some(connection)
+                    if connection.active_range_requests < MAX_CONCURRENT_RANGE_REQUESTS_PER_PEER =>
+                {
+                    connection.active_range_requests += 1;
+                    true
+                }
+                _ => false,
+            }
+        };
+
+        let result = if admitted {
+            let upload = match upload_slots {
+                Some(limiter) => limiter
+                    .acquire_upload(peer_id, &request.file_hash)
+                    .await
+                    .map(Some)
+                    .map_err(|refusal| refusal.to_string()),
+                None => Ok(None),
+            };
+            let result = match upload {
+                Ok(upload) => {
+                    let result =
+                        Self::serve_range(peer_id, request, file_transfer_service, connections, bandwidth)
+                            .await;
+                    if let Some(mut upload) = upload {
+                        if let Ok(bytes) = &result {
+                            upload.record_bytes(*bytes);
+                        }
+                        upload.finish().await;
+                    }
+                    result.map(|_| ())
+                }
+                Err(refusal) => Err(refusal),
+            };
+            if let Some(connection) = connections.lock().await.get_mut(peer_id) {
+                connection.active_range_requests = connection.active_range_requests.saturating_sub(1);
+            }
+            result
+        } else {
+            Err("Too many concurrent range requests".to_string())
+        };
+
+        if let Err(error) = result {
+            warn!(
+                "Range request {} from {} for {} failed: {}",
+                request.request_id, peer_id, request.file_hash, error
+            );
+            let response = WebRTCMessage::RangeResponse(WebRTCRangeResponse {
+                request_id: request.request_id.clone(),
+                file_size: 0,
+                first_chunk: 0,
+                chunk_count: 0,
+                error: Some(error),
+            });
+            if let Ok(dc) = Self::open_data_channel(peer_id, connections).await {
+                let _ = Self::send_message(&dc, &response).await;
+            }
+        }
+    }
+
+    /// Read only the chunks covering the requested range from disk and send
+    /// them; returns the bytes sent
+    async fn serve_range(
+        peer_id: &str,
+        request: &WebRTCRangeRequest,
+        file_transfer_service: &Arc<FileTransferService>,
+        connections: &Arc<Mutex<HashMap<String, PeerConnection>>>,
+        bandwidth: &Arc<BandwidthController>,
+    ) -> Result<u64, String> {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+        // The hash names a file in storage, so never let it contain a path
+        if request.file_hash.is_empty()
+            || !request.file_hash.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return Err("Invalid file hash".to_string());
+        }
+        let path = file_transfer_service.get_storage_path().join(&request.file_hash);
+        let file_size = tokio::fs::metadata(&path)
+            .await
+            .map_err(|_| "File not found locally".to_string())?
+            .len();
+        byte_range::validate_range(request.offset, request.length, file_size)?;
+        let (first_chunk, chunk_count) =
+            byte_range::covering_chunks(request.offset, request.length, CHUNK_SIZE);
+
+        let dc = Self::open_data_channel(peer_id, connections).await?;
+        Self::send_message(
+            &dc,
+            &WebRTCMessage::RangeResponse(WebRTCRangeResponse {
+                request_id: request.request_id.clone(),
+                file_size,
+                first_chunk,
+                chunk_count,
+                error: None,
+            }),
+        )
+        .await?;
+
+        let mut file = tokio::fs::File::open(&path)
+            .await
+            .map_err(|e| format!("Failed to open file: {}", e))?;
+        let start = first_chunk as u64 * CHUNK_SIZE as u64;
+        file.seek(std::io::SeekFrom::Start(start))
+            .await
+            .map_err(|e| format!("Failed to seek: {}", e))?;
+
+        for index in first_chunk..first_chunk + chunk_count {
+            let chunk_start = index as u64 * CHUNK_SIZE as u64;
+            let size = (file_size - chunk_start).min(CHUNK_SIZE as u64) as usize;
+            let mut data = vec![0u8; size];
+            file.read_exact(&mut data)
+                .await
+                .map_err(|e| format!("Failed to read chunk {}: {}", index, e))?;
+            bandwidth
+                .acquire_upload_for_transfer(size, &request.file_hash)
+                .await;
+            Self::send_message(
+                &dc,
+                &WebRTCMessage::RangeChunk(WebRTCRangeChunk {
+                    request_id: request.request_id.clone(),
+                    chunk_index: index,
+                    data,
+                }),
+            )
+            .await?;
+        }
+        debug!(
+            "Served range {}+{} of {} to {} ({} chunks)",
+            request.offset, request.length, request.file_hash, peer_id, chunk_count
+        );
+        Ok(file_size.min(start + chunk_count as u64 * CHUNK_SIZE as u64) - start)
+    }
+
     async fn start_file_transfer(
         peer_id: &str,
         request: &WebRTCFileRequest,
@@ -1336,7 +1729,7 @@ impl WebRTCService {
         keystore: &Arc<Mutex<Keystore>>,
         stream_auth: &Arc<Mutex<StreamAuthService>>,
         bandwidth: &Arc<BandwidthController>,
-    ) -> Result<(), String> {
+    ) -> Result<u64, String> {
         // Get file data from local storage
         let file_data = match file_transfer_service
             .get_file_data(&request.file_hash)
@@ -1351,7 +1744,7 @@ impl WebRTCService {
                         error: "File data not available".to_string(),
                     })
                     .await;
-                return Ok(());
+                return Ok(0);
             }
         };
 
@@ -1566,7 +1959,7 @@ impl WebRTCService {
                 file_hash: request.file_hash.clone(),
             })
             .await;
-        Ok(())
+        Ok(file_data.len() as u64)
     }
 
     async fn process_incoming_chunk(
@@ -1636,7 +2029,9 @@ impl WebRTCService {
             return;
         }
 
-        bandwidth.acquire_download(chunk_len).await;
+        bandwidth
+            .acquire_download_for_transfer(chunk_len, &chunk.file_hash)
+            .await;
 
         // Get data channel reference before locking connections
         let dc_for_ack = {
@@ -1928,6 +2323,9 @@ impl WebRTCService {
             acked_chunks: HashMap::new(),
             pending_acks: HashMap::new(),
             retry_context: Some(retry_ctx),
+            pending_manifests: HashMap::new(),
+            pending_ranges: HashMap::new(),
+            active_range_requests: 0,
         };
         conns.insert(peer_id, connection);
 
@@ -2133,6 +2531,9 @@ impl WebRTCService {
             acked_chunks: HashMap::new(),
             pending_acks: HashMap::new(),
             retry_context: Some(retry_ctx),
+            pending_manifests: HashMap::new(),
+            pending_ranges: HashMap::new(),
+            active_range_requests: 0,
         };
         conns.insert(peer_id.clone(), connection);
         info!("✅ Peer {} stored in connections map, now calling set_remote_description", peer_id);
@@ -2278,6 +2679,133 @@ impl WebRTCService {
         events
     }
 
+    /// Fetch the chunk hash list of `file_hash` from a connected peer
+    async fn request_chunk_hashes(&self, peer_id: &str, file_hash: &str) -> Result<Vec<String>, String> {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut conns = self.connections.lock().await;
+            let connection = conns
+                .get_mut(peer_id)
+                .ok_or_else(|| format!("Peer {} is not connected", peer_id))?;
+            connection
+                .pending_manifests
+                .entry(file_hash.to_string())
+                .or_default()
+                .push(tx);
+        }
+
+        let dc = Self::open_data_channel(peer_id, &self.connections).await?;
+        Self::send_message(
+            &dc,
+            &WebRTCMessage::ManifestRequest(WebRTCManifestRequest {
+                file_hash: file_hash.to_string(),
+            }),
+        )
+        .await?;
+
+        let manifest_json = tokio::time::timeout(MANIFEST_RESPONSE_TIMEOUT, rx)
+            .await
+            .map_err(|_| "Timed out waiting for the chunk hash list".to_string())?
+            .map_err(|_| "Connection closed before the chunk hash list arrived".to_string())?;
+        let manifest: FileManifest = serde_json::from_str(&manifest_json)
+            .map_err(|e| format!("Invalid manifest: {}", e))?;
+
+        let mut chunks = manifest.chunks;
+        chunks.sort_by_key(|c| c.index);
+        if chunks.iter().enumerate().any(|(i, c)| c.index as usize != i) {
+            return Err("Manifest chunk list is not contiguous".to_string());
+        }
+        let hashes: Vec<String> = chunks.into_iter().map(|c| c.hash).collect();
+        // The list comes from the peer that also sends the chunks; only its
+        // Merkle root ties it to the file that was asked for
+        if !merkle_root_of(&hashes)?.eq_ignore_ascii_case(file_hash) {
+            return Err(format!("Chunk hash list from {} does not match {}", peer_id, file_hash));
+        }
+        Ok(hashes)
+    }
+
+    /// Ask a connected peer whether it still has `file_hash`; returns its chunk count
+    pub async fn has_file(&self, peer_id: &str, file_hash: &str) -> Result<usize, String> {
+        self.request_chunk_hashes(peer_id, file_hash)
+            .await
+            .map(|hashes| hashes.len())
+    }
+
+    /// Download `length` bytes of `file_hash` starting at `offset` from a
+    /// connected peer. Only the covering chunks are transferred, and each is
+    /// verified against the file's chunk hash list.
+    pub async fn request_range(
+        &self,
+        peer_id: &str,
+        file_hash: &str,
+        offset: u64,
+        length: u64,
+    ) -> Result<Vec<u8>, String> {
+        byte_range::validate_range(offset, length, u64::MAX)?;
+        let chunk_hashes = self.request_chunk_hashes(peer_id, file_hash).await?;
+
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut conns = self.connections.lock().await;
+            let connection = conns
+                .get_mut(peer_id)
+                .ok_or_else(|| format!("Peer {} is not connected", peer_id))?;
+            connection.pending_ranges.insert(
+                request_id.clone(),
+                PendingRange {
+                    file_hash: file_hash.to_string(),
+                    response: None,
+                    chunks: BTreeMap::new(),
+                    reply: tx,
+                },
+            );
+        }
+
+        let request = WebRTCRangeRequest {
+            request_id: request_id.clone(),
+            file_hash: file_hash.to_string(),
+            offset,
+            length,
+        };
+        let sent = match Self::open_data_channel(peer_id, &self.connections).await {
+            Ok(dc) => Self::send_message(&dc, &WebRTCMessage::RangeRequest(request)).await,
+            Err(e) => Err(e),
+        };
+        let result = match sent {
+            Ok(()) => tokio::time::timeout(RANGE_REQUEST_TIMEOUT, rx)
+                .await
+                .map_err(|_| "Timed out waiting for range data".to_string())
+                .and_then(|r| r.map_err(|_| "Connection closed during range request".to_string())),
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            if let Some(connection) = self.connections.lock().await.get_mut(peer_id) {
+                connection.pending_ranges.remove(&request_id);
+            }
+        }
+        let (response, chunks) = result??;
+
+        byte_range::validate_range(offset, length, response.file_size)?;
+        let expected_chunks = response.file_size.div_ceil(CHUNK_SIZE as u64);
+        if chunk_hashes.len() as u64 != expected_chunks {
+            return Err(format!(
+                "Hash list has {} chunks, file of {} bytes needs {}",
+                chunk_hashes.len(),
+                response.file_size,
+                expected_chunks
+            ));
+        }
+        byte_range::assemble_range(
+            &chunks,
+            response.first_chunk,
+            &chunk_hashes,
+            CHUNK_SIZE,
+            offset,
+            length,
+        )
+    }
+
     pub async fn get_connection_status(&self, peer_id: &str) -> bool {
         let connections = self.connections.lock().await;
         connections
@@ -2362,6 +2890,44 @@ impl WebRTCService {
     }
 }
 
+/// Add a finished upload to the transfer history, if one is managed
+fn record_upload_history(
+    app_handle: &tauri::AppHandle,
+    peer_id: &str,
+    request: &WebRTCFileRequest,
+    started: Instant,
+    error: Option<&String>,
+) {
+    if let Some(history) = app_handle.try_state::<Arc<TransferHistory>>() {
+        let elapsed = started.elapsed();
+        let average_rate_bps = if error.is_none() && elapsed.as_secs_f64() > 0.0 {
+            request.file_size as f64 / elapsed.as_secs_f64()
+        } else {
+            0.0
+        };
+        let record = TransferRecord {
+            transfer_id: format!("{}-{}", peer_id, request.file_hash),
+            content_hash: request.file_hash.clone(),
+            file_name: request.file_name.clone(),
+            file_size: request.file_size,
+            direction: TransferDirection::Upload,
+            peers: vec![peer_id.to_string()],
+            duration_secs: elapsed.as_secs(),
+            average_rate_bps,
+            finished_at: current_timestamp_ms(),
+            state: if error.is_some() {
+                TransferState::Failed
+            } else {
+                TransferState::Completed
+            },
+            error: error.cloned(),
+        };
+        if let Err(e) = history.record(record) {
+            warn!("Failed to record upload history: {}", e);
+        }
+    }
+}
+
 // Singleton instance
 use lazy_static::lazy_static;
 
@@ -0,0 +1,223 @@
+/// Control API integration tests
+///
+/// Each test starts a DHT node the way the headless binary does, serves the
+/// control API on a random loopback port and operates the node over HTTP
+/// only.
+use chiral_network::control_api::{self, ControlApi};
+use chiral_network::dht::DhtService;
+use chiral_network::download_restart::DownloadRestartService;
+use chiral_network::messaging::MessageStore;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+struct Node {
+    dht: Arc<DhtService>,
+    base: String,
+    token: String,
+    client: reqwest::Client,
+    _data_dir: tempfile::TempDir,
+}
+
+impl Node {
+    async fn start() -> Self {
+        let data_dir = tempfile::tempdir().unwrap();
+        let dht = Arc::new(
+            DhtService::new(
+                0,          // Random port
+                vec![],     // No bootstrap nodes
+                None,       // No identity secret
+                false,      // Not bootstrap node
+                false,      // No AutoNAT
+                None,       // autonat_probe_interval
+                vec![],     // autonat_servers
+                None,       // No proxy
+                None,       // No file transfer service
+                None,       // No chunk manager
+                Some(256),  // chunk_size_kb
+                Some(1024), // cache_size_mb
+                false,      // enable_autorelay
+                Vec::new(), // preferred_relays
+                false,      // enable_relay_server
+                false,      // enable_upnp
+                None,       // blockstore_db_path
+                None,       // last_autorelay_enabled_at
+                None,       // last_autorelay_disabled_at
+            )
+            .await
+            .expect("DHT service should start"),
+        );
+        let token = control_api::load_or_create_token(data_dir.path()).unwrap();
+        let api = ControlApi {
+            dht: dht.clone(),
+            downloads: Some(Arc::new(DownloadRestartService::new(None))),
+            file_transfer: None,
+            messages: Some(Arc::new(MessageStore::open_in_memory().unwrap())),
+            settings: json!({ "network": { "port": 0, "secret": "<redacted>" } }),
+            token: token.clone(),
+            reload: None,
+        };
+        let addr = control_api::start_server(Arc::new(api), "127.0.0.1:0".parse().unwrap(), false)
+            .await
+            .unwrap();
+        Self {
+            dht,
+            base: format!("http://{}/api/v1", addr),
+            token,
+            client: reqwest::Client::new(),
+            _data_dir: data_dir,
+        }
+    }
+
+    async fn get(&self, path: &str) -> (u16, Value) {
+        let response = self
+            .client
+            .get(format!("{}{}", self.base, path))
+            .bearer_auth(&self.token)
+            .send()
+            .await
+            .unwrap();
+        (response.status().as_u16(), response.json().await.unwrap())
+    }
+
+    async fn post(&self, path: &str, body: Value) -> (u16, Value) {
+        let response = self
+            .client
+            .post(format!("{}{}", self.base, path))
+            .bearer_auth(&self.token)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        (response.status().as_u16(), response.json().await.unwrap())
+    }
+
+    async fn delete(&self, path: &str) -> u16 {
+        let response = self
+            .client
+            .delete(format!("{}{}", self.base, path))
+            .bearer_auth(&self.token)
+            .send()
+            .await
+            .unwrap();
+        response.status().as_u16()
+    }
+}
+
+#[tokio::test]
+async fn test_status_endpoints_require_the_token() {
+    let node = Node::start().await;
+
+    let anonymous = node
+        .client
+        .get(format!("{}/node", node.base))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(anonymous.status().as_u16(), 401);
+    let wrong = node
+        .client
+        .get(format!("{}/node", node.base))
+        .bearer_auth("not-the-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(wrong.status().as_u16(), 401);
+
+    let (status, info) = node.get("/node").await;
+    assert_eq!(status, 200);
+    assert_eq!(info["peerId"], node.dht.get_peer_id().await);
+
+    let (status, peers) = node.get("/peers").await;
+    assert_eq!(status, 200);
+    assert_eq!(peers, json!([]));
+
+    let (status, bootstrap) = node.get("/bootstrap").await;
+    assert_eq!(status, 200);
+    assert_eq!(bootstrap["configured"], 0);
+
+    let (status, stats) = node.get("/stats").await;
+    assert_eq!(status, 200);
+    assert_eq!(stats["peersConnected"], 0);
+    assert_eq!(stats["activeTransfers"], 0);
+
+    let (status, nat) = node.get("/nat").await;
+    assert_eq!(status, 200);
+    assert_eq!(nat["autonatEnabled"], false);
+    assert_eq!(nat["portForwarding"]["upnpEnabled"], false);
+
+    let (status, settings) = node.get("/settings").await;
+    assert_eq!(status, 200);
+    assert_eq!(settings["network"]["secret"], "<redacted>");
+
+    let (status, error) = node.post("/reload", json!({})).await;
+    assert_eq!(status, 400);
+    assert!(error["error"].is_string());
+
+    let (status, error) = node.post("/peers", json!({ "address": "not a multiaddr" })).await;
+    assert_eq!(status, 400);
+    assert!(error["error"].is_string());
+
+    let _ = node.dht.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_publish_and_downloads_over_the_api() {
+    let node = Node::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hello.txt");
+    std::fs::write(&path, b"hello over the control API").unwrap();
+
+    let (status, metadata) = node
+        .post(
+            "/files",
+            json!({ "filePath": path.to_string_lossy(), "mimeType": "text/plain" }),
+        )
+        .await;
+    assert_eq!(status, 200, "{}", metadata);
+    assert_eq!(metadata["fileName"], "hello.txt");
+    assert_eq!(metadata["fileSize"], 26);
+    let hash = metadata["merkleRoot"].as_str().unwrap().to_string();
+    assert_eq!(node.delete(&format!("/files/{}", hash)).await, 200);
+
+    let (status, missing) = node
+        .post("/files", json!({ "filePath": dir.path().join("absent").to_string_lossy() }))
+        .await;
+    assert_eq!(status, 400);
+    assert!(missing["error"].as_str().unwrap().contains("absent"));
+
+    let (status, downloads) = node.get("/downloads").await;
+    assert_eq!(status, 200);
+    assert_eq!(downloads, json!([]));
+    let (status, _) = node.get("/downloads/unknown").await;
+    assert_eq!(status, 400);
+
+    let _ = node.dht.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_post_and_list_channel_messages() {
+    let node = Node::start().await;
+
+    let (status, _) = node.post("/channels/lobby", json!({})).await;
+    assert_eq!(status, 200);
+    let (status, id) = node
+        .post("/channels/lobby/messages", json!({ "payload": b"hello".to_vec() }))
+        .await;
+    assert_eq!(status, 200, "{}", id);
+
+    // Our own message is kept, as gossipsub never delivers it back
+    let (status, messages) = node.get("/channels/lobby/messages?limit=10").await;
+    assert_eq!(status, 200);
+    assert_eq!(messages[0]["id"], id);
+    assert_eq!(messages[0]["payload"], json!(b"hello".to_vec()));
+    let (_, other) = node.get("/channels/elsewhere/messages").await;
+    assert_eq!(other, json!([]));
+
+    let (status, error) = node
+        .post("/channels/lobby/messages", json!({ "payload": [], "replyTo": "not-an-id" }))
+        .await;
+    assert_eq!(status, 400);
+    assert!(error["error"].is_string());
+
+    let _ = node.dht.shutdown().await;
+}
@@ -127,6 +127,7 @@ fn test_file_metadata_with_ed2k_sources() {
         encrypted_key_bundle: None,
         ftp_sources: None,
         http_sources: None,
+        web_seeds: None,
         info_hash: None,
         trackers: None,
         is_root: true,
@@ -175,6 +176,7 @@ fn test_multiple_ed2k_sources() {
         encrypted_key_bundle: None,
         ftp_sources: None,
         http_sources: None,
+        web_seeds: None,
         info_hash: None,
         trackers: None,
         is_root: true,
@@ -197,7 +199,7 @@ fn test_empty_none_source_lists() {
         file_name: String::new(), file_size: 0, file_data: vec![], seeders: vec![], created_at: 0,
         mime_type: None, is_encrypted: false, encryption_method: None, key_fingerprint: None,
         parent_hash: None, cids: None, encrypted_key_bundle: None,
-        ftp_sources: None, http_sources: None, info_hash: None, trackers: None, is_root: true,
+        ftp_sources: None, http_sources: None, web_seeds: None, info_hash: None, trackers: None, is_root: true,
         download_path: None, price: None, uploader_address: None,
     };
     let metadata_none = FileMetadata {
@@ -206,7 +208,7 @@ fn test_empty_none_source_lists() {
         file_name: String::new(), file_size: 0, file_data: vec![], seeders: vec![], created_at: 0,
         mime_type: None, is_encrypted: false, encryption_method: None, key_fingerprint: None,
         parent_hash: None, cids: None, encrypted_key_bundle: None,
-        ftp_sources: None, http_sources: None, info_hash: None, trackers: None, is_root: true,
+        ftp_sources: None, http_sources: None, web_seeds: None, info_hash: None, trackers: None, is_root: true,
         download_path: None, price: None, uploader_address: None,
     };
     let json_empty = serde_json::to_string(&metadata_empty).unwrap();
@@ -264,7 +266,7 @@ fn test_dht_storage_round_trip() {
         file_name: "test.iso".to_string(), file_size: 12345, file_data: vec![], seeders: vec![], created_at: 0,
         mime_type: None, is_encrypted: false, encryption_method: None, key_fingerprint: None,
         parent_hash: None, cids: None, encrypted_key_bundle: None,
-        ftp_sources: None, http_sources: None, info_hash: None, trackers: None, is_root: true,
+        ftp_sources: None, http_sources: None, web_seeds: None, info_hash: None, trackers: None, is_root: true,
         download_path: None, price: None, uploader_address: None,
     };
 
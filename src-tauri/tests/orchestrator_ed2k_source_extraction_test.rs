@@ -29,6 +29,7 @@ fn test_extract_ed2k_sources_from_metadata_empty() {
         encrypted_key_bundle: None,
         ftp_sources: None,
         http_sources: None,
+        web_seeds: None,
         ed2k_sources: None, // No ed2k sources
         is_root: true,
         download_path: None,
@@ -70,6 +71,7 @@ fn test_extract_ed2k_sources_from_metadata_single() {
         encrypted_key_bundle: None,
         ftp_sources: None,
         http_sources: None,
+        web_seeds: None,
         ed2k_sources: Some(vec![ed2k_info.clone()]),
         is_root: true,
         download_path: None,
@@ -126,6 +128,7 @@ fn test_extract_ed2k_sources_from_metadata_multiple() {
         encrypted_key_bundle: None,
         ftp_sources: None,
         http_sources: None,
+        web_seeds: None,
         ed2k_sources: Some(vec![ed2k_info1.clone(), ed2k_info2.clone()]),
         is_root: true,
         download_path: None,
@@ -172,6 +175,7 @@ fn test_calculate_chunk_size_with_ed2k_source() {
         encrypted_key_bundle: None,
         ftp_sources: None,
         http_sources: None,
+        web_seeds: None,
         ed2k_sources: Some(vec![ed2k_info]),
         is_root: true,
         download_path: None,
@@ -208,6 +212,7 @@ fn test_calculate_chunk_size_without_ed2k_source() {
         encrypted_key_bundle: None,
         ftp_sources: None,
         http_sources: None,
+        web_seeds: None,
         ed2k_sources: None, // No ed2k sources
         is_root: true,
         download_path: None,
@@ -51,6 +51,7 @@ async fn test_extract_ftp_sources_from_metadata() {
         ]),
         ed2k_sources: None,
         http_sources: None,
+        web_seeds: None,
         is_root: true,
         download_path: None,
         price: None,
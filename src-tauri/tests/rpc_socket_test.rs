@@ -0,0 +1,122 @@
+#![cfg(unix)]
+
+/// JSON-RPC socket integration tests
+///
+/// Starts a DHT node the way the headless binary does, serves it on a Unix
+/// socket in a temporary directory and talks to it as a script would.
+use chiral_network::control_api::ControlApi;
+use chiral_network::dht::DhtService;
+use chiral_network::download_restart::DownloadRestartService;
+use chiral_network::rpc::{self, HeadlessRPCServer, METHOD_NOT_FOUND};
+use serde_json::{json, Value};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+async fn start_node() -> Arc<DhtService> {
+    Arc::new(
+        DhtService::new(
+            0,          // Random port
+            vec![],     // No bootstrap nodes
+            None,       // No identity secret
+            false,      // Not bootstrap node
+            false,      // No AutoNAT
+            None,       // autonat_probe_interval
+            vec![],     // autonat_servers
+            None,       // No proxy
+            None,       // No file transfer service
+            None,       // No chunk manager
+            Some(256),  // chunk_size_kb
+            Some(1024), // cache_size_mb
+            false,      // enable_autorelay
+            Vec::new(), // preferred_relays
+            false,      // enable_relay_server
+            false,      // enable_upnp
+            None,       // blockstore_db_path
+            None,       // last_autorelay_enabled_at
+            None,       // last_autorelay_disabled_at
+        )
+        .await
+        .expect("DHT service should start"),
+    )
+}
+
+/// Send raw lines on one connection and read `answers` lines back
+async fn exchange(socket: &Path, lines: &[&str], answers: usize) -> Vec<Value> {
+    let stream = tokio::net::UnixStream::connect(socket).await.unwrap();
+    let (reader, mut writer) = stream.into_split();
+    for line in lines {
+        writer
+            .write_all(format!("{}\n", line).as_bytes())
+            .await
+            .unwrap();
+    }
+    let mut reader = BufReader::new(reader).lines();
+    let mut out = Vec::new();
+    for _ in 0..answers {
+        let line = reader.next_line().await.unwrap().unwrap();
+        out.push(serde_json::from_str(&line).unwrap());
+    }
+    out
+}
+
+#[tokio::test]
+async fn test_round_trip_over_the_socket() {
+    let data_dir = tempfile::tempdir().unwrap();
+    let socket = data_dir.path().join(rpc::SOCKET_NAME);
+    // A stale socket from an earlier run is replaced
+    std::fs::write(&socket, b"stale").unwrap();
+
+    let dht = start_node().await;
+    let node = Arc::new(ControlApi {
+        dht: dht.clone(),
+        downloads: Some(Arc::new(DownloadRestartService::new(None))),
+        file_transfer: None,
+        messages: None,
+        settings: json!({ "network": { "secret": "<redacted>" } }),
+        token: String::new(),
+        reload: None,
+    });
+    let server = HeadlessRPCServer::start(&socket, node).unwrap();
+    let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    assert_eq!(std::fs::read_dir(data_dir.path()).unwrap().count(), 1);
+
+    let peer_id = rpc::call(&socket, "get_dht_peer_id", Value::Null)
+        .await
+        .unwrap();
+    assert_eq!(peer_id, json!(dht.get_peer_id().await));
+    let settings = rpc::call(&socket, "get_settings", Value::Null)
+        .await
+        .unwrap();
+    assert_eq!(settings["network"]["secret"], "<redacted>");
+    let unknown = rpc::call(&socket, "no_such_method", Value::Null)
+        .await
+        .unwrap_err();
+    assert!(unknown.contains(&METHOD_NOT_FOUND.to_string()));
+
+    // The notification gets no answer, so the next line is the null id's
+    let answers = exchange(
+        &socket,
+        &[
+            r#"{"jsonrpc":"2.0","method":"list_methods"}"#,
+            r#"{"jsonrpc":"2.0","method":"list_methods","id":null}"#,
+            r#"[{"jsonrpc":"2.0","method":"get_dht_connected_peers","id":2},{"jsonrpc":"2.0","method":"list_methods"}]"#,
+            "not json",
+        ],
+        3,
+    )
+    .await;
+    assert_eq!(answers[0]["id"], Value::Null);
+    assert_eq!(answers[0]["result"][0], "get_dht_peer_id");
+    assert_eq!(
+        answers[1],
+        json!([{ "jsonrpc": "2.0", "result": [], "id": 2 }])
+    );
+    assert_eq!(answers[2]["error"]["code"], rpc::PARSE_ERROR);
+
+    drop(server);
+    assert!(!socket.exists());
+    let _ = dht.shutdown().await;
+}
@@ -57,6 +57,16 @@ export interface Ed2kSourceInfo {
   file_hash: string;
 }
 
+/** Web seed URLs as signed by the file's publisher */
+export interface WebSeeds {
+  urls: string[];
+  chunkSize: number;
+  /** Hex SHA-256 of each chunk */
+  chunkHashes: string[];
+  publisher: string;
+  signature: string;
+}
+
 export interface FileMetadata {
   fileHash: string;
   fileName: string;
@@ -76,12 +86,26 @@ export interface FileMetadata {
   price: number;
   uploaderAddress?: string;
   httpSources?: HttpSourceInfo[];
+  /** Publisher-hosted HTTP(S) copies of the file */
+  webSeeds?: WebSeeds;
   ftpSources?: FtpSourceInfo[];
   ed2kSources?: Ed2kSourceInfo[];
   infoHash?: string;
   trackers?: string[];
 }
 
+export interface PortForwardingStatus {
+  upnpEnabled: boolean;
+  upnpSuccess: boolean;
+  upnpExternalPort: number | null;
+  natPmpEnabled: boolean;
+  natPmpSuccess: boolean;
+  natPmpExternalPort: number | null;
+  externalIp: string | null;
+  gatewayAddr: string | null;
+  lastChecked: number | null;
+}
+
 export interface DhtHealth {
   peerCount: number;
   lastBootstrap: number | null;
@@ -126,6 +150,70 @@ export interface DhtHealth {
   dcutrHolePunchFailures: number;
   lastDcutrSuccess: number | null;
   lastDcutrFailure: number | null;
+  /** Gossip messages dropped by the topic filter */
+  gossipMessagesBlocked: number;
+  /** Payload bytes of compressed gossip, before and after compression */
+  gossipBytesUncompressed: number;
+  gossipBytesCompressed: number;
+  connectionsOpened: number;
+  connectionsClosed: number;
+  kadRequestsServed: number;
+  kadRecordsStored: number;
+  relayCircuitsAccepted: number;
+  relayCircuitsActive: number;
+  transfersReceived: number;
+  transfersFailed: number;
+}
+
+export type ReachabilityProbe =
+  | { status: "notProbed" }
+  | { status: "reachable"; latencyMs: number }
+  | { status: "unreachable" };
+
+export interface RankedSearchResult {
+  fileHash: string;
+  fileName: string;
+  fileSize: number;
+  /** Only set for the first page of results */
+  providerCount: number | null;
+  probe: ReachabilityProbe;
+  score: number;
+}
+
+export interface PeerAddrMatch {
+  peerId: string;
+  addrs: string[];
+}
+
+/** Payload of `bootstrap-node-down` / `bootstrap-node-recovered` events */
+export interface BootstrapNodeEvent {
+  addr: string;
+  failureCount: number;
+}
+
+export interface BootstrapNodeStatus {
+  addr: string;
+  isReachable: boolean;
+  failureCount: number;
+  lastSuccessSecsAgo: number | null;
+}
+
+export interface BootstrapContributionStats {
+  nodeAddr: string;
+  peersIntroduced: number;
+  successfulDialsToDiscoveredPeers: number;
+  /** Unix seconds; 0 if the node has not contributed yet */
+  lastContributionTime: number;
+}
+
+export interface NodeAnnouncement {
+  peerId: string;
+  protocols: string[];
+  agentVersion: string;
+  uptimeSecs: number;
+  /** Circuit reservations offered; null when the node does not relay */
+  relayCapacity: number | null;
+  timestamp: number;
 }
 
 export class DhtService {
@@ -225,7 +313,8 @@ export class DhtService {
     filePath: string,
     price?: number,
     protocol?: string,
-    originalFileName?: string
+    originalFileName?: string,
+    webSeeds?: string[]
   ): Promise<FileMetadata> {
     try {
       // Start listening for the published_file event
@@ -267,6 +356,7 @@ export class DhtService {
         price: price ?? 0, // Default to 0 instead of null
         protocol: protocol ?? "Bitswap", // Default to Bitswap if no protocol specified
         originalFileName: originalFileName || null,
+        webSeeds: webSeeds?.length ? webSeeds : null,
       });
 
       // Wait until the event arrives
@@ -462,6 +552,56 @@ export class DhtService {
     }
   }
 
+  /** Search known files by name, best-seeded and most reachable first */
+  async searchFiles(query: string, probe = true): Promise<RankedSearchResult[]> {
+    return await invoke<RankedSearchResult[]>("search_files", { query, probe });
+  }
+
+  /** Capabilities other nodes announced on the mesh, most recent first */
+  async getNodeAnnouncements(): Promise<NodeAnnouncement[]> {
+    try {
+      return await invoke<NodeAnnouncement[]>("get_node_announcements_command");
+    } catch (error) {
+      console.error("Failed to get node announcements:", error);
+      return [];
+    }
+  }
+
+  /**
+   * Peers whose id contains `query` (up to 10) with their addresses, best
+   * first; addresses not seen for a week are listed last
+   */
+  async findPeerAddresses(query: string): Promise<PeerAddrMatch[]> {
+    try {
+      return await invoke<PeerAddrMatch[]>("find_peer_addresses", { query });
+    } catch (error) {
+      console.error("Failed to find peer addresses:", error);
+      return [];
+    }
+  }
+
+  /** Reachability of the bootstrap nodes, probed every minute */
+  async getBootstrapNodeStatus(): Promise<BootstrapNodeStatus[]> {
+    try {
+      return await invoke<BootstrapNodeStatus[]>("get_bootstrap_node_status");
+    } catch (error) {
+      console.error("Failed to get bootstrap node status:", error);
+      return [];
+    }
+  }
+
+  /** Peers found through each bootstrap node, most helpful first */
+  async getBootstrapContributionStats(): Promise<BootstrapContributionStats[]> {
+    try {
+      return await invoke<BootstrapContributionStats[]>(
+        "get_bootstrap_contribution_stats_command"
+      );
+    } catch (error) {
+      console.error("Failed to get bootstrap contribution stats:", error);
+      return [];
+    }
+  }
+
   async getPeerCount(): Promise<number> {
     try {
       const count = await invoke<number>("get_dht_peer_count");
@@ -482,6 +622,15 @@ export class DhtService {
     }
   }
 
+  async getPortForwardingStatus(): Promise<PortForwardingStatus | null> {
+    try {
+      return await invoke<PortForwardingStatus>("get_port_forwarding_status_command");
+    } catch (error) {
+      console.error("Failed to get port forwarding status:", error);
+      return null;
+    }
+  }
+
   async searchFileMetadata(
     fileHash: string,
     timeoutMs = 10_000
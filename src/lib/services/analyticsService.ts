@@ -1,9 +1,12 @@
 import { invoke } from "@tauri-apps/api/core";
+import type { BandwidthScheduleStatus } from "./bandwidthScheduler";
 
 export interface BandwidthStats {
   uploadBytes: number;
   downloadBytes: number;
   lastUpdated: number;
+  /** Bandwidth schedule state; absent in the fallback value */
+  schedule?: BandwidthScheduleStatus;
 }
 
 export interface BandwidthDataPoint {
@@ -1,3 +1,4 @@
+import { invoke } from "@tauri-apps/api/core";
 import { get } from "svelte/store";
 import { settings, activeBandwidthLimits } from "$lib/stores";
 import type {
@@ -5,21 +6,31 @@ import type {
   ActiveBandwidthLimits,
 } from "$lib/stores";
 
-type ScheduleMatch = {
-  schedule: BandwidthScheduleEntry;
-  day: number;
-};
+/** Schedule state reported by the backend in `get_bandwidth_stats` */
+export interface BandwidthScheduleStatus {
+  enabled: boolean;
+  /** Suspended by a manual override */
+  suspended: boolean;
+  source: "default" | "schedule" | "override";
+  activeEntry: BandwidthScheduleEntry | null;
+  uploadLimitKbps: number;
+  downloadLimitKbps: number;
+  /** Unix ms */
+  nextChangeAt: number | null;
+}
 
 /**
  * Bandwidth Scheduler Service
  *
- * This service manages bandwidth scheduling based on time of day and day of week.
- * It checks active schedules and applies appropriate bandwidth limits.
+ * The backend evaluates the schedule in local time and applies it to the
+ * global limiter as the clock crosses entry boundaries. This service pushes
+ * the schedule and default limits from settings to the backend and mirrors
+ * the limits in force into the `activeBandwidthLimits` store.
  */
 export class BandwidthSchedulerService {
   private static instance: BandwidthSchedulerService | null = null;
   private checkInterval: number | null = null;
-  private readonly CHECK_INTERVAL_MS = 60000; // Check every minute
+  private readonly CHECK_INTERVAL_MS = 60000; // Refresh every minute
 
   private currentUploadLimit: number = 0;
   private currentDownloadLimit: number = 0;
@@ -34,27 +45,30 @@ export class BandwidthSchedulerService {
     return BandwidthSchedulerService.instance;
   }
 
+  private get available(): boolean {
+    return typeof window !== "undefined" && "__TAURI_INTERNALS__" in window;
+  }
+
   /**
-   * Start the bandwidth scheduler
+   * Start mirroring the backend's limits
    */
   start() {
     if (this.checkInterval !== null) {
       return; // Already running
     }
 
-    // Initial check
-    this.checkAndApplySchedule();
+    this.forceUpdate();
 
-    // Set up periodic checking
     this.checkInterval = window.setInterval(() => {
-      this.checkAndApplySchedule();
+      this.refresh();
     }, this.CHECK_INTERVAL_MS);
 
     console.log("Bandwidth scheduler started");
   }
 
   /**
-   * Stop the bandwidth scheduler
+   * Stop mirroring; the backend falls back to the default limits once
+   * settings disable scheduling
    */
   stop() {
     if (this.checkInterval !== null) {
@@ -63,123 +77,70 @@ export class BandwidthSchedulerService {
       console.log("Bandwidth scheduler stopped");
     }
 
-    const currentSettings = get(settings);
-    this.applyLimits(
-      currentSettings.uploadBandwidth,
-      currentSettings.downloadBandwidth,
-      {
-        source: "default",
-        nextChangeAt: null,
-      }
-    );
+    this.forceUpdate();
   }
 
   /**
-   * Check current time and apply appropriate bandwidth schedule
+   * Push the current settings to the backend and refresh the active limits.
+   * Resolves with the backend's error when the schedule has overlapping or
+   * malformed entries, which leaves the previous schedule in force.
    */
-  private checkAndApplySchedule() {
+  async forceUpdate(): Promise<string | null> {
+    if (!this.available) {
+      return null;
+    }
     const currentSettings = get(settings);
 
-    const now = new Date();
-    const currentTime = this.formatTime(now);
-    const currentDay = now.getDay(); // 0-6, where 0 = Sunday
-    const enabledSchedules =
-      currentSettings.bandwidthSchedules?.filter((entry) => entry.enabled) ??
-      [];
-
-    const activeMatch = currentSettings.enableBandwidthScheduling
-      ? this.findActiveSchedule(enabledSchedules, currentTime, currentDay)
-      : null;
-
-    const nextChangeAt = this.computeNextChangeTimestamp(
-      now,
-      enabledSchedules,
-      activeMatch
-    );
-
-    // Find active schedule
-    if (activeMatch) {
-      this.applyLimits(
-        activeMatch.schedule.uploadLimit,
-        activeMatch.schedule.downloadLimit,
-        {
-          source: "schedule",
-          schedule: activeMatch.schedule,
-          nextChangeAt,
-        }
-      );
-      return;
+    try {
+      await invoke("set_bandwidth_limits", {
+        uploadKbps: Math.max(0, Math.floor(currentSettings.uploadBandwidth || 0)),
+        downloadKbps: Math.max(0, Math.floor(currentSettings.downloadBandwidth || 0)),
+      });
+      const status = await invoke<BandwidthScheduleStatus>("set_bandwidth_schedule", {
+        entries: currentSettings.bandwidthSchedules ?? [],
+        enabled: currentSettings.enableBandwidthScheduling,
+      });
+      this.applyStatus(status);
+      return null;
+    } catch (error) {
+      console.error("Failed to apply bandwidth schedule:", error);
+      await this.refresh();
+      return String(error);
     }
-
-    // No active schedule or scheduling disabled: fall back to defaults.
-    this.applyLimits(
-      currentSettings.uploadBandwidth,
-      currentSettings.downloadBandwidth,
-      {
-        source: "default",
-        nextChangeAt,
-      }
-    );
   }
 
   /**
-   * Find the active schedule for the current time and day
+   * Re-read the limits in force from the backend
    */
-  private findActiveSchedule(
-    schedules: BandwidthScheduleEntry[],
-    currentTime: string,
-    currentDay: number
-  ): ScheduleMatch | null {
-    // Filter to only enabled schedules for today
-    const applicableSchedules = schedules.filter((schedule) =>
-      schedule.daysOfWeek.includes(currentDay)
-    );
-
-    // Find schedules where current time falls within the range
-    for (const schedule of applicableSchedules) {
-      if (
-        this.isTimeInRange(currentTime, schedule.startTime, schedule.endTime)
-      ) {
-        return { schedule, day: currentDay };
-      }
+  async refresh(): Promise<void> {
+    if (!this.available) {
+      return;
     }
-
-    return null;
-  }
-
-  /**
-   * Check if a time falls within a range
-   * Handles ranges that cross midnight (e.g., 22:00 to 06:00)
-   */
-  private isTimeInRange(time: string, start: string, end: string): boolean {
-    const timeMinutes = this.timeToMinutes(time);
-    const startMinutes = this.timeToMinutes(start);
-    const endMinutes = this.timeToMinutes(end);
-
-    if (startMinutes <= endMinutes) {
-      // Normal range (doesn't cross midnight)
-      return timeMinutes >= startMinutes && timeMinutes < endMinutes;
-    } else {
-      // Range crosses midnight
-      return timeMinutes >= startMinutes || timeMinutes < endMinutes;
+    try {
+      const stats = await invoke<{ schedule: BandwidthScheduleStatus }>("get_bandwidth_stats");
+      this.applyStatus(stats.schedule);
+    } catch (error) {
+      console.error("Failed to read bandwidth schedule:", error);
     }
   }
 
   /**
-   * Convert time string (HH:MM) to minutes since midnight
+   * Use these limits and suspend the schedule until `resumeSchedule`
    */
-  private timeToMinutes(time: string): number {
-    const [hours, minutes] = time.split(":").map(Number);
-    return hours * 60 + minutes;
+  async overrideLimits(uploadKbps: number, downloadKbps: number): Promise<void> {
+    const status = await invoke<BandwidthScheduleStatus>("override_bandwidth_limits", {
+      uploadKbps,
+      downloadKbps,
+    });
+    this.applyStatus(status);
   }
 
   /**
-   * Format date to HH:MM
+   * End a manual override and follow the schedule again
    */
-  private formatTime(date: Date): string {
-    const hours = String(date.getHours()).padStart(2, "0");
-    const minutes = String(date.getMinutes()).padStart(2, "0");
-    return `${hours}:${minutes}`;
+  async resumeSchedule(): Promise<void> {
+    const status = await invoke<BandwidthScheduleStatus>("resume_bandwidth_schedule");
+    this.applyStatus(status);
   }
 
   /**
@@ -220,186 +181,21 @@ export class BandwidthSchedulerService {
     return `Upload: ${upload}, Download: ${download}`;
   }
 
-  /**
-   * Force immediate check and update
-   */
-  forceUpdate() {
-    this.checkAndApplySchedule();
-  }
-
-  private applyLimits(
-    uploadLimit: number,
-    downloadLimit: number,
-    meta: {
-      source: ActiveBandwidthLimits["source"];
-      schedule?: BandwidthScheduleEntry;
-      nextChangeAt?: number | null;
-    }
-  ) {
-    this.currentUploadLimit = uploadLimit;
-    this.currentDownloadLimit = downloadLimit;
-    this.currentScheduleId = meta.schedule?.id ?? null;
+  private applyStatus(status: BandwidthScheduleStatus) {
+    this.currentUploadLimit = status.uploadLimitKbps;
+    this.currentDownloadLimit = status.downloadLimitKbps;
+    this.currentScheduleId = status.activeEntry?.id ?? null;
 
     const limits: ActiveBandwidthLimits = {
-      uploadLimitKbps: uploadLimit,
-      downloadLimitKbps: downloadLimit,
-      source: meta.source,
-      scheduleId: meta.schedule?.id ?? undefined,
-      scheduleName: meta.schedule?.name ?? undefined,
-      nextChangeAt: meta.nextChangeAt ?? undefined,
+      uploadLimitKbps: status.uploadLimitKbps,
+      downloadLimitKbps: status.downloadLimitKbps,
+      source: status.source,
+      scheduleId: status.activeEntry?.id ?? undefined,
+      scheduleName: status.activeEntry?.name ?? undefined,
+      nextChangeAt: status.nextChangeAt ?? undefined,
     };
 
     activeBandwidthLimits.set(limits);
-
-    if (meta.schedule) {
-      console.log(`Applied bandwidth schedule "${meta.schedule.name}":`, {
-        upload: uploadLimit === 0 ? "unlimited" : `${uploadLimit} KB/s`,
-        download: downloadLimit === 0 ? "unlimited" : `${downloadLimit} KB/s`,
-        nextChangeAt: meta.nextChangeAt,
-      });
-    }
-  }
-
-  private computeNextChangeTimestamp(
-    now: Date,
-    schedules: BandwidthScheduleEntry[],
-    activeMatch: ScheduleMatch | null
-  ): number | null {
-    let candidate: number | null = null;
-
-    if (activeMatch) {
-      const endTs = this.getActiveOccurrenceEndTimestamp(
-        activeMatch.schedule,
-        now,
-        activeMatch.day
-      );
-      if (endTs !== null && endTs > now.getTime()) {
-        candidate = endTs;
-      }
-    }
-
-    for (const schedule of schedules) {
-      for (const day of schedule.daysOfWeek) {
-        const startTs = this.getNextOccurrenceTimestamp(
-          schedule.startTime,
-          day,
-          now
-        );
-        if (startTs !== null && startTs > now.getTime()) {
-          if (candidate === null || startTs < candidate) {
-            candidate = startTs;
-          }
-        }
-      }
-    }
-
-    return candidate;
-  }
-
-  private getNextOccurrenceTimestamp(
-    time: string,
-    day: number,
-    reference: Date
-  ): number | null {
-    if (time.trim().length === 0) {
-      return null;
-    }
-
-    const [hours, minutes] = time.split(":").map(Number);
-    const occurrence = new Date(reference);
-    const diff = (day - reference.getDay() + 7) % 7;
-    occurrence.setDate(occurrence.getDate() + diff);
-    occurrence.setHours(hours, minutes, 0, 0);
-
-    if (occurrence <= reference) {
-      occurrence.setDate(occurrence.getDate() + 7);
-    }
-
-    return occurrence.getTime();
-  }
-
-  private getActiveOccurrenceEndTimestamp(
-    schedule: BandwidthScheduleEntry,
-    reference: Date,
-    activeDay: number
-  ): number | null {
-    const durationMinutes = this.getScheduleDurationMinutes(schedule);
-    if (durationMinutes <= 0) {
-      return null;
-    }
-
-    const startTs = this.getMostRecentOccurrenceTimestamp(
-      schedule.startTime,
-      schedule.daysOfWeek,
-      reference,
-      activeDay
-    );
-
-    if (startTs === null) {
-      return null;
-    }
-
-    return startTs + durationMinutes * 60 * 1000;
-  }
-
-  private getMostRecentOccurrenceTimestamp(
-    time: string,
-    days: number[],
-    reference: Date,
-    preferredDay: number
-  ): number | null {
-    if (time.trim().length === 0 || days.length === 0) {
-      return null;
-    }
-
-    const [hours, minutes] = time.split(":").map(Number);
-    let latest: number | null = null;
-
-    const orderedDays = [
-      preferredDay,
-      ...days.filter((day) => day !== preferredDay),
-    ];
-
-    for (const day of orderedDays) {
-      const occurrence = new Date(reference);
-      const diff = (reference.getDay() - day + 7) % 7;
-      occurrence.setDate(occurrence.getDate() - diff);
-      occurrence.setHours(hours, minutes, 0, 0);
-
-      if (occurrence > reference) {
-        occurrence.setDate(occurrence.getDate() - 7);
-      }
-
-      const timestamp = occurrence.getTime();
-      if (timestamp <= reference.getTime()) {
-        if (latest === null || timestamp > latest) {
-          latest = timestamp;
-        }
-      }
-    }
-
-    return latest;
-  }
-
-  private getScheduleDurationMinutes(schedule: BandwidthScheduleEntry): number {
-    const startMinutes = this.timeToMinutes(schedule.startTime);
-    const endMinutes = this.timeToMinutes(schedule.endTime);
-
-    if (isNaN(startMinutes) || isNaN(endMinutes)) {
-      return 0;
-    }
-
-    if (startMinutes === endMinutes) {
-      // Treat as full-day schedule
-      return 24 * 60;
-    }
-
-    if (startMinutes < endMinutes) {
-      return endMinutes - startMinutes;
-    }
-
-    // Crosses midnight
-    return 24 * 60 - startMinutes + endMinutes;
   }
 }
 
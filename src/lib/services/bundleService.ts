@@ -0,0 +1,42 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+
+export interface BundleFileError {
+  path: string;
+  error: string;
+}
+
+export interface BundleProgress {
+  manifestHash: string;
+  name: string;
+  totalFiles: number;
+  completedFiles: number;
+  skippedFiles: number;
+  failedFiles: BundleFileError[];
+  totalBytes: number;
+  downloadedBytes: number;
+  currentFile?: string;
+}
+
+/** Share a directory; resolves to the bundle manifest hash */
+export async function publishDirectory(path: string, price?: number): Promise<string> {
+  return await invoke<string>("publish_directory", { path, price });
+}
+
+/**
+ * Download a bundle into `destDir`. `selected` limits the download to file
+ * paths or directory prefixes; calling again resumes a partial download.
+ */
+export async function downloadBundle(
+  manifestHash: string,
+  destDir: string,
+  selected?: string[]
+): Promise<BundleProgress> {
+  return await invoke<BundleProgress>("download_bundle", { manifestHash, destDir, selected });
+}
+
+export async function onBundleProgress(
+  handler: (progress: BundleProgress) => void
+): Promise<UnlistenFn> {
+  return await listen<BundleProgress>("bundle_download_progress", (event) => handler(event.payload));
+}
@@ -0,0 +1,118 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+
+export interface MessageReaction {
+  messageId: string;
+  reactorPeerId: string;
+  emoji: string;
+  action: "add" | "remove";
+  /** Unix seconds */
+  timestamp: number;
+}
+
+/** Receive messages and reactions published on `channel` */
+export async function joinChannel(channel: string): Promise<void> {
+  await invoke("join_message_channel", { channel });
+}
+
+export type TopicFilterMode =
+  | "block_peer"
+  | "unblock_peer"
+  | "mute_topic"
+  | "unmute_topic"
+  | "allow_topic_only"
+  | "allow_all_topics";
+
+export interface TopicFilterState {
+  blockedPeers: string[];
+  mutedTopics: string[];
+  /** Null unless only allow-listed channels are received */
+  allowedTopics: string[] | null;
+}
+
+/** Mute channels or block peers; filtered messages are dropped on arrival */
+export async function setTopicFilter(
+  mode: TopicFilterMode,
+  topicOrPeer: string
+): Promise<TopicFilterState> {
+  return await invoke<TopicFilterState>("set_topic_filter_command", { mode, topicOrPeer });
+}
+
+export async function reactToMessage(
+  channel: string,
+  messageId: string,
+  emoji: string,
+  remove = false
+): Promise<MessageReaction> {
+  return await invoke<MessageReaction>("react_to_message", { channel, messageId, emoji, remove });
+}
+
+export async function getMessageReactions(messageId: string): Promise<MessageReaction[]> {
+  return await invoke<MessageReaction[]>("get_message_reactions_command", { messageId });
+}
+
+/** Fires when a peer's reaction changed the stored reactions */
+export async function onMessageReaction(
+  handler: (reaction: MessageReaction) => void
+): Promise<UnlistenFn> {
+  return await listen<MessageReaction>("message-reaction", (event) => handler(event.payload));
+}
+
+export interface StoredMessage {
+  id: string;
+  topic: string;
+  fromPeer: string;
+  payload: number[];
+  timestamp: number;
+  receivedAt: number;
+  replyTo?: string;
+  threadRootId?: string;
+  /** Peers that sent a read receipt; only tracked for our own messages */
+  readBy: string[];
+}
+
+export interface MessagePage {
+  messages: StoredMessage[];
+  total: number;
+  limit: number;
+  offset: number;
+}
+
+/** Post on `channel`; pass `replyTo` to answer an earlier message in its thread */
+export async function publishMessage(
+  channel: string,
+  payload: Uint8Array,
+  replyTo?: string
+): Promise<StoredMessage> {
+  return await invoke<StoredMessage>("publish_message_command", {
+    channel,
+    payload: Array.from(payload),
+    replyTo,
+  });
+}
+
+export async function getThread(rootId: string, limit = 50, offset = 0): Promise<MessagePage> {
+  return await invoke<MessagePage>("get_thread_command", { rootId, limit, offset });
+}
+
+/** Fires for each message a peer published on a joined channel */
+export async function onChannelMessage(handler: (message: StoredMessage) => void): Promise<UnlistenFn> {
+  return await listen<StoredMessage>("channel-message", (event) => handler(event.payload));
+}
+
+export interface ReadReceipt {
+  messageId: string;
+  readerPeerId: string;
+  /** Unix seconds */
+  readAt: number;
+}
+
+/** Send read receipts to the authors; resolves to the number sent */
+export async function markMessagesRead(messageIds: string[]): Promise<number> {
+  return await invoke<number>("mark_messages_read", { messageIds });
+}
+
+/** Fires when a peer reads one of our messages for the first time */
+export async function onMessageRead(handler: (receipt: ReadReceipt) => void): Promise<UnlistenFn> {
+  return await listen<ReadReceipt>("message-read", (event) => handler(event.payload));
+}
@@ -0,0 +1,64 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+
+/** Payload of `file-transfer-progress`, emitted while a peer sends us a file */
+export interface DirectTransferProgress {
+  transfer_id: string;
+  peer_id: string;
+  filename: string;
+  bytes_received: number;
+  total_bytes: number;
+  /** Bytes that crossed the wire; below `bytes_received` when compressed */
+  wire_bytes: number;
+}
+
+/** Payload of `file-transfer-offer`: a peer waits for us to accept or reject a file */
+export interface FileOffer {
+  transfer_id: string;
+  peer_id: string;
+  filename: string;
+  size: number;
+}
+
+export interface CompressionStats {
+  logicalBytes: number;
+  wireBytes: number;
+}
+
+export interface TransferCompressionStats {
+  sent: CompressionStats;
+  received: CompressionStats;
+}
+
+/** Send a file straight to a peer; resolves with the bytes the peer confirmed */
+export async function sendFileToPeer(peerId: string, filePath: string): Promise<number> {
+  return await invoke<number>("send_file_to_peer", { peerId, filePath });
+}
+
+/** Start receiving an offered file; fails if it does not fit on disk or in the quota */
+export async function acceptFileTransfer(transferId: string): Promise<void> {
+  await invoke("accept_file_transfer", { transferId });
+}
+
+export async function rejectFileTransfer(transferId: string, reason?: string): Promise<void> {
+  await invoke("reject_file_transfer", { transferId, reason });
+}
+
+/** Logical vs. on-the-wire bytes of direct transfers since startup */
+export async function getTransferCompressionStats(): Promise<TransferCompressionStats> {
+  return await invoke<TransferCompressionStats>("get_transfer_compression_stats");
+}
+
+export async function onFileTransferProgress(
+  handler: (progress: DirectTransferProgress) => void
+): Promise<UnlistenFn> {
+  return await listen<DirectTransferProgress>("file-transfer-progress", (event) =>
+    handler(event.payload)
+  );
+}
+
+export async function onFileTransferOffer(
+  handler: (offer: FileOffer) => void
+): Promise<UnlistenFn> {
+  return await listen<FileOffer>("file-transfer-offer", (event) => handler(event.payload));
+}
@@ -0,0 +1,32 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+
+export interface DirectMessageReceipt {
+  messageId: string;
+  /** False if the message was queued for retransmission */
+  delivered: boolean;
+}
+
+export interface MessageSendFailed {
+  messageId: string;
+  targetPeer: string;
+  attempts: number;
+  error: string;
+}
+
+/** Send a direct message; undelivered messages are retried in the background */
+export async function sendDirectMessage(
+  peerId: string,
+  payload: Uint8Array
+): Promise<DirectMessageReceipt> {
+  return await invoke<DirectMessageReceipt>("send_direct_message", {
+    peerId,
+    payload: Array.from(payload),
+  });
+}
+
+export async function onMessageSendFailed(
+  handler: (failure: MessageSendFailed) => void
+): Promise<UnlistenFn> {
+  return await listen<MessageSendFailed>("message-send-failed", (event) => handler(event.payload));
+}
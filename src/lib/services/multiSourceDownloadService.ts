@@ -1,4 +1,5 @@
 import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 
 export interface ChunkInfo {
   chunkId: number;
@@ -44,12 +45,41 @@ export interface SourceAssignment {
   status: 'Connecting' | 'Connected' | 'Downloading' | 'Failed' | 'Completed';
   connectedAt?: number;
   lastActivity?: number;
+  /** Request pipelining state, for sources that pipeline chunk requests */
+  pipeline?: RequestWindowInfo;
+}
+
+export interface RequestWindowInfo {
+  /** Requests currently allowed in flight */
+  window: number;
+  maxDepth: number;
+  adaptive: boolean;
+  inFlight: number;
+  inFlightBytes: number;
+  timeouts: number;
 }
 
 // Legacy type for backwards compatibility
 /** @deprecated Use SourceAssignment instead */
 export type PeerAssignment = SourceAssignment;
 
+export interface ProviderProbe {
+  peerId: string;
+  hasContent: boolean;
+  latencyMs?: number;
+  throughputBps?: number;
+  error?: string;
+  selected: boolean;
+}
+
+export interface ExcludedSource {
+  sourceId: string;
+  contentHash: string;
+  reason: string;
+  failures: number;
+  excludedAt: number;
+}
+
 export interface MultiSourceProgress {
   fileHash: string;
   fileName: string;
@@ -61,14 +91,50 @@ export interface MultiSourceProgress {
   downloadSpeedBps: number;
   etaSeconds?: number;
   sourceAssignments: SourceAssignment[];
+  rateLimit?: { uploadKbps: number; downloadKbps: number };
+  providerProbes?: ProviderProbe[];
+  excludedSources?: ExcludedSource[];
+  /** Verified bytes fetched from HTTP(S) web seeds */
+  webSeedBytes?: number;
 }
 
+/** Persisted state of an unfinished download */
+export interface ResumeRecord {
+  fileHash: string;
+  fileName: string;
+  outputPath: string;
+  manifestRoot: string;
+  fileSize: number;
+  chunkSize: number;
+  sequential: boolean;
+  autoResume: boolean;
+  /** Chunk id -> SHA-256 of the data in the partial file */
+  completedChunks: Record<string, string>;
+  updatedAt: number;
+}
+
+/** One entry of the `downloads-resumed` event */
+export type ResumedDownload = {
+  fileHash: string;
+  fileName: string;
+  outputPath: string;
+} & (
+  | { outcome: 'fresh' }
+  | { outcome: 'continued'; chunksKept: number; bytesKept: number }
+  | { outcome: 'restarted'; reason: 'partialMissing' | 'manifestChanged' | 'spotCheckFailed' | 'sessionKeyLost' }
+  | { outcome: 'failed'; error: string }
+);
+
 export interface MultiSourceDownloadOptions {
   maxPeers?: number;
   chunkSize?: number;
   preferMultiSource?: boolean;
   selectedPeers?: string[];  // Explicitly selected peers from peer selection modal
   peerAllocation?: Array<{peerId: string; percentage: number}>;  // Manual chunk allocation
+  sequential?: boolean;  // Fetch chunks in order for streaming playback
+  probeProviders?: boolean;  // Probe providers first; defaults to on for large files
+  overwrite?: boolean;  // Replace an existing file instead of saving as "name (1).ext"
+  encryptPartials?: boolean;  // Keep partial data encrypted on disk; cannot resume after a restart
 }
 
 export class MultiSourceDownloadService {
@@ -87,10 +153,60 @@ export class MultiSourceDownloadService {
       maxPeers: options?.maxPeers,
       chunkSize: options?.chunkSize,
       selectedPeers: options?.selectedPeers,
-      peerAllocation: options?.peerAllocation
+      peerAllocation: options?.peerAllocation,
+      sequential: options?.sequential,
+      probeProviders: options?.probeProviders,
+      overwrite: options?.overwrite,
+      encryptPartials: options?.encryptPartials
     });
   }
 
+  /**
+   * Limit this transfer's bandwidth (KB/s); pass 0 for both to remove the limit
+   */
+  static async setRateLimit(fileHash: string, downKbps: number, upKbps: number): Promise<void> {
+    return invoke('set_transfer_rate_limit', { id: fileHash, downKbps, upKbps });
+  }
+
+  /**
+   * Switch an active download between in-order and parallel chunk fetching
+   */
+  static async setSequential(fileHash: string, sequential: boolean): Promise<void> {
+    return invoke('set_transfer_sequential', { fileHash, sequential });
+  }
+
+  /**
+   * Read verified bytes of an in-progress download.
+   * Resolves to null if the range is not available yet.
+   */
+  static async readRange(
+    fileHash: string,
+    offset: number,
+    len: number,
+    waitMs?: number
+  ): Promise<Uint8Array | null> {
+    const data = await invoke<number[] | null>('read_transfer_range', {
+      id: fileHash,
+      offset,
+      len,
+      waitMs
+    });
+    return data ? new Uint8Array(data) : null;
+  }
+
+  /**
+   * Download a byte range of a file to `dest` without fetching the whole file.
+   * Resolves to the number of bytes written.
+   */
+  static async downloadRange(
+    contentHash: string,
+    offset: number,
+    len: number,
+    dest: string
+  ): Promise<number> {
+    return await invoke<number>('download_range', { contentHash, offset, len, dest });
+  }
+
   /**
    * Cancel an active multi-source download
    */
@@ -105,6 +221,34 @@ export class MultiSourceDownloadService {
     return invoke('get_multi_source_progress', { fileHash });
   }
 
+  /**
+   * Unfinished downloads that survive a restart of the app
+   */
+  static async getResumableDownloads(): Promise<ResumeRecord[]> {
+    return invoke('get_resumable_downloads');
+  }
+
+  /**
+   * Choose whether an unfinished download is queued again on the next startup
+   */
+  static async setAutoResume(fileHash: string, autoResume: boolean): Promise<void> {
+    return invoke('set_download_auto_resume', { fileHash, autoResume });
+  }
+
+  /**
+   * Fires once after startup with the interrupted downloads that were queued again
+   */
+  static async onDownloadsResumed(handler: (resumed: ResumedDownload[]) => void): Promise<UnlistenFn> {
+    return listen<ResumedDownload[]>('downloads-resumed', (event) => handler(event.payload));
+  }
+
+  /**
+   * Readmit providers excluded for serving corrupt chunks; returns how many were cleared
+   */
+  static async clearSourceExclusions(transferId: string): Promise<number> {
+    return invoke('clear_source_exclusions', { transferId });
+  }
+
   /**
    * Download a file with automatic multi-source detection
    * Falls back to single-source if multi-source is not beneficial
@@ -118,7 +262,8 @@ export class MultiSourceDownloadService {
       fileHash,
       outputPath,
       preferMultiSource: options?.preferMultiSource ?? true,
-      maxPeers: options?.maxPeers
+      maxPeers: options?.maxPeers,
+      overwrite: options?.overwrite
     });
   }
 
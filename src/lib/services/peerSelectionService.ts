@@ -23,6 +23,26 @@ export interface PeerMetrics {
   encryption_support: boolean;
 }
 
+export type ConnectionQuality = "good" | "fair" | "poor" | "unusable";
+
+/**
+ * Peer metrics labelled with their connection quality
+ */
+export interface FullPeerInfo extends PeerMetrics {
+  connection_quality: ConnectionQuality;
+}
+
+/**
+ * Payload of the `quality-degraded` event
+ */
+export interface QualityDegradedEvent {
+  peerId: string;
+  previous: ConnectionQuality;
+  quality: ConnectionQuality;
+  latencyMs: number | null;
+  errorRate: number;
+}
+
 /**
  * Peer selection strategies
  */
@@ -108,6 +128,19 @@ export class PeerSelectionService {
     }
   }
 
+  /**
+   * Get all peer metrics with their connection quality
+   */
+  static async getFullPeerInfo(): Promise<FullPeerInfo[]> {
+    try {
+      const peers = await invoke<FullPeerInfo[]>("get_full_peer_info");
+      return peers || [];
+    } catch (error) {
+      console.error("Failed to get peer info:", error);
+      return [];
+    }
+  }
+
   /**
    * Select peers using a specific strategy
    */
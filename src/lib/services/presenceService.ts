@@ -0,0 +1,27 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+
+/** Payload of `peer-typing` */
+export interface PeerTyping {
+  peerId: string;
+  channel: string;
+  typing: boolean;
+}
+
+/**
+ * Report a keypress in `channel`. Safe to call on every input event: the
+ * backend only publishes when peers need to hear about it, and sends the
+ * stop on its own once typing pauses.
+ */
+export async function notifyTyping(channel: string): Promise<void> {
+  await invoke("start_typing", { channel }).catch(() => {});
+}
+
+/** Clear our indicator right away, e.g. after sending the message */
+export async function stopTyping(channel: string): Promise<void> {
+  await invoke("stop_typing", { channel }).catch(() => {});
+}
+
+export async function onPeerTyping(handler: (event: PeerTyping) => void): Promise<UnlistenFn> {
+  return await listen<PeerTyping>("peer-typing", (e) => handler(e.payload));
+}
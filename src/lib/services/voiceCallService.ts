@@ -0,0 +1,126 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+
+/** Payload of `incoming-call`, `call-accepted`, `call-rejected` and `call-ended` */
+export interface CallEvent {
+  sessionId: string;
+  peerId: string;
+  /** Caller's offer on `incoming-call`, callee's answer on `call-accepted` */
+  sdp?: string;
+  reason?: string;
+}
+
+export interface CallInfo {
+  sessionId: string;
+  peerId: string;
+  direction: "outgoing" | "incoming";
+  state: "ringing" | "active";
+  startedAt: number;
+  answeredAt?: number;
+}
+
+/**
+ * Offer/answer are exchanged once over the backend, without trickle ICE, so
+ * wait for candidate gathering to finish before handing the SDP over.
+ */
+async function gatheredDescription(pc: RTCPeerConnection): Promise<string> {
+  if (pc.iceGatheringState !== "complete") {
+    await new Promise<void>((resolve) => {
+      const check = () => {
+        if (pc.iceGatheringState === "complete") {
+          pc.removeEventListener("icegatheringstatechange", check);
+          resolve();
+        }
+      };
+      pc.addEventListener("icegatheringstatechange", check);
+    });
+  }
+  return pc.localDescription?.sdp ?? "";
+}
+
+/**
+ * One voice call: the RTCPeerConnection that carries the microphone track
+ * (Opus) and the signaling session it was negotiated over.
+ */
+export class VoiceCall {
+  sessionId = "";
+  readonly remoteStream = new MediaStream();
+
+  private constructor(
+    readonly peerId: string,
+    private readonly pc: RTCPeerConnection,
+    private readonly localStream: MediaStream
+  ) {
+    pc.ontrack = (event) => event.streams[0]?.getTracks().forEach((t) => this.remoteStream.addTrack(t));
+  }
+
+  private static async open(peerId: string): Promise<VoiceCall> {
+    const localStream = await navigator.mediaDevices.getUserMedia({ audio: true });
+    const pc = new RTCPeerConnection({
+      iceServers: [{ urls: "stun:stun.l.google.com:19302" }, { urls: "stun:global.stun.twilio.com:3478" }],
+    });
+    localStream.getTracks().forEach((track) => pc.addTrack(track, localStream));
+    return new VoiceCall(peerId, pc, localStream);
+  }
+
+  /** Call a peer; resolves once the invite has been sent */
+  static async start(peerId: string): Promise<VoiceCall> {
+    const call = await VoiceCall.open(peerId);
+    await call.pc.setLocalDescription(await call.pc.createOffer());
+    const sdpOffer = await gatheredDescription(call.pc);
+    call.sessionId = await invoke<string>("start_call", { peerId, sdpOffer });
+    return call;
+  }
+
+  /** Answer an `incoming-call` event */
+  static async accept(incoming: CallEvent): Promise<VoiceCall> {
+    const call = await VoiceCall.open(incoming.peerId);
+    call.sessionId = incoming.sessionId;
+    await call.pc.setRemoteDescription({ type: "offer", sdp: incoming.sdp ?? "" });
+    await call.pc.setLocalDescription(await call.pc.createAnswer());
+    const sdpAnswer = await gatheredDescription(call.pc);
+    await invoke("accept_call", { sessionId: call.sessionId, sdpAnswer });
+    return call;
+  }
+
+  /** Apply the callee's answer from a `call-accepted` event */
+  async connect(accepted: CallEvent): Promise<void> {
+    await this.pc.setRemoteDescription({ type: "answer", sdp: accepted.sdp ?? "" });
+  }
+
+  setMuted(muted: boolean): void {
+    this.localStream.getAudioTracks().forEach((track) => (track.enabled = !muted));
+  }
+
+  /** Release the microphone and connection; `notifyPeer` is false when the peer ended it */
+  async hangUp(notifyPeer = true): Promise<void> {
+    this.localStream.getTracks().forEach((track) => track.stop());
+    this.pc.close();
+    if (notifyPeer && this.sessionId) {
+      await invoke("end_call", { sessionId: this.sessionId }).catch(() => {});
+    }
+  }
+}
+
+export async function rejectCall(sessionId: string, reason?: string): Promise<void> {
+  await invoke("reject_call", { sessionId, reason });
+}
+
+export async function getActiveCalls(): Promise<CallInfo[]> {
+  return await invoke<CallInfo[]>("get_active_calls");
+}
+
+export async function onCallEvents(handlers: {
+  incoming?: (event: CallEvent) => void;
+  accepted?: (event: CallEvent) => void;
+  rejected?: (event: CallEvent) => void;
+  ended?: (event: CallEvent) => void;
+}): Promise<UnlistenFn> {
+  const unlisteners = await Promise.all([
+    listen<CallEvent>("incoming-call", (e) => handlers.incoming?.(e.payload)),
+    listen<CallEvent>("call-accepted", (e) => handlers.accepted?.(e.payload)),
+    listen<CallEvent>("call-rejected", (e) => handlers.rejected?.(e.payload)),
+    listen<CallEvent>("call-ended", (e) => handlers.ended?.(e.payload)),
+  ]);
+  return () => unlisteners.forEach((unlisten) => unlisten());
+}
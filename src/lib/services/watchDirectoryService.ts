@@ -0,0 +1,53 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+
+export interface WatchDirConfig {
+  /** Directory to watch; null stops watching */
+  path: string | null;
+  /** Globs relative to the directory; empty publishes every file */
+  include: string[];
+  exclude: string[];
+  /** Seconds a file must stay unchanged before it is published */
+  settleSecs: number;
+}
+
+export type WatchFileState =
+  | { state: "settling" }
+  | { state: "publishing" }
+  | { state: "published"; contentHash: string }
+  | { state: "failed"; error: string };
+
+export type WatchedFile = { path: string; size: number } & WatchFileState;
+
+export interface WatchStatus {
+  config: WatchDirConfig;
+  watching: boolean;
+  error: string | null;
+  files: WatchedFile[];
+}
+
+export interface WatchFileEvent {
+  path: string;
+  contentHash: string | null;
+  error: string | null;
+}
+
+export async function setWatchDirectory(config: WatchDirConfig): Promise<WatchStatus> {
+  return await invoke<WatchStatus>("set_watch_directory", { config });
+}
+
+export async function getWatchStatus(): Promise<WatchStatus> {
+  return await invoke<WatchStatus>("get_watch_status");
+}
+
+/** Files published, failed or un-announced by the watch directory */
+export async function onWatchFileEvent(
+  handler: (kind: "published" | "failed" | "removed", event: WatchFileEvent) => void
+): Promise<UnlistenFn> {
+  const unlisteners = await Promise.all(
+    (["published", "failed", "removed"] as const).map((kind) =>
+      listen<WatchFileEvent>(`watch-file-${kind}`, (event) => handler(kind, event.payload))
+    )
+  );
+  return () => unlisteners.forEach((unlisten) => unlisten());
+}
@@ -621,7 +621,7 @@ export interface BandwidthScheduleEntry {
 export interface ActiveBandwidthLimits {
   uploadLimitKbps: number;
   downloadLimitKbps: number;
-  source: "default" | "schedule";
+  source: "default" | "schedule" | "override";
   scheduleId?: string;
   scheduleName?: string;
   nextChangeAt?: number;
@@ -663,6 +663,7 @@ export interface AppSettings {
   relayServerAlias: string; // Public alias/name for your relay server (appears in logs and bootstrapping)
   anonymousMode: boolean;
   shareAnalytics: boolean;
+  enablePeerGeolocation: boolean; // Locate peers on the network map from a local GeoIP file
   enableWalletAutoLock: boolean;
   enableNotifications: boolean;
   notifyOnComplete: boolean;
@@ -682,9 +683,12 @@ export interface AppSettings {
   capWarningThresholds: number[]; // Percentages, e.g. [75, 90]
   enableFileLogging: boolean; // Enable file-based logging
   maxLogSizeMB: number; // Maximum size of a single log file in MB
+  maxLogFiles: number; // Number of rotated log files kept
+  logFormat: "text" | "json"; // Log line format; JSON is for log aggregation
   pricePerMb: number; // Price per MB in Chiral (e.g., 0.001)
   customBootstrapNodes: string[]; // Custom bootstrap nodes for DHT (leave empty to use defaults)
   autoStartDHT: boolean; // Whether to automatically start DHT on app launch
+  enableWebSeeds: boolean; // Download from publishers' HTTP(S) web seeds (HTTP egress)
   selectedProtocol: "WebRTC" | "Bitswap" | "BitTorrent" | "ED2K" | "FTP"; // Protocol selected for file uploads
 }
 
@@ -716,6 +720,7 @@ export const settings = writable<AppSettings>({
   relayServerAlias: "", // Empty by default - user can set a friendly name
   anonymousMode: false,
   shareAnalytics: true,
+  enablePeerGeolocation: false,
   enableWalletAutoLock: false,
   enableNotifications: true,
   notifyOnComplete: true,
@@ -735,9 +740,12 @@ export const settings = writable<AppSettings>({
   capWarningThresholds: [75, 90],
   enableFileLogging: false, // Disabled by default
   maxLogSizeMB: 10, // 10 MB per log file by default
+  maxLogFiles: 5,
+  logFormat: "text",
   pricePerMb: 0.001, // Default price: 0.001, until ability to set pricePerMb is there, then change to 0.001 Chiral per MB
   customBootstrapNodes: [], // Empty by default - use hardcoded bootstrap nodes
   autoStartDHT: false, // Don't auto-start DHT by default
+  enableWebSeeds: true,
   selectedProtocol: "Bitswap", // Default to Bitswap
 });
 
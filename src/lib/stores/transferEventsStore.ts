@@ -90,9 +90,18 @@ export interface Transfer {
   progressPercentage: number;
 
   // Speed tracking
+  /** Smoothed over recent progress */
   downloadSpeedBps: number;
   uploadSpeedBps: number;
+  /** Rate since the previous progress event */
+  instantaneousSpeedBps?: number;
+  /** Unset while stalled */
   etaSeconds?: number;
+  /** No new bytes for a while; the ETA is unknown */
+  stalled?: boolean;
+  /** Set while retrying a stalled transfer with new sources */
+  recoveryAttempt?: number;
+  maxRecoveryAttempts?: number;
 
   // Source tracking
   availableSources: SourceInfo[];
@@ -206,6 +215,9 @@ function createTransferStore() {
           case "completed":
             handleCompletedEvent(transfers, event);
             break;
+          case "recovering":
+            handleRecoveringEvent(transfers, event);
+            break;
           case "failed":
             handleFailedEvent(transfers, event);
             break;
@@ -390,7 +402,12 @@ function handleProgressEvent(transfers: Map<string, Transfer>, event: any) {
   transfer.progressPercentage = event.progressPercentage;
   transfer.downloadSpeedBps = event.downloadSpeedBps;
   transfer.uploadSpeedBps = event.uploadSpeedBps;
-  transfer.etaSeconds = event.etaSeconds;
+  transfer.instantaneousSpeedBps = event.instantaneousSpeedBps;
+  transfer.etaSeconds = event.etaSeconds ?? undefined;
+  transfer.stalled = event.stalled ?? false;
+  if (!transfer.stalled) {
+    transfer.recoveryAttempt = undefined;
+  }
   transfer.activeSources = event.activeSources;
 
   if (transfer.status !== "downloading" && transfer.status !== "paused") {
@@ -433,6 +450,19 @@ function handleCompletedEvent(transfers: Map<string, Transfer>, event: any) {
   transfer.sourcesUsed = event.sourcesUsed;
 }
 
+function handleRecoveringEvent(transfers: Map<string, Transfer>, event: any) {
+  const transfer = transfers.get(event.transferId);
+  if (!transfer) return;
+
+  transfer.stalled = true;
+  transfer.recoveryAttempt = event.attempt;
+  transfer.maxRecoveryAttempts = event.maxAttempts;
+  transfer.downloadedBytes = event.downloadedBytes;
+  for (const sourceId of event.droppedSources || []) {
+    transfer.connectedSources.delete(sourceId);
+  }
+}
+
 function handleFailedEvent(transfers: Map<string, Transfer>, event: any) {
   const transfer = transfers.get(event.transferId);
   if (!transfer) return;
@@ -0,0 +1,96 @@
+// Payloads of the `chiral-event` Tauri event; mirrors
+// src-tauri/src/chiral_events.rs, keep the two in sync.
+
+import type { TransferEventPayload } from '$lib/stores/transferEventsStore';
+
+export const CHIRAL_EVENT = 'chiral-event';
+
+export type ChiralEventKind =
+  | 'peer_discovered'
+  | 'peer_connected'
+  | 'peer_disconnected'
+  | 'nat_status'
+  | 'relay'
+  | 'bootstrap'
+  | 'transfer'
+  | 'transfer_progress'
+  | 'direct_transfer_progress'
+  | 'bandwidth';
+
+export interface PeerDiscoveredPayload {
+  peerId: string;
+  addresses: string[];
+}
+
+export interface PeerConnectedPayload {
+  peerId: string;
+  address: string | null;
+}
+
+export interface PeerDisconnectedPayload {
+  peerId: string;
+}
+
+export interface NatStatusPayload {
+  state: 'unknown' | 'public' | 'private';
+  confidence: 'low' | 'medium' | 'high';
+  lastError: string | null;
+  summary: string | null;
+}
+
+export interface RelayPayload {
+  peerId: string;
+  eventType: string;
+  impact: number;
+  data: unknown;
+}
+
+export interface BootstrapPayload {
+  connected: number;
+  configured: number;
+  dhtReady: boolean;
+}
+
+export interface TransferProgressPayload {
+  transferId: string;
+  downloadedBytes: number;
+  totalBytes: number;
+  completedChunks: number;
+  totalChunks: number;
+  progressPercentage: number;
+  downloadSpeedBps: number;
+  uploadSpeedBps: number;
+  instantaneousSpeedBps: number;
+  etaSeconds: number | null;
+  stalled: boolean;
+  activeSources: number;
+  timestamp: number;
+}
+
+export interface DirectTransferProgressPayload {
+  transferId: string;
+  peerId: string;
+  filename: string;
+  bytesReceived: number;
+  totalBytes: number;
+}
+
+export interface BandwidthPayload {
+  bytesReceived: number;
+  bytesSent: number;
+  /** Bytes per second */
+  downloadRate: number;
+  uploadRate: number;
+}
+
+export type ChiralEvent =
+  | { kind: 'peer_discovered'; payload: PeerDiscoveredPayload }
+  | { kind: 'peer_connected'; payload: PeerConnectedPayload }
+  | { kind: 'peer_disconnected'; payload: PeerDisconnectedPayload }
+  | { kind: 'nat_status'; payload: NatStatusPayload }
+  | { kind: 'relay'; payload: RelayPayload }
+  | { kind: 'bootstrap'; payload: BootstrapPayload }
+  | { kind: 'transfer'; payload: TransferEventPayload }
+  | { kind: 'transfer_progress'; payload: TransferProgressPayload }
+  | { kind: 'direct_transfer_progress'; payload: DirectTransferProgressPayload }
+  | { kind: 'bandwidth'; payload: BandwidthPayload };
@@ -46,7 +46,7 @@ cd src-tauri
 # Start bootstrap in background and capture output
 ./target/release/chiral-network \
     --headless \
-    --is-bootstrap \
+    --infra-mode \
     --dht-port 4001 \
     --secret "$SECRET" \
     --show-multiaddr \
@@ -61,7 +61,7 @@ sleep 5
 # Extract Peer ID from log
 echo ""
 echo "Extracting bootstrap Peer ID..."
-PEER_ID=$(grep -oP '(?<=p2p/)[A-Za-z0-9]+' /tmp/chiral-bootstrap.log | head -1)
+PEER_ID=$(sed -n 's/^CHIRAL_PEER_ID=//p' /tmp/chiral-bootstrap.log | head -1)
 
 if [ -z "$PEER_ID" ]; then
     echo "❌ Could not extract Peer ID. Check /tmp/chiral-bootstrap.log"